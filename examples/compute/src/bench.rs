@@ -0,0 +1,161 @@
+//! Gpu time of the subgroup variants of the compute kernels against their
+//! naive versions, run with `cargo run -p compute -- --bench`.
+//!
+//! The kernels fall back to their naive version when the device lacks the
+//! subgroup operations they need, both timings are then the same.
+
+use std::{error::Error, sync::Arc};
+
+use ash::vk;
+use tracing::info;
+use vks::{
+    cmd_downsample_mipmaps, Context, DepthPyramid, DepthReduction, GpuProfiler, Image,
+    ImageParameters, LuminanceHistogram, SamplerParameters, Texture,
+};
+
+const ITERATIONS: usize = 100;
+const SOURCE_WIDTH: u32 = 1920;
+const SOURCE_HEIGHT: u32 = 1080;
+const MIPMAPS_SIZE: u32 = 2048;
+const MIPMAPS_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let context = Arc::new(Context::new_headless(None, false)?);
+    info!("Subgroup support: {:?}", context.subgroup_support());
+
+    let mut profiler = GpuProfiler::new(&context, 1, ITERATIONS);
+    if !profiler.is_supported() {
+        return Err("The device does not support timestamps".into());
+    }
+
+    // Its red channel stands for the depth of the pyramid.
+    let source = create_source(&context);
+    for subgroups in [false, true] {
+        let histogram = LuminanceHistogram::with_subgroups(&context, &source, -8.0, 8.0, subgroups);
+        let duration = time(&context, &mut profiler, |command_buffer| {
+            histogram.cmd_build(command_buffer)
+        });
+        report("Luminance histogram", subgroups, duration);
+    }
+
+    for subgroups in [false, true] {
+        let pyramid =
+            DepthPyramid::with_subgroups(&context, &source, DepthReduction::Max, subgroups);
+        let duration = time(&context, &mut profiler, |command_buffer| {
+            pyramid.cmd_build(command_buffer)
+        });
+        report(
+            &format!("Depth pyramid ({} passes)", pyramid.pass_count()),
+            subgroups,
+            duration,
+        );
+    }
+
+    if supports_write_without_format(&context) {
+        let image = create_mipmapped_image(&context);
+        for subgroups in [false, true] {
+            // The transition discarding the mips is timed with both versions.
+            let duration = time(&context, &mut profiler, |command_buffer| {
+                image.cmd_transition_image_layout(
+                    command_buffer,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+                cmd_downsample_mipmaps(&context, command_buffer, &image, subgroups)
+            });
+            report("Mipmaps downsample", subgroups, duration);
+        }
+        // The downsampler resources are destroyed by the deletion queue.
+        context.wait_idle();
+        context.deletion_queue().flush(context.device());
+    } else {
+        info!("Mipmaps downsample skipped, storage images can't be written without format");
+    }
+
+    Ok(())
+}
+
+/// `RGBA32F` source of luminances spread over the histogram range, with a
+/// uniform area where all the invocations of a subgroup count the same bin.
+fn create_source(context: &Arc<Context>) -> Texture {
+    let rgba = (0..SOURCE_WIDTH * SOURCE_HEIGHT)
+        .flat_map(|index| {
+            let (x, y) = (index % SOURCE_WIDTH, index / SOURCE_WIDTH);
+            let value = if x < SOURCE_WIDTH / 2 {
+                ((x * 7 + y * 13) % 1024) as f32 / 64.0
+            } else {
+                0.5
+            };
+            [value, value, value, 1.0]
+        })
+        .collect::<Vec<_>>();
+    Texture::from_rgba_32(
+        context,
+        SOURCE_WIDTH,
+        SOURCE_HEIGHT,
+        false,
+        &rgba,
+        Some(SamplerParameters {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            anisotropy_enabled: false,
+        }),
+    )
+}
+
+fn create_mipmapped_image(context: &Arc<Context>) -> Image {
+    Image::create(
+        Arc::clone(context),
+        ImageParameters {
+            mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            extent: vk::Extent2D {
+                width: MIPMAPS_SIZE,
+                height: MIPMAPS_SIZE,
+            },
+            mip_levels: MIPMAPS_SIZE.ilog2() + 1,
+            format: MIPMAPS_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            ..Default::default()
+        },
+    )
+}
+
+fn supports_write_without_format(context: &Context) -> bool {
+    let features = unsafe {
+        context
+            .instance()
+            .get_physical_device_features(context.physical_device())
+    };
+    features.shader_storage_image_write_without_format == vk::TRUE
+}
+
+/// Average gpu time in milliseconds of the commands recorded by `record`.
+fn time(
+    context: &Context,
+    profiler: &mut GpuProfiler,
+    mut record: impl FnMut(vk::CommandBuffer),
+) -> f64 {
+    profiler.clear();
+    for _ in 0..ITERATIONS {
+        context.execute_one_time_commands(|command_buffer| {
+            profiler.cmd_begin_frame(command_buffer, 0);
+            profiler.begin_scope(command_buffer, "kernel");
+            record(command_buffer);
+            profiler.end_scope(command_buffer);
+        });
+        profiler.resolve(0);
+    }
+
+    let totals = profiler
+        .frames()
+        .map(|frame| frame.total())
+        .collect::<Vec<_>>();
+    totals.iter().sum::<f64>() / totals.len().max(1) as f64
+}
+
+fn report(kernel: &str, subgroups: bool, duration: f64) {
+    let version = if subgroups { "subgroups" } else { "naive" };
+    info!("{} {}: {:.3} ms", kernel, version, duration);
+}
@@ -0,0 +1,722 @@
+//! Compute shader writing a texture sampled by a quad.
+//!
+//! Each frame a compute pass writes an animated pattern into a storage image,
+//! then the textured quad pipeline samples it over the whole window.
+//!
+//! Usage: `cargo run -p compute -- [--bench]`, `--bench` timing the compute
+//! kernels against their naive versions instead, see [`bench`].
+
+mod bench;
+
+use std::{error::Error, mem::size_of, sync::Arc, time::Instant};
+
+use ash::vk;
+use tracing::Level;
+use vks::{
+    create_compute_pipeline, create_device_local_buffer_with_data, create_pipeline, create_sampler,
+    Buffer, Camera, ComputePipelineParameters, Context, Descriptors, Image, ImageParameters,
+    PipelineParameters, RenderData, RenderError, ShaderParameters, Texture, Vertex,
+    VulkanExampleBase, WindowApp,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, StartCause, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::{Window, WindowId},
+};
+
+const COMPUTE_SHADER_NAME: &str = "compute_texture";
+const QUAD_SHADER_NAME: &str = "texture";
+const TEXTURE_SIZE: u32 = 256;
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// Local size of the compute shader.
+const GROUP_SIZE: u32 = 8;
+
+struct App {
+    window: Option<Window>,
+    compute_app: Option<ComputeApp>,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = event_loop
+            .create_window(
+                Window::default_attributes()
+                    .with_title("Compute")
+                    .with_inner_size(PhysicalSize::new(800, 600)),
+            )
+            .expect("Failed to create window");
+
+        self.compute_app = Some(ComputeApp::new(&window));
+        self.window = Some(window);
+    }
+
+    fn new_events(&mut self, _: &ActiveEventLoop, _: StartCause) {
+        if let Some(app) = self.compute_app.as_mut() {
+            app.new_frame();
+        }
+    }
+
+    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+        self.compute_app
+            .as_mut()
+            .unwrap()
+            .end_frame(self.window.as_ref().unwrap());
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        if let WindowEvent::CloseRequested = event {
+            event_loop.exit();
+        }
+
+        self.compute_app
+            .as_mut()
+            .unwrap()
+            .handle_window_event(self.window.as_ref().unwrap(), &event);
+    }
+
+    fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
+        self.compute_app
+            .as_mut()
+            .unwrap()
+            .handle_device_event(&event);
+    }
+
+    fn exiting(&mut self, _: &ActiveEventLoop) {
+        // Drop the app while the window is alive, the surface must be destroyed first.
+        if let Some(mut app) = self.compute_app.take() {
+            app.on_exit();
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct QuadVertex {
+    position: [f32; 2],
+    coords: [f32; 2],
+}
+
+impl Vertex for QuadVertex {
+    fn get_bindings_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<QuadVertex>() as _,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    fn get_attributes_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 8,
+            },
+        ]
+    }
+}
+
+struct QuadModel {
+    vertices: Buffer,
+    indices: Buffer,
+}
+
+impl QuadModel {
+    fn new(context: &Arc<Context>) -> Self {
+        let indices: [u32; 6] = [0, 1, 2, 2, 3, 0];
+        let indices = create_device_local_buffer_with_data::<u8, _>(
+            context,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            &indices,
+        );
+        let vertices = [
+            QuadVertex {
+                position: [-1.0, -1.0],
+                coords: [0.0, 0.0],
+            },
+            QuadVertex {
+                position: [1.0, -1.0],
+                coords: [1.0, 0.0],
+            },
+            QuadVertex {
+                position: [1.0, 1.0],
+                coords: [1.0, 1.0],
+            },
+            QuadVertex {
+                position: [-1.0, 1.0],
+                coords: [0.0, 1.0],
+            },
+        ];
+        let vertices = create_device_local_buffer_with_data::<u8, _>(
+            context,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &vertices,
+        );
+
+        indices.set_name("quad indices");
+        vertices.set_name("quad vertices");
+
+        Self { vertices, indices }
+    }
+}
+
+/// Texture written by the compute pipeline and sampled by the quad pipeline.
+fn create_storage_texture(context: &Arc<Context>) -> Texture {
+    let image = Image::create(
+        Arc::clone(context),
+        ImageParameters {
+            mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            extent: vk::Extent2D {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+            },
+            format: TEXTURE_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            ..Default::default()
+        },
+    );
+    let view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::COLOR);
+    let sampler = create_sampler(context, vk::Filter::LINEAR, vk::Filter::LINEAR);
+    let texture = Texture::new(Arc::clone(context), image, view, Some(sampler));
+    texture.set_name("compute texture");
+    texture
+}
+
+/// A single set shared by both pipelines, the compute shader writes the
+/// storage image at binding 0 and the fragment shader samples it at binding 1.
+fn create_descriptors(context: &Arc<Context>, texture: &Texture) -> Descriptors {
+    let device = context.device();
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        },
+    ];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let storage_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(texture.view)];
+    let sampled_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(texture.view)
+        .sampler(texture.sampler.unwrap())];
+    let writes = [
+        vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&storage_info),
+        vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&sampled_info),
+    ];
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_pipeline_layout(
+    context: &Context,
+    set_layout: vk::DescriptorSetLayout,
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> vk::PipelineLayout {
+    let set_layouts = [set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+fn create_quad_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    color_format: vk::Format,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .min_sample_shading(1.0);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    create_pipeline::<QuadVertex>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new(QUAD_SHADER_NAME),
+            fragment_shader_params: ShaderParameters::new(QUAD_SHADER_NAME),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: None,
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[color_format],
+            depth_attachment_format: None,
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
+}
+
+pub struct ComputeApp {
+    // Declared before `base` to be dropped while the device is alive.
+    model: QuadModel,
+    texture: Texture,
+    descriptors: Descriptors,
+    compute_pipeline_layout: vk::PipelineLayout,
+    compute_pipeline: vk::Pipeline,
+    quad_pipeline_layout: vk::PipelineLayout,
+    quad_pipeline: vk::Pipeline,
+    base: VulkanExampleBase,
+    start_time: Instant,
+    dirty_swapchain: bool,
+}
+
+impl ComputeApp {
+    fn new(window: &Window) -> Self {
+        let base = VulkanExampleBase::new(window, true);
+        let context = &base.context;
+
+        let model = QuadModel::new(context);
+        let texture = create_storage_texture(context);
+        let descriptors = create_descriptors(context, &texture);
+
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: size_of::<f32>() as _,
+        };
+        let compute_pipeline_layout =
+            create_pipeline_layout(context, descriptors.layout(), &[push_constant_range]);
+        let compute_pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::new(COMPUTE_SHADER_NAME),
+                layout: compute_pipeline_layout,
+            },
+        );
+        context.set_object_name(compute_pipeline, "compute pipeline");
+
+        let color_format = context
+            .color_policy()
+            .attachment_format(base.swapchain.properties().format);
+        let quad_pipeline_layout = create_pipeline_layout(context, descriptors.layout(), &[]);
+        let quad_pipeline = create_quad_pipeline(context, quad_pipeline_layout, color_format);
+        context.set_object_name(quad_pipeline, "quad pipeline");
+
+        Self {
+            model,
+            texture,
+            descriptors,
+            compute_pipeline_layout,
+            compute_pipeline,
+            quad_pipeline_layout,
+            quad_pipeline,
+            base,
+            start_time: Instant::now(),
+            dirty_swapchain: false,
+        }
+    }
+
+    /// Write the pattern of the current time into the texture and leave it
+    /// ready to be sampled by the fragment shader.
+    fn cmd_write_texture(&self, command_buffer: vk::CommandBuffer) {
+        let device = self.base.context.device();
+        let image = &self.texture.image;
+        let time = self.start_time.elapsed().as_secs_f32();
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        );
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.compute_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.compute_pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.compute_pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &time.to_ne_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                TEXTURE_SIZE.div_ceil(GROUP_SIZE),
+                TEXTURE_SIZE.div_ceil(GROUP_SIZE),
+                1,
+            );
+        }
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+}
+
+impl WindowApp for ComputeApp {
+    fn new_frame(&mut self) {}
+
+    fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent) {
+        if let WindowEvent::Resized(PhysicalSize { width, height }) = event {
+            tracing::debug!("resize {:?}", (width, height));
+            self.dirty_swapchain = true;
+        }
+    }
+
+    fn handle_device_event(&mut self, _event: &DeviceEvent) {}
+
+    fn end_frame(&mut self, window: &Window) {
+        // If swapchain must be recreated wait for windows to not be minimized anymore
+        if self.dirty_swapchain {
+            let PhysicalSize { width, height } = window.inner_size();
+            if width > 0 && height > 0 {
+                self.base.recreate_swapchain(window.inner_size().into(), true, false);
+            } else {
+                return;
+            }
+        }
+        self.dirty_swapchain = match self.render(window, Camera::default()) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
+    }
+
+    fn on_exit(&mut self) {
+        self.base.wait_idle_gpu();
+    }
+
+    fn render(&mut self, _window: &Window, _camera: Camera) -> Result<(), RenderError> {
+        let sync_objects = self.base.in_flight_frames.next().unwrap();
+        let image_available_semaphore = sync_objects.image_available_semaphore;
+        let render_finished_semaphore = sync_objects.render_finished_semaphore;
+        let in_flight_fence = sync_objects.fence;
+        let wait_fences = [in_flight_fence];
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .unwrap_or_else(|error| {
+                    self.base
+                        .context
+                        .handle_device_error(error, "Failed to wait for frame fence")
+                })
+        };
+        self.base.in_flight_frames.collect_deletions();
+
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .reset_fences(&wait_fences)
+                .unwrap()
+        };
+
+        let command_buffer = self.base.command_buffers[image_index as usize];
+        {
+            let device = self.base.context.device();
+            unsafe {
+                device
+                    .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                    .unwrap();
+                device
+                    .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+                    .unwrap();
+            }
+        }
+
+        self.cmd_draw(command_buffer, image_index as _, None);
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .end_command_buffer(command_buffer)
+                .unwrap()
+        };
+
+        // Submit command buffer
+        {
+            let wait_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(image_available_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+
+            let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(render_finished_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS);
+
+            let cmd_buffer_submit_info =
+                vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer);
+
+            let submit_info = vk::SubmitInfo2::default()
+                .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info))
+                .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
+                .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
+
+            let _queue_guard = self.base.context.lock_queue();
+            unsafe {
+                self.base
+                    .context
+                    .synchronization2()
+                    .queue_submit2(
+                        self.base.context.graphics_compute_queue(),
+                        std::slice::from_ref(&submit_info),
+                        in_flight_fence,
+                    )
+                    .unwrap_or_else(|error| {
+                        self.base
+                            .context
+                            .handle_device_error(error, "Failed to submit frame")
+                    })
+            };
+        }
+
+        let swapchains = [self.base.swapchain.swapchain_khr()];
+        let images_indices = [image_index];
+        let signal_semaphores = [render_finished_semaphore];
+
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&images_indices);
+
+        match self.base.swapchain.present(&present_info) {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RenderError::DirtySwapchain),
+            Err(error) => self
+                .base
+                .context
+                .handle_device_error(error, "Failed to present queue"),
+            _ => Ok(()),
+        }
+    }
+
+    fn cmd_draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        _ui_render_data: Option<&RenderData>,
+    ) {
+        // The texture is written outside of the rendering.
+        self.base.context.cmd_begin_pass(command_buffer, "compute");
+        self.cmd_write_texture(command_buffer);
+        self.base.context.cmd_end_pass(command_buffer);
+
+        let image = &self.base.swapchain.images()[frame_index];
+        let image_view = self.base.swapchain.image_views()[frame_index];
+        let extent = vk::Extent2D {
+            width: image.extent.width,
+            height: image.extent.height,
+        };
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
+
+        let device = self.base.context.device();
+        unsafe {
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+        }
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .image_view(image_view)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        unsafe {
+            self.base
+                .context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+            self.base.context.cmd_begin_pass(command_buffer, "quad");
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.quad_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.quad_pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.model.vertices.buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.model.indices.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0);
+
+            self.base.context.cmd_end_pass(command_buffer);
+            self.base
+                .context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer);
+        }
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        );
+    }
+}
+
+impl Drop for ComputeApp {
+    fn drop(&mut self) {
+        let device = self.base.context.device();
+        unsafe {
+            device.destroy_pipeline(self.quad_pipeline, None);
+            device.destroy_pipeline_layout(self.quad_pipeline_layout, None);
+            device.destroy_pipeline(self.compute_pipeline, None);
+            device.destroy_pipeline_layout(self.compute_pipeline_layout, None);
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    if std::env::args().skip(1).any(|arg| arg == "--bench") {
+        return bench::run();
+    }
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut app = App {
+        window: None,
+        compute_app: None,
+    };
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::point_cloud::Point;
+
+/// Size of the largest public header, the one of LAS 1.4.
+const MAX_HEADER_SIZE: usize = 375;
+/// Size of the LAS 1.0 to 1.2 header, the smallest one.
+const MIN_HEADER_SIZE: usize = 227;
+
+struct Header {
+    version: (u8, u8),
+    point_data_offset: u64,
+    point_format: u8,
+    point_record_length: usize,
+    point_count: u64,
+    scale: [f64; 3],
+    offset: [f64; 3],
+    center: [f64; 3],
+}
+
+impl Header {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut bytes = [0; MAX_HEADER_SIZE];
+        reader.read_exact(&mut bytes[..MIN_HEADER_SIZE])?;
+        if &bytes[0..4] != b"LASF" {
+            return Err("Missing LAS file signature".into());
+        }
+        let header_size = LittleEndian::read_u16(&bytes[94..]) as usize;
+        let header_size = header_size.clamp(MIN_HEADER_SIZE, MAX_HEADER_SIZE);
+        reader.read_exact(&mut bytes[MIN_HEADER_SIZE..header_size])?;
+
+        let version = (bytes[24], bytes[25]);
+        let point_format = bytes[104];
+        if point_format & 0xc0 != 0 {
+            return Err("Compressed LAZ files are not supported".into());
+        }
+
+        let legacy_point_count = LittleEndian::read_u32(&bytes[107..]) as u64;
+        let point_count = if version >= (1, 4) && header_size >= MAX_HEADER_SIZE {
+            LittleEndian::read_u64(&bytes[247..])
+        } else {
+            legacy_point_count
+        };
+
+        let read_f64s = |offset: usize| {
+            [0, 1, 2].map(|index| LittleEndian::read_f64(&bytes[offset + index * 8..]))
+        };
+        // Bounds are stored as max x, min x, max y, min y, max z, min z.
+        let bounds_center = |offset: usize| {
+            let max = LittleEndian::read_f64(&bytes[offset..]);
+            let min = LittleEndian::read_f64(&bytes[offset + 8..]);
+            (max + min) * 0.5
+        };
+
+        Ok(Self {
+            version,
+            point_data_offset: LittleEndian::read_u32(&bytes[96..]) as u64,
+            point_format,
+            point_record_length: LittleEndian::read_u16(&bytes[105..]) as usize,
+            point_count,
+            scale: read_f64s(131),
+            offset: read_f64s(155),
+            center: [bounds_center(179), bounds_center(195), bounds_center(211)],
+        })
+    }
+
+    /// Offset of the rgb channels in the point records, `None` if the format has no color.
+    fn color_offset(&self) -> Option<usize> {
+        match self.point_format {
+            2 => Some(20),
+            3 | 5 => Some(28),
+            7 | 8 | 10 => Some(30),
+            _ => None,
+        }
+    }
+}
+
+/// Load the points of an uncompressed LAS file.
+///
+/// Only the position and the color are read, the intensity standing for the
+/// color of formats without one. Points are centered on the bounds of the file
+/// to keep the precision of single floats and converted from z up to y up.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Point>, Box<dyn Error>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Read the points of the LAS data of `reader`, see [`load`].
+fn read<R: Read + Seek>(mut reader: R) -> Result<Vec<Point>, Box<dyn Error>> {
+    let header = Header::read(&mut reader)?;
+    tracing::debug!(
+        "LAS {}.{} with {} points of format {}",
+        header.version.0,
+        header.version.1,
+        header.point_count,
+        header.point_format
+    );
+
+    let color_offset = header.color_offset();
+    let min_record_length = color_offset.map_or(14, |offset| offset + 6);
+    if header.point_record_length < min_record_length {
+        return Err(format!(
+            "LAS point records of {} bytes are too short for format {}",
+            header.point_record_length, header.point_format
+        )
+        .into());
+    }
+
+    reader.seek(SeekFrom::Start(header.point_data_offset))?;
+
+    let count = header.point_count as usize;
+    let mut positions = Vec::with_capacity(count);
+    let mut colors = Vec::with_capacity(count);
+    let mut record = vec![0; header.point_record_length];
+    for _ in 0..count {
+        reader.read_exact(&mut record)?;
+        let [x, y, z] = [0, 1, 2].map(|index| {
+            let value = LittleEndian::read_i32(&record[index * 4..]) as f64;
+            (value * header.scale[index] + header.offset[index] - header.center[index]) as f32
+        });
+        positions.push([x, z, -y]);
+
+        let color = match color_offset {
+            Some(offset) => {
+                [0, 1, 2].map(|index| LittleEndian::read_u16(&record[offset + index * 2..]))
+            }
+            None => [LittleEndian::read_u16(&record[12..]); 3],
+        };
+        colors.push(color);
+    }
+
+    // Colors and intensities are 16 bits but many files only use the low 8 bits.
+    let max = colors.iter().flatten().copied().max().unwrap_or(0);
+    let shift = if max > 255 { 8 } else { 0 };
+    let points = positions
+        .into_iter()
+        .zip(colors)
+        .map(|(position, color)| Point::new(position, color.map(|c| (c >> shift) as u8)))
+        .collect();
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use byteorder::WriteBytesExt;
+
+    use crate::point_cloud::pack_color;
+
+    /// LAS 1.2 file of `records` in `point_format`, with a scale of 0.01 and
+    /// bounds from (10, 20, 30) to (12, 24, 36).
+    fn las_file(point_format: u8, record_length: u16, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0; MIN_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(b"LASF");
+        bytes[24..26].copy_from_slice(&[1, 2]);
+        LittleEndian::write_u16(&mut bytes[94..], MIN_HEADER_SIZE as u16);
+        // Variable length records between the header and the points are skipped.
+        LittleEndian::write_u32(&mut bytes[96..], MIN_HEADER_SIZE as u32 + 54);
+        bytes[104] = point_format;
+        LittleEndian::write_u16(&mut bytes[105..], record_length);
+        LittleEndian::write_u32(&mut bytes[107..], records.len() as u32);
+        for (index, value) in [0.01, 0.01, 0.01, 10.0, 20.0, 30.0].into_iter().enumerate() {
+            LittleEndian::write_f64(&mut bytes[131 + index * 8..], value);
+        }
+        for (index, value) in [12.0, 10.0, 24.0, 20.0, 36.0, 30.0].into_iter().enumerate() {
+            LittleEndian::write_f64(&mut bytes[179 + index * 8..], value);
+        }
+
+        bytes.resize(MIN_HEADER_SIZE + 54, 0xff);
+        for record in records {
+            assert_eq!(record.len(), record_length as usize);
+            bytes.extend_from_slice(record);
+        }
+        bytes
+    }
+
+    /// Record of a point at `coordinates` in units of the scale, with `intensity`.
+    fn record(coordinates: [i32; 3], intensity: u16, length: usize) -> Vec<u8> {
+        let mut record = Vec::with_capacity(length);
+        for coordinate in coordinates {
+            record.write_i32::<LittleEndian>(coordinate).unwrap();
+        }
+        record.write_u16::<LittleEndian>(intensity).unwrap();
+        record.resize(length, 0);
+        record
+    }
+
+    fn with_color(mut record: Vec<u8>, offset: usize, color: [u16; 3]) -> Vec<u8> {
+        for (index, channel) in color.into_iter().enumerate() {
+            LittleEndian::write_u16(&mut record[offset + index * 2..], channel);
+        }
+        record
+    }
+
+    fn read_bytes(bytes: Vec<u8>) -> Result<Vec<Point>, Box<dyn Error>> {
+        read(Cursor::new(bytes))
+    }
+
+    #[test]
+    fn centers_the_points_with_y_up() {
+        let records = [
+            with_color(record([100, 200, 300], 0, 26), 20, [65535, 0, 32768]),
+            with_color(record([0, 0, 0], 0, 26), 20, [256, 512, 1024]),
+        ];
+        let points = read_bytes(las_file(2, 26, &records)).unwrap();
+
+        // (11, 22, 33) is the center, z becomes y and y becomes -z.
+        assert_eq!(points[0].position, [0.0, 0.0, 0.0]);
+        assert_eq!(points[1].position, [-1.0, -3.0, 2.0]);
+        // Colors above 255 are 16 bits.
+        assert_eq!(points[0].color, pack_color([255, 0, 128]));
+        assert_eq!(points[1].color, pack_color([1, 2, 4]));
+    }
+
+    #[test]
+    fn keeps_8_bits_colors() {
+        let records = [with_color(record([0, 0, 0], 0, 34), 28, [200, 100, 50])];
+        let points = read_bytes(las_file(3, 34, &records)).unwrap();
+
+        assert_eq!(points[0].color, pack_color([200, 100, 50]));
+    }
+
+    #[test]
+    fn intensity_stands_for_the_missing_color() {
+        let records = [record([0, 0, 0], 90, 20), record([0, 0, 0], 0xff00, 20)];
+        let points = read_bytes(las_file(1, 20, &records)).unwrap();
+
+        assert_eq!(points[0].color, pack_color([0; 3]));
+        assert_eq!(points[1].color, pack_color([255; 3]));
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let mut unsigned = las_file(0, 20, &[]);
+        unsigned[0..4].copy_from_slice(b"LASX");
+        assert!(read_bytes(unsigned).is_err());
+
+        // The high bits of the point format mark LAZ compression.
+        assert!(read_bytes(las_file(0x82, 26, &[])).is_err());
+
+        // Too short to hold the color of format 2.
+        assert!(read_bytes(las_file(2, 20, &[record([0; 3], 0, 20)])).is_err());
+
+        let mut truncated = las_file(0, 20, &[record([0; 3], 0, 20)]);
+        truncated.pop();
+        assert!(read_bytes(truncated).is_err());
+
+        assert!(read_bytes(b"LASF".to_vec()).is_err());
+    }
+}
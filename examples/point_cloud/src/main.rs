@@ -0,0 +1,670 @@
+//! Point cloud viewer.
+//!
+//! Points are pulled by the vertex shader from storage buffers and drawn as
+//! sprites sized by their distance, then shaded with Eye-Dome Lighting.
+//! Without a file a procedural cloud is generated.
+//!
+//! Usage: `cargo run -p point_cloud -- [<file.ply|file.las>] [--points <count>] [--budget <MiB>]`
+//!
+//! With a budget only the points closest to the camera are kept in device
+//! memory, streamed in and out as it moves. The residency is shown in the title.
+//!
+//! Keys: `e` toggles EDL, `+`/`-` change the size of the points, `m` picks a
+//! point under the cursor to measure the distance between the last two picked,
+//! `n` annotates the point under the cursor and `c` clears both. `3` cycles the
+//! stereo preview modes, `[`/`]` change the distance between the eyes and
+//! `,`/`.` the distance where they converge.
+
+mod las;
+mod ply;
+mod point_cloud;
+mod renderer;
+mod streaming;
+
+use std::{error::Error, path::PathBuf, time::Instant};
+
+use ash::vk::{self, RenderingAttachmentInfo, RenderingInfo};
+use math::cgmath::{EuclideanSpace, Point3, Vector3};
+use point_cloud::PointCloud;
+use renderer::{PointCloudRenderer, PointSettings};
+use streaming::ResidencyStats;
+use tracing::{info, Level};
+use vks::{
+    Camera, CameraUniforms, Context, InputState, Measurements, RenderData, RenderError,
+    StereoCompositePass, StereoMode, StereoSettings, Texture, VulkanExampleBase, WindowApp,
+    MAX_FRAMES_IN_FLIGHT,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, StartCause, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::Key,
+    window::{Window, WindowId},
+};
+
+const DEFAULT_POINT_COUNT: usize = 2_000_000;
+const WINDOW_TITLE: &str = "Point cloud";
+/// Camera uniform slot of the first eye of the first frame in flight, after the
+/// slots of the frames in flight.
+const EYE_CAMERA_SLOT: usize = MAX_FRAMES_IN_FLIGHT as usize;
+
+struct PointCloudOptions {
+    path: Option<PathBuf>,
+    point_count: usize,
+    /// Device memory of the points in bytes, all of them are uploaded without.
+    budget: Option<vk::DeviceSize>,
+}
+
+impl PointCloudOptions {
+    fn from_args() -> Result<Self, Box<dyn Error>> {
+        let mut options = Self {
+            path: None,
+            point_count: DEFAULT_POINT_COUNT,
+            budget: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--points" => {
+                    let count = args.next().ok_or("Missing value for --points")?;
+                    options.point_count = count.parse::<usize>()?.max(1);
+                }
+                "--budget" => {
+                    let budget = args.next().ok_or("Missing value for --budget")?;
+                    options.budget = Some(budget.parse::<vk::DeviceSize>()?.max(1) * 1024 * 1024);
+                }
+                _ => options.path = Some(PathBuf::from(arg)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn load(&self) -> Result<PointCloud, Box<dyn Error>> {
+        let start = Instant::now();
+        let point_cloud = match self.path.as_ref() {
+            Some(path) => PointCloud::load(path)?,
+            None => PointCloud::generate(self.point_count),
+        };
+        info!(
+            "Loaded {} points in {:?}",
+            point_cloud.points.len(),
+            start.elapsed()
+        );
+        Ok(point_cloud)
+    }
+}
+
+struct App {
+    window: Option<Window>,
+    point_cloud_app: Option<PointCloudApp>,
+    point_cloud: Option<PointCloud>,
+    budget: Option<vk::DeviceSize>,
+}
+
+impl App {
+    fn new() -> Result<Self, Box<dyn Error>> {
+        let options = PointCloudOptions::from_args()?;
+        Ok(Self {
+            window: None,
+            point_cloud_app: None,
+            point_cloud: Some(options.load()?),
+            budget: options.budget,
+        })
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = event_loop
+            .create_window(
+                Window::default_attributes()
+                    .with_title(WINDOW_TITLE)
+                    .with_inner_size(PhysicalSize::new(1280, 720)),
+            )
+            .expect("Failed to create window");
+
+        let point_cloud = self.point_cloud.take().unwrap();
+        self.point_cloud_app = Some(PointCloudApp::new(&window, point_cloud, self.budget));
+        self.window = Some(window);
+    }
+
+    fn new_events(&mut self, _: &ActiveEventLoop, _: StartCause) {
+        if let Some(app) = self.point_cloud_app.as_mut() {
+            app.new_frame();
+        }
+    }
+
+    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+        self.point_cloud_app
+            .as_mut()
+            .unwrap()
+            .end_frame(self.window.as_ref().unwrap());
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        if let WindowEvent::CloseRequested = event {
+            event_loop.exit();
+        }
+
+        self.point_cloud_app
+            .as_mut()
+            .unwrap()
+            .handle_window_event(self.window.as_ref().unwrap(), &event);
+    }
+
+    fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
+        self.point_cloud_app
+            .as_mut()
+            .unwrap()
+            .handle_device_event(&event);
+    }
+
+    fn exiting(&mut self, _: &ActiveEventLoop) {
+        // Drop the app while the window is alive, the surface must be destroyed first.
+        if let Some(mut app) = self.point_cloud_app.take() {
+            app.on_exit();
+        }
+    }
+}
+
+/// Views of the eyes rendered for the stereo preview.
+struct StereoTargets {
+    eyes: [Texture; 2],
+    composite: StereoCompositePass,
+}
+
+impl StereoTargets {
+    fn new(base: &VulkanExampleBase) -> Self {
+        let context = &base.context;
+        let properties = base.swapchain.properties();
+        let eyes = [(); 2].map(|_| {
+            Texture::create_renderable_texture(
+                context,
+                properties.extent.width,
+                properties.extent.height,
+                properties.format.format,
+            )
+        });
+        let composite =
+            StereoCompositePass::new(context, properties.format.format, &eyes[0], &eyes[1]);
+        Self { eyes, composite }
+    }
+}
+
+/// Camera uniform slot of `eye` for the frame in flight `frame_index`.
+fn eye_camera_slot(frame_index: usize, eye: usize) -> usize {
+    EYE_CAMERA_SLOT + frame_index * 2 + eye
+}
+
+pub struct PointCloudApp {
+    // Declared before `base` to be dropped while the device is alive.
+    renderer: PointCloudRenderer,
+    camera_uniforms: CameraUniforms,
+    stereo: Option<StereoTargets>,
+    base: VulkanExampleBase,
+    settings: PointSettings,
+    camera: Camera,
+    input_state: InputState,
+    /// Last cursor position in pixels, where points are picked.
+    cursor_position: [f32; 2],
+    measurements: Measurements,
+    stereo_settings: StereoSettings,
+    /// Residency last shown in the title, `None` without budget.
+    shown_residency: Option<ResidencyStats>,
+    time: Instant,
+    dirty_swapchain: bool,
+}
+
+impl PointCloudApp {
+    fn new(window: &Window, point_cloud: PointCloud, budget: Option<vk::DeviceSize>) -> Self {
+        let base = VulkanExampleBase::new(window, true);
+        let context = &base.context;
+
+        // One slot per frame in flight for the camera and two more for the eyes.
+        let camera_uniforms = CameraUniforms::new(context, EYE_CAMERA_SLOT * 3);
+        let bounds = point_cloud.bounds;
+        let renderer = PointCloudRenderer::new(
+            context,
+            point_cloud.points,
+            budget,
+            &camera_uniforms,
+            base.swapchain.properties().extent,
+            base.swapchain.properties().format,
+        );
+
+        // Frame the whole cloud.
+        let size = bounds.get_larger_side_size().max(f32::EPSILON);
+        let center = Point3::from_vec(bounds.get_center());
+        let mut camera = Camera::default();
+        camera.z_near = size * 0.001;
+        camera.z_far = size * 10.0;
+        camera.look_at(center + Vector3::new(0.0, size * 0.5, size * 1.5), center);
+
+        let settings = PointSettings {
+            point_size: size * 0.001,
+            ..Default::default()
+        };
+        let stereo_settings = StereoSettings {
+            ipd: size * 0.02,
+            convergence: size * 1.5,
+            ..Default::default()
+        };
+
+        Self {
+            renderer,
+            camera_uniforms,
+            stereo: None,
+            base,
+            settings,
+            camera,
+            input_state: InputState::default(),
+            cursor_position: [0.0; 2],
+            measurements: Measurements::default(),
+            stereo_settings,
+            shown_residency: budget.map(|_| ResidencyStats::default()),
+            time: Instant::now(),
+            dirty_swapchain: false,
+        }
+    }
+}
+
+impl PointCloudApp {
+    fn pick_under_cursor(&self) -> Option<Point3<f32>> {
+        let extent = self.base.swapchain.properties().extent;
+        let ray = self
+            .camera
+            .screen_ray(self.cursor_position, [extent.width, extent.height]);
+        self.renderer.pick(&ray)
+    }
+
+    /// Switch from mono to side by side, anaglyph then back to mono.
+    fn cycle_stereo_mode(&mut self) {
+        let modes = StereoMode::all();
+        let next = match self.stereo.as_ref() {
+            None => Some(modes[0]),
+            Some(_) => modes
+                .iter()
+                .position(|mode| *mode == self.stereo_settings.mode)
+                .and_then(|index| modes.get(index + 1))
+                .copied(),
+        };
+        match next {
+            Some(mode) => {
+                self.stereo_settings.mode = mode;
+                if self.stereo.is_none() {
+                    self.stereo = Some(StereoTargets::new(&self.base));
+                }
+            }
+            // Still used by the frames in flight.
+            None => {
+                if let Some(stereo) = self.stereo.take() {
+                    self.base.context.deletion_queue().enqueue(stereo);
+                }
+            }
+        }
+        info!(
+            "Stereo preview: {}",
+            next.map_or_else(|| "off".to_string(), |mode| mode.to_string())
+        );
+    }
+
+    /// Recreate the swapchain and the targets sized after it.
+    fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool) {
+        self.base.recreate_swapchain(dimensions, vsync, hdr);
+        let properties = self.base.swapchain.properties();
+        self.renderer.resize(properties.extent, properties.format);
+        if let Some(stereo) = self.stereo.as_mut() {
+            let previous = std::mem::replace(stereo, StereoTargets::new(&self.base));
+            self.base.context.deletion_queue().enqueue(previous);
+        }
+    }
+}
+
+impl WindowApp for PointCloudApp {
+    fn new_frame(&mut self) {
+        self.input_state = self.input_state.reset();
+    }
+
+    fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent) {
+        self.input_state = self.input_state.handle_window_event(event);
+
+        match event {
+            WindowEvent::Resized(PhysicalSize { width, height }) => {
+                tracing::debug!("resize {:?}", (width, height));
+                self.dirty_swapchain = true;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = [position.x as _, position.y as _];
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: Key::Character(c),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => match c.as_str() {
+                "e" => {
+                    self.settings.edl = !self.settings.edl;
+                    info!("Eye-Dome Lighting enabled: {}", self.settings.edl);
+                }
+                "+" | "=" => self.settings.point_size *= 1.25,
+                "-" => self.settings.point_size /= 1.25,
+                "m" => match self.pick_under_cursor() {
+                    Some(point) => {
+                        if let Some(measurement) = self.measurements.add_point(point) {
+                            info!("Measured distance: {:.3}", measurement.distance());
+                        }
+                    }
+                    None => info!("Nothing to measure under the cursor"),
+                },
+                "n" => {
+                    if let Some(point) = self.pick_under_cursor() {
+                        let text = format!("#{}", self.measurements.annotations().len() + 1);
+                        info!("Annotation {} at {:?}", text, point);
+                        self.measurements.annotate(point, text);
+                    }
+                }
+                "c" => self.measurements.clear(),
+                "3" => self.cycle_stereo_mode(),
+                "[" => self.stereo_settings.ipd /= 1.25,
+                "]" => self.stereo_settings.ipd *= 1.25,
+                "," => self.stereo_settings.convergence /= 1.25,
+                "." => self.stereo_settings.convergence *= 1.25,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn handle_device_event(&mut self, event: &DeviceEvent) {
+        self.input_state = self.input_state.handle_device_event(event);
+    }
+
+    fn end_frame(&mut self, window: &Window) {
+        let new_time = Instant::now();
+        let delta_s = (new_time - self.time).as_secs_f32();
+        self.time = new_time;
+        self.camera.update(&self.input_state, delta_s);
+
+        // If swapchain must be recreated wait for windows to not be minimized anymore
+        if self.dirty_swapchain {
+            let PhysicalSize { width, height } = window.inner_size();
+            if width > 0 && height > 0 {
+                self.recreate_swapchain(window.inner_size().into(), true, false);
+            } else {
+                return;
+            }
+        }
+        self.dirty_swapchain = match self.render(window, self.camera) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
+
+        if let Some(shown) = self.shown_residency.as_mut() {
+            let residency = self.renderer.residency_stats();
+            if residency != *shown {
+                window.set_title(&format!("{} - {}", WINDOW_TITLE, residency));
+                *shown = residency;
+            }
+        }
+    }
+
+    fn on_exit(&mut self) {
+        self.base.wait_idle_gpu();
+    }
+
+    fn render(&mut self, _window: &Window, camera: Camera) -> Result<(), RenderError> {
+        let sync_objects = self.base.in_flight_frames.next().unwrap();
+        let image_available_semaphore = sync_objects.image_available_semaphore;
+        let render_finished_semaphore = sync_objects.render_finished_semaphore;
+        let in_flight_fence = sync_objects.fence;
+        let wait_fences = [in_flight_fence];
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .unwrap_or_else(|error| {
+                    self.base
+                        .context
+                        .handle_device_error(error, "Failed to wait for frame fence")
+                })
+        };
+        self.base.in_flight_frames.collect_deletions();
+
+        // Per frame data is only written once the fence of the frame was waited for.
+        let in_flight_index = self.base.in_flight_frames.current_frame_index();
+        let properties = self.base.swapchain.properties();
+        let aspect = properties.aspect();
+        self.camera_uniforms
+            .set_pre_rotation(properties.pre_rotation());
+        let camera_ubo = self
+            .camera_uniforms
+            .update(in_flight_index, &camera, aspect);
+        self.renderer.update_streaming(camera.position());
+        // The eyes are close enough to the camera to draw the chunks it sees.
+        self.renderer.update_visibility(camera_ubo.view_proj());
+        if self.stereo.is_some() {
+            let eye_cameras = self.stereo_settings.eye_cameras(&camera);
+            for (eye, eye_camera) in eye_cameras.iter().enumerate() {
+                self.camera_uniforms.update(
+                    eye_camera_slot(in_flight_index, eye),
+                    eye_camera,
+                    aspect,
+                );
+            }
+        }
+
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .reset_fences(&wait_fences)
+                .unwrap()
+        };
+
+        // record_command_buffer
+        {
+            let command_buffer = self.base.command_buffers[image_index as usize];
+            let device = self.base.context.device();
+
+            unsafe {
+                device
+                    .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                    .unwrap();
+                device
+                    .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+                    .unwrap();
+            }
+
+            self.cmd_draw(command_buffer, image_index as _, None);
+
+            unsafe {
+                self.base
+                    .context
+                    .device()
+                    .end_command_buffer(command_buffer)
+                    .unwrap()
+            };
+        }
+
+        // Submit command buffer
+        {
+            let wait_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(image_available_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+
+            let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(render_finished_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS);
+
+            let cmd_buffer_submit_info = vk::CommandBufferSubmitInfo::default()
+                .command_buffer(self.base.command_buffers[image_index as usize]);
+
+            let submit_info = vk::SubmitInfo2::default()
+                .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info))
+                .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
+                .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
+
+            let _queue_guard = self.base.context.lock_queue();
+            unsafe {
+                self.base
+                    .context
+                    .synchronization2()
+                    .queue_submit2(
+                        self.base.context.graphics_compute_queue(),
+                        std::slice::from_ref(&submit_info),
+                        in_flight_fence,
+                    )
+                    .unwrap_or_else(|error| {
+                        self.base
+                            .context
+                            .handle_device_error(error, "Failed to submit frame")
+                    })
+            };
+            self.camera_uniforms
+                .submitted(in_flight_index, in_flight_fence);
+            if self.stereo.is_some() {
+                for eye in 0..2 {
+                    self.camera_uniforms
+                        .submitted(eye_camera_slot(in_flight_index, eye), in_flight_fence);
+                }
+            }
+        }
+
+        let swapchains = [self.base.swapchain.swapchain_khr()];
+        let images_indices = [image_index];
+        let signal_semaphores = [render_finished_semaphore];
+
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&images_indices);
+
+        match self.base.swapchain.present(&present_info) {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RenderError::DirtySwapchain),
+            Err(error) => self
+                .base
+                .context
+                .handle_device_error(error, "Failed to present queue"),
+            _ => Ok(()),
+        }
+    }
+
+    fn cmd_draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        _ui_render_data: Option<&RenderData>,
+    ) {
+        let image = &self.base.swapchain.images()[frame_index];
+        let image_view = self.base.swapchain.image_views()[frame_index];
+        // Uniforms are per frame in flight, not per swapchain image.
+        let in_flight_index = self.base.in_flight_frames.current_frame_index();
+
+        if let Some(stereo) = self.stereo.as_ref() {
+            for (eye, texture) in stereo.eyes.iter().enumerate() {
+                if eye > 0 {
+                    cmd_wait_previous_eye(&self.base.context, command_buffer);
+                }
+                self.renderer.cmd_draw(
+                    command_buffer,
+                    eye_camera_slot(in_flight_index, eye),
+                    (&texture.image, texture.view),
+                    &self.settings,
+                    (self.camera.z_near, self.camera.z_far),
+                );
+                texture.image.cmd_transition_image_layout(
+                    command_buffer,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
+            }
+
+            image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+            let extent = self.base.swapchain.properties().extent;
+            let color_attachment_info = RenderingAttachmentInfo::default()
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .image_view(image_view)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE);
+            let rendering_info = RenderingInfo::default()
+                .color_attachments(std::slice::from_ref(&color_attachment_info))
+                .layer_count(1)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                });
+            let dynamic_rendering = self.base.context.dynamic_rendering();
+            unsafe { dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info) };
+            stereo
+                .composite
+                .cmd_draw(command_buffer, extent, self.stereo_settings.mode);
+            unsafe { dynamic_rendering.cmd_end_rendering(command_buffer) };
+        } else {
+            self.renderer.cmd_draw(
+                command_buffer,
+                in_flight_index,
+                (image, image_view),
+                &self.settings,
+                (self.camera.z_near, self.camera.z_far),
+            );
+        }
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        );
+    }
+}
+
+/// Wait for the EDL pass of the previous eye to read the attachments of the
+/// renderer before the next eye draws in them.
+fn cmd_wait_previous_eye(context: &Context, command_buffer: vk::CommandBuffer) {
+    let barrier = vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+        .src_access_mask(vk::AccessFlags2::SHADER_READ)
+        .dst_stage_mask(
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        )
+        .dst_access_mask(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        );
+    let dependency_info =
+        vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut app = App::new()?;
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
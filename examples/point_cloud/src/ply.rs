@@ -0,0 +1,393 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::point_cloud::Point;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(format!("Unknown ply property type {}", name).into()),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    fn read<B: ByteOrder>(&self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::I8 => bytes[0] as i8 as f64,
+            Self::U8 => bytes[0] as f64,
+            Self::I16 => B::read_i16(bytes) as f64,
+            Self::U16 => B::read_u16(bytes) as f64,
+            Self::I32 => B::read_i32(bytes) as f64,
+            Self::U32 => B::read_u32(bytes) as f64,
+            Self::F32 => B::read_f32(bytes) as f64,
+            Self::F64 => B::read_f64(bytes),
+        }
+    }
+
+    /// Scale of a color channel stored with this type to 0..255.
+    fn color_scale(&self) -> f64 {
+        match self {
+            Self::F32 | Self::F64 => 255.0,
+            Self::U16 | Self::I16 => 255.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    ty: ScalarType,
+}
+
+struct Header {
+    format: Format,
+    vertex_count: usize,
+    properties: Vec<Property>,
+}
+
+impl Header {
+    fn read<R: BufRead>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim() != "ply" {
+            return Err("Missing ply magic number".into());
+        }
+
+        let mut format = None;
+        let mut vertex_count = None;
+        let mut properties = Vec::new();
+        // Name of the element whose properties are being declared.
+        let mut element = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err("Unexpected end of ply header".into());
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("format") => {
+                    format = Some(match words.next() {
+                        Some("ascii") => Format::Ascii,
+                        Some("binary_little_endian") => Format::BinaryLittleEndian,
+                        Some("binary_big_endian") => Format::BinaryBigEndian,
+                        other => return Err(format!("Unknown ply format {:?}", other).into()),
+                    });
+                }
+                Some("element") => {
+                    element = words.next().unwrap_or_default().to_owned();
+                    let count = words.next().ok_or("Missing ply element count")?.parse()?;
+                    if element == "vertex" {
+                        vertex_count = Some(count);
+                    } else if vertex_count.is_none() {
+                        // Other elements are skipped, which is only possible after the points.
+                        return Err(format!("Ply element {} precedes the vertices", element).into());
+                    }
+                }
+                Some("property") if element == "vertex" => {
+                    let ty = words.next().ok_or("Missing ply property type")?;
+                    if ty == "list" {
+                        return Err("List properties are not supported on ply vertices".into());
+                    }
+                    properties.push(Property {
+                        ty: ScalarType::parse(ty)?,
+                        name: words.next().ok_or("Missing ply property name")?.to_owned(),
+                    });
+                }
+                Some("end_header") => break,
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            format: format.ok_or("Missing ply format")?,
+            vertex_count: vertex_count.ok_or("Missing ply vertex element")?,
+            properties,
+        })
+    }
+
+    fn index_of(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// Load the vertices of a ply file, with their colors if it has any.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Point>, Box<dyn Error>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Read the vertices of the ply data of `reader`, see [`load`].
+fn read<R: BufRead>(mut reader: R) -> Result<Vec<Point>, Box<dyn Error>> {
+    let header = Header::read(&mut reader)?;
+
+    let position = [
+        header.index_of(&["x"]).ok_or("Missing ply x property")?,
+        header.index_of(&["y"]).ok_or("Missing ply y property")?,
+        header.index_of(&["z"]).ok_or("Missing ply z property")?,
+    ];
+    let color = [
+        header.index_of(&["red", "r", "diffuse_red"]),
+        header.index_of(&["green", "g", "diffuse_green"]),
+        header.index_of(&["blue", "b", "diffuse_blue"]),
+    ];
+
+    let to_point = |values: &[f64]| {
+        let position = position.map(|index| values[index] as f32);
+        let color = color.map(|index| match index {
+            Some(index) => {
+                let scale = header.properties[index].ty.color_scale();
+                (values[index] * scale).clamp(0.0, 255.0) as u8
+            }
+            None => 255,
+        });
+        Point::new(position, color)
+    };
+
+    let mut points = Vec::with_capacity(header.vertex_count);
+    let mut values = vec![0.0; header.properties.len()];
+    match header.format {
+        Format::Ascii => {
+            let mut line = String::new();
+            for _ in 0..header.vertex_count {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Err("Unexpected end of ply vertices".into());
+                }
+                for (value, word) in values.iter_mut().zip(line.split_whitespace()) {
+                    *value = word.parse()?;
+                }
+                points.push(to_point(&values));
+            }
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            let stride = header.properties.iter().map(|p| p.ty.size()).sum();
+            let mut record = vec![0; stride];
+            for _ in 0..header.vertex_count {
+                reader.read_exact(&mut record)?;
+                let mut offset = 0;
+                for (value, property) in values.iter_mut().zip(header.properties.iter()) {
+                    let bytes = &record[offset..];
+                    *value = if header.format == Format::BinaryLittleEndian {
+                        property.ty.read::<LittleEndian>(bytes)
+                    } else {
+                        property.ty.read::<BigEndian>(bytes)
+                    };
+                    offset += property.ty.size();
+                }
+                points.push(to_point(&values));
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::point_cloud::pack_color;
+
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Point>, Box<dyn Error>> {
+        read(Cursor::new(bytes))
+    }
+
+    fn positions(points: &[Point]) -> Vec<[f32; 3]> {
+        points.iter().map(|point| point.position).collect()
+    }
+
+    fn colors(points: &[Point]) -> Vec<u32> {
+        points.iter().map(|point| point.color).collect()
+    }
+
+    #[test]
+    fn reads_ascii_vertices() {
+        let ply = b"ply
+format ascii 1.0
+comment scanned
+element vertex 2
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+end_header
+1 2 3 255 0 10
+-1.5 0 0.25 1 2 3
+";
+        let points = read_bytes(ply).unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [-1.5, 0.0, 0.25]]);
+        assert_eq!(
+            colors(&points),
+            [pack_color([255, 0, 10]), pack_color([1, 2, 3])]
+        );
+    }
+
+    #[test]
+    fn reads_little_endian_vertices_and_skips_the_faces() {
+        let mut ply = b"ply
+format binary_little_endian 1.0
+element vertex 2
+property float x
+property float y
+property float z
+property float intensity
+property float r
+property float g
+property float b
+element face 1
+property list uchar int vertex_indices
+end_header
+"
+        .to_vec();
+        for vertex in [
+            [1.0f32, 2.0, 3.0, 7.0, 1.0, 0.5, 0.0],
+            [4.0, 5.0, 6.0, 7.0, 2.0, -1.0, 0.25],
+        ] {
+            vertex
+                .iter()
+                .for_each(|value| ply.extend_from_slice(&value.to_le_bytes()));
+        }
+        // The face is never read.
+        ply.extend_from_slice(&[3, 0, 0, 0, 0]);
+
+        let points = read_bytes(&ply).unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        // Float colors are scaled from 0..1 and clamped.
+        assert_eq!(
+            colors(&points),
+            [pack_color([255, 127, 0]), pack_color([255, 0, 63])]
+        );
+    }
+
+    #[test]
+    fn reads_big_endian_vertices() {
+        let mut ply = b"ply
+format binary_big_endian 1.0
+element vertex 1
+property double x
+property double y
+property double z
+property ushort diffuse_red
+property ushort diffuse_green
+property ushort diffuse_blue
+end_header
+"
+        .to_vec();
+        for value in [0.5f64, -2.0, 8.0] {
+            ply.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in [65535u16, 0, 32896] {
+            ply.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let points = read_bytes(&ply).unwrap();
+
+        assert_eq!(positions(&points), [[0.5, -2.0, 8.0]]);
+        assert_eq!(colors(&points), [pack_color([255, 0, 128])]);
+    }
+
+    #[test]
+    fn vertices_without_color_are_white() {
+        let ply = b"ply
+format ascii 1.0
+element vertex 1
+property int x
+property int y
+property int z
+end_header
+1 2 3
+";
+        let points = read_bytes(ply).unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0]]);
+        assert_eq!(colors(&points), [pack_color([255; 3])]);
+    }
+
+    #[test]
+    fn rejects_unsupported_headers() {
+        let headers: [&[u8]; 5] = [
+            b"obj\nformat ascii 1.0\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty list uchar int x\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement face 1\nelement vertex 1\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float y\nproperty float z\nend_header\n1 2\n",
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n",
+        ];
+        for header in headers {
+            assert!(read_bytes(header).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_vertices() {
+        let ascii = b"ply
+format ascii 1.0
+element vertex 2
+property float x
+property float y
+property float z
+end_header
+1 2 3
+";
+        assert!(read_bytes(ascii).is_err());
+
+        let mut binary = b"ply
+format binary_little_endian 1.0
+element vertex 1
+property float x
+property float y
+property float z
+end_header
+"
+        .to_vec();
+        binary.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(read_bytes(&binary).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::{error::Error, f32::consts::PI, path::Path};
+
+use math::{
+    cgmath::{Vector3, Zero},
+    partial_max, partial_min, Aabb,
+};
+
+use crate::{las, ply};
+
+/// A point as read by the vertex shader, 16 bytes with std430 layout.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Point {
+    pub position: [f32; 3],
+    /// RGBA8, red in the lowest byte as read by `unpackUnorm4x8`.
+    pub color: u32,
+}
+
+impl Point {
+    pub fn new(position: [f32; 3], color: [u8; 3]) -> Self {
+        Self {
+            position,
+            color: pack_color(color),
+        }
+    }
+}
+
+pub fn pack_color([r, g, b]: [u8; 3]) -> u32 {
+    u32::from_le_bytes([r, g, b, 255])
+}
+
+pub struct PointCloud {
+    pub points: Vec<Point>,
+    pub bounds: Aabb<f32>,
+}
+
+impl PointCloud {
+    /// Load a `.ply` or `.las` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let points = match extension.as_deref() {
+            Some("ply") => ply::load(path)?,
+            Some("las") => las::load(path)?,
+            _ => return Err(format!("Unsupported point cloud file {}", path.display()).into()),
+        };
+        if points.is_empty() {
+            return Err(format!("No point in {}", path.display()).into());
+        }
+        Ok(Self::from_points(points))
+    }
+
+    /// Generate `count` points on a colored torus knot, used when no file is given.
+    pub fn generate(count: usize) -> Self {
+        let points = (0..count)
+            .map(|index| {
+                // Deterministic spread over the curve and around the tube.
+                let t = index as f32 / count as f32 * 2.0 * PI;
+                let a = (index as f32 * 0.618_034).fract() * 2.0 * PI;
+                let (p, q) = (2.0, 3.0);
+                let r = 2.0 + (q * t).cos();
+                let center = Vector3::new(r * (p * t).cos(), -(q * t).sin(), r * (p * t).sin());
+                let offset = Vector3::new(a.cos(), a.sin(), (a * 0.5).sin()) * 0.35;
+                let position = center + offset;
+                let color = [
+                    (127.5 + 127.5 * t.cos()) as u8,
+                    (127.5 + 127.5 * (t + 2.0 * PI / 3.0).cos()) as u8,
+                    (127.5 + 127.5 * a.sin()) as u8,
+                ];
+                Point::new(position.into(), color)
+            })
+            .collect();
+        Self::from_points(points)
+    }
+
+    fn from_points(points: Vec<Point>) -> Self {
+        let axis = |index: usize| {
+            let values = || points.iter().map(move |point| point.position[index]);
+            (partial_min(values()), partial_max(values()))
+        };
+        let (x, y, z) = (axis(0), axis(1), axis(2));
+        let bounds = match (x, y, z) {
+            (
+                (Some(min_x), Some(max_x)),
+                (Some(min_y), Some(max_y)),
+                (Some(min_z), Some(max_z)),
+            ) => Aabb::new(
+                Vector3::new(min_x, min_y, min_z),
+                Vector3::new(max_x, max_y, max_z),
+            ),
+            _ => Aabb::new(Vector3::zero(), Vector3::zero()),
+        };
+        Self { points, bounds }
+    }
+}
@@ -0,0 +1,797 @@
+use std::{mem::size_of, sync::Arc};
+
+use ash::vk::{self, RenderingAttachmentInfo, RenderingInfo};
+use util::any_as_u8_slice;
+use math::{
+    cgmath::{Matrix4, Point3},
+    Bvh, Frustum, Ray,
+};
+use vks::{
+    cmd_transition_images_layouts, create_pipeline, create_sampler, pick_point, CameraUniforms,
+    Context, Descriptors, Image, ImageParameters, LayoutTransition, MipsRange, OutputTransform,
+    PipelineParameters, ShaderParameters, Texture,
+};
+
+use crate::{
+    point_cloud::Point,
+    streaming::{ChunkStreamer, ResidencyStats},
+};
+
+/// Largest storage buffer holding points, the device limit may be lower.
+const MAX_CHUNK_SIZE: vk::DeviceSize = 128 * 1024 * 1024;
+/// Size of the chunks streamed in and out of a memory budget.
+const STREAMED_CHUNK_SIZE: vk::DeviceSize = 8 * 1024 * 1024;
+const COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const MIN_POINT_SIZE: f32 = 1.0;
+const BACKGROUND_COLOR: [f32; 3] = [0.02, 0.02, 0.03];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PointConstants {
+    viewport_height: f32,
+    point_size: f32,
+    min_point_size: f32,
+    max_point_size: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EdlConstants {
+    z_near: f32,
+    z_far: f32,
+    strength: f32,
+    radius: f32,
+    background: [f32; 3],
+    encode_srgb: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointSettings {
+    /// World space size of the points.
+    pub point_size: f32,
+    /// Eye-Dome Lighting, shading the points by the depth of their neighbours.
+    pub edl: bool,
+    pub edl_strength: f32,
+    /// Distance of the neighbours in pixels.
+    pub edl_radius: f32,
+}
+
+impl Default for PointSettings {
+    fn default() -> Self {
+        Self {
+            point_size: 0.02,
+            edl: true,
+            edl_strength: 1.0,
+            edl_radius: 1.5,
+        }
+    }
+}
+
+/// Attachments of the point pass, sampled by the EDL pass.
+struct PointTargets {
+    color: Texture,
+    depth: Texture,
+}
+
+impl PointTargets {
+    fn new(context: &Arc<Context>, extent: vk::Extent2D, depth_format: vk::Format) -> Self {
+        let create_target = |format, usage, aspect| {
+            let image = Image::create(
+                Arc::clone(context),
+                ImageParameters {
+                    mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    extent,
+                    format,
+                    usage: usage | vk::ImageUsageFlags::SAMPLED,
+                    ..Default::default()
+                },
+            );
+            let view = image.create_view(vk::ImageViewType::TYPE_2D, aspect);
+            let sampler = create_sampler(context, vk::Filter::NEAREST, vk::Filter::NEAREST);
+            Texture::new(Arc::clone(context), image, view, Some(sampler))
+        };
+
+        Self {
+            color: create_target(
+                COLOR_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+            ),
+            depth: create_target(
+                depth_format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                vk::ImageAspectFlags::DEPTH,
+            ),
+        }
+    }
+}
+
+/// Draw a point cloud with vertex pulling, then shade it with Eye-Dome Lighting
+/// into the swapchain image.
+///
+/// The points are split in storage buffers no larger than the device allows.
+/// With a memory budget only the chunks closest to the camera are resident,
+/// see [`ChunkStreamer`]. Only the chunks in the view frustum are drawn.
+pub struct PointCloudRenderer {
+    context: Arc<Context>,
+    chunks: ChunkStreamer,
+    chunk_bvh: Bvh<f32>,
+    visible_chunks: Vec<usize>,
+    camera_descriptors: Descriptors,
+    chunk_descriptors: Descriptors,
+    edl_descriptors: Descriptors,
+    point_layout: vk::PipelineLayout,
+    point_pipeline: vk::Pipeline,
+    edl_layout: vk::PipelineLayout,
+    edl_pipeline: vk::Pipeline,
+    output_format: vk::SurfaceFormatKHR,
+    depth_format: vk::Format,
+    max_point_size: f32,
+    targets: PointTargets,
+}
+
+impl PointCloudRenderer {
+    /// Upload `points`, or stream them within `budget` bytes of device local memory.
+    pub fn new(
+        context: &Arc<Context>,
+        points: Vec<Point>,
+        budget: Option<vk::DeviceSize>,
+        camera_uniforms: &CameraUniforms,
+        extent: vk::Extent2D,
+        output_format: vk::SurfaceFormatKHR,
+    ) -> Self {
+        let chunks = create_chunks(context, points, budget);
+
+        let camera_descriptors = create_descriptors(
+            context,
+            &[vk::DescriptorType::UNIFORM_BUFFER],
+            vk::ShaderStageFlags::VERTEX,
+            camera_uniforms.count() as _,
+        );
+        for (index, set) in camera_descriptors.sets().iter().enumerate() {
+            let buffer_info = [camera_uniforms.descriptor_info(index)];
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&buffer_info);
+            unsafe { context.device().update_descriptor_sets(&[write], &[]) };
+        }
+
+        let chunk_descriptors = create_descriptors(
+            context,
+            &[vk::DescriptorType::STORAGE_BUFFER],
+            vk::ShaderStageFlags::VERTEX,
+            chunks.chunk_count() as _,
+        );
+        for index in 0..chunks.chunk_count() {
+            write_chunk_descriptors(context, &chunks, index, &chunk_descriptors);
+        }
+
+        let depth_format = context
+            .find_supported_format(
+                &[vk::Format::D32_SFLOAT, vk::Format::D16_UNORM],
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            )
+            .expect("Failed to find a sampled depth format");
+        let targets = PointTargets::new(context, extent, depth_format);
+        let edl_descriptors = create_edl_descriptors(context, &targets);
+
+        let point_layout = create_pipeline_layout(
+            context,
+            &[camera_descriptors.layout(), chunk_descriptors.layout()],
+            vk::ShaderStageFlags::VERTEX,
+            size_of::<PointConstants>(),
+        );
+        let point_pipeline = create_point_pipeline(context, point_layout, depth_format);
+        let edl_layout = create_pipeline_layout(
+            context,
+            &[edl_descriptors.layout()],
+            vk::ShaderStageFlags::FRAGMENT,
+            size_of::<EdlConstants>(),
+        );
+        let edl_pipeline = create_edl_pipeline(context, edl_layout, output_format);
+
+        // Points are 1 pixel wide without the largePoints feature.
+        let max_point_size = if context.enabled_device_features().features.large_points == vk::TRUE
+        {
+            context.physical_device_limits().point_size_range[1]
+        } else {
+            MIN_POINT_SIZE
+        };
+
+        let chunk_bounds = (0..chunks.chunk_count())
+            .map(|index| chunks.bounds(index))
+            .collect::<Vec<_>>();
+
+        Self {
+            context: Arc::clone(context),
+            chunks,
+            chunk_bvh: Bvh::new(&chunk_bounds),
+            visible_chunks: (0..chunk_bounds.len()).collect(),
+            camera_descriptors,
+            chunk_descriptors,
+            edl_descriptors,
+            point_layout,
+            point_pipeline,
+            edl_layout,
+            edl_pipeline,
+            output_format,
+            depth_format,
+            max_point_size,
+            targets,
+        }
+    }
+
+    /// Recreate the targets for a new swapchain. The previous targets, their
+    /// descriptors and pipeline go to the [`vks::DeletionQueue`].
+    pub fn resize(&mut self, extent: vk::Extent2D, output_format: vk::SurfaceFormatKHR) {
+        let targets = PointTargets::new(&self.context, extent, self.depth_format);
+        let edl_descriptors = create_edl_descriptors(&self.context, &targets);
+        let deletion_queue = self.context.deletion_queue();
+        deletion_queue.enqueue(std::mem::replace(&mut self.targets, targets));
+        deletion_queue.enqueue(std::mem::replace(
+            &mut self.edl_descriptors,
+            edl_descriptors,
+        ));
+
+        if output_format != self.output_format {
+            let pipeline = std::mem::replace(
+                &mut self.edl_pipeline,
+                create_edl_pipeline(&self.context, self.edl_layout, output_format),
+            );
+            deletion_queue
+                .enqueue_destroy(move |device| unsafe { device.destroy_pipeline(pipeline, None) });
+            self.output_format = output_format;
+        }
+    }
+
+    /// Update the resident chunks for a camera at `eye`.
+    ///
+    /// Must be called once per frame, after waiting for the in flight fence
+    /// and before recording the frame.
+    pub fn update_streaming(&mut self, eye: Point3<f32>) {
+        for index in self.chunks.update(eye) {
+            write_chunk_descriptors(&self.context, &self.chunks, index, &self.chunk_descriptors);
+        }
+    }
+
+    /// Select the chunks drawn by the next frame, those in the frustum of `view_proj`.
+    pub fn update_visibility(&mut self, view_proj: Matrix4<f32>) {
+        self.visible_chunks.clear();
+        self.chunk_bvh.query_frustum(
+            &Frustum::from_view_proj(view_proj),
+            &mut self.visible_chunks,
+        );
+    }
+
+    /// Point where `ray` enters the bounds of the nearest chunk it hits.
+    pub fn pick(&self, ray: &Ray<f32>) -> Option<Point3<f32>> {
+        pick_point(&self.chunk_bvh, ray)
+    }
+
+    pub fn residency_stats(&self) -> ResidencyStats {
+        self.chunks.stats()
+    }
+
+    /// Record the point and EDL passes, leaving `output` in the color attachment layout.
+    pub fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        output: (&Image, vk::ImageView),
+        settings: &PointSettings,
+        z_planes: (f32, f32),
+    ) {
+        let device = self.context.device();
+        let extent = vk::Extent2D {
+            width: output.0.extent.width,
+            height: output.0.extent.height,
+        };
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        cmd_transition_images_layouts(
+            command_buffer,
+            &[
+                LayoutTransition {
+                    image: &self.targets.color.image,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    mips_range: MipsRange::All,
+                },
+                LayoutTransition {
+                    image: &self.targets.depth.image,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    mips_range: MipsRange::All,
+                },
+            ],
+        );
+
+        unsafe {
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+        }
+
+        // Point pass
+        {
+            let color_attachment_info = RenderingAttachmentInfo::default()
+                .clear_value(vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 0.0],
+                    },
+                })
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .image_view(self.targets.color.view)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE);
+
+            let depth_attachment_info = RenderingAttachmentInfo::default()
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                })
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .image_view(self.targets.depth.view)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE);
+
+            let rendering_info = RenderingInfo::default()
+                .color_attachments(std::slice::from_ref(&color_attachment_info))
+                .depth_attachment(&depth_attachment_info)
+                .layer_count(1)
+                .render_area(render_area);
+
+            let constants = PointConstants {
+                viewport_height: extent.height as _,
+                point_size: settings.point_size,
+                min_point_size: MIN_POINT_SIZE,
+                max_point_size: self.max_point_size,
+            };
+
+            unsafe {
+                self.context
+                    .dynamic_rendering()
+                    .cmd_begin_rendering(command_buffer, &rendering_info);
+                self.context.cmd_begin_pass(command_buffer, "points");
+
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.point_pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.point_layout,
+                    0,
+                    &self.camera_descriptors.sets()[frame_index..=frame_index],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.point_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    any_as_u8_slice(&constants),
+                );
+                for &index in &self.visible_chunks {
+                    let Some((_, count)) = self.chunks.resident_chunk(index) else {
+                        continue;
+                    };
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.point_layout,
+                        1,
+                        &self.chunk_descriptors.sets()[index..=index],
+                        &[],
+                    );
+                    device.cmd_draw(command_buffer, count, 1, 0, 0);
+                }
+
+                self.context.cmd_end_pass(command_buffer);
+                self.context
+                    .dynamic_rendering()
+                    .cmd_end_rendering(command_buffer);
+            }
+        }
+
+        cmd_transition_images_layouts(
+            command_buffer,
+            &[
+                LayoutTransition {
+                    image: &self.targets.color.image,
+                    old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    mips_range: MipsRange::All,
+                },
+                LayoutTransition {
+                    image: &self.targets.depth.image,
+                    old_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    new_layout: self.targets.depth.sampling_layout(),
+                    mips_range: MipsRange::All,
+                },
+            ],
+        );
+
+        // EDL pass
+        {
+            output.0.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+
+            let color_attachment_info = RenderingAttachmentInfo::default()
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .image_view(output.1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE);
+
+            let rendering_info = RenderingInfo::default()
+                .color_attachments(std::slice::from_ref(&color_attachment_info))
+                .layer_count(1)
+                .render_area(render_area);
+
+            let output_transform = self
+                .context
+                .color_policy()
+                .output_transform(self.output_format);
+            let constants = EdlConstants {
+                z_near: z_planes.0,
+                z_far: z_planes.1,
+                strength: if settings.edl {
+                    settings.edl_strength
+                } else {
+                    0.0
+                },
+                radius: settings.edl_radius,
+                background: BACKGROUND_COLOR,
+                encode_srgb: (output_transform == OutputTransform::ShaderSrgb) as _,
+            };
+
+            unsafe {
+                self.context
+                    .dynamic_rendering()
+                    .cmd_begin_rendering(command_buffer, &rendering_info);
+                self.context.cmd_begin_pass(command_buffer, "edl");
+
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.edl_pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.edl_layout,
+                    0,
+                    self.edl_descriptors.sets(),
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.edl_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    any_as_u8_slice(&constants),
+                );
+                device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+                self.context.cmd_end_pass(command_buffer);
+                self.context
+                    .dynamic_rendering()
+                    .cmd_end_rendering(command_buffer);
+            }
+        }
+    }
+}
+
+impl Drop for PointCloudRenderer {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.point_pipeline, None);
+            device.destroy_pipeline_layout(self.point_layout, None);
+            device.destroy_pipeline(self.edl_pipeline, None);
+            device.destroy_pipeline_layout(self.edl_layout, None);
+        }
+    }
+}
+
+/// Split `points` in storage buffers of at most [`MAX_CHUNK_SIZE`] bytes, or
+/// [`STREAMED_CHUNK_SIZE`] with a `budget`, and the storage buffer range of the device.
+///
+/// Each chunk goes through its own staging buffer so the host visible memory
+/// used at once stays bounded whatever the size of the cloud.
+fn create_chunks(
+    context: &Arc<Context>,
+    points: Vec<Point>,
+    budget: Option<vk::DeviceSize>,
+) -> ChunkStreamer {
+    let max_range = context.physical_device_limits().max_storage_buffer_range as vk::DeviceSize;
+    let chunk_size = if budget.is_some() {
+        STREAMED_CHUNK_SIZE
+    } else {
+        MAX_CHUNK_SIZE
+    };
+    let chunk_points = (max_range.min(chunk_size) / size_of::<Point>() as vk::DeviceSize) as usize;
+
+    let point_count = points.len();
+    let size = std::mem::size_of_val(points.as_slice());
+    let chunks = ChunkStreamer::new(context, points, chunk_points, budget);
+    match budget {
+        Some(budget) => tracing::info!(
+            "Streaming {} points, {} MiB in {} buffers within {} MiB",
+            point_count,
+            size / (1024 * 1024),
+            chunks.chunk_count(),
+            budget / (1024 * 1024)
+        ),
+        None => tracing::info!(
+            "Uploaded {} points, {} MiB in {} buffers",
+            point_count,
+            size / (1024 * 1024),
+            chunks.chunk_count()
+        ),
+    }
+    chunks
+}
+
+/// Point the descriptor set of the chunk at `index` to its buffer, if resident.
+fn write_chunk_descriptors(
+    context: &Context,
+    chunks: &ChunkStreamer,
+    index: usize,
+    descriptors: &Descriptors,
+) {
+    let Some((buffer, _)) = chunks.resident_chunk(index) else {
+        return;
+    };
+    let buffer_info = [vk::DescriptorBufferInfo {
+        buffer: buffer.buffer,
+        offset: 0,
+        range: vk::WHOLE_SIZE,
+    }];
+    let write = vk::WriteDescriptorSet::default()
+        .dst_set(descriptors.sets()[index])
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(&buffer_info);
+    unsafe { context.device().update_descriptor_sets(&[write], &[]) };
+}
+
+/// Create `set_count` descriptor sets with one binding per entry of `types`.
+fn create_descriptors(
+    context: &Arc<Context>,
+    types: &[vk::DescriptorType],
+    stages: vk::ShaderStageFlags,
+    set_count: u32,
+) -> Descriptors {
+    let device = context.device();
+
+    let bindings = types
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(index as _)
+                .descriptor_type(*ty)
+                .descriptor_count(1)
+                .stage_flags(stages)
+        })
+        .collect::<Vec<_>>();
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = types
+        .iter()
+        .map(|ty| vk::DescriptorPoolSize {
+            ty: *ty,
+            descriptor_count: set_count,
+        })
+        .collect::<Vec<_>>();
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(set_count);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = vec![layout; set_count as usize];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+/// Create the set sampling `targets` in the EDL pass. The layout is the same
+/// for every targets so the set matches the EDL pipeline layout.
+fn create_edl_descriptors(context: &Arc<Context>, targets: &PointTargets) -> Descriptors {
+    let descriptors = create_descriptors(
+        context,
+        &[vk::DescriptorType::COMBINED_IMAGE_SAMPLER; 2],
+        vk::ShaderStageFlags::FRAGMENT,
+        1,
+    );
+    write_edl_descriptors(context, descriptors.sets()[0], targets);
+    descriptors
+}
+
+fn write_edl_descriptors(context: &Context, set: vk::DescriptorSet, targets: &PointTargets) {
+    let image_info = |texture: &Texture| {
+        [vk::DescriptorImageInfo::default()
+            .image_layout(texture.sampling_layout())
+            .image_view(texture.sampling_view())
+            .sampler(texture.sampler.unwrap())]
+    };
+    let color_info = image_info(&targets.color);
+    let depth_info = image_info(&targets.depth);
+
+    let writes = [
+        vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&color_info),
+        vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&depth_info),
+    ];
+    unsafe { context.device().update_descriptor_sets(&writes, &[]) };
+}
+
+fn create_pipeline_layout(
+    context: &Context,
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_stages: vk::ShaderStageFlags,
+    push_constant_size: usize,
+) -> vk::PipelineLayout {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: push_constant_stages,
+        offset: 0,
+        size: push_constant_size as _,
+    }];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+fn create_point_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    depth_format: vk::Format,
+) -> vk::Pipeline {
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    create_pass_pipeline(
+        context,
+        "point_cloud",
+        vk::PrimitiveTopology::POINT_LIST,
+        Some(&depth_stencil_info),
+        COLOR_FORMAT,
+        Some(depth_format),
+        layout,
+    )
+}
+
+fn create_edl_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    output_format: vk::SurfaceFormatKHR,
+) -> vk::Pipeline {
+    create_pass_pipeline(
+        context,
+        "edl",
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        None,
+        context.color_policy().attachment_format(output_format),
+        None,
+        layout,
+    )
+}
+
+fn create_pass_pipeline(
+    context: &Arc<Context>,
+    shader: &str,
+    topology: vk::PrimitiveTopology,
+    depth_stencil_info: Option<&vk::PipelineDepthStencilStateCreateInfo>,
+    color_format: vk::Format,
+    depth_format: Option<vk::Format>,
+    layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(false)
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    create_pipeline::<()>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new(shader),
+            fragment_shader_params: ShaderParameters::new(shader),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info,
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[color_format],
+            depth_attachment_format: depth_format,
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
+}
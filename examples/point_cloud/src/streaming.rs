@@ -0,0 +1,329 @@
+use std::{
+    fmt,
+    mem::size_of,
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use ash::vk;
+use math::{
+    cgmath::{Point3, Vector3},
+    Aabb,
+};
+use vks::{create_device_local_buffer_with_data, Buffer, Context, MAX_FRAMES_IN_FLIGHT};
+
+use crate::point_cloud::Point;
+
+/// Uploads in progress at once, bounding the staging memory of the uploader.
+const MAX_PENDING_UPLOADS: usize = 2;
+/// Bits of the grid cell coordinates interleaved to sort the points.
+const MORTON_BITS: u32 = 10;
+
+/// State of the points of a chunk in device local memory.
+enum Residency {
+    Evicted,
+    Loading,
+    Resident(Buffer),
+    /// Evicted but still read by the frames in flight.
+    Retiring {
+        _buffer: Buffer,
+        frames_left: u32,
+    },
+}
+
+/// Consecutive points drawn with a single call.
+struct StreamedChunk {
+    range: Range<usize>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    residency: Residency,
+}
+
+impl StreamedChunk {
+    fn new(points: &[Point], range: Range<usize>, residency: Residency) -> Self {
+        let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for point in &points[range.clone()] {
+            let [x, y, z] = point.position;
+            min = Vector3::new(min.x.min(x), min.y.min(y), min.z.min(z));
+            max = Vector3::new(max.x.max(x), max.y.max(y), max.z.max(z));
+        }
+        Self {
+            range,
+            min,
+            max,
+            residency,
+        }
+    }
+
+    fn size(&self) -> vk::DeviceSize {
+        (self.range.len() * size_of::<Point>()) as _
+    }
+
+    /// Distance from `eye` to the bounds of the chunk, 0 inside.
+    fn distance(&self, eye: Point3<f32>) -> f32 {
+        let dx = (self.min.x - eye.x).max(eye.x - self.max.x).max(0.0);
+        let dy = (self.min.y - eye.y).max(eye.y - self.max.y).max(0.0);
+        let dz = (self.min.z - eye.z).max(eye.z - self.max.z).max(0.0);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+/// Residency of the chunks, shown in the title of the window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResidencyStats {
+    pub resident_chunks: usize,
+    pub loading_chunks: usize,
+    pub total_chunks: usize,
+    pub resident_bytes: vk::DeviceSize,
+    pub total_bytes: vk::DeviceSize,
+}
+
+impl fmt::Display for ResidencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} MiB resident, {}/{} chunks, {} loading",
+            self.resident_bytes / (1024 * 1024),
+            self.total_bytes / (1024 * 1024),
+            self.resident_chunks,
+            self.total_chunks,
+            self.loading_chunks
+        )
+    }
+}
+
+/// Upload thread of the streamed chunks, with its own [`Context`].
+struct Uploader {
+    requests: Sender<(usize, Range<usize>)>,
+    uploads: Receiver<(usize, Buffer)>,
+}
+
+impl Uploader {
+    fn new(context: &Arc<Context>, points: Arc<[Point]>) -> Self {
+        let (requests, request_receiver) = mpsc::channel::<(usize, Range<usize>)>();
+        let (upload_sender, uploads) = mpsc::channel();
+        let context = Arc::new(context.new_thread());
+        thread::Builder::new()
+            .name("point-uploader".into())
+            .spawn(move || {
+                // Ends when the streamer is dropped.
+                for (index, range) in request_receiver {
+                    let buffer = create_device_local_buffer_with_data::<u8, _>(
+                        &context,
+                        vk::BufferUsageFlags::STORAGE_BUFFER,
+                        &points[range],
+                    );
+                    if upload_sender.send((index, buffer)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn point uploader thread");
+
+        Self { requests, uploads }
+    }
+}
+
+/// Points split in chunks kept in device local memory.
+///
+/// Without budget all the chunks are uploaded up front and stay resident.
+/// With a budget the points are sorted spatially so each chunk covers a
+/// compact region, and [`ChunkStreamer::update`] keeps the chunks closest to
+/// the camera resident within the budget. Missing chunks are uploaded on a
+/// background thread and the others are evicted, their memory being freed
+/// once the frames in flight are done with them.
+pub struct ChunkStreamer {
+    chunks: Vec<StreamedChunk>,
+    budget: Option<vk::DeviceSize>,
+    uploader: Option<Uploader>,
+}
+
+impl ChunkStreamer {
+    /// Split `points` in chunks of at most `chunk_points` points.
+    pub fn new(
+        context: &Arc<Context>,
+        mut points: Vec<Point>,
+        chunk_points: usize,
+        budget: Option<vk::DeviceSize>,
+    ) -> Self {
+        let ranges = |count: usize| {
+            (0..count)
+                .step_by(chunk_points.max(1))
+                .map(move |start| start..(start + chunk_points.max(1)).min(count))
+        };
+
+        let Some(budget) = budget else {
+            let chunks = ranges(points.len())
+                .map(|range| {
+                    let buffer = create_device_local_buffer_with_data::<u8, _>(
+                        context,
+                        vk::BufferUsageFlags::STORAGE_BUFFER,
+                        &points[range.clone()],
+                    );
+                    StreamedChunk::new(&points, range, Residency::Resident(buffer))
+                })
+                .collect();
+            return Self {
+                chunks,
+                budget: None,
+                uploader: None,
+            };
+        };
+
+        sort_spatially(&mut points);
+        let chunks = ranges(points.len())
+            .map(|range| StreamedChunk::new(&points, range, Residency::Evicted))
+            .collect();
+        Self {
+            chunks,
+            budget: Some(budget),
+            uploader: Some(Uploader::new(context, points.into())),
+        }
+    }
+}
+
+impl ChunkStreamer {
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Bounds of the points of the chunk at `index`.
+    pub fn bounds(&self, index: usize) -> Aabb<f32> {
+        let chunk = &self.chunks[index];
+        Aabb::new(chunk.min, chunk.max)
+    }
+
+    /// Buffer and point count of the resident chunk at `index`.
+    pub fn resident_chunk(&self, index: usize) -> Option<(&Buffer, u32)> {
+        let chunk = &self.chunks[index];
+        match &chunk.residency {
+            Residency::Resident(buffer) => Some((buffer, chunk.range.len() as _)),
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self) -> ResidencyStats {
+        let mut stats = ResidencyStats {
+            total_chunks: self.chunks.len(),
+            ..Default::default()
+        };
+        for chunk in &self.chunks {
+            stats.total_bytes += chunk.size();
+            match chunk.residency {
+                Residency::Resident(_) => {
+                    stats.resident_chunks += 1;
+                    stats.resident_bytes += chunk.size();
+                }
+                Residency::Loading => stats.loading_chunks += 1,
+                Residency::Evicted | Residency::Retiring { .. } => {}
+            }
+        }
+        stats
+    }
+
+    /// Update the residency of the chunks for a camera at `eye`.
+    ///
+    /// Must be called once per frame, after waiting for the in flight fence
+    /// and before recording the frame.
+    ///
+    /// # Returns
+    ///
+    /// The indices of the chunks that became resident, whose descriptors must
+    /// be written before recording.
+    pub fn update(&mut self, eye: Point3<f32>) -> Vec<usize> {
+        for chunk in self.chunks.iter_mut() {
+            if let Residency::Retiring { frames_left, .. } = &mut chunk.residency {
+                *frames_left = frames_left.saturating_sub(1);
+                if *frames_left == 0 {
+                    chunk.residency = Residency::Evicted;
+                }
+            }
+        }
+
+        let (Some(uploader), Some(budget)) = (self.uploader.as_ref(), self.budget) else {
+            return Vec::new();
+        };
+
+        let mut ready = Vec::new();
+        while let Ok((index, buffer)) = uploader.uploads.try_recv() {
+            self.chunks[index].residency = Residency::Resident(buffer);
+            ready.push(index);
+        }
+
+        let distances = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.distance(eye))
+            .collect::<Vec<_>>();
+        let mut order = (0..self.chunks.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| distances[*a].total_cmp(&distances[*b]));
+
+        let mut pending = self
+            .chunks
+            .iter()
+            .filter(|chunk| matches!(chunk.residency, Residency::Loading))
+            .count();
+        let mut used = 0;
+        for index in order {
+            let chunk = &mut self.chunks[index];
+            let wanted = used + chunk.size() <= budget;
+            if wanted {
+                used += chunk.size();
+            }
+
+            match (&chunk.residency, wanted) {
+                (Residency::Evicted, true) if pending < MAX_PENDING_UPLOADS => {
+                    uploader
+                        .requests
+                        .send((index, chunk.range.clone()))
+                        .expect("Point uploader thread stopped");
+                    chunk.residency = Residency::Loading;
+                    pending += 1;
+                }
+                (Residency::Resident(_), false) => {
+                    if let Residency::Resident(buffer) =
+                        std::mem::replace(&mut chunk.residency, Residency::Evicted)
+                    {
+                        chunk.residency = Residency::Retiring {
+                            _buffer: buffer,
+                            frames_left: MAX_FRAMES_IN_FLIGHT,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        ready
+    }
+}
+
+/// Sort `points` along a Morton curve over their bounds, so consecutive points are close.
+fn sort_spatially(points: &mut [Point]) {
+    let (min, max) = points
+        .iter()
+        .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), point| {
+            (
+                std::array::from_fn(|axis| min[axis].min(point.position[axis])),
+                std::array::from_fn(|axis| max[axis].max(point.position[axis])),
+            )
+        });
+
+    let cells = ((1 << MORTON_BITS) - 1) as f32;
+    points.sort_by_cached_key(|point| {
+        let mut code = 0u32;
+        for (axis, position) in point.position.into_iter().enumerate() {
+            let extent = (max[axis] - min[axis]).max(f32::EPSILON);
+            let cell = ((position - min[axis]) / extent * cells) as u32;
+            for bit in 0..MORTON_BITS {
+                code |= ((cell >> bit) & 1) << (bit * 3 + axis as u32);
+            }
+        }
+        code
+    });
+}
@@ -7,7 +7,7 @@ use ash::{
 };
 use tracing::{debug, info, Level};
 use vks::{
-    allocate_command_buffers, cmd_transition_images_layouts, create_device_local_buffer_with_data, create_pipeline, Buffer, Camera, Context, Descriptors, LayoutTransition, MipsRange, PipelineParameters, RenderData, RenderError, ShaderParameters, Swapchain, SwapchainSupportDetails, Texture, Vertex, VulkanExampleBase, WindowApp
+    cmd_transition_images_layouts, create_device_local_buffer_with_data, create_pipeline, Buffer, Camera, Context, Descriptors, LayoutTransition, MipsRange, PipelineParameters, RenderData, RenderError, ShaderParameters, Texture, Vertex, VulkanExampleBase, WindowApp
 };
 use winit::{
     application::ApplicationHandler,
@@ -17,11 +17,6 @@ use winit::{
     keyboard::Key,
     window::{Fullscreen, Window, WindowId},
 };
-pub const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
-    format: vk::Format::R16G16B16A16_SFLOAT,
-    color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
-};
-
 struct App {
     window: Option<Window>,
     triangle_app: Option<TriangleApp>,
@@ -81,7 +76,10 @@ impl ApplicationHandler for App {
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
-        self.triangle_app.as_mut().unwrap().on_exit();
+        // Drop the app while the window is alive, the surface must be destroyed first.
+        if let Some(mut app) = self.triangle_app.take() {
+            app.on_exit();
+        }
     }
 }
 
@@ -142,6 +140,9 @@ impl QuadModel {
             &vertices,
         );
 
+        indices.set_name("quad indices");
+        vertices.set_name("quad vertices");
+
         Self { vertices, indices }
     }
 }
@@ -151,13 +152,17 @@ pub struct TriangleApp {
     model: QuadModel,
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+    color_format: vk::Format,
 
     camera: Camera,
     time: Instant,
     dirty_swapchain: bool,
 }
 
-fn prepare_pipeline(context: &Arc<Context>) -> (vk::Pipeline, vk::PipelineLayout) {
+fn prepare_pipeline(
+    context: &Arc<Context>,
+    color_format: vk::Format,
+) -> (vk::Pipeline, vk::PipelineLayout) {
     let device = context.device();
     let layout = {
         let layout_info = vk::PipelineLayoutCreateInfo::default();
@@ -226,17 +231,22 @@ fn prepare_pipeline(context: &Arc<Context>) -> (vk::Pipeline, vk::PipelineLayout
                 fragment_shader_params: ShaderParameters::new("quad"),
                 multisampling_info: &multisampling_info,
                 viewport_info: &viewport_info,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
                 rasterizer_info: &rasterizer_info,
                 dynamic_state_info: Some(&dynamic_state_info),
                 depth_stencil_info: Some(&depth_stencil_info),
+                stencil: None,
+                shading_rate: None,
+                shading_rate_attachment: false,
                 color_blend_attachments: &color_blend_attachments,
-                color_attachment_formats: &[vk::Format::R16G16B16A16_SFLOAT],
+                color_attachment_formats: &[color_format],
                 depth_attachment_format: None,
                 layout,
                 parent: None,
                 allow_derivatives: false,
             },
         )
+        .expect("Failed to create graphics pipeline")
     };
 
     (pipeline, layout)
@@ -257,7 +267,11 @@ impl TriangleApp {
         let context = &base.context;
         let model = QuadModel::new(context);
 
-        let (pipeline, pipeline_layout) = prepare_pipeline(context);
+        let color_format = context
+            .color_policy()
+            .attachment_format(base.swapchain.properties().format);
+        let (pipeline, pipeline_layout) = prepare_pipeline(context, color_format);
+        context.set_object_name(pipeline, "quad pipeline");
         Self {
             model,
             camera: Camera::default(),
@@ -265,6 +279,7 @@ impl TriangleApp {
             dirty_swapchain: false,
             pipeline_layout,
             pipeline,
+            color_format,
             base,
         }
     }
@@ -308,37 +323,6 @@ impl WindowApp for TriangleApp {
         // self.input_state = self.input_state.handle_device_event(event);
     }
 
-    fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool) {
-        tracing::debug!("Recreating swapchain.");
-
-        self.base.context.graphics_queue_wait_idle();
-
-        unsafe {
-            self.base.context.device().free_command_buffers(
-                self.base.context.general_command_pool(),
-                &self.base.command_buffers,
-            )
-        };
-
-        let swapchain_support_details = SwapchainSupportDetails::new(
-            self.base.context.physical_device(),
-            self.base.context.surface(),
-            self.base.context.surface_khr(),
-        );
-
-        self.base.swapchain = Swapchain::create(
-            Arc::clone(&self.base.context),
-            swapchain_support_details,
-            dimensions,
-            hdr.then_some(HDR_SURFACE_FORMAT),
-            vsync,
-        );
-
-        self.base.on_new_swapchain();
-        self.base.command_buffers =
-            allocate_command_buffers(&self.base.context, self.base.swapchain.image_count());
-    }
-
     fn end_frame(&mut self, window: &Window) {
         let new_time = Instant::now();
         let delta_s = (new_time - self.time).as_secs_f32();
@@ -350,14 +334,21 @@ impl WindowApp for TriangleApp {
             if width > 0 && height > 0 {
                 self.base
                     .recreate_swapchain(window.inner_size().into(), false, true);
+                self.base.context.color_policy().audit_attachment(
+                    "quad",
+                    self.color_format,
+                    self.base.swapchain.properties().format.format,
+                );
             } else {
                 return;
             }
         }
-        self.dirty_swapchain = matches!(
-            self.render(window, self.camera),
-            Err(RenderError::DirtySwapchain)
-        );
+        self.dirty_swapchain = match self.render(window, self.camera) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
     }
 
     fn on_exit(&mut self) {
@@ -379,18 +370,9 @@ impl WindowApp for TriangleApp {
                 .wait_for_fences(&wait_fences, true, u64::MAX)
                 .unwrap()
         };
+        self.base.in_flight_frames.collect_deletions();
 
-        let result =
-            self.base
-                .swapchain
-                .acquire_next_image(None, Some(image_available_semaphore), None);
-        let image_index = match result {
-            Ok((image_index, _)) => image_index,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return Err(RenderError::DirtySwapchain);
-            }
-            Err(error) => panic!("Error while acquiring next image. Cause: {}", error),
-        };
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
 
         unsafe {
             self.base
@@ -456,6 +438,7 @@ impl WindowApp for TriangleApp {
                 .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
                 .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
 
+            let _queue_guard = self.base.context.lock_queue();
             unsafe {
                 self.base
                     .context
@@ -584,6 +567,7 @@ impl WindowApp for TriangleApp {
                         .cmd_begin_rendering(command_buffer, &rendering_info)
                 };
             }
+            self.base.context.cmd_begin_pass(command_buffer, "quad");
             let device = self.base.context.device();
 
             // Bind skybox pipeline
@@ -615,6 +599,7 @@ impl WindowApp for TriangleApp {
 
             // Draw skybox
             unsafe { device.cmd_draw_indexed(command_buffer, 36, 1, 0, 0, 0) };
+            self.base.context.cmd_end_pass(command_buffer);
 
             unsafe {
                 self.base
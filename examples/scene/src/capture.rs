@@ -0,0 +1,52 @@
+use std::{error::Error, path::Path};
+
+use ash::vk;
+
+/// Format the renders are converted to before being saved to png, the
+/// scene renders to a float format.
+pub const PNG_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Check that images of `format` can be saved to png.
+///
+/// # Returns
+///
+/// True if the red and blue channels of the texels must be swapped.
+pub fn png_swizzle(format: vk::Format) -> Result<bool, Box<dyn Error>> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+        format => Err(format!("Unsupported format {:?}", format).into()),
+    }
+}
+
+/// Save the 8 bits rgba texels `pixels` to a png file, swapping red and blue if `swizzle`.
+pub fn save_png<P: AsRef<Path>>(
+    path: P,
+    mut pixels: Vec<u8>,
+    [width, height]: [u32; 2],
+    swizzle: bool,
+) -> Result<(), Box<dyn Error>> {
+    if swizzle {
+        pixels
+            .chunks_exact_mut(4)
+            .for_each(|pixel| pixel.swap(0, 2));
+    }
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+    Ok(())
+}
+
+/// Save the 32 bits float rgba texels `pixels`, in native endianness, to an exr file.
+pub fn save_exr<P: AsRef<Path>>(
+    path: P,
+    pixels: &[u8],
+    [width, height]: [u32; 2],
+) -> Result<(), Box<dyn Error>> {
+    let texels = pixels
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    let image = image::Rgba32FImage::from_raw(width, height, texels)
+        .ok_or("Texels do not match the extent")?;
+    image.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use ash::vk;
+use scene::{save_png, PNG_FORMAT};
+use tracing::info;
+
+use crate::TextureApp;
+
+/// Path of the captures saved with the `p` key.
+const CAPTURE_PATH: &str = "capture.png";
+
+impl TextureApp {
+    /// Save the current view, rendered at twice the resolution of the swapchain.
+    pub fn save_capture(&mut self) {
+        let extent = self.base.swapchain.properties().extent;
+        let target = self.create_offscreen_target(vk::Extent2D {
+            width: extent.width * 2,
+            height: extent.height * 2,
+        });
+        let camera = self.camera;
+        self.render_to(&target, &camera);
+
+        let extent = target.extent();
+        let result = save_png(
+            CAPTURE_PATH,
+            target.read_back_as(PNG_FORMAT),
+            [extent.width, extent.height],
+            false,
+        );
+        match result {
+            Ok(()) => info!("Capture saved to {}", CAPTURE_PATH),
+            Err(error) => tracing::error!("Failed to save capture {}: {}", CAPTURE_PATH, error),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use tracing::info;
+
+use crate::TextureApp;
+
+impl TextureApp {
+    /// Reuse the commands of the scene while it does not change, or record them every frame.
+    ///
+    /// Toggled with the `r` key. The commands are only reused in the frames
+    /// without ui, see [`vks::CommandBufferCache`].
+    pub fn toggle_command_cache(&mut self) {
+        let enabled = !self.base.command_cache.is_enabled();
+        self.base.command_cache.set_enabled(enabled);
+        info!("Command cache enabled: {}", enabled);
+    }
+}
@@ -0,0 +1,12 @@
+//! Tools of the viewer toggled or triggered from the keyboard, each adding
+//! its methods to [`crate::TextureApp`].
+
+mod capture;
+mod command_cache;
+mod panorama;
+mod probe_grid;
+mod shading_rate;
+mod texture_usage;
+mod turntable;
+
+pub use self::{shading_rate::create_shading_rate_image, texture_usage::create_texture_feedback};
@@ -0,0 +1,39 @@
+use scene::{save_exr, save_png};
+use tracing::info;
+use vks::{PanoramaCapture, PanoramaFormat};
+
+use crate::TextureApp;
+
+/// Path of the panoramas saved with the `o` key, or `O` for exr, without extension.
+const PANORAMA_PATH: &str = "panorama";
+/// Size in pixels of the faces rendered for the panoramas.
+const PANORAMA_FACE_SIZE: u32 = 1024;
+
+impl TextureApp {
+    /// Save the 360° panorama around the camera in `format`.
+    pub fn save_panorama(&mut self, format: PanoramaFormat) {
+        let capture = PanoramaCapture::new(
+            &self.base.context,
+            PANORAMA_FACE_SIZE,
+            self.base.scene_color_format,
+            self.base.depth_format,
+            self.scene_target.attachments().samples,
+            format,
+        );
+        let camera = self.camera;
+        let panorama = capture.capture(&camera, |_, ubo| {
+            self.render_ubo_to(capture.face_target(), ubo)
+        });
+
+        let path = format!("{}.{}", PANORAMA_PATH, format.extension());
+        let extent = [panorama.width, panorama.height];
+        let result = match format {
+            PanoramaFormat::Png => save_png(&path, panorama.data, extent, false),
+            PanoramaFormat::Exr => save_exr(&path, &panorama.data, extent),
+        };
+        match result {
+            Ok(()) => info!("Panorama saved to {}", path),
+            Err(error) => tracing::error!("Failed to save panorama {}: {}", path, error),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use tracing::info;
+use vks::{ProbeGrid, ProbeGridSettings};
+
+use crate::TextureApp;
+
+impl TextureApp {
+    /// Bake a grid of probes around the scene and show them, or remove it.
+    pub fn toggle_probe_grid(&mut self) {
+        if self.probe_grid.take().is_some() {
+            self.base.wait_idle_gpu();
+        } else {
+            let mut grid = ProbeGrid::new(
+                &self.base.context,
+                ProbeGridSettings::default(),
+                self.base.scene_color_format,
+                self.base.depth_format,
+                self.scene_target.attachments().samples,
+                self.shading_rate.is_some(),
+            );
+            let camera = self.camera;
+            grid.bake(&camera, |target, ubo| self.render_ubo_to(target, ubo));
+            self.probe_grid = Some(grid);
+        }
+        self.base.command_cache.invalidate();
+        info!("Probe grid enabled: {}", self.probe_grid.is_some());
+    }
+}
@@ -0,0 +1,92 @@
+use ash::vk;
+use math::cgmath::MetricSpace;
+use tracing::info;
+use vks::{Image, ShadingRateImage, VulkanExampleBase};
+
+use crate::TextureApp;
+
+/// Create the shading rate image of the swapchain, `None` if the device or
+/// the swapchain do not support it.
+pub fn create_shading_rate_image(base: &VulkanExampleBase) -> Option<ShadingRateImage> {
+    let transfers = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+    if !base.context.shading_rate_support().is_supported() {
+        tracing::warn!("Variable rate shading is not supported by the device");
+        None
+    } else if !base.swapchain.properties().usage.contains(transfers) {
+        tracing::warn!("Variable rate shading requires transfers from the swapchain images");
+        None
+    } else {
+        Some(ShadingRateImage::new(
+            &base.context,
+            base.swapchain.properties().extent,
+        ))
+    }
+}
+
+impl TextureApp {
+    /// Shade the scene at the rates of the shading rate image, or at full rate.
+    pub fn toggle_shading_rate(&mut self) {
+        if self.shading_rate.is_some() {
+            self.shading_rate_enabled = !self.shading_rate_enabled;
+            info!(
+                "Variable rate shading enabled: {}",
+                self.shading_rate_enabled
+            );
+        }
+    }
+
+    /// Show the rates of the last frame over the swapchain image, or hide them.
+    pub fn toggle_shading_rate_debug(&mut self) {
+        if self.shading_rate.is_some() {
+            self.shading_rate_debug = !self.shading_rate_debug;
+        }
+    }
+
+    /// Lower the rates while the camera moves, from its speed since the last frame.
+    pub fn update_shading_rate_motion(&mut self, delta_s: f32) {
+        let camera_position = self.camera.position();
+        if let Some(previous) = self.previous_camera_position.replace(camera_position) {
+            self.shading_rate_parameters.motion =
+                camera_position.distance(previous) / delta_s.max(f32::EPSILON);
+        }
+    }
+
+    /// Record the transition of the swapchain `image` for presentation.
+    ///
+    /// With a shading rate image, the frame is first copied for the rates of
+    /// the next one and the debug view is drawn over it if enabled.
+    pub fn cmd_transition_to_present(&self, command_buffer: vk::CommandBuffer, image: &Image) {
+        if let Some(shading_rate) = self.shading_rate.as_ref() {
+            // Keep the frame for the rates of the next one.
+            image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+            shading_rate.cmd_capture(command_buffer, image);
+
+            let layout = if self.shading_rate_debug {
+                image.cmd_transition_image_layout(
+                    command_buffer,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+                shading_rate.cmd_draw_debug(command_buffer, image);
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL
+            } else {
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+            };
+            image.cmd_transition_image_layout(
+                command_buffer,
+                layout,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            );
+        } else {
+            image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            );
+        }
+    }
+}
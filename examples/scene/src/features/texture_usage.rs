@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use tracing::info;
+use vks::{Context, Texture, TextureFeedback, MAX_FRAMES_IN_FLIGHT};
+
+use crate::TextureApp;
+
+/// Entries of the texture feedback buffer, one per 8x8 tile of a 1024x1024 screen.
+const TEXTURE_FEEDBACK_ENTRIES: u32 = 16384;
+
+/// Create the texture feedback of the scene, tracking the mips of the quad `texture`.
+pub fn create_texture_feedback(context: &Arc<Context>, texture: &Texture) -> TextureFeedback {
+    let mut feedback = TextureFeedback::new(
+        context,
+        TEXTURE_FEEDBACK_ENTRIES,
+        1,
+        MAX_FRAMES_IN_FLIGHT as _,
+    );
+    feedback.register("android", &texture.image);
+    feedback
+}
+
+impl TextureApp {
+    /// Log the mips of the textures sampled in the last frames, with the `t` key.
+    pub fn log_texture_usage(&self) {
+        if let Some(feedback) = self.texture_feedback.as_ref() {
+            info!("Texture usage: {}", feedback.report());
+        }
+    }
+}
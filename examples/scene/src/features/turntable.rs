@@ -0,0 +1,71 @@
+use std::{error::Error, path::Path};
+
+use scene::{save_png, PNG_FORMAT};
+use tracing::info;
+use vks::Turntable;
+
+use crate::TextureApp;
+
+/// Directory of the turntable frames saved with the `Y` key.
+const TURNTABLE_DIR: &str = "turntable";
+
+impl TextureApp {
+    /// Start orbiting the camera around its target, or stop where it is.
+    pub fn toggle_turntable(&mut self) {
+        self.turntable = match self.turntable {
+            Some(_) => None,
+            None => Some(Turntable::new(self.turntable_settings, &self.camera)),
+        };
+        self.turntable_position.reset(self.camera.position());
+        info!("Turntable enabled: {}", self.turntable.is_some());
+    }
+
+    /// Orbit the camera by the steps of the simulation in `delta_s`.
+    pub fn advance_turntable(&mut self, delta_s: f32) {
+        // The orbit advances at the fixed rate of the simulation and the camera
+        // is placed between its last two steps.
+        let mut orbit_camera = self.camera;
+        let alpha = self.base.simulation.advance(delta_s, |step| {
+            if let Some(turntable) = self.turntable.as_mut() {
+                turntable.advance(&mut orbit_camera, step);
+                self.turntable_position.set(orbit_camera.position());
+            }
+        });
+        if self.turntable.is_some() {
+            let target = self.camera.target();
+            self.camera
+                .look_at(self.turntable_position.interpolate(alpha), target);
+        }
+    }
+
+    /// Render a full revolution of the turntable to numbered png files in
+    /// [`TURNTABLE_DIR`], at the resolution of the swapchain.
+    pub fn save_turntable(&mut self) {
+        let turntable = Turntable::new(self.turntable_settings, &self.camera);
+        match self.write_turntable_frames(&turntable) {
+            Ok(()) => info!(
+                "{} turntable frames saved to {}",
+                turntable.frame_count(),
+                TURNTABLE_DIR
+            ),
+            Err(error) => tracing::error!("Failed to save turntable {}: {}", TURNTABLE_DIR, error),
+        }
+    }
+
+    fn write_turntable_frames(&mut self, turntable: &Turntable) -> Result<(), Box<dyn Error>> {
+        let target = self.create_offscreen_target(self.base.swapchain.properties().extent);
+        let extent = target.extent();
+        std::fs::create_dir_all(TURNTABLE_DIR)?;
+        for frame in 0..turntable.frame_count() {
+            let camera = turntable.frame_camera(&self.camera, frame);
+            self.render_to(&target, &camera);
+            save_png(
+                Path::new(TURNTABLE_DIR).join(format!("frame_{:04}.png", frame)),
+                target.read_back_as(PNG_FORMAT),
+                [extent.width, extent.height],
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
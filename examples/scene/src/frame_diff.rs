@@ -0,0 +1,146 @@
+use std::{error::Error, fmt, path::Path};
+
+use crate::save_png;
+
+/// Side of the square windows over which the SSIM is computed.
+const SSIM_WINDOW: usize = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+/// Amplification of the differences in the comparison image.
+const DIFF_GAIN: u8 = 8;
+
+/// Difference between two renders of the same view, compared on their color channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameDiff {
+    /// Largest difference of a channel, out of 255.
+    pub max_difference: u8,
+    pub differing_pixels: usize,
+    pub pixel_count: usize,
+    /// Peak signal to noise ratio in dB, infinite for identical renders.
+    pub psnr: f64,
+    /// Mean structural similarity of the luminance, 1 for identical renders.
+    pub ssim: f64,
+}
+
+impl FrameDiff {
+    /// Compare the 8 bits rgba texels `a` and `b` of two renders of `width` x `height`.
+    pub fn compute(a: &[u8], b: &[u8], [width, height]: [u32; 2]) -> Self {
+        let pixel_count = width as usize * height as usize;
+        assert!(
+            a.len() == pixel_count * 4 && b.len() == pixel_count * 4,
+            "Renders do not match the compared extent"
+        );
+
+        let mut max_difference = 0;
+        let mut differing_pixels = 0;
+        let mut squared_error = 0.0;
+        for (a, b) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+            let pixel_difference = (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap();
+            if pixel_difference > 0 {
+                differing_pixels += 1;
+                max_difference = max_difference.max(pixel_difference);
+            }
+            squared_error += (0..3)
+                .map(|c| (a[c] as f64 - b[c] as f64).powi(2))
+                .sum::<f64>();
+        }
+
+        let mse = squared_error / (pixel_count * 3).max(1) as f64;
+        let psnr = if mse == 0.0 {
+            f64::INFINITY
+        } else {
+            10.0 * (255.0 * 255.0 / mse).log10()
+        };
+
+        Self {
+            max_difference,
+            differing_pixels,
+            pixel_count,
+            psnr,
+            ssim: mean_ssim(a, b, width as usize, height as usize),
+        }
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+impl fmt::Display for FrameDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} pixels differ, max difference {}, PSNR {:.2} dB, SSIM {:.5}",
+            self.differing_pixels, self.pixel_count, self.max_difference, self.psnr, self.ssim
+        )
+    }
+}
+
+/// Save `a`, `b` and their amplified difference side by side to a png file.
+///
+/// `a` and `b` are 8 bits rgba texels, their red and blue are swapped if `swizzle`.
+pub fn save_comparison<P: AsRef<Path>>(
+    path: P,
+    a: &[u8],
+    b: &[u8],
+    [width, height]: [u32; 2],
+    swizzle: bool,
+) -> Result<(), Box<dyn Error>> {
+    let row_size = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_size * 3 * height as usize);
+    for (a, b) in a.chunks_exact(row_size).zip(b.chunks_exact(row_size)) {
+        pixels.extend_from_slice(a);
+        pixels.extend_from_slice(b);
+        pixels.extend(a.chunks_exact(4).zip(b.chunks_exact(4)).flat_map(|(a, b)| {
+            [
+                a[0].abs_diff(b[0]).saturating_mul(DIFF_GAIN),
+                a[1].abs_diff(b[1]).saturating_mul(DIFF_GAIN),
+                a[2].abs_diff(b[2]).saturating_mul(DIFF_GAIN),
+                u8::MAX,
+            ]
+        }));
+    }
+    save_png(path, pixels, [width * 3, height], swizzle)
+}
+
+/// Mean of the SSIM of the luminance over non overlapping windows.
+fn mean_ssim(a: &[u8], b: &[u8], width: usize, height: usize) -> f64 {
+    let luminance = |texels: &[u8], x: usize, y: usize| {
+        let texel = &texels[(y * width + x) * 4..];
+        0.299 * texel[0] as f64 + 0.587 * texel[1] as f64 + 0.114 * texel[2] as f64
+    };
+
+    let mut total = 0.0;
+    let mut window_count = 0;
+    for window_y in (0..height).step_by(SSIM_WINDOW) {
+        for window_x in (0..width).step_by(SSIM_WINDOW) {
+            let xs = window_x..(window_x + SSIM_WINDOW).min(width);
+            let ys = window_y..(window_y + SSIM_WINDOW).min(height);
+            let samples = ys
+                .flat_map(|y| xs.clone().map(move |x| (x, y)))
+                .map(|(x, y)| (luminance(a, x, y), luminance(b, x, y)))
+                .collect::<Vec<_>>();
+
+            let n = samples.len() as f64;
+            let mean_a = samples.iter().map(|(a, _)| a).sum::<f64>() / n;
+            let mean_b = samples.iter().map(|(_, b)| b).sum::<f64>() / n;
+            let (mut variance_a, mut variance_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (a, b) in samples {
+                variance_a += (a - mean_a).powi(2) / n;
+                variance_b += (b - mean_b).powi(2) / n;
+                covariance += (a - mean_a) * (b - mean_b) / n;
+            }
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1)
+                    * (variance_a + variance_b + SSIM_C2));
+            window_count += 1;
+        }
+    }
+
+    if window_count == 0 {
+        1.0
+    } else {
+        total / window_count as f64
+    }
+}
@@ -1,2 +1,6 @@
+mod capture;
+mod frame_diff;
 mod renderer;
-pub use renderer::*;
\ No newline at end of file
+pub use capture::*;
+pub use frame_diff::*;
+pub use renderer::*;
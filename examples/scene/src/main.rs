@@ -1,37 +1,168 @@
-use std::{error::Error, ffi::CString, io::Cursor, mem::offset_of, sync::Arc, time::Instant};
+use std::{
+    error::Error,
+    ffi::CString,
+    io::Cursor,
+    mem::offset_of,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 use ash::{
     util::read_spv,
-    vk::{self, Extent2D, PipelineLayoutCreateInfo, RenderingAttachmentInfo, RenderingInfo},
+    vk::{self, Extent2D, PipelineLayoutCreateInfo, RenderingInfo},
     Device,
 };
+use egui_ash_renderer::{DynamicRendering, Options, Renderer};
+use environment::{Environment, EnvironmentLoader};
+use gltf_model::MaterialFeatures;
+use scene::{
+    create_model_descriptor_set_layout, create_model_pipeline, create_model_pipeline_layout,
+    load_assets, save_comparison, FrameDiff, ModelRender, SceneAttachments, SceneTarget, Skybox,
+    ThumbnailGenerator, PNG_FORMAT,
+};
+use math::cgmath::{Deg, Point3, Vector3};
 use tracing::{debug, info, Level};
 use util::load_image;
 use vks::{
-    allocate_command_buffers, cmd_transition_images_layouts, create_device_local_buffer_with_data, create_pipeline, Buffer, Camera, CameraUBO, Context, Descriptors, Image, ImageParameters, LayoutTransition, MipsRange, PipelineParameters, RenderData, RenderError, ShaderParameters, Swapchain, SwapchainSupportDetails, Texture, Vertex, VulkanExampleBase, WindowApp
+    create_device_local_buffer_with_data, create_pipeline, Buffer, Camera, CameraPose, CameraUBO, CameraUniforms, ConfigChange, ConfigRebuild, ConfigWatcher, Context, DemoAction, DemoPlayer, DemoScript, DescriptorAllocator, Descriptors, DrawDebugId, Gui, Image, ImageParameters, InputState, Interpolated, Light, LightManager, MaskEdges, PipelineVariantCache, OffscreenTarget, OrientationGizmo, PanoramaFormat, PipelineParameters, ProbeGrid, RenderData, RenderError, RendererConfig, Session, ShaderParameters, ShaderWatcher, ShadingRateImage, ShadingRateParameters, ShadingRateState, SpecializationConstants, Texture, TextureFeedback, Turntable, TurntableSettings, UiLayer, Vertex, VulkanExampleBase, WindowApp, DEFAULT_POOL_SIZE_RATIOS, DEFAULT_SESSION_PATH, DEFAULT_UI_WHITE_NITS, MAX_FRAMES_IN_FLIGHT, UI_LAYER_FORMAT, UI_LAYER_SURFACE_FORMAT
 };
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
-    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, StartCause, WindowEvent},
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::Key,
     window::{Fullscreen, Window, WindowId},
 };
-pub const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
-    format: vk::Format::R16G16B16A16_SFLOAT,
-    color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
-};
+
+mod features;
+#[cfg(feature = "scripting")]
+mod script;
+
+use features::{create_shading_rate_image, create_texture_feedback};
+#[cfg(feature = "scripting")]
+use script::ScriptRunner;
+
+/// Camera uniform slot of the offscreen renders, after the slots of the frames in flight.
+const OFFSCREEN_CAMERA_SLOT: usize = MAX_FRAMES_IN_FLIGHT as usize;
+
+const DEFAULT_DEMO_SCRIPT: &str = "assets/demo/showcase.ron";
+const DEFAULT_COMPARISON_PATH: &str = "comparison.png";
+
+/// Two configs to render the same view with, requested with `--compare`.
+struct Comparison {
+    configs: [RendererConfig; 2],
+    output: PathBuf,
+}
 
 struct App {
     window: Option<Window>,
     triangle_app: Option<TextureApp>,
+    demo_script: Option<DemoScript>,
+    config_path: Option<String>,
+    /// Session restored on startup and saved on exit, `None` for demos and scripts.
+    session_path: Option<PathBuf>,
+    comparison: Option<Comparison>,
+    turntable_settings: TurntableSettings,
+    /// Error of the comparison, reported by the exit code.
+    comparison_error: Option<String>,
+    #[cfg(feature = "scripting")]
+    script: Option<ScriptRunner>,
+    /// Failures of the script, reported by the exit code.
+    #[cfg(feature = "scripting")]
+    script_failures: u32,
+    draw_debug_ids: bool,
+    texture_feedback: bool,
+    shading_rate: bool,
+    /// Create all the pipeline variants on startup, always done for demos.
+    prewarm: bool,
 }
 impl App {
     fn new() -> Result<Self, Box<dyn Error>> {
+        let mut demo_script = None;
+        let mut config_path = None;
+        let mut session = true;
+        let mut comparison = None;
+        #[cfg(feature = "scripting")]
+        let mut script = None;
+        let mut draw_debug_ids = false;
+        let mut texture_feedback = false;
+        let mut shading_rate = false;
+        let mut prewarm = false;
+        let mut turntable_settings = TurntableSettings::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--demo" {
+                let path = args
+                    .next()
+                    .unwrap_or_else(|| DEFAULT_DEMO_SCRIPT.to_owned());
+                info!("Running demo script {}", path);
+                demo_script = Some(DemoScript::from_file(path)?);
+                session = false;
+            } else if arg == "--config" {
+                config_path = args.next();
+            } else if arg == "--script" {
+                let path = args.next().ok_or("Missing script path")?;
+                session = false;
+                #[cfg(feature = "scripting")]
+                {
+                    info!("Running script {}", path);
+                    script = Some(ScriptRunner::from_file(path)?);
+                }
+                #[cfg(not(feature = "scripting"))]
+                tracing::warn!("Ignoring script {}, build with the scripting feature", path);
+            } else if arg == "--compare" {
+                let a = args.next().ok_or("Missing first config to compare")?;
+                let b = args.next().ok_or("Missing second config to compare")?;
+                let output = match args.next() {
+                    Some(arg) if !arg.starts_with("--") => arg,
+                    _ => DEFAULT_COMPARISON_PATH.to_owned(),
+                };
+                comparison = Some(Comparison {
+                    configs: [RendererConfig::from_file(a)?, RendererConfig::from_file(b)?],
+                    output: PathBuf::from(output),
+                });
+                // Both renders must see the default view.
+                session = false;
+            } else if arg == "--no-session" {
+                session = false;
+            } else if arg == "--draw-ids" {
+                draw_debug_ids = true;
+            } else if arg == "--texture-feedback" {
+                texture_feedback = true;
+            } else if arg == "--vrs" {
+                shading_rate = true;
+            } else if arg == "--prewarm" {
+                prewarm = true;
+            } else if arg == "--turntable" {
+                let duration = args.next().ok_or("Missing turntable duration")?;
+                let fps = args.next().ok_or("Missing turntable fps")?;
+                turntable_settings.duration = duration.parse()?;
+                turntable_settings.fps = fps.parse()?;
+                if let Some(radius) = args.next() {
+                    turntable_settings.radius = Some(radius.parse()?);
+                }
+            }
+        }
+
         Ok(Self {
             window: None,
             triangle_app: None,
+            demo_script,
+            config_path,
+            session_path: session.then(|| PathBuf::from(DEFAULT_SESSION_PATH)),
+            comparison,
+            turntable_settings,
+            comparison_error: None,
+            #[cfg(feature = "scripting")]
+            script,
+            #[cfg(feature = "scripting")]
+            script_failures: 0,
+            draw_debug_ids,
+            texture_feedback,
+            shading_rate,
+            prewarm,
         })
     }
 }
@@ -46,7 +177,35 @@ impl ApplicationHandler for App {
             )
             .expect("Failed to create window");
 
-        self.triangle_app = Some(TextureApp::new(&window, true));
+        let mut app = TextureApp::new(&window, true, self.texture_feedback, self.shading_rate);
+        app.base.context.set_draw_debug_ids(self.draw_debug_ids);
+        app.turntable_settings = self.turntable_settings;
+        app.demo = self.demo_script.take().map(DemoPlayer::new);
+        if self.prewarm || app.demo.is_some() {
+            app.prewarm_pipelines();
+        }
+        if let Some(path) = self.config_path.take() {
+            app.config = ConfigWatcher::new(path);
+        }
+        #[cfg(feature = "scripting")]
+        {
+            app.script = self.script.take();
+        }
+        if let Some(path) = self.session_path.take() {
+            app.restore_session(&path);
+            app.session_path = Some(path);
+        }
+        // Without ui the scene is only recorded again when it changes.
+        app.base.command_cache.set_enabled(app.demo.is_some());
+        if let Some(comparison) = self.comparison.take() {
+            match app.compare_configs(&comparison.configs, &comparison.output) {
+                Ok(diff) if diff.is_identical() => info!("Renders are identical"),
+                Ok(diff) => info!("Renders differ: {}", diff),
+                Err(error) => self.comparison_error = Some(error.to_string()),
+            }
+            event_loop.exit();
+        }
+        self.triangle_app = Some(app);
         self.window = Some(window);
     }
 
@@ -56,11 +215,12 @@ impl ApplicationHandler for App {
         }
     }
 
-    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
-        self.triangle_app
-            .as_mut()
-            .unwrap()
-            .end_frame(self.window.as_ref().unwrap());
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let app = self.triangle_app.as_mut().unwrap();
+        app.end_frame(self.window.as_ref().unwrap());
+        if app.should_exit() {
+            event_loop.exit();
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
@@ -82,7 +242,14 @@ impl ApplicationHandler for App {
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
-        self.triangle_app.as_mut().unwrap().on_exit();
+        // Drop the app while the window is alive, the surface must be destroyed first.
+        if let Some(mut app) = self.triangle_app.take() {
+            app.on_exit();
+            #[cfg(feature = "scripting")]
+            {
+                self.script_failures = app.script.as_ref().map_or(0, |s| s.failure_count());
+            }
+        }
     }
 }
 
@@ -161,107 +328,293 @@ impl QuadModel {
             &vertices,
         );
 
+        indices.set_name("quad indices");
+        vertices.set_name("quad vertices");
+
         Self { vertices, indices }
     }
 }
 
+const ENVIRONMENTS_DIR: &str = "assets/env";
+const ENVIRONMENT_RESOLUTION: u32 = 1024;
+/// First binding of the environment maps in the scene set, see the uber shader.
+const ENVIRONMENT_BINDING: u32 = 9;
+const THUMBNAIL_SIZE: u32 = 256;
+/// Largest channel difference with the reference thumbnails tolerated by
+/// `--golden`, for rounding differences between drivers.
+const GOLDEN_MAX_DIFFERENCE: u8 = 2;
+/// The quad has no gltf material, only its texture.
+const QUAD_MATERIAL: MaterialFeatures = MaterialFeatures::NONE;
+
+/// Variant of the uber shader for the features of a material, lit by the
+/// environment or not.
+type UberVariant = (MaterialFeatures, bool);
+
+/// List the hdr environments available in `dir`.
+fn list_environments<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+    let mut paths = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "hdr"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+/// List the glTF models of `dir`.
+fn list_models<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "gltf" || ext == "glb")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Write `<name>.thumbnail.png` next to each glTF model of `dir`.
+fn generate_thumbnails<P: AsRef<Path>>(dir: P) -> Result<(), Box<dyn Error>> {
+    let paths = list_models(dir)?;
+    let generator = ThumbnailGenerator::new(THUMBNAIL_SIZE);
+    let mut failures = 0;
+    for path in paths {
+        let output = path.with_extension("thumbnail.png");
+        if let Err(error) = generator.generate(&path, &output) {
+            tracing::error!("Failed to generate the thumbnail of {}: {}", path.display(), error);
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        return Err(format!("{} thumbnail(s) failed", failures).into());
+    }
+    Ok(())
+}
+
+/// Render the thumbnail of each glTF model of `dir` without window and
+/// compare it to the `<name>.thumbnail.png` written by `--thumbnails`.
+///
+/// Renders differing from their reference are saved side by side with it to
+/// `<name>.golden_diff.png`.
+fn check_golden_thumbnails<P: AsRef<Path>>(dir: P) -> Result<(), Box<dyn Error>> {
+    let paths = list_models(dir)?;
+    let generator = ThumbnailGenerator::new(THUMBNAIL_SIZE);
+    let extent = generator.extent();
+    let mut failures = 0;
+    for path in paths {
+        let reference_path = path.with_extension("thumbnail.png");
+        let result = generator.render(&path).and_then(|render| {
+            let reference = image::open(&reference_path)?.to_rgba8();
+            if reference.dimensions() != (extent[0], extent[1]) {
+                return Err(format!(
+                    "Reference {} is not {}x{}",
+                    reference_path.display(),
+                    extent[0],
+                    extent[1]
+                )
+                .into());
+            }
+            let diff = FrameDiff::compute(&render, &reference, extent);
+            if diff.max_difference > GOLDEN_MAX_DIFFERENCE {
+                save_comparison(
+                    path.with_extension("golden_diff.png"),
+                    &render,
+                    &reference,
+                    extent,
+                    false,
+                )?;
+                return Err(format!("Render differs from its reference: {}", diff).into());
+            }
+            Ok(diff)
+        });
+        match result {
+            Ok(diff) => info!("{} matches its reference: {}", path.display(), diff),
+            Err(error) => {
+                tracing::error!("Golden check of {} failed: {}", path.display(), error);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(format!("{} golden check(s) failed", failures).into());
+    }
+    Ok(())
+}
+
 pub struct TextureApp {
+    // Holds a raw device, declared before `base` to be dropped while the device is alive.
+    gui_renderer: Renderer,
+    gui_context: Gui,
+    environment: Option<Environment>,
+    environment_loader: EnvironmentLoader,
+    environment_paths: Vec<PathBuf>,
+    /// Path of the last environment requested.
+    environment_path: Option<PathBuf>,
+    /// Camera slots whose set binds the current environment.
+    environment_slots: Vec<bool>,
     base: VulkanExampleBase,
     model: QuadModel,
+    /// glTF model loaded by the demo, animated each frame and drawn after the quad.
+    animated_model: Option<ModelRender>,
     pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline,
+    pipelines: PipelineVariantCache<UberVariant>,
+    model_set_layout: vk::DescriptorSetLayout,
+    model_pipeline_layout: vk::PipelineLayout,
+    /// Variants of the uber shader for the materials of the glTF model.
+    model_pipelines: PipelineVariantCache<UberVariant>,
+    /// How the edges of the mask materials of the glTF model are drawn,
+    /// alpha to coverage by default when the scene is multisampled.
+    mask_edges: MaskEdges,
+    /// Drawn behind the quad in the camera slots binding an environment.
+    skybox: Skybox,
+    /// Attachments of the scene pass, in the formats and sample count of the pipelines.
+    scene_target: SceneTarget,
+    descriptor_allocator: DescriptorAllocator,
     descriptors: Descriptors,
+    camera_uniforms: CameraUniforms,
+    /// One lights buffer per camera slot, bound next to its camera uniforms.
+    lights: LightManager,
     texture: Texture,
-    
+    texture_feedback: Option<TextureFeedback>,
+    shading_rate: Option<ShadingRateImage>,
+    shading_rate_parameters: ShadingRateParameters,
+    shading_rate_enabled: bool,
+    shading_rate_debug: bool,
+    previous_camera_position: Option<Point3<f32>>,
+
     camera: Camera,
+    turntable_settings: TurntableSettings,
+    /// Orbit driving the camera, toggled with the `y` key.
+    turntable: Option<Turntable>,
+    /// Positions of the camera at the last two steps of the turntable.
+    turntable_position: Interpolated<Point3<f32>>,
+    /// Irradiance probes baked around the scene, toggled with the `g` key.
+    probe_grid: Option<ProbeGrid>,
+    gizmo: OrientationGizmo,
+    /// Layer the gui is drawn to, composited over the scene at a fixed brightness.
+    ui_layer: UiLayer,
+    /// Last cursor position in pixels, to pick the gizmo axes.
+    cursor_position: [f32; 2],
+    input_state: InputState,
+    demo: Option<DemoPlayer>,
+    config: ConfigWatcher,
+    shaders: ShaderWatcher,
+    session_path: Option<PathBuf>,
+    #[cfg(feature = "scripting")]
+    script: Option<ScriptRunner>,
+    clear_color: [f32; 4],
+    vsync: bool,
+    exit_requested: bool,
     time: Instant,
+    /// Duration of the last frame in seconds.
+    delta_s: f32,
     dirty_swapchain: bool,
 }
 
-fn prepare_pipeline(context: &Arc<Context>,set_layouts: &[vk::DescriptorSetLayout]) -> (vk::Pipeline, vk::PipelineLayout) {
-    let device = context.device();
-    let layout = {
-        let layout_info = vk::PipelineLayoutCreateInfo::default()
-        .set_layouts(set_layouts);
-
-        unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
-    };
-
-    let pipeline = {
-        let viewport_info = vk::PipelineViewportStateCreateInfo::default()
-            .viewport_count(1)
-            .scissor_count(1);
-
-        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
-            .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0);
-
-        let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            .min_sample_shading(1.0)
-            .alpha_to_coverage_enable(false)
-            .alpha_to_one_enable(false);
-
-        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .blend_enable(false)
-            .src_color_blend_factor(vk::BlendFactor::ONE)
-            .dst_color_blend_factor(vk::BlendFactor::ZERO)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-            .alpha_blend_op(vk::BlendOp::ADD)];
-
-        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-        let dynamic_state_info =
-            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
-
-        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(false)
-            .depth_write_enable(false)
-            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
-            .depth_bounds_test_enable(false)
-            .min_depth_bounds(0.0)
-            .max_depth_bounds(1.0)
-            .stencil_test_enable(false)
-            .front(Default::default())
-            .back(Default::default());
-
-        create_pipeline::<QuadVertex>(
-            context,
-            PipelineParameters {
-                vertex_shader_params: ShaderParameters::new("texture"),
-                fragment_shader_params: ShaderParameters::new("texture"),
-                multisampling_info: &multisampling_info,
-                viewport_info: &viewport_info,
-                rasterizer_info: &rasterizer_info,
-                dynamic_state_info: Some(&dynamic_state_info),
-                depth_stencil_info: Some(&depth_stencil_info),
-                color_blend_attachments: &color_blend_attachments,
-                color_attachment_formats: &[vk::Format::R8G8B8A8_SRGB],
-                depth_attachment_format: None,
-                layout,
-                parent: None,
-                allow_derivatives: false,
-            },
-        )
-    };
+fn create_pipeline_layout(
+    context: &Arc<Context>,
+    set_layouts: &[vk::DescriptorSetLayout],
+) -> vk::PipelineLayout {
+    let push_constant_ranges = [DrawDebugId::push_constant_range(
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        0,
+    )];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+    .set_layouts(set_layouts)
+    .push_constant_ranges(&push_constant_ranges);
+
+    unsafe { context.device().create_pipeline_layout(&layout_info, None).unwrap() }
+}
 
-    (pipeline, layout)
+/// Create the variant of the uber shader for `features`, drawn to the
+/// `attachments` of the scene pass.
+fn create_uber_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    attachments: SceneAttachments,
+    texture_feedback: bool,
+    shading_rate: bool,
+    (features, environment): UberVariant,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(features.cull_mode())
+        .front_face(vk::FrontFace::CLOCKWISE)
+        .depth_bias_enable(false)
+        .depth_bias_constant_factor(0.0)
+        .depth_bias_clamp(0.0)
+        .depth_bias_slope_factor(0.0);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(attachments.samples)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(features.alpha_to_coverage())
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [features.color_blend_attachment()];
+
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if shading_rate {
+        dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+    }
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(false)
+        .front(Default::default())
+        .back(Default::default());
+
+    // The quad texture is the first and only texture registered in the feedback.
+    let mut constants = SpecializationConstants::new();
+    constants.add_u32(0, 0);
+    constants.add_bool(1, texture_feedback);
+    features.add_constants(&mut constants);
+    // Lit by the environment instead of the constant ambient.
+    constants.add_bool(12, environment);
+    let specialization_info = constants.info();
+
+    create_pipeline::<QuadVertex>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new("texture"),
+            fragment_shader_params: ShaderParameters::specialized("uber", &specialization_info),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: shading_rate.then(ShadingRateState::attachment),
+            shading_rate_attachment: shading_rate,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[attachments.color_format],
+            depth_attachment_format: Some(attachments.depth_format),
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
 }
 
 pub fn create_shader_module(device: &ash::Device, code: Vec<u32>) -> vk::ShaderModule {
@@ -273,8 +626,11 @@ pub fn create_shader_module(device: &ash::Device, code: Vec<u32>) -> vk::ShaderM
     }
 }
 
-fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
-    let bindings = [
+fn create_descriptor_set_layout(
+    device: &Device,
+    texture_feedback: bool,
+) -> vk::DescriptorSetLayout {
+    let mut bindings = vec![
         vk::DescriptorSetLayoutBinding::default()
             .binding(0)
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
@@ -285,7 +641,23 @@ fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        // Lights of the uber shader.
+        LightManager::descriptor_set_layout_binding(8, vk::ShaderStageFlags::FRAGMENT),
     ];
+    // Written when an environment is loaded, see TextureApp::bind_environment.
+    bindings.extend(Environment::layout_bindings(
+        ENVIRONMENT_BINDING,
+        vk::ShaderStageFlags::FRAGMENT,
+    ));
+    if texture_feedback {
+        bindings.push(
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        );
+    }
 
     let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
 
@@ -296,69 +668,33 @@ fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
     }
 }
 
-fn create_descriptor_pool(device: &Device, descriptor_count: u32) -> vk::DescriptorPool {
-    let pool_sizes = [
-        vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count,
-        },
-        vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count,
-        },
-    ];
-
-    let create_info = vk::DescriptorPoolCreateInfo::default()
-        .pool_sizes(&pool_sizes)
-        .max_sets(descriptor_count);
-
-    unsafe { device.create_descriptor_pool(&create_info, None).unwrap() }
-}
-
-fn create_camera_ubos(context: &Arc<Context>, count: u32) -> Vec<Buffer> {
-    (0..count)
-        .map(|_| {
-            Buffer::create(
-                Arc::clone(context),
-                size_of::<CameraUBO>() as _,
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            )
-        })
-        .collect::<Vec<_>>()
-}
-
 fn create_descriptor_sets(
     context: &Arc<Context>,
-    pool: vk::DescriptorPool,
+    allocator: &mut DescriptorAllocator,
     layout: vk::DescriptorSetLayout,
-    buffers: &[Buffer],
+    camera_uniforms: &CameraUniforms,
+    lights: &LightManager,
     texture: &Texture,
+    texture_feedback: Option<&TextureFeedback>,
 ) -> Vec<vk::DescriptorSet> {
-    let layouts = (0..buffers.len()).map(|_| layout).collect::<Vec<_>>();
-
-    let allocate_info = vk::DescriptorSetAllocateInfo::default()
-        .descriptor_pool(pool)
-        .set_layouts(&layouts);
-    let sets = unsafe {
-        context
-            .device()
-            .allocate_descriptor_sets(&allocate_info)
-            .unwrap()
-    };
+    let layouts = (0..camera_uniforms.count()).map(|_| layout).collect::<Vec<_>>();
+    let sets = allocator
+        .allocate_many(&layouts)
+        .expect("Failed to allocate descriptor sets");
 
-    sets.iter().zip(buffers.iter()).for_each(|(set, buffer)| {
-        let buffer_info = [vk::DescriptorBufferInfo::default()
-            .buffer(buffer.buffer)
-            .offset(0)
-            .range(vk::WHOLE_SIZE)];
+    sets.iter().enumerate().for_each(|(index, set)| {
+        let buffer_info = [camera_uniforms.descriptor_info(index)];
 
         let cubemap_info = [vk::DescriptorImageInfo::default()
             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
             .image_view(texture.view)
             .sampler(texture.sampler.unwrap())];
 
-        let descriptor_writes = [
+        let lights_info = [lights.descriptor_info(index)];
+
+        let feedback_info = texture_feedback.map(|feedback| [feedback.descriptor_info()]);
+
+        let mut descriptor_writes = vec![
             vk::WriteDescriptorSet::default()
                 .dst_set(*set)
                 .dst_binding(0)
@@ -369,7 +705,21 @@ fn create_descriptor_sets(
                 .dst_binding(1)
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                 .image_info(&cubemap_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(8)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&lights_info),
         ];
+        if let Some(feedback_info) = feedback_info.as_ref() {
+            descriptor_writes.push(
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(2)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(feedback_info),
+            );
+        }
 
         unsafe {
             context
@@ -382,40 +732,665 @@ fn create_descriptor_sets(
 }
 
 impl TextureApp {
-    fn new(window: &Window, enable_debug: bool) -> Self {
+    fn new(
+        window: &Window,
+        enable_debug: bool,
+        texture_feedback: bool,
+        shading_rate: bool,
+    ) -> Self {
         let base = VulkanExampleBase::new(window, enable_debug);
         let context = &base.context;
         let model = QuadModel::new(context);
 
         let (width, height, image_data) = load_image("assets/android.png");
         
-        let texture = Texture::from_rgba(&context, width, height, &image_data, true);
-        let desc_layout = create_descriptor_set_layout(context.device());
-        let (pipeline, pipeline_layout) = prepare_pipeline(context,&[desc_layout]);
-        let camera_ubos = create_camera_ubos(&context, base.swapchain.image_count() as u32);
-        let pool = create_descriptor_pool(context.device(), camera_ubos.len() as u32);
-        
-        let desc_sets = create_descriptor_sets(context, pool, desc_layout, &camera_ubos, &texture);
-        let descriptors = Descriptors::new(context.clone(), desc_layout, pool, desc_sets);
+        let texture = Texture::from_rgba(&context, width, height, &image_data, true)
+            .expect("Failed to create texture");
+        texture.set_name("android");
+        let texture_feedback =
+            texture_feedback.then(|| create_texture_feedback(context, &texture));
+        let shading_rate = shading_rate
+            .then(|| create_shading_rate_image(&base))
+            .flatten();
+        let desc_layout =
+            create_descriptor_set_layout(context.device(), texture_feedback.is_some());
+        let pipeline_layout = create_pipeline_layout(context, &[desc_layout]);
+        let scene_target = create_scene_target(&base, base.msaa_samples);
+        let mut shaders = ShaderWatcher::new();
+        shaders.watch("texture");
+        shaders.watch("uber");
+        shaders.watch("skybox");
+        shaders.watch("model");
+        let mut pipelines = PipelineVariantCache::new(Arc::clone(context));
+        pipelines.get_or_create((QUAD_MATERIAL, false), |variant| {
+            create_uber_pipeline(
+                context,
+                pipeline_layout,
+                scene_target.attachments(),
+                texture_feedback.is_some(),
+                shading_rate.is_some(),
+                variant,
+            )
+        });
+        let skybox = Skybox::new(
+            context,
+            pipeline_layout,
+            scene_target.attachments(),
+            shading_rate.is_some(),
+        );
+        let model_set_layout = create_model_descriptor_set_layout(context);
+        let model_pipeline_layout = create_model_pipeline_layout(context, model_set_layout);
+        let camera_uniforms = CameraUniforms::new(context, OFFSCREEN_CAMERA_SLOT + 1);
+        let mut lights = LightManager::new(context, OFFSCREEN_CAMERA_SLOT + 1);
+        lights.add(Light::directional(
+            Vector3::new(-0.3, 0.5, 1.0),
+            [1.0; 3],
+            1.0,
+        ));
+        let mut descriptor_allocator =
+            DescriptorAllocator::new(context, &DEFAULT_POOL_SIZE_RATIOS);
+
+        let desc_sets = create_descriptor_sets(
+            context,
+            &mut descriptor_allocator,
+            desc_layout,
+            &camera_uniforms,
+            &lights,
+            &texture,
+            texture_feedback.as_ref(),
+        );
+        let descriptors = Descriptors::from_allocator(context.clone(), desc_layout, desc_sets);
+        let gui_renderer = Renderer::with_default_allocator(
+            base.context.instance(),
+            base.context.physical_device(),
+            base.context.device().clone(),
+            DynamicRendering {
+                color_attachment_format: UI_LAYER_FORMAT,
+                depth_attachment_format: None,
+            },
+            Options {
+                in_flight_frames: MAX_FRAMES_IN_FLIGHT as _,
+                // The ui layer encodes the linear colors of the gui.
+                srgb_framebuffer: context
+                    .color_policy()
+                    .gui_srgb_framebuffer(UI_LAYER_SURFACE_FORMAT),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mask_edges = MaskEdges::for_samples(scene_target.attachments().samples);
+        let mut gui_context = Gui::new(window, None);
+        gui_context.set_gpu_info(Some(context));
+        gui_context.set_mask_edges(mask_edges);
+        let environment_paths = list_environments(ENVIRONMENTS_DIR);
+        gui_context.set_environments(
+            environment_paths
+                .iter()
+                .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+                .collect(),
+        );
+        let mut environment_loader = EnvironmentLoader::new(context, ENVIRONMENT_RESOLUTION);
+        let environment_path = environment_paths.first().cloned();
+        if let Some(path) = environment_path.as_ref() {
+            environment_loader.load(path);
+        }
 
         Self {
+            gui_renderer,
+            gui_context,
+            environment: None,
+            environment_loader,
+            environment_paths,
+            environment_path,
+            environment_slots: vec![false; OFFSCREEN_CAMERA_SLOT + 1],
             model,
+            animated_model: None,
             camera: Camera::default(),
+            turntable_settings: TurntableSettings::default(),
+            turntable: None,
+            turntable_position: Interpolated::new(Point3::new(0.0, 0.0, 0.0)),
+            probe_grid: None,
+            gizmo: OrientationGizmo::new(
+                context,
+                base.scene_color_format,
+                Some(base.depth_format),
+                scene_target.attachments().samples,
+                shading_rate.is_some(),
+            ),
+            ui_layer: create_ui_layer(&base),
+            cursor_position: [0.0; 2],
+            input_state: InputState::default(),
+            demo: None,
+            config: ConfigWatcher::default(),
+            shaders,
+            session_path: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            clear_color: [1.0, 0.0, 0.0, 1.0],
+            vsync: false,
+            exit_requested: false,
             time: Instant::now(),
+            delta_s: 0.0,
             dirty_swapchain: false,
             pipeline_layout,
-            pipeline,
+            pipelines,
+            model_set_layout,
+            model_pipeline_layout,
+            model_pipelines: PipelineVariantCache::new(Arc::clone(context)),
+            mask_edges,
+            skybox,
+            scene_target,
             base,
+            descriptor_allocator,
             descriptors,
+            camera_uniforms,
+            lights,
             texture,
+            texture_feedback,
+            shading_rate,
+            shading_rate_parameters: ShadingRateParameters::default(),
+            shading_rate_enabled: true,
+            shading_rate_debug: false,
+            previous_camera_position: None,
         }
     }
 }
 
+fn create_scene_target(base: &VulkanExampleBase, samples: vk::SampleCountFlags) -> SceneTarget {
+    let properties = base.swapchain.properties();
+    SceneTarget::new(
+        &base.context,
+        properties.extent,
+        base.scene_color_format,
+        base.depth_format,
+        samples,
+        properties.format,
+    )
+}
+
+fn create_ui_layer(base: &VulkanExampleBase) -> UiLayer {
+    let properties = base.swapchain.properties();
+    UiLayer::new(&base.context, properties.extent, properties.format)
+}
+
+impl TextureApp {
+    /// Load the environment at `path` in the background.
+    ///
+    /// The current environment is kept until the new one is ready.
+    fn set_environment<P: AsRef<Path>>(&mut self, path: P) {
+        self.environment_path = Some(path.as_ref().to_owned());
+        self.environment_loader.load(path);
+    }
+
+    /// Restore the session saved at `path`, if any.
+    ///
+    /// The config file, once found, takes precedence over the restored renderer settings.
+    fn restore_session(&mut self, path: &Path) {
+        if !path.exists() {
+            return;
+        }
+        let session = match Session::from_file(path) {
+            Ok(session) => session,
+            Err(error) => {
+                tracing::warn!("Ignoring invalid session {}: {}", path.display(), error);
+                return;
+            }
+        };
+
+        if let Some(renderer) = session.renderer {
+            for change in renderer.changes_from(None) {
+                self.dirty_swapchain |= change.rebuild() == ConfigRebuild::Swapchain;
+                self.apply_config_change(change);
+            }
+        }
+        if let Some(pose) = session.camera {
+            pose.apply(&mut self.camera);
+        }
+        if let Some(name) = session.environment {
+            match self
+                .environment_paths
+                .iter()
+                .position(|path| path.file_stem().is_some_and(|stem| stem == name.as_str()))
+            {
+                Some(index) => {
+                    self.set_environment(self.environment_paths[index].clone());
+                    self.gui_context.set_selected_environment(index);
+                }
+                None => tracing::warn!("Environment {} of the session not found", name),
+            }
+        }
+        self.gui_context.set_layout(session.gui);
+        info!("Session {} restored", path.display());
+    }
+
+    fn save_session(&self, path: &Path) {
+        let session = Session {
+            camera: Some(CameraPose::from_camera(&self.camera)),
+            environment: self
+                .environment_path
+                .as_ref()
+                .and_then(|path| path.file_stem())
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            renderer: Some(RendererConfig {
+                clear_color: self.clear_color,
+                fov: self.camera.fov.0,
+                vsync: self.vsync,
+                max_fps: self.base.fps_limiter.max_fps(),
+                ..self.config.config().cloned().unwrap_or_default()
+            }),
+            gui: self.gui_context.layout(),
+        };
+        match session.save(path) {
+            Ok(()) => info!("Session saved to {}", path.display()),
+            Err(error) => tracing::error!("Failed to save session {}: {}", path.display(), error),
+        }
+    }
+
+    /// Render the current view with each of `configs`, compare the renders and
+    /// save them side by side with their difference to `output`.
+    ///
+    /// The settings not used by this example do not change the renders.
+    fn compare_configs(
+        &mut self,
+        configs: &[RendererConfig; 2],
+        output: &Path,
+    ) -> Result<FrameDiff, Box<dyn Error>> {
+        let target = self.create_offscreen_target(self.base.swapchain.properties().extent);
+
+        let mut renders = Vec::with_capacity(configs.len());
+        for config in configs {
+            for change in config.changes_from(None) {
+                self.apply_config_change(change);
+            }
+            let camera = self.camera;
+            self.render_to(&target, &camera);
+            renders.push(target.read_back_as(PNG_FORMAT));
+        }
+
+        let extent = target.extent();
+        let extent = [extent.width, extent.height];
+        let diff = FrameDiff::compute(&renders[0], &renders[1], extent);
+        save_comparison(output, &renders[0], &renders[1], extent, false)?;
+        info!("Comparison saved to {}", output.display());
+        Ok(diff)
+    }
+
+    /// Create every pipeline variant the materials of the scene can request.
+    fn prewarm_pipelines(&mut self) {
+        let context = Arc::clone(&self.base.context);
+        let variants = MaterialFeatures::permutations([QUAD_MATERIAL])
+            .into_iter()
+            .flat_map(|features| [(features, false), (features, true)]);
+        let report = self.pipelines.prewarm(variants, |variant| {
+            create_uber_pipeline(
+                &context,
+                self.pipeline_layout,
+                self.scene_target.attachments(),
+                self.texture_feedback.is_some(),
+                self.shading_rate.is_some(),
+                variant,
+            )
+        });
+        info!("Pipelines prewarmed: {}", report);
+    }
+
+    /// Recreate the pipelines of the shaders recompiled since the last call.
+    fn reload_shaders(&mut self) {
+        let reloaded = self.shaders.poll();
+        if reloaded.is_empty() {
+            return;
+        }
+        info!("Reloading the pipelines of {}", reloaded.join(", "));
+        self.recreate_pipelines();
+    }
+
+    /// Recreate the pipelines of the scene pass for the attachments of the scene target.
+    fn recreate_pipelines(&mut self) {
+        // The old pipelines may still be used by the frames in flight.
+        self.base.wait_idle_gpu();
+        self.pipelines.clear();
+        self.model_pipelines.clear();
+        self.skybox = Skybox::new(
+            &self.base.context,
+            self.pipeline_layout,
+            self.scene_target.attachments(),
+            self.shading_rate.is_some(),
+        );
+        self.create_quad_pipeline(false);
+        if self.environment.is_some() {
+            self.create_quad_pipeline(true);
+        }
+        self.create_model_pipelines();
+        self.base.command_cache.invalidate();
+    }
+
+    /// Render the scene with `samples` per pixel, recreating its target and pipelines.
+    fn set_msaa_samples(&mut self, samples: vk::SampleCountFlags) {
+        if samples == self.scene_target.attachments().samples {
+            return;
+        }
+        self.base.wait_idle_gpu();
+        self.scene_target = create_scene_target(&self.base, samples);
+        self.gizmo = OrientationGizmo::new(
+            &self.base.context,
+            self.base.scene_color_format,
+            Some(self.base.depth_format),
+            samples,
+            self.shading_rate.is_some(),
+        );
+        self.recreate_pipelines();
+        // The faces of the probes are rendered with the scene pipelines.
+        if self.probe_grid.take().is_some() {
+            self.toggle_probe_grid();
+        }
+        info!("MSAA samples: {}", samples.as_raw());
+    }
+
+    /// Create the pipeline of the quad, lit by the environment or not, unless it is cached.
+    fn create_quad_pipeline(&mut self, environment: bool) {
+        let context = Arc::clone(&self.base.context);
+        self.pipelines
+            .get_or_create((QUAD_MATERIAL, environment), |variant| {
+                create_uber_pipeline(
+                    &context,
+                    self.pipeline_layout,
+                    self.scene_target.attachments(),
+                    self.texture_feedback.is_some(),
+                    self.shading_rate.is_some(),
+                    variant,
+                )
+            });
+    }
+
+    /// Draw the edges of the mask materials of the glTF model as `mask_edges`.
+    fn set_mask_edges(&mut self, mask_edges: MaskEdges) {
+        self.mask_edges = mask_edges;
+        self.create_model_pipelines();
+        self.base.command_cache.invalidate();
+        info!("Mask edges: {}", mask_edges);
+    }
+
+    /// Create the pipelines of the materials of the glTF model, lit by the
+    /// environment if there is one and drawing the mask edges as selected,
+    /// unless they are cached.
+    fn create_model_pipelines(&mut self) {
+        let Some(model) = self.animated_model.as_ref() else {
+            return;
+        };
+        let context = Arc::clone(&self.base.context);
+        let environments = [false, self.environment.is_some()];
+        for features in model.model().material_features() {
+            let features = features.with_mask_edges(self.mask_edges);
+            for environment in environments {
+                self.model_pipelines
+                    .get_or_create((features, environment), |variant| {
+                        create_model_pipeline(
+                            &context,
+                            self.model_pipeline_layout,
+                            self.scene_target.attachments(),
+                            self.shading_rate.is_some(),
+                            variant,
+                        )
+                    });
+            }
+        }
+    }
+
+    /// Write the current environment to the set of the camera slot `slot`,
+    /// unless it was already written since the environment was swapped.
+    ///
+    /// The set must not be used by pending commands.
+    ///
+    /// # Returns
+    ///
+    /// True if the set was written, the commands binding it must be recorded again.
+    fn bind_environment(&mut self, slot: usize) -> bool {
+        let Some(environment) = self.environment.as_ref() else {
+            return false;
+        };
+        if self.environment_slots[slot] {
+            return false;
+        }
+        environment.write_descriptors(
+            &self.base.context,
+            self.descriptors.sets()[slot],
+            ENVIRONMENT_BINDING,
+        );
+        if let Some(model) = self.animated_model.as_ref() {
+            model.write_environment(environment, slot);
+        }
+        self.environment_slots[slot] = true;
+        true
+    }
+
+    fn apply_demo_action(&mut self, action: DemoAction) {
+        info!("Demo action {:?}", action);
+        match action {
+            DemoAction::SetEnvironment(name) => {
+                let path = self
+                    .environment_paths
+                    .iter()
+                    .find(|path| path.file_stem().is_some_and(|stem| stem == name.as_str()))
+                    .cloned()
+                    .unwrap_or_else(|| PathBuf::from(name));
+                self.set_environment(path);
+            }
+            DemoAction::LoadModel(path) => {
+                let model = load_assets(Arc::clone(&self.base.context), &path).finish();
+                let model = ModelRender::new(
+                    Arc::clone(&self.base.context),
+                    model,
+                    self.model_set_layout,
+                    &self.camera_uniforms,
+                    &self.lights,
+                    self.environment.as_ref(),
+                );
+                if let Some(previous) = self.animated_model.replace(model) {
+                    // Its buffers may still be read by the frames in flight.
+                    self.base.context.deletion_queue().enqueue(previous);
+                }
+                self.create_model_pipelines();
+                self.base.command_cache.invalidate();
+            }
+            DemoAction::SetVsync(vsync) => {
+                self.vsync = vsync;
+                self.dirty_swapchain = true;
+            }
+            DemoAction::SetFov(fov) => self.camera.fov = Deg(fov),
+            DemoAction::Quit => self.exit_requested = true,
+        }
+    }
+
+    fn apply_config_change(&mut self, change: ConfigChange) {
+        info!("Config change {:?}", change);
+        match change {
+            ConfigChange::ClearColor(color) => {
+                self.clear_color = color;
+                self.base.command_cache.invalidate();
+            }
+            ConfigChange::CameraSpeed(speed) => self.camera.set_move_speed(speed),
+            ConfigChange::Fov(fov) => self.camera.fov = Deg(fov),
+            ConfigChange::Vsync(vsync) => self.vsync = vsync,
+            ConfigChange::MaxFps(max_fps) => self.base.fps_limiter.set_max_fps(max_fps),
+            ConfigChange::Renderer(settings) => {
+                let settings = settings.validated(&self.base.context);
+                self.set_msaa_samples(settings.sample_count());
+            }
+            ConfigChange::Hdr(_) | ConfigChange::Post(_) => {
+                tracing::debug!("{:?} is not used by this example", change);
+            }
+        }
+    }
+
+    /// Record the draws of the scene in the rendering begun by the caller.
+    ///
+    /// `uniform_slot` is the camera uniform slot to read, the shading rate
+    /// attachment is only used if `shading_rate_attachment` is true.
+    fn cmd_draw_scene(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        uniform_slot: usize,
+        shading_rate_attachment: bool,
+    ) {
+        self.base.context.cmd_begin_pass(command_buffer, "scene");
+        let device = self.base.context.device();
+
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &self.descriptors.sets()[uniform_slot..=uniform_slot],
+                &[],
+            )
+        };
+
+        if self.shading_rate.is_some() {
+            let state = if shading_rate_attachment {
+                ShadingRateState::attachment()
+            } else {
+                ShadingRateState::default()
+            };
+            self.base
+                .context
+                .cmd_set_fragment_shading_rate(command_buffer, state);
+        }
+
+        // Draw skybox
+        let environment = self.environment_slots[uniform_slot];
+        if environment {
+            self.skybox.cmd_draw(command_buffer);
+        }
+
+        // Bind quad pipeline
+        let pipeline = self.pipelines.get(&(QUAD_MATERIAL, environment)).unwrap();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            )
+        };
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.model.vertices.buffer],
+                &[0],
+            );
+        }
+
+        unsafe {
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.model.indices.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+
+        // Draw quad
+        self.base.context.cmd_set_draw_debug_id(
+            command_buffer,
+            self.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+            DrawDebugId::new(Some(0), Some(0)),
+        );
+        unsafe { device.cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0) };
+
+        if let Some(model) = self.animated_model.as_ref() {
+            model.cmd_draw(
+                command_buffer,
+                self.model_pipeline_layout,
+                uniform_slot,
+                |features| {
+                    self.model_pipelines
+                        .get(&(features.with_mask_edges(self.mask_edges), environment))
+                        .expect("Missing pipeline of the glTF model")
+                },
+            );
+        }
+        self.base.context.cmd_end_pass(command_buffer);
+    }
+
+    /// Create a target [`TextureApp::render_to`] can render to.
+    fn create_offscreen_target(&self, extent: vk::Extent2D) -> OffscreenTarget {
+        OffscreenTarget::new(
+            &self.base.context,
+            extent,
+            self.base.scene_color_format,
+            self.base.depth_format,
+            self.scene_target.attachments().samples,
+        )
+    }
+
+    /// Render the scene seen by `camera` to `target`, outside of the frame loop.
+    ///
+    /// Shares the draws of the swapchain path, for captures, thumbnails or probes.
+    /// The target must come from [`TextureApp::create_offscreen_target`] to match
+    /// the formats and sample count of the pipelines. Waits for the render to complete.
+    fn render_to(&mut self, target: &OffscreenTarget, camera: &Camera) {
+        let extent = target.extent();
+        let aspect = extent.width as f32 / extent.height as f32;
+        let view = camera.view_matrix();
+        let proj = camera.projection_matrix(aspect);
+        let ubo = CameraUBO::new(
+            view,
+            proj,
+            proj * view,
+            camera.position(),
+            camera.z_near,
+            camera.z_far,
+        );
+        self.render_ubo_to(target, ubo);
+    }
+
+    /// Render the scene seen with the camera uniforms `ubo` to `target`, see
+    /// [`TextureApp::render_to`].
+    fn render_ubo_to(&mut self, target: &OffscreenTarget, ubo: CameraUBO) {
+        let attachments = self.scene_target.attachments();
+        assert_eq!(
+            (target.color_format(), target.depth_format(), target.samples()),
+            (
+                attachments.color_format,
+                attachments.depth_format,
+                attachments.samples
+            ),
+            "Offscreen target formats do not match the scene pipelines"
+        );
+
+        // Written as is so the motion history of the frame loop is kept.
+        self.camera_uniforms.write(OFFSCREEN_CAMERA_SLOT, ubo);
+        self.lights.update(OFFSCREEN_CAMERA_SLOT);
+        if let Some(model) = self.animated_model.as_mut() {
+            model.write_skins(OFFSCREEN_CAMERA_SLOT);
+        }
+        // The offscreen set is only used by the one time commands, which are waited for.
+        self.bind_environment(OFFSCREEN_CAMERA_SLOT);
+
+        self.base
+            .context
+            .execute_one_time_commands(|command_buffer| {
+                target.cmd_begin_rendering(command_buffer, self.clear_color);
+                self.cmd_draw_scene(command_buffer, OFFSCREEN_CAMERA_SLOT, false);
+                target.cmd_end_rendering(command_buffer);
+            });
+    }
+}
+
 impl WindowApp for TextureApp {
-    fn new_frame(&mut self) {}
+    fn new_frame(&mut self) {
+        self.input_state = self.input_state.reset();
+    }
+
+    fn handle_window_event(&mut self, window: &Window, event: &WindowEvent) {
+        self.gui_context.handle_event(window, event);
+        self.input_state = self.input_state.handle_window_event(event);
 
-    fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent) {
         match event {
             // Resizing
             WindowEvent::Resized(PhysicalSize { width, height }) => {
@@ -423,6 +1398,22 @@ impl WindowApp for TextureApp {
 
                 self.dirty_swapchain = true;
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = [position.x as _, position.y as _];
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let extent = self.base.swapchain.properties().extent;
+                if let Some(axis) =
+                    OrientationGizmo::pick(&self.camera, extent, self.cursor_position)
+                {
+                    info!("Snapping camera to {}", axis);
+                    axis.snap(&mut self.camera);
+                }
+            }
             // Key events
             WindowEvent::KeyboardInput {
                 event:
@@ -436,69 +1427,143 @@ impl WindowApp for TextureApp {
                 if c == "h" {
                     // self.enable_ui = !self.enable_ui;
                 }
+                if c == "r" {
+                    self.toggle_command_cache();
+                }
+                if c == "t" {
+                    self.log_texture_usage();
+                }
+                if c == "p" {
+                    self.save_capture();
+                }
+                if c == "o" {
+                    self.save_panorama(PanoramaFormat::Png);
+                }
+                if c == "O" {
+                    self.save_panorama(PanoramaFormat::Exr);
+                }
+                if c == "y" {
+                    self.toggle_turntable();
+                }
+                if c == "Y" {
+                    self.save_turntable();
+                }
+                if c == "g" {
+                    self.toggle_probe_grid();
+                }
+                if c == "v" {
+                    self.toggle_shading_rate();
+                }
+                if c == "b" {
+                    self.toggle_shading_rate_debug();
+                }
             }
             _ => (),
         }
     }
 
     fn handle_device_event(&mut self, event: &DeviceEvent) {
-        // self.input_state = self.input_state.handle_device_event(event);
+        self.input_state = self.input_state.handle_device_event(event);
     }
 
-    fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool) {
-        tracing::debug!("Recreating swapchain.");
+    fn end_frame(&mut self, window: &Window) {
+        self.base.fps_limiter.wait();
+        let new_time = Instant::now();
+        let delta_s = (new_time - self.time).as_secs_f32();
+        self.time = new_time;
+        self.delta_s = delta_s;
 
-        self.base.context.graphics_queue_wait_idle();
+        if !self.gui_context.wants_pointer_input() {
+            self.camera.update(&self.input_state, delta_s);
+        }
 
-        unsafe {
-            self.base.context.device().free_command_buffers(
-                self.base.context.general_command_pool(),
-                &self.base.command_buffers,
-            )
-        };
+        let demo_actions = self.demo.as_mut().map_or_else(Vec::new, |demo| {
+            let actions = demo.update(delta_s);
+            demo.apply_camera(&mut self.camera);
+            actions
+        });
+        for action in demo_actions {
+            self.apply_demo_action(action);
+        }
 
-        let swapchain_support_details = SwapchainSupportDetails::new(
-            self.base.context.physical_device(),
-            self.base.context.surface(),
-            self.base.context.surface_khr(),
-        );
+        self.advance_turntable(delta_s);
 
-        self.base.swapchain = Swapchain::create(
-            Arc::clone(&self.base.context),
-            swapchain_support_details,
-            dimensions,
-            hdr.then_some(HDR_SURFACE_FORMAT),
-            vsync,
-        );
+        #[cfg(feature = "scripting")]
+        {
+            let commands = self
+                .script
+                .as_mut()
+                .map_or_else(Vec::new, |script| script.update(delta_s));
+            for command in commands {
+                match command {
+                    vks::ScriptCommand::LookAt { position, target } => self
+                        .camera
+                        .look_at(Point3::from(position), Point3::from(target)),
+                    vks::ScriptCommand::Action(action) => self.apply_demo_action(action),
+                    _ => {}
+                }
+            }
+        }
 
-        self.base.on_new_swapchain();
-        self.base.command_buffers =
-            allocate_command_buffers(&self.base.context, self.base.swapchain.image_count());
-    }
+        for change in self.config.poll() {
+            self.apply_config_change(change);
+        }
+        self.reload_shaders();
+        for change in self.config.take_queued(ConfigRebuild::Pipelines) {
+            self.apply_config_change(change);
+        }
+        if self.config.needs_rebuild(ConfigRebuild::Swapchain) {
+            self.dirty_swapchain = true;
+        }
 
-    fn end_frame(&mut self, window: &Window) {
-        let new_time = Instant::now();
-        let delta_s = (new_time - self.time).as_secs_f32();
-        self.time = new_time;
+        self.update_shading_rate_motion(delta_s);
 
         // If swapchain must be recreated wait for windows to not be minimized anymore
         if self.dirty_swapchain {
             let PhysicalSize { width, height } = window.inner_size();
             if width > 0 && height > 0 {
+                for change in self.config.take_queued(ConfigRebuild::Swapchain) {
+                    self.apply_config_change(change);
+                }
                 self.base
-                    .recreate_swapchain(window.inner_size().into(), false, false);
+                    .recreate_swapchain(window.inner_size().into(), self.vsync, false);
+                // Still used by the frames in flight.
+                let deletion_queue = self.base.context.deletion_queue();
+                deletion_queue.enqueue(std::mem::replace(
+                    &mut self.ui_layer,
+                    create_ui_layer(&self.base),
+                ));
+                if self.shading_rate.is_some() {
+                    deletion_queue.enqueue(std::mem::replace(
+                        &mut self.shading_rate,
+                        create_shading_rate_image(&self.base),
+                    ));
+                }
+                let properties = self.base.swapchain.properties();
+                self.scene_target
+                    .resize(properties.extent, properties.format);
             } else {
                 return;
             }
         }
-        self.dirty_swapchain = matches!(
-            self.render(window, self.camera),
-            Err(RenderError::DirtySwapchain)
-        );
+        self.dirty_swapchain = match self.render(window, self.camera) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
     }
 
     fn on_exit(&mut self) {
         self.base.wait_idle_gpu();
+        self.log_texture_usage();
+        if let Some(path) = self.session_path.as_ref() {
+            self.save_session(path);
+        }
+    }
+
+    fn should_exit(&self) -> bool {
+        self.exit_requested
     }
 
     fn render(&mut self, window: &Window, camera: Camera) -> Result<(), RenderError> {
@@ -514,20 +1579,46 @@ impl WindowApp for TextureApp {
                 .context
                 .device()
                 .wait_for_fences(&wait_fences, true, u64::MAX)
-                .unwrap()
+                .unwrap_or_else(|error| {
+                    self.base
+                        .context
+                        .handle_device_error(error, "Failed to wait for frame fence")
+                })
         };
+        self.base.in_flight_frames.collect_deletions();
+
+        // Per frame data is only written once the fence of the frame was waited for.
+        let in_flight_index = self.base.in_flight_frames.current_frame_index();
+        let properties = self.base.swapchain.properties();
+        let aspect = properties.aspect();
+        self.camera_uniforms
+            .set_pre_rotation(properties.pre_rotation());
+        self.camera_uniforms.update(in_flight_index, &camera, aspect);
+        self.lights.update(in_flight_index);
+        if let Some(model) = self.animated_model.as_mut() {
+            model.update(in_flight_index, self.delta_s);
+        }
+        if let Some(feedback) = self.texture_feedback.as_mut() {
+            feedback.collect(in_flight_index);
+        }
+        // The sets are rewritten before the command cache decides to reuse the
+        // commands binding them.
+        if self.environment_loader.swap(&mut self.environment) {
+            self.environment_slots.fill(false);
+            self.create_quad_pipeline(true);
+            self.create_model_pipelines();
+        }
+        if self.bind_environment(in_flight_index) {
+            self.base.command_cache.invalidate();
+        }
 
-        let result =
-            self.base
-                .swapchain
-                .acquire_next_image(None, Some(image_available_semaphore), None);
-        let image_index = match result {
-            Ok((image_index, _)) => image_index,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return Err(RenderError::DirtySwapchain);
-            }
-            Err(error) => panic!("Error while acquiring next image. Cause: {}", error),
-        };
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
+
+        // Must happen before the reset of the fence that may guard the previous submission.
+        let reuse_commands = self
+            .base
+            .command_cache
+            .prepare(image_index as _, in_flight_index);
 
         unsafe {
             self.base
@@ -537,8 +1628,58 @@ impl WindowApp for TextureApp {
                 .unwrap()
         };
 
+        if !self.base.in_flight_frames.gui_textures_to_free.is_empty() {
+            self.gui_renderer
+                .free_textures(&self.base.in_flight_frames.gui_textures_to_free)
+                .unwrap();
+        }
+        // The demo mode runs without ui.
+        let ui_render_data = if self.demo.is_some() {
+            None
+        } else {
+            self.gui_context
+                .set_environment_loading(self.environment_loader.is_loading());
+            self.gui_context
+                .set_memory_stats(Some(self.base.context.memory_stats()));
+            self.gui_context.set_camera(Some(self.camera));
+            self.gui_context.set_lights(Some(self.lights.lights()));
+            let render_data = self.gui_context.render(window);
+            if let Some(camera) = self.gui_context.get_new_camera() {
+                self.camera = camera;
+            }
+            if let Some(lights) = self.gui_context.get_new_lights() {
+                self.lights.set_lights(lights.to_vec());
+            }
+            if let Some(index) = self.gui_context.get_selected_environment() {
+                let path = self.environment_paths[index].clone();
+                self.set_environment(path);
+            }
+            let mask_edges = self.gui_context.mask_edges();
+            if mask_edges != self.mask_edges {
+                self.set_mask_edges(mask_edges);
+            }
+
+            self.base.in_flight_frames.gui_textures_to_free.clear();
+            self.base
+                .in_flight_frames
+                .gui_textures_to_free
+                .extend_from_slice(&render_data.textures_delta.free);
+
+            let _queue_guard = self.base.context.lock_queue();
+            self.gui_renderer
+                .set_textures(
+                    self.base.context.graphics_compute_queue(),
+                    self.base.context.transient_command_pool(),
+                    &render_data.textures_delta.set,
+                )
+                .unwrap();
+
+            Some(render_data)
+        };
+
         // record_command_buffer
-        {
+        // The ui changes every frame so its commands are never reused.
+        if ui_render_data.is_some() || !reuse_commands {
             let command_buffer = self.base.command_buffers[image_index as usize];
             let frame_index = image_index as _;
 
@@ -563,7 +1704,7 @@ impl WindowApp for TextureApp {
                 };
             }
 
-            self.cmd_draw(command_buffer, frame_index,None);
+            self.cmd_draw(command_buffer, frame_index, ui_render_data.as_ref());
 
             // End command buffer
             unsafe {
@@ -573,6 +1714,12 @@ impl WindowApp for TextureApp {
                     .end_command_buffer(command_buffer)
                     .unwrap()
             };
+
+            // The shading rates depend on the motion of the camera, pushed when recording.
+            let reusable = ui_render_data.is_none() && self.shading_rate.is_none();
+            self.base
+                .command_cache
+                .set_recorded(image_index as _, in_flight_index, reusable);
         }
 
         // Submit command buffer
@@ -593,6 +1740,7 @@ impl WindowApp for TextureApp {
                 .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
                 .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
 
+            let _queue_guard = self.base.context.lock_queue();
             unsafe {
                 self.base
                     .context
@@ -602,8 +1750,26 @@ impl WindowApp for TextureApp {
                         std::slice::from_ref(&submit_info),
                         in_flight_fence,
                     )
-                    .unwrap()
+                    .unwrap_or_else(|error| {
+                        self.base
+                            .context
+                            .handle_device_error(error, "Failed to submit frame")
+                    })
             };
+            self.base
+                .command_cache
+                .submitted(image_index as _, in_flight_fence);
+
+            // Read back the frame before presenting it.
+            #[cfg(feature = "scripting")]
+            if let Some(script) = self.script.as_mut() {
+                script.capture(
+                    &self.base.swapchain.images()[image_index as usize],
+                    self.base.swapchain.properties().usage,
+                );
+            }
+            self.camera_uniforms
+                .submitted(in_flight_index, in_flight_fence);
         }
 
         let swapchains = [self.base.swapchain.swapchain_khr()];
@@ -621,7 +1787,10 @@ impl WindowApp for TextureApp {
                 Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                     return Err(RenderError::DirtySwapchain)
                 }
-                Err(error) => panic!("Failed to present queue. Cause: {}", error),
+                Err(error) => self
+                    .base
+                    .context
+                    .handle_device_error(error, "Failed to present queue"),
                 _ => {}
             }
         }
@@ -631,35 +1800,17 @@ impl WindowApp for TextureApp {
 
     fn cmd_draw(&mut self, command_buffer: vk::CommandBuffer, frame_index: usize,ui_render_data: Option<&RenderData>,) {
         // Prepare attachments and inputs for lighting pass
-        let transitions = vec![
-            LayoutTransition {
-                image: &self.base.scene_color.image,
-                old_layout: vk::ImageLayout::UNDEFINED,
-                new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                mips_range: MipsRange::All,
-            },
-            LayoutTransition {
-                image: &self.base.scene_depth.image,
-                old_layout: vk::ImageLayout::UNDEFINED,
-                new_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                mips_range: MipsRange::All,
-            },
-        ];
-        cmd_transition_images_layouts(command_buffer, &transitions);
+        self.scene_target.cmd_prepare_attachments(command_buffer);
+        if let Some(shading_rate) = self.shading_rate.as_ref() {
+            shading_rate.cmd_generate(command_buffer, self.shading_rate_parameters);
+        }
         let (image, image_view) = (
             &self.base.swapchain.images()[frame_index],
             &self.base.swapchain.image_views()[frame_index],
         );
         // Scene Pass
         {
-            // let extent = vk::Extent2D {
-            //     width: self.base.scene_color.image.extent.width,
-            //     height: self.base.scene_color.image.extent.height,
-            // };
-            let extent = vk::Extent2D {
-                width: image.extent.width,
-                height: image.extent.height,
-            };
+            let extent = self.scene_target.extent();
 
             unsafe {
                 self.base.context.device().cmd_set_viewport(
@@ -683,30 +1834,15 @@ impl WindowApp for TextureApp {
             }
 
             {
-                let color_attachment_info = RenderingAttachmentInfo::default()
-                    .clear_value(vk::ClearValue {
-                        color: vk::ClearColorValue {
-                            float32: [1.0, 0.0, 0.0, 1.0],
-                        },
-                    })
-                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                    .image_view(*image_view)
-                    .load_op(vk::AttachmentLoadOp::CLEAR)
-                    .store_op(vk::AttachmentStoreOp::STORE);
-
-                let depth_attachment_info = RenderingAttachmentInfo::default()
-                    .clear_value(vk::ClearValue {
-                        depth_stencil: vk::ClearDepthStencilValue {
-                            depth: 1.0,
-                            stencil: 0,
-                        },
-                    })
-                    .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-                    .image_view(self.base.scene_depth.view)
-                    .load_op(vk::AttachmentLoadOp::CLEAR)
-                    .store_op(vk::AttachmentStoreOp::STORE);
-
-                let rendering_info = RenderingInfo::default()
+                let color_attachment_info =
+                    self.scene_target.color_attachment_info(self.clear_color);
+                let depth_attachment_info = self.scene_target.depth_attachment_info();
+
+                let mut shading_rate_attachment_info = self
+                    .shading_rate
+                    .as_ref()
+                    .map(ShadingRateImage::rendering_attachment_info);
+                let mut rendering_info = RenderingInfo::default()
                     .color_attachments(std::slice::from_ref(&color_attachment_info))
                     .depth_attachment(&depth_attachment_info)
                     .layer_count(1)
@@ -714,6 +1850,9 @@ impl WindowApp for TextureApp {
                         offset: vk::Offset2D { x: 0, y: 0 },
                         extent,
                     });
+                if let Some(info) = shading_rate_attachment_info.as_mut() {
+                    rendering_info = rendering_info.push_next(info);
+                }
                 unsafe {
                     self.base
                         .context
@@ -721,47 +1860,19 @@ impl WindowApp for TextureApp {
                         .cmd_begin_rendering(command_buffer, &rendering_info)
                 };
             }
-            let device = self.base.context.device();
-
-            // Bind skybox pipeline
-            unsafe {
-                device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.pipeline,
-                )
-            };
-
-            unsafe {
-                device.cmd_bind_vertex_buffers(
-                    command_buffer,
-                    0,
-                    &[self.model.vertices.buffer],
-                    &[0],
-                );
+            // Uniforms are per frame in flight, not per swapchain image.
+            let in_flight_index = self.base.in_flight_frames.current_frame_index();
+            self.cmd_draw_scene(command_buffer, in_flight_index, self.shading_rate_enabled);
+
+            if let Some(grid) = self.probe_grid.as_ref() {
+                let aspect = extent.width as f32 / extent.height as f32;
+                let view_proj = self.camera.projection_matrix(aspect) * self.camera.view_matrix();
+                grid.cmd_draw_probes(command_buffer, view_proj);
             }
 
-            unsafe {
-                device.cmd_bind_index_buffer(
-                    command_buffer,
-                    self.model.indices.buffer,
-                    0,
-                    vk::IndexType::UINT32,
-                );
+            if ui_render_data.is_some() {
+                self.gizmo.cmd_draw(command_buffer, extent, &self.camera);
             }
-            unsafe {
-                device.cmd_bind_descriptor_sets(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.pipeline_layout,
-                    0,
-                    &self.descriptors.sets()[frame_index..=frame_index],
-                    &[],
-                )
-            };
-
-            // Draw skybox
-            unsafe { device.cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0) };
 
             unsafe {
                 self.base
@@ -769,15 +1880,42 @@ impl WindowApp for TextureApp {
                     .dynamic_rendering()
                     .cmd_end_rendering(command_buffer)
             };
-        }
-        // Transition swapchain image for presentation
-        {
-            self.base.swapchain.images()[frame_index].cmd_transition_image_layout(
+
+            image.cmd_transition_image_layout(
                 command_buffer,
+                vk::ImageLayout::UNDEFINED,
                 vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                vk::ImageLayout::PRESENT_SRC_KHR,
             );
+            self.scene_target.cmd_output(command_buffer, *image_view);
+
+            if let Some(RenderData {
+                pixels_per_point,
+                clipped_primitives,
+                ..
+            }) = ui_render_data
+            {
+                self.base.context.cmd_begin_pass(command_buffer, "gui");
+                self.ui_layer.cmd_begin_rendering(command_buffer);
+                self.gui_renderer
+                    .cmd_draw(
+                        command_buffer,
+                        extent,
+                        *pixels_per_point,
+                        clipped_primitives,
+                    )
+                    .unwrap();
+                self.ui_layer.cmd_end_rendering(command_buffer);
+                self.ui_layer
+                    .cmd_composite(command_buffer, *image_view, DEFAULT_UI_WHITE_NITS);
+                self.base.context.cmd_end_pass(command_buffer);
+            }
         }
+        if let Some(feedback) = self.texture_feedback.as_ref() {
+            let in_flight_index = self.base.in_flight_frames.current_frame_index();
+            feedback.cmd_reduce(command_buffer, in_flight_index);
+        }
+        // Transition swapchain image for presentation
+        self.cmd_transition_to_present(command_buffer, image);
     }
 }
 
@@ -788,12 +1926,34 @@ fn main() -> Result<(), Box<dyn Error>> {
         .with_max_level(Level::DEBUG)
         // builds the subscriber.
         .finish();
+    #[cfg(feature = "tracy")]
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+        subscriber,
+        vks::tracing_tracy::TracyLayer::default(),
+    );
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 
     debug!("Hello, world!");
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(index) = args.iter().position(|arg| arg == "--thumbnails") {
+        let dir = args.get(index + 1).ok_or("Missing thumbnails directory")?;
+        return generate_thumbnails(dir);
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--golden") {
+        let dir = args.get(index + 1).ok_or("Missing models directory")?;
+        return check_golden_thumbnails(dir);
+    }
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
     let mut app = App::new()?;
     event_loop.run_app(&mut app)?;
+    if let Some(error) = app.comparison_error.take() {
+        return Err(format!("Comparison failed: {}", error).into());
+    }
+    #[cfg(feature = "scripting")]
+    if app.script_failures > 0 {
+        return Err(format!("{} script failure(s)", app.script_failures).into());
+    }
     Ok(())
 }
@@ -1 +1,6 @@
-mod model_renderer;
\ No newline at end of file
+mod model_renderer;
+mod scene_target;
+mod skybox;
+mod thumbnail;
+
+pub use self::{model_renderer::*, scene_target::*, skybox::*, thumbnail::*};
@@ -1,11 +1,53 @@
-use std::sync::Arc;
+use std::{mem::size_of, sync::Arc};
 
 use ash::vk;
-use gltf_model::{Model, ModelStagingResources, MAX_JOINTS_PER_MESH};
-use math::cgmath::Matrix4;
-use vks::{Buffer, Context, PreLoadedResource};
+use environment::Environment;
+use gltf_model::{
+    JointsBuffer, Material, MaterialFeatures, MaterialUniforms, Model, ModelStagingResources,
+    ModelVertex, MAX_JOINTS_PER_MESH,
+};
+use math::cgmath::{Matrix4, SquareMatrix};
+use vks::{
+    create_host_visible_buffer, create_pipeline, mem_copy, Buffer, CameraUniforms, Context,
+    DrawDebugId, GrowableDescriptorPool, LightManager, PipelineParameters, PoolSizeRatio,
+    PreLoadedResource, ShaderParameters, ShadingRateState, SpecializationConstants, Texture,
+    DEFAULT_EMISSIVE_INTENSITY,
+};
 
-type JointsBuffer = [Matrix4<f32>; MAX_JOINTS_PER_MESH];
+use crate::SceneAttachments;
+
+/// Bindings of the sets of the models, laid out as the uber shader expects them.
+const CAMERA_BINDING: u32 = 0;
+const COLOR_BINDING: u32 = 1;
+const MATERIAL_BINDING: u32 = 3;
+/// Normal, emissive, clearcoat and transmission textures.
+const MATERIAL_TEXTURES_BINDING: u32 = 4;
+const LIGHTS_BINDING: u32 = 8;
+const ENVIRONMENT_BINDING: u32 = 9;
+/// Joints of the skin of the drawn node, a dynamic uniform buffer.
+const SKIN_BINDING: u32 = 12;
+
+/// Descriptors of a set of [`create_model_descriptor_set_layout`].
+const POOL_SIZE_RATIOS: [PoolSizeRatio; 4] = [
+    PoolSizeRatio::new(vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    PoolSizeRatio::new(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    PoolSizeRatio::new(vk::DescriptorType::STORAGE_BUFFER, 1.0),
+    PoolSizeRatio::new(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 8.0),
+];
+
+const VERTEX_SHADER_NAME: &str = "model";
+const FRAGMENT_SHADER_NAME: &str = "uber";
+
+/// Pushed after the [`DrawDebugId`] of each draw.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ModelPushConstants {
+    transform: [[f32; 4]; 4],
+    skinned: u32,
+}
+
+/// Offset of [`ModelPushConstants`], after the draw id and aligned for its matrix.
+const MODEL_PUSH_CONSTANTS_OFFSET: u32 = 16;
 
 pub fn load_assets(
     context: Arc<Context>,
@@ -42,12 +84,496 @@ pub fn load_assets(
     model
 }
 
+/// A glTF model drawn in the scene pass with the variants of the uber shader
+/// for its materials.
+///
+/// Each camera slot has its own skin buffer and one set per primitive, binding
+/// the camera uniforms and lights of the slot with the material of the
+/// primitive. The sets are laid out by [`create_model_descriptor_set_layout`]
+/// and the pipelines created by [`create_model_pipeline`].
 pub struct ModelRender {
     context: Arc<Context>,
     model: Box<Model>,
-    transform_ubos: Vec<Buffer>,
     skin_ubos: Vec<Buffer>,
     skin_matrices: Vec<Vec<JointsBuffer>>,
-    materials_ubo: Buffer,
-    light_ubos: Vec<Buffer>,
+    material_uniforms: MaterialUniforms,
+    /// Bound in place of the textures missing from the materials.
+    white_texture: Texture,
+    /// Sets of each camera slot, indexed by primitive.
+    sets: Vec<Vec<vk::DescriptorSet>>,
+    _descriptor_pool: GrowableDescriptorPool,
+}
+
+impl ModelRender {
+    /// Create the skin buffers and sets of `model` for each slot of `camera_uniforms`.
+    ///
+    /// The sets bind `environment` if there is one, see [`ModelRender::write_environment`].
+    pub fn new(
+        context: Arc<Context>,
+        model: Model,
+        layout: vk::DescriptorSetLayout,
+        camera_uniforms: &CameraUniforms,
+        lights: &LightManager,
+        environment: Option<&Environment>,
+    ) -> Self {
+        let slot_count = camera_uniforms.count();
+
+        // A model without skin still gets a buffer so the descriptors are always valid.
+        let skin_count = model.skins().len().max(1);
+        let identity: JointsBuffer = [Matrix4::identity(); MAX_JOINTS_PER_MESH];
+        let mut skin_matrices = vec![vec![identity; skin_count]; slot_count];
+        for matrices in skin_matrices.iter_mut() {
+            matrices
+                .iter_mut()
+                .zip(model.skins())
+                .for_each(|(buffer, skin)| skin.write_joints_buffer(buffer));
+        }
+        let skin_ubos = skin_matrices
+            .iter()
+            .map(|matrices| {
+                create_host_visible_buffer(&context, vk::BufferUsageFlags::UNIFORM_BUFFER, matrices)
+            })
+            .collect();
+
+        // The materials of the scene are not edited, they are uploaded once.
+        let mut material_uniforms = MaterialUniforms::new(&context, &model, slot_count);
+        for slot in 0..slot_count {
+            material_uniforms.update(slot, &model, DEFAULT_EMISSIVE_INTENSITY);
+        }
+
+        let white_texture = Texture::from_rgba(&context, 1, 1, &[u8::MAX; 4], true)
+            .expect("Failed to create texture");
+
+        let primitive_count = model.primitive_count();
+        let mut descriptor_pool = GrowableDescriptorPool::new(
+            Arc::clone(&context),
+            &POOL_SIZE_RATIOS,
+            (slot_count * primitive_count) as _,
+        );
+        let layouts = vec![layout; primitive_count];
+        let sets = (0..slot_count)
+            .map(|_| {
+                if layouts.is_empty() {
+                    return Vec::new();
+                }
+                descriptor_pool
+                    .allocate_many(&layouts)
+                    .expect("Failed to allocate descriptor sets")
+            })
+            .collect();
+
+        let model_render = Self {
+            context,
+            model: Box::new(model),
+            skin_ubos,
+            skin_matrices,
+            material_uniforms,
+            white_texture,
+            sets,
+            _descriptor_pool: descriptor_pool,
+        };
+        for slot in 0..slot_count {
+            model_render.write_descriptors(slot, camera_uniforms, lights);
+            if let Some(environment) = environment {
+                model_render.write_environment(environment, slot);
+            }
+        }
+        model_render
+    }
+
+    fn write_descriptors(
+        &self,
+        slot: usize,
+        camera_uniforms: &CameraUniforms,
+        lights: &LightManager,
+    ) {
+        let camera_info = [camera_uniforms.descriptor_info(slot)];
+        let lights_info = [lights.descriptor_info(slot)];
+        let skin_info = [vk::DescriptorBufferInfo::default()
+            .buffer(self.skin_ubos[slot].buffer)
+            .offset(0)
+            .range(size_of::<JointsBuffer>() as _)];
+
+        for primitive in self
+            .model
+            .meshes()
+            .iter()
+            .flat_map(|mesh| mesh.primitives())
+        {
+            let set = self.sets[slot][primitive.index()];
+            let material_info = [self
+                .material_uniforms
+                .descriptor_info(slot, primitive.index())];
+            let texture_infos = self.texture_infos(&primitive.material());
+
+            let mut descriptor_writes = vec![
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(CAMERA_BINDING)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&camera_info),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(COLOR_BINDING)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&texture_infos[..1]),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(MATERIAL_BINDING)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&material_info),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(LIGHTS_BINDING)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&lights_info),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(SKIN_BINDING)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                    .buffer_info(&skin_info),
+            ];
+            descriptor_writes.extend(texture_infos[1..].iter().enumerate().map(
+                |(index, image_info)| {
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(set)
+                        .dst_binding(MATERIAL_TEXTURES_BINDING + index as u32)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(std::slice::from_ref(image_info))
+                },
+            ));
+
+            unsafe {
+                self.context
+                    .device()
+                    .update_descriptor_sets(&descriptor_writes, &[])
+            }
+        }
+    }
+
+    /// Color, normal, emissive, clearcoat and transmission textures of `material`,
+    /// the white texture for the ones it does not have.
+    fn texture_infos(&self, material: &Material) -> [vk::DescriptorImageInfo; 5] {
+        [
+            material.get_color_texture_index(),
+            material.get_normals_texture_index(),
+            material.get_emissive_texture_index(),
+            material
+                .get_clearcoat()
+                .and_then(|clearcoat| clearcoat.factor_texture_index()),
+            material
+                .get_transmission()
+                .and_then(|transmission| transmission.texture_index()),
+        ]
+        .map(|index| {
+            let (view, sampler) = match index {
+                Some(index) => {
+                    let texture = &self.model.textures()[index];
+                    (texture.get_view(), texture.get_sampler())
+                }
+                None => (self.white_texture.view, self.white_texture.sampler.unwrap()),
+            };
+            vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(view)
+                .sampler(sampler)
+        })
+    }
+
+    /// Write the textures of `environment` to the sets of the camera slot `slot`.
+    ///
+    /// The sets must not be used by pending commands.
+    pub fn write_environment(&self, environment: &Environment, slot: usize) {
+        for set in self.sets[slot].iter() {
+            environment.write_descriptors(&self.context, *set, ENVIRONMENT_BINDING);
+        }
+    }
+}
+
+impl ModelRender {
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Buffer holding the joints of every skin for the camera slot `slot`, one [`JointsBuffer`] per skin.
+    pub fn skin_buffer(&self, slot: usize) -> &Buffer {
+        &self.skin_ubos[slot]
+    }
+
+    /// Advance the animation of the model by `delta_time` and write the joints
+    /// of its skins to the skin buffer of `slot`.
+    ///
+    /// The buffer is written even if the animation is paused, as it may hold
+    /// the joints of an older frame.
+    pub fn update(&mut self, slot: usize, delta_time: f32) {
+        self.model.update(delta_time);
+        self.write_skins(slot);
+    }
+
+    /// Write the joints of the current pose to the skin buffer of `slot`,
+    /// without advancing the animation.
+    pub fn write_skins(&mut self, slot: usize) {
+        let matrices = &mut self.skin_matrices[slot];
+        matrices
+            .iter_mut()
+            .zip(self.model.skins())
+            .for_each(|(buffer, skin)| skin.write_joints_buffer(buffer));
+
+        let buffer = &mut self.skin_ubos[slot];
+        unsafe { mem_copy(buffer.map_memory(), matrices) };
+    }
+
+    /// Record the draws of the primitives of the model in the rendering begun
+    /// by the caller, with the sets of the camera slot `slot`.
+    ///
+    /// `pipeline` returns the pipeline of the variant of the uber shader for
+    /// the features of a material, created with `layout`.
+    pub fn cmd_draw<F: Fn(MaterialFeatures) -> vk::Pipeline>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        slot: usize,
+        pipeline: F,
+    ) {
+        let device = self.context.device();
+        let mut bound_pipeline = vk::Pipeline::null();
+
+        for (node_index, node) in self.model.nodes().nodes().iter().enumerate() {
+            let Some(mesh_index) = node.mesh_index() else {
+                continue;
+            };
+            let skin_offset = node
+                .skin_index()
+                .map_or(0, |index| index * size_of::<JointsBuffer>());
+            let constants = ModelPushConstants {
+                transform: node.transform().into(),
+                skinned: node.skin_index().is_some() as _,
+            };
+
+            for primitive in self.model.mesh(mesh_index).primitives() {
+                let material = primitive.material();
+                let primitive_pipeline = pipeline(material.features());
+                if primitive_pipeline != bound_pipeline {
+                    unsafe {
+                        device.cmd_bind_pipeline(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            primitive_pipeline,
+                        )
+                    };
+                    bound_pipeline = primitive_pipeline;
+                }
+
+                self.context.cmd_set_draw_debug_id(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    DrawDebugId::new(Some(node_index), primitive.material_index()),
+                );
+                unsafe {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout,
+                        0,
+                        &[self.sets[slot][primitive.index()]],
+                        &[skin_offset as u32],
+                    );
+                    device.cmd_push_constants(
+                        command_buffer,
+                        layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        MODEL_PUSH_CONSTANTS_OFFSET,
+                        util::any_as_u8_slice(&constants),
+                    );
+
+                    let vertices = primitive.vertices();
+                    device.cmd_bind_vertex_buffers(
+                        command_buffer,
+                        0,
+                        &[vertices.buffer().buffer],
+                        &[vertices.offset()],
+                    );
+                    match primitive.indices() {
+                        Some(indices) => {
+                            device.cmd_bind_index_buffer(
+                                command_buffer,
+                                indices.buffer().buffer,
+                                indices.offset(),
+                                indices.index_type(),
+                            );
+                            device.cmd_draw_indexed(
+                                command_buffer,
+                                indices.element_count(),
+                                1,
+                                0,
+                                0,
+                                0,
+                            );
+                        }
+                        None => device.cmd_draw(command_buffer, vertices.element_count(), 1, 0, 0),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Create the layout of the sets of a [`ModelRender`].
+pub fn create_model_descriptor_set_layout(context: &Context) -> vk::DescriptorSetLayout {
+    let fragment = vk::ShaderStageFlags::FRAGMENT;
+    let mut bindings = vec![
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(CAMERA_BINDING)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | fragment),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(COLOR_BINDING)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(fragment),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(MATERIAL_BINDING)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(fragment),
+        LightManager::descriptor_set_layout_binding(LIGHTS_BINDING, fragment),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(SKIN_BINDING)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+    ];
+    bindings.extend((0..4).map(|index| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(MATERIAL_TEXTURES_BINDING + index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(fragment)
+    }));
+    bindings.extend(Environment::layout_bindings(ENVIRONMENT_BINDING, fragment));
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    unsafe {
+        context
+            .device()
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    }
+}
+
+/// Create the layout of the pipelines of the models, with the sets of `set_layout`.
+pub fn create_model_pipeline_layout(
+    context: &Context,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let push_constant_ranges = [
+        DrawDebugId::push_constant_range(
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+        ),
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: MODEL_PUSH_CONSTANTS_OFFSET,
+            size: size_of::<ModelPushConstants>() as _,
+        },
+    ];
+    let set_layouts = [set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+/// Create the variant of the uber shader drawing the primitives of the models
+/// with materials of `features`, lit by the environment or not, to the
+/// `attachments` of the scene pass.
+///
+/// `shading_rate` must be true if the pass uses a shading rate attachment,
+/// which is then set dynamically.
+pub fn create_model_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    attachments: SceneAttachments,
+    shading_rate: bool,
+    (features, environment): (MaterialFeatures, bool),
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(features.cull_mode())
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(attachments.samples)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(features.alpha_to_coverage())
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [features.color_blend_attachment()];
+
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if shading_rate {
+        dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+    }
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    // The models do not write texture feedback, only the quad texture is tracked.
+    let mut constants = SpecializationConstants::new();
+    constants.add_u32(0, 0);
+    constants.add_bool(1, false);
+    features.add_constants(&mut constants);
+    constants.add_bool(12, environment);
+    let specialization_info = constants.info();
+
+    create_pipeline::<ModelVertex>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new(VERTEX_SHADER_NAME),
+            fragment_shader_params: ShaderParameters::specialized(
+                FRAGMENT_SHADER_NAME,
+                &specialization_info,
+            ),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: shading_rate.then(ShadingRateState::attachment),
+            shading_rate_attachment: shading_rate,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[attachments.color_format],
+            depth_attachment_format: Some(attachments.depth_format),
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
 }
@@ -0,0 +1,359 @@
+use std::{mem::size_of, sync::Arc};
+
+use ash::vk;
+use util::any_as_u8_slice;
+use vks::{
+    create_pipeline, Context, Descriptors, OffscreenTarget, OutputTransform, PipelineParameters,
+    ShaderParameters, Texture,
+};
+
+const SHADER_NAME: &str = "scene_output";
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct OutputConstants {
+    encode_srgb: u32,
+}
+
+/// Formats and sample count of the attachments of the scene pass, which the
+/// pipelines drawing the scene are created for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SceneAttachments {
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+/// Attachments the scene is rendered to, in the negotiated scene color format,
+/// then written to the swapchain images.
+///
+/// Multisampled targets resolve the color at the end of the scene pass. The
+/// scene pipelines only depend on the format and sample count of the target,
+/// not on the format of the swapchain. [`SceneTarget::cmd_output`] applies the output
+/// transform of the surface. The target must be resized with the swapchain.
+pub struct SceneTarget {
+    context: Arc<Context>,
+    target: OffscreenTarget,
+    output_format: vk::SurfaceFormatKHR,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl SceneTarget {
+    /// Create a target of `extent` with attachments of `color_format` and
+    /// `depth_format` with `samples`, written to images of `output_format`.
+    pub fn new(
+        context: &Arc<Context>,
+        extent: vk::Extent2D,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
+        output_format: vk::SurfaceFormatKHR,
+    ) -> Self {
+        let target = OffscreenTarget::new(context, extent, color_format, depth_format, samples);
+        let descriptors = create_descriptors(context, target.color());
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline = create_output_pipeline(context, pipeline_layout, output_format);
+
+        Self {
+            context: Arc::clone(context),
+            target,
+            output_format,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl SceneTarget {
+    pub fn extent(&self) -> vk::Extent2D {
+        self.target.extent()
+    }
+
+    pub fn attachments(&self) -> SceneAttachments {
+        SceneAttachments {
+            color_format: self.target.color_format(),
+            depth_format: self.target.depth_format(),
+            samples: self.target.samples(),
+        }
+    }
+
+    /// Transition the attachments for the scene pass, see
+    /// [`OffscreenTarget::cmd_prepare_attachments`].
+    pub fn cmd_prepare_attachments(&self, command_buffer: vk::CommandBuffer) {
+        self.target.cmd_prepare_attachments(command_buffer);
+    }
+
+    /// Color attachment of the scene pass, resolved if the target is multisampled.
+    pub fn color_attachment_info(
+        &self,
+        clear_color: [f32; 4],
+    ) -> vk::RenderingAttachmentInfo<'static> {
+        self.target.color_attachment_info(clear_color)
+    }
+
+    pub fn depth_attachment_info(&self) -> vk::RenderingAttachmentInfo<'static> {
+        self.target.depth_attachment_info()
+    }
+
+    /// Recreate the attachments for a new swapchain. The previous ones, their
+    /// descriptors and pipeline go to the [`vks::DeletionQueue`].
+    pub fn resize(&mut self, extent: vk::Extent2D, output_format: vk::SurfaceFormatKHR) {
+        let target = OffscreenTarget::new(
+            &self.context,
+            extent,
+            self.target.color_format(),
+            self.target.depth_format(),
+            self.target.samples(),
+        );
+        let descriptors = create_descriptors(&self.context, target.color());
+        let deletion_queue = self.context.deletion_queue();
+        deletion_queue.enqueue(std::mem::replace(&mut self.target, target));
+        deletion_queue.enqueue(std::mem::replace(&mut self.descriptors, descriptors));
+
+        if output_format != self.output_format {
+            let pipeline = std::mem::replace(
+                &mut self.pipeline,
+                create_output_pipeline(&self.context, self.pipeline_layout, output_format),
+            );
+            deletion_queue
+                .enqueue_destroy(move |device| unsafe { device.destroy_pipeline(pipeline, None) });
+            self.output_format = output_format;
+        }
+    }
+
+    /// Record the write of the scene color to `output_view`, outside of any rendering.
+    ///
+    /// The color must be in `COLOR_ATTACHMENT_OPTIMAL` layout, it is left in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout. `output_view` must be in
+    /// `COLOR_ATTACHMENT_OPTIMAL` layout, with the extent of the target and
+    /// the format passed at creation. Its previous content is discarded.
+    pub fn cmd_output(&self, command_buffer: vk::CommandBuffer, output_view: vk::ImageView) {
+        let device = self.context.device();
+        let extent = self.extent();
+
+        self.target.color().image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .image_view(output_view)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        let output_transform = self
+            .context
+            .color_policy()
+            .output_transform(self.output_format);
+        let constants = OutputConstants {
+            encode_srgb: (output_transform == OutputTransform::ShaderSrgb) as _,
+        };
+
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+            self.context.cmd_begin_pass(command_buffer, "scene output");
+
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                any_as_u8_slice(&constants),
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            self.context.cmd_end_pass(command_buffer);
+            self.context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer);
+        }
+    }
+}
+
+impl Drop for SceneTarget {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Create the set sampling `color` in the output pass. The layout is the same
+/// for every target so the set matches the output pipeline layout.
+fn create_descriptors(context: &Arc<Context>, color: &Texture) -> Descriptors {
+    let device = context.device();
+    let bindings = [vk::DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 1,
+    }];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let image_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(color.view)
+        .sampler(color.sampler.expect("Scene color must have a sampler"))];
+    let descriptor_writes = [vk::WriteDescriptorSet::default()
+        .dst_set(sets[0])
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(&image_info)];
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_pipeline_layout(
+    context: &Context,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let layouts = [set_layout];
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: size_of::<OutputConstants>() as _,
+    }];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+fn create_output_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    output_format: vk::SurfaceFormatKHR,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(false)
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    create_pipeline::<()>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new(SHADER_NAME),
+            fragment_shader_params: ShaderParameters::new(SHADER_NAME),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: None,
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[context.color_policy().attachment_format(output_format)],
+            depth_attachment_format: None,
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
+}
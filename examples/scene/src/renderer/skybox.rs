@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use ash::vk;
+use vks::{create_pipeline, Context, PipelineParameters, ShaderParameters, ShadingRateState};
+
+use crate::SceneAttachments;
+
+const SHADER_NAME: &str = "skybox";
+
+/// Background of the scene, the environment seen in the direction of each pixel.
+///
+/// It is drawn first in the scene pass, without depth, with the set of the
+/// scene bound. The set must bind the camera uniforms at binding 0 and the
+/// pre-filtered map of the environment at binding 10, see
+/// [`environment::Environment::layout_bindings`].
+pub struct Skybox {
+    context: Arc<Context>,
+    pipeline: vk::Pipeline,
+}
+
+impl Skybox {
+    /// Create the pipeline of the skybox for the `attachments` of the scene
+    /// pass. `shading_rate` must be true if the pass uses a shading rate
+    /// attachment, which is then set dynamically.
+    pub fn new(
+        context: &Arc<Context>,
+        layout: vk::PipelineLayout,
+        attachments: SceneAttachments,
+        shading_rate: bool,
+    ) -> Self {
+        let pipeline = create_skybox_pipeline(context, layout, attachments, shading_rate);
+
+        Self {
+            context: Arc::clone(context),
+            pipeline,
+        }
+    }
+}
+
+impl Skybox {
+    /// Record the draw of the skybox in the rendering begun by the caller.
+    pub fn cmd_draw(&self, command_buffer: vk::CommandBuffer) {
+        let device = self.context.device();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        unsafe { self.context.device().destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+fn create_skybox_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    attachments: SceneAttachments,
+    shading_rate: bool,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(attachments.samples)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(false)
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if shading_rate {
+        dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+    }
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    // Behind everything drawn after it.
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    create_pipeline::<()>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new(SHADER_NAME),
+            fragment_shader_params: ShaderParameters::new(SHADER_NAME),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: shading_rate.then(ShadingRateState::attachment),
+            shading_rate_attachment: shading_rate,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[attachments.color_format],
+            depth_attachment_format: Some(attachments.depth_format),
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
+}
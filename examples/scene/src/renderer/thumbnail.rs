@@ -0,0 +1,376 @@
+use std::{error::Error, mem::size_of, path::Path, sync::Arc};
+
+use ash::vk;
+use gltf_model::{IndexBuffer, Model, ModelOptions, ModelVertex, StaticBatching, VertexBuffer};
+use math::{
+    cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3},
+    Aabb,
+};
+use vks::{
+    create_pipeline, find_depth_format, Camera, Context, OffscreenTarget, PipelineParameters,
+    ShaderParameters,
+};
+
+use crate::save_png;
+
+/// Format of the thumbnails, saved as is to png.
+const THUMBNAIL_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+const CLEAR_COLOR: [f32; 4] = [0.18, 0.18, 0.2, 1.0];
+const FOV: f32 = 35.0;
+/// Direction from the model to the camera, from the front right and above.
+const VIEW_DIRECTION: [f32; 3] = [0.6, 0.5, 1.0];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ThumbnailPushConstants {
+    mvp: [[f32; 4]; 4],
+    /// Columns of the rotation and scale of the model matrix.
+    normal_matrix: [[f32; 4]; 3],
+    color: [f32; 4],
+}
+
+/// Render previews of glTF models to png files, for asset browsers.
+///
+/// Runs on its own headless context so it never stalls the interactive
+/// renderer and can live on a worker thread. Models are drawn in their bind
+/// pose with the base color of their materials under a fixed light, framed by
+/// a camera placed from their bounds.
+///
+/// Each model is released once its thumbnail is written.
+pub struct ThumbnailGenerator {
+    target: OffscreenTarget,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    context: Arc<Context>,
+}
+
+impl ThumbnailGenerator {
+    /// Create a generator of square thumbnails of `size` pixels.
+    pub fn new(size: u32) -> Self {
+        let context = Arc::new(
+            Context::new_headless(None, false).expect("Failed to create thumbnail context"),
+        );
+        let depth_format = find_depth_format(&context);
+        let target = OffscreenTarget::new(
+            &context,
+            vk::Extent2D {
+                width: size,
+                height: size,
+            },
+            THUMBNAIL_FORMAT,
+            depth_format,
+            vk::SampleCountFlags::TYPE_1,
+        );
+        let pipeline_layout = create_pipeline_layout(&context);
+        let pipeline = create_thumbnail_pipeline(&context, pipeline_layout, depth_format);
+
+        Self {
+            target,
+            pipeline_layout,
+            pipeline,
+            context,
+        }
+    }
+}
+
+impl ThumbnailGenerator {
+    /// Width and height of the thumbnails in pixels.
+    pub fn extent(&self) -> [u32; 2] {
+        let extent = self.target.extent();
+        [extent.width, extent.height]
+    }
+
+    /// Load the glTF model at `model_path` and write its thumbnail to `output_path`.
+    pub fn generate<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        model_path: P,
+        output_path: Q,
+    ) -> Result<(), Box<dyn Error>> {
+        let pixels = self.render(model_path.as_ref())?;
+        save_png(output_path.as_ref(), pixels, self.extent(), false)?;
+        tracing::info!(
+            "Thumbnail of {} saved to {}",
+            model_path.as_ref().display(),
+            output_path.as_ref().display()
+        );
+        Ok(())
+    }
+
+    /// Load the glTF model at `model_path` and render its thumbnail.
+    ///
+    /// # Returns
+    ///
+    /// The 8 bits rgba texels of the thumbnail, as saved by [`ThumbnailGenerator::generate`].
+    pub fn render<P: AsRef<Path>>(&self, model_path: P) -> Result<Vec<u8>, Box<dyn Error>> {
+        let model = load_model(&self.context, model_path.as_ref())?;
+        let aabb = model_aabb(&model).ok_or("Model has no mesh")?;
+        let view_proj = frame_camera(aabb);
+
+        self.context.execute_one_time_commands(|command_buffer| {
+            self.target.cmd_begin_rendering(command_buffer, CLEAR_COLOR);
+            self.cmd_draw_model(command_buffer, &model, view_proj);
+            self.target.cmd_end_rendering(command_buffer);
+        });
+
+        Ok(self.target.read_back())
+    }
+
+    fn cmd_draw_model(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        model: &Model,
+        view_proj: Matrix4<f32>,
+    ) {
+        let device = self.context.device();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            )
+        };
+
+        let cmd_draw = |transform: Matrix4<f32>,
+                        color: [f32; 4],
+                        vertices: &VertexBuffer,
+                        indices: Option<&IndexBuffer>| {
+            let constants = ThumbnailPushConstants {
+                mvp: (view_proj * transform).into(),
+                normal_matrix: [transform.x.into(), transform.y.into(), transform.z.into()],
+                color,
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    util::any_as_u8_slice(&constants),
+                );
+                device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[vertices.buffer().buffer],
+                    &[vertices.offset()],
+                );
+                match indices {
+                    Some(indices) => {
+                        device.cmd_bind_index_buffer(
+                            command_buffer,
+                            indices.buffer().buffer,
+                            indices.offset(),
+                            indices.index_type(),
+                        );
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            indices.element_count(),
+                            1,
+                            0,
+                            0,
+                            0,
+                        );
+                    }
+                    None => device.cmd_draw(command_buffer, vertices.element_count(), 1, 0, 0),
+                }
+            }
+        };
+
+        for batch in model.static_batches() {
+            cmd_draw(
+                model.global_transform(),
+                batch.material().get_color(),
+                batch.vertices(),
+                Some(batch.indices()),
+            );
+        }
+
+        for (node_index, node) in model.nodes().nodes().iter().enumerate() {
+            let Some(mesh_index) = node.mesh_index() else {
+                continue;
+            };
+            for primitive in model.mesh(mesh_index).primitives() {
+                if model.is_batched(node_index, primitive) {
+                    continue;
+                }
+                cmd_draw(
+                    node.transform(),
+                    primitive.material().get_color(),
+                    primitive.vertices(),
+                    primitive.indices().as_ref(),
+                );
+            }
+        }
+    }
+}
+
+impl Drop for ThumbnailGenerator {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Load the model at `path` and wait for its upload.
+fn load_model(context: &Arc<Context>, path: &Path) -> Result<Model, Box<dyn Error>> {
+    let device = context.device();
+    let command_buffer = {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(context.general_command_pool())
+            .level(vk::CommandBufferLevel::SECONDARY)
+            .command_buffer_count(1);
+        unsafe {
+            device
+                .allocate_command_buffers(&allocate_info)
+                .expect("Failed to allocate command buffer")[0]
+        }
+    };
+
+    let inheritance_info = vk::CommandBufferInheritanceInfo::default();
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .inheritance_info(&inheritance_info)
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .expect("Failed to begin command buffer")
+    };
+
+    // Thumbnails are drawn once, in the bind pose, so the static parts are merged.
+    let options = ModelOptions {
+        static_batching: Some(StaticBatching::default()),
+        ..Default::default()
+    };
+    let result =
+        Model::create_from_file_with_options(Arc::clone(context), command_buffer, path, &options);
+    unsafe {
+        device
+            .end_command_buffer(command_buffer)
+            .expect("Failed to end command buffer")
+    };
+    match result {
+        // Submits the upload, waits for it and frees the command buffer.
+        Ok(mut model) => Ok(model.finish()),
+        Err(error) => {
+            unsafe {
+                device.free_command_buffers(context.general_command_pool(), &[command_buffer])
+            };
+            Err(error)
+        }
+    }
+}
+
+/// Bounds of the meshes of `model` in its bind pose, `None` if it has no mesh.
+fn model_aabb(model: &Model) -> Option<Aabb<f32>> {
+    let aabbs = model
+        .nodes()
+        .nodes()
+        .iter()
+        .filter_map(|node| {
+            node.mesh_index()
+                .map(|index| model.mesh(index).aabb() * node.transform())
+        })
+        .collect::<Vec<_>>();
+    Aabb::union(&aabbs)
+}
+
+/// View projection matrix of a camera seeing the whole bounding sphere of `aabb`.
+fn frame_camera(aabb: Aabb<f32>) -> Matrix4<f32> {
+    let center = aabb.get_center();
+    let radius = aabb
+        .get_corners()
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(f32::EPSILON, f32::max);
+    // Distance at which the sphere fits the vertical field of view, with a margin.
+    let distance = radius / (FOV * 0.5).to_radians().sin() * 1.05;
+
+    let target = Point3::from_vec(center);
+    let position = target + Vector3::from(VIEW_DIRECTION).normalize() * distance;
+    let mut camera = Camera::default();
+    camera.fov = Deg(FOV);
+    camera.z_near = (distance - radius).max(distance * 0.01);
+    camera.z_far = distance + radius;
+    camera.look_at(position, target);
+
+    camera.projection_matrix(1.0) * camera.view_matrix()
+}
+
+fn create_pipeline_layout(context: &Arc<Context>) -> vk::PipelineLayout {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: size_of::<ThumbnailPushConstants>() as _,
+    }];
+    let layout_info =
+        vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+fn create_thumbnail_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    depth_format: vk::Format,
+) -> vk::Pipeline {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    // The winding of the models is not trusted, both sides are drawn.
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .min_sample_shading(1.0);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0);
+
+    create_pipeline::<ModelVertex>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new("thumbnail"),
+            fragment_shader_params: ShaderParameters::new("thumbnail"),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[THUMBNAIL_FORMAT],
+            depth_attachment_format: Some(depth_format),
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline")
+}
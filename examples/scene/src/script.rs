@@ -0,0 +1,102 @@
+use std::{error::Error, path::Path};
+
+use ash::vk;
+use scene::{png_swizzle, save_png};
+use vks::{pick_pixel, Image, PickTarget, ScriptCommand, ScriptHost};
+
+/// Run a [`ScriptHost`], keeping the screenshots and pixel checks of a frame
+/// until it is submitted.
+pub struct ScriptRunner {
+    host: ScriptHost,
+    captures: Vec<ScriptCommand>,
+}
+
+impl ScriptRunner {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            host: ScriptHost::from_file(path)?,
+            captures: Vec::new(),
+        })
+    }
+
+    /// Advance the script and return the commands to apply before rendering.
+    pub fn update(&mut self, delta_time_secs: f32) -> Vec<ScriptCommand> {
+        let (captures, commands) = self
+            .host
+            .update(delta_time_secs)
+            .into_iter()
+            .partition::<Vec<_>, _>(|command| {
+                matches!(
+                    command,
+                    ScriptCommand::Screenshot(_) | ScriptCommand::AssertPixel(_)
+                )
+            });
+        self.captures.extend(captures);
+        commands
+    }
+
+    /// Take the screenshots and check the pixels asked for the frame rendered to `image`.
+    ///
+    /// `image` is a swapchain image in `PRESENT_SRC_KHR` layout whose rendering was
+    /// submitted but not presented yet. Waits for the device.
+    pub fn capture(&mut self, image: &Image, usage: vk::ImageUsageFlags) {
+        if self.captures.is_empty() {
+            return;
+        }
+        let readable = usage.contains(vk::ImageUsageFlags::TRANSFER_SRC);
+        if !readable {
+            tracing::warn!("Swapchain images can't be read back, script captures fail");
+        }
+
+        for capture in std::mem::take(&mut self.captures) {
+            match capture {
+                ScriptCommand::Screenshot(path) if readable => {
+                    if let Err(error) = save_screenshot(image, &path) {
+                        tracing::error!("Failed to save screenshot {}: {}", path.display(), error);
+                    }
+                }
+                ScriptCommand::AssertPixel(assertion) => {
+                    let picked = readable
+                        .then(|| {
+                            pick_pixel(
+                                image,
+                                vk::ImageLayout::PRESENT_SRC_KHR,
+                                PickTarget::Color,
+                                assertion.position,
+                            )
+                        })
+                        .flatten();
+                    self.host.report_assertion(&assertion, picked);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn failure_count(&self) -> u32 {
+        self.host.failure_count()
+    }
+}
+
+fn save_screenshot(image: &Image, path: &Path) -> Result<(), Box<dyn Error>> {
+    let swizzle = png_swizzle(image.format)?;
+
+    image.transition_image_layout(
+        vk::ImageLayout::PRESENT_SRC_KHR,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    );
+    let pixels = image.read_back(0, 4);
+    image.transition_image_layout(
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::ImageLayout::PRESENT_SRC_KHR,
+    );
+
+    save_png(
+        path,
+        pixels,
+        [image.extent.width, image.extent.height],
+        swizzle,
+    )?;
+    tracing::info!("Screenshot saved to {}", path.display());
+    Ok(())
+}
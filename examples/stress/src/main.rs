@@ -0,0 +1,600 @@
+//! Soak test for the resource lifetimes.
+//!
+//! Cycles through swapchain recreations, HDR/vsync/MSAA toggles, model loads
+//! and fullscreen switches for as long as requested. After every cycle the
+//! live resources reported by the leak tracker are compared with the ones of
+//! the first cycle and any growth is reported.
+//!
+//! Usage: `cargo run -p stress -- [--duration <seconds>] [--step <frames>]`
+
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ash::vk::{self, RenderingAttachmentInfo, RenderingInfo};
+use tracing::{info, warn, Level};
+use util::load_image;
+use vks::{
+    create_device_local_buffer_with_data, Buffer, Camera, LayoutTransition, LeakSnapshot,
+    MipsRange, RenderData, RenderError, Texture, VulkanExampleBase, WindowApp,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, StartCause, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::{Fullscreen, Window, WindowId},
+};
+
+const DEFAULT_STEP_FRAMES: u32 = 120;
+const MODEL_PATH: &str = "assets/chalet.obj";
+const MODEL_TEXTURE_PATH: &str = "assets/chalet.jpg";
+
+#[derive(Debug, Clone, Copy)]
+enum StressAction {
+    Resize([u32; 2]),
+    ToggleVsync,
+    ToggleHdr,
+    ToggleMsaa,
+    LoadModel,
+    UnloadModel,
+    ToggleFullscreen,
+}
+
+/// Actions of one cycle. Every toggle is done twice so the state at the end
+/// of a cycle is the same as the one at its start.
+const STRESS_CYCLE: [StressAction; 12] = [
+    StressAction::Resize([640, 480]),
+    StressAction::ToggleVsync,
+    StressAction::LoadModel,
+    StressAction::ToggleMsaa,
+    StressAction::ToggleHdr,
+    StressAction::ToggleFullscreen,
+    StressAction::ToggleFullscreen,
+    StressAction::ToggleHdr,
+    StressAction::UnloadModel,
+    StressAction::ToggleMsaa,
+    StressAction::ToggleVsync,
+    StressAction::Resize([800, 600]),
+];
+
+struct StressOptions {
+    duration: Option<Duration>,
+    step_frames: u32,
+}
+
+impl StressOptions {
+    fn from_args() -> Result<Self, Box<dyn Error>> {
+        let mut options = Self {
+            duration: None,
+            step_frames: DEFAULT_STEP_FRAMES,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--duration" => {
+                    let seconds = args.next().ok_or("Missing value for --duration")?;
+                    options.duration = Some(Duration::from_secs(seconds.parse()?));
+                }
+                "--step" => {
+                    let frames = args.next().ok_or("Missing value for --step")?;
+                    options.step_frames = frames.parse::<u32>()?.max(1);
+                }
+                _ => return Err(format!("Unknown argument {}", arg).into()),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+struct App {
+    window: Option<Window>,
+    stress_app: Option<StressApp>,
+    options: Option<StressOptions>,
+}
+
+impl App {
+    fn new() -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            window: None,
+            stress_app: None,
+            options: Some(StressOptions::from_args()?),
+        })
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = event_loop
+            .create_window(
+                Window::default_attributes()
+                    .with_title("Stress")
+                    .with_inner_size(PhysicalSize::new(800, 600)),
+            )
+            .expect("Failed to create window");
+
+        let options = self.options.take().unwrap();
+        self.stress_app = Some(StressApp::new(&window, options));
+        self.window = Some(window);
+    }
+
+    fn new_events(&mut self, _: &ActiveEventLoop, _: StartCause) {
+        if let Some(app) = self.stress_app.as_mut() {
+            app.new_frame();
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let app = self.stress_app.as_mut().unwrap();
+        app.end_frame(self.window.as_ref().unwrap());
+        if app.should_exit() {
+            event_loop.exit();
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        if let WindowEvent::CloseRequested = event {
+            event_loop.exit();
+        }
+
+        self.stress_app
+            .as_mut()
+            .unwrap()
+            .handle_window_event(self.window.as_ref().unwrap(), &event);
+    }
+
+    fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
+        self.stress_app
+            .as_mut()
+            .unwrap()
+            .handle_device_event(&event);
+    }
+
+    fn exiting(&mut self, _: &ActiveEventLoop) {
+        if let Some(mut app) = self.stress_app.take() {
+            app.on_exit();
+        }
+
+        // Everything created by the app must be gone now.
+        let remaining = LeakSnapshot::capture();
+        if remaining.is_within(&LeakSnapshot::default()) {
+            info!("No leak detected. {}", remaining);
+        } else {
+            warn!("Resources still alive after shutdown. {}", remaining);
+        }
+    }
+}
+
+/// Geometry and texture loaded and dropped during the test.
+struct StressModel {
+    _vertices: Buffer,
+    _indices: Buffer,
+    _texture: Texture,
+}
+
+impl StressModel {
+    fn load(context: &Arc<vks::Context>) -> Self {
+        let (models, _) =
+            tobj::load_obj(MODEL_PATH, &tobj::GPU_LOAD_OPTIONS).expect("Failed to load model");
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for mesh in models.iter().map(|m| &m.mesh) {
+            let offset = (vertices.len() / 5) as u32;
+            for (index, position) in mesh.positions.chunks_exact(3).enumerate() {
+                vertices.extend_from_slice(position);
+                let coords = mesh.texcoords.get(index * 2..index * 2 + 2);
+                vertices.extend_from_slice(coords.unwrap_or(&[0.0, 0.0]));
+            }
+            indices.extend(mesh.indices.iter().map(|i| i + offset));
+        }
+
+        let vertices = create_device_local_buffer_with_data::<u8, _>(
+            context,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &vertices,
+        );
+        let indices = create_device_local_buffer_with_data::<u8, _>(
+            context,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            &indices,
+        );
+        let (width, height, data) = load_image(MODEL_TEXTURE_PATH);
+        let texture = Texture::from_rgba(context, width, height, &data, false)
+            .expect("Failed to create texture");
+
+        Self {
+            _vertices: vertices,
+            _indices: indices,
+            _texture: texture,
+        }
+    }
+}
+
+pub struct StressApp {
+    base: VulkanExampleBase,
+    options: StressOptions,
+    model: Option<StressModel>,
+    vsync: bool,
+    hdr: bool,
+    fullscreen: bool,
+    default_msaa_samples: vk::SampleCountFlags,
+    frame: u64,
+    cycle: u64,
+    action_index: usize,
+    baseline: Option<LeakSnapshot>,
+    start: Instant,
+    camera: Camera,
+    dirty_swapchain: bool,
+}
+
+impl StressApp {
+    fn new(window: &Window, options: StressOptions) -> Self {
+        let base = VulkanExampleBase::new(window, true);
+        let default_msaa_samples = base.msaa_samples;
+        let hdr = base.swapchain.properties().format.format == vk::Format::R16G16B16A16_SFLOAT;
+
+        info!(
+            "Starting stress test. Duration: {:?}, {} frames per action",
+            options.duration, options.step_frames
+        );
+
+        Self {
+            base,
+            options,
+            model: None,
+            vsync: true,
+            hdr,
+            fullscreen: false,
+            default_msaa_samples,
+            frame: 0,
+            cycle: 0,
+            action_index: 0,
+            baseline: None,
+            start: Instant::now(),
+            camera: Camera::default(),
+            dirty_swapchain: false,
+        }
+    }
+
+    fn apply_action(&mut self, window: &Window, action: StressAction) {
+        tracing::debug!("Stress action {:?}", action);
+        match action {
+            StressAction::Resize([width, height]) => {
+                let _ = window.request_inner_size(PhysicalSize::new(width, height));
+            }
+            StressAction::ToggleVsync => {
+                self.vsync = !self.vsync;
+                self.dirty_swapchain = true;
+            }
+            StressAction::ToggleHdr => {
+                if self.base.context.has_hdr_support() {
+                    self.hdr = !self.hdr;
+                    self.dirty_swapchain = true;
+                }
+            }
+            StressAction::ToggleMsaa => {
+                self.base.msaa_samples = if self.base.msaa_samples == self.default_msaa_samples {
+                    vk::SampleCountFlags::TYPE_1
+                } else {
+                    self.default_msaa_samples
+                };
+                self.dirty_swapchain = true;
+            }
+            StressAction::LoadModel => {
+                self.model = Some(StressModel::load(&self.base.context));
+            }
+            StressAction::UnloadModel => {
+                // Do not free resources the gpu may still use.
+                self.base.wait_idle_gpu();
+                self.model = None;
+            }
+            StressAction::ToggleFullscreen => {
+                self.fullscreen = !self.fullscreen;
+                window.set_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)));
+            }
+        }
+    }
+
+    fn end_cycle(&mut self) {
+        self.cycle += 1;
+        let snapshot = LeakSnapshot::capture();
+
+        // The first cycle creates resources that are kept for the whole run.
+        let Some(baseline) = self.baseline else {
+            info!("Stress cycle {} done. {}", self.cycle, snapshot);
+            self.baseline = Some(snapshot);
+            return;
+        };
+
+        if snapshot.is_within(&baseline) {
+            info!("Stress cycle {} done. {}", self.cycle, snapshot);
+        } else {
+            warn!(
+                "Stress cycle {}: resources grew since the first cycle. Growth: {}",
+                self.cycle,
+                snapshot.since(&baseline)
+            );
+        }
+    }
+}
+
+impl WindowApp for StressApp {
+    fn new_frame(&mut self) {}
+
+    fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent) {
+        if let WindowEvent::Resized(PhysicalSize { width, height }) = event {
+            tracing::debug!("resize {:?}", (width, height));
+            self.dirty_swapchain = true;
+        }
+    }
+
+    fn handle_device_event(&mut self, _event: &DeviceEvent) {}
+
+    fn end_frame(&mut self, window: &Window) {
+        self.frame += 1;
+        if self.frame.is_multiple_of(self.options.step_frames as u64) {
+            let action = STRESS_CYCLE[self.action_index];
+            self.apply_action(window, action);
+            self.action_index = (self.action_index + 1) % STRESS_CYCLE.len();
+            if self.action_index == 0 {
+                self.end_cycle();
+            }
+        }
+
+        // If swapchain must be recreated wait for windows to not be minimized anymore
+        if self.dirty_swapchain {
+            let PhysicalSize { width, height } = window.inner_size();
+            if width > 0 && height > 0 {
+                let (vsync, hdr) = (self.vsync, self.hdr);
+                self.base.recreate_swapchain(window.inner_size().into(), vsync, hdr);
+            } else {
+                return;
+            }
+        }
+        self.dirty_swapchain = match self.render(window, self.camera) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
+    }
+
+    fn on_exit(&mut self) {
+        self.base.wait_idle_gpu();
+        info!(
+            "Stress test ran {} cycles in {:?}",
+            self.cycle,
+            self.start.elapsed()
+        );
+        self.base.destroy_swapchain();
+    }
+
+    fn should_exit(&self) -> bool {
+        self.options
+            .duration
+            .is_some_and(|duration| self.start.elapsed() >= duration)
+    }
+
+    fn render(&mut self, _window: &Window, _camera: Camera) -> Result<(), RenderError> {
+        let sync_objects = self.base.in_flight_frames.next().unwrap();
+        let image_available_semaphore = sync_objects.image_available_semaphore;
+        let render_finished_semaphore = sync_objects.render_finished_semaphore;
+        let in_flight_fence = sync_objects.fence;
+        let wait_fences = [in_flight_fence];
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .unwrap()
+        };
+        self.base.in_flight_frames.collect_deletions();
+
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
+
+        unsafe {
+            self.base
+                .context
+                .device()
+                .reset_fences(&wait_fences)
+                .unwrap()
+        };
+
+        // record_command_buffer
+        {
+            let command_buffer = self.base.command_buffers[image_index as usize];
+            let device = self.base.context.device();
+
+            unsafe {
+                device
+                    .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                    .unwrap();
+                device
+                    .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+                    .unwrap();
+            }
+
+            self.cmd_draw(command_buffer, image_index as _, None);
+
+            unsafe {
+                self.base
+                    .context
+                    .device()
+                    .end_command_buffer(command_buffer)
+                    .unwrap()
+            };
+        }
+
+        // Submit command buffer
+        {
+            let wait_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(image_available_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+
+            let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+                .semaphore(render_finished_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS);
+
+            let cmd_buffer_submit_info = vk::CommandBufferSubmitInfo::default()
+                .command_buffer(self.base.command_buffers[image_index as usize]);
+
+            let submit_info = vk::SubmitInfo2::default()
+                .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info))
+                .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
+                .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
+
+            let _queue_guard = self.base.context.lock_queue();
+            unsafe {
+                self.base
+                    .context
+                    .synchronization2()
+                    .queue_submit2(
+                        self.base.context.graphics_compute_queue(),
+                        std::slice::from_ref(&submit_info),
+                        in_flight_fence,
+                    )
+                    .unwrap()
+            };
+        }
+
+        let swapchains = [self.base.swapchain.swapchain_khr()];
+        let images_indices = [image_index];
+        let signal_semaphores = [render_finished_semaphore];
+
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&images_indices);
+
+        match self.base.swapchain.present(&present_info) {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RenderError::DirtySwapchain),
+            Err(error) => panic!("Failed to present queue. Cause: {}", error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Clear the scene attachments, with the current msaa settings, then the
+    /// swapchain image. Nothing is drawn, only the attachments are exercised.
+    fn cmd_draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        _ui_render_data: Option<&RenderData>,
+    ) {
+        let extent = self.base.swapchain.properties().extent;
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let clear_color = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [(self.frame % 256) as f32 / 255.0, 0.0, 0.0, 1.0],
+            },
+        };
+
+        vks::cmd_transition_images_layouts(
+            command_buffer,
+            &[
+                LayoutTransition {
+                    image: &self.base.scene_color.image,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    mips_range: MipsRange::All,
+                },
+                LayoutTransition {
+                    image: &self.base.scene_depth.image,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    mips_range: MipsRange::All,
+                },
+            ],
+        );
+
+        // Scene pass
+        {
+            let color_attachment_info = RenderingAttachmentInfo::default()
+                .clear_value(clear_color)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .image_view(self.base.scene_color.view)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE);
+
+            let depth_attachment_info = RenderingAttachmentInfo::default()
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                })
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .image_view(self.base.scene_depth.view)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE);
+
+            let rendering_info = RenderingInfo::default()
+                .color_attachments(std::slice::from_ref(&color_attachment_info))
+                .depth_attachment(&depth_attachment_info)
+                .layer_count(1)
+                .render_area(render_area);
+
+            unsafe {
+                let dynamic_rendering = self.base.context.dynamic_rendering();
+                dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info);
+                dynamic_rendering.cmd_end_rendering(command_buffer);
+            }
+        }
+
+        // Swapchain pass
+        {
+            let swapchain_image = &self.base.swapchain.images()[frame_index];
+            swapchain_image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+
+            let color_attachment_info = RenderingAttachmentInfo::default()
+                .clear_value(clear_color)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .image_view(self.base.swapchain.image_views()[frame_index])
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE);
+
+            let rendering_info = RenderingInfo::default()
+                .color_attachments(std::slice::from_ref(&color_attachment_info))
+                .layer_count(1)
+                .render_area(render_area);
+
+            unsafe {
+                let dynamic_rendering = self.base.context.dynamic_rendering();
+                dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info);
+                dynamic_rendering.cmd_end_rendering(command_buffer);
+            }
+
+            swapchain_image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            );
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut app = App::new()?;
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
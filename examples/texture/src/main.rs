@@ -6,14 +6,16 @@ use ash::{
     Device,
 };
 use egui_ash_renderer::{DynamicRendering, Options, Renderer};
+use math::cgmath::Vector3;
 use tracing::{debug, info, Level};
 use util::load_image;
 use vks::{
-    allocate_command_buffers, cmd_transition_images_layouts, create_device_local_buffer_with_data,
-    create_pipeline, Buffer, Camera, CameraUBO, Context, Descriptors, Gui, Image, ImageParameters,
-    LayoutTransition, MipsRange, PipelineParameters, RenderData, RenderError, RendererSetting,
-    ShaderParameters, Swapchain, SwapchainSupportDetails, Texture, Vertex, VulkanExampleBase,
-    WindowApp, MAX_FRAMES_IN_FLIGHT,
+    cmd_transition_images_layouts, create_device_local_buffer_with_data, create_pipeline, Buffer,
+    Camera, CameraUniforms, Context, DescriptorAllocator, Descriptors, FrameStage, FrameTelemetry,
+    GpuProfiler, Gui, Image, ImageParameters, InputState, LayoutTransition, Light, LightManager,
+    MipsRange, PipelineParameters, PresentPacer, RenderData, RenderError, ShaderParameters,
+    SpecializationConstants, TestPatternPass, Texture, Vertex, VulkanExampleBase, WindowApp,
+    DEFAULT_GPU_PROFILER_CAPACITY, DEFAULT_POOL_SIZE_RATIOS, MAX_FRAMES_IN_FLIGHT,
 };
 use winit::{
     application::ApplicationHandler,
@@ -23,10 +25,7 @@ use winit::{
     keyboard::Key,
     window::{Fullscreen, Window, WindowId},
 };
-pub const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
-    format: vk::Format::R16G16B16A16_SFLOAT,
-    color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
-};
+const TELEMETRY_EXPORT_PATH: &str = "frame_telemetry.json";
 
 struct App {
     window: Option<Window>,
@@ -87,7 +86,10 @@ impl ApplicationHandler for App {
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
-        self.triangle_app.as_mut().unwrap().on_exit();
+        // Drop the app while the window is alive, the surface must be destroyed first.
+        if let Some(mut app) = self.triangle_app.take() {
+            app.on_exit();
+        }
     }
 }
 
@@ -165,20 +167,36 @@ impl QuadModel {
             &vertices,
         );
 
+        indices.set_name("quad indices");
+        vertices.set_name("quad vertices");
+
         Self { vertices, indices }
     }
 }
 
 pub struct TextureApp {
+    // Holds a raw device, declared before `base` to be dropped while the device is alive.
     gui_renderer: Renderer,
+    /// The `srgb_framebuffer` option the gui renderer was created with.
+    gui_srgb_framebuffer: bool,
     gui_context: Gui,
+    test_pattern_pass: TestPatternPass,
     base: VulkanExampleBase,
     model: QuadModel,
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+    color_format: vk::Format,
+    descriptor_allocator: DescriptorAllocator,
     descriptors: Descriptors,
+    camera_uniforms: CameraUniforms,
+    lights: LightManager,
+    telemetry: FrameTelemetry,
+    gpu_profiler: GpuProfiler,
+    present_pacer: PresentPacer,
     texture: Texture,
     camera: Camera,
+    input_state: InputState,
+    /// When the input was last sampled.
     time: Instant,
     dirty_swapchain: bool,
 }
@@ -186,6 +204,7 @@ pub struct TextureApp {
 fn prepare_pipeline(
     context: &Arc<Context>,
     set_layouts: &[vk::DescriptorSetLayout],
+    color_format: vk::Format,
 ) -> (vk::Pipeline, vk::PipelineLayout) {
     let device = context.device();
     let layout = {
@@ -248,24 +267,37 @@ fn prepare_pipeline(
             .front(Default::default())
             .back(Default::default());
 
+        // Lit by the lights of the LightManager.
+        let mut constants = SpecializationConstants::new();
+        constants.add_bool(0, true);
+        let specialization_info = constants.info();
+
         create_pipeline::<QuadVertex>(
             context,
             PipelineParameters {
                 vertex_shader_params: ShaderParameters::new("texture"),
-                fragment_shader_params: ShaderParameters::new("texture"),
+                fragment_shader_params: ShaderParameters::specialized(
+                    "texture",
+                    &specialization_info,
+                ),
                 multisampling_info: &multisampling_info,
                 viewport_info: &viewport_info,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
                 rasterizer_info: &rasterizer_info,
                 dynamic_state_info: Some(&dynamic_state_info),
                 depth_stencil_info: Some(&depth_stencil_info),
+                stencil: None,
+                shading_rate: None,
+                shading_rate_attachment: false,
                 color_blend_attachments: &color_blend_attachments,
-                color_attachment_formats: &[vk::Format::R8G8B8A8_SRGB],
+                color_attachment_formats: &[color_format],
                 depth_attachment_format: None,
                 layout,
                 parent: None,
                 allow_derivatives: false,
             },
         )
+        .expect("Failed to create graphics pipeline")
     };
 
     (pipeline, layout)
@@ -292,6 +324,7 @@ fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        LightManager::descriptor_set_layout_binding(2, vk::ShaderStageFlags::FRAGMENT),
     ];
 
     let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
@@ -303,68 +336,30 @@ fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
     }
 }
 
-fn create_descriptor_pool(device: &Device, descriptor_count: u32) -> vk::DescriptorPool {
-    let pool_sizes = [
-        vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count,
-        },
-        vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count,
-        },
-    ];
-
-    let create_info = vk::DescriptorPoolCreateInfo::default()
-        .pool_sizes(&pool_sizes)
-        .max_sets(descriptor_count);
-
-    unsafe { device.create_descriptor_pool(&create_info, None).unwrap() }
-}
-
-fn create_camera_ubos(context: &Arc<Context>, count: u32) -> Vec<Buffer> {
-    (0..count)
-        .map(|_| {
-            Buffer::create(
-                Arc::clone(context),
-                size_of::<CameraUBO>() as _,
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            )
-        })
-        .collect::<Vec<_>>()
-}
-
 fn create_descriptor_sets(
     context: &Arc<Context>,
-    pool: vk::DescriptorPool,
+    allocator: &mut DescriptorAllocator,
     layout: vk::DescriptorSetLayout,
-    buffers: &[Buffer],
+    camera_uniforms: &CameraUniforms,
+    lights: &LightManager,
     texture: &Texture,
 ) -> Vec<vk::DescriptorSet> {
-    let layouts = (0..buffers.len()).map(|_| layout).collect::<Vec<_>>();
-
-    let allocate_info = vk::DescriptorSetAllocateInfo::default()
-        .descriptor_pool(pool)
-        .set_layouts(&layouts);
-    let sets = unsafe {
-        context
-            .device()
-            .allocate_descriptor_sets(&allocate_info)
-            .unwrap()
-    };
+    let layouts = (0..camera_uniforms.count()).map(|_| layout).collect::<Vec<_>>();
+    let sets = allocator
+        .allocate_many(&layouts)
+        .expect("Failed to allocate descriptor sets");
 
-    sets.iter().zip(buffers.iter()).for_each(|(set, buffer)| {
-        let buffer_info = [vk::DescriptorBufferInfo::default()
-            .buffer(buffer.buffer)
-            .offset(0)
-            .range(vk::WHOLE_SIZE)];
+    sets.iter().enumerate().for_each(|(index, set)| {
+        let buffer_info = [camera_uniforms.descriptor_info(index)];
 
         let cubemap_info = [vk::DescriptorImageInfo::default()
             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
             .image_view(texture.view)
             .sampler(texture.sampler.unwrap())];
 
+        // The lights buffers are per frame in flight too.
+        let lights_info = [lights.descriptor_info(index)];
+
         let descriptor_writes = [
             vk::WriteDescriptorSet::default()
                 .dst_set(*set)
@@ -376,6 +371,11 @@ fn create_descriptor_sets(
                 .dst_binding(1)
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                 .image_info(&cubemap_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&lights_info),
         ];
 
         unsafe {
@@ -396,14 +396,38 @@ impl TextureApp {
 
         let (width, height, image_data) = load_image("assets/android.png");
 
-        let texture = Texture::from_rgba(&context, width, height, &image_data, true);
+        let texture = Texture::from_rgba(&context, width, height, &image_data, true)
+            .expect("Failed to create texture");
+        texture.set_name("android");
         let desc_layout = create_descriptor_set_layout(context.device());
-        let (pipeline, pipeline_layout) = prepare_pipeline(context, &[desc_layout]);
-        let camera_ubos = create_camera_ubos(&context, base.swapchain.image_count() as u32);
-        let pool = create_descriptor_pool(context.device(), camera_ubos.len() as u32);
-
-        let desc_sets = create_descriptor_sets(context, pool, desc_layout, &camera_ubos, &texture);
-        let descriptors = Descriptors::new(context.clone(), desc_layout, pool, desc_sets);
+        let color_format = context
+            .color_policy()
+            .attachment_format(base.swapchain.properties().format);
+        let (pipeline, pipeline_layout) = prepare_pipeline(context, &[desc_layout], color_format);
+        context.set_object_name(pipeline, "quad pipeline");
+        let camera_uniforms = CameraUniforms::new(context, MAX_FRAMES_IN_FLIGHT as _);
+        let mut descriptor_allocator =
+            DescriptorAllocator::new(context, &DEFAULT_POOL_SIZE_RATIOS);
+        let mut lights = LightManager::new(context, MAX_FRAMES_IN_FLIGHT as _);
+        // Toward +z to light the quad, which faces -z.
+        lights.add(Light::directional(
+            Vector3::new(-1.0, -1.0, 1.0),
+            [1.0; 3],
+            1.0,
+        ));
+
+        let desc_sets = create_descriptor_sets(
+            context,
+            &mut descriptor_allocator,
+            desc_layout,
+            &camera_uniforms,
+            &lights,
+            &texture,
+        );
+        let descriptors = Descriptors::from_allocator(context.clone(), desc_layout, desc_sets);
+        let gui_srgb_framebuffer = context
+            .color_policy()
+            .gui_srgb_framebuffer(base.swapchain.properties().format);
         let gui_renderer = Renderer::with_default_allocator(
             base.context.instance(),
             base.context.physical_device(),
@@ -414,26 +438,57 @@ impl TextureApp {
             },
             Options {
                 in_flight_frames: MAX_FRAMES_IN_FLIGHT as _,
-                srgb_framebuffer: true,
+                srgb_framebuffer: gui_srgb_framebuffer,
                 ..Default::default()
             },
         )
         .unwrap();
 
-        let gui_context = Gui::new(window, None);
+        let mut gui_context = Gui::new(window, None);
+        gui_context.set_gpu_info(Some(context));
+        let test_pattern_pass = TestPatternPass::new(context, color_format, None);
+        let gpu_profiler = GpuProfiler::new(
+            context,
+            MAX_FRAMES_IN_FLIGHT as _,
+            DEFAULT_GPU_PROFILER_CAPACITY,
+        );
         Self {
             model,
             camera: Camera::default(),
+            input_state: InputState::default(),
             time: Instant::now(),
             dirty_swapchain: false,
             pipeline_layout,
             pipeline,
+            color_format,
             base,
+            descriptor_allocator,
             descriptors,
+            camera_uniforms,
+            lights,
+            telemetry: FrameTelemetry::default(),
+            gpu_profiler,
+            present_pacer: PresentPacer::default(),
             texture,
             gui_renderer,
+            gui_srgb_framebuffer,
             gui_context,
+            test_pattern_pass,
+        }
+    }
+}
+
+impl TextureApp {
+    /// Move the camera with the input received since the last sample.
+    fn sample_input(&mut self) {
+        let new_time = Instant::now();
+        let delta_s = (new_time - self.time).as_secs_f32();
+        self.time = new_time;
+
+        if !self.gui_context.wants_pointer_input() {
+            self.camera.update(&self.input_state, delta_s);
         }
+        self.telemetry.mark(FrameStage::InputSampled);
     }
 }
 
@@ -441,9 +496,14 @@ impl TextureApp {
 
 
 impl WindowApp for TextureApp {
-    fn new_frame(&mut self) {}
+    fn new_frame(&mut self) {
+        self.input_state = self.input_state.reset();
+    }
+
+    fn handle_window_event(&mut self, window: &Window, event: &WindowEvent) {
+        self.gui_context.handle_event(window, event);
+        self.input_state = self.input_state.handle_window_event(event);
 
-    fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent) {
         match event {
             // Resizing
             WindowEvent::Resized(PhysicalSize { width, height }) => {
@@ -470,66 +530,43 @@ impl WindowApp for TextureApp {
     }
 
     fn  handle_device_event(&mut self, event: &DeviceEvent) {
-        // self.input_state = self.input_state.handle_device_event(event);
-    }
-
-    fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool) {
-        tracing::debug!("Recreating swapchain.");
-
-        self.base.context.graphics_queue_wait_idle();
-
-        unsafe {
-            self.base.context.device().free_command_buffers(
-                self.base.context.general_command_pool(),
-                &self.base.command_buffers,
-            )
-        };
-
-        let swapchain_support_details = SwapchainSupportDetails::new(
-            self.base.context.physical_device(),
-            self.base.context.surface(),
-            self.base.context.surface_khr(),
-        );
-
-        self.base.swapchain = Swapchain::create(
-            Arc::clone(&self.base.context),
-            swapchain_support_details,
-            dimensions,
-            hdr.then_some(HDR_SURFACE_FORMAT),
-            vsync,
-        );
-
-        self.base.on_new_swapchain();
-        self.base.command_buffers =
-            allocate_command_buffers(&self.base.context, self.base.swapchain.image_count());
+        self.input_state = self.input_state.handle_device_event(event);
     }
 
     fn end_frame(&mut self, window: &Window) {
-        let new_time = Instant::now();
-        let delta_s = (new_time - self.time).as_secs_f32();
-        self.time = new_time;
-
         // If swapchain must be recreated wait for windows to not be minimized anymore
         if self.dirty_swapchain {
             let PhysicalSize { width, height } = window.inner_size();
             if width > 0 && height > 0 {
                 self.base
                     .recreate_swapchain(window.inner_size().into(), false, false);
+                let color_policy = self.base.context.color_policy();
+                color_policy.audit_attachment(
+                    "texture",
+                    self.color_format,
+                    self.base.swapchain.properties().format.format,
+                );
+                color_policy.audit_gui(
+                    self.gui_srgb_framebuffer,
+                    self.base.swapchain.properties().format,
+                );
             } else {
                 return;
             }
         }
-        self.dirty_swapchain = matches!(
-            self.render(window, self.camera),
-            Err(RenderError::DirtySwapchain)
-        );
+        self.dirty_swapchain = match self.render(window, self.camera) {
+            Ok(()) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(RenderError::Timeout) => false,
+            Err(RenderError::Vks(error)) => panic!("Failed to render frame: {}", error),
+        };
     }
 
     fn on_exit(&mut self) {
         self.base.wait_idle_gpu();
     }
 
-    fn render(&mut self, window: &Window, camera: Camera) -> Result<(), RenderError> {
+    fn render(&mut self, window: &Window, _camera: Camera) -> Result<(), RenderError> {
         tracing::trace!("Drawing frame.");
         let sync_objects = self.base.in_flight_frames.next().unwrap();
         let image_available_semaphore = sync_objects.image_available_semaphore;
@@ -537,25 +574,41 @@ impl WindowApp for TextureApp {
         let in_flight_fence = sync_objects.fence;
         let wait_fences = [in_flight_fence];
 
+        self.present_pacer.set_mode(self.gui_context.latency_mode());
+        self.present_pacer
+            .wait(&self.base.swapchain, &mut self.telemetry);
+        self.telemetry.begin_frame();
         unsafe {
             self.base
                 .context
                 .device()
                 .wait_for_fences(&wait_fences, true, u64::MAX)
-                .unwrap()
-        };
-
-        let result =
-            self.base
-                .swapchain
-                .acquire_next_image(None, Some(image_available_semaphore), None);
-        let image_index = match result {
-            Ok((image_index, _)) => image_index,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return Err(RenderError::DirtySwapchain);
-            }
-            Err(error) => panic!("Error while acquiring next image. Cause: {}", error),
+                .unwrap_or_else(|error| {
+                    self.base
+                        .context
+                        .handle_device_error(error, "Failed to wait for frame fence")
+                })
         };
+        self.base.in_flight_frames.collect_deletions();
+        self.telemetry.fence_signaled(in_flight_fence);
+
+        // Per frame data is only written once the fence of the frame was waited for.
+        let in_flight_index = self.base.in_flight_frames.current_frame_index();
+        self.gpu_profiler.resolve(in_flight_index);
+        let properties = self.base.swapchain.properties();
+        let aspect = properties.aspect();
+        self.lights.update(in_flight_index);
+
+        let image_index = self.base.acquire_next_image(image_available_semaphore)?;
+        self.telemetry.mark(FrameStage::Acquired);
+
+        // The input is sampled as late as possible, once the image is acquired
+        // and right before the camera uniforms are written.
+        self.sample_input();
+        self.camera_uniforms
+            .set_pre_rotation(properties.pre_rotation());
+        self.camera_uniforms
+            .update(in_flight_index, &self.camera, aspect);
 
         unsafe {
             self.base
@@ -565,6 +618,7 @@ impl WindowApp for TextureApp {
                 .unwrap()
         };
 
+
         // // record_command_buffer
         // {
         //     let command_buffer = self.base.command_buffers[image_index as usize];
@@ -609,7 +663,19 @@ impl WindowApp for TextureApp {
                 .unwrap();
         }
         let ui_render_data = {
+            self.gui_context.set_lights(Some(self.lights.lights()));
+            self.gui_context.set_frame_telemetry(Some(&self.telemetry));
+            self.gui_context.set_gpu_profiler(Some(&self.gpu_profiler));
             let render_data = self.gui_context.render(window);
+            if let Some(lights) = self.gui_context.get_new_lights() {
+                self.lights.set_lights(lights.to_vec());
+            }
+            if self.gui_context.should_export_telemetry() {
+                match self.telemetry.export_json(TELEMETRY_EXPORT_PATH) {
+                    Ok(()) => info!("Frame timings exported to {}", TELEMETRY_EXPORT_PATH),
+                    Err(error) => tracing::error!("Failed to export frame timings: {}", error),
+                }
+            }
 
             self.base.in_flight_frames.gui_textures_to_free.clear();
             self.base
@@ -617,6 +683,7 @@ impl WindowApp for TextureApp {
                 .gui_textures_to_free
                 .extend_from_slice(&render_data.textures_delta.free);
 
+            let _queue_guard = self.base.context.lock_queue();
             self.gui_renderer
                 .set_textures(
                     self.base.context.graphics_compute_queue(),
@@ -664,6 +731,7 @@ impl WindowApp for TextureApp {
                     .end_command_buffer(command_buffer)
                     .unwrap()
             };
+            self.telemetry.mark(FrameStage::Recorded);
 
             // Submit command buffer
             {
@@ -683,6 +751,7 @@ impl WindowApp for TextureApp {
                     .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
                     .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
 
+                let _queue_guard = self.base.context.lock_queue();
                 unsafe {
                     self.base
                         .context
@@ -692,9 +761,16 @@ impl WindowApp for TextureApp {
                             std::slice::from_ref(&submit_info),
                             in_flight_fence,
                         )
-                        .unwrap()
+                        .unwrap_or_else(|error| {
+                            self.base
+                                .context
+                                .handle_device_error(error, "Failed to submit frame")
+                        })
                 };
+                self.camera_uniforms
+                    .submitted(in_flight_index, in_flight_fence);
             }
+            self.telemetry.mark(FrameStage::Submitted);
         }
 
         let swapchains = [self.base.swapchain.swapchain_khr()];
@@ -708,11 +784,25 @@ impl WindowApp for TextureApp {
                 .swapchains(&swapchains)
                 .image_indices(&images_indices);
 
-            match self.base.swapchain.present(&present_info) {
+            let frame = self.telemetry.current_frame().unwrap();
+            let present_id = self
+                .present_pacer
+                .next_present_id(&self.base.swapchain, frame);
+            let result = self
+                .base
+                .swapchain
+                .present_with_id(present_info, present_id);
+            self.telemetry.mark(FrameStage::Presented);
+            self.telemetry.end_frame(in_flight_fence);
+
+            match result {
                 Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                     return Err(RenderError::DirtySwapchain)
                 }
-                Err(error) => panic!("Failed to present queue. Cause: {}", error),
+                Err(error) => self
+                    .base
+                    .context
+                    .handle_device_error(error, "Failed to present queue"),
                 _ => {}
             }
         }
@@ -726,6 +816,10 @@ impl WindowApp for TextureApp {
         frame_index: usize,
         ui_render_data: Option<&RenderData>,
     ) {
+        let in_flight_index = self.base.in_flight_frames.current_frame_index();
+        self.gpu_profiler
+            .cmd_begin_frame(command_buffer, in_flight_index);
+
         // Prepare attachments and inputs for lighting pass
         let transitions = vec![
             LayoutTransition {
@@ -817,6 +911,8 @@ impl WindowApp for TextureApp {
                         .cmd_begin_rendering(command_buffer, &rendering_info)
                 };
             }
+            self.base.context.cmd_begin_pass(command_buffer, "scene");
+            self.gpu_profiler.begin_scope(command_buffer, "scene");
             let device = self.base.context.device();
 
             // Bind skybox pipeline
@@ -845,13 +941,14 @@ impl WindowApp for TextureApp {
                     vk::IndexType::UINT32,
                 );
             }
+            // Uniforms are per frame in flight, not per swapchain image.
             unsafe {
                 device.cmd_bind_descriptor_sets(
                     command_buffer,
                     vk::PipelineBindPoint::GRAPHICS,
                     self.pipeline_layout,
                     0,
-                    &self.descriptors.sets()[frame_index..=frame_index],
+                    &self.descriptors.sets()[in_flight_index..=in_flight_index],
                     &[],
                 )
             };
@@ -859,6 +956,21 @@ impl WindowApp for TextureApp {
             // Draw skybox
             unsafe { device.cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0) };
 
+            if let Some(pattern) = self.gui_context.test_pattern() {
+                self.base
+                    .context
+                    .cmd_begin_debug_label(command_buffer, "test pattern");
+                self.test_pattern_pass.cmd_draw(
+                    command_buffer,
+                    extent,
+                    pattern,
+                    self.gui_context.should_encode_test_pattern(),
+                    1.0,
+                );
+                self.base.context.cmd_end_debug_label(command_buffer);
+            }
+            self.gpu_profiler.end_scope(command_buffer);
+            self.base.context.cmd_end_pass(command_buffer);
         }
         if let Some(RenderData {
             pixels_per_point,
@@ -868,6 +980,8 @@ impl WindowApp for TextureApp {
         {
             let extent: Extent2D = self.base.swapchain.properties().extent;
 
+            self.base.context.cmd_begin_pass(command_buffer, "gui");
+            self.gpu_profiler.begin_scope(command_buffer, "gui");
             self.gui_renderer
                 .cmd_draw(
                     command_buffer,
@@ -876,6 +990,8 @@ impl WindowApp for TextureApp {
                     clipped_primitives,
                 )
                 .unwrap();
+            self.gpu_profiler.end_scope(command_buffer);
+            self.base.context.cmd_end_pass(command_buffer);
             unsafe {
                 self.base
                     .context
@@ -895,6 +1011,20 @@ impl WindowApp for TextureApp {
     }
 }
 
+impl Drop for TextureApp {
+    fn drop(&mut self) {
+        // Destroyed with the resources of the last frames when the base is dropped.
+        let (pipeline, pipeline_layout) = (self.pipeline, self.pipeline_layout);
+        self.base
+            .context
+            .deletion_queue()
+            .enqueue_destroy(move |device| unsafe {
+                device.destroy_pipeline(pipeline, None);
+                device.destroy_pipeline_layout(pipeline_layout, None);
+            });
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let subscriber = tracing_subscriber::FmtSubscriber::builder()
         // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
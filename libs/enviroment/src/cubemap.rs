@@ -27,7 +27,6 @@ pub(crate) fn create_skybox_cubemap<P: AsRef<Path>>(
 
     let sampler_parameters = SamplerParameters {
         anisotropy_enabled: true,
-        max_anisotropy: 16.0,
         ..Default::default()
     };
     let texture = Texture::from_rgba_32(context, w, h, true, &data, Some(sampler_parameters));
@@ -124,7 +123,7 @@ pub(crate) fn create_skybox_cubemap<P: AsRef<Path>>(
 
     let view_matrices = get_view_matrices();
 
-    let proj = perspective(Deg(90.0), 1.0, 0.1, 10.0);
+    let proj = perspective_with(Deg(90.0), 1.0, 0.1, Some(10.0), DepthMode::Standard);
 
     // Render
     context.execute_one_time_commands(|buffer| {
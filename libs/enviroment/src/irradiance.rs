@@ -116,7 +116,7 @@ pub(crate) fn create_irradiance_map(
 
     let view_matrices = get_view_matrices();
 
-    let proj = perspective(Deg(90.0), 1.0, 0.1, 10.0);
+    let proj = perspective_with(Deg(90.0), 1.0, 0.1, Some(10.0), DepthMode::Standard);
 
     // Render
     context.execute_one_time_commands(|buffer| {
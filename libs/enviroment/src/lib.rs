@@ -1,8 +1,11 @@
 mod brdf;
 mod cubemap;
 mod irradiance;
+mod loader;
 mod pre_filtered;
 
+pub use loader::EnvironmentLoader;
+
 use brdf::create_brdf_lookup;
 use cgmath::{Matrix4, Point3, Vector3};
 use cubemap::create_skybox_cubemap;
@@ -19,6 +22,8 @@ use vks::{
 };
 
 pub const PRE_FILTERED_MAP_SIZE: u32 = 512;
+/// Number of bindings of the lighting textures, see [`Environment::layout_bindings`].
+pub const IBL_BINDING_COUNT: u32 = 3;
 
 pub struct Environment {
     skybox: Texture,
@@ -59,6 +64,60 @@ impl Environment {
     pub fn brdf_lookup(&self) -> &Texture {
         &self.brdf_lookup
     }
+
+    /// Number of mips of the pre-filtered map, the roughest one being the last.
+    pub fn pre_filtered_mip_levels(&self) -> u32 {
+        self.pre_filtered.image.mip_levels
+    }
+
+    /// Bindings of the irradiance map, the pre-filtered map and the brdf lookup,
+    /// from `first_binding`, for the set layouts of the materials lit by the environment.
+    pub fn layout_bindings(
+        first_binding: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> [vk::DescriptorSetLayoutBinding<'static>; IBL_BINDING_COUNT as usize] {
+        std::array::from_fn(|index| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(first_binding + index as u32)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(stage_flags)
+        })
+    }
+
+    /// Image infos of the textures, in the order of [`Environment::layout_bindings`].
+    pub fn image_infos(&self) -> [vk::DescriptorImageInfo; IBL_BINDING_COUNT as usize] {
+        [&self.irradiance, &self.pre_filtered, &self.brdf_lookup].map(|texture| {
+            vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(texture.view)
+                .sampler(texture.sampler.unwrap())
+        })
+    }
+
+    /// Write the textures in `set` at the bindings of [`Environment::layout_bindings`].
+    ///
+    /// Sets must be written again when the environment is swapped, see [`EnvironmentLoader::swap`].
+    pub fn write_descriptors(&self, context: &Context, set: vk::DescriptorSet, first_binding: u32) {
+        let image_infos = self.image_infos();
+        let descriptor_writes = image_infos
+            .iter()
+            .enumerate()
+            .map(|(index, image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(first_binding + index as u32)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(image_info))
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            context
+                .device()
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+    }
 }
 
 #[repr(C)]
@@ -275,9 +334,13 @@ fn create_env_pipeline<V: Vertex>(
             fragment_shader_params: ShaderParameters::new(params.fragment_shader_name),
             multisampling_info: &multisampling_info,
             viewport_info: params.viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             rasterizer_info: params.rasterizer_info,
             dynamic_state_info: params.dynamic_state_info,
             depth_stencil_info: None,
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
             color_blend_attachments: &color_blend_attachments,
             color_attachment_formats: &[params.format],
             depth_attachment_format: None,
@@ -286,4 +349,5 @@ fn create_env_pipeline<V: Vertex>(
             allow_derivatives: false,
         },
     )
+    .expect("Failed to create graphics pipeline")
 }
@@ -0,0 +1,111 @@
+use super::Environment;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use vks::{Context, MAX_FRAMES_IN_FLIGHT};
+
+struct PendingEnvironment {
+    path: PathBuf,
+    receiver: Receiver<Environment>,
+}
+
+struct RetiredEnvironment {
+    _environment: Environment,
+    frames_left: u32,
+}
+
+/// Load environments on a background thread and swap them at a frame boundary.
+///
+/// The cubemap and the IBL maps are generated with a dedicated [`Context`]
+/// so the render loop keeps running while they are built. The replaced
+/// environment is kept alive until the frames that may still sample it
+/// are done.
+pub struct EnvironmentLoader {
+    context: Arc<Context>,
+    resolution: u32,
+    pending: Option<PendingEnvironment>,
+    retired: Vec<RetiredEnvironment>,
+}
+
+impl EnvironmentLoader {
+    pub fn new(context: &Arc<Context>, resolution: u32) -> Self {
+        Self {
+            context: Arc::clone(context),
+            resolution,
+            pending: None,
+            retired: Vec::new(),
+        }
+    }
+
+    /// Start loading the environment at `path`.
+    ///
+    /// If another environment is still loading its result will be discarded.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref().to_path_buf();
+        tracing::info!("Loading environment {}", path.display());
+
+        let (sender, receiver) = mpsc::channel();
+        let context = Arc::new(self.context.new_thread());
+        let resolution = self.resolution;
+        let thread_path = path.clone();
+        thread::Builder::new()
+            .name("environment-loader".into())
+            .spawn(move || {
+                let environment = Environment::new(&context, thread_path, resolution);
+                // The receiver is gone if another load was requested meanwhile.
+                let _ = sender.send(environment);
+            })
+            .expect("Failed to spawn environment loader thread");
+
+        self.pending = Some(PendingEnvironment { path, receiver });
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn loading_path(&self) -> Option<&Path> {
+        self.pending.as_ref().map(|p| p.path.as_path())
+    }
+
+    /// Swap `current` with the loaded environment if it is ready.
+    ///
+    /// Must be called once per frame, after waiting for the in flight fence
+    /// and before recording the frame.
+    ///
+    /// # Returns
+    ///
+    /// True if `current` was replaced. Descriptors referencing the previous
+    /// environment must then be updated before recording.
+    pub fn swap(&mut self, current: &mut Option<Environment>) -> bool {
+        self.retired.retain_mut(|retired| {
+            retired.frames_left = retired.frames_left.saturating_sub(1);
+            retired.frames_left > 0
+        });
+
+        let Some(pending) = self.pending.as_ref() else {
+            return false;
+        };
+
+        match pending.receiver.try_recv() {
+            Ok(environment) => {
+                tracing::info!("Environment {} ready", pending.path.display());
+                self.pending = None;
+                if let Some(previous) = current.replace(environment) {
+                    self.retired.push(RetiredEnvironment {
+                        _environment: previous,
+                        frames_left: MAX_FRAMES_IN_FLIGHT,
+                    });
+                }
+                true
+            }
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                tracing::error!("Failed to load environment {}", pending.path.display());
+                self.pending = None;
+                false
+            }
+        }
+    }
+}
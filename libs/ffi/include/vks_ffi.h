@@ -0,0 +1,89 @@
+/* C declarations of the vks_ffi library, kept in sync with libs/ffi/src by hand. */
+#ifndef VKS_FFI_H
+#define VKS_FFI_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum VksStatus {
+    VKS_STATUS_SUCCESS = 0,
+    /* A pointer is null or a path is not valid UTF-8. */
+    VKS_STATUS_INVALID_ARGUMENT = 1,
+    /* The platform of the window is unknown or a handle it needs is null. */
+    VKS_STATUS_INVALID_WINDOW = 2,
+    /* The glTF file can't be read or is not valid. */
+    VKS_STATUS_MODEL_LOAD = 3,
+    /* The device was lost, the renderer must be destroyed. */
+    VKS_STATUS_DEVICE_LOST = 4,
+    /* The surface of the window was lost, the renderer must be destroyed. */
+    VKS_STATUS_SURFACE_LOST = 5,
+    VKS_STATUS_OUT_OF_MEMORY = 6,
+    /* The instance, the device or the surface lack a feature of the renderer. */
+    VKS_STATUS_UNSUPPORTED_FEATURE = 7,
+    /* Any other error returned by Vulkan. */
+    VKS_STATUS_VULKAN = 8,
+    /* The renderer panicked and is left in an unknown state, it must be destroyed. */
+    VKS_STATUS_PANIC = 9,
+} VksStatus;
+
+typedef enum VksPlatform {
+    VKS_PLATFORM_WIN32 = 1,
+    VKS_PLATFORM_XLIB = 2,
+    VKS_PLATFORM_XCB = 3,
+    VKS_PLATFORM_WAYLAND = 4,
+    VKS_PLATFORM_APPKIT = 5,
+} VksPlatform;
+
+/*
+ * Native window of the host application.
+ *
+ * - Win32: display is the HINSTANCE and window the HWND.
+ * - Xlib: display is the Display* and window_id the Window.
+ * - Xcb: display is the xcb_connection_t* and window_id the xcb_window_t.
+ * - Wayland: display is the wl_display* and window the wl_surface*.
+ * - AppKit: window is the NSView*, backed by a CAMetalLayer.
+ */
+typedef struct VksWindow {
+    /* One of VksPlatform. */
+    uint32_t platform;
+    void *display;
+    void *window;
+    uint64_t window_id;
+    int32_t screen;
+} VksWindow;
+
+/* Camera looking from position at target with the y axis up. */
+typedef struct VksCamera {
+    float position[3];
+    float target[3];
+    /* Vertical field of view in degrees. */
+    float fov_y;
+    float z_near;
+    float z_far;
+} VksCamera;
+
+typedef struct VksRenderer VksRenderer;
+
+/* The window must outlive the renderer, written to *renderer on success. */
+VksStatus vks_renderer_create(const VksWindow *window, uint32_t width, uint32_t height,
+                              VksRenderer **renderer);
+/* UTF-8 path of a .gltf or .glb file, replacing the current model. */
+VksStatus vks_renderer_load_gltf(VksRenderer *renderer, const char *path);
+VksStatus vks_renderer_set_camera(VksRenderer *renderer, const VksCamera *camera);
+/* Skipped while the window is minimized. */
+VksStatus vks_renderer_render_frame(VksRenderer *renderer);
+/* Zero while the window is minimized. */
+VksStatus vks_renderer_resize(VksRenderer *renderer, uint32_t width, uint32_t height);
+/* Null is ignored. */
+void vks_renderer_destroy(VksRenderer *renderer);
+/* Message of the last failure of the calling thread, null if none. */
+const char *vks_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* VKS_FFI_H */
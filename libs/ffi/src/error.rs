@@ -0,0 +1,128 @@
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::{c_char, CString},
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use vks::{RenderError, VksError};
+
+/// Result of the functions of the library, the message of the failures is
+/// returned by [`crate::vks_last_error_message`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VksStatus {
+    Success = 0,
+    /// A pointer is null or a path is not valid UTF-8.
+    InvalidArgument = 1,
+    /// The platform of the window is unknown or a handle it needs is null.
+    InvalidWindow = 2,
+    /// The glTF file can't be read or is not valid.
+    ModelLoad = 3,
+    /// The device was lost, the renderer must be destroyed.
+    DeviceLost = 4,
+    /// The surface of the window was lost, the renderer must be destroyed.
+    SurfaceLost = 5,
+    OutOfMemory = 6,
+    /// The instance, the device or the surface lack a feature of the renderer.
+    UnsupportedFeature = 7,
+    /// Any other error returned by Vulkan.
+    Vulkan = 8,
+    /// The renderer panicked and is left in an unknown state, it must be destroyed.
+    Panic = 9,
+}
+
+/// Failure of a function of the library, turned into a [`VksStatus`] at the boundary.
+#[derive(Debug)]
+pub struct FfiError {
+    status: VksStatus,
+    message: String,
+}
+
+impl FfiError {
+    pub fn new<S: Into<String>>(status: VksStatus, message: S) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn status(&self) -> VksStatus {
+        self.status
+    }
+}
+
+impl From<VksError> for FfiError {
+    fn from(error: VksError) -> Self {
+        let status = match error {
+            VksError::DeviceLost => VksStatus::DeviceLost,
+            VksError::SurfaceLost => VksStatus::SurfaceLost,
+            VksError::OutOfMemory => VksStatus::OutOfMemory,
+            VksError::UnsupportedFeature(_) => VksStatus::UnsupportedFeature,
+            VksError::ShaderLoad { .. } | VksError::Vulkan(_) => VksStatus::Vulkan,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl From<RenderError> for FfiError {
+    fn from(error: RenderError) -> Self {
+        match error {
+            RenderError::Vks(error) => error.into(),
+            // Handled by the renderer, only reached if the swapchain can't be recreated.
+            RenderError::DirtySwapchain => Self::new(VksStatus::Vulkan, "Swapchain out of date"),
+            RenderError::Timeout => Self::new(VksStatus::Vulkan, "Swapchain image timeout"),
+        }
+    }
+}
+
+impl fmt::Display for FfiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for FfiError {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run `f` and record its failure as the last error of the thread.
+///
+/// Panics must not unwind into the host, they are caught and reported as
+/// [`VksStatus::Panic`].
+pub fn run<F: FnOnce() -> Result<(), FfiError>>(f: F) -> VksStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_string());
+        Err(FfiError::new(VksStatus::Panic, message))
+    });
+
+    match result {
+        Ok(()) => VksStatus::Success,
+        Err(error) => {
+            tracing::error!("{}", error);
+            let message = CString::new(error.message.replace('\0', " "))
+                .expect("Failed to create error message");
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+            error.status
+        }
+    }
+}
+
+/// Message of the last failure of the thread, null if none. Valid until the
+/// next failure on the same thread.
+pub fn last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
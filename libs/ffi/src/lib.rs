@@ -0,0 +1,250 @@
+//! C bindings of a minimal renderer, for applications embedding it in a
+//! window they own. The declarations are in `include/vks_ffi.h`.
+//!
+//! The renderer is an opaque [`VksRenderer`] handle created by
+//! [`vks_renderer_create`] and released by [`vks_renderer_destroy`]. Each
+//! function returns a [`VksStatus`], the message of the failures is read with
+//! [`vks_last_error_message`]. A renderer must only be used by one thread at
+//! a time.
+mod error;
+mod renderer;
+mod window;
+
+pub use self::{error::*, renderer::*, window::*};
+
+use std::{
+    ffi::{c_char, CStr},
+    path::Path,
+    ptr,
+};
+
+/// Opaque handle of a renderer.
+pub struct VksRenderer(Renderer);
+
+/// Create a renderer presenting to `window`, whose drawable area is `width`
+/// by `height` pixels, and write its handle to `renderer`.
+///
+/// # Safety
+///
+/// `window` must point to valid handles of a window outliving the renderer
+/// and `renderer` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_create(
+    window: *const VksWindow,
+    width: u32,
+    height: u32,
+    renderer: *mut *mut VksRenderer,
+) -> VksStatus {
+    run(|| {
+        let window = window.as_ref().ok_or_else(null_argument)?;
+        let renderer = renderer.as_mut().ok_or_else(null_argument)?;
+        *renderer = ptr::null_mut();
+        let (display_handle, window_handle) = window
+            .raw_handles()
+            .ok_or_else(|| FfiError::new(VksStatus::InvalidWindow, "Invalid window handles"))?;
+
+        let created = Renderer::new(display_handle, window_handle, [width, height])?;
+        *renderer = Box::into_raw(Box::new(VksRenderer(created)));
+        Ok(())
+    })
+}
+
+/// Load the glTF file at the UTF-8 `path`, replacing the model of `renderer`.
+///
+/// # Safety
+///
+/// `renderer` must be a live handle and `path` a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_load_gltf(
+    renderer: *mut VksRenderer,
+    path: *const c_char,
+) -> VksStatus {
+    run(|| {
+        let renderer = renderer.as_mut().ok_or_else(null_argument)?;
+        if path.is_null() {
+            return Err(null_argument());
+        }
+        let path = CStr::from_ptr(path)
+            .to_str()
+            .map_err(|_| FfiError::new(VksStatus::InvalidArgument, "Path is not valid UTF-8"))?;
+        renderer.0.load_gltf(Path::new(path))
+    })
+}
+
+/// Set the camera of the next frames of `renderer`.
+///
+/// # Safety
+///
+/// `renderer` must be a live handle and `camera` must be valid for reads.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_set_camera(
+    renderer: *mut VksRenderer,
+    camera: *const VksCamera,
+) -> VksStatus {
+    run(|| {
+        let renderer = renderer.as_mut().ok_or_else(null_argument)?;
+        let camera = camera.as_ref().ok_or_else(null_argument)?;
+        renderer.0.set_camera(camera);
+        Ok(())
+    })
+}
+
+/// Render and present a frame, skipped while the window is minimized.
+///
+/// # Safety
+///
+/// `renderer` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_render_frame(renderer: *mut VksRenderer) -> VksStatus {
+    run(|| {
+        let renderer = renderer.as_mut().ok_or_else(null_argument)?;
+        renderer.0.render_frame()
+    })
+}
+
+/// Resize the swapchain of `renderer` to a drawable area of `width` by
+/// `height` pixels, zero while the window is minimized.
+///
+/// # Safety
+///
+/// `renderer` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_resize(
+    renderer: *mut VksRenderer,
+    width: u32,
+    height: u32,
+) -> VksStatus {
+    run(|| {
+        let renderer = renderer.as_mut().ok_or_else(null_argument)?;
+        renderer.0.resize([width, height]);
+        Ok(())
+    })
+}
+
+/// Wait for the frames of `renderer` and release it, null is ignored.
+///
+/// # Safety
+///
+/// `renderer` must be null or a live handle, which is no longer valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vks_renderer_destroy(renderer: *mut VksRenderer) {
+    if !renderer.is_null() {
+        run(|| {
+            drop(Box::from_raw(renderer));
+            Ok(())
+        });
+    }
+}
+
+/// Message of the last failure of the calling thread, null if none.
+///
+/// The string is owned by the library and valid until the next failure on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn vks_last_error_message() -> *const c_char {
+    last_error_message()
+}
+
+fn null_argument() -> FfiError {
+    FfiError::new(VksStatus::InvalidArgument, "Null pointer argument")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::c_void;
+
+    fn message() -> String {
+        let message = vks_last_error_message();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn create_rejects_null_arguments() {
+        let mut renderer = ptr::null_mut();
+        let status = unsafe { vks_renderer_create(ptr::null(), 800, 600, &mut renderer) };
+
+        assert_eq!(status, VksStatus::InvalidArgument);
+        assert!(renderer.is_null());
+        assert_eq!(message(), "Null pointer argument");
+    }
+
+    #[test]
+    fn create_rejects_invalid_window() {
+        let window = VksWindow {
+            platform: 42,
+            display: ptr::null_mut(),
+            window: ptr::null_mut(),
+            window_id: 0,
+            screen: 0,
+        };
+        let mut renderer = ptr::null_mut();
+        let status = unsafe { vks_renderer_create(&window, 800, 600, &mut renderer) };
+
+        assert_eq!(status, VksStatus::InvalidWindow);
+        assert!(renderer.is_null());
+    }
+
+    #[test]
+    fn functions_reject_null_renderer() {
+        let camera = VksCamera {
+            position: [0.0, 0.0, 5.0],
+            target: [0.0; 3],
+            fov_y: 45.0,
+            z_near: 0.1,
+            z_far: 100.0,
+        };
+        unsafe {
+            assert_eq!(
+                vks_renderer_set_camera(ptr::null_mut(), &camera),
+                VksStatus::InvalidArgument
+            );
+            assert_eq!(
+                vks_renderer_render_frame(ptr::null_mut()),
+                VksStatus::InvalidArgument
+            );
+            assert_eq!(
+                vks_renderer_resize(ptr::null_mut(), 800, 600),
+                VksStatus::InvalidArgument
+            );
+            assert_eq!(
+                vks_renderer_load_gltf(ptr::null_mut(), c"model.gltf".as_ptr()),
+                VksStatus::InvalidArgument
+            );
+            vks_renderer_destroy(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn raw_handles_need_the_handles_of_the_platform() {
+        let mut window = VksWindow {
+            platform: VksPlatform::Wayland as u32,
+            display: ptr::NonNull::<c_void>::dangling().as_ptr(),
+            window: ptr::null_mut(),
+            window_id: 0,
+            screen: 0,
+        };
+        assert!(window.raw_handles().is_none());
+
+        window.window = ptr::NonNull::<c_void>::dangling().as_ptr();
+        assert!(window.raw_handles().is_some());
+
+        window.platform = VksPlatform::Xlib as u32;
+        assert!(window.raw_handles().is_none());
+
+        window.window_id = 0x2a;
+        assert!(window.raw_handles().is_some());
+    }
+
+    #[test]
+    fn run_catches_panics() {
+        let status = run(|| panic!("Failed to render"));
+
+        assert_eq!(status, VksStatus::Panic);
+        assert_eq!(message(), "Failed to render");
+    }
+}
@@ -0,0 +1,553 @@
+use std::{mem::size_of, path::Path, sync::Arc};
+
+use ash::vk;
+use gltf_model::{IndexBuffer, Model, ModelOptions, ModelVertex, VertexBuffer};
+use math::cgmath::{Deg, Matrix4, Point3};
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+use vks::{
+    create_pipeline, create_scene_depth, Camera, Context, DepthFormatRequest, PipelineParameters,
+    PresentConfig, RenderError, ShaderParameters, Texture, VksError, VulkanExampleBase,
+};
+
+use crate::{FfiError, VksStatus};
+
+const CLEAR_COLOR: [f32; 4] = [0.18, 0.18, 0.2, 1.0];
+
+/// Camera of [`crate::vks_renderer_set_camera`], looking from `position` at `target`
+/// with the y axis up.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VksCamera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view in degrees.
+    pub fov_y: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+}
+
+/// Same layout as the push constants of the thumbnail shaders, whose lighting it reuses.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PushConstants {
+    mvp: [[f32; 4]; 4],
+    /// Columns of the rotation and scale of the model matrix.
+    normal_matrix: [[f32; 4]; 3],
+    color: [f32; 4],
+}
+
+/// Renderer presenting to a window of the host application.
+///
+/// Draws a glTF model in its bind pose with the base color of its materials
+/// under a fixed light, as the thumbnails of the scene example.
+pub struct Renderer {
+    model: Option<Model>,
+    /// Single sampled, unlike the depth of the base.
+    depth: Texture,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    camera: Camera,
+    /// Size of the window, the swapchain is recreated with it when out of date.
+    extent: [u32; 2],
+    dirty_swapchain: bool,
+    base: VulkanExampleBase,
+}
+
+impl Renderer {
+    /// Create a renderer presenting to the window of `display_handle` and
+    /// `window_handle`, whose drawable area is `extent` pixels.
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid and the window must outlive the renderer.
+    pub unsafe fn new(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        extent: [u32; 2],
+    ) -> Result<Self, FfiError> {
+        let base = VulkanExampleBase::from_raw_handles(
+            display_handle,
+            window_handle,
+            extent,
+            false,
+            DepthFormatRequest::default(),
+        )?;
+        let context = &base.context;
+        let depth = create_depth(&base);
+        let color_format = context
+            .color_policy()
+            .attachment_format(base.swapchain.properties().format);
+        let pipeline_layout = create_pipeline_layout(context);
+        let pipeline =
+            create_model_pipeline(context, pipeline_layout, color_format, base.depth_format)
+                .inspect_err(|_| unsafe {
+                    context
+                        .device()
+                        .destroy_pipeline_layout(pipeline_layout, None)
+                })?;
+
+        Ok(Self {
+            model: None,
+            depth,
+            pipeline_layout,
+            pipeline,
+            camera: Camera::default(),
+            extent,
+            dirty_swapchain: false,
+            base,
+        })
+    }
+
+    /// Load the glTF model at `path`, replacing the current one.
+    pub fn load_gltf(&mut self, path: &Path) -> Result<(), FfiError> {
+        let model = load_model(&self.base.context, path)
+            .map_err(|error| FfiError::new(VksStatus::ModelLoad, error.to_string()))?;
+        // The previous model may be used by the frames in flight.
+        self.base.wait_idle_gpu();
+        self.model = Some(model);
+        Ok(())
+    }
+
+    pub fn set_camera(&mut self, camera: &VksCamera) {
+        self.camera.fov = Deg(camera.fov_y);
+        self.camera.z_near = camera.z_near;
+        self.camera.z_far = camera.z_far;
+        self.camera
+            .look_at(Point3::from(camera.position), Point3::from(camera.target));
+    }
+
+    /// Recreate the swapchain for a window of `extent` pixels.
+    ///
+    /// A minimized window has an empty extent, the frames are skipped until
+    /// it is resized again.
+    pub fn resize(&mut self, extent: [u32; 2]) {
+        self.extent = extent;
+        self.dirty_swapchain = true;
+        self.recreate_swapchain_if_dirty();
+    }
+
+    /// Render and present a frame.
+    ///
+    /// The frame is skipped when the window is minimized or no swapchain
+    /// image is available in time.
+    pub fn render_frame(&mut self) -> Result<(), FfiError> {
+        if !self.recreate_swapchain_if_dirty() {
+            return Ok(());
+        }
+        self.dirty_swapchain = match self.render() {
+            Ok(()) | Err(RenderError::Timeout) => false,
+            Err(RenderError::DirtySwapchain) => true,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(())
+    }
+
+    /// Recreate the swapchain if it is out of date.
+    ///
+    /// # Returns
+    ///
+    /// False if the window is minimized and can't be rendered to.
+    fn recreate_swapchain_if_dirty(&mut self) -> bool {
+        if self.extent.contains(&0) {
+            return false;
+        }
+        if self.dirty_swapchain {
+            self.base
+                .recreate_swapchain_with_config(self.extent, &PresentConfig::default(), true);
+            let depth = create_depth(&self.base);
+            self.base
+                .context
+                .deletion_queue()
+                .enqueue(std::mem::replace(&mut self.depth, depth));
+            self.dirty_swapchain = false;
+        }
+        true
+    }
+
+    fn render(&mut self) -> Result<(), RenderError> {
+        let device = self.base.context.device();
+        let sync_objects = self.base.in_flight_frames.next().unwrap();
+        let wait_fences = [sync_objects.fence];
+
+        unsafe {
+            device
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .map_err(VksError::from)?
+        };
+        self.base.in_flight_frames.collect_deletions();
+
+        let image_index = self
+            .base
+            .acquire_next_image(sync_objects.image_available_semaphore)?;
+
+        unsafe { device.reset_fences(&wait_fences).map_err(VksError::from)? };
+
+        let command_buffer = self.base.command_buffers[image_index as usize];
+        unsafe {
+            device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(VksError::from)?;
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .map_err(VksError::from)?;
+        }
+        self.cmd_draw(command_buffer, image_index as usize);
+        unsafe {
+            device
+                .end_command_buffer(command_buffer)
+                .map_err(VksError::from)?
+        };
+
+        let wait_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+            .semaphore(sync_objects.image_available_semaphore)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo::default()
+            .semaphore(sync_objects.render_finished_semaphore)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS);
+        let cmd_buffer_submit_info =
+            vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer);
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info))
+            .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_submit_info))
+            .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_submit_info));
+        {
+            let _queue_guard = self.base.context.lock_queue();
+            unsafe {
+                self.base
+                    .context
+                    .synchronization2()
+                    .queue_submit2(
+                        self.base.context.graphics_compute_queue(),
+                        std::slice::from_ref(&submit_info),
+                        sync_objects.fence,
+                    )
+                    .map_err(VksError::from)?
+            };
+        }
+
+        let wait_semaphores = [sync_objects.render_finished_semaphore];
+        let swapchains = [self.base.swapchain.swapchain_khr()];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+        match self.base.swapchain.present(&present_info) {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RenderError::DirtySwapchain),
+            Ok(false) => Ok(()),
+            Err(error) => Err(RenderError::Vks(error.into())),
+        }
+    }
+
+    fn cmd_draw(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        let device = self.base.context.device();
+        let image = &self.base.swapchain.images()[image_index];
+        let image_view = self.base.swapchain.image_views()[image_index];
+        let extent = vk::Extent2D {
+            width: image.extent.width,
+            height: image.extent.height,
+        };
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: CLEAR_COLOR,
+                },
+            })
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .image_view(image_view)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let depth_attachment_info = vk::RenderingAttachmentInfo::default()
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            })
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .image_view(self.depth.view)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .depth_attachment(&depth_attachment_info)
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        unsafe {
+            self.base
+                .context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+        }
+
+        if let Some(model) = self.model.as_ref() {
+            let aspect = extent.width as f32 / extent.height as f32;
+            let view_proj = self.camera.projection_matrix(aspect) * self.camera.view_matrix();
+            self.cmd_draw_model(command_buffer, model, view_proj);
+        }
+
+        unsafe {
+            self.base
+                .context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer)
+        };
+
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        );
+    }
+
+    fn cmd_draw_model(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        model: &Model,
+        view_proj: Matrix4<f32>,
+    ) {
+        let device = self.base.context.device();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            )
+        };
+
+        let cmd_draw = |transform: Matrix4<f32>,
+                        color: [f32; 4],
+                        vertices: &VertexBuffer,
+                        indices: Option<&IndexBuffer>| {
+            let constants = PushConstants {
+                mvp: (view_proj * transform).into(),
+                normal_matrix: [transform.x.into(), transform.y.into(), transform.z.into()],
+                color,
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    util::any_as_u8_slice(&constants),
+                );
+                device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[vertices.buffer().buffer],
+                    &[vertices.offset()],
+                );
+                match indices {
+                    Some(indices) => {
+                        device.cmd_bind_index_buffer(
+                            command_buffer,
+                            indices.buffer().buffer,
+                            indices.offset(),
+                            indices.index_type(),
+                        );
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            indices.element_count(),
+                            1,
+                            0,
+                            0,
+                            0,
+                        );
+                    }
+                    None => device.cmd_draw(command_buffer, vertices.element_count(), 1, 0, 0),
+                }
+            }
+        };
+
+        for node in model.nodes().nodes() {
+            let Some(mesh_index) = node.mesh_index() else {
+                continue;
+            };
+            for primitive in model.mesh(mesh_index).primitives() {
+                cmd_draw(
+                    node.transform(),
+                    primitive.material().get_color(),
+                    primitive.vertices(),
+                    primitive.indices().as_ref(),
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.base.wait_idle_gpu();
+        let device = self.base.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn create_depth(base: &VulkanExampleBase) -> Texture {
+    create_scene_depth(
+        &base.context,
+        base.depth_format,
+        base.swapchain.properties().extent,
+        vk::SampleCountFlags::TYPE_1,
+    )
+}
+
+/// Load the model at `path` and wait for its upload.
+fn load_model(context: &Arc<Context>, path: &Path) -> Result<Model, Box<dyn std::error::Error>> {
+    let device = context.device();
+    let command_buffer = {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(context.general_command_pool())
+            .level(vk::CommandBufferLevel::SECONDARY)
+            .command_buffer_count(1);
+        unsafe {
+            device
+                .allocate_command_buffers(&allocate_info)
+                .expect("Failed to allocate command buffer")[0]
+        }
+    };
+
+    let inheritance_info = vk::CommandBufferInheritanceInfo::default();
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .inheritance_info(&inheritance_info)
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .expect("Failed to begin command buffer")
+    };
+
+    let result = Model::create_from_file_with_options(
+        Arc::clone(context),
+        command_buffer,
+        path,
+        &ModelOptions::default(),
+    );
+    unsafe {
+        device
+            .end_command_buffer(command_buffer)
+            .expect("Failed to end command buffer")
+    };
+    match result {
+        // Submits the upload, waits for it and frees the command buffer.
+        Ok(mut model) => Ok(model.finish()),
+        Err(error) => {
+            unsafe {
+                device.free_command_buffers(context.general_command_pool(), &[command_buffer])
+            };
+            Err(error)
+        }
+    }
+}
+
+fn create_pipeline_layout(context: &Arc<Context>) -> vk::PipelineLayout {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: size_of::<PushConstants>() as _,
+    }];
+    let layout_info =
+        vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+fn create_model_pipeline(
+    context: &Arc<Context>,
+    layout: vk::PipelineLayout,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+) -> Result<vk::Pipeline, VksError> {
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    // The winding of the models is not trusted, both sides are drawn.
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .min_sample_shading(1.0);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0);
+
+    create_pipeline::<ModelVertex>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new("thumbnail"),
+            fragment_shader_params: ShaderParameters::new("thumbnail"),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment: false,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[color_format],
+            depth_attachment_format: Some(depth_format),
+            layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+}
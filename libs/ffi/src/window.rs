@@ -0,0 +1,90 @@
+use std::{
+    ffi::c_void,
+    num::{NonZeroIsize, NonZeroU32, NonZeroU64},
+    ptr::NonNull,
+};
+
+use raw_window_handle::{
+    AppKitDisplayHandle, AppKitWindowHandle, RawDisplayHandle, RawWindowHandle,
+    WaylandDisplayHandle, WaylandWindowHandle, Win32WindowHandle, WindowsDisplayHandle,
+    XcbDisplayHandle, XcbWindowHandle, XlibDisplayHandle, XlibWindowHandle,
+};
+
+/// Windowing systems of [`VksWindow::platform`].
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VksPlatform {
+    Win32 = 1,
+    Xlib = 2,
+    Xcb = 3,
+    Wayland = 4,
+    AppKit = 5,
+}
+
+impl VksPlatform {
+    fn from_raw(platform: u32) -> Option<Self> {
+        [
+            Self::Win32,
+            Self::Xlib,
+            Self::Xcb,
+            Self::Wayland,
+            Self::AppKit,
+        ]
+        .into_iter()
+        .find(|candidate| *candidate as u32 == platform)
+    }
+}
+
+/// Native window of the host application, the renderer presents to it.
+///
+/// - Win32: `display` is the `HINSTANCE` and `window` the `HWND`.
+/// - Xlib: `display` is the `Display*` and `window_id` the `Window`.
+/// - Xcb: `display` is the `xcb_connection_t*` and `window_id` the `xcb_window_t`.
+/// - Wayland: `display` is the `wl_display*` and `window` the `wl_surface*`.
+/// - AppKit: `window` is the `NSView*`, backed by a `CAMetalLayer`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VksWindow {
+    /// One of [`VksPlatform`].
+    pub platform: u32,
+    pub display: *mut c_void,
+    pub window: *mut c_void,
+    /// Id of the X11 windows, which are not pointers.
+    pub window_id: u64,
+    /// X11 screen of the window.
+    pub screen: i32,
+}
+
+impl VksWindow {
+    /// Raw handles of the window, `None` if the platform is unknown or a
+    /// handle it needs is null.
+    pub fn raw_handles(&self) -> Option<(RawDisplayHandle, RawWindowHandle)> {
+        let handles = match VksPlatform::from_raw(self.platform)? {
+            VksPlatform::Win32 => {
+                let mut window = Win32WindowHandle::new(NonZeroIsize::new(self.window as isize)?);
+                window.hinstance = NonZeroIsize::new(self.display as isize);
+                (
+                    WindowsDisplayHandle::new().into(),
+                    RawWindowHandle::Win32(window),
+                )
+            }
+            VksPlatform::Xlib => (
+                XlibDisplayHandle::new(Some(NonNull::new(self.display)?), self.screen).into(),
+                XlibWindowHandle::new(NonZeroU64::new(self.window_id)?.get() as _).into(),
+            ),
+            VksPlatform::Xcb => (
+                XcbDisplayHandle::new(Some(NonNull::new(self.display)?), self.screen).into(),
+                XcbWindowHandle::new(NonZeroU32::new(u32::try_from(self.window_id).ok()?)?).into(),
+            ),
+            VksPlatform::Wayland => (
+                WaylandDisplayHandle::new(NonNull::new(self.display)?).into(),
+                WaylandWindowHandle::new(NonNull::new(self.window)?).into(),
+            ),
+            VksPlatform::AppKit => (
+                AppKitDisplayHandle::new().into(),
+                AppKitWindowHandle::new(NonNull::new(self.window)?).into(),
+            ),
+        };
+        Some(handles)
+    }
+}
@@ -11,7 +11,44 @@ use gltf::{
 };
 use math::cgmath::{InnerSpace, Quaternion, Vector3, VectorSpace};
 use math::slerp;
-use std::cmp::Ordering;
+use std::{cmp::Ordering, iter, mem::size_of};
+
+/// Default largest distance between the reduced and original translations and scales.
+pub const DEFAULT_ANIMATION_TOLERANCE: f32 = 1e-4;
+/// Default largest angle in radians between the reduced and original rotations.
+pub const DEFAULT_ANIMATION_ROTATION_TOLERANCE: f32 = 1e-4;
+
+/// Options of the compression of the animations done when loading a model.
+///
+/// Long clips are often exported with a keyframe per frame for each channel,
+/// most of which linear interpolation gives back. Those are removed, and the
+/// rotations can be stored on 16 bits per component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationCompression {
+    /// Resample the linear and cubic spline channels at this many keyframes
+    /// per second before the reduction. Cubic splines are only reduced once
+    /// resampled, and the keyframes of a resampled channel are found without
+    /// searching if none is removed.
+    pub resample_rate: Option<f32>,
+    /// Largest distance between the reduced and original translations and scales.
+    pub tolerance: f32,
+    /// Largest angle in radians between the reduced and original rotations.
+    pub rotation_tolerance: f32,
+    /// Store the rotations of the linear and step channels as 16 bits
+    /// normalized integers.
+    pub quantize_rotations: bool,
+}
+
+impl Default for AnimationCompression {
+    fn default() -> Self {
+        Self {
+            resample_rate: None,
+            tolerance: DEFAULT_ANIMATION_TOLERANCE,
+            rotation_tolerance: DEFAULT_ANIMATION_ROTATION_TOLERANCE,
+            quantize_rotations: true,
+        }
+    }
+}
 
 trait Interpolate: Copy {
     fn linear(self, other: Self, amount: f32) -> Self;
@@ -77,7 +114,57 @@ impl Interpolate for Quaternion<f32> {
     }
 }
 
+/// Value stored in the keyframes of a sampler, decoded when sampled.
+trait Keyframe: Copy {
+    type Value: Interpolate;
+
+    fn decode(self) -> Self::Value;
+}
+
+impl Keyframe for Vector3<f32> {
+    type Value = Self;
+
+    fn decode(self) -> Self {
+        self
+    }
+}
+
+impl Keyframe for Quaternion<f32> {
+    type Value = Self;
+
+    fn decode(self) -> Self {
+        self
+    }
+}
+
+/// Unit quaternion stored as four 16 bits normalized integers, half the size
+/// of a [`Quaternion<f32>`] for an error below 0.01 degree.
 #[derive(Copy, Clone, Debug)]
+struct QuantizedRotation([i16; 4]);
+
+impl From<Quaternion<f32>> for QuantizedRotation {
+    fn from(rotation: Quaternion<f32>) -> Self {
+        let rotation = rotation.normalize();
+        let quantize = |value: f32| (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        Self([
+            quantize(rotation.v.x),
+            quantize(rotation.v.y),
+            quantize(rotation.v.z),
+            quantize(rotation.s),
+        ])
+    }
+}
+
+impl Keyframe for QuantizedRotation {
+    type Value = Quaternion<f32>;
+
+    fn decode(self) -> Quaternion<f32> {
+        let [x, y, z, w] = self.0.map(|value| value as f32 / i16::MAX as f32);
+        Quaternion::new(w, x, y, z).normalize()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum Interpolation {
     Linear,
     Step,
@@ -85,34 +172,57 @@ enum Interpolation {
 }
 
 #[derive(Debug)]
-struct Sampler<T> {
+struct Sampler<K> {
     interpolation: Interpolation,
     times: Vec<f32>,
-    values: Vec<T>,
+    values: Vec<K>,
+    /// Keyframes per second of evenly spaced keyframes, whose index is then
+    /// computed from the time instead of searched.
+    rate: Option<f32>,
 }
 
-impl<T> Sampler<T> {
+impl<K> Sampler<K> {
     fn get_max_time(&self) -> f32 {
         self.times.last().copied().unwrap_or(0.0)
     }
-}
 
-impl<T: Interpolate> Sampler<T> {
-    fn sample(&self, t: f32) -> Option<T> {
-        let index = {
-            let mut index = None;
-            for i in 0..(self.times.len() - 1) {
-                let previous = self.times[i];
-                let next = self.times[i + 1];
-                if t >= previous && t < next {
-                    index = Some(i);
-                    break;
+    fn keyframe_count(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Size in bytes of the times and values.
+    fn size(&self) -> usize {
+        self.times.len() * size_of::<f32>() + self.values.len() * size_of::<K>()
+    }
+
+    /// Index of the keyframe starting the interval containing `t`.
+    fn find_keyframe(&self, t: f32) -> Option<usize> {
+        if let (Some(rate), Some(start)) = (self.rate, self.times.first()) {
+            let index = ((t - start) * rate).floor();
+            if index >= 0.0 {
+                let index = index as usize;
+                // Rounding can put the time in a neighbouring interval.
+                if index + 1 < self.times.len()
+                    && t >= self.times[index]
+                    && t < self.times[index + 1]
+                {
+                    return Some(index);
                 }
             }
-            index
-        };
+        }
 
-        index.map(|i| {
+        let next = self.times.partition_point(|time| *time <= t);
+        if next > 0 && next < self.times.len() {
+            Some(next - 1)
+        } else {
+            None
+        }
+    }
+}
+
+impl<K: Keyframe> Sampler<K> {
+    fn sample(&self, t: f32) -> Option<K::Value> {
+        self.find_keyframe(t).map(|i| {
             let previous_time = self.times[i];
             let next_time = self.times[i + 1];
             let delta = next_time - previous_time;
@@ -120,24 +230,18 @@ impl<T: Interpolate> Sampler<T> {
             let factor = from_start / delta;
 
             match self.interpolation {
-                Interpolation::Step => self.values[i],
+                Interpolation::Step => self.values[i].decode(),
                 Interpolation::Linear => {
-                    let previous_value = self.values[i];
-                    let next_value = self.values[i + 1];
+                    let previous_value = self.values[i].decode();
+                    let next_value = self.values[i + 1].decode();
 
                     previous_value.linear(next_value, factor)
                 }
                 Interpolation::CubicSpline => {
-                    let previous_values = [
-                        self.values[i * 3],
-                        self.values[i * 3 + 1],
-                        self.values[i * 3 + 2],
-                    ];
-                    let next_values = [
-                        self.values[i * 3 + 3],
-                        self.values[i * 3 + 4],
-                        self.values[i * 3 + 5],
-                    ];
+                    let previous_values =
+                        [i * 3, i * 3 + 1, i * 3 + 2].map(|index| self.values[index].decode());
+                    let next_values =
+                        [i * 3 + 3, i * 3 + 4, i * 3 + 5].map(|index| self.values[index].decode());
                     Interpolate::cubic_spline(
                         previous_values,
                         previous_time,
@@ -151,24 +255,134 @@ impl<T: Interpolate> Sampler<T> {
     }
 }
 
+impl<T: Interpolate + Keyframe<Value = T>> Sampler<T> {
+    /// Value at `t`, the first or last keyframe outside of the sampler.
+    fn value_at(&self, t: f32) -> Option<T> {
+        self.sample(t).or_else(|| {
+            let (first, last) = match self.interpolation {
+                Interpolation::CubicSpline => (1, self.values.len().checked_sub(2)?),
+                _ => (0, self.values.len().checked_sub(1)?),
+            };
+            let index = if t < *self.times.first()? {
+                first
+            } else {
+                last
+            };
+            self.values.get(index).copied()
+        })
+    }
+
+    /// Resample linear and cubic spline keyframes at `rate` keyframes per
+    /// second, with linear interpolation. Step keyframes are left alone.
+    fn resample(&mut self, rate: f32) {
+        if self.interpolation == Interpolation::Step {
+            return;
+        }
+        let (Some(&start), Some(&end)) = (self.times.first(), self.times.last()) else {
+            return;
+        };
+
+        let count = ((end - start) * rate).ceil() as usize;
+        let times = (0..count)
+            .map(|index| start + index as f32 / rate)
+            .chain(iter::once(end))
+            .collect::<Vec<_>>();
+        let Some(values) = times
+            .iter()
+            .map(|time| self.value_at(*time))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+
+        self.interpolation = Interpolation::Linear;
+        self.times = times;
+        self.values = values;
+        self.rate = Some(rate);
+    }
+
+    /// Remove the keyframes reproduced within `tolerance` by the interpolation
+    /// of the remaining ones, `error` measuring the difference of two values.
+    ///
+    /// Linear keyframes are removed when interpolating their neighbours gives
+    /// back all the removed values, step keyframes when they repeat the previous
+    /// one. Cubic spline keyframes are kept.
+    fn reduce(&mut self, tolerance: f32, error: impl Fn(T, T) -> f32) {
+        let count = self.times.len();
+        if count < 3 || self.interpolation == Interpolation::CubicSpline {
+            return;
+        }
+
+        let mut kept = vec![0];
+        if self.interpolation == Interpolation::Step {
+            for index in 1..count - 1 {
+                if error(self.values[index], self.values[kept[kept.len() - 1]]) > tolerance {
+                    kept.push(index);
+                }
+            }
+        } else {
+            let mut start = 0;
+            for end in 2..count {
+                let duration = self.times[end] - self.times[start];
+                let fits = (start + 1..end).all(|index| {
+                    let factor = (self.times[index] - self.times[start]) / duration;
+                    let value = self.values[start].linear(self.values[end], factor);
+                    error(value, self.values[index]) <= tolerance
+                });
+                if !fits {
+                    start = end - 1;
+                    kept.push(start);
+                }
+            }
+        }
+        kept.push(count - 1);
+
+        if kept.len() < count {
+            self.times = kept.iter().map(|index| self.times[*index]).collect();
+            self.values = kept.iter().map(|index| self.values[*index]).collect();
+            self.rate = None;
+        }
+    }
+}
+
 #[derive(Debug)]
-struct Channel<T> {
-    sampler: Sampler<T>,
+struct Channel<K> {
+    sampler: Sampler<K>,
     node_index: usize,
 }
 
-impl<T> Channel<T> {
+impl<K> Channel<K> {
     fn get_max_time(&self) -> f32 {
         self.sampler.get_max_time()
     }
 }
 
-impl<T: Interpolate> Channel<T> {
-    fn sample(&self, t: f32) -> Option<(usize, T)> {
+impl<K: Keyframe> Channel<K> {
+    fn sample(&self, t: f32) -> Option<(usize, K::Value)> {
         self.sampler.sample(t).map(|s| (self.node_index, s))
     }
 }
 
+impl Channel<Quaternion<f32>> {
+    fn quantize(self) -> Channel<QuantizedRotation> {
+        let Sampler {
+            interpolation,
+            times,
+            values,
+            rate,
+        } = self.sampler;
+        Channel {
+            sampler: Sampler {
+                interpolation,
+                times,
+                values: values.into_iter().map(QuantizedRotation::from).collect(),
+                rate,
+            },
+            node_index: self.node_index,
+        }
+    }
+}
+
 struct NodesKeyFrame(
     Vec<(usize, Vector3<f32>)>,
     Vec<(usize, Quaternion<f32>)>,
@@ -267,6 +481,7 @@ pub struct Animation {
     total_time: f32,
     translation_channels: Vec<Channel<Vector3<f32>>>,
     rotation_channels: Vec<Channel<Quaternion<f32>>>,
+    quantized_rotation_channels: Vec<Channel<QuantizedRotation>>,
     scale_channels: Vec<Channel<Vector3<f32>>>,
 }
 
@@ -298,6 +513,11 @@ impl Animation {
             self.rotation_channels
                 .iter()
                 .filter_map(|tc| tc.sample(t))
+                .chain(
+                    self.quantized_rotation_channels
+                        .iter()
+                        .filter_map(|tc| tc.sample(t)),
+                )
                 .collect::<Vec<_>>(),
             self.scale_channels
                 .iter()
@@ -305,16 +525,108 @@ impl Animation {
                 .collect::<Vec<_>>(),
         )
     }
+
+    /// Keyframes of all the channels.
+    pub fn keyframe_count(&self) -> usize {
+        count_keyframes(&self.translation_channels)
+            + count_keyframes(&self.rotation_channels)
+            + count_keyframes(&self.quantized_rotation_channels)
+            + count_keyframes(&self.scale_channels)
+    }
+
+    /// Size in bytes of the keyframes of all the channels.
+    pub fn keyframes_size(&self) -> usize {
+        keyframes_size(&self.translation_channels)
+            + keyframes_size(&self.rotation_channels)
+            + keyframes_size(&self.quantized_rotation_channels)
+            + keyframes_size(&self.scale_channels)
+    }
+
+    fn compress(&mut self, compression: &AnimationCompression) {
+        let resample_rate = compression.resample_rate.filter(|rate| *rate > 0.0);
+        let vector_channels = self
+            .translation_channels
+            .iter_mut()
+            .chain(self.scale_channels.iter_mut());
+        for channel in vector_channels {
+            if let Some(rate) = resample_rate {
+                channel.sampler.resample(rate);
+            }
+            channel
+                .sampler
+                .reduce(compression.tolerance, |a, b| (a - b).magnitude());
+        }
+
+        for channel in self.rotation_channels.iter_mut() {
+            if let Some(rate) = resample_rate {
+                channel.sampler.resample(rate);
+            }
+            channel
+                .sampler
+                .reduce(compression.rotation_tolerance, rotation_angle);
+        }
+
+        // The tangents of cubic splines are not unit quaternions.
+        if compression.quantize_rotations {
+            let (cubic_channels, channels) = std::mem::take(&mut self.rotation_channels)
+                .into_iter()
+                .partition::<Vec<_>, _>(|channel| {
+                    channel.sampler.interpolation == Interpolation::CubicSpline
+                });
+            self.rotation_channels = cubic_channels;
+            self.quantized_rotation_channels
+                .extend(channels.into_iter().map(Channel::quantize));
+        }
+    }
+}
+
+fn count_keyframes<K>(channels: &[Channel<K>]) -> usize {
+    channels
+        .iter()
+        .map(|channel| channel.sampler.keyframe_count())
+        .sum()
 }
 
-pub fn load_animations(gltf_animations: GltfAnimations, data: &[Data]) -> Option<Animations> {
+fn keyframes_size<K>(channels: &[Channel<K>]) -> usize {
+    channels.iter().map(|channel| channel.sampler.size()).sum()
+}
+
+/// Angle in radians between the unit quaternions `a` and `b`.
+fn rotation_angle(a: Quaternion<f32>, b: Quaternion<f32>) -> f32 {
+    // The acos of their dot product can't tell apart angles below 1e-3 in
+    // single precision, the chord between them can.
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    4.0 * ((a - b).magnitude() * 0.5).min(1.0).asin()
+}
+
+/// Load the animations of a document, compressing them according to
+/// `compression` if any.
+pub fn load_animations(
+    gltf_animations: GltfAnimations,
+    data: &[Data],
+    compression: Option<&AnimationCompression>,
+) -> Option<Animations> {
     if gltf_animations.len() == 0 {
         return None;
     }
 
-    let animations = gltf_animations
+    let mut animations = gltf_animations
         .map(|a| map_animation(&a, data))
         .collect::<Vec<_>>();
+    if let Some(compression) = compression {
+        let size = animations
+            .iter()
+            .map(Animation::keyframes_size)
+            .sum::<usize>();
+        animations
+            .iter_mut()
+            .for_each(|animation| animation.compress(compression));
+        let compressed_size = animations
+            .iter()
+            .map(Animation::keyframes_size)
+            .sum::<usize>();
+        tracing::debug!("Compressed animations from {size} to {compressed_size} bytes");
+    }
     let total_time = animations.first().map_or(0.0, |a| a.total_time);
 
     Some(Animations {
@@ -359,6 +671,7 @@ fn map_animation(gltf_animation: &GltfAnimation, data: &[Data]) -> Animation {
         total_time,
         translation_channels,
         rotation_channels,
+        quantized_rotation_channels: Vec::new(),
         scale_channels,
     }
 }
@@ -385,6 +698,7 @@ fn map_translation_channel(
                     interpolation: i,
                     times,
                     values: output,
+                    rate: None,
                 },
                 node_index: gltf_channel.target().node().index(),
             }
@@ -416,6 +730,7 @@ fn map_rotation_channel(
                     interpolation,
                     times,
                     values: output,
+                    rate: None,
                 },
                 node_index: gltf_channel.target().node().index(),
             }
@@ -444,6 +759,7 @@ fn map_scale_channel(gltf_channel: &GltfChannel, data: &[Data]) -> Option<Channe
                     interpolation: i,
                     times,
                     values: output,
+                    rate: None,
                 },
                 node_index: gltf_channel.target().node().index(),
             }
@@ -506,3 +822,239 @@ where
             _ => vec![],
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::cgmath::{Rad, Rotation3};
+
+    fn sampler<K>(interpolation: Interpolation, times: &[f32], values: Vec<K>) -> Sampler<K> {
+        Sampler {
+            interpolation,
+            times: times.to_vec(),
+            values,
+            rate: None,
+        }
+    }
+
+    fn vectors(xs: &[f32]) -> Vec<Vector3<f32>> {
+        xs.iter().map(|x| Vector3::new(*x, 0.0, 0.0)).collect()
+    }
+
+    fn xs(values: &[Vector3<f32>]) -> Vec<f32> {
+        values.iter().map(|value| value.x).collect()
+    }
+
+    fn distance(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+        (a - b).magnitude()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn resample_spaces_linear_keyframes_evenly() {
+        let mut sampler = sampler(
+            Interpolation::Linear,
+            &[0.0, 1.0, 2.0],
+            vectors(&[0.0, 10.0, 30.0]),
+        );
+        sampler.resample(4.0);
+
+        assert_eq!(sampler.rate, Some(4.0));
+        assert_eq!(
+            sampler.times,
+            [0.0, 0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0]
+        );
+        assert_eq!(
+            xs(&sampler.values),
+            [0.0, 2.5, 5.0, 7.5, 10.0, 15.0, 20.0, 25.0, 30.0]
+        );
+        // Found from the rate, as when searched.
+        assert_eq!(sampler.find_keyframe(1.3), Some(5));
+        assert_eq!(sampler.find_keyframe(1.75), Some(7));
+        assert_eq!(sampler.find_keyframe(2.0), None);
+        assert_eq!(sampler.find_keyframe(-0.1), None);
+    }
+
+    #[test]
+    fn resample_keeps_the_last_keyframe() {
+        let mut sampler = sampler(Interpolation::Linear, &[0.5, 1.6], vectors(&[1.0, 2.1]));
+        sampler.resample(2.0);
+
+        assert_eq!(sampler.times, [0.5, 1.0, 1.5, 1.6]);
+        assert_close(sampler.values[1].x, 1.5);
+        assert_close(sampler.values[3].x, 2.1);
+    }
+
+    #[test]
+    fn resample_turns_cubic_splines_linear() {
+        // In tangent, value and out tangent of each keyframe.
+        let values = vectors(&[0.0, 0.0, 4.0, -4.0, 2.0, 0.0]);
+        let mut sampler = sampler(Interpolation::CubicSpline, &[0.0, 1.0], values);
+        let expected = [0.25, 0.5, 0.75].map(|t| sampler.sample(t).unwrap().x);
+        sampler.resample(4.0);
+
+        assert_eq!(sampler.interpolation, Interpolation::Linear);
+        assert_eq!(sampler.times, [0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(sampler.values.len(), 5);
+        assert_close(sampler.values[0].x, 0.0);
+        for (value, expected) in sampler.values[1..4].iter().zip(expected) {
+            assert_close(value.x, expected);
+        }
+        assert_close(sampler.values[4].x, 2.0);
+    }
+
+    #[test]
+    fn resample_leaves_step_keyframes() {
+        let mut sampler = sampler(Interpolation::Step, &[0.0, 1.0], vectors(&[0.0, 1.0]));
+        sampler.resample(10.0);
+
+        assert_eq!(sampler.times, [0.0, 1.0]);
+        assert_eq!(sampler.rate, None);
+    }
+
+    #[test]
+    fn reduce_removes_the_interpolated_linear_keyframes() {
+        let times = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut sampler = sampler(
+            Interpolation::Linear,
+            &times,
+            vectors(&[0.0, 1.0, 2.00005, 3.0, 3.0, 3.0]),
+        );
+        sampler.rate = Some(1.0);
+        sampler.reduce(1e-4, distance);
+
+        assert_eq!(sampler.times, [0.0, 3.0, 5.0]);
+        assert_eq!(xs(&sampler.values), [0.0, 3.0, 3.0]);
+        assert_eq!(sampler.rate, None);
+        assert_close(sampler.sample(1.5).unwrap().x, 1.5);
+    }
+
+    #[test]
+    fn reduce_keeps_the_keyframes_out_of_tolerance() {
+        let times = [0.0, 1.0, 2.0];
+        let mut sampler = sampler(Interpolation::Linear, &times, vectors(&[0.0, 1.01, 2.0]));
+        sampler.rate = Some(1.0);
+        sampler.reduce(1e-3, distance);
+
+        assert_eq!(sampler.times, times);
+        assert_eq!(sampler.rate, Some(1.0));
+    }
+
+    #[test]
+    fn reduce_removes_repeated_step_keyframes() {
+        let times = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut sampler = sampler(
+            Interpolation::Step,
+            &times,
+            vectors(&[1.0, 1.0, 2.0, 2.0, 2.0, 3.0]),
+        );
+        sampler.reduce(1e-4, distance);
+
+        assert_eq!(sampler.times, [0.0, 2.0, 5.0]);
+        assert_eq!(sampler.sample(4.5).unwrap().x, 2.0);
+    }
+
+    #[test]
+    fn reduce_keeps_cubic_splines() {
+        let values = vectors(&[0.0; 9]);
+        let mut sampler = sampler(Interpolation::CubicSpline, &[0.0, 1.0, 2.0], values);
+        sampler.reduce(1.0, distance);
+
+        assert_eq!(sampler.times.len(), 3);
+        assert_eq!(sampler.values.len(), 9);
+    }
+
+    #[test]
+    fn rotation_angle_measures_small_angles() {
+        let a = Quaternion::from_angle_y(Rad(0.3));
+        for angle in [5e-5, 1e-4, 1e-3, 1.0] {
+            let b = Quaternion::from_angle_y(Rad(0.3 + angle));
+            assert!((rotation_angle(a, b) - angle).abs() < angle * 0.05);
+            // Opposite quaternions are the same rotation.
+            assert!((rotation_angle(a, -b) - angle).abs() < angle * 0.05);
+        }
+    }
+
+    #[test]
+    fn quantized_rotations_stay_within_a_hundredth_of_degree() {
+        let rotations = [
+            Quaternion::from_angle_y(Rad(0.0)),
+            Quaternion::from_angle_x(Rad(1.0)),
+            Quaternion::from_axis_angle(Vector3::new(1.0, 2.0, 3.0).normalize(), Rad(2.5)),
+            Quaternion::from_angle_z(Rad(-3.0)),
+        ];
+        for rotation in rotations {
+            let decoded = QuantizedRotation::from(rotation).decode();
+            assert!(rotation_angle(rotation, decoded) < 0.01f32.to_radians());
+        }
+    }
+
+    #[test]
+    fn compress_reduces_and_quantizes_the_rotations() {
+        let times = [0.0, 1.0, 2.0, 3.0];
+        let rotations = times
+            .iter()
+            .map(|time| Quaternion::from_angle_y(Rad(*time * 0.5)))
+            .collect::<Vec<_>>();
+        let cubic_values = rotations
+            .iter()
+            .flat_map(|rotation| {
+                [
+                    Quaternion::new(0.0, 0.0, 0.0, 0.0),
+                    *rotation,
+                    Quaternion::new(0.0, 0.0, 0.0, 0.0),
+                ]
+            })
+            .collect();
+        let mut animation = Animation {
+            total_time: 3.0,
+            translation_channels: vec![Channel {
+                sampler: sampler(
+                    Interpolation::Linear,
+                    &times,
+                    vectors(&[0.0, 1.0, 2.0, 3.0]),
+                ),
+                node_index: 0,
+            }],
+            rotation_channels: vec![
+                Channel {
+                    sampler: sampler(Interpolation::Linear, &times, rotations),
+                    node_index: 1,
+                },
+                Channel {
+                    sampler: sampler(Interpolation::CubicSpline, &times, cubic_values),
+                    node_index: 2,
+                },
+            ],
+            quantized_rotation_channels: Vec::new(),
+            scale_channels: Vec::new(),
+        };
+        let size = animation.keyframes_size();
+
+        animation.compress(&AnimationCompression::default());
+
+        // Both linear channels are reduced to their ends.
+        assert_eq!(animation.translation_channels[0].sampler.times, [0.0, 3.0]);
+        assert_eq!(animation.quantized_rotation_channels.len(), 1);
+        let quantized = &animation.quantized_rotation_channels[0];
+        assert_eq!(quantized.node_index, 1);
+        assert_eq!(quantized.sampler.times, [0.0, 3.0]);
+        // The tangents of the cubic spline are not unit quaternions, it stays as is.
+        assert_eq!(animation.rotation_channels.len(), 1);
+        assert_eq!(animation.rotation_channels[0].sampler.values.len(), 12);
+
+        assert_eq!(animation.keyframe_count(), 2 + 2 + 4);
+        assert!(animation.keyframes_size() < size);
+        let (node_index, rotation) = quantized.sample(1.5).unwrap();
+        assert_eq!(node_index, 1);
+        assert!(rotation_angle(rotation, Quaternion::from_angle_y(Rad(0.75))) < 1e-3);
+    }
+}
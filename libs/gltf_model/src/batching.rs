@@ -0,0 +1,299 @@
+use super::{
+    IndexBuffer, IndexBufferPart, Material, ModelVertex, PrimitiveData, VertexBuffer,
+    VertexBufferPart,
+};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3, Vector4};
+use gltf::{mesh::Mode, Document, Node};
+use math::Aabb;
+use std::{
+    collections::{HashMap, HashSet},
+    mem::size_of,
+};
+
+/// Default largest vertex count of the primitives merged by static batching.
+pub const DEFAULT_BATCHING_MAX_VERTICES: usize = 4096;
+
+/// Options of the static batching done when loading a model.
+///
+/// Small primitives of the nodes that are neither animated nor skinned are
+/// transformed to the space of the scene and merged by material, so scenes
+/// made of many small objects are drawn with a few calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticBatching {
+    /// Primitives with more vertices are left alone.
+    pub max_vertices: usize,
+}
+
+impl Default for StaticBatching {
+    fn default() -> Self {
+        Self {
+            max_vertices: DEFAULT_BATCHING_MAX_VERTICES,
+        }
+    }
+}
+
+/// Primitive of a node merged into a [`StaticBatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchedPrimitive {
+    pub node_index: usize,
+    /// Index of the primitive, see [`crate::Primitive::index`].
+    pub primitive_index: usize,
+    /// Range of the indices of the batch drawing the primitive.
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+/// Static primitives sharing a material merged in a single draw.
+///
+/// The vertices are in the space of the scene, the model is drawn with its
+/// global transform only. The batched nodes must not be moved.
+pub struct StaticBatch {
+    vertices: VertexBuffer,
+    indices: IndexBuffer,
+    material: Material,
+    material_index: Option<usize>,
+    aabb: Aabb<f32>,
+    primitives: Vec<BatchedPrimitive>,
+}
+
+impl StaticBatch {
+    pub fn vertices(&self) -> &VertexBuffer {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &IndexBuffer {
+        &self.indices
+    }
+
+    pub fn material(&self) -> Material {
+        self.material
+    }
+
+    pub fn material_index(&self) -> Option<usize> {
+        self.material_index
+    }
+
+    pub fn aabb(&self) -> Aabb<f32> {
+        self.aabb
+    }
+
+    /// Primitives merged in the batch, in the order of their indices.
+    pub fn primitives(&self) -> &[BatchedPrimitive] {
+        &self.primitives
+    }
+
+    /// Primitive drawing the triangle at `triangle`, `gl_PrimitiveID` of the
+    /// draw, to map the picking results back to the scene.
+    pub fn primitive_of_triangle(&self, triangle: u32) -> Option<&BatchedPrimitive> {
+        let index = triangle * 3;
+        let position = self
+            .primitives
+            .partition_point(|primitive| primitive.first_index + primitive.index_count <= index);
+        self.primitives
+            .get(position)
+            .filter(|primitive| primitive.first_index <= index)
+    }
+}
+
+/// A batch before its buffers are uploaded.
+pub(crate) struct StaticBatchData {
+    pub vertices: VertexBufferPart,
+    pub indices: IndexBufferPart,
+    pub material: Material,
+    pub material_index: Option<usize>,
+    pub aabb: Aabb<f32>,
+    pub primitives: Vec<BatchedPrimitive>,
+}
+
+impl StaticBatchData {
+    pub fn into_batch(self, vertices: VertexBuffer, indices: IndexBuffer) -> StaticBatch {
+        StaticBatch {
+            vertices,
+            indices,
+            material: self.material,
+            material_index: self.material_index,
+            aabb: self.aabb,
+            primitives: self.primitives,
+        }
+    }
+}
+
+/// Merge the small static primitives of `meshes` by material, appending the
+/// merged vertices and indices to `all_vertices` and `all_indices`.
+pub(crate) fn batch_static_primitives(
+    document: &Document,
+    meshes: &[Vec<PrimitiveData>],
+    all_vertices: &mut Vec<ModelVertex>,
+    all_indices: &mut Vec<u32>,
+    options: &StaticBatching,
+) -> Vec<StaticBatchData> {
+    let animated_nodes = document
+        .animations()
+        .flat_map(|animation| animation.channels())
+        .map(|channel| channel.target().node().index())
+        .collect::<HashSet<_>>();
+
+    let mut static_nodes = Vec::new();
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            gather_static_nodes(
+                &node,
+                Matrix4::identity(),
+                &animated_nodes,
+                &mut static_nodes,
+            );
+        }
+    }
+
+    // Group by material, keeping the order of the document within groups.
+    let mut groups = Vec::<(Option<usize>, Vec<(usize, Matrix4<f32>, &PrimitiveData)>)>::new();
+    let mut group_of_material = HashMap::new();
+    for (node_index, mesh_index, transform) in static_nodes {
+        for primitive in &meshes[mesh_index] {
+            if primitive.mode != Mode::Triangles || primitive.vertices.1 > options.max_vertices {
+                continue;
+            }
+            let group = *group_of_material
+                .entry(primitive.material_index)
+                .or_insert_with(|| {
+                    groups.push((primitive.material_index, Vec::new()));
+                    groups.len() - 1
+                });
+            groups[group].1.push((node_index, transform, primitive));
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, primitives)| primitives.len() > 1)
+        .map(|(material_index, primitives)| {
+            let first_vertex = all_vertices.len();
+            let first_index = all_indices.len();
+            let mut batched = Vec::with_capacity(primitives.len());
+            for (node_index, transform, primitive) in primitives.iter() {
+                let batch_first_vertex = (all_vertices.len() - first_vertex) as u32;
+                let (offset, count) = primitive.vertices;
+                let start = offset / size_of::<ModelVertex>();
+                let vertices = all_vertices[start..start + count]
+                    .iter()
+                    .map(|vertex| transform_vertex(vertex, *transform))
+                    .collect::<Vec<_>>();
+                all_vertices.extend(vertices);
+
+                let primitive_first_index = all_indices.len();
+                match primitive.indices {
+                    Some((offset, count)) => {
+                        let start = offset / size_of::<u32>();
+                        let indices = all_indices[start..start + count]
+                            .iter()
+                            .map(|index| index + batch_first_vertex)
+                            .collect::<Vec<_>>();
+                        all_indices.extend(indices);
+                    }
+                    None => all_indices
+                        .extend((0..count as u32).map(|index| index + batch_first_vertex)),
+                }
+
+                batched.push(BatchedPrimitive {
+                    node_index: *node_index,
+                    primitive_index: primitive.index,
+                    first_index: (primitive_first_index - first_index) as _,
+                    index_count: (all_indices.len() - primitive_first_index) as _,
+                });
+            }
+
+            let (min, max) = all_vertices[first_vertex..].iter().fold(
+                ([f32::MAX; 3], [f32::MIN; 3]),
+                |(min, max), vertex| {
+                    (
+                        std::array::from_fn(|axis| min[axis].min(vertex.position[axis])),
+                        std::array::from_fn(|axis| max[axis].max(vertex.position[axis])),
+                    )
+                },
+            );
+
+            StaticBatchData {
+                vertices: (
+                    first_vertex * size_of::<ModelVertex>(),
+                    all_vertices.len() - first_vertex,
+                ),
+                indices: (
+                    first_index * size_of::<u32>(),
+                    all_indices.len() - first_index,
+                ),
+                material: primitives[0].2.material,
+                material_index,
+                aabb: Aabb::new(min.into(), max.into()),
+                primitives: batched,
+            }
+        })
+        .collect()
+}
+
+/// Push the nodes with a mesh under `node`, included, that can be batched
+/// with their transform in the space of the scene.
+fn gather_static_nodes(
+    node: &Node,
+    parent_transform: Matrix4<f32>,
+    animated_nodes: &HashSet<usize>,
+    static_nodes: &mut Vec<(usize, usize, Matrix4<f32>)>,
+) {
+    // Anything under an animated node moves with it.
+    if animated_nodes.contains(&node.index()) {
+        return;
+    }
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    // Mirroring transforms flip the winding of the triangles.
+    let mirrored = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    )
+    .determinant()
+        < 0.0;
+    if let Some(mesh) = node.mesh() {
+        if node.skin().is_none() && !mirrored {
+            static_nodes.push((node.index(), mesh.index(), transform));
+        }
+    }
+
+    for child in node.children() {
+        gather_static_nodes(&child, transform, animated_nodes, static_nodes);
+    }
+}
+
+fn transform_vertex(vertex: &ModelVertex, transform: Matrix4<f32>) -> ModelVertex {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(linear);
+    let normalize = |vector: Vector3<f32>| {
+        if vector.magnitude2() > 0.0 {
+            vector.normalize()
+        } else {
+            vector
+        }
+    };
+
+    let [x, y, z] = vertex.position;
+    let position = transform * Vector4::new(x, y, z, 1.0);
+    let normal = normalize(normal_matrix * Vector3::from(vertex.normal));
+    let [tx, ty, tz, handedness] = vertex.tangent;
+    let tangent = normalize(linear * Vector3::new(tx, ty, tz));
+
+    ModelVertex {
+        position: position.truncate().into(),
+        normal: normal.into(),
+        tangent: [tangent.x, tangent.y, tangent.z, handedness],
+        ..*vertex
+    }
+}
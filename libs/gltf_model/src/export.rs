@@ -0,0 +1,346 @@
+use crate::{Light, Material, Model, Type, Workflow};
+use gltf::{binary::Header, scene::Transform, Glb, Gltf};
+use serde_json::{json, Map, Value};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+const EMISSIVE_STRENGTH_EXTENSION: &str = "KHR_materials_emissive_strength";
+const SPECULAR_GLOSSINESS_EXTENSION: &str = "KHR_materials_pbrSpecularGlossiness";
+const LIGHTS_EXTENSION: &str = "KHR_lights_punctual";
+
+impl Model {
+    /// Write the model back to a `.gltf` or `.glb` file, depending on the extension of `path`.
+    ///
+    /// The file the model was loaded from is copied with the node transforms,
+    /// material parameters and lights of the model, so the edits made through
+    /// [`Model::set_node_transform`], [`Model::material_mut`] and [`Model::light_mut`]
+    /// are kept. Everything else, like meshes and animations, is written as loaded.
+    ///
+    /// A `.glb` embeds all buffers and images. A `.gltf` references them, so they are
+    /// copied next to it if it is written to another directory than the source.
+    pub fn export<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let source = Path::new(self.metadata.path());
+        let source_dir = source.parent().unwrap_or_else(|| Path::new(""));
+        tracing::debug!("Exporting {} to {}", source.display(), path.display());
+
+        let bytes = fs::read(source)?;
+        let gltf = Gltf::from_slice(&bytes)?;
+        let json = if bytes.starts_with(b"glTF") {
+            Glb::from_slice(&bytes)?.json.into_owned()
+        } else {
+            bytes.clone()
+        };
+        let mut root: Value = serde_json::from_slice(&json)?;
+
+        self.write_nodes(&mut root);
+        self.write_materials(&mut root);
+        self.write_lights(&mut root);
+
+        let is_glb = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+        if is_glb {
+            write_glb(root, gltf, source_dir, path)
+        } else {
+            write_gltf(root, gltf, source_dir, path)
+        }
+    }
+
+    fn write_nodes(&self, root: &mut Value) {
+        let Some(json_nodes) = root["nodes"].as_array_mut() else {
+            return;
+        };
+
+        for (node, json_node) in self.nodes.nodes().iter().zip(json_nodes) {
+            let json_node = json_node.as_object_mut().unwrap();
+            for key in ["matrix", "translation", "rotation", "scale"] {
+                json_node.remove(key);
+            }
+
+            match node.local_transform() {
+                Transform::Matrix { matrix } => {
+                    json_node.insert("matrix".into(), json!(matrix.concat()));
+                }
+                Transform::Decomposed {
+                    translation,
+                    rotation,
+                    scale,
+                } => {
+                    json_node.insert("translation".into(), json!(translation));
+                    json_node.insert("rotation".into(), json!(rotation));
+                    json_node.insert("scale".into(), json!(scale));
+                }
+            }
+        }
+    }
+
+    fn write_materials(&self, root: &mut Value) {
+        let mut uses_emissive_strength = false;
+        if let Some(json_materials) = root["materials"].as_array_mut() {
+            for (material, json_material) in self.materials.iter().zip(json_materials) {
+                uses_emissive_strength |=
+                    write_material(material, json_material.as_object_mut().unwrap());
+            }
+        }
+
+        if uses_emissive_strength {
+            mark_extension_used(root, EMISSIVE_STRENGTH_EXTENSION);
+        }
+    }
+
+    fn write_lights(&self, root: &mut Value) {
+        let Some(json_lights) = root["extensions"][LIGHTS_EXTENSION]["lights"].as_array_mut()
+        else {
+            return;
+        };
+
+        for (light, json_light) in self.lights.iter().zip(json_lights) {
+            write_light(light, json_light.as_object_mut().unwrap());
+        }
+    }
+}
+
+/// Write the parameters of `material`.
+///
+/// # Returns
+///
+/// True if the emissive strength extension is required.
+fn write_material(material: &Material, json_material: &mut Map<String, Value>) -> bool {
+    match material.get_workflow() {
+        Workflow::MetallicRoughness(workflow) => {
+            let pbr = get_object(json_material, "pbrMetallicRoughness");
+            pbr.insert("baseColorFactor".into(), json!(material.get_color()));
+            pbr.insert("metallicFactor".into(), json!(workflow.get_metallic()));
+            pbr.insert("roughnessFactor".into(), json!(workflow.get_roughness()));
+        }
+        Workflow::SpecularGlossiness(workflow) => {
+            let extensions = get_object(json_material, "extensions");
+            let pbr = get_object(extensions, SPECULAR_GLOSSINESS_EXTENSION);
+            pbr.insert("diffuseFactor".into(), json!(material.get_color()));
+            pbr.insert("specularFactor".into(), json!(workflow.get_specular()));
+            pbr.insert("glossinessFactor".into(), json!(workflow.get_glossiness()));
+        }
+    }
+
+    // The emissive color was multiplied by the strength when loading.
+    let emissive = material.get_emissive();
+    let strength = emissive.iter().copied().fold(1.0, f32::max);
+    json_material.insert(
+        "emissiveFactor".into(),
+        json!(emissive.map(|channel| channel / strength)),
+    );
+    let extensions = get_object(json_material, "extensions");
+    if strength > 1.0 {
+        extensions.insert(
+            EMISSIVE_STRENGTH_EXTENSION.into(),
+            json!({ "emissiveStrength": strength }),
+        );
+    } else {
+        extensions.remove(EMISSIVE_STRENGTH_EXTENSION);
+    }
+    if extensions.is_empty() {
+        json_material.remove("extensions");
+    }
+
+    json_material.insert("alphaCutoff".into(), json!(material.get_alpha_cutoff()));
+    json_material.insert("doubleSided".into(), json!(material.is_double_sided()));
+
+    strength > 1.0
+}
+
+fn write_light(light: &Light, json_light: &mut Map<String, Value>) {
+    json_light.insert("color".into(), json!(light.color()));
+    json_light.insert("intensity".into(), json!(light.intensity()));
+    match light.range() {
+        Some(range) => json_light.insert("range".into(), json!(range)),
+        None => json_light.remove("range"),
+    };
+    if let Type::Spot {
+        inner_cone_angle,
+        outer_cone_angle,
+    } = light.light_type()
+    {
+        json_light.insert(
+            "spot".into(),
+            json!({
+                "innerConeAngle": inner_cone_angle,
+                "outerConeAngle": outer_cone_angle,
+            }),
+        );
+    }
+}
+
+/// Get the object at `key`, inserting an empty one if missing.
+fn get_object<'a>(object: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    object
+        .entry(key)
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .unwrap()
+}
+
+fn mark_extension_used(root: &mut Value, name: &str) {
+    let used = root
+        .as_object_mut()
+        .unwrap()
+        .entry("extensionsUsed")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .unwrap();
+    if !used.iter().any(|used| used == name) {
+        used.push(json!(name));
+    }
+}
+
+/// Pack all buffers and the external images in the binary chunk.
+fn write_glb(
+    mut root: Value,
+    gltf: Gltf,
+    source_dir: &Path,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let buffers = gltf::import_buffers(&gltf.document, Some(source_dir), gltf.blob)?;
+
+    let mut bin = Vec::new();
+    let mut buffer_offsets = Vec::with_capacity(buffers.len());
+    for buffer in buffers.iter() {
+        align_to_four(&mut bin);
+        buffer_offsets.push(bin.len());
+        bin.extend_from_slice(buffer);
+    }
+
+    if let Some(views) = root["bufferViews"].as_array_mut() {
+        for view in views.iter_mut() {
+            let buffer_index = view["buffer"].as_u64().unwrap() as usize;
+            let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+            view["buffer"] = json!(0);
+            view["byteOffset"] = json!(buffer_offsets[buffer_index] + offset);
+        }
+    }
+
+    let first_image_view = root["bufferViews"].as_array().map_or(0, Vec::len);
+    let mut image_views = Vec::new();
+    if let Some(images) = root["images"].as_array_mut() {
+        for image in images.iter_mut() {
+            let Some(uri) = image["uri"].as_str() else {
+                continue;
+            };
+            if uri.starts_with("data:") {
+                continue;
+            }
+
+            let image_path = source_dir.join(uri);
+            let data = fs::read(&image_path)?;
+            let mime_type = match image_path.extension().and_then(|ext| ext.to_str()) {
+                Some("jpg" | "jpeg") => "image/jpeg",
+                _ => "image/png",
+            };
+
+            align_to_four(&mut bin);
+            let image = image.as_object_mut().unwrap();
+            image.remove("uri");
+            image.insert("mimeType".into(), json!(mime_type));
+            image.insert(
+                "bufferView".into(),
+                json!(first_image_view + image_views.len()),
+            );
+
+            image_views.push(json!({
+                "buffer": 0,
+                "byteOffset": bin.len(),
+                "byteLength": data.len(),
+            }));
+            bin.extend_from_slice(&data);
+        }
+    }
+    if !image_views.is_empty() {
+        root.as_object_mut()
+            .unwrap()
+            .entry("bufferViews")
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .unwrap()
+            .extend(image_views);
+    }
+
+    let bin = if bin.is_empty() {
+        root.as_object_mut().unwrap().remove("buffers");
+        None
+    } else {
+        root["buffers"] = json!([{ "byteLength": bin.len() }]);
+        Some(Cow::Owned(bin))
+    };
+
+    let glb = Glb {
+        header: Header {
+            magic: *b"glTF",
+            version: 2,
+            length: 0,
+        },
+        json: Cow::Owned(serde_json::to_vec(&root)?),
+        bin,
+    };
+    glb.to_writer(BufWriter::new(File::create(path)?))?;
+
+    Ok(())
+}
+
+/// Write the json, copying the referenced files if needed.
+///
+/// The binary chunk of a glb source is written to `<name>.bin`.
+fn write_gltf(
+    mut root: Value,
+    gltf: Gltf,
+    source_dir: &Path,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let target_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let same_dir = fs::canonicalize(source_dir).ok() == fs::canonicalize(target_dir).ok();
+
+    if let Some(buffers) = root["buffers"].as_array_mut() {
+        for buffer in buffers.iter_mut() {
+            if buffer.get("uri").is_none() {
+                if let Some(blob) = gltf.blob.as_ref() {
+                    let bin_name = path.with_extension("bin");
+                    fs::write(&bin_name, blob)?;
+                    buffer["uri"] = json!(bin_name.file_name().unwrap().to_string_lossy());
+                }
+            }
+        }
+    }
+
+    if !same_dir {
+        for key in ["buffers", "images"] {
+            let uris = root[key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|item| item["uri"].as_str().filter(|uri| !uri.starts_with("data:")));
+            for uri in uris {
+                let source = source_dir.join(uri);
+                if !source.exists() {
+                    continue;
+                }
+                let target = target_dir.join(uri);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(source, target)?;
+            }
+        }
+    }
+
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &root)?;
+
+    Ok(())
+}
+
+fn align_to_four(data: &mut Vec<u8>) {
+    data.resize(data.len().next_multiple_of(4), 0);
+}
@@ -1,7 +1,10 @@
 mod animation;
+mod batching;
 mod error;
+mod export;
 mod light;
 mod material;
+mod material_uniforms;
 mod mesh;
 pub mod metadata;
 mod mikktspace;
@@ -12,14 +15,16 @@ mod vertex;
 
 use self::mikktspace::generate_tangents;
 pub use self::{
-    animation::*, error::*, light::*, material::*, mesh::*, node::*, skin::*, texture::*, vertex::*,
+    animation::*, batching::*, error::*, light::*, material::*, material_uniforms::*, mesh::*,
+    node::*, skin::*, texture::*, vertex::*,
 };
 use cgmath::Matrix4;
+pub use gltf::scene::Transform;
 use math::*;
 use metadata::Metadata;
-use std::{error::Error, path::Path, result::Result, sync::Arc};
+use std::{collections::HashSet, error::Error, path::Path, result::Result, sync::Arc};
 use vks::ash::vk;
-use vks::{Buffer, Context, PreLoadedResource};
+use vks::{Buffer, Context, PreLoadedResource, TextureCompression, TextureRegistry};
 
 pub struct ModelStagingResources {
     _staged_vertices: Buffer,
@@ -27,9 +32,22 @@ pub struct ModelStagingResources {
     _staged_textures: Vec<Buffer>,
 }
 
+/// Options of the loading of a model.
+#[derive(Debug, Clone, Default)]
+pub struct ModelOptions {
+    pub compression: TextureCompression,
+    /// Merge the small static primitives by material, disabled when `None`.
+    pub static_batching: Option<StaticBatching>,
+    /// Reduce and quantize the keyframes of the animations, disabled when `None`.
+    pub animation_compression: Option<AnimationCompression>,
+}
+
 pub struct Model {
     metadata: Metadata,
     meshes: Vec<Mesh>,
+    batches: Vec<StaticBatch>,
+    /// Node index / primitive index of the primitives drawn by the batches.
+    batched_primitives: HashSet<(usize, usize)>,
     nodes: Nodes,
     global_transform: Matrix4<f32>,
     animations: Option<Animations>,
@@ -44,6 +62,40 @@ impl Model {
         context: Arc<Context>,
         command_buffer: vk::CommandBuffer,
         path: P,
+    ) -> Result<PreLoadedResource<Model, ModelStagingResources>, Box<dyn Error>> {
+        Self::create_from_file_with_compression(
+            context,
+            command_buffer,
+            path,
+            &TextureCompression::default(),
+        )
+    }
+
+    /// Like [`Model::create_from_file`], compressing the textures according to `compression`.
+    pub fn create_from_file_with_compression<P: AsRef<Path>>(
+        context: Arc<Context>,
+        command_buffer: vk::CommandBuffer,
+        path: P,
+        compression: &TextureCompression,
+    ) -> Result<PreLoadedResource<Model, ModelStagingResources>, Box<dyn Error>> {
+        Self::create_from_file_with_options(
+            context,
+            command_buffer,
+            path,
+            &ModelOptions {
+                compression: compression.clone(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like [`Model::create_from_file`], with the textures compression, static
+    /// batching and animation compression of `options`.
+    pub fn create_from_file_with_options<P: AsRef<Path>>(
+        context: Arc<Context>,
+        command_buffer: vk::CommandBuffer,
+        path: P,
+        options: &ModelOptions,
     ) -> Result<PreLoadedResource<Model, ModelStagingResources>, Box<dyn Error>> {
         tracing::debug!("Importing gltf file");
         let (document, buffers, images) = gltf::import(&path)?;
@@ -55,7 +107,13 @@ impl Model {
             return Err(Box::new(ModelLoadingError::new("There is no scene")));
         }
 
-        let meshes = create_meshes_from_gltf(&context, command_buffer, &document, &buffers);
+        let meshes = create_meshes_from_gltf(
+            &context,
+            command_buffer,
+            &document,
+            &buffers,
+            options.static_batching.as_ref(),
+        );
         if meshes.is_none() {
             return Err(Box::new(ModelLoadingError::new(
                 "Could not find any renderable primitives",
@@ -64,6 +122,7 @@ impl Model {
 
         let Meshes {
             meshes,
+            batches,
             vertices: staged_vertices,
             indices: staged_indices,
         } = meshes.unwrap();
@@ -72,7 +131,11 @@ impl Model {
             .default_scene()
             .unwrap_or_else(|| document.scenes().next().unwrap());
 
-        let animations = load_animations(document.animations(), &buffers);
+        let animations = load_animations(
+            document.animations(),
+            &buffers,
+            options.animation_compression.as_ref(),
+        );
 
         let mut skins = create_skins_from_gltf(document.skins(), &buffers);
 
@@ -98,15 +161,43 @@ impl Model {
             document.textures(),
             document.materials(),
             &images,
+            &options.compression,
         );
 
-        let materials = create_materials_from_gltf(&document);
+        let mut materials = create_materials_from_gltf(&document);
+        // Normal maps compressed to two channels have no z.
+        for material in materials.iter_mut() {
+            let two_channels = material.get_normals_texture_index().is_some_and(|index| {
+                textures.textures[index].get_format() == vk::Format::BC5_UNORM_BLOCK
+            });
+            if two_channels {
+                material.set_normal_decoding(NormalMapDecoding {
+                    reconstruct_z: true,
+                    ..material.get_normal_decoding()
+                });
+            }
+        }
 
         let lights = create_lights_from_gltf(&document);
 
+        let batched_primitives: HashSet<_> = batches
+            .iter()
+            .flat_map(StaticBatch::primitives)
+            .map(|primitive| (primitive.node_index, primitive.primitive_index))
+            .collect();
+        if !batches.is_empty() {
+            tracing::debug!(
+                "{} primitives merged in {} static batches",
+                batched_primitives.len(),
+                batches.len()
+            );
+        }
+
         let model = Model {
             metadata,
             meshes,
+            batches,
+            batched_primitives,
             nodes,
             global_transform,
             animations,
@@ -140,18 +231,112 @@ impl Model {
         };
 
         if updated {
-            self.nodes.transform(Some(self.global_transform));
-            self.nodes
-                .get_skins_transform()
-                .iter()
-                .for_each(|(index, transform)| {
-                    let skin = &mut self.skins[*index];
-                    skin.compute_joints_matrices(*transform, self.nodes.nodes());
-                });
+            self.update_transforms();
         }
 
         updated
     }
+
+    fn update_transforms(&mut self) {
+        self.nodes.transform(Some(self.global_transform));
+        self.nodes
+            .get_skins_transform()
+            .iter()
+            .for_each(|(index, transform)| {
+                let skin = &mut self.skins[*index];
+                skin.compute_joints_matrices(*transform, self.nodes.nodes());
+            });
+    }
+}
+
+/// Editing methods
+///
+/// The edits are kept by [`Model::export`].
+impl Model {
+    /// Set the local transform of the node at `index` and update the global transforms.
+    ///
+    /// Lights are placed by moving the node they are attached to.
+    pub fn set_node_transform(&mut self, index: usize, transform: Transform) {
+        self.nodes.nodes_mut()[index].set_local_transform(transform);
+        self.update_transforms();
+    }
+
+    pub fn material_mut(&mut self, index: usize) -> &mut Material {
+        &mut self.materials[index]
+    }
+}
+
+/// Material overrides
+///
+/// Overrides only change how primitives are drawn, they are not exported.
+impl Model {
+    /// Draw the primitive at `primitive_index`, see [`Primitive::index`], with
+    /// `material` instead of its own until the override is cleared.
+    ///
+    /// Renderers pick up the change with [`MaterialUniforms::update`]. Primitives
+    /// drawn by a static batch keep the material of the batch.
+    pub fn set_material_override(&mut self, primitive_index: usize, material: Material) {
+        self.primitive_mut(primitive_index)
+            .set_material_override(Some(material));
+    }
+
+    /// Draw the primitive at `primitive_index` with its own material again.
+    pub fn clear_material_override(&mut self, primitive_index: usize) {
+        self.primitive_mut(primitive_index)
+            .set_material_override(None);
+    }
+
+    pub fn clear_material_overrides(&mut self) {
+        self.meshes
+            .iter_mut()
+            .flat_map(|mesh| mesh.primitives_mut().iter_mut())
+            .for_each(|primitive| primitive.set_material_override(None));
+    }
+
+    /// Register the textures of the model in `registry`.
+    ///
+    /// # Returns
+    ///
+    /// The handles of the textures, indexed like [`Model::textures`], to build
+    /// the [`MaterialTextureIds`] of the primitives with
+    /// [`Model::material_texture_ids`].
+    ///
+    /// # Panics
+    ///
+    /// If the registry is full.
+    pub fn register_textures(&self, registry: &mut TextureRegistry) -> Vec<u32> {
+        self.textures()
+            .iter()
+            .map(|texture| {
+                registry
+                    .register(texture.get_view(), texture.get_sampler())
+                    .expect("Bindless texture registry is full")
+            })
+            .collect()
+    }
+
+    /// Texture handles of the materials of the primitives, overrides included,
+    /// at [`Primitive::index`].
+    pub fn material_texture_ids(&self, handles: &[u32]) -> Vec<MaterialTextureIds> {
+        let mut ids =
+            vec![MaterialTextureIds::new(&Material::default(), handles); self.primitive_count()];
+        for primitive in self.meshes.iter().flat_map(Mesh::primitives) {
+            ids[primitive.index()] = MaterialTextureIds::new(&primitive.material(), handles);
+        }
+        ids
+    }
+
+    fn primitive_mut(&mut self, primitive_index: usize) -> &mut Primitive {
+        self.meshes
+            .iter_mut()
+            .flat_map(|mesh| mesh.primitives_mut().iter_mut())
+            .find(|primitive| primitive.index() == primitive_index)
+            .unwrap_or_else(|| panic!("No primitive at index {}", primitive_index))
+    }
+
+    pub fn light_mut(&mut self, index: usize) -> &mut Light {
+        &mut self.lights[index]
+    }
 }
 
 /// Animations methods
@@ -212,6 +397,35 @@ impl Model {
         self.meshes.iter().map(Mesh::primitive_count).sum()
     }
 
+    /// Batches of the static primitives, drawn with [`Model::global_transform`].
+    pub fn static_batches(&self) -> &[StaticBatch] {
+        &self.batches
+    }
+
+    /// True if `primitive` of the node at `node_index` is drawn by a static
+    /// batch and must not be drawn on its own.
+    pub fn is_batched(&self, node_index: usize, primitive: &Primitive) -> bool {
+        self.batched_primitives
+            .contains(&(node_index, primitive.index()))
+    }
+
+    /// Transform fitting the model in a cube at the origin, applied on top of the nodes.
+    pub fn global_transform(&self) -> Matrix4<f32> {
+        self.global_transform
+    }
+
+    /// Features of the materials of the primitives, without duplicates.
+    pub fn material_features(&self) -> Vec<MaterialFeatures> {
+        let mut features = Vec::new();
+        for primitive in self.meshes.iter().flat_map(Mesh::primitives) {
+            let primitive_features = primitive.material().features();
+            if !features.contains(&primitive_features) {
+                features.push(primitive_features);
+            }
+        }
+        features
+    }
+
     pub fn skins(&self) -> &[Skin] {
         &self.skins
     }
@@ -26,6 +26,18 @@ impl Light {
     pub fn light_type(&self) -> Type {
         self.light_type
     }
+
+    pub fn set_color(&mut self, color: [f32; 3]) {
+        self.color = color;
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    pub fn set_range(&mut self, range: Option<f32>) {
+        self.range = range;
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
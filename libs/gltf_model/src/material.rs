@@ -4,6 +4,9 @@ use gltf::{
     Document,
 };
 use math::cgmath::{Matrix3, Rad};
+use vks::{
+    ash::vk, MaskEdges, SpecializationConstants, VertexColorMode, DEFAULT_EMISSIVE_INTENSITY,
+};
 
 const ALPHA_MODE_OPAQUE: u32 = 0;
 const ALPHA_MODE_MASK: u32 = 1;
@@ -11,7 +14,375 @@ const ALPHA_MODE_BLEND: u32 = 2;
 
 const DEFAULT_IOR: f32 = 1.5;
 
-#[derive(Clone, Copy, Debug)]
+/// Flags of [`NormalMapDecoding`] in `MaterialUniform::normalFlags`.
+const NORMAL_FLAG_FLIP_Y: u32 = 1;
+const NORMAL_FLAG_RECONSTRUCT_Z: u32 = 2;
+
+/// Rows of the 2x3 matrix transforming the uvs of a texture, padded for std140.
+type UvTransform = [[f32; 4]; 2];
+
+const IDENTITY_UV_TRANSFORM: UvTransform = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0]];
+
+const DEBUG_VIEW_NONE: u32 = 0;
+const DEBUG_VIEW_NORMALS: u32 = 1;
+
+/// Features of a material selecting a variant of the uber shader.
+///
+/// They are passed to the fragment shader as specialization constants starting
+/// at [`MaterialFeatures::FIRST_CONSTANT_ID`], so each combination gets its own
+/// pipeline, usually from a [`vks::PipelineVariantCache`] keyed by the features.
+/// [`MaterialFeatures::NONE`] selects the variant without material, which only
+/// samples the color texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MaterialFeatures {
+    pub normal_map: bool,
+    pub emissive: bool,
+    pub clearcoat: bool,
+    pub transmission: bool,
+    /// One of the alpha mode indices of [`Material::get_alpha_mode`].
+    pub alpha_mode: u32,
+    /// Back faces are drawn, with their normals flipped.
+    pub double_sided: bool,
+    pub debug_view: MaterialDebugView,
+    pub vertex_colors: VertexColorMode,
+    /// How the edges of mask materials are drawn, see [`MaterialFeatures::with_mask_edges`].
+    pub mask_edges: MaskEdges,
+    has_material: bool,
+}
+
+impl MaterialFeatures {
+    /// Id of the `MATERIAL` constant, followed by `NORMAL_MAP`, `EMISSIVE`,
+    /// `CLEARCOAT`, `TRANSMISSION`, `ALPHA_MODE`, `DEBUG_VIEW`, `VERTEX_COLOR`,
+    /// `DOUBLE_SIDED` and `MASK_EDGES`.
+    pub const FIRST_CONSTANT_ID: u32 = 2;
+
+    /// Variant without material.
+    pub const NONE: MaterialFeatures = MaterialFeatures {
+        normal_map: false,
+        emissive: false,
+        clearcoat: false,
+        transmission: false,
+        alpha_mode: ALPHA_MODE_OPAQUE,
+        double_sided: false,
+        debug_view: MaterialDebugView::None,
+        vertex_colors: VertexColorMode::Ignored,
+        mask_edges: MaskEdges::Discard,
+        has_material: false,
+    };
+
+    /// The same variant, outputting `debug_view` instead of the shaded color.
+    pub fn with_debug_view(self, debug_view: MaterialDebugView) -> Self {
+        Self { debug_view, ..self }
+    }
+
+    /// The same variant, modulating the base color by the vertex colors as `vertex_colors`.
+    pub fn with_vertex_colors(self, vertex_colors: VertexColorMode) -> Self {
+        Self {
+            vertex_colors,
+            ..self
+        }
+    }
+
+    /// The same variant, drawing the edges as `mask_edges` if it has a mask material.
+    ///
+    /// Alpha to coverage smooths the edges of cutout foliage under MSAA, the
+    /// covered samples follow the alpha around the cutoff. Other materials
+    /// keep [`MaskEdges::Discard`], which never discards them.
+    pub fn with_mask_edges(self, mask_edges: MaskEdges) -> Self {
+        Self {
+            mask_edges: if self.alpha_mode == ALPHA_MODE_MASK {
+                mask_edges
+            } else {
+                MaskEdges::Discard
+            },
+            ..self
+        }
+    }
+
+    /// True if the pipeline of the variant enables alpha to coverage.
+    pub fn alpha_to_coverage(&self) -> bool {
+        self.mask_edges == MaskEdges::AlphaToCoverage
+    }
+
+    /// True if the pipeline of the variant blends its color over the target,
+    /// for blend and transmission materials and blended mask edges.
+    pub fn blend(&self) -> bool {
+        self.alpha_mode == ALPHA_MODE_BLEND
+            || self.transmission
+            || self.mask_edges == MaskEdges::Blend
+    }
+
+    /// Color blend state of the pipeline of the variant, blending over the
+    /// target with the output alpha if [`MaterialFeatures::blend`].
+    pub fn color_blend_attachment(&self) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(self.blend())
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+    }
+
+    /// Faces to cull in the pipeline of the variant, none for double sided materials.
+    pub fn cull_mode(&self) -> vk::CullModeFlags {
+        if self.double_sided {
+            vk::CullModeFlags::NONE
+        } else {
+            vk::CullModeFlags::BACK
+        }
+    }
+
+    /// Every variant the renderer can request for materials of `features`,
+    /// each of them with all the debug views and vertex color modes, without duplicates.
+    pub fn permutations<I: IntoIterator<Item = MaterialFeatures>>(features: I) -> Vec<Self> {
+        let mut permutations = Vec::new();
+        for features in features {
+            for debug_view in MaterialDebugView::all() {
+                for vertex_colors in VertexColorMode::all() {
+                    let variant = features
+                        .with_debug_view(debug_view)
+                        .with_vertex_colors(vertex_colors);
+                    if !permutations.contains(&variant) {
+                        permutations.push(variant);
+                    }
+                }
+            }
+        }
+        permutations
+    }
+
+    /// Add the constants selecting this variant to `constants`.
+    pub fn add_constants(&self, constants: &mut SpecializationConstants) {
+        let id = Self::FIRST_CONSTANT_ID;
+        constants.add_bool(id, self.has_material);
+        constants.add_bool(id + 1, self.normal_map);
+        constants.add_bool(id + 2, self.emissive);
+        constants.add_bool(id + 3, self.clearcoat);
+        constants.add_bool(id + 4, self.transmission);
+        constants.add_u32(id + 5, self.alpha_mode);
+        constants.add_u32(id + 6, self.debug_view.index());
+        constants.add_u32(id + 7, self.vertex_colors.index());
+        constants.add_bool(id + 8, self.double_sided);
+        constants.add_u32(id + 9, self.mask_edges.index());
+    }
+}
+
+/// What the uber shader outputs for materials, to inspect their inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaterialDebugView {
+    /// The shaded color.
+    #[default]
+    None,
+    /// The world space normal after decoding the normal map, remapped to [0, 1].
+    Normals,
+}
+
+impl MaterialDebugView {
+    pub fn all() -> [Self; 2] {
+        [Self::None, Self::Normals]
+    }
+
+    fn index(self) -> u32 {
+        match self {
+            Self::None => DEBUG_VIEW_NONE,
+            Self::Normals => DEBUG_VIEW_NORMALS,
+        }
+    }
+}
+
+/// How the texels of a normal map are turned into a tangent space normal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalMapDecoding {
+    /// Scale of x and y, from `normalTexture.scale` of the gltf material.
+    pub scale: f32,
+    /// Negate y, for maps authored with the DirectX convention where green points down.
+    pub flip_y: bool,
+    /// Compute z from x and y, for maps stored in two channels like BC5.
+    pub reconstruct_z: bool,
+}
+
+impl Default for NormalMapDecoding {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            flip_y: false,
+            reconstruct_z: false,
+        }
+    }
+}
+
+impl NormalMapDecoding {
+    fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.flip_y {
+            flags |= NORMAL_FLAG_FLIP_Y;
+        }
+        if self.reconstruct_z {
+            flags |= NORMAL_FLAG_RECONSTRUCT_Z;
+        }
+        flags
+    }
+}
+
+/// Parameters of a material read by the uber shader from its `MaterialUniform` block.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialUniform {
+    color: [f32; 4],
+    emissive: [f32; 3],
+    alpha_cutoff: f32,
+    metallic: f32,
+    roughness: f32,
+    clearcoat_factor: f32,
+    clearcoat_roughness: f32,
+    transmission: f32,
+    normal_scale: f32,
+    normal_flags: u32,
+    emissive_intensity: f32,
+    /// KHR_texture_transform of the textures sampled by the uber shader.
+    color_uv_transform: UvTransform,
+    normal_uv_transform: UvTransform,
+    emissive_uv_transform: UvTransform,
+    clearcoat_uv_transform: UvTransform,
+    transmission_uv_transform: UvTransform,
+}
+
+vks::assert_std140_layout!(MaterialUniform {
+    color: 0,
+    emissive: 16,
+    alpha_cutoff: 28,
+    metallic: 32,
+    roughness: 36,
+    clearcoat_factor: 40,
+    clearcoat_roughness: 44,
+    transmission: 48,
+    normal_scale: 52,
+    normal_flags: 56,
+    emissive_intensity: 60,
+    color_uv_transform: 64,
+    normal_uv_transform: 96,
+    emissive_uv_transform: 128,
+    clearcoat_uv_transform: 160,
+    transmission_uv_transform: 192,
+}, size: 224);
+
+impl From<&Material> for MaterialUniform {
+    fn from(material: &Material) -> Self {
+        let (metallic, roughness) = match material.workflow {
+            Workflow::MetallicRoughness(workflow) => (workflow.metallic, workflow.roughness),
+            Workflow::SpecularGlossiness(workflow) => (0.0, 1.0 - workflow.glossiness),
+        };
+        let clearcoat = material.clearcoat.unwrap_or_default();
+
+        Self {
+            color: material.color,
+            emissive: material.emissive,
+            alpha_cutoff: material.alpha_cutoff,
+            metallic,
+            roughness,
+            clearcoat_factor: clearcoat.factor,
+            clearcoat_roughness: clearcoat.roughness,
+            transmission: material.transmission.map_or(0.0, |t| t.factor),
+            normal_scale: material.normal_decoding.scale,
+            normal_flags: material.normal_decoding.flags(),
+            emissive_intensity: DEFAULT_EMISSIVE_INTENSITY,
+            color_uv_transform: uv_transform(material.color_texture),
+            normal_uv_transform: uv_transform(material.normals_texture),
+            emissive_uv_transform: uv_transform(material.emissive_texture),
+            clearcoat_uv_transform: uv_transform(
+                material
+                    .clearcoat
+                    .and_then(|clearcoat| clearcoat.factor_texture),
+            ),
+            transmission_uv_transform: uv_transform(
+                material
+                    .transmission
+                    .and_then(|transmission| transmission.texture),
+            ),
+        }
+    }
+}
+
+/// Rows of the uv transform of `texture`, the identity without transform.
+fn uv_transform(texture: Option<TextureInfo>) -> UvTransform {
+    texture
+        .and_then(|texture| texture.transform)
+        .map_or(IDENTITY_UV_TRANSFORM, |matrix| {
+            [
+                [matrix.x.x, matrix.y.x, matrix.z.x, 0.0],
+                [matrix.x.y, matrix.y.y, matrix.z.y, 0.0],
+            ]
+        })
+}
+
+impl MaterialUniform {
+    /// Scale the emissive color by `intensity`, the emissive intensity setting of the renderer.
+    ///
+    /// The result is not clamped so emissive surfaces exceed 1 in the hdr scene
+    /// color and pass the [`vks::BLOOM_THRESHOLD`].
+    pub fn with_emissive_intensity(self, intensity: f32) -> Self {
+        Self {
+            emissive_intensity: intensity,
+            ..self
+        }
+    }
+}
+
+/// Handle of an absent texture in [`MaterialTextureIds`].
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+/// Handles in a [`vks::TextureRegistry`] of the textures of a material, for the
+/// renderers indexing a bindless texture array instead of binding a set per
+/// primitive. Absent textures are [`NO_TEXTURE`].
+///
+/// See `shader/bindless/bindless.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialTextureIds {
+    pub color: u32,
+    pub normal: u32,
+    pub emissive: u32,
+    pub occlusion: u32,
+    /// Metallic roughness or specular glossiness texture, depending on the workflow.
+    pub workflow: u32,
+    pub clearcoat: u32,
+    pub transmission: u32,
+}
+
+impl MaterialTextureIds {
+    /// Handles of the textures of `material`, `handles` being the ones of the
+    /// textures of the model returned by [`crate::Model::register_textures`].
+    pub fn new(material: &Material, handles: &[u32]) -> Self {
+        let id = |texture: Option<TextureInfo>| {
+            texture
+                .and_then(|texture| handles.get(texture.index).copied())
+                .unwrap_or(NO_TEXTURE)
+        };
+        let workflow_texture = match material.workflow {
+            Workflow::MetallicRoughness(workflow) => workflow.metallic_roughness_texture,
+            Workflow::SpecularGlossiness(workflow) => workflow.specular_glossiness_texture,
+        };
+
+        Self {
+            color: id(material.color_texture),
+            normal: id(material.normals_texture),
+            emissive: id(material.emissive_texture),
+            occlusion: id(material.occlusion_texture),
+            workflow: id(workflow_texture),
+            clearcoat: id(material
+                .clearcoat
+                .and_then(|clearcoat| clearcoat.factor_texture)),
+            transmission: id(material
+                .transmission
+                .and_then(|transmission| transmission.texture)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Material {
     color: [f32; 4],
     emissive: [f32; 3],
@@ -19,6 +390,7 @@ pub struct Material {
     color_texture: Option<TextureInfo>,
     emissive_texture: Option<TextureInfo>,
     normals_texture: Option<TextureInfo>,
+    normal_decoding: NormalMapDecoding,
     occlusion_texture: Option<TextureInfo>,
     workflow: Workflow,
     alpha_mode: u32,
@@ -26,6 +398,7 @@ pub struct Material {
     double_sided: bool,
     is_unlit: bool,
     clearcoat: Option<Clearcoat>,
+    transmission: Option<Transmission>,
     ior: f32,
 }
 
@@ -38,6 +411,7 @@ impl Default for Material {
             color_texture: None,
             emissive_texture: None,
             normals_texture: None,
+            normal_decoding: NormalMapDecoding::default(),
             occlusion_texture: None,
             workflow: Workflow::MetallicRoughness(MetallicRoughnessWorkflow {
                 metallic: 1.0,
@@ -49,25 +423,26 @@ impl Default for Material {
             double_sided: false,
             is_unlit: false,
             clearcoat: None,
+            transmission: None,
             ior: DEFAULT_IOR,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TextureInfo {
     index: usize,
     channel: u32,
     transform: Option<Matrix3<f32>>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Workflow {
     MetallicRoughness(MetallicRoughnessWorkflow),
     SpecularGlossiness(SpecularGlossinessWorkflow),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MetallicRoughnessWorkflow {
     metallic: f32,
     roughness: f32,
@@ -92,7 +467,7 @@ impl MetallicRoughnessWorkflow {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SpecularGlossinessWorkflow {
     specular: [f32; 3],
     glossiness: f32,
@@ -117,7 +492,7 @@ impl SpecularGlossinessWorkflow {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Clearcoat {
     factor: f32,
     roughness: f32,
@@ -160,6 +535,26 @@ impl Clearcoat {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Transmission {
+    factor: f32,
+    texture: Option<TextureInfo>,
+}
+
+impl Transmission {
+    pub fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    pub fn texture(&self) -> Option<TextureInfo> {
+        self.texture
+    }
+
+    pub fn texture_index(&self) -> Option<usize> {
+        self.texture.map(|info| info.index)
+    }
+}
+
 impl Material {
     pub fn get_color(&self) -> [f32; 4] {
         self.color
@@ -197,6 +592,10 @@ impl Material {
         self.normals_texture
     }
 
+    pub fn get_normal_decoding(&self) -> NormalMapDecoding {
+        self.normal_decoding
+    }
+
     pub fn get_occlusion_texture(&self) -> Option<TextureInfo> {
         self.occlusion_texture
     }
@@ -233,9 +632,69 @@ impl Material {
         self.workflow
     }
 
+    pub fn get_transmission(&self) -> Option<Transmission> {
+        self.transmission
+    }
+
     pub fn get_ior(&self) -> f32 {
         self.ior
     }
+
+    /// Features of the uber shader variant required to render the material.
+    pub fn features(&self) -> MaterialFeatures {
+        MaterialFeatures {
+            normal_map: self.normals_texture.is_some(),
+            emissive: self.emissive.iter().any(|channel| *channel > 0.0),
+            clearcoat: self.clearcoat.is_some(),
+            transmission: self.transmission.is_some(),
+            alpha_mode: self.alpha_mode,
+            double_sided: self.double_sided,
+            debug_view: MaterialDebugView::None,
+            vertex_colors: VertexColorMode::default(),
+            mask_edges: MaskEdges::Discard,
+            has_material: true,
+        }
+    }
+}
+
+/// Setters used to edit the material, see [`crate::Model::export`].
+impl Material {
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    /// Set the emissive color, already multiplied by the emissive strength.
+    pub fn set_emissive(&mut self, emissive: [f32; 3]) {
+        self.emissive = emissive;
+    }
+
+    pub fn set_normal_decoding(&mut self, normal_decoding: NormalMapDecoding) {
+        self.normal_decoding = normal_decoding;
+    }
+
+    pub fn set_alpha_cutoff(&mut self, alpha_cutoff: f32) {
+        self.alpha_cutoff = alpha_cutoff;
+    }
+
+    pub fn set_double_sided(&mut self, double_sided: bool) {
+        self.double_sided = double_sided;
+    }
+
+    /// Does nothing if the material uses the specular glossiness workflow.
+    pub fn set_metallic_roughness(&mut self, metallic: f32, roughness: f32) {
+        if let Workflow::MetallicRoughness(workflow) = &mut self.workflow {
+            workflow.metallic = metallic;
+            workflow.roughness = roughness;
+        }
+    }
+
+    /// Does nothing if the material uses the metallic roughness workflow.
+    pub fn set_specular_glossiness(&mut self, specular: [f32; 3], glossiness: f32) {
+        if let Workflow::SpecularGlossiness(workflow) = &mut self.workflow {
+            workflow.specular = specular;
+            workflow.glossiness = glossiness;
+        }
+    }
 }
 
 impl TextureInfo {
@@ -277,7 +736,11 @@ impl<'a> From<GltfMaterial<'a>> for Material {
         };
         let color_texture = get_texture(color_texture);
         let emissive_texture = get_texture(material.emissive_texture());
-        let normals_texture = get_normals_texture(material.normal_texture());
+        let (normal_scale, normals_texture) = get_normals_texture(material.normal_texture());
+        let normal_decoding = NormalMapDecoding {
+            scale: normal_scale,
+            ..Default::default()
+        };
         let (occlusion, occlusion_texture) = get_occlusion(material.occlusion_texture());
 
         let workflow = match material.pbr_specular_glossiness() {
@@ -311,6 +774,11 @@ impl<'a> From<GltfMaterial<'a>> for Material {
             normal_texture: get_texture(m.clearcoat_normal_texture()),
         });
 
+        let transmission = material.transmission().map(|m| Transmission {
+            factor: m.transmission_factor(),
+            texture: get_texture(m.transmission_texture()),
+        });
+
         let ior = material.ior().unwrap_or(DEFAULT_IOR);
 
         Material {
@@ -320,6 +788,7 @@ impl<'a> From<GltfMaterial<'a>> for Material {
             color_texture,
             emissive_texture,
             normals_texture,
+            normal_decoding,
             occlusion_texture,
             workflow,
             alpha_mode,
@@ -327,6 +796,7 @@ impl<'a> From<GltfMaterial<'a>> for Material {
             double_sided,
             is_unlit,
             clearcoat,
+            transmission,
             ior,
         }
     }
@@ -350,8 +820,12 @@ fn get_texture(texture_info: Option<Info>) -> Option<TextureInfo> {
     })
 }
 
-fn get_normals_texture(texture_info: Option<NormalTexture>) -> Option<TextureInfo> {
-    texture_info.map(|tex_info| {
+fn get_normals_texture(texture_info: Option<NormalTexture>) -> (f32, Option<TextureInfo>) {
+    let scale = texture_info
+        .as_ref()
+        .map_or(1.0, |tex_info| tex_info.scale());
+
+    let texture = texture_info.map(|tex_info| {
         let transform = tex_info
             .texture_transform()
             .map(|tt| map_texture_transform(&tt));
@@ -365,7 +839,9 @@ fn get_normals_texture(texture_info: Option<NormalTexture>) -> Option<TextureInf
             channel,
             transform,
         }
-    })
+    });
+
+    (scale, texture)
 }
 
 fn get_occlusion(texture_info: Option<OcclusionTexture>) -> (f32, Option<TextureInfo>) {
@@ -0,0 +1,128 @@
+use super::{Material, MaterialUniform, Mesh, Model};
+use std::{mem::size_of, sync::Arc};
+use vks::{ash::vk, mem_copy, Buffer, Context};
+
+/// Per frame uniform buffers of the materials of the primitives of a [`Model`],
+/// overrides included.
+///
+/// Each buffer holds one [`MaterialUniform`] slot per primitive, at
+/// [`crate::Primitive::index`], aligned on the device uniform buffer offset
+/// alignment so it can be bound with an offset or through a dynamic uniform
+/// buffer descriptor.
+///
+/// There is one buffer per frame in flight so materials can be edited while
+/// the previous frames are still drawn. [`MaterialUniforms::update`] only
+/// rewrites the slots of a frame whose material changed since the last upload
+/// of that frame, and reports them so the renderer can write the texture
+/// descriptors of that frame again when textures were swapped.
+pub struct MaterialUniforms {
+    buffers: Vec<Buffer>,
+    stride: vk::DeviceSize,
+    /// Materials and emissive intensity of the last upload of each frame.
+    uploaded: Vec<Option<(Vec<Material>, f32)>>,
+}
+
+impl MaterialUniforms {
+    pub fn new(context: &Arc<Context>, model: &Model, frame_count: usize) -> Self {
+        let stride = context.get_ubo_alignment::<MaterialUniform>() as vk::DeviceSize;
+        let size = stride * model.primitive_count().max(1) as vk::DeviceSize;
+        let buffers = (0..frame_count)
+            .map(|_| {
+                let mut buffer = Buffer::create(
+                    Arc::clone(context),
+                    size,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                );
+                buffer.map_memory();
+                buffer
+            })
+            .collect();
+
+        Self {
+            buffers,
+            stride,
+            uploaded: vec![None; frame_count],
+        }
+    }
+}
+
+impl MaterialUniforms {
+    /// Upload the materials of `model` that changed since the last update of
+    /// `frame_index`, scaling their emissive colors by `emissive_intensity`.
+    ///
+    /// Must be called after waiting for the fence of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The indices of the primitives whose material changed, whose texture
+    /// descriptors of the frame must be written again.
+    pub fn update(
+        &mut self,
+        frame_index: usize,
+        model: &Model,
+        emissive_intensity: f32,
+    ) -> Vec<usize> {
+        let materials = model
+            .meshes()
+            .iter()
+            .flat_map(Mesh::primitives)
+            .map(|primitive| (primitive.index(), primitive.material()))
+            .collect::<Vec<_>>();
+        let mut current = vec![Material::default(); materials.len()];
+        for (index, material) in materials {
+            current[index] = material;
+        }
+
+        let (changed, intensity_changed) = match self.uploaded[frame_index].as_ref() {
+            Some((uploaded, uploaded_intensity)) => (
+                (0..current.len())
+                    .filter(|index| uploaded.get(*index) != Some(&current[*index]))
+                    .collect::<Vec<_>>(),
+                *uploaded_intensity != emissive_intensity,
+            ),
+            None => ((0..current.len()).collect(), true),
+        };
+
+        let written = if intensity_changed {
+            (0..current.len()).collect()
+        } else {
+            changed.clone()
+        };
+        let buffer = &mut self.buffers[frame_index];
+        for index in written {
+            let uniform =
+                MaterialUniform::from(&current[index]).with_emissive_intensity(emissive_intensity);
+            unsafe {
+                let ptr = buffer
+                    .map_memory()
+                    .add((self.stride * index as vk::DeviceSize) as usize);
+                mem_copy(ptr, &[uniform]);
+            }
+        }
+
+        self.uploaded[frame_index] = Some((current, emissive_intensity));
+        changed
+    }
+
+    pub fn buffer(&self, frame_index: usize) -> &Buffer {
+        &self.buffers[frame_index]
+    }
+
+    /// Offset of the slot of the primitive at `primitive_index`.
+    pub fn offset(&self, primitive_index: usize) -> vk::DeviceSize {
+        self.stride * primitive_index as vk::DeviceSize
+    }
+
+    /// Descriptor of the material of the primitive at `primitive_index` for `frame_index`.
+    pub fn descriptor_info(
+        &self,
+        frame_index: usize,
+        primitive_index: usize,
+    ) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffers[frame_index].buffer)
+            .offset(self.offset(primitive_index))
+            .range(size_of::<MaterialUniform>() as _)
+    }
+}
@@ -1,11 +1,14 @@
 use vks::{cmd_create_device_local_buffer_with_data, Buffer, Context};
 
-use super::{generate_tangents, IndexBuffer, Material, ModelVertex, VertexBuffer};
+use super::{
+    batch_static_primitives, generate_tangents, IndexBuffer, Material, ModelVertex, StaticBatch,
+    StaticBatching, VertexBuffer,
+};
 use vks::ash::vk;
 use cgmath::Vector3;
 use gltf::{
     buffer::{Buffer as GltfBuffer, Data},
-    mesh::{Bounds, Reader, Semantic},
+    mesh::{Bounds, Mode, Reader, Semantic},
     Document,
 };
 use math::*;
@@ -33,6 +36,10 @@ impl Mesh {
         self.primitives.len()
     }
 
+    pub(crate) fn primitives_mut(&mut self) -> &mut [Primitive] {
+        &mut self.primitives
+    }
+
     pub fn aabb(&self) -> Aabb<f32> {
         self.aabb
     }
@@ -44,6 +51,8 @@ pub struct Primitive {
     indices: Option<IndexBuffer>,
     material: Material,
     material_index: Option<usize>,
+    /// Material drawn instead of `material`, see [`crate::Model::set_material_override`].
+    material_override: Option<Material>,
     aabb: Aabb<f32>,
 }
 
@@ -60,10 +69,25 @@ impl Primitive {
         &self.indices
     }
 
+    /// Material to draw the primitive with, its override if any.
     pub fn material(&self) -> Material {
+        self.material_override.unwrap_or(self.material)
+    }
+
+    /// Material of the primitive in the document, ignoring overrides.
+    pub fn original_material(&self) -> Material {
         self.material
     }
 
+    pub fn has_material_override(&self) -> bool {
+        self.material_override.is_some()
+    }
+
+    pub(crate) fn set_material_override(&mut self, material: Option<Material>) {
+        self.material_override = material;
+    }
+
+    /// Index of the material of the primitive in the document, ignoring overrides.
     pub fn material_index(&self) -> Option<usize> {
         self.material_index
     }
@@ -74,22 +98,24 @@ impl Primitive {
 }
 
 /// Vertex buffer byte offset / element count
-type VertexBufferPart = (usize, usize);
+pub(crate) type VertexBufferPart = (usize, usize);
 
 /// Index buffer byte offset / element count
-type IndexBufferPart = (usize, usize);
-
-struct PrimitiveData {
-    index: usize,
-    indices: Option<IndexBufferPart>,
-    vertices: VertexBufferPart,
-    material: Material,
-    material_index: Option<usize>,
-    aabb: Aabb<f32>,
+pub(crate) type IndexBufferPart = (usize, usize);
+
+pub(crate) struct PrimitiveData {
+    pub index: usize,
+    pub mode: Mode,
+    pub indices: Option<IndexBufferPart>,
+    pub vertices: VertexBufferPart,
+    pub material: Material,
+    pub material_index: Option<usize>,
+    pub aabb: Aabb<f32>,
 }
 
 pub struct Meshes {
     pub meshes: Vec<Mesh>,
+    pub batches: Vec<StaticBatch>,
     pub vertices: Buffer,
     pub indices: Option<Buffer>,
 }
@@ -99,6 +125,7 @@ pub fn create_meshes_from_gltf(
     command_buffer: vk::CommandBuffer,
     document: &Document,
     buffers: &[Data],
+    batching: Option<&StaticBatching>,
 ) -> Option<Meshes> {
     let mut meshes_data = Vec::<Vec<PrimitiveData>>::new();
     let mut all_vertices = Vec::<ModelVertex>::new();
@@ -176,6 +203,7 @@ pub fn create_meshes_from_gltf(
 
                 primitives_buffers.push(PrimitiveData {
                     index,
+                    mode: primitive.mode(),
                     indices,
                     vertices: (offset, accessor.count()),
                     material,
@@ -189,6 +217,16 @@ pub fn create_meshes_from_gltf(
     }
 
     if !meshes_data.is_empty() {
+        let batches_data = batching.map_or(Vec::new(), |batching| {
+            batch_static_primitives(
+                document,
+                &meshes_data,
+                &mut all_vertices,
+                &mut all_indices,
+                batching,
+            )
+        });
+
         let indices = if all_indices.is_empty() {
             None
         } else {
@@ -236,6 +274,7 @@ pub fn create_meshes_from_gltf(
                             indices: index_buffer,
                             material: buffers.material,
                             material_index: buffers.material_index,
+                            material_override: None,
                             aabb: buffers.aabb,
                         }
                     })
@@ -244,8 +283,26 @@ pub fn create_meshes_from_gltf(
             })
             .collect();
 
+        let batches = batches_data
+            .into_iter()
+            .map(|batch| {
+                let vertex_buffer = VertexBuffer::new(
+                    Arc::clone(&vertices),
+                    batch.vertices.0 as _,
+                    batch.vertices.1 as _,
+                );
+                let index_buffer = IndexBuffer::new(
+                    Arc::clone(indices.as_ref().map(|(indices, _)| indices).unwrap()),
+                    batch.indices.0 as _,
+                    batch.indices.1 as _,
+                );
+                batch.into_batch(vertex_buffer, index_buffer)
+            })
+            .collect();
+
         return Some(Meshes {
             meshes,
+            batches,
             vertices: staged_vertices,
             indices: indices.map(|(_, staged_indices)| staged_indices),
         });
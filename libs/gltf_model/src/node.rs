@@ -136,6 +136,15 @@ impl Node {
         self.light_index
     }
 
+    /// Transform relative to the parent node, as written in the gltf file.
+    pub fn local_transform(&self) -> &Transform {
+        &self.local_transform
+    }
+
+    pub fn set_local_transform(&mut self, transform: Transform) {
+        self.local_transform = transform;
+    }
+
     pub fn set_translation(&mut self, translation: Vector3<f32>) {
         if let Transform::Decomposed {
             rotation, scale, ..
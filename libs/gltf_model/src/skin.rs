@@ -1,9 +1,10 @@
 use super::node::Node;
 use gltf::{buffer::Data, iter::Skins as GltfSkins, Skin as GltfSkin};
 use math::cgmath::{Matrix4, SquareMatrix};
+pub use vks::MAX_JOINTS_PER_MESH;
 
-// Must be kept in sync with the value in model.vert
-pub const MAX_JOINTS_PER_MESH: usize = 512;
+/// Joints matrices of a skin as uploaded to the skinning uniform buffers.
+pub type JointsBuffer = [Matrix4<f32>; MAX_JOINTS_PER_MESH];
 
 #[derive(Clone, Debug)]
 pub struct Skin {
@@ -23,6 +24,16 @@ impl Skin {
     pub fn joints(&self) -> &[Joint] {
         &self.joints
     }
+
+    /// Write the matrices of the joints to `buffer`, the unused slots are set
+    /// to the identity and the joints past [`MAX_JOINTS_PER_MESH`] are dropped.
+    pub fn write_joints_buffer(&self, buffer: &mut JointsBuffer) {
+        buffer.fill(Matrix4::identity());
+        buffer
+            .iter_mut()
+            .zip(&self.joints)
+            .for_each(|(matrix, joint)| *matrix = joint.matrix());
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -5,17 +5,21 @@ use gltf::texture::Sampler;
 use std::collections::HashSet;
 use std::sync::Arc;
 use vks::ash::vk;
-use vks::{Buffer, Context, Image, Texture as VulkanTexture};
+use vks::{
+    Buffer, Context, Image, SamplerKey, Texture as VulkanTexture, TextureCompression,
+    TextureUsageHint,
+};
 
 pub(crate) struct Textures {
     _images: Vec<VulkanTexture>,
     pub textures: Vec<GltfTexture>,
 }
 
+/// A view of an image and its sampler, owned by the sampler cache of the context.
 pub struct GltfTexture {
-    context: Arc<Context>,
     view: vk::ImageView,
     sampler: vk::Sampler,
+    format: vk::Format,
 }
 
 impl GltfTexture {
@@ -26,13 +30,9 @@ impl GltfTexture {
     pub fn get_sampler(&self) -> vk::Sampler {
         self.sampler
     }
-}
 
-impl Drop for GltfTexture {
-    fn drop(&mut self) {
-        unsafe {
-            self.context.device().destroy_sampler(self.sampler, None);
-        }
+    pub fn get_format(&self) -> vk::Format {
+        self.format
     }
 }
 
@@ -43,11 +43,12 @@ pub(crate) fn create_textures_from_gltf(
     textures: GltfTextures,
     materials: Materials,
     images: &[Data],
+    compression: &TextureCompression,
 ) -> (Textures, Vec<Buffer>) {
     let srgb_image_indices = {
         let mut indices = HashSet::new();
 
-        for m in materials {
+        for m in materials.clone() {
             if let Some(t) = m.pbr_metallic_roughness().base_color_texture() {
                 indices.insert(t.texture().source().index());
             }
@@ -70,12 +71,55 @@ pub(crate) fn create_textures_from_gltf(
         indices
     };
 
+    // An image can only have one format so flag the ones also sampled as data.
+    let color_policy = context.color_policy();
+    for m in materials.clone() {
+        let data_textures = [
+            m.normal_texture().map(|t| t.texture()),
+            m.occlusion_texture().map(|t| t.texture()),
+            m.pbr_metallic_roughness()
+                .metallic_roughness_texture()
+                .map(|t| t.texture()),
+        ];
+        for texture in data_textures.into_iter().flatten() {
+            let index = texture.source().index();
+            if srgb_image_indices.contains(&index) {
+                color_policy.audit_texture(
+                    &format!("image {}", index),
+                    color_policy.texture_format(false),
+                    true,
+                );
+            }
+        }
+    }
+
+    let compress = compression.is_enabled(context);
+    let usage_hints = if compress {
+        compute_usage_hints(materials, &srgb_image_indices)
+    } else {
+        Vec::new()
+    };
+
     let (images, buffers) = images
         .iter()
         .enumerate()
         .map(|(index, image)| {
             let pixels = build_rgba_buffer(image);
             let is_srgb = srgb_image_indices.contains(&index);
+            if compress {
+                let hint = usage_hints
+                    .get(index)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(TextureUsageHint::Data);
+                let compressed = compression.compress(image.width, image.height, &pixels, hint);
+                return VulkanTexture::cmd_from_compressed(
+                    context,
+                    command_buffer,
+                    &compressed,
+                    !is_srgb,
+                );
+            }
             VulkanTexture::cmd_from_rgba(
                 context,
                 command_buffer,
@@ -89,14 +133,13 @@ pub(crate) fn create_textures_from_gltf(
 
     let textures = textures
         .map(|t| {
-            let context = Arc::clone(context);
             let image = &images[t.source().index()];
             let view = image.view;
-            let sampler = map_sampler(&context, &image.image, &t.sampler());
+            let sampler = map_sampler(context, &image.image, &t.sampler());
             GltfTexture {
-                context,
                 view,
                 sampler,
+                format: image.image.format,
             }
         })
         .collect();
@@ -110,6 +153,44 @@ pub(crate) fn create_textures_from_gltf(
     )
 }
 
+/// Usage of each image, the ones sampled in different ways keep all their channels.
+fn compute_usage_hints(
+    materials: Materials,
+    srgb_image_indices: &HashSet<usize>,
+) -> Vec<Option<TextureUsageHint>> {
+    let mut hints = Vec::<Option<TextureUsageHint>>::new();
+    let mut set_hint = |index: usize, hint: TextureUsageHint| {
+        if hints.len() <= index {
+            hints.resize(index + 1, None);
+        }
+        hints[index] = match hints[index] {
+            Some(previous) if previous != hint => Some(TextureUsageHint::Data),
+            _ => Some(hint),
+        };
+    };
+
+    for m in materials {
+        if let Some(t) = m.normal_texture() {
+            set_hint(t.texture().source().index(), TextureUsageHint::NormalMap);
+        }
+        if let Some(t) = m.occlusion_texture() {
+            set_hint(
+                t.texture().source().index(),
+                TextureUsageHint::SingleChannel,
+            );
+        }
+        if let Some(t) = m.pbr_metallic_roughness().metallic_roughness_texture() {
+            set_hint(t.texture().source().index(), TextureUsageHint::Data);
+        }
+    }
+
+    for index in srgb_image_indices.iter() {
+        set_hint(*index, TextureUsageHint::Color);
+    }
+
+    hints
+}
+
 fn build_rgba_buffer(image: &Data) -> Vec<u8> {
     let mut buffer = Vec::new();
     let size = image.width * image.height;
@@ -160,29 +241,17 @@ fn map_sampler(context: &Arc<Context>, image: &Image, sampler: &Sampler) -> vk::
         0.25
     };
 
-    let sampler_info = vk::SamplerCreateInfo::default()
-        .mag_filter(map_mag_filter(mag_filter))
-        .min_filter(map_min_filter(min_filter))
-        .address_mode_u(map_wrap_mode(sampler.wrap_s()))
-        .address_mode_v(map_wrap_mode(sampler.wrap_t()))
-        .address_mode_w(vk::SamplerAddressMode::REPEAT)
-        .anisotropy_enable(has_mipmaps)
-        .max_anisotropy(16.0)
-        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
-        .unnormalized_coordinates(false)
-        .compare_enable(false)
-        .compare_op(vk::CompareOp::ALWAYS)
-        .mipmap_mode(map_mipmap_filter(min_filter))
-        .mip_lod_bias(0.0)
-        .min_lod(0.0)
-        .max_lod(max_lod);
-
-    unsafe {
-        context
-            .device()
-            .create_sampler(&sampler_info, None)
-            .expect("Failed to create sampler")
-    }
+    context.get_sampler(SamplerKey {
+        mag_filter: map_mag_filter(mag_filter),
+        min_filter: map_min_filter(min_filter),
+        mipmap_mode: map_mipmap_filter(min_filter),
+        address_mode_u: map_wrap_mode(sampler.wrap_s()),
+        address_mode_v: map_wrap_mode(sampler.wrap_t()),
+        address_mode_w: vk::SamplerAddressMode::REPEAT,
+        anisotropy: has_mipmaps,
+        border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+        max_lod,
+    })
 }
 
 fn has_mipmaps(filter: MinFilter) -> bool {
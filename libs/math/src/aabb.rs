@@ -38,6 +38,14 @@ impl<S: BaseFloat> Aabb<S> {
         }
     }
 
+    pub fn min(&self) -> Vector3<S> {
+        self.min
+    }
+
+    pub fn max(&self) -> Vector3<S> {
+        self.max
+    }
+
     /// Get the size of the larger side of the AABB.
     pub fn get_larger_side_size(&self) -> S {
         let size = self.max - self.min;
@@ -54,6 +62,21 @@ impl<S: BaseFloat> Aabb<S> {
         }
     }
 
+    /// Get the eight corners of the AABB.
+    pub fn get_corners(&self) -> [Vector3<S>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vector3::new(min.x, min.y, min.z),
+            Vector3::new(max.x, min.y, min.z),
+            Vector3::new(min.x, max.y, min.z),
+            Vector3::new(max.x, max.y, min.z),
+            Vector3::new(min.x, min.y, max.z),
+            Vector3::new(max.x, min.y, max.z),
+            Vector3::new(min.x, max.y, max.z),
+            Vector3::new(max.x, max.y, max.z),
+        ]
+    }
+
     /// Get the center of the AABB.
     pub fn get_center(&self) -> Vector3<S> {
         let two = S::one() + S::one();
@@ -0,0 +1,338 @@
+use super::{Aabb, Frustum, Ray};
+use cgmath::BaseFloat;
+
+/// Largest number of items in a leaf.
+const MAX_LEAF_ITEMS: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+enum BvhNodeKind {
+    /// Range of the items of the tree.
+    Leaf {
+        start: usize,
+        count: usize,
+    },
+    Internal {
+        left: usize,
+        right: usize,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode<S> {
+    aabb: Aabb<S>,
+    kind: BvhNodeKind,
+}
+
+/// Bounding volume hierarchy over the bounds of a set of items, to find the
+/// ones seen by a frustum or hit by a ray without testing all of them.
+///
+/// Items are referred to by their index in the slice the hierarchy was built
+/// from. When items move, [`Bvh::set_aabb`] then [`Bvh::refit`] update the
+/// bounds of the nodes without changing the tree. Its quality degrades with
+/// large moves, rebuild it with [`Bvh::new`] when the scene changes a lot.
+#[derive(Clone, Debug)]
+pub struct Bvh<S> {
+    /// Children always come after their parent, the root is the first node.
+    nodes: Vec<BvhNode<S>>,
+    /// Indices of the items, grouped by leaf.
+    items: Vec<usize>,
+    aabbs: Vec<Aabb<S>>,
+    dirty: bool,
+}
+
+impl<S: BaseFloat> Bvh<S> {
+    /// Build the hierarchy of the items bounded by `aabbs`.
+    pub fn new(aabbs: &[Aabb<S>]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            items: (0..aabbs.len()).collect(),
+            aabbs: aabbs.to_vec(),
+            dirty: false,
+        };
+        if !aabbs.is_empty() {
+            bvh.build_node(0, aabbs.len());
+        }
+        bvh
+    }
+
+    /// Build the node of `items[start..start + count]` and its children.
+    fn build_node(&mut self, start: usize, count: usize) -> usize {
+        let items = &mut self.items[start..start + count];
+        let bounds = items
+            .iter()
+            .map(|item| self.aabbs[*item])
+            .collect::<Vec<_>>();
+        let aabb = Aabb::union(&bounds).unwrap();
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            aabb,
+            kind: BvhNodeKind::Leaf { start, count },
+        });
+        if count <= MAX_LEAF_ITEMS {
+            return index;
+        }
+
+        // Split at the median of the centers along the largest axis of their bounds.
+        let centers = items
+            .iter()
+            .map(|item| self.aabbs[*item])
+            .map(|aabb| {
+                let center = aabb.get_center();
+                Aabb::new(center, center)
+            })
+            .collect::<Vec<_>>();
+        let extent = Aabb::union(&centers).unwrap();
+        let size = extent.max() - extent.min();
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let aabbs = &self.aabbs;
+        items.sort_by(|a, b| {
+            let a = aabbs[*a].get_center()[axis];
+            let b = aabbs[*b].get_center()[axis];
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let half = count / 2;
+        let left = self.build_node(start, half);
+        let right = self.build_node(start + half, count - half);
+        self.nodes[index].kind = BvhNodeKind::Internal { left, right };
+        index
+    }
+}
+
+impl<S: BaseFloat> Bvh<S> {
+    pub fn len(&self) -> usize {
+        self.aabbs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aabbs.is_empty()
+    }
+
+    pub fn aabb(&self, item: usize) -> Aabb<S> {
+        self.aabbs[item]
+    }
+
+    /// Set the bounds of `item`, taken into account by the queries after [`Bvh::refit`].
+    pub fn set_aabb(&mut self, item: usize, aabb: Aabb<S>) {
+        self.aabbs[item] = aabb;
+        self.dirty = true;
+    }
+
+    /// Update the bounds of the nodes after items moved.
+    pub fn refit(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        // Children come after their parent so they are refitted first.
+        for index in (0..self.nodes.len()).rev() {
+            let aabb = match self.nodes[index].kind {
+                BvhNodeKind::Leaf { start, count } => {
+                    let bounds = self.items[start..start + count]
+                        .iter()
+                        .map(|item| self.aabbs[*item])
+                        .collect::<Vec<_>>();
+                    Aabb::union(&bounds).unwrap()
+                }
+                BvhNodeKind::Internal { left, right } => {
+                    Aabb::union(&[self.nodes[left].aabb, self.nodes[right].aabb]).unwrap()
+                }
+            };
+            self.nodes[index].aabb = aabb;
+        }
+        self.dirty = false;
+    }
+
+    /// Items whose bounds pass `intersects`, appended to `found`.
+    ///
+    /// Subtrees whose bounds fail are skipped, so `intersects` must pass any
+    /// box containing a box it passes. Call [`Bvh::refit`] first if items moved.
+    pub fn query<F>(&self, intersects: F, found: &mut Vec<usize>)
+    where
+        F: Fn(&Aabb<S>) -> bool,
+    {
+        self.visit(&intersects, |item, aabb| {
+            if intersects(aabb) {
+                found.push(item);
+            }
+        });
+    }
+
+    /// Items whose bounds intersect `frustum`, appended to `visible`.
+    pub fn query_frustum(&self, frustum: &Frustum<S>, visible: &mut Vec<usize>) {
+        self.query(|aabb| frustum.intersects_aabb(aabb), visible);
+    }
+
+    /// Items whose bounds are hit by `ray`, with the distance at which it enters
+    /// them, nearest first.
+    ///
+    /// The bounds only select candidates, the exact hit is left to the caller.
+    pub fn query_ray(&self, ray: &Ray<S>) -> Vec<(usize, S)> {
+        let mut hits = Vec::new();
+        self.visit(
+            |aabb| ray.intersect_aabb(aabb).is_some(),
+            |item, aabb| {
+                if let Some(distance) = ray.intersect_aabb(aabb) {
+                    hits.push((item, distance));
+                }
+            },
+        );
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+
+    /// Call `visit_item` on the items of the leaves whose ancestors all pass `enter`.
+    fn visit<E, V>(&self, enter: E, mut visit_item: V)
+    where
+        E: Fn(&Aabb<S>) -> bool,
+        V: FnMut(usize, &Aabb<S>),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !enter(&node.aabb) {
+                continue;
+            }
+            match node.kind {
+                BvhNodeKind::Leaf { start, count } => {
+                    for item in &self.items[start..start + count] {
+                        visit_item(*item, &self.aabbs[*item]);
+                    }
+                }
+                BvhNodeKind::Internal { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, Vector3};
+
+    /// Unit boxes scattered over a 10 x 10 x 10 volume, in a fixed order.
+    fn scattered_aabbs(count: usize) -> Vec<Aabb<f32>> {
+        (0..count)
+            .map(|index| {
+                let min = Vector3::new(
+                    (index * 7 % 10) as f32,
+                    (index * 3 % 10) as f32,
+                    (index * 11 % 10) as f32,
+                );
+                Aabb::new(min, min + Vector3::new(1.0, 1.0, 1.0))
+            })
+            .collect()
+    }
+
+    fn overlaps(a: &Aabb<f32>, b: &Aabb<f32>) -> bool {
+        (0..3).all(|axis| a.min()[axis] <= b.max()[axis] && b.min()[axis] <= a.max()[axis])
+    }
+
+    fn query_box(bvh: &Bvh<f32>, region: &Aabb<f32>) -> Vec<usize> {
+        let mut found = Vec::new();
+        bvh.query(|aabb| overlaps(aabb, region), &mut found);
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn leaves_hold_every_item_once() {
+        let bvh = Bvh::new(&scattered_aabbs(37));
+
+        let mut items = bvh.items.clone();
+        items.sort_unstable();
+        assert_eq!(items, (0..37).collect::<Vec<_>>());
+
+        for (index, node) in bvh.nodes.iter().enumerate() {
+            match node.kind {
+                BvhNodeKind::Leaf { count, .. } => assert!(count <= MAX_LEAF_ITEMS),
+                BvhNodeKind::Internal { left, right } => {
+                    assert!(left > index && right > index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn query_finds_the_same_items_as_a_linear_search() {
+        let aabbs = scattered_aabbs(50);
+        let bvh = Bvh::new(&aabbs);
+        let regions = [
+            Aabb::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(2.5, 2.5, 2.5)),
+            Aabb::new(Vector3::new(4.2, -1.0, 3.0), Vector3::new(6.0, 11.0, 3.5)),
+            Aabb::new(
+                Vector3::new(20.0, 20.0, 20.0),
+                Vector3::new(21.0, 21.0, 21.0),
+            ),
+        ];
+
+        for region in regions {
+            let expected = (0..aabbs.len())
+                .filter(|item| overlaps(&aabbs[*item], &region))
+                .collect::<Vec<_>>();
+            assert_eq!(query_box(&bvh, &region), expected);
+        }
+    }
+
+    #[test]
+    fn query_ray_sorts_the_hits_by_distance() {
+        let aabbs = (0..10)
+            .map(|index| {
+                let min = Vector3::new(index as f32 * 2.0, 0.0, 0.0);
+                Aabb::new(min, min + Vector3::new(1.0, 1.0, 1.0))
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(&aabbs);
+
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        let hits = bvh.query_ray(&ray);
+        assert_eq!(
+            hits.iter().map(|(item, _)| *item).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        assert_eq!(hits[3].1, 7.0);
+
+        let miss = Ray::new(Point3::new(-1.0, 2.0, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        assert!(bvh.query_ray(&miss).is_empty());
+    }
+
+    #[test]
+    fn refit_follows_the_moved_items() {
+        let aabbs = scattered_aabbs(20);
+        let mut bvh = Bvh::new(&aabbs);
+        let far = Aabb::new(
+            Vector3::new(50.0, 50.0, 50.0),
+            Vector3::new(51.0, 51.0, 51.0),
+        );
+
+        bvh.set_aabb(4, far);
+        bvh.refit();
+
+        assert_eq!(query_box(&bvh, &far), [4]);
+        assert!(!query_box(&bvh, &aabbs[4]).contains(&4));
+        assert_eq!(bvh.aabb(4).min(), far.min());
+    }
+
+    #[test]
+    fn empty_hierarchy_finds_nothing() {
+        let bvh = Bvh::<f32>::new(&[]);
+        let region = Aabb::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0));
+
+        assert!(bvh.is_empty());
+        assert!(query_box(&bvh, &region).is_empty());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(bvh.query_ray(&ray).is_empty());
+    }
+}
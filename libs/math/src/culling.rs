@@ -0,0 +1,80 @@
+use super::Aabb;
+use cgmath::{BaseFloat, InnerSpace, Matrix4, Point3, Vector3, Vector4};
+
+/// The six planes of a view frustum, pointing inwards.
+///
+/// Planes are stored as `(normal, distance)` in a [`Vector4`], a point `p` being
+/// inside a plane if `dot(normal, p) + distance >= 0`.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum<S> {
+    planes: [Vector4<S>; 6],
+}
+
+impl<S: BaseFloat> Frustum<S> {
+    /// Extract the planes of the frustum of `view_proj`, with depth in [0, 1].
+    ///
+    /// Works for both depth modes since only the near and far planes are swapped.
+    pub fn from_view_proj(view_proj: Matrix4<S>) -> Self {
+        let row = |index: usize| {
+            Vector4::new(
+                view_proj.x[index],
+                view_proj.y[index],
+                view_proj.z[index],
+                view_proj.w[index],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    /// True if `aabb` is at least partially inside the frustum.
+    ///
+    /// Conservative: some boxes outside but close to the corners of the frustum pass.
+    pub fn intersects_aabb(&self, aabb: &Aabb<S>) -> bool {
+        let (min, max) = (aabb.min(), aabb.max());
+        self.planes.iter().all(|plane| {
+            // Corner of the box the furthest along the normal of the plane.
+            let corner = Vector3::new(
+                if plane.x >= S::zero() { max.x } else { min.x },
+                if plane.y >= S::zero() { max.y } else { min.y },
+                if plane.z >= S::zero() { max.z } else { min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= S::zero()
+        })
+    }
+}
+
+/// Half line from `origin` along `direction`, for picking.
+#[derive(Copy, Clone, Debug)]
+pub struct Ray<S> {
+    pub origin: Point3<S>,
+    pub direction: Vector3<S>,
+}
+
+impl<S: BaseFloat> Ray<S> {
+    pub fn new(origin: Point3<S>, direction: Vector3<S>) -> Self {
+        Self { origin, direction }
+    }
+
+    /// Distance along the ray, in lengths of `direction`, at which it enters
+    /// `aabb`, 0 if it starts inside and `None` if it misses it.
+    pub fn intersect_aabb(&self, aabb: &Aabb<S>) -> Option<S> {
+        let (min, max) = (aabb.min(), aabb.max());
+        let mut near = S::zero();
+        let mut far = S::infinity();
+        for axis in 0..3 {
+            let inverse = S::one() / self.direction[axis];
+            let t0 = (min[axis] - self.origin[axis]) * inverse;
+            let t1 = (max[axis] - self.origin[axis]) * inverse;
+            let (t0, t1) = if t0 <= t1 { (t0, t1) } else { (t1, t0) };
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
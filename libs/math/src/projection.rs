@@ -0,0 +1,237 @@
+use cgmath::prelude::*;
+use cgmath::{BaseFloat, Matrix4, Rad};
+
+/// How the view depth is mapped to the 0..1 depth range of Vulkan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthMode {
+    /// The near plane at 0 and the far plane at 1.
+    #[default]
+    Standard,
+    /// The near plane at 1 and the far plane at 0, which spreads the precision
+    /// of floating point depth buffers evenly. Requires a `GREATER` depth
+    /// compare op and clearing the depth to 0.
+    Reversed,
+}
+
+/// Perspective matrix that is suitable for Vulkan.
+///
+/// It inverts the projected y-axis. And set the depth range to 0..1
+/// instead of -1..1. Mind the vertex winding order though.
+pub fn perspective<S, F>(fovy: F, aspect: S, near: S, far: S) -> Matrix4<S>
+where
+    S: BaseFloat,
+    F: Into<Rad<S>>,
+{
+    perspective_with(fovy, aspect, near, Some(far), DepthMode::Standard)
+}
+
+/// Perspective matrix that is suitable for Vulkan, see [`perspective`].
+///
+/// Without `far` the far plane is at infinity, so nothing is clipped however far it is.
+#[rustfmt::skip]
+pub fn perspective_with<S, F>(
+    fovy: F,
+    aspect: S,
+    near: S,
+    far: Option<S>,
+    depth_mode: DepthMode,
+) -> Matrix4<S>
+where
+    S: BaseFloat,
+    F: Into<Rad<S>>,
+{
+    let two = S::one() + S::one();
+    let f = Rad::cot(fovy.into() / two);
+
+    let (c2r2, c3r2) = match (depth_mode, far) {
+        (DepthMode::Standard, Some(far)) => (-far / (far - near), -(far * near) / (far - near)),
+        (DepthMode::Standard, None) => (-S::one(), -near),
+        (DepthMode::Reversed, Some(far)) => (near / (far - near), (far * near) / (far - near)),
+        (DepthMode::Reversed, None) => (S::zero(), near),
+    };
+
+    let c0r0 = f / aspect;
+    let c0r1 = S::zero();
+    let c0r2 = S::zero();
+    let c0r3 = S::zero();
+
+    let c1r0 = S::zero();
+    let c1r1 = -f;
+    let c1r2 = S::zero();
+    let c1r3 = S::zero();
+
+    let c2r0 = S::zero();
+    let c2r1 = S::zero();
+    let c2r3 = -S::one();
+
+    let c3r0 = S::zero();
+    let c3r1 = S::zero();
+    let c3r3 = S::zero();
+
+    Matrix4::new(
+        c0r0, c0r1, c0r2, c0r3,
+        c1r0, c1r1, c1r2, c1r3,
+        c2r0, c2r1, c2r2, c2r3,
+        c3r0, c3r1, c3r2, c3r3,
+    )
+}
+
+/// Orthographic matrix that is suitable for Vulkan.
+///
+/// Like [`perspective`], the projected y-axis is inverted and the depth range is 0..1.
+/// The planes are in view space, the camera looking down the negative z axis.
+#[rustfmt::skip]
+pub fn orthographic<S: BaseFloat>(
+    left: S,
+    right: S,
+    bottom: S,
+    top: S,
+    near: S,
+    far: S,
+    depth_mode: DepthMode,
+) -> Matrix4<S> {
+    let two = S::one() + S::one();
+
+    let (c2r2, c3r2) = match depth_mode {
+        DepthMode::Standard => (-S::one() / (far - near), -near / (far - near)),
+        DepthMode::Reversed => (S::one() / (far - near), far / (far - near)),
+    };
+
+    let c0r0 = two / (right - left);
+    let c0r1 = S::zero();
+    let c0r2 = S::zero();
+    let c0r3 = S::zero();
+
+    let c1r0 = S::zero();
+    let c1r1 = -two / (top - bottom);
+    let c1r2 = S::zero();
+    let c1r3 = S::zero();
+
+    let c2r0 = S::zero();
+    let c2r1 = S::zero();
+    let c2r3 = S::zero();
+
+    let c3r0 = -(right + left) / (right - left);
+    let c3r1 = (top + bottom) / (top - bottom);
+    let c3r3 = S::one();
+
+    Matrix4::new(
+        c0r0, c0r1, c0r2, c0r3,
+        c1r0, c1r1, c1r2, c1r3,
+        c2r0, c2r1, c2r2, c2r3,
+        c3r0, c3r1, c3r2, c3r3,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Vector3, Vector4};
+
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 100.0;
+    const EPSILON: f32 = 1e-5;
+
+    /// Normalized device coordinates of a view space `point`.
+    fn project(matrix: Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+        let clip = matrix * Vector4::new(point.x, point.y, point.z, 1.0);
+        clip.truncate() / clip.w
+    }
+
+    fn depth(matrix: Matrix4<f32>, distance: f32) -> f32 {
+        project(matrix, Vector3::new(0.0, 0.0, -distance)).z
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn perspective(far: Option<f32>, depth_mode: DepthMode) -> Matrix4<f32> {
+        perspective_with(Deg(60.0), 1.5, NEAR, far, depth_mode)
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_planes() {
+        let matrix = perspective(Some(FAR), DepthMode::Standard);
+        assert_close(depth(matrix, NEAR), 0.0);
+        assert_close(depth(matrix, FAR), 1.0);
+        assert!(depth(matrix, 1.0) < depth(matrix, 10.0));
+    }
+
+    #[test]
+    fn reversed_perspective_maps_near_and_far_planes() {
+        let matrix = perspective(Some(FAR), DepthMode::Reversed);
+        assert_close(depth(matrix, NEAR), 1.0);
+        assert_close(depth(matrix, FAR), 0.0);
+        assert!(depth(matrix, 1.0) > depth(matrix, 10.0));
+    }
+
+    #[test]
+    fn infinite_perspective_is_the_limit_of_the_far_plane() {
+        for depth_mode in [DepthMode::Standard, DepthMode::Reversed] {
+            let infinite = perspective(None, depth_mode);
+            let far = perspective(Some(1e7), depth_mode);
+            for distance in [NEAR, 1.0, 1000.0] {
+                assert_close(depth(infinite, distance), depth(far, distance));
+            }
+        }
+
+        let infinite = perspective(None, DepthMode::Standard);
+        assert_close(depth(infinite, NEAR), 0.0);
+        let far_depth = depth(infinite, 1e6);
+        assert!(far_depth < 1.0 && far_depth > 1.0 - EPSILON);
+
+        let infinite = perspective(None, DepthMode::Reversed);
+        assert_close(depth(infinite, NEAR), 1.0);
+        let far_depth = depth(infinite, 1e6);
+        assert!(far_depth > 0.0 && far_depth < EPSILON);
+    }
+
+    #[test]
+    fn perspective_remaps_the_opengl_convention() {
+        // cgmath follows OpenGL, y up and depth in -1..1.
+        let opengl = cgmath::perspective(Deg(60.0), 1.5, NEAR, FAR);
+        let vulkan = perspective(Some(FAR), DepthMode::Standard);
+
+        assert_close(depth(opengl, NEAR), -1.0);
+        assert_close(depth(opengl, FAR), 1.0);
+
+        for point in [
+            Vector3::new(0.5, 0.25, -1.0),
+            Vector3::new(-2.0, 3.0, -10.0),
+            Vector3::new(1.0, -1.0, -FAR),
+        ] {
+            let expected = project(opengl, point);
+            let actual = project(vulkan, point);
+            assert_close(actual.x, expected.x);
+            assert_close(actual.y, -expected.y);
+            assert_close(actual.z, (expected.z + 1.0) / 2.0);
+        }
+    }
+
+    #[test]
+    fn orthographic_maps_the_view_volume() {
+        let matrix = orthographic(-2.0, 4.0, -1.0, 3.0, NEAR, FAR, DepthMode::Standard);
+        let corner = project(matrix, Vector3::new(-2.0, 3.0, -NEAR));
+        assert_close(corner.x, -1.0);
+        assert_close(corner.y, -1.0);
+        assert_close(corner.z, 0.0);
+
+        let corner = project(matrix, Vector3::new(4.0, -1.0, -FAR));
+        assert_close(corner.x, 1.0);
+        assert_close(corner.y, 1.0);
+        assert_close(corner.z, 1.0);
+    }
+
+    #[test]
+    fn reversed_orthographic_maps_near_and_far_planes() {
+        let matrix = orthographic(-1.0, 1.0, -1.0, 1.0, NEAR, FAR, DepthMode::Reversed);
+        assert_close(depth(matrix, NEAR), 1.0);
+        assert_close(depth(matrix, FAR), 0.0);
+    }
+}
@@ -0,0 +1,127 @@
+use super::{orthographic, partial_max, partial_min, Aabb, DepthMode};
+use cgmath::prelude::*;
+use cgmath::{BaseFloat, Matrix4, Point3, Vector3, Vector4};
+
+/// View and projection of a directional light shadow map.
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowProjection<S> {
+    pub view: Matrix4<S>,
+    pub proj: Matrix4<S>,
+}
+
+impl<S: BaseFloat> ShadowProjection<S> {
+    pub fn view_proj(&self) -> Matrix4<S> {
+        self.proj * self.view
+    }
+}
+
+/// Split distances of `count` cascades between `near` and `far`.
+///
+/// `lambda` blends between uniform (0) and logarithmic (1) splits.
+/// The last split is always `far`.
+pub fn cascade_splits<S: BaseFloat>(near: S, far: S, count: usize, lambda: S) -> Vec<S> {
+    let count_s = S::from(count).unwrap();
+    (1..=count)
+        .map(|i| {
+            let ratio = S::from(i).unwrap() / count_s;
+            let log = near * (far / near).powf(ratio);
+            let uniform = near + (far - near) * ratio;
+            lambda * log + (S::one() - lambda) * uniform
+        })
+        .collect()
+}
+
+/// World space corners of the frustum of `view_proj`, the near ones first.
+///
+/// Works for both depth modes since only the depth order of the corners changes.
+pub fn frustum_corners<S: BaseFloat>(view_proj: Matrix4<S>) -> [Vector3<S>; 8] {
+    let inverted = view_proj.invert().unwrap_or_else(Matrix4::identity);
+    let one = S::one();
+    let mut corners = [Vector3::zero(); 8];
+    for (index, corner) in corners.iter_mut().enumerate() {
+        let x = if index & 1 == 0 { -one } else { one };
+        let y = if index & 2 == 0 { -one } else { one };
+        let z = if index & 4 == 0 { S::zero() } else { one };
+        let corner_h = inverted * Vector4::new(x, y, z, one);
+        *corner = corner_h.truncate() / corner_h.w;
+    }
+    corners
+}
+
+/// Corners of the slice of a frustum between the view distances `slice_near` and `slice_far`.
+///
+/// `corners` are the ones returned by [`frustum_corners`] for a camera whose
+/// planes are at `near` and `far`, for example one cascade of a shadow map.
+pub fn frustum_slice_corners<S: BaseFloat>(
+    corners: &[Vector3<S>; 8],
+    near: S,
+    far: S,
+    slice_near: S,
+    slice_far: S,
+) -> [Vector3<S>; 8] {
+    let start = (slice_near - near) / (far - near);
+    let end = (slice_far - near) / (far - near);
+    let mut slice = *corners;
+    for index in 0..4 {
+        let edge = corners[index + 4] - corners[index];
+        slice[index] = corners[index] + edge * start;
+        slice[index + 4] = corners[index] + edge * end;
+    }
+    slice
+}
+
+/// Fit the orthographic projection of a directional light around a frustum slice.
+///
+/// `light_direction` points from the light toward the scene. The projection
+/// tightly bounds `slice_corners` but is clipped to `scene` on the sides when given,
+/// and its near plane is pulled back so every caster of `scene` still lands in the map.
+pub fn fit_directional_shadow<S: BaseFloat>(
+    light_direction: Vector3<S>,
+    slice_corners: &[Vector3<S>; 8],
+    scene: Option<Aabb<S>>,
+    depth_mode: DepthMode,
+) -> ShadowProjection<S> {
+    let direction = light_direction.normalize();
+    let up = if direction.y.abs() > S::from(0.99).unwrap() {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let view = Matrix4::look_to_rh(Point3::new(S::zero(), S::zero(), S::zero()), direction, up);
+
+    let to_light = |points: &[Vector3<S>]| -> Vec<Vector3<S>> {
+        points
+            .iter()
+            .map(|p| (view * p.extend(S::one())).truncate())
+            .collect()
+    };
+    let slice = to_light(slice_corners);
+    let bounds = |points: &[Vector3<S>]| {
+        let min = Vector3::new(
+            partial_min(points.iter().map(|p| p.x)).unwrap(),
+            partial_min(points.iter().map(|p| p.y)).unwrap(),
+            partial_min(points.iter().map(|p| p.z)).unwrap(),
+        );
+        let max = Vector3::new(
+            partial_max(points.iter().map(|p| p.x)).unwrap(),
+            partial_max(points.iter().map(|p| p.y)).unwrap(),
+            partial_max(points.iter().map(|p| p.z)).unwrap(),
+        );
+        (min, max)
+    };
+    let (mut min, mut max) = bounds(&slice);
+
+    if let Some(scene) = scene {
+        let (scene_min, scene_max) = bounds(&to_light(&scene.get_corners()));
+        min.x = min.x.max(scene_min.x);
+        min.y = min.y.max(scene_min.y);
+        max.x = max.x.min(scene_max.x);
+        max.y = max.y.min(scene_max.y);
+        // The light looks down -z so the casters closest to it have the largest z.
+        max.z = max.z.max(scene_max.z);
+        min.z = min.z.max(scene_min.z);
+    }
+
+    let proj = orthographic(min.x, max.x, min.y, max.y, -max.z, -min.z, depth_mode);
+    ShadowProjection { view, proj }
+}
@@ -0,0 +1,492 @@
+use crate::find_memory_type;
+use ash::{vk, Device, Instance};
+use std::{ffi::c_void, fmt, sync::Mutex};
+
+/// Size of the blocks of device memory the allocations are carved from.
+pub const DEFAULT_MEMORY_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Mapped pointer of a block, only dereferenced by the owner of an allocation.
+struct MappedPointer(*mut c_void);
+unsafe impl Send for MappedPointer {}
+
+/// Device memory shared by several allocations.
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type: u32,
+    size: vk::DeviceSize,
+    /// Free ranges as offset and size, sorted by offset and never adjacent.
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+    allocation_count: usize,
+    /// Blocks of host visible memory stay mapped for their whole life.
+    mapped_pointer: Option<MappedPointer>,
+}
+
+impl MemoryBlock {
+    /// Find and reserve a range of `size` bytes aligned to `alignment`.
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (index, offset) =
+            self.free_ranges
+                .iter()
+                .enumerate()
+                .find_map(|(index, &(start, range_size))| {
+                    let offset = align(start, alignment);
+                    (offset + size <= start + range_size).then_some((index, offset))
+                })?;
+
+        let (start, range_size) = self.free_ranges.remove(index);
+        let end = start + range_size;
+        if offset + size < end {
+            self.free_ranges
+                .insert(index, (offset + size, end - offset - size));
+        }
+        if start < offset {
+            self.free_ranges.insert(index, (start, offset - start));
+        }
+        self.allocation_count += 1;
+        Some(offset)
+    }
+
+    /// Release the range of `size` bytes at `offset`, merging it with its free neighbours.
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .free_ranges
+            .partition_point(|&(start, _)| start < offset);
+        self.free_ranges.insert(index, (offset, size));
+
+        if index + 1 < self.free_ranges.len() {
+            let (next_start, next_size) = self.free_ranges[index + 1];
+            if offset + size == next_start {
+                self.free_ranges[index].1 += next_size;
+                self.free_ranges.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (previous_start, previous_size) = self.free_ranges[index - 1];
+            if previous_start + previous_size == offset {
+                self.free_ranges[index - 1].1 += self.free_ranges[index].1;
+                self.free_ranges.remove(index);
+            }
+        }
+        self.allocation_count -= 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.allocation_count == 0
+    }
+
+    fn free_size(&self) -> vk::DeviceSize {
+        self.free_ranges.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// Memory of a buffer or an image, see [`MemoryAllocator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    /// Requested size.
+    size: vk::DeviceSize,
+    /// Reserved size, the requested size aligned to the granularity of the blocks.
+    reserved_size: vk::DeviceSize,
+    memory_type: u32,
+    /// `None` for dedicated allocations.
+    block: Option<usize>,
+}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Offset of the allocation in [`Allocation::memory`], to bind the resources at.
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn memory_type(&self) -> u32 {
+        self.memory_type
+    }
+
+    /// True if the allocation has its own device memory.
+    pub fn is_dedicated(&self) -> bool {
+        self.block.is_none()
+    }
+}
+
+/// Usage of the device memory of a [`MemoryAllocator`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub blocks: usize,
+    pub block_bytes: vk::DeviceSize,
+    /// Allocations carved from the blocks.
+    pub suballocations: usize,
+    /// Bytes of the blocks reserved by the suballocations.
+    pub suballocated_bytes: vk::DeviceSize,
+    pub dedicated_allocations: usize,
+    pub dedicated_bytes: vk::DeviceSize,
+}
+
+impl MemoryStats {
+    /// Number of `vkAllocateMemory` allocations alive, bounded by
+    /// `maxMemoryAllocationCount`.
+    pub fn device_allocations(&self) -> usize {
+        self.blocks + self.dedicated_allocations
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MIB: vk::DeviceSize = 1024 * 1024;
+        write!(
+            f,
+            "{} allocations in {} blocks ({}/{} MiB used), {} dedicated ({} MiB)",
+            self.suballocations,
+            self.blocks,
+            self.suballocated_bytes / MIB,
+            self.block_bytes / MIB,
+            self.dedicated_allocations,
+            self.dedicated_bytes / MIB
+        )
+    }
+}
+
+#[derive(Default)]
+struct AllocatorState {
+    /// Destroyed blocks leave a `None` so the indices of the others stay valid.
+    blocks: Vec<Option<MemoryBlock>>,
+    dedicated_allocations: usize,
+    dedicated_bytes: vk::DeviceSize,
+}
+
+/// Allocator of the device memory of the buffers and images, shared by the
+/// contexts of a device.
+///
+/// Allocating device memory for each resource quickly reaches the limit of
+/// allocations of the device and fragments the memory, so resources are
+/// placed in large blocks of each memory type instead. Free ranges of a
+/// block are kept in a list and merged when released. Resources larger than
+/// half a block get their own allocation. Empty blocks are released, except
+/// the last one of their memory type so short lived resources do not
+/// allocate a block each time.
+///
+/// Blocks of host visible memory are mapped once for their whole life, use
+/// [`MemoryAllocator::map`] instead of mapping the memory of an allocation.
+pub struct MemoryAllocator {
+    state: Mutex<AllocatorState>,
+    mem_properties: vk::PhysicalDeviceMemoryProperties,
+    block_size: vk::DeviceSize,
+    /// Alignment of the suballocations, so linear and optimal resources never share a page.
+    granularity: vk::DeviceSize,
+}
+
+impl MemoryAllocator {
+    pub(crate) fn new(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mem_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        Self {
+            state: Mutex::new(AllocatorState::default()),
+            mem_properties,
+            block_size: DEFAULT_MEMORY_BLOCK_SIZE,
+            granularity: properties.limits.buffer_image_granularity.max(1),
+        }
+    }
+}
+
+impl MemoryAllocator {
+    /// Allocate memory for a resource with `requirements` in a memory type
+    /// with `mem_properties`.
+    pub fn allocate(
+        &self,
+        device: &Device,
+        requirements: vk::MemoryRequirements,
+        mem_properties: vk::MemoryPropertyFlags,
+    ) -> Allocation {
+        let memory_type = find_memory_type(requirements, self.mem_properties, mem_properties);
+        let mut state = self.state.lock().unwrap();
+
+        let reserved_size = align(requirements.size, self.granularity);
+        if reserved_size > self.block_size / 2 {
+            let memory = allocate_device_memory(device, requirements.size, memory_type);
+            state.dedicated_allocations += 1;
+            state.dedicated_bytes += requirements.size;
+            return Allocation {
+                memory,
+                offset: 0,
+                size: requirements.size,
+                reserved_size: requirements.size,
+                memory_type,
+                block: None,
+            };
+        }
+
+        let alignment = requirements.alignment.max(self.granularity);
+        let found = state
+            .blocks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, block)| Some((index, block.as_mut()?)))
+            .filter(|(_, block)| block.memory_type == memory_type)
+            .find_map(|(index, block)| {
+                block
+                    .allocate(reserved_size, alignment)
+                    .map(|offset| (index, block.memory, offset))
+            });
+        let (block_index, memory, offset) = found.unwrap_or_else(|| {
+            let mut block = self.create_block(device, memory_type);
+            let offset = block
+                .allocate(reserved_size, alignment)
+                .expect("Failed to allocate memory from a new block");
+            let memory = block.memory;
+            let index = match state.blocks.iter().position(Option::is_none) {
+                Some(index) => {
+                    state.blocks[index] = Some(block);
+                    index
+                }
+                None => {
+                    state.blocks.push(Some(block));
+                    state.blocks.len() - 1
+                }
+            };
+            (index, memory, offset)
+        });
+
+        Allocation {
+            memory,
+            offset,
+            size: requirements.size,
+            reserved_size,
+            memory_type,
+            block: Some(block_index),
+        }
+    }
+
+    fn create_block(&self, device: &Device, memory_type: u32) -> MemoryBlock {
+        let memory = allocate_device_memory(device, self.block_size, memory_type);
+        let host_visible = self.mem_properties.memory_types[memory_type as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped_pointer = host_visible.then(|| unsafe {
+            let pointer = device
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .expect("Failed to map memory");
+            MappedPointer(pointer)
+        });
+        tracing::debug!(
+            "Memory block of {} MiB allocated for memory type {}",
+            self.block_size / (1024 * 1024),
+            memory_type
+        );
+
+        MemoryBlock {
+            memory,
+            memory_type,
+            size: self.block_size,
+            free_ranges: vec![(0, self.block_size)],
+            allocation_count: 0,
+            mapped_pointer,
+        }
+    }
+
+    /// Release `allocation`. The resources bound to it must be destroyed.
+    pub fn free(&self, device: &Device, allocation: Allocation) {
+        let mut state = self.state.lock().unwrap();
+        let Some(block_index) = allocation.block else {
+            unsafe { device.free_memory(allocation.memory, None) };
+            state.dedicated_allocations -= 1;
+            state.dedicated_bytes -= allocation.size;
+            return;
+        };
+
+        let block = state.blocks[block_index]
+            .as_mut()
+            .expect("Allocation freed from a destroyed block");
+        block.free(allocation.offset, allocation.reserved_size);
+        if !block.is_empty() {
+            return;
+        }
+
+        let memory_type = block.memory_type;
+        let other_blocks = state
+            .blocks
+            .iter()
+            .flatten()
+            .filter(|block| block.memory_type == memory_type)
+            .count()
+            - 1;
+        if other_blocks > 0 {
+            if let Some(block) = state.blocks[block_index].take() {
+                destroy_block(device, block);
+            }
+        }
+    }
+
+    /// Host pointer to the memory of `allocation`, which must be host visible.
+    ///
+    /// Blocks stay mapped so this is free for suballocations. Dedicated
+    /// allocations are mapped and must be unmapped with [`MemoryAllocator::unmap`].
+    pub fn map(&self, device: &Device, allocation: &Allocation) -> *mut c_void {
+        let Some(block_index) = allocation.block else {
+            return unsafe {
+                device
+                    .map_memory(
+                        allocation.memory,
+                        0,
+                        allocation.size,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map memory")
+            };
+        };
+
+        let state = self.state.lock().unwrap();
+        let pointer = state.blocks[block_index]
+            .as_ref()
+            .and_then(|block| block.mapped_pointer.as_ref())
+            .expect("Failed to map memory that is not host visible");
+        unsafe { pointer.0.add(allocation.offset as _) }
+    }
+
+    /// Unmap the memory of `allocation` mapped with [`MemoryAllocator::map`].
+    pub fn unmap(&self, device: &Device, allocation: &Allocation) {
+        if allocation.is_dedicated() {
+            unsafe { device.unmap_memory(allocation.memory) };
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let state = self.state.lock().unwrap();
+        let blocks = state.blocks.iter().flatten();
+        MemoryStats {
+            blocks: blocks.clone().count(),
+            block_bytes: blocks.clone().map(|block| block.size).sum(),
+            suballocations: blocks.clone().map(|block| block.allocation_count).sum(),
+            suballocated_bytes: blocks.map(|block| block.size - block.free_size()).sum(),
+            dedicated_allocations: state.dedicated_allocations,
+            dedicated_bytes: state.dedicated_bytes,
+        }
+    }
+
+    pub(crate) fn destroy(&self, device: &Device) {
+        let mut state = self.state.lock().unwrap();
+        for block in state.blocks.drain(..).flatten() {
+            if !block.is_empty() {
+                tracing::error!(
+                    "Memory block destroyed with {} live allocations",
+                    block.allocation_count
+                );
+            }
+            destroy_block(device, block);
+        }
+    }
+}
+
+fn allocate_device_memory(
+    device: &Device,
+    size: vk::DeviceSize,
+    memory_type: u32,
+) -> vk::DeviceMemory {
+    let alloc_info = vk::MemoryAllocateInfo::default()
+        .allocation_size(size)
+        .memory_type_index(memory_type);
+    unsafe {
+        device
+            .allocate_memory(&alloc_info, None)
+            .expect("Failed to allocate memory")
+    }
+}
+
+fn destroy_block(device: &Device, block: MemoryBlock) {
+    unsafe {
+        if block.mapped_pointer.is_some() {
+            device.unmap_memory(block.memory);
+        }
+        device.free_memory(block.memory, None);
+    }
+}
+
+fn align(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: vk::DeviceSize) -> MemoryBlock {
+        MemoryBlock {
+            memory: vk::DeviceMemory::null(),
+            memory_type: 0,
+            size,
+            free_ranges: vec![(0, size)],
+            allocation_count: 0,
+            mapped_pointer: None,
+        }
+    }
+
+    #[test]
+    fn aligns_up() {
+        assert_eq!(align(0, 256), 0);
+        assert_eq!(align(1, 256), 256);
+        assert_eq!(align(256, 256), 256);
+        assert_eq!(align(257, 16), 272);
+    }
+
+    #[test]
+    fn allocations_are_aligned_and_keep_the_padding_free() {
+        let mut block = block(1024);
+
+        assert_eq!(block.allocate(100, 1), Some(0));
+        assert_eq!(block.allocate(100, 256), Some(256));
+        assert_eq!(block.free_ranges, [(100, 156), (356, 668)]);
+        assert_eq!(block.free_size(), 1024 - 200);
+
+        // The padding is reused by the allocations fitting in it.
+        assert_eq!(block.allocate(64, 4), Some(100));
+        assert_eq!(block.free_ranges, [(164, 92), (356, 668)]);
+        assert_eq!(block.allocation_count, 3);
+    }
+
+    #[test]
+    fn free_merges_the_neighbour_ranges() {
+        let mut block = block(1024);
+        let offsets = [256, 256, 256, 256].map(|size| block.allocate(size, 1).unwrap());
+        assert_eq!(offsets, [0, 256, 512, 768]);
+        assert!(block.free_ranges.is_empty());
+
+        block.free(0, 256);
+        block.free(512, 256);
+        assert_eq!(block.free_ranges, [(0, 256), (512, 256)]);
+
+        // Merged with both the previous and the next range.
+        block.free(256, 256);
+        assert_eq!(block.free_ranges, [(0, 768)]);
+
+        block.free(768, 256);
+        assert_eq!(block.free_ranges, [(0, 1024)]);
+        assert!(block.is_empty());
+    }
+
+    #[test]
+    fn fragmented_blocks_fail_until_the_ranges_merge() {
+        let mut block = block(1024);
+        let offsets = [256, 256, 256, 256].map(|size| block.allocate(size, 1).unwrap());
+        block.free(offsets[0], 256);
+        block.free(offsets[2], 256);
+
+        assert_eq!(block.free_size(), 512);
+        assert_eq!(block.allocate(512, 1), None);
+
+        block.free(offsets[1], 256);
+        assert_eq!(block.allocate(512, 1), Some(0));
+        assert_eq!(block.free_ranges, [(512, 256)]);
+    }
+}
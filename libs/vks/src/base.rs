@@ -1,58 +1,146 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use ash::{vk::{self, RenderingAttachmentInfo, RenderingInfo}, Device};
-use egui::TextureId;
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+#[cfg(feature = "winit")]
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+#[cfg(feature = "winit")]
 use winit::window::Window;
 
 use crate::{
-    allocate_command_buffers, cmd_transition_images_layouts, create_sampler, create_scene_color,
-    create_scene_depth, create_sync_objects, find_depth_format, in_flight_frames::InFlightFrames,
-    Camera, Context, Image, ImageParameters, LayoutTransition, MipsRange, Swapchain,
-    SwapchainSupportDetails, Texture, HDR_SURFACE_FORMAT,
+    allocate_command_buffers, check_buffer_limits, cmd_transition_images_layouts, create_sampler,
+    create_scene_color, create_scene_depth, create_sync_objects, find_depth_format_for,
+    find_scene_color_format, in_flight_frames::InFlightFrames, Camera, CommandBufferCache, Context,
+    DepthFormatRequest, FixedTimestep, FpsLimiter, Image, ImageParameters, LayoutTransition,
+    MipsRange, PresentConfig, RendererSettings, Swapchain, SwapchainSupportDetails, Texture,
+    VksError, HDR_SURFACE_FORMAT, SCENE_COLOR_USAGE,
 };
 
+/// Why a frame could not be rendered, see [`crate::WindowApp::render`].
+#[derive(Debug)]
 pub enum RenderError {
+    /// The swapchain is out of date or suboptimal and must be recreated.
     DirtySwapchain,
+    /// No swapchain image was available before the acquire timeout, the
+    /// frame is skipped and can be rendered again later.
+    Timeout,
+    /// The frame failed and can't be recovered by recreating the swapchain.
+    Vks(VksError),
 }
 
+impl From<VksError> for RenderError {
+    fn from(error: VksError) -> Self {
+        Self::Vks(error)
+    }
+}
+
+/// Default of [`VulkanExampleBase::acquire_timeout`].
+pub const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Swapchain and per frame objects shared by the examples.
+///
+/// Dropping it waits for the device to be idle and destroys the swapchain.
+/// Its context must outlive anything of the example holding raw handles, so
+/// the structs of the examples declare those fields before `base` to drop
+/// them first.
 pub struct VulkanExampleBase {
     pub context: Arc<Context>,
     pub swapchain: Swapchain,
     pub command_buffers: Vec<vk::CommandBuffer>,
+    pub command_cache: CommandBufferCache,
     pub in_flight_frames: InFlightFrames,
     pub depth_format: vk::Format,
+    /// Negotiated format of `scene_color`, for the pipelines drawing in it.
+    pub scene_color_format: vk::Format,
     pub msaa_samples: vk::SampleCountFlags,
     pub scene_color: Texture,
     pub scene_depth: Texture,
+    /// How long [`VulkanExampleBase::acquire_next_image`] waits for an image
+    /// before skipping the frame, `None` to wait forever. `Some(Duration::ZERO)`
+    /// only renders when an image is ready.
+    pub acquire_timeout: Option<Duration>,
+    /// Fixed rate of the animations of the examples, decoupled from the render rate.
+    pub simulation: FixedTimestep,
+    /// Cap of the frame rate of the examples, unlimited by default.
+    pub fps_limiter: FpsLimiter,
 }
 
 impl VulkanExampleBase {
+    #[cfg(feature = "winit")]
     pub fn new(window: &Window,enable_debug: bool) -> Self {
-        let context = Arc::new(Context::new(window, enable_debug));
+        Self::with_depth_format(window, enable_debug, DepthFormatRequest::default())
+    }
+
+    /// Create the base with the depth format of the scene and the pipelines picked for `depth_request`.
+    #[cfg(feature = "winit")]
+    pub fn with_depth_format(
+        window: &Window,
+        enable_debug: bool,
+        depth_request: DepthFormatRequest,
+    ) -> Self {
+        unsafe {
+            Self::from_raw_handles(
+                window.display_handle().unwrap().as_raw(),
+                window.window_handle().unwrap().as_raw(),
+                window.inner_size().into(),
+                enable_debug,
+                depth_request,
+            )
+            .expect("Failed to create base")
+        }
+    }
+
+    /// Create the base for a window owned by the host application, whose
+    /// drawable area is `extent` pixels.
+    ///
+    /// Fails if the device, the surface or the swapchain can't be created.
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid and the window must outlive the base.
+    pub unsafe fn from_raw_handles(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        extent: [u32; 2],
+        enable_debug: bool,
+        depth_request: DepthFormatRequest,
+    ) -> Result<Self, VksError> {
+        let context = Arc::new(Context::from_raw_handles(
+            display_handle,
+            window_handle,
+            enable_debug,
+        )?);
+        check_buffer_limits(&context);
         let swapchain_support_details = SwapchainSupportDetails::new(
             context.physical_device(),
             context.surface(),
             context.surface_khr(),
         );
         // let resolution = [800, 600];
-        let depth_format = find_depth_format(&context);
-        let msaa_samples = vk::SampleCountFlags::TYPE_4;
-        window.inner_size();
+        let depth_format = find_depth_format_for(&context, depth_request);
+        let scene_color_format = find_scene_color_format(&context, SCENE_COLOR_USAGE);
+        let msaa_samples = RendererSettings::default().validated(&context).sample_count();
         let swapchain = Swapchain::create(
             Arc::clone(&context),
             swapchain_support_details,
-            window.inner_size().into(),
+            extent,
             Some(vk::SurfaceFormatKHR {
                 format: vk::Format::R16G16B16A16_SFLOAT,
                 color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
             }),
-            true,
-        );
+            &PresentConfig::default(),
+        )?;
 
         let command_buffers = allocate_command_buffers(&context, swapchain.image_count());
+        let command_cache = CommandBufferCache::new(Arc::clone(&context), command_buffers.len());
 
         let in_flight_frames = create_sync_objects(&context);
-        let scene_color = create_scene_color(&context, swapchain.properties().extent, msaa_samples);
+        let scene_color = create_scene_color(
+            &context,
+            scene_color_format,
+            swapchain.properties().extent,
+            msaa_samples,
+        );
         let scene_depth = create_scene_depth(
             &context,
             depth_format,
@@ -60,51 +148,106 @@ impl VulkanExampleBase {
             msaa_samples,
         );
 
-        Self {
+        Ok(Self {
             context,
             swapchain,
             command_buffers,
+            command_cache,
             in_flight_frames,
             depth_format,
+            scene_color_format,
             msaa_samples,
             scene_color,
             scene_depth,
-        }
+            acquire_timeout: Some(DEFAULT_ACQUIRE_TIMEOUT),
+            simulation: FixedTimestep::default(),
+            fps_limiter: FpsLimiter::default(),
+        })
     }
     pub fn destroy_swapchain(&mut self) {
-        unsafe {
-            self.context
-                .device()
-                .free_command_buffers(self.context.general_command_pool(), &self.command_buffers);
+        if !self.command_buffers.is_empty() {
+            unsafe {
+                self.context.device().free_command_buffers(
+                    self.context.general_command_pool(),
+                    &self.command_buffers,
+                );
+            }
+            self.command_buffers.clear();
         }
         self.swapchain.destroy();
     }
+    /// Recreate the targets of the scene, the previous ones go to the [`crate::DeletionQueue`].
     pub fn on_new_swapchain(&mut self) {
         let swapchain_properties = self.swapchain.properties();
-        self.scene_color = create_scene_color(
+        let scene_color = create_scene_color(
             &self.context,
+            self.scene_color_format,
             swapchain_properties.extent,
             self.msaa_samples,
         );
-        self.scene_depth = create_scene_depth(
+        let scene_depth = create_scene_depth(
             &self.context,
             self.depth_format,
             swapchain_properties.extent,
             self.msaa_samples,
         );
+        let deletion_queue = self.context.deletion_queue();
+        deletion_queue.enqueue(std::mem::replace(&mut self.scene_color, scene_color));
+        deletion_queue.enqueue(std::mem::replace(&mut self.scene_depth, scene_depth));
+    }
+
+    /// Acquire the next image of the swapchain, signaling `semaphore` when it
+    /// can be rendered to.
+    ///
+    /// Gives up after `acquire_timeout` with [`RenderError::Timeout`] so that a
+    /// stalled compositor doesn't block the event loop.
+    pub fn acquire_next_image(&self, semaphore: vk::Semaphore) -> Result<u32, RenderError> {
+        let timeout = self
+            .acquire_timeout
+            .map(|timeout| u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+        match self
+            .swapchain
+            .acquire_next_image(timeout, Some(semaphore), None)
+        {
+            Ok((image_index, _)) => Ok(image_index),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RenderError::DirtySwapchain),
+            Err(vk::Result::TIMEOUT | vk::Result::NOT_READY) => {
+                tracing::debug!("No swapchain image available, skipping frame.");
+                Err(RenderError::Timeout)
+            }
+            Err(error) => Err(RenderError::Vks(error.into())),
+        }
     }
 
+    /// Wait for the device to be idle. Does not panic if the device was lost.
     pub fn wait_idle_gpu(&self) {
-        unsafe { self.context.device().device_wait_idle().unwrap() };
+        self.context.wait_idle();
     }
 
+    /// Recreate the swapchain and the targets of the scene without waiting for
+    /// the device to be idle, the previous ones go to the [`crate::DeletionQueue`].
     pub fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool) {
+        self.recreate_swapchain_with_config(dimensions, &PresentConfig::vsync(vsync), hdr);
+    }
+
+    /// Like [`VulkanExampleBase::recreate_swapchain`], presenting according to `present_config`.
+    pub fn recreate_swapchain_with_config(
+        &mut self,
+        dimensions: [u32; 2],
+        present_config: &PresentConfig,
+        hdr: bool,
+    ) {
         tracing::debug!("Recreating swapchain.");
         tracing::debug!("extent: {:?}", dimensions);
 
-        self.wait_idle_gpu();
-
-        self.destroy_swapchain();
+        let deletion_queue = self.context.deletion_queue();
+        let command_pool = self.context.general_command_pool();
+        let command_buffers = std::mem::take(&mut self.command_buffers);
+        if !command_buffers.is_empty() {
+            deletion_queue.enqueue_destroy(move |device| unsafe {
+                device.free_command_buffers(command_pool, &command_buffers)
+            });
+        }
 
         let swapchain_support_details = SwapchainSupportDetails::new(
             self.context.physical_device(),
@@ -112,18 +255,28 @@ impl VulkanExampleBase {
             self.context.surface_khr(),
         );
 
-        self.swapchain = Swapchain::create(
-            Arc::clone(&self.context),
+        let swapchain = self.swapchain.recreate(
             swapchain_support_details,
             dimensions,
             hdr.then_some(HDR_SURFACE_FORMAT),
-            vsync,
-        );
+            present_config,
+        )
+        .expect("Failed to recreate swapchain");
+        deletion_queue.enqueue(std::mem::replace(&mut self.swapchain, swapchain));
 
         self.on_new_swapchain();
 
         self.command_buffers =
             allocate_command_buffers(&self.context, self.swapchain.image_count());
+        self.command_cache.reset(self.command_buffers.len());
     }
 
 }
+
+impl Drop for VulkanExampleBase {
+    fn drop(&mut self) {
+        self.wait_idle_gpu();
+        self.context.deletion_queue().flush(self.context.device());
+        self.destroy_swapchain();
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{Context, Texture, VksError};
+use ash::{ext, khr, vk, Entry, Instance};
+use std::{
+    ffi::CStr,
+    sync::{Arc, Mutex},
+};
+
+/// Binding of the texture array in the set of a [`TextureRegistry`], see
+/// `shader/bindless/bindless.glsl`.
+pub const BINDLESS_TEXTURES_BINDING: u32 = 0;
+
+/// Capacity of a [`TextureRegistry`] created with [`TextureRegistry::new`],
+/// before clamping to the limits of the device.
+pub const DEFAULT_BINDLESS_CAPACITY: u32 = 4096;
+
+/// Check that `physical_device` supports VK_EXT_descriptor_indexing with the
+/// features enabled by [`bindless_features`].
+pub(crate) fn query_bindless_support(
+    entry: &Entry,
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let extension_props = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .expect("Failed to enumerate device extention properties")
+    };
+    let has_extension = extension_props.iter().any(|props| {
+        let ext_name = unsafe { CStr::from_ptr(props.extension_name.as_ptr()) };
+        ext_name == ext::descriptor_indexing::NAME
+    });
+    if !has_extension {
+        return false;
+    }
+
+    let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut indexing_features);
+    unsafe { properties2.get_physical_device_features2(physical_device, &mut features) };
+
+    indexing_features.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+        && indexing_features.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+        && indexing_features.descriptor_binding_update_unused_while_pending == vk::TRUE
+        && indexing_features.descriptor_binding_partially_bound == vk::TRUE
+        && indexing_features.descriptor_binding_variable_descriptor_count == vk::TRUE
+        && indexing_features.runtime_descriptor_array == vk::TRUE
+}
+
+/// Features of VK_EXT_descriptor_indexing used by the [`TextureRegistry`].
+pub(crate) fn bindless_features<'a>() -> vk::PhysicalDeviceDescriptorIndexingFeatures<'a> {
+    vk::PhysicalDeviceDescriptorIndexingFeatures::default()
+        .shader_sampled_image_array_non_uniform_indexing(true)
+        .descriptor_binding_sampled_image_update_after_bind(true)
+        .descriptor_binding_update_unused_while_pending(true)
+        .descriptor_binding_partially_bound(true)
+        .descriptor_binding_variable_descriptor_count(true)
+        .runtime_descriptor_array(true)
+}
+
+/// Descriptor set holding a variable count array of combined image samplers
+/// indexed by the shaders with the u32 handles given when registering textures.
+///
+/// The array is partially bound so only the registered slots must be valid,
+/// and updated after bind so textures can be registered while previous frames
+/// using the set are still in flight. A released handle is only given again
+/// once the frames that could sample it are complete.
+///
+/// The registry does not own the textures, they must outlive their
+/// registration and the frames in flight when released.
+///
+/// ```ignore
+/// let handle = registry.register_texture(&texture).unwrap();
+/// // The shaders sample `bindlessTextures[handle]`.
+/// registry.cmd_bind(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline_layout, 1);
+/// // ...
+/// registry.release(handle);
+/// context.deletion_queue().enqueue(texture);
+/// ```
+pub struct TextureRegistry {
+    context: Arc<Context>,
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    capacity: u32,
+    /// Number of slots ever handed out, the next new handle.
+    allocated: u32,
+    /// Released slots whose frames are complete.
+    free: Arc<Mutex<Vec<u32>>>,
+}
+
+impl TextureRegistry {
+    /// Create a registry of [`DEFAULT_BINDLESS_CAPACITY`] textures.
+    pub fn new(context: Arc<Context>) -> Result<Self, VksError> {
+        Self::with_capacity(context, DEFAULT_BINDLESS_CAPACITY)
+    }
+
+    /// Create a registry of up to `capacity` textures, fewer if the device
+    /// limits are lower.
+    ///
+    /// Fails with [`VksError::UnsupportedFeature`] if the device lacks
+    /// descriptor indexing, see [`Context::has_bindless_support`].
+    pub fn with_capacity(context: Arc<Context>, capacity: u32) -> Result<Self, VksError> {
+        if !context.has_bindless_support() {
+            return Err(VksError::UnsupportedFeature(
+                "VK_EXT_descriptor_indexing".to_owned(),
+            ));
+        }
+        let capacity = capacity.min(max_bindless_textures(&context)).max(1);
+        let device = context.device();
+
+        let bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(BINDLESS_TEXTURES_BINDING)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(capacity)
+            .stage_flags(vk::ShaderStageFlags::ALL)];
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING];
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&bindings)
+            .push_next(&mut binding_flags_info);
+        let layout = unsafe { device.create_descriptor_set_layout(&layout_info, None)? };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: capacity,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(pool) => pool,
+            Err(error) => {
+                unsafe { device.destroy_descriptor_set_layout(layout, None) };
+                return Err(error.into());
+            }
+        };
+
+        let layouts = [layout];
+        let counts = [capacity];
+        let mut count_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+            .descriptor_counts(&counts);
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts)
+            .push_next(&mut count_info);
+        let set = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets[0],
+            Err(error) => {
+                unsafe {
+                    device.destroy_descriptor_pool(pool, None);
+                    device.destroy_descriptor_set_layout(layout, None);
+                }
+                return Err(error.into());
+            }
+        };
+        context.set_object_name(set, "bindless textures");
+
+        Ok(Self {
+            context,
+            layout,
+            pool,
+            set,
+            capacity,
+            allocated: 0,
+            free: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+}
+
+impl TextureRegistry {
+    /// Layout of [`TextureRegistry::set`], for the pipeline layouts.
+    pub fn layout(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    /// Maximum number of textures registered at once.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Write `view` sampled with `sampler` in a free slot of the array.
+    ///
+    /// # Returns
+    ///
+    /// The index of the slot, `None` if the registry is full.
+    pub fn register(&mut self, view: vk::ImageView, sampler: vk::Sampler) -> Option<u32> {
+        let handle = match self.free.lock().unwrap().pop() {
+            Some(handle) => handle,
+            None if self.allocated < self.capacity => {
+                self.allocated += 1;
+                self.allocated - 1
+            }
+            None => return None,
+        };
+
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(BINDLESS_TEXTURES_BINDING)
+            .dst_array_element(handle)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { self.context.device().update_descriptor_sets(&[write], &[]) };
+
+        Some(handle)
+    }
+
+    /// Register the view and sampler of `texture`, see [`TextureRegistry::register`].
+    ///
+    /// # Panics
+    ///
+    /// If `texture` has no sampler.
+    pub fn register_texture(&mut self, texture: &Texture) -> Option<u32> {
+        let sampler = texture.sampler.expect("Bindless texture has no sampler");
+        self.register(texture.view, sampler)
+    }
+
+    /// Free the slot of `handle`. It is handed out again once the current frame is complete.
+    pub fn release(&mut self, handle: u32) {
+        debug_assert!(
+            handle < self.allocated,
+            "Unknown bindless handle {}",
+            handle
+        );
+        let free = Arc::clone(&self.free);
+        self.context
+            .deletion_queue()
+            .enqueue_destroy(move |_| free.lock().unwrap().push(handle));
+    }
+
+    /// Bind the set of the registry at `set_index` of `pipeline_layout`.
+    pub fn cmd_bind(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        pipeline_layout: vk::PipelineLayout,
+        set_index: u32,
+    ) {
+        unsafe {
+            self.context.device().cmd_bind_descriptor_sets(
+                command_buffer,
+                bind_point,
+                pipeline_layout,
+                set_index,
+                &[self.set],
+                &[],
+            )
+        };
+    }
+}
+
+impl Drop for TextureRegistry {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
+
+/// Number of combined image samplers a single update after bind set can hold.
+fn max_bindless_textures(context: &Context) -> u32 {
+    let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+    let mut properties =
+        vk::PhysicalDeviceProperties2::default().push_next(&mut indexing_properties);
+    unsafe {
+        context
+            .instance()
+            .get_physical_device_properties2(context.physical_device(), &mut properties)
+    };
+
+    [
+        indexing_properties.max_per_stage_descriptor_update_after_bind_samplers,
+        indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+        indexing_properties.max_descriptor_set_update_after_bind_samplers,
+        indexing_properties.max_descriptor_set_update_after_bind_sampled_images,
+    ]
+    .into_iter()
+    .min()
+    .unwrap_or(0)
+}
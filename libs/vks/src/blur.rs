@@ -0,0 +1,339 @@
+use crate::{
+    compute_pass::*, create_compute_pipeline, create_sampler, texture::create_texture,
+    ComputePipelineParameters, Context, Descriptors, ShaderParameters, SpecializationConstants,
+    Texture,
+};
+use ash::vk;
+use std::sync::Arc;
+
+const GAUSSIAN_SHADER_NAME: &str = "gaussian_blur";
+const KAWASE_SHADER_NAME: &str = "kawase_blur";
+const INTERMEDIATE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Samples taken on each side of a texel are limited to keep large sigmas affordable.
+pub const MAX_GAUSSIAN_RADIUS: u32 = 64;
+
+/// Separable gaussian blur of a texture into another.
+///
+/// Runs a horizontal then a vertical compute pass through an intermediate
+/// texture of the size of the target. Shared by the features needing a blur,
+/// like bloom, SSAO, depth of field or the ui background.
+///
+/// The source must have a sampler and be in `SHADER_READ_ONLY_OPTIMAL` layout
+/// when the blur is recorded. The target must have the `STORAGE` usage, its
+/// content is discarded and it is left in `SHADER_READ_ONLY_OPTIMAL` layout.
+/// Both are referenced by the descriptors so the blur must be recreated with them.
+pub struct GaussianBlur {
+    context: Arc<Context>,
+    sigma: f32,
+    source_extent: vk::Extent3D,
+    intermediate: Texture,
+    target: vk::Image,
+    target_extent: vk::Extent3D,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl GaussianBlur {
+    pub fn new(context: &Arc<Context>, source: &Texture, target: &Texture, sigma: f32) -> Self {
+        check_write_without_format(context);
+
+        let target_extent = target.image.extent;
+        let intermediate = create_intermediate(context, target_extent.width, target_extent.height);
+
+        let descriptors = create_descriptors(
+            context,
+            &[(source, intermediate.view), (&intermediate, target.view)],
+        );
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::new(GAUSSIAN_SHADER_NAME),
+                layout: pipeline_layout,
+            },
+        );
+
+        Self {
+            context: Arc::clone(context),
+            sigma,
+            source_extent: source.image.extent,
+            intermediate,
+            target: target.image.image,
+            target_extent,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl GaussianBlur {
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+
+    pub fn set_sigma(&mut self, sigma: f32) {
+        self.sigma = sigma;
+    }
+
+    /// Number of texels sampled on each side of a texel for the current sigma.
+    pub fn radius(&self) -> u32 {
+        ((self.sigma * 3.0).ceil() as u32).min(MAX_GAUSSIAN_RADIUS)
+    }
+
+    /// Record the two passes of the blur. Must be recorded outside of a render pass.
+    pub fn cmd_blur(&self, command_buffer: vk::CommandBuffer) {
+        let device = self.context.device();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            )
+        };
+
+        let radius = self.radius();
+        let sigma = self.sigma.max(f32::EPSILON);
+        let horizontal = [
+            (1.0 / self.source_extent.width as f32).to_bits(),
+            0,
+            sigma.to_bits(),
+            radius,
+        ];
+        let vertical = [
+            0,
+            (1.0 / self.target_extent.height as f32).to_bits(),
+            sigma.to_bits(),
+            radius,
+        ];
+
+        cmd_begin_write(&self.context, command_buffer, self.intermediate.image.image);
+        cmd_dispatch_pass(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            self.descriptors.sets()[0],
+            &horizontal,
+            self.target_extent,
+        );
+        cmd_end_write(&self.context, command_buffer, self.intermediate.image.image);
+
+        cmd_begin_write(&self.context, command_buffer, self.target);
+        cmd_dispatch_pass(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            self.descriptors.sets()[1],
+            &vertical,
+            self.target_extent,
+        );
+        cmd_end_write(&self.context, command_buffer, self.target);
+    }
+}
+
+impl Drop for GaussianBlur {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Dual filter Kawase blur of a texture into another.
+///
+/// The source is downsampled `iterations` times, each level being half the size
+/// of the previous one, then upsampled back to the target. Cheaper than a gaussian
+/// blur for large radii, the blur grows with the number of iterations and the
+/// sample offset.
+///
+/// Same layout and usage requirements as [`GaussianBlur`].
+pub struct KawaseBlur {
+    context: Arc<Context>,
+    offset: f32,
+    source_extent: vk::Extent3D,
+    levels: Vec<Texture>,
+    target: vk::Image,
+    target_extent: vk::Extent3D,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    downsample_pipeline: vk::Pipeline,
+    upsample_pipeline: vk::Pipeline,
+}
+
+impl KawaseBlur {
+    pub fn new(
+        context: &Arc<Context>,
+        source: &Texture,
+        target: &Texture,
+        iterations: u32,
+        offset: f32,
+    ) -> Self {
+        check_write_without_format(context);
+        assert!(iterations > 0, "Kawase blur needs at least one iteration");
+
+        let source_extent = source.image.extent;
+        let levels = (1..=iterations)
+            .map(|level| {
+                create_intermediate(
+                    context,
+                    (source_extent.width >> level).max(1),
+                    (source_extent.height >> level).max(1),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Downsample from the source to the last level, then upsample back to the target.
+        let mut passes = vec![(source, levels[0].view)];
+        passes.extend(levels.windows(2).map(|pair| (&pair[0], pair[1].view)));
+        passes.extend(levels.windows(2).rev().map(|pair| (&pair[1], pair[0].view)));
+        passes.push((&levels[0], target.view));
+        let descriptors = create_descriptors(context, &passes);
+
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let create_pipeline = |upsample: bool| {
+            let mut constants = SpecializationConstants::new();
+            constants.add_bool(0, upsample);
+            let specialization_info = constants.info();
+            create_compute_pipeline(
+                context,
+                ComputePipelineParameters {
+                    shader_params: ShaderParameters::specialized(
+                        KAWASE_SHADER_NAME,
+                        &specialization_info,
+                    ),
+                    layout: pipeline_layout,
+                },
+            )
+        };
+        let downsample_pipeline = create_pipeline(false);
+        let upsample_pipeline = create_pipeline(true);
+
+        Self {
+            context: Arc::clone(context),
+            offset,
+            source_extent,
+            levels,
+            target: target.image.image,
+            target_extent: target.image.extent,
+            descriptors,
+            pipeline_layout,
+            downsample_pipeline,
+            upsample_pipeline,
+        }
+    }
+}
+
+impl KawaseBlur {
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Distance of the samples from the center in half texels, 1 by default.
+    pub fn set_offset(&mut self, offset: f32) {
+        self.offset = offset;
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Record the passes of the blur. Must be recorded outside of a render pass.
+    pub fn cmd_blur(&self, command_buffer: vk::CommandBuffer) {
+        let device = self.context.device();
+        let sets = self.descriptors.sets();
+        let level_count = self.levels.len();
+
+        let parameters = |source_extent: vk::Extent3D| {
+            [
+                (0.5 / source_extent.width as f32).to_bits(),
+                (0.5 / source_extent.height as f32).to_bits(),
+                self.offset.to_bits(),
+                0,
+            ]
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.downsample_pipeline,
+            )
+        };
+        for (index, level) in self.levels.iter().enumerate() {
+            let source_extent = match index {
+                0 => self.source_extent,
+                _ => self.levels[index - 1].image.extent,
+            };
+            cmd_begin_write(&self.context, command_buffer, level.image.image);
+            cmd_dispatch_pass(
+                &self.context,
+                command_buffer,
+                self.pipeline_layout,
+                sets[index],
+                &parameters(source_extent),
+                level.image.extent,
+            );
+            cmd_end_write(&self.context, command_buffer, level.image.image);
+        }
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.upsample_pipeline,
+            )
+        };
+        for (pass, index) in (0..level_count - 1).rev().enumerate() {
+            let level = &self.levels[index];
+            cmd_begin_write(&self.context, command_buffer, level.image.image);
+            cmd_dispatch_pass(
+                &self.context,
+                command_buffer,
+                self.pipeline_layout,
+                sets[level_count + pass],
+                &parameters(self.levels[index + 1].image.extent),
+                level.image.extent,
+            );
+            cmd_end_write(&self.context, command_buffer, level.image.image);
+        }
+
+        cmd_begin_write(&self.context, command_buffer, self.target);
+        cmd_dispatch_pass(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            sets[sets.len() - 1],
+            &parameters(self.levels[0].image.extent),
+            self.target_extent,
+        );
+        cmd_end_write(&self.context, command_buffer, self.target);
+    }
+}
+
+impl Drop for KawaseBlur {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.downsample_pipeline, None);
+            device.destroy_pipeline(self.upsample_pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn create_intermediate(context: &Arc<Context>, width: u32, height: u32) -> Texture {
+    create_texture(
+        context,
+        vk::Extent2D { width, height },
+        INTERMEDIATE_FORMAT,
+        vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        Some(create_sampler(
+            context,
+            vk::Filter::LINEAR,
+            vk::Filter::LINEAR,
+        )),
+    )
+}
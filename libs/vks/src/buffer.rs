@@ -1,4 +1,4 @@
-use super::{context::*, util::*};
+use super::{allocator::*, context::*, leak_tracker::*, util::*};
 use ash::vk;
 use std::{
     ffi::c_void,
@@ -15,7 +15,7 @@ unsafe impl Sync for MemoryMapPointer {}
 pub struct Buffer {
     context: Arc<Context>,
     pub buffer: vk::Buffer,
-    pub memory: vk::DeviceMemory,
+    allocation: Allocation,
     pub size: vk::DeviceSize,
     mapped_pointer: Option<MemoryMapPointer>,
 }
@@ -24,24 +24,22 @@ impl Buffer {
     fn new(
         context: Arc<Context>,
         buffer: vk::Buffer,
-        memory: vk::DeviceMemory,
+        allocation: Allocation,
         size: vk::DeviceSize,
     ) -> Self {
+        track_create(TrackedResource::Buffer);
+        track_allocation(allocation.size());
+
         Self {
             context,
             buffer,
-            memory,
+            allocation,
             size,
             mapped_pointer: None,
         }
     }
 
-    /// Create a buffer and allocate its memory.
-    ///
-    /// # Returns
-    ///
-    /// The buffer, its memory and the actual size in bytes of the
-    /// allocated memory since in may differ from the requested size.
+    /// Create a buffer and allocate its memory from the [`MemoryAllocator`] of `context`.
     pub fn create(
         context: Arc<Context>,
         size: vk::DeviceSize,
@@ -62,30 +60,38 @@ impl Buffer {
         };
 
         let mem_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
-        let memory = {
-            let mem_type = find_memory_type(
-                mem_requirements,
-                context.get_mem_properties(),
-                mem_properties,
-            );
-
-            let alloc_info = vk::MemoryAllocateInfo::default()
-                .allocation_size(mem_requirements.size)
-                .memory_type_index(mem_type);
-            unsafe {
-                device
-                    .allocate_memory(&alloc_info, None)
-                    .expect("Failed to allocate memory")
-            }
-        };
+        let allocation = context.allocate_memory(mem_requirements, mem_properties);
 
         unsafe {
             device
-                .bind_buffer_memory(buffer, memory, 0)
+                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
                 .expect("Failed to bind buffer memory")
         };
 
-        Buffer::new(context, buffer, memory, size)
+        tracing::debug!(
+            target: RESOURCES_TRACING_TARGET,
+            handle = ?buffer,
+            size,
+            allocation_size = mem_requirements.size,
+            dedicated = allocation.is_dedicated(),
+            ?usage,
+            ?mem_properties,
+            "Buffer created"
+        );
+
+        Buffer::new(context, buffer, allocation, size)
+    }
+}
+
+impl Buffer {
+    /// Memory of the buffer, shared with other resources unless dedicated.
+    pub fn allocation(&self) -> &Allocation {
+        &self.allocation
+    }
+
+    /// Name the buffer in the debug tools, see [`Context::set_object_name`].
+    pub fn set_name(&self, name: &str) {
+        self.context.set_object_name(self.buffer, name);
     }
 }
 
@@ -106,6 +112,25 @@ impl Buffer {
         };
     }
 
+    /// Copy the content of the buffer to host memory and wait for it.
+    ///
+    /// The buffer must have been created with `TRANSFER_SRC` usage. Used to
+    /// bring the results of a headless context back to the main one.
+    pub fn read_back(&self) -> Vec<u8> {
+        let mut staging_buffer = Buffer::create(
+            Arc::clone(&self.context),
+            self.size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        self.context.execute_one_time_commands(|command_buffer| {
+            staging_buffer.cmd_copy(command_buffer, self, self.size)
+        });
+
+        let data_ptr = staging_buffer.map_memory() as *const u8;
+        unsafe { std::slice::from_raw_parts(data_ptr, self.size as _) }.to_vec()
+    }
+
     /// Map the buffer memory and return the mapped pointer.
     ///
     /// If the memory is already mapped it just returns the pointer.
@@ -113,15 +138,12 @@ impl Buffer {
         if let Some(ptr) = &self.mapped_pointer {
             ptr.0
         } else {
-            unsafe {
-                let ptr = self
-                    .context
-                    .device()
-                    .map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())
-                    .expect("Failed to map memory");
-                self.mapped_pointer = Some(MemoryMapPointer(ptr));
-                ptr
-            }
+            let ptr = self
+                .context
+                .allocator()
+                .map(self.context.device(), &self.allocation);
+            self.mapped_pointer = Some(MemoryMapPointer(ptr));
+            ptr
         }
     }
 
@@ -130,20 +152,28 @@ impl Buffer {
     /// Does nothing if memory is not mapped.
     pub fn unmap_memory(&mut self) {
         if self.mapped_pointer.take().is_some() {
-            unsafe {
-                self.context.device().unmap_memory(self.memory);
-            }
+            self.context
+                .allocator()
+                .unmap(self.context.device(), &self.allocation);
         }
     }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
+        self.unmap_memory();
         unsafe {
-            self.unmap_memory();
             self.context.device().destroy_buffer(self.buffer, None);
-            self.context.device().free_memory(self.memory, None);
         }
+        self.context.free_memory(self.allocation);
+        track_destroy(TrackedResource::Buffer);
+        track_free(self.allocation.size());
+        tracing::debug!(
+            target: RESOURCES_TRACING_TARGET,
+            handle = ?self.buffer,
+            size = self.size,
+            "Buffer destroyed"
+        );
     }
 }
 
@@ -153,6 +183,8 @@ impl Drop for Buffer {
 /// a device local buffer. The data is first copied from the cpu to the
 /// staging buffer. Then we copy the data from the staging buffer to the
 /// final buffer using a one-time command buffer.
+///
+/// It blocks until the copy is complete, see [`crate::UploadContext`] to stream uploads.
 pub fn create_device_local_buffer_with_data<A, T: Copy>(
     context: &Arc<Context>,
     usage: vk::BufferUsageFlags,
@@ -1,15 +1,53 @@
 use crate::controls::*;
-use math::cgmath::{Deg, InnerSpace, Matrix3, Matrix4, Point3, Rad, Vector3, Zero};
-use math::clamp;
+use crate::{DEFAULT_FOV, DEFAULT_Z_FAR, DEFAULT_Z_NEAR};
+use math::cgmath::{
+    Deg, InnerSpace, Matrix3, Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4, Zero,
+};
+use math::{clamp, orthographic, perspective_with, DepthMode, Ray};
+use std::fmt;
 
 const MIN_ORBITAL_CAMERA_DISTANCE: f32 = 0.5;
 const TARGET_MOVEMENT_SPEED: f32 = 0.003;
 const ROTATION_SPEED_DEG: f32 = 0.4;
 pub const DEFAULT_FPS_MOVE_SPEED: f32 = 6.0;
 
-pub const DEFAULT_FOV: f32 = 45.0;
-pub const DEFAULT_Z_NEAR: f32 = 0.01;
-pub const DEFAULT_Z_FAR: f32 = 100.0;
+/// Distance framed by the orthographic projection of the fps camera.
+const FPS_ORTHOGRAPHIC_DISTANCE: f32 = 10.0;
+
+/// Projection of the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Frames what the perspective projection shows at the target distance,
+    /// so zooming the orbital camera still works.
+    Orthographic,
+}
+
+/// How the camera moves with the inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Turns around a target with the left button, pans the target with the
+    /// right one and zooms with the wheel, to inspect models.
+    Orbital,
+    /// Flies with the movement keys and looks around with the left button.
+    Fps,
+}
+
+impl CameraMode {
+    pub fn all() -> [Self; 2] {
+        [Self::Orbital, Self::Fps]
+    }
+}
+
+impl fmt::Display for CameraMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Orbital => write!(f, "Orbital"),
+            Self::Fps => write!(f, "Fps"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 
@@ -18,6 +56,11 @@ pub struct Camera {
     pub fov: Deg<f32>,
     pub z_near: f32,
     pub z_far: f32,
+    /// Push the far plane of the perspective projection to infinity, ignoring `z_far`.
+    pub infinite_far: bool,
+    /// Reversed depth needs a `GREATER` depth test and a depth cleared to 0.
+    pub depth_mode: DepthMode,
+    pub projection: Projection,
 }
 
 impl Default for Camera {
@@ -27,6 +70,9 @@ impl Default for Camera {
             fov: Deg(DEFAULT_FOV),
             z_near: DEFAULT_Z_NEAR,
             z_far: DEFAULT_Z_FAR,
+            infinite_far: false,
+            depth_mode: DepthMode::Standard,
+            projection: Projection::Perspective,
         }
     }
 }
@@ -65,6 +111,21 @@ impl Camera {
         }
     }
 
+    pub fn mode(&self) -> CameraMode {
+        match self.mode {
+            Mode::Orbital(_) => CameraMode::Orbital,
+            Mode::Fps(_) => CameraMode::Fps,
+        }
+    }
+
+    /// Switch to `mode`, keeping the position of the camera.
+    pub fn with_mode(self, mode: CameraMode) -> Self {
+        match mode {
+            CameraMode::Orbital => self.to_orbital(),
+            CameraMode::Fps => self.to_fps(),
+        }
+    }
+
     pub fn to_orbital(self) -> Self {
         let mode = match self.mode {
             Mode::Orbital(_) => self.mode,
@@ -81,6 +142,87 @@ impl Camera {
         Self { mode, ..self }
     }
 
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.position(), self.target(), Vector3::unit_y())
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                let z_far = (!self.infinite_far).then_some(self.z_far);
+                perspective_with(self.fov, aspect, self.z_near, z_far, self.depth_mode)
+            }
+            Projection::Orthographic => {
+                let half_height = self.orthographic_distance() * self.half_fov_tan();
+                let half_width = half_height * aspect;
+                orthographic(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.z_near,
+                    self.z_far,
+                    self.depth_mode,
+                )
+            }
+        }
+    }
+
+    /// Ray from the camera through `position`, in pixels of a target of `extent`, for picking.
+    pub fn screen_ray(&self, position: [f32; 2], extent: [u32; 2]) -> Ray<f32> {
+        let aspect = extent[0] as f32 / extent[1] as f32;
+        // The view is y up while pixels go down.
+        let x = position[0] / extent[0] as f32 * 2.0 - 1.0;
+        let y = 1.0 - position[1] / extent[1] as f32 * 2.0;
+        let half_height = match self.projection {
+            Projection::Perspective => self.half_fov_tan(),
+            Projection::Orthographic => self.orthographic_distance() * self.half_fov_tan(),
+        };
+        let view_to_world = self
+            .view_matrix()
+            .invert()
+            .expect("Failed to invert the view matrix");
+        let offset = (view_to_world
+            * Vector4::new(x * half_height * aspect, y * half_height, 0.0, 0.0))
+        .truncate();
+        let forward = (self.target() - self.position()).normalize();
+
+        match self.projection {
+            Projection::Perspective => {
+                Ray::new(self.position(), (forward + offset).normalize())
+            }
+            Projection::Orthographic => Ray::new(self.position() + offset, forward),
+        }
+    }
+
+    fn half_fov_tan(&self) -> f32 {
+        (Rad::from(self.fov) / 2.0).0.tan()
+    }
+
+    /// Distance framed by the orthographic projection.
+    fn orthographic_distance(&self) -> f32 {
+        match self.mode {
+            Mode::Orbital(c) => c.r,
+            Mode::Fps(_) => FPS_ORTHOGRAPHIC_DISTANCE,
+        }
+    }
+
+    /// Place the camera at `position` looking at `target`, keeping its mode.
+    pub fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>) {
+        match &mut self.mode {
+            Mode::Orbital(c) => c.look_at(position, target),
+            Mode::Fps(c) => c.look_at(position, target),
+        }
+    }
+
+    /// Speed of the fps camera in units per second, `None` in orbital mode.
+    pub fn move_speed(&self) -> Option<f32> {
+        match self.mode {
+            Mode::Orbital(_) => None,
+            Mode::Fps(c) => Some(c.move_speed),
+        }
+    }
+
     pub fn set_move_speed(&mut self, move_speed: f32) {
         if let Mode::Fps(c) = &mut self.mode {
             c.move_speed = move_speed;
@@ -158,6 +300,14 @@ impl Orbital {
         self.forward(input.wheel_delta() * self.r * 0.2);
     }
 
+    fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>) {
+        let offset = position - target;
+        self.r = offset.magnitude().max(MIN_ORBITAL_CAMERA_DISTANCE);
+        self.theta = offset.x.atan2(offset.z);
+        self.phi = (offset.y / self.r).clamp(-1.0, 1.0).acos();
+        self.target = target;
+    }
+
     fn rotate(&mut self, theta: f32, phi: f32) {
         self.theta += theta;
         let phi = self.phi + phi;
@@ -259,6 +409,13 @@ impl Fps {
         }
     }
 
+    fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>) {
+        self.position = position;
+        if target != position {
+            self.direction = (target - position).normalize();
+        }
+    }
+
     fn position(&self) -> Point3<f32> {
         self.position
     }
@@ -268,12 +425,20 @@ impl Fps {
     }
 }
 
+/// Camera data as seen by the shaders.
+///
+/// `prev_view_proj` holds the view projection of the previous frame
+/// so temporal effects can reproject the current pixels.
+#[repr(C)]
 #[derive(Clone, Copy)]
 #[allow(dead_code)]
 pub struct CameraUBO {
     view: Matrix4<f32>,
     proj: Matrix4<f32>,
+    view_proj: Matrix4<f32>,
+    inverted_view: Matrix4<f32>,
     inverted_proj: Matrix4<f32>,
+    prev_view_proj: Matrix4<f32>,
     eye: Point3<f32>,
     padding: f32,
     z_near: f32,
@@ -284,7 +449,7 @@ impl CameraUBO {
     pub fn new(
         view: Matrix4<f32>,
         proj: Matrix4<f32>,
-        inverted_proj: Matrix4<f32>,
+        prev_view_proj: Matrix4<f32>,
         eye: Point3<f32>,
         z_near: f32,
         z_far: f32,
@@ -292,11 +457,31 @@ impl CameraUBO {
         Self {
             view,
             proj,
-            inverted_proj,
+            view_proj: proj * view,
+            inverted_view: view.invert().unwrap_or_else(Matrix4::identity),
+            inverted_proj: proj.invert().unwrap_or_else(Matrix4::identity),
+            prev_view_proj,
             eye,
             padding: 0.0,
             z_near,
             z_far,
         }
     }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.view_proj
+    }
 }
+
+crate::assert_std140_layout!(CameraUBO {
+    view: 0,
+    proj: 64,
+    view_proj: 128,
+    inverted_view: 192,
+    inverted_proj: 256,
+    prev_view_proj: 320,
+    eye: 384,
+    padding: 396,
+    z_near: 400,
+    z_far: 404,
+});
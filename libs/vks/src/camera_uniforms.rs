@@ -0,0 +1,136 @@
+use crate::{mem_copy, Buffer, Camera, CameraUBO, Context};
+use ash::vk;
+use math::cgmath::{Matrix4, SquareMatrix};
+use std::sync::Arc;
+
+/// Per frame camera uniform buffers.
+///
+/// Holds one [`CameraUBO`] slot per frame in flight in a single persistently mapped buffer.
+/// Each slot is aligned on the device uniform buffer offset alignment so it can
+/// be bound with an offset or through a dynamic uniform buffer descriptor.
+///
+/// A slot must only be written once the fence of its frame has been waited for.
+/// Call [`CameraUniforms::submitted`] with that fence after each submission so
+/// debug builds can catch writes to a slot still read by the gpu.
+///
+/// The view projection matrix of the last update is kept and written as the
+/// previous frame matrix on the next update.
+pub struct CameraUniforms {
+    context: Arc<Context>,
+    buffer: Buffer,
+    fences: Vec<Option<vk::Fence>>,
+    stride: vk::DeviceSize,
+    count: usize,
+    prev_view_proj: Option<Matrix4<f32>>,
+    /// Applied after the projection, see [`crate::SwapchainProperties::pre_rotation`].
+    pre_rotation: Matrix4<f32>,
+}
+
+impl CameraUniforms {
+    pub fn new(context: &Arc<Context>, count: usize) -> Self {
+        let stride = context.get_ubo_alignment::<CameraUBO>() as vk::DeviceSize;
+        let mut buffer = Buffer::create(
+            Arc::clone(context),
+            stride * count as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        buffer.map_memory();
+
+        Self {
+            context: Arc::clone(context),
+            buffer,
+            fences: vec![None; count],
+            stride,
+            count,
+            prev_view_proj: None,
+            pre_rotation: Matrix4::identity(),
+        }
+    }
+
+    /// Compute the camera matrices and write them in the slot of `frame_index`.
+    pub fn update(&mut self, frame_index: usize, camera: &Camera, aspect: f32) -> CameraUBO {
+        let view = camera.view_matrix();
+        let proj = self.pre_rotation * camera.projection_matrix(aspect);
+        let prev_view_proj = self.prev_view_proj.unwrap_or(proj * view);
+
+        let ubo = CameraUBO::new(
+            view,
+            proj,
+            prev_view_proj,
+            camera.position(),
+            camera.z_near,
+            camera.z_far,
+        );
+        self.write(frame_index, ubo);
+        self.prev_view_proj = Some(ubo.view_proj());
+
+        ubo
+    }
+
+    /// Rotate the projections of the next updates to match the surface transform.
+    pub fn set_pre_rotation(&mut self, pre_rotation: Matrix4<f32>) {
+        self.pre_rotation = pre_rotation;
+    }
+
+    /// Write `ubo` as is in the slot of `frame_index`.
+    pub fn write(&mut self, frame_index: usize, ubo: CameraUBO) {
+        assert!(
+            frame_index < self.count,
+            "Camera uniform slot {} out of range (count is {})",
+            frame_index,
+            self.count
+        );
+        debug_assert!(
+            !self.is_in_flight(frame_index),
+            "Camera uniform slot {} written while still in use by the gpu",
+            frame_index
+        );
+
+        let offset = self.offset(frame_index);
+        unsafe {
+            let ptr = self.buffer.map_memory().add(offset as usize);
+            mem_copy(ptr, &[ubo]);
+        }
+    }
+
+    /// Record the fence signaled when the gpu is done with the slot of `frame_index`.
+    pub fn submitted(&mut self, frame_index: usize, fence: vk::Fence) {
+        self.fences[frame_index] = Some(fence);
+    }
+
+    /// True if the last submission reading the slot of `frame_index` is not complete.
+    pub fn is_in_flight(&self, frame_index: usize) -> bool {
+        self.fences[frame_index].is_some_and(|fence| unsafe {
+            !self
+                .context
+                .device()
+                .get_fence_status(fence)
+                .expect("Failed to get fence status")
+        })
+    }
+
+    /// Forget the previous frame matrices, after a camera cut for example.
+    pub fn reset_history(&mut self) {
+        self.prev_view_proj = None;
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn offset(&self, frame_index: usize) -> vk::DeviceSize {
+        self.stride * frame_index as vk::DeviceSize
+    }
+
+    pub fn descriptor_info(&self, frame_index: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .offset(self.offset(frame_index))
+            .range(size_of::<CameraUBO>() as _)
+    }
+}
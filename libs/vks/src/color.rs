@@ -0,0 +1,221 @@
+use ash::vk;
+use std::fmt;
+
+/// Color space in which shading happens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WorkingSpace {
+    /// Shaders work on linear values with sRGB primaries.
+    ///
+    /// Color textures are decoded when sampled and the result is
+    /// encoded again by the output transform.
+    LinearSrgb,
+    /// Shaders work directly on display encoded values.
+    ///
+    /// No decoding happens when sampling textures.
+    Display,
+}
+
+/// How the `COLOR_0` vertex colors of the meshes modulate their base color.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum VertexColorMode {
+    Ignored,
+    /// Linear values, as required by glTF.
+    #[default]
+    Linear,
+    /// sRGB encoded values, as written by some photogrammetry tools.
+    /// They are decoded by the shader before modulating the base color.
+    Srgb,
+}
+
+impl VertexColorMode {
+    pub fn all() -> [Self; 3] {
+        [Self::Ignored, Self::Linear, Self::Srgb]
+    }
+
+    /// Value of the `VERTEX_COLOR` specialization constant of the shaders.
+    pub fn index(self) -> u32 {
+        match self {
+            Self::Ignored => 0,
+            Self::Linear => 1,
+            Self::Srgb => 2,
+        }
+    }
+}
+
+impl fmt::Display for VertexColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ignored => write!(f, "Ignored"),
+            Self::Linear => write!(f, "Linear"),
+            Self::Srgb => write!(f, "sRGB"),
+        }
+    }
+}
+
+/// How the shaded values reach the presentation surface.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputTransform {
+    /// The target is an `*_SRGB` format and the hardware encodes on write.
+    HardwareSrgb,
+    /// The target is a UNORM format so the shader has to apply the sRGB curve.
+    ShaderSrgb,
+    /// The target is an extended range linear format (scRGB).
+    Linear,
+}
+
+impl OutputTransform {
+    pub fn from_surface_format(format: vk::SurfaceFormatKHR) -> Self {
+        if is_srgb_format(format.format) {
+            Self::HardwareSrgb
+        } else if format.color_space == vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+            || is_float_format(format.format)
+        {
+            Self::Linear
+        } else {
+            Self::ShaderSrgb
+        }
+    }
+}
+
+/// Color management policy.
+///
+/// Decides the format of loaded textures, the format of the attachments
+/// pipelines render to and how the gui renderer should output its colors
+/// so that all of them agree with each other.
+///
+/// When `audit` is set mismatches are reported through `tracing::warn!`.
+#[derive(Copy, Clone, Debug)]
+pub struct ColorPolicy {
+    pub working_space: WorkingSpace,
+    pub audit: bool,
+}
+
+impl Default for ColorPolicy {
+    fn default() -> Self {
+        Self {
+            working_space: WorkingSpace::LinearSrgb,
+            audit: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl ColorPolicy {
+    /// Format of an 8 bits per channel rgba texture.
+    ///
+    /// `linear` textures hold data (normals, roughness, ...) and are never decoded.
+    pub fn texture_format(&self, linear: bool) -> vk::Format {
+        match (self.working_space, linear) {
+            (WorkingSpace::LinearSrgb, false) => vk::Format::R8G8B8A8_SRGB,
+            _ => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    /// Format of the color attachment of pipelines writing to a surface of `surface_format`.
+    pub fn attachment_format(&self, surface_format: vk::SurfaceFormatKHR) -> vk::Format {
+        surface_format.format
+    }
+
+    pub fn output_transform(&self, surface_format: vk::SurfaceFormatKHR) -> OutputTransform {
+        OutputTransform::from_surface_format(surface_format)
+    }
+
+    /// Value of the `srgb_framebuffer` option of the gui renderer.
+    ///
+    /// When true the gui outputs linear colors and lets the target encode them.
+    pub fn gui_srgb_framebuffer(&self, surface_format: vk::SurfaceFormatKHR) -> bool {
+        match self.working_space {
+            WorkingSpace::LinearSrgb => {
+                self.output_transform(surface_format) != OutputTransform::ShaderSrgb
+            }
+            WorkingSpace::Display => false,
+        }
+    }
+
+    /// Check that a pipeline color attachment matches the format it is rendering to.
+    ///
+    /// # Returns
+    ///
+    /// True if no mismatch was found.
+    pub fn audit_attachment(
+        &self,
+        label: &str,
+        pipeline_format: vk::Format,
+        target_format: vk::Format,
+    ) -> bool {
+        let matches = pipeline_format == target_format;
+        if self.audit && !matches {
+            tracing::warn!(
+                "Color audit: pipeline '{}' renders to {:?} but its attachment is {:?}",
+                label,
+                pipeline_format,
+                target_format
+            );
+        }
+        matches
+    }
+
+    /// Check that a texture format agrees with the kind of data it holds.
+    ///
+    /// # Returns
+    ///
+    /// True if no mismatch was found.
+    pub fn audit_texture(&self, label: &str, format: vk::Format, linear: bool) -> bool {
+        let expected = self.texture_format(linear);
+        let matches = !(is_srgb_format(format) ^ is_srgb_format(expected));
+        if self.audit && !matches {
+            tracing::warn!(
+                "Color audit: texture '{}' uses {:?} but the policy expects {:?}",
+                label,
+                format,
+                expected
+            );
+        }
+        matches
+    }
+
+    /// Check that the gui renderer option agrees with the target surface.
+    ///
+    /// # Returns
+    ///
+    /// True if no mismatch was found.
+    pub fn audit_gui(&self, srgb_framebuffer: bool, surface_format: vk::SurfaceFormatKHR) -> bool {
+        let expected = self.gui_srgb_framebuffer(surface_format);
+        let matches = srgb_framebuffer == expected;
+        if self.audit && !matches {
+            tracing::warn!(
+                "Color audit: gui srgb_framebuffer is {} but surface {:?} expects {}",
+                srgb_framebuffer,
+                surface_format,
+                expected
+            );
+        }
+        matches
+    }
+}
+
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::BC1_RGB_SRGB_BLOCK
+            | vk::Format::BC1_RGBA_SRGB_BLOCK
+            | vk::Format::BC2_SRGB_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK
+            | vk::Format::BC7_SRGB_BLOCK
+    )
+}
+
+pub fn is_float_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R16G16B16A16_SFLOAT
+            | vk::Format::R32G32B32A32_SFLOAT
+            | vk::Format::B10G11R11_UFLOAT_PACK32
+    )
+}
@@ -0,0 +1,113 @@
+use crate::Context;
+use ash::vk;
+use std::sync::Arc;
+
+/// Track which of the per swapchain image command buffers can be submitted
+/// again without being recorded.
+///
+/// When the scene is static, recording the same commands every frame is wasted
+/// cpu time. Once enabled, a command buffer recorded as reusable is kept until
+/// [`CommandBufferCache::invalidate`] is called, or the cache is reset after a
+/// swapchain recreation.
+///
+/// Before a command buffer is recorded or submitted again, the cache waits for
+/// the fence of its previous submission so the same commands are never pending
+/// twice.
+///
+/// The commands bind the per frame in flight resources they were recorded with,
+/// so they are only reused for the same swapchain image and frame in flight pair.
+///
+/// ```ignore
+/// wait_for_fences(frame_fence);
+/// let image_index = acquire_next_image();
+/// let reuse = cache.prepare(image_index, frame_index);
+/// reset_fences(frame_fence);
+/// if !reuse {
+///     record(command_buffers[image_index], frame_index);
+///     cache.set_recorded(image_index, frame_index, true);
+/// }
+/// submit(command_buffers[image_index], frame_fence);
+/// cache.submitted(image_index, frame_fence);
+/// ```
+pub struct CommandBufferCache {
+    context: Arc<Context>,
+    enabled: bool,
+    recorded: Vec<Option<usize>>,
+    fences: Vec<Option<vk::Fence>>,
+}
+
+impl CommandBufferCache {
+    pub fn new(context: Arc<Context>, count: usize) -> Self {
+        Self {
+            context,
+            enabled: false,
+            recorded: vec![None; count],
+            fences: vec![None; count],
+        }
+    }
+}
+
+impl CommandBufferCache {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            tracing::debug!("Command buffer reuse enabled: {}", enabled);
+            self.enabled = enabled;
+            self.invalidate();
+        }
+    }
+
+    /// Force the recording of all command buffers on their next use.
+    ///
+    /// Must be called when anything recorded in the command buffers changes:
+    /// settings, pipelines, descriptors or the content of the scene.
+    pub fn invalidate(&mut self) {
+        self.recorded.iter_mut().for_each(|recorded| *recorded = None);
+    }
+
+    /// Forget every command buffer, to call when they are reallocated
+    /// with the swapchain.
+    ///
+    /// The previous command buffers must not be submitted anymore.
+    pub fn reset(&mut self, count: usize) {
+        self.recorded = vec![None; count];
+        self.fences = vec![None; count];
+    }
+
+    /// Wait for the previous submission of the command buffer of `image_index`.
+    ///
+    /// Must be called before resetting the fence of the current frame since that
+    /// fence may be the one of the previous submission.
+    ///
+    /// # Returns
+    ///
+    /// True if the command buffer can be submitted again as is for `frame_index`.
+    pub fn prepare(&mut self, image_index: usize, frame_index: usize) -> bool {
+        if let Some(fence) = self.fences[image_index].take() {
+            unsafe {
+                self.context
+                    .device()
+                    .wait_for_fences(&[fence], true, u64::MAX)
+                    .expect("Failed to wait for command buffer fence")
+            };
+        }
+
+        self.enabled && self.recorded[image_index] == Some(frame_index)
+    }
+
+    /// Mark the command buffer of `image_index` as recorded for `frame_index`.
+    ///
+    /// `reusable` must be false when the recorded commands depend on per frame
+    /// data, like the ui.
+    pub fn set_recorded(&mut self, image_index: usize, frame_index: usize, reusable: bool) {
+        self.recorded[image_index] = reusable.then_some(frame_index);
+    }
+
+    /// Record the fence signaled by the submission of the command buffer of `image_index`.
+    pub fn submitted(&mut self, image_index: usize, fence: vk::Fence) {
+        self.fences[image_index] = Some(fence);
+    }
+}
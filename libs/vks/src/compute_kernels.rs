@@ -0,0 +1,1227 @@
+use crate::{
+    compute_pass::*, create_compute_pipeline, create_device_local_buffer_with_data, create_sampler,
+    texture::create_texture, Buffer, ComputePipelineParameters, Context, Descriptors,
+    ShaderParameters, SpecializationConstants, SubgroupSpecialization, Texture,
+};
+use ash::vk;
+use std::{marker::PhantomData, mem::size_of, sync::Arc};
+
+const REDUCE_SHADER_NAME: &str = "reduce";
+const PREFIX_SUM_SHADER_NAME: &str = "prefix_sum";
+const DEPTH_PYRAMID_SHADER_NAME: &str = "depth_pyramid";
+const DEPTH_PYRAMID_SUBGROUP_SHADER_NAME: &str = "depth_pyramid_subgroup";
+const LUMINANCE_HISTOGRAM_SHADER_NAME: &str = "luminance_histogram";
+const LUMINANCE_HISTOGRAM_SUBGROUP_SHADER_NAME: &str = "luminance_histogram_subgroup";
+/// Elements handled by a group of the buffer kernels, two per invocation.
+const BLOCK_SIZE: u32 = 512;
+const HISTOGRAM_GROUP_SIZE: u32 = 16;
+const DEPTH_PYRAMID_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+/// Modes of the prefix sum shader.
+const PREFIX_SUM_SCAN: u32 = 0;
+const PREFIX_SUM_ADD_BLOCK_SUMS: u32 = 1;
+pub const LUMINANCE_HISTOGRAM_BIN_COUNT: usize = 256;
+
+/// 32 bits element of the buffers processed by the kernels.
+pub trait KernelElement: Copy + 'static {
+    /// Type of the element in the shaders, see `shader/reduce/elements.glsl`.
+    const ELEMENT_TYPE: u32;
+
+    fn from_bits(bits: u32) -> Self;
+}
+
+impl KernelElement for u32 {
+    const ELEMENT_TYPE: u32 = 0;
+
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+}
+
+impl KernelElement for i32 {
+    const ELEMENT_TYPE: u32 = 1;
+
+    fn from_bits(bits: u32) -> Self {
+        bits as i32
+    }
+}
+
+impl KernelElement for f32 {
+    const ELEMENT_TYPE: u32 = 2;
+
+    fn from_bits(bits: u32) -> Self {
+        f32::from_bits(bits)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Min,
+    Max,
+}
+
+impl ReduceOp {
+    /// Operation in the shaders, see `shader/reduce/elements.glsl`.
+    fn id(self) -> u32 {
+        match self {
+            Self::Sum => 0,
+            Self::Min => 1,
+            Self::Max => 2,
+        }
+    }
+}
+
+/// Parallel reduction of a buffer of `T` to a single value.
+///
+/// Each pass reduces blocks of 512 elements to one until a single one is left,
+/// so a million elements take three passes. The input is referenced by the
+/// descriptors so the reduction must be recreated with it.
+pub struct Reduction<T: KernelElement> {
+    context: Arc<Context>,
+    op: ReduceOp,
+    /// Element count of the input then of each partial buffer.
+    counts: Vec<u32>,
+    partials: Vec<Buffer>,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    _element: PhantomData<T>,
+}
+
+impl<T: KernelElement> Reduction<T> {
+    /// Reduce the `count` first elements of `input` which must have the `STORAGE_BUFFER` usage.
+    ///
+    /// # Panics
+    ///
+    /// If `count` is 0 or `input` is too small for it.
+    pub fn new(context: &Arc<Context>, input: &Buffer, count: u32, op: ReduceOp) -> Self {
+        check_input(input, count);
+
+        let mut counts = vec![count];
+        loop {
+            let groups = counts[counts.len() - 1].div_ceil(BLOCK_SIZE);
+            counts.push(groups);
+            if groups == 1 {
+                break;
+            }
+        }
+        let partials = counts[1..]
+            .iter()
+            .map(|count| create_storage_buffer(context, *count))
+            .collect::<Vec<_>>();
+
+        let mut passes = vec![vec![
+            KernelBinding::Buffer(input.buffer),
+            KernelBinding::Buffer(partials[0].buffer),
+        ]];
+        passes.extend(partials.windows(2).map(|pair| {
+            vec![
+                KernelBinding::Buffer(pair[0].buffer),
+                KernelBinding::Buffer(pair[1].buffer),
+            ]
+        }));
+        let descriptors = create_kernel_descriptors(context, &passes);
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline =
+            create_element_pipeline::<T>(context, REDUCE_SHADER_NAME, op, pipeline_layout);
+
+        Self {
+            context: Arc::clone(context),
+            op,
+            counts,
+            partials,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+            _element: PhantomData,
+        }
+    }
+}
+
+impl<T: KernelElement> Reduction<T> {
+    pub fn op(&self) -> ReduceOp {
+        self.op
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Buffer holding the result in its first element once the reduction is executed.
+    pub fn result(&self) -> &Buffer {
+        &self.partials[self.partials.len() - 1]
+    }
+
+    /// Record the passes of the reduction. Must be recorded outside of a render pass.
+    ///
+    /// Writes of the input must be visible to compute shaders. The result is made
+    /// visible to compute and fragment shaders and to transfers.
+    pub fn cmd_reduce(&self, command_buffer: vk::CommandBuffer) {
+        cmd_bind_compute_pipeline(&self.context, command_buffer, self.pipeline);
+        let sets = self.descriptors.sets();
+        for (pass, counts) in self.counts.windows(2).enumerate() {
+            cmd_dispatch_groups(
+                &self.context,
+                command_buffer,
+                self.pipeline_layout,
+                sets[pass],
+                &[counts[0], 0, 0, 0],
+                (counts[1], 1),
+            );
+            cmd_end_kernel_pass(&self.context, command_buffer);
+        }
+    }
+
+    /// Copy the result to host memory and wait for it.
+    pub fn read_result(&self) -> T {
+        read_elements(self.result(), 1)[0]
+    }
+}
+
+impl<T: KernelElement> Drop for Reduction<T> {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Upload `values`, reduce them and wait for the result.
+///
+/// # Returns
+///
+/// `None` if `values` is empty.
+pub fn reduce<T: KernelElement>(context: &Arc<Context>, values: &[T], op: ReduceOp) -> Option<T> {
+    if values.is_empty() {
+        return None;
+    }
+    let input = create_input_buffer(context, values);
+    let reduction = Reduction::<T>::new(context, &input, values.len() as _, op);
+    context.execute_one_time_commands(|command_buffer| reduction.cmd_reduce(command_buffer));
+    Some(reduction.read_result())
+}
+
+/// Exclusive prefix sum of a buffer of `T` into another.
+///
+/// Blocks of 512 elements are scanned, then the sums of the blocks are scanned
+/// the same way and added back to the blocks. Input and output are referenced
+/// by the descriptors so the prefix sum must be recreated with them.
+pub struct PrefixSum<T: KernelElement> {
+    context: Arc<Context>,
+    /// Element count of the input then of each level of block sums but the last one.
+    counts: Vec<u32>,
+    /// Sums of the blocks of each level, the last one holding the total.
+    block_sums: Vec<Buffer>,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    _element: PhantomData<T>,
+}
+
+impl<T: KernelElement> PrefixSum<T> {
+    /// Scan the `count` first elements of `input` into `output`.
+    ///
+    /// Both must have the `STORAGE_BUFFER` usage and can be the same buffer.
+    ///
+    /// # Panics
+    ///
+    /// If `count` is 0 or a buffer is too small for it.
+    pub fn new(context: &Arc<Context>, input: &Buffer, output: &Buffer, count: u32) -> Self {
+        check_input(input, count);
+        check_input(output, count);
+
+        let mut counts = vec![count];
+        let mut block_sums = Vec::new();
+        loop {
+            let groups = counts[counts.len() - 1].div_ceil(BLOCK_SIZE);
+            block_sums.push(create_storage_buffer(context, groups));
+            if groups == 1 {
+                break;
+            }
+            counts.push(groups);
+        }
+
+        // Levels after the first one scan the block sums of the previous level in place.
+        let mut passes = vec![vec![
+            KernelBinding::Buffer(input.buffer),
+            KernelBinding::Buffer(output.buffer),
+            KernelBinding::Buffer(block_sums[0].buffer),
+        ]];
+        passes.extend(block_sums.windows(2).map(|pair| {
+            vec![
+                KernelBinding::Buffer(pair[0].buffer),
+                KernelBinding::Buffer(pair[0].buffer),
+                KernelBinding::Buffer(pair[1].buffer),
+            ]
+        }));
+        let descriptors = create_kernel_descriptors(context, &passes);
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline = create_element_pipeline::<T>(
+            context,
+            PREFIX_SUM_SHADER_NAME,
+            ReduceOp::Sum,
+            pipeline_layout,
+        );
+
+        Self {
+            context: Arc::clone(context),
+            counts,
+            block_sums,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+            _element: PhantomData,
+        }
+    }
+}
+
+impl<T: KernelElement> PrefixSum<T> {
+    /// Buffer holding the sum of all the input elements in its first element
+    /// once the prefix sum is executed.
+    pub fn total(&self) -> &Buffer {
+        &self.block_sums[self.block_sums.len() - 1]
+    }
+
+    /// Record the passes of the prefix sum. Must be recorded outside of a render pass.
+    ///
+    /// Same visibility requirements and guarantees as [`Reduction::cmd_reduce`].
+    pub fn cmd_scan(&self, command_buffer: vk::CommandBuffer) {
+        cmd_bind_compute_pipeline(&self.context, command_buffer, self.pipeline);
+        let sets = self.descriptors.sets();
+        let dispatch = |level: usize, mode: u32| {
+            let count = self.counts[level];
+            cmd_dispatch_groups(
+                &self.context,
+                command_buffer,
+                self.pipeline_layout,
+                sets[level],
+                &[count, mode, 0, 0],
+                (count.div_ceil(BLOCK_SIZE), 1),
+            );
+            cmd_end_kernel_pass(&self.context, command_buffer);
+        };
+
+        for level in 0..self.counts.len() {
+            dispatch(level, PREFIX_SUM_SCAN);
+        }
+        // The block sums of a level are complete once the next level got its own.
+        for level in (0..self.counts.len() - 1).rev() {
+            dispatch(level, PREFIX_SUM_ADD_BLOCK_SUMS);
+        }
+    }
+
+    /// Copy the total to host memory and wait for it.
+    pub fn read_total(&self) -> T {
+        read_elements(self.total(), 1)[0]
+    }
+}
+
+impl<T: KernelElement> Drop for PrefixSum<T> {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Upload `values`, compute their exclusive prefix sum and wait for it.
+pub fn prefix_sum<T: KernelElement>(context: &Arc<Context>, values: &[T]) -> Vec<T> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let count = values.len() as u32;
+    let input = create_input_buffer(context, values);
+    let output = create_storage_buffer(context, count);
+    let prefix_sum = PrefixSum::<T>::new(context, &input, &output, count);
+    context.execute_one_time_commands(|command_buffer| prefix_sum.cmd_scan(command_buffer));
+    read_elements(&output, values.len())
+}
+
+/// Depth kept by the levels of a [`DepthPyramid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthReduction {
+    /// Farthest depth for a regular depth buffer, as needed for occlusion culling.
+    Max,
+    /// Farthest depth for a reversed depth buffer, or closest for a regular one.
+    Min,
+}
+
+/// Hierarchical depth, each level keeping the min or max depth of the texels
+/// of the previous one it covers.
+///
+/// The first level is half the size of the depth and the last one is a single
+/// texel. Levels are separate `R32_SFLOAT` textures with a nearest sampler.
+///
+/// The depth must have a sampler and be in `DEPTH_STENCIL_READ_ONLY_OPTIMAL`
+/// layout when the pyramid is recorded, see [`Texture::sampling_layout`]. Levels are left in `SHADER_READ_ONLY_OPTIMAL`
+/// layout. The depth is referenced by the descriptors so the pyramid must be
+/// recreated with it.
+///
+/// With subgroup clustered operations, a pass also writes the next level when
+/// it is half the size of the one it writes, sparing a pass and a read of the
+/// level.
+pub struct DepthPyramid {
+    context: Arc<Context>,
+    reduction: DepthReduction,
+    depth_extent: vk::Extent3D,
+    levels: Vec<Texture>,
+    passes: Vec<PyramidPass>,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+/// Pass of a [`DepthPyramid`] writing `level` and possibly the next one.
+#[derive(Debug, Clone, Copy)]
+struct PyramidPass {
+    level: usize,
+    writes_next: bool,
+}
+
+impl DepthPyramid {
+    pub fn new(context: &Arc<Context>, depth: &Texture, reduction: DepthReduction) -> Self {
+        Self::with_subgroups(context, depth, reduction, true)
+    }
+
+    /// Create a pyramid whose passes use subgroup operations if `subgroups`
+    /// is true and the device supports them, see [`SubgroupSpecialization`].
+    pub fn with_subgroups(
+        context: &Arc<Context>,
+        depth: &Texture,
+        reduction: DepthReduction,
+        subgroups: bool,
+    ) -> Self {
+        let depth_extent = depth.image.extent;
+        let largest_side = depth_extent.width.max(depth_extent.height);
+        let level_count = (u32::BITS - 1 - largest_side.leading_zeros()).max(1);
+        let levels = (1..=level_count)
+            .map(|level| {
+                create_texture(
+                    context,
+                    vk::Extent2D {
+                        width: (depth_extent.width >> level).max(1),
+                        height: (depth_extent.height >> level).max(1),
+                    },
+                    DEPTH_PYRAMID_FORMAT,
+                    vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::TRANSFER_SRC,
+                    Some(create_sampler(
+                        context,
+                        vk::Filter::NEAREST,
+                        vk::Filter::NEAREST,
+                    )),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let specialization = SubgroupSpecialization::new(
+            &context.subgroup_support(),
+            vk::SubgroupFeatureFlags::CLUSTERED,
+            4,
+            subgroups,
+        );
+        let passes = pyramid_passes(&levels, specialization.is_some());
+
+        // Passes not writing the next level bind their level twice.
+        let descriptors = create_kernel_descriptors(
+            context,
+            &passes
+                .iter()
+                .map(|pass| {
+                    let source = match pass.level {
+                        0 => depth,
+                        level => &levels[level - 1],
+                    };
+                    let next = pass.level + pass.writes_next as usize;
+                    vec![
+                        KernelBinding::sampled(source),
+                        KernelBinding::StorageImage(levels[pass.level].view),
+                        KernelBinding::StorageImage(levels[next].view),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let mut constants = SpecializationConstants::new();
+        constants.add_bool(
+            SubgroupSpecialization::KERNEL_CONSTANT_ID,
+            reduction == DepthReduction::Min,
+        );
+        let shader_name = match specialization {
+            Some(specialization) => {
+                specialization.add_constants(&mut constants);
+                DEPTH_PYRAMID_SUBGROUP_SHADER_NAME
+            }
+            None => DEPTH_PYRAMID_SHADER_NAME,
+        };
+        let specialization_info = constants.info();
+        let pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::specialized(shader_name, &specialization_info),
+                layout: pipeline_layout,
+            },
+        );
+
+        Self {
+            context: Arc::clone(context),
+            reduction,
+            depth_extent,
+            levels,
+            passes,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl DepthPyramid {
+    pub fn reduction(&self) -> DepthReduction {
+        self.reduction
+    }
+
+    pub fn levels(&self) -> &[Texture] {
+        &self.levels
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.passes.len()
+    }
+
+    /// Record the passes building the levels. Must be recorded outside of a render pass.
+    pub fn cmd_build(&self, command_buffer: vk::CommandBuffer) {
+        cmd_bind_compute_pipeline(&self.context, command_buffer, self.pipeline);
+        let sets = self.descriptors.sets();
+        for (set, pass) in sets.iter().zip(&self.passes) {
+            let source_extent = match pass.level {
+                0 => self.depth_extent,
+                level => self.levels[level - 1].image.extent,
+            };
+            let written = &self.levels[pass.level..=pass.level + pass.writes_next as usize];
+            for level in written {
+                cmd_begin_write(&self.context, command_buffer, level.image.image);
+            }
+            cmd_dispatch_pass(
+                &self.context,
+                command_buffer,
+                self.pipeline_layout,
+                *set,
+                &[
+                    source_extent.width,
+                    source_extent.height,
+                    pass.writes_next as u32,
+                    0,
+                ],
+                self.levels[pass.level].image.extent,
+            );
+            for level in written {
+                cmd_end_write(&self.context, command_buffer, level.image.image);
+            }
+        }
+    }
+
+    /// Copy the depths of `level` to host memory, row by row, and wait for it.
+    pub fn read_level(&self, level: usize) -> Vec<f32> {
+        let image = &self.levels[level].image;
+        image.transition_image_layout(
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        let bytes = image.read_back(0, size_of::<f32>() as _);
+        image.transition_image_layout(
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        bytes_to_elements(&bytes, bytes.len() / size_of::<f32>())
+    }
+}
+
+impl Drop for DepthPyramid {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Passes writing `levels`, a pass writing two levels when `pairs` is true and
+/// the second one is half the size of the first.
+fn pyramid_passes(levels: &[Texture], pairs: bool) -> Vec<PyramidPass> {
+    let mut passes = Vec::new();
+    let mut level = 0;
+    while level < levels.len() {
+        let writes_next = pairs
+            && levels.get(level + 1).is_some_and(|next| {
+                let (extent, next_extent) = (levels[level].image.extent, next.image.extent);
+                extent.width == 2 * next_extent.width && extent.height == 2 * next_extent.height
+            });
+        passes.push(PyramidPass { level, writes_next });
+        level += 1 + writes_next as usize;
+    }
+    passes
+}
+
+/// Histogram of the log2 luminance of a texture, as needed for auto exposure.
+///
+/// Texels are counted in [`LUMINANCE_HISTOGRAM_BIN_COUNT`] bins, the first one
+/// for the black texels and the others spread over the log2 luminance range.
+///
+/// The source must have a sampler and be in `SHADER_READ_ONLY_OPTIMAL` layout
+/// when the histogram is recorded. It is referenced by the descriptors so the
+/// histogram must be recreated with it.
+///
+/// With subgroup ballots, the invocations of a subgroup counting the same bin
+/// add to it once.
+pub struct LuminanceHistogram {
+    context: Arc<Context>,
+    min_log_luminance: f32,
+    max_log_luminance: f32,
+    source_extent: vk::Extent3D,
+    bins: Buffer,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl LuminanceHistogram {
+    pub fn new(
+        context: &Arc<Context>,
+        source: &Texture,
+        min_log_luminance: f32,
+        max_log_luminance: f32,
+    ) -> Self {
+        Self::with_subgroups(context, source, min_log_luminance, max_log_luminance, true)
+    }
+
+    /// Create a histogram using subgroup operations if `subgroups` is true
+    /// and the device supports them, see [`SubgroupSpecialization`].
+    pub fn with_subgroups(
+        context: &Arc<Context>,
+        source: &Texture,
+        min_log_luminance: f32,
+        max_log_luminance: f32,
+        subgroups: bool,
+    ) -> Self {
+        let bins = create_storage_buffer(context, LUMINANCE_HISTOGRAM_BIN_COUNT as _);
+        let descriptors = create_kernel_descriptors(
+            context,
+            &[vec![
+                KernelBinding::sampled(source),
+                KernelBinding::Buffer(bins.buffer),
+            ]],
+        );
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let specialization = SubgroupSpecialization::new(
+            &context.subgroup_support(),
+            vk::SubgroupFeatureFlags::BALLOT,
+            1,
+            subgroups,
+        );
+        let mut constants = SpecializationConstants::new();
+        let shader_name = match specialization {
+            Some(specialization) => {
+                specialization.add_constants(&mut constants);
+                LUMINANCE_HISTOGRAM_SUBGROUP_SHADER_NAME
+            }
+            None => LUMINANCE_HISTOGRAM_SHADER_NAME,
+        };
+        let specialization_info = constants.info();
+        let pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::specialized(shader_name, &specialization_info),
+                layout: pipeline_layout,
+            },
+        );
+
+        let mut histogram = Self {
+            context: Arc::clone(context),
+            min_log_luminance: 0.0,
+            max_log_luminance: 0.0,
+            source_extent: source.image.extent,
+            bins,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        };
+        histogram.set_range(min_log_luminance, max_log_luminance);
+        histogram
+    }
+}
+
+impl LuminanceHistogram {
+    /// Log2 luminance range covered by the bins after the first one.
+    pub fn range(&self) -> (f32, f32) {
+        (self.min_log_luminance, self.max_log_luminance)
+    }
+
+    pub fn set_range(&mut self, min_log_luminance: f32, max_log_luminance: f32) {
+        assert!(
+            min_log_luminance < max_log_luminance,
+            "Luminance histogram range must not be empty"
+        );
+        self.min_log_luminance = min_log_luminance;
+        self.max_log_luminance = max_log_luminance;
+    }
+
+    /// Buffer of [`LUMINANCE_HISTOGRAM_BIN_COUNT`] u32 counts once the histogram is executed.
+    pub fn bins(&self) -> &Buffer {
+        &self.bins
+    }
+
+    /// Record the clear of the bins and the counting pass. Must be recorded outside of a render pass.
+    ///
+    /// The bins are made visible to compute and fragment shaders and to transfers.
+    pub fn cmd_build(&self, command_buffer: vk::CommandBuffer) {
+        let device = self.context.device();
+        unsafe { device.cmd_fill_buffer(command_buffer, self.bins.buffer, 0, vk::WHOLE_SIZE, 0) };
+        cmd_buffer_barrier(
+            &self.context,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+            ),
+        );
+
+        cmd_bind_compute_pipeline(&self.context, command_buffer, self.pipeline);
+        let range = self.max_log_luminance - self.min_log_luminance;
+        cmd_dispatch_groups(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            self.descriptors.sets()[0],
+            &[
+                self.min_log_luminance.to_bits(),
+                (1.0 / range).to_bits(),
+                0,
+                0,
+            ],
+            (
+                self.source_extent.width.div_ceil(HISTOGRAM_GROUP_SIZE),
+                self.source_extent.height.div_ceil(HISTOGRAM_GROUP_SIZE),
+            ),
+        );
+        cmd_end_kernel_pass(&self.context, command_buffer);
+    }
+
+    /// Copy the bins to host memory and wait for them.
+    pub fn read_bins(&self) -> Vec<u32> {
+        read_elements(&self.bins, LUMINANCE_HISTOGRAM_BIN_COUNT)
+    }
+
+    /// Log2 luminance at the center of `bin`, `None` for the first bin counting the black texels.
+    pub fn bin_log_luminance(&self, bin: usize) -> Option<f32> {
+        if bin == 0 {
+            return None;
+        }
+        let bin_range = (self.max_log_luminance - self.min_log_luminance)
+            / (LUMINANCE_HISTOGRAM_BIN_COUNT - 2) as f32;
+        Some(self.min_log_luminance + (bin as f32 - 0.5) * bin_range)
+    }
+}
+
+impl Drop for LuminanceHistogram {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Resource bound by a kernel, in binding order.
+#[derive(Clone, Copy)]
+enum KernelBinding {
+    Texture(vk::ImageView, vk::Sampler, vk::ImageLayout),
+    /// Image written in `GENERAL` layout.
+    StorageImage(vk::ImageView),
+    Buffer(vk::Buffer),
+}
+
+impl KernelBinding {
+    /// Binding sampling `texture` in its sampling layout.
+    fn sampled(texture: &Texture) -> Self {
+        Self::Texture(
+            texture.sampling_view(),
+            texture.sampler.expect("Kernel source must have a sampler"),
+            texture.sampling_layout(),
+        )
+    }
+
+    fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            Self::Texture(..) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            Self::StorageImage(_) => vk::DescriptorType::STORAGE_IMAGE,
+            Self::Buffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+        }
+    }
+}
+
+/// Create one set per pass, all passes binding the same kinds of resources.
+fn create_kernel_descriptors(context: &Arc<Context>, passes: &[Vec<KernelBinding>]) -> Descriptors {
+    let device = context.device();
+    let bindings = passes[0]
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(index as _)
+                .descriptor_type(binding.descriptor_type())
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        })
+        .collect::<Vec<_>>();
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let set_count = passes.len() as u32;
+    let pool_sizes = bindings
+        .iter()
+        .map(|binding| vk::DescriptorPoolSize {
+            ty: binding.descriptor_type,
+            descriptor_count: set_count,
+        })
+        .collect::<Vec<_>>();
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(set_count);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = vec![layout; passes.len()];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    for (set, pass) in sets.iter().zip(passes) {
+        let image_infos = pass
+            .iter()
+            .map(|binding| match binding {
+                KernelBinding::Texture(view, sampler, layout) => {
+                    [vk::DescriptorImageInfo::default()
+                        .image_layout(*layout)
+                        .image_view(*view)
+                        .sampler(*sampler)]
+                }
+                KernelBinding::StorageImage(view) => [vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(*view)],
+                KernelBinding::Buffer(_) => [vk::DescriptorImageInfo::default()],
+            })
+            .collect::<Vec<_>>();
+        let buffer_infos = pass
+            .iter()
+            .map(|binding| match binding {
+                KernelBinding::Buffer(buffer) => [vk::DescriptorBufferInfo::default()
+                    .buffer(*buffer)
+                    .range(vk::WHOLE_SIZE)],
+                _ => [vk::DescriptorBufferInfo::default()],
+            })
+            .collect::<Vec<_>>();
+        let writes = pass
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(index as _)
+                    .descriptor_type(binding.descriptor_type());
+                match binding {
+                    KernelBinding::Buffer(_) => write.buffer_info(&buffer_infos[index]),
+                    _ => write.image_info(&image_infos[index]),
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_element_pipeline<T: KernelElement>(
+    context: &Arc<Context>,
+    shader_name: &'static str,
+    op: ReduceOp,
+    layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let mut constants = SpecializationConstants::new();
+    constants.add_u32(0, T::ELEMENT_TYPE);
+    constants.add_u32(1, op.id());
+    let specialization_info = constants.info();
+    create_compute_pipeline(
+        context,
+        ComputePipelineParameters {
+            shader_params: ShaderParameters::specialized(shader_name, &specialization_info),
+            layout,
+        },
+    )
+}
+
+fn check_input(buffer: &Buffer, count: u32) {
+    assert!(count > 0, "Compute kernels need at least one element");
+    assert!(
+        buffer.size >= count as vk::DeviceSize * size_of::<u32>() as vk::DeviceSize,
+        "Buffer is too small for {} elements",
+        count
+    );
+}
+
+/// Device local buffer of `count` elements that can be read back.
+fn create_storage_buffer(context: &Arc<Context>, count: u32) -> Buffer {
+    Buffer::create(
+        Arc::clone(context),
+        count.max(1) as vk::DeviceSize * size_of::<u32>() as vk::DeviceSize,
+        vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_SRC
+            | vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )
+}
+
+/// Upload `values` and make them visible to the compute shaders of the next submissions.
+fn create_input_buffer<T: KernelElement>(context: &Arc<Context>, values: &[T]) -> Buffer {
+    let buffer = create_device_local_buffer_with_data::<u32, _>(
+        context,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        values,
+    );
+    context.execute_one_time_commands(|command_buffer| {
+        cmd_buffer_barrier(
+            context,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_READ,
+            ),
+        )
+    });
+    buffer
+}
+
+fn read_elements<T: KernelElement>(buffer: &Buffer, count: usize) -> Vec<T> {
+    bytes_to_elements(&buffer.read_back(), count)
+}
+
+fn bytes_to_elements<T: KernelElement>(bytes: &[u8], count: usize) -> Vec<T> {
+    bytes
+        .chunks_exact(size_of::<u32>())
+        .take(count)
+        .map(|bytes| T::from_bits(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+        .collect()
+}
+
+fn cmd_bind_compute_pipeline(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    pipeline: vk::Pipeline,
+) {
+    unsafe {
+        context
+            .device()
+            .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline)
+    };
+}
+
+/// Make the output of a pass visible to the next passes, shaders and transfers.
+fn cmd_end_kernel_pass(context: &Context, command_buffer: vk::CommandBuffer) {
+    cmd_buffer_barrier(
+        context,
+        command_buffer,
+        (
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_WRITE,
+        ),
+        (
+            vk::PipelineStageFlags2::COMPUTE_SHADER
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                | vk::PipelineStageFlags2::ALL_TRANSFER,
+            vk::AccessFlags2::SHADER_READ
+                | vk::AccessFlags2::SHADER_WRITE
+                | vk::AccessFlags2::TRANSFER_READ,
+        ),
+    );
+}
+
+fn cmd_buffer_barrier(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let barrier = vk::MemoryBarrier2::default()
+        .src_stage_mask(src.0)
+        .src_access_mask(src.1)
+        .dst_stage_mask(dst.0)
+        .dst_access_mask(dst.1);
+    let dependency_info =
+        vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SamplerParameters;
+
+    /// The kernels run on a real device, run with `cargo test -- --ignored`
+    /// once the shaders are compiled.
+    fn create_context() -> Arc<Context> {
+        // Shaders are loaded relative to the workspace root.
+        std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../.."))
+            .expect("Failed to find the workspace root");
+        Arc::new(Context::new_headless(None, false).expect("Failed to create headless context"))
+    }
+
+    /// `RGBA32F` texture of `width` x `height` with a nearest sampler.
+    fn create_source(context: &Arc<Context>, width: u32, height: u32, rgba: &[f32]) -> Texture {
+        Texture::from_rgba_32(
+            context,
+            width,
+            height,
+            false,
+            rgba,
+            Some(SamplerParameters {
+                mag_filter: vk::Filter::NEAREST,
+                min_filter: vk::Filter::NEAREST,
+                anisotropy_enabled: false,
+            }),
+        )
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn empty_inputs_are_not_dispatched() {
+        let context = create_context();
+        assert_eq!(reduce::<u32>(&context, &[], ReduceOp::Sum), None);
+        assert!(prefix_sum::<u32>(&context, &[]).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn reduction_matches_cpu() {
+        let context = create_context();
+
+        // Three passes of 512 elements blocks.
+        let values = (0..300_000u32).map(|i| i % 1000).collect::<Vec<_>>();
+        let sum = values
+            .iter()
+            .fold(0u32, |sum, value| sum.wrapping_add(*value));
+        assert_eq!(reduce(&context, &values, ReduceOp::Sum), Some(sum));
+
+        let values = (0..1000i32)
+            .map(|i| (i * 7919) % 2003 - 1001)
+            .collect::<Vec<_>>();
+        let min = values.iter().copied().min();
+        let max = values.iter().copied().max();
+        assert_eq!(reduce(&context, &values, ReduceOp::Min), min);
+        assert_eq!(reduce(&context, &values, ReduceOp::Max), max);
+
+        let values = (0..5000)
+            .map(|i| (i % 17) as f32 * 0.25)
+            .collect::<Vec<_>>();
+        let sum = values.iter().sum::<f32>();
+        let result = reduce(&context, &values, ReduceOp::Sum).unwrap();
+        assert!(
+            (result - sum).abs() < 1e-3 * sum,
+            "{} is not {}",
+            result,
+            sum
+        );
+        assert_eq!(reduce(&context, &[42.0f32], ReduceOp::Max), Some(42.0));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn prefix_sum_matches_cpu() {
+        let context = create_context();
+
+        for count in [1, 511, 512, 513, 70_000] {
+            let values = (0..count).map(|i| i % 7).collect::<Vec<u32>>();
+            let expected = values
+                .iter()
+                .scan(0, |sum, value| {
+                    let previous = *sum;
+                    *sum += value;
+                    Some(previous)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                prefix_sum(&context, &values),
+                expected,
+                "{} elements",
+                count
+            );
+        }
+
+        let values = (0..2000).map(|i| (i % 5) - 2).collect::<Vec<i32>>();
+        let input = create_input_buffer(&context, &values);
+        let output = create_storage_buffer(&context, values.len() as _);
+        let scan = PrefixSum::<i32>::new(&context, &input, &output, values.len() as _);
+        context.execute_one_time_commands(|command_buffer| scan.cmd_scan(command_buffer));
+        assert_eq!(scan.read_total(), values.iter().sum::<i32>());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn depth_pyramid_matches_cpu() {
+        let context = create_context();
+
+        // Odd sizes make texels of the pyramid cover 3 texels of the previous
+        // level, even ones let subgroup passes write two levels.
+        for (width, height) in [(13usize, 7usize), (64, 32)] {
+            let depths = (0..width * height)
+                .map(|i| ((i * 37) % 101) as f32 / 100.0)
+                .collect::<Vec<_>>();
+            let rgba = depths
+                .iter()
+                .flat_map(|depth| [*depth, 0.0, 0.0, 1.0])
+                .collect::<Vec<_>>();
+            let source = create_source(&context, width as _, height as _, &rgba);
+
+            for (reduction, subgroups) in [
+                (DepthReduction::Max, false),
+                (DepthReduction::Min, false),
+                (DepthReduction::Max, true),
+                (DepthReduction::Min, true),
+            ] {
+                let pyramid = DepthPyramid::with_subgroups(&context, &source, reduction, subgroups);
+                context
+                    .execute_one_time_commands(|command_buffer| pyramid.cmd_build(command_buffer));
+                if !subgroups {
+                    assert_eq!(pyramid.pass_count(), pyramid.levels().len());
+                }
+
+                let mut expected = depths.clone();
+                let mut source_size = (width, height);
+                for (index, level) in pyramid.levels().iter().enumerate() {
+                    let size = (
+                        level.image.extent.width as usize,
+                        level.image.extent.height as usize,
+                    );
+                    expected = downsample(&expected, source_size, size, reduction);
+                    assert_eq!(
+                        pyramid.read_level(index),
+                        expected,
+                        "level {} of {}x{}, subgroups {}",
+                        index,
+                        width,
+                        height,
+                        subgroups
+                    );
+                    source_size = size;
+                }
+                assert_eq!(source_size, (1, 1));
+            }
+        }
+    }
+
+    /// Same as `shader/depth_pyramid/depth_pyramid.comp`.
+    fn downsample(
+        source: &[f32],
+        source_size: (usize, usize),
+        size: (usize, usize),
+        reduction: DepthReduction,
+    ) -> Vec<f32> {
+        let reduce = |a: f32, b: f32| match reduction {
+            DepthReduction::Max => a.max(b),
+            DepthReduction::Min => a.min(b),
+        };
+        let covered = |texel: usize, source_size: usize, size: usize| {
+            let first = texel * source_size / size;
+            let last = ((texel + 1) * source_size).div_ceil(size) - 1;
+            first..=last.min(first + 2)
+        };
+        let mut target = Vec::with_capacity(size.0 * size.1);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let depth = covered(y, source_size.1, size.1)
+                    .flat_map(|sy| {
+                        covered(x, source_size.0, size.0).map(move |sx| sy * source_size.0 + sx)
+                    })
+                    .map(|index| source[index])
+                    .reduce(reduce)
+                    .unwrap();
+                target.push(depth);
+            }
+        }
+        target
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn luminance_histogram_matches_cpu() {
+        let context = create_context();
+
+        let (min_log_luminance, max_log_luminance) = (-8.0, 8.0);
+        let bin_range =
+            (max_log_luminance - min_log_luminance) / (LUMINANCE_HISTOGRAM_BIN_COUNT - 2) as f32;
+
+        // Gray texels at the center of the bins, and black ones, over several groups.
+        let (width, height) = (40u32, 21u32);
+        let mut expected = vec![0u32; LUMINANCE_HISTOGRAM_BIN_COUNT];
+        let rgba = (0..width * height)
+            .flat_map(|index| {
+                let bin = (index as usize * 13) % LUMINANCE_HISTOGRAM_BIN_COUNT;
+                expected[bin] += 1;
+                let luminance = match bin {
+                    0 => 0.0,
+                    _ => (min_log_luminance + (bin as f32 - 0.5) * bin_range).exp2(),
+                };
+                [luminance, luminance, luminance, 1.0]
+            })
+            .collect::<Vec<_>>();
+        let source = create_source(&context, width, height, &rgba);
+
+        for subgroups in [false, true] {
+            let histogram = LuminanceHistogram::with_subgroups(
+                &context,
+                &source,
+                min_log_luminance,
+                max_log_luminance,
+                subgroups,
+            );
+            context.execute_one_time_commands(|command_buffer| histogram.cmd_build(command_buffer));
+            assert_eq!(histogram.read_bins(), expected, "subgroups {}", subgroups);
+            assert_eq!(histogram.bin_log_luminance(0), None);
+
+            // Clearing the bins each time, the counts do not accumulate.
+            context.execute_one_time_commands(|command_buffer| histogram.cmd_build(command_buffer));
+            assert_eq!(histogram.read_bins(), expected, "subgroups {}", subgroups);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{image::cmd_image_barrier, Context, Descriptors, Texture};
+use ash::vk;
+use std::{mem::size_of, sync::Arc};
+
+const GROUP_SIZE: u32 = 8;
+/// Push constants of the passes, 4 32 bits values.
+const PUSH_CONSTANTS_SIZE: u32 = 4 * size_of::<u32>() as u32;
+
+/// Panic if targets of any format cannot be written from compute shaders.
+pub(crate) fn check_write_without_format(context: &Context) {
+    let features = unsafe {
+        context
+            .instance()
+            .get_physical_device_features(context.physical_device())
+    };
+    assert!(
+        features.shader_storage_image_write_without_format == vk::TRUE,
+        "Compute passes writing any format require shaderStorageImageWriteWithoutFormat"
+    );
+}
+
+/// Create one set per pass, sampling the texture and writing the view.
+pub(crate) fn create_descriptors(
+    context: &Arc<Context>,
+    passes: &[(&Texture, vk::ImageView)],
+) -> Descriptors {
+    let device = context.device();
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+    ];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let set_count = passes.len() as u32;
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: set_count,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: set_count,
+        },
+    ];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(set_count);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = vec![layout; passes.len()];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    for (set, (input, output)) in sets.iter().zip(passes) {
+        let input_info = [vk::DescriptorImageInfo::default()
+            .image_layout(input.sampling_layout())
+            .image_view(input.sampling_view())
+            .sampler(input.sampler.expect("Pass input must have a sampler"))];
+        let output_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(*output)];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&input_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&output_info),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+pub(crate) fn create_pipeline_layout(
+    context: &Context,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::COMPUTE,
+        offset: 0,
+        size: PUSH_CONSTANTS_SIZE,
+    }];
+    let set_layouts = [set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    }
+}
+
+pub(crate) fn cmd_dispatch_pass(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    set: vk::DescriptorSet,
+    parameters: &[u32; 4],
+    extent: vk::Extent3D,
+) {
+    let groups = (
+        extent.width.div_ceil(GROUP_SIZE),
+        extent.height.div_ceil(GROUP_SIZE),
+    );
+    cmd_dispatch_groups(
+        context,
+        command_buffer,
+        pipeline_layout,
+        set,
+        parameters,
+        groups,
+    );
+}
+
+/// Dispatch a pass whose group count does not derive from an image extent.
+pub(crate) fn cmd_dispatch_groups(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    set: vk::DescriptorSet,
+    parameters: &[u32; 4],
+    (group_count_x, group_count_y): (u32, u32),
+) {
+    let device = context.device();
+    let bytes = parameters
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect::<Vec<_>>();
+    unsafe {
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            &bytes,
+        );
+        device.cmd_dispatch(command_buffer, group_count_x, group_count_y, 1);
+    }
+}
+
+/// Discard the content of `image` before a pass writes it.
+pub(crate) fn cmd_begin_write(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+) {
+    cmd_image_barrier(
+        context,
+        command_buffer,
+        image,
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+        (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::NONE,
+        ),
+        (
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_WRITE,
+        ),
+    );
+}
+
+/// Make the output of a pass readable by the next passes and shaders.
+pub(crate) fn cmd_end_write(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+) {
+    cmd_image_barrier(
+        context,
+        command_buffer,
+        image,
+        (
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        ),
+        (
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_WRITE,
+        ),
+        (
+            vk::PipelineStageFlags2::COMPUTE_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::SHADER_READ,
+        ),
+    );
+}
@@ -0,0 +1,250 @@
+use crate::{RendererSettings, DEFAULT_FOV, DEFAULT_FPS_MOVE_SPEED};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+/// Delay between two checks of the modification time of the config file.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Settings of the post processing passes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostConfig {
+    /// Sharpness of the upscale pass, see [`crate::CasUpscale::set_sharpness`].
+    pub sharpness: f32,
+}
+
+impl Default for PostConfig {
+    fn default() -> Self {
+        Self { sharpness: 0.5 }
+    }
+}
+
+/// Renderer settings read from a TOML file.
+///
+/// ```toml
+/// clear_color = [0.1, 0.1, 0.1, 1.0]
+/// camera_speed = 6.0
+/// fov = 45.0
+/// vsync = false
+/// max_fps = 120
+///
+/// [post]
+/// sharpness = 0.5
+///
+/// [renderer]
+/// msaa_samples = 4
+/// ```
+///
+/// Missing entries take their default value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    pub clear_color: [f32; 4],
+    pub camera_speed: f32,
+    /// Vertical field of view of the camera, in degrees.
+    pub fov: f32,
+    pub vsync: bool,
+    /// Cap of the frame rate, see [`crate::FpsLimiter`]. Unlimited if absent.
+    pub max_fps: Option<u32>,
+    pub hdr: bool,
+    pub post: PostConfig,
+    pub renderer: RendererSettings,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            camera_speed: DEFAULT_FPS_MOVE_SPEED,
+            fov: DEFAULT_FOV,
+            vsync: false,
+            max_fps: None,
+            hdr: false,
+            post: PostConfig::default(),
+            renderer: RendererSettings::default(),
+        }
+    }
+}
+
+impl RendererConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Changes from `previous` to this config, all the settings without previous config.
+    pub fn changes_from(&self, previous: Option<&Self>) -> Vec<ConfigChange> {
+        let all = [
+            ConfigChange::ClearColor(self.clear_color),
+            ConfigChange::CameraSpeed(self.camera_speed),
+            ConfigChange::Fov(self.fov),
+            ConfigChange::Vsync(self.vsync),
+            ConfigChange::MaxFps(self.max_fps),
+            ConfigChange::Hdr(self.hdr),
+            ConfigChange::Post(self.post),
+            ConfigChange::Renderer(self.renderer),
+        ];
+        let Some(previous) = previous else {
+            return all.to_vec();
+        };
+        let previous = previous.changes_from(None);
+        all.into_iter()
+            .zip(previous)
+            .filter(|(change, previous)| change != previous)
+            .map(|(change, _)| change)
+            .collect()
+    }
+}
+
+/// What has to be recreated to apply a [`ConfigChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigRebuild {
+    /// The change can be applied right away.
+    None,
+    Swapchain,
+    Pipelines,
+}
+
+/// A setting whose value changed in the config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigChange {
+    ClearColor([f32; 4]),
+    CameraSpeed(f32),
+    Fov(f32),
+    Vsync(bool),
+    MaxFps(Option<u32>),
+    Hdr(bool),
+    Post(PostConfig),
+    Renderer(RendererSettings),
+}
+
+impl ConfigChange {
+    pub fn rebuild(&self) -> ConfigRebuild {
+        match self {
+            Self::ClearColor(_)
+            | Self::CameraSpeed(_)
+            | Self::Fov(_)
+            | Self::MaxFps(_)
+            | Self::Post(_) => ConfigRebuild::None,
+            Self::Vsync(_) | Self::Hdr(_) => ConfigRebuild::Swapchain,
+            Self::Renderer(_) => ConfigRebuild::Pipelines,
+        }
+    }
+}
+
+/// Watch a [`RendererConfig`] file and report its changes.
+///
+/// Call [`ConfigWatcher::poll`] once per frame and apply the returned changes,
+/// which are safe to apply live. Changes needing a rebuild are queued until the
+/// application takes them with [`ConfigWatcher::take_queued`], when it recreates
+/// the swapchain or the pipelines.
+///
+/// The file is read the first time it is found, reporting all its settings. An
+/// invalid file is logged and ignored, the previous settings staying in place.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    config: Option<RendererConfig>,
+    modified: Option<SystemTime>,
+    last_poll: Option<Instant>,
+    queued: Vec<ConfigChange>,
+}
+
+impl ConfigWatcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            config: None,
+            modified: None,
+            last_poll: None,
+            queued: Vec::new(),
+        }
+    }
+}
+
+impl ConfigWatcher {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Last valid config read, `None` before the file is first found.
+    pub fn config(&self) -> Option<&RendererConfig> {
+        self.config.as_ref()
+    }
+
+    /// Reload the file if it was modified since the last call.
+    ///
+    /// # Returns
+    ///
+    /// The changes that can be applied right away.
+    pub fn poll(&mut self) -> Vec<ConfigChange> {
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last_poll| now - last_poll < POLL_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+
+        let Ok(modified) = fs::metadata(&self.path).and_then(|metadata| metadata.modified()) else {
+            return Vec::new();
+        };
+        if self.modified == Some(modified) {
+            return Vec::new();
+        }
+        self.modified = Some(modified);
+
+        let config = match RendererConfig::from_file(&self.path) {
+            Ok(config) => config,
+            Err(error) => {
+                tracing::warn!("Ignoring invalid config {}: {}", self.path.display(), error);
+                return Vec::new();
+            }
+        };
+        let changes = config.changes_from(self.config.as_ref());
+        self.config = Some(config);
+        tracing::info!(
+            "Config {} reloaded with {} change(s)",
+            self.path.display(),
+            changes.len()
+        );
+
+        let (live, queued): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|change| change.rebuild() == ConfigRebuild::None);
+        for change in queued {
+            // Only the latest value of a setting is kept.
+            self.queued
+                .retain(|queued| std::mem::discriminant(queued) != std::mem::discriminant(&change));
+            self.queued.push(change);
+        }
+        live
+    }
+
+    /// True if a queued change needs `rebuild`.
+    pub fn needs_rebuild(&self, rebuild: ConfigRebuild) -> bool {
+        self.queued.iter().any(|change| change.rebuild() == rebuild)
+    }
+
+    /// Take the queued changes needing `rebuild`.
+    pub fn take_queued(&mut self, rebuild: ConfigRebuild) -> Vec<ConfigChange> {
+        let (taken, kept) = self
+            .queued
+            .drain(..)
+            .partition(|change| change.rebuild() == rebuild);
+        self.queued = kept;
+        taken
+    }
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONFIG_PATH)
+    }
+}
@@ -0,0 +1,227 @@
+use super::Context;
+use ash::vk;
+use std::{ffi::CStr, fmt::Write};
+
+/// Extensions and core features enabled when the device was created.
+#[derive(Debug, Clone)]
+pub struct EnabledDeviceFeatures {
+    pub extensions: Vec<String>,
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+impl EnabledDeviceFeatures {
+    pub fn has_extension(&self, name: &CStr) -> bool {
+        let name = name.to_string_lossy();
+        self.extensions.iter().any(|extension| *extension == name)
+    }
+}
+
+/// A queue family of the physical device and what the context uses it for.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueFamilyInfo {
+    pub index: u32,
+    pub properties: vk::QueueFamilyProperties,
+    pub graphics_compute: bool,
+    pub present: bool,
+    pub transfer: bool,
+}
+
+/// Read-only queries of the device, surface and capabilities of the context.
+impl Context {
+    pub fn physical_device_properties(&self) -> vk::PhysicalDeviceProperties {
+        unsafe {
+            self.instance()
+                .get_physical_device_properties(self.physical_device())
+        }
+    }
+
+    pub fn enabled_device_features(&self) -> &EnabledDeviceFeatures {
+        self.shared_context.enabled_device_features()
+    }
+
+    pub fn queue_families(&self) -> Vec<QueueFamilyInfo> {
+        let indices = self.queue_families_indices();
+        let properties = unsafe {
+            self.instance()
+                .get_physical_device_queue_family_properties(self.physical_device())
+        };
+        properties
+            .into_iter()
+            .enumerate()
+            .map(|(index, properties)| QueueFamilyInfo {
+                index: index as _,
+                properties,
+                graphics_compute: index as u32 == indices.graphics_index,
+                present: !self.is_headless() && index as u32 == indices.present_index,
+                transfer: indices.transfer_index == Some(index as u32),
+            })
+            .collect()
+    }
+
+    /// Capabilities of the surface, `None` for headless contexts.
+    pub fn surface_capabilities(&self) -> Option<vk::SurfaceCapabilitiesKHR> {
+        (!self.is_headless()).then(|| unsafe {
+            self.surface()
+                .get_physical_device_surface_capabilities(
+                    self.physical_device(),
+                    self.surface_khr(),
+                )
+                .expect("Failed to get surface capabilities")
+        })
+    }
+
+    /// Formats of the surface, empty for headless contexts.
+    pub fn surface_formats(&self) -> Vec<vk::SurfaceFormatKHR> {
+        if self.is_headless() {
+            return Vec::new();
+        }
+        unsafe {
+            self.surface()
+                .get_physical_device_surface_formats(self.physical_device(), self.surface_khr())
+                .expect("Failed to get surface formats")
+        }
+    }
+
+    /// Present modes of the surface, empty for headless contexts.
+    pub fn present_modes(&self) -> Vec<vk::PresentModeKHR> {
+        if self.is_headless() {
+            return Vec::new();
+        }
+        unsafe {
+            self.surface()
+                .get_physical_device_surface_present_modes(
+                    self.physical_device(),
+                    self.surface_khr(),
+                )
+                .expect("Failed to get surface present modes")
+        }
+    }
+
+    /// Human readable report of the device and its capabilities, to attach to bug reports.
+    pub fn capabilities_report(&self) -> String {
+        let properties = self.physical_device_properties();
+        let limits = properties.limits;
+        let enabled = self.enabled_device_features();
+        let mut report = String::new();
+
+        let _ = writeln!(report, "Device: {}", self.physical_device_info());
+        let _ = writeln!(
+            report,
+            "Driver version: {}",
+            format_driver_version(properties.vendor_id, properties.driver_version)
+        );
+        let _ = writeln!(
+            report,
+            "Vendor id: {:#06x}, device id: {:#06x}",
+            properties.vendor_id, properties.device_id
+        );
+
+        let _ = writeln!(report, "\nEnabled extensions:");
+        for extension in enabled.extensions.iter() {
+            let _ = writeln!(report, "  {}", extension);
+        }
+
+        let _ = writeln!(report, "\nQueue families:");
+        for family in self.queue_families() {
+            let mut usage = String::new();
+            if family.graphics_compute {
+                usage.push_str(", graphics");
+            }
+            if family.present {
+                usage.push_str(", present");
+            }
+            if family.transfer {
+                usage.push_str(", transfer");
+            }
+            let _ = writeln!(
+                report,
+                "  #{} {:?} x{}{}",
+                family.index, family.properties.queue_flags, family.properties.queue_count, usage
+            );
+        }
+
+        let memory = self.get_mem_properties();
+        let _ = writeln!(report, "\nMemory heaps:");
+        for (index, heap) in memory.memory_heaps[..memory.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+        {
+            let _ = writeln!(
+                report,
+                "  #{} {} MiB {:?}",
+                index,
+                heap.size / (1024 * 1024),
+                heap.flags
+            );
+        }
+
+        let _ = writeln!(report, "\nLimits:");
+        let _ = writeln!(report, "  max image 2D: {}", limits.max_image_dimension2_d);
+        let _ = writeln!(
+            report,
+            "  max per stage samplers: {}",
+            limits.max_per_stage_descriptor_samplers
+        );
+        let _ = writeln!(
+            report,
+            "  max uniform buffer range: {}",
+            limits.max_uniform_buffer_range
+        );
+        let _ = writeln!(
+            report,
+            "  max storage buffer range: {}",
+            limits.max_storage_buffer_range
+        );
+        let _ = writeln!(
+            report,
+            "  max push constants size: {}",
+            limits.max_push_constants_size
+        );
+        let _ = writeln!(
+            report,
+            "  max sampler anisotropy: {}",
+            limits.max_sampler_anisotropy
+        );
+        let _ = writeln!(
+            report,
+            "  framebuffer color sample counts: {:?}",
+            limits.framebuffer_color_sample_counts
+        );
+
+        if let Some(capabilities) = self.surface_capabilities() {
+            let _ = writeln!(report, "\nSurface:");
+            let _ = writeln!(
+                report,
+                "  images: {}..{}",
+                capabilities.min_image_count, capabilities.max_image_count
+            );
+            let _ = writeln!(report, "  present modes: {:?}", self.present_modes());
+            let _ = writeln!(report, "  hdr: {}", self.has_hdr_support());
+        }
+
+        report
+    }
+}
+
+/// Format the driver version with the encoding of the vendor, Vulkan's encoding by default.
+pub fn format_driver_version(vendor_id: u32, version: u32) -> String {
+    const NVIDIA: u32 = 0x10de;
+    const INTEL: u32 = 0x8086;
+
+    match vendor_id {
+        NVIDIA => format!(
+            "{}.{}.{}.{}",
+            (version >> 22) & 0x3ff,
+            (version >> 14) & 0xff,
+            (version >> 6) & 0xff,
+            version & 0x3f
+        ),
+        INTEL if cfg!(windows) => format!("{}.{}", version >> 14, version & 0x3fff),
+        _ => format!(
+            "{}.{}.{}",
+            vk::api_version_major(version),
+            vk::api_version_minor(version),
+            vk::api_version_patch(version)
+        ),
+    }
+}
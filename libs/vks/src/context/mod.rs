@@ -1,25 +1,84 @@
+mod introspection;
 mod shared;
 
+pub use self::introspection::*;
 pub use self::shared::HDR_SURFACE_FORMAT;
 
 use self::shared::*;
-use crate::MsaaSamples;
+use crate::{
+    Allocation, ColorPolicy, CrashDiagnostics, DeletionQueue, DrawDebugId, MemoryAllocator,
+    MemoryStats, MsaaSamples, PhysicalDeviceInfo, SamplerCache, SamplerKey, ShadingRateState,
+    ShadingRateSupport, SubgroupSupport, VksError,
+};
 use ash::{
-    khr::{dynamic_rendering, surface, synchronization2},
+    ext::debug_utils,
+    khr::{dynamic_rendering, present_wait, surface, synchronization2},
     vk, Device, Instance,
 };
-use std::sync::Arc;
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+use std::{
+    ffi::CString,
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, MutexGuard,
+    },
+};
+#[cfg(feature = "winit")]
 use winit::window::Window;
 
+/// Vulkan context of a thread.
+///
+/// The device is destroyed when the last `Arc<Context>` is dropped, so every
+/// resource of the crate holds one. Raw handles created from [`Context::device`]
+/// must be destroyed by their owner before that, and in debug builds
+/// destroying a tracked resource after the device is an assertion failure.
 pub struct Context {
     shared_context: Arc<SharedContext>,
     general_command_pool: vk::CommandPool,
     transient_command_pool: vk::CommandPool,
+    color_policy: ColorPolicy,
+    draw_debug_ids: Arc<AtomicBool>,
+    /// Shared with the contexts of the other threads.
+    deletion_queue: Arc<DeletionQueue>,
 }
 
 impl Context {
-    pub fn new(window: &Window, enable_debug: bool) -> Self {
-        let shared_context = Arc::new(SharedContext::new(window, enable_debug));
+    #[cfg(feature = "winit")]
+    pub fn new(window: &Window, enable_debug: bool) -> Result<Self, VksError> {
+        SharedContext::new(window, enable_debug)
+            .map(|shared_context| Self::from_shared_context(shared_context, enable_debug))
+    }
+
+    /// Create a context presenting to a window owned by the host application,
+    /// for embedding in a Qt, SDL or editor window without winit.
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid and the window must outlive the context.
+    pub unsafe fn from_raw_handles(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        enable_debug: bool,
+    ) -> Result<Self, VksError> {
+        SharedContext::from_raw_handles(display_handle, window_handle, enable_debug)
+            .map(|shared_context| Self::from_shared_context(shared_context, enable_debug))
+    }
+
+    /// Create a context without window on another gpu, see [`crate::enumerate_physical_devices`].
+    ///
+    /// Use it for offline work, like baking, so it does not stall the
+    /// interactive renderer. It can be moved to a worker thread and its results
+    /// brought back through host memory, see [`crate::Buffer::read_back`] and
+    /// [`crate::Image::read_back`]. It has no swapchain and presents nothing.
+    pub fn new_headless(device_index: Option<usize>, enable_debug: bool) -> Result<Self, VksError> {
+        SharedContext::new_headless(device_index, enable_debug)
+            .map(|shared_context| Self::from_shared_context(shared_context, enable_debug))
+    }
+
+    fn from_shared_context(shared_context: SharedContext, enable_debug: bool) -> Self {
+        let shared_context = Arc::new(shared_context);
         let general_command_pool = create_command_pool(
             shared_context.device(),
             shared_context.queue_families_indices,
@@ -31,10 +90,18 @@ impl Context {
             vk::CommandPoolCreateFlags::TRANSIENT,
         );
 
+        let color_policy = ColorPolicy {
+            audit: enable_debug,
+            ..Default::default()
+        };
+
         Self {
             shared_context,
             general_command_pool,
             transient_command_pool,
+            color_policy,
+            draw_debug_ids: Arc::new(AtomicBool::new(false)),
+            deletion_queue: Arc::new(DeletionQueue::default()),
         }
     }
 
@@ -55,6 +122,9 @@ impl Context {
             shared_context,
             general_command_pool,
             transient_command_pool,
+            color_policy: self.color_policy,
+            draw_debug_ids: Arc::clone(&self.draw_debug_ids),
+            deletion_queue: Arc::clone(&self.deletion_queue),
         }
     }
 }
@@ -88,10 +158,26 @@ impl Context {
         self.shared_context.surface_khr()
     }
 
+    pub fn is_headless(&self) -> bool {
+        self.shared_context.is_headless()
+    }
+
     pub fn physical_device(&self) -> vk::PhysicalDevice {
         self.shared_context.physical_device()
     }
 
+    pub fn physical_device_info(&self) -> &PhysicalDeviceInfo {
+        self.shared_context.physical_device_info()
+    }
+
+    pub fn physical_device_limits(&self) -> vk::PhysicalDeviceLimits {
+        unsafe {
+            self.instance()
+                .get_physical_device_properties(self.physical_device())
+                .limits
+        }
+    }
+
     pub fn device(&self) -> &Device {
         self.shared_context.device()
     }
@@ -104,6 +190,11 @@ impl Context {
         self.shared_context.graphics_compute_queue()
     }
 
+    /// The dedicated transfer queue, or the graphics queue when the device has none.
+    pub fn transfer_queue(&self) -> vk::Queue {
+        self.shared_context.transfer_queue()
+    }
+
     pub fn present_queue(&self) -> vk::Queue {
         self.shared_context.present_queue()
     }
@@ -120,6 +211,84 @@ impl Context {
         self.shared_context.has_hdr_support()
     }
 
+    pub fn shading_rate_support(&self) -> ShadingRateSupport {
+        self.shared_context.shading_rate_support()
+    }
+
+    pub fn subgroup_support(&self) -> SubgroupSupport {
+        self.shared_context.subgroup_support()
+    }
+
+    /// Samplers shared by all the contexts of the device, see [`SamplerCache`].
+    pub fn sampler_cache(&self) -> &SamplerCache {
+        self.shared_context.sampler_cache()
+    }
+
+    /// Get the sampler for `key` from the [`SamplerCache`].
+    pub fn get_sampler(&self, key: SamplerKey) -> vk::Sampler {
+        self.sampler_cache().get(self.device(), key)
+    }
+
+    /// Allocator of the device memory shared by all the contexts of the device,
+    /// see [`MemoryAllocator`].
+    pub fn allocator(&self) -> &MemoryAllocator {
+        self.shared_context.allocator()
+    }
+
+    /// Allocate memory with `mem_properties` for a resource with `requirements`.
+    pub fn allocate_memory(
+        &self,
+        requirements: vk::MemoryRequirements,
+        mem_properties: vk::MemoryPropertyFlags,
+    ) -> Allocation {
+        self.allocator()
+            .allocate(self.device(), requirements, mem_properties)
+    }
+
+    /// Release memory allocated with [`Context::allocate_memory`].
+    pub fn free_memory(&self, allocation: Allocation) {
+        self.allocator().free(self.device(), allocation)
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.allocator().stats()
+    }
+
+    /// Pipeline cache shared by all the contexts of the device.
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.shared_context.pipeline_cache()
+    }
+
+    /// True if pipelines can be created with [`crate::PipelineCompileMode::CacheOnly`].
+    pub fn has_pipeline_cache_control(&self) -> bool {
+        self.shared_context.has_pipeline_cache_control()
+    }
+
+    /// True if a [`crate::TextureRegistry`] can be created.
+    pub fn has_bindless_support(&self) -> bool {
+        self.shared_context.has_bindless_support()
+    }
+
+    /// Set the shading rate of the next draws of pipelines with the
+    /// `FRAGMENT_SHADING_RATE_KHR` dynamic state.
+    pub fn cmd_set_fragment_shading_rate(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        state: ShadingRateState,
+    ) {
+        let loader = self
+            .shared_context
+            .fragment_shading_rate()
+            .expect("Fragment shading rate is not supported by the device");
+        unsafe {
+            (loader.fp().cmd_set_fragment_shading_rate_khr)(
+                command_buffer,
+                &state.fragment_size,
+                &state.combiner_ops,
+            )
+        };
+    }
+
     pub fn general_command_pool(&self) -> vk::CommandPool {
         self.general_command_pool
     }
@@ -127,6 +296,38 @@ impl Context {
     pub fn transient_command_pool(&self) -> vk::CommandPool {
         self.transient_command_pool
     }
+
+    pub fn color_policy(&self) -> ColorPolicy {
+        self.color_policy
+    }
+
+    /// Resources to destroy once the frames in flight are complete.
+    pub fn deletion_queue(&self) -> &DeletionQueue {
+        &self.deletion_queue
+    }
+
+    pub fn crash_diagnostics(&self) -> &CrashDiagnostics {
+        self.shared_context.crash_diagnostics()
+    }
+
+    pub fn present_wait(&self) -> Option<&present_wait::Device> {
+        self.shared_context.present_wait()
+    }
+
+    pub fn debug_utils(&self) -> Option<&debug_utils::Device> {
+        self.shared_context.debug_utils()
+    }
+
+    pub fn draw_debug_ids(&self) -> bool {
+        self.draw_debug_ids.load(Ordering::Relaxed)
+    }
+
+    /// Enable the push of a [`DrawDebugId`] before each draw.
+    ///
+    /// Shared by all the contexts created with [`Context::new_thread`].
+    pub fn set_draw_debug_ids(&self, enabled: bool) {
+        self.draw_debug_ids.store(enabled, Ordering::Relaxed);
+    }
 }
 
 impl Context {
@@ -163,14 +364,126 @@ impl Context {
             .execute_one_time_commands(self.transient_command_pool, executor)
     }
 
+    /// Lock the graphics queue for a submission or a present.
+    ///
+    /// Required as soon as resources are loaded on another thread.
+    pub fn lock_queue(&self) -> MutexGuard<'_, ()> {
+        self.shared_context.lock_queue()
+    }
+
+    /// Lock the queue returned by [`Context::transfer_queue`].
+    pub fn lock_transfer_queue(&self) -> MutexGuard<'_, ()> {
+        self.shared_context.lock_transfer_queue()
+    }
+
     pub fn graphics_queue_wait_idle(&self) {
         self.shared_context.graphics_queue_wait_idle()
     }
+
+    /// Wait for the device to be idle. A lost device is logged instead of panicking.
+    pub fn wait_idle(&self) {
+        self.shared_context.wait_idle()
+    }
+
+    /// True once an operation returned `ERROR_DEVICE_LOST`.
+    pub fn is_device_lost(&self) -> bool {
+        self.shared_context.is_device_lost()
+    }
+
+    /// Begin the pass `name` in a command buffer submitted to the graphics queue.
+    ///
+    /// See [`CrashDiagnostics`].
+    pub fn cmd_begin_pass(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        self.crash_diagnostics()
+            .cmd_begin_pass(command_buffer, self.graphics_compute_queue(), name)
+    }
+
+    pub fn cmd_end_pass(&self, command_buffer: vk::CommandBuffer) {
+        self.crash_diagnostics().cmd_end_pass(command_buffer)
+    }
+
+    /// Name `handle` in the validation messages and in capture tools such as RenderDoc.
+    ///
+    /// Does nothing unless validation is enabled.
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(debug_utils) = self.debug_utils() else {
+            return;
+        };
+        let object_name = CString::new(name).unwrap_or_default();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&object_name);
+        if let Err(error) = unsafe { debug_utils.set_debug_utils_object_name(&name_info) } {
+            tracing::warn!("Failed to name object {}: {}", name, error);
+        }
+    }
+
+    /// Begin the debug label `name` in `command_buffer`, ended by [`Context::cmd_end_debug_label`].
+    ///
+    /// Unlike [`Context::cmd_begin_pass`] it is not recorded for the crash reports,
+    /// meant for the steps nested in a pass. Does nothing unless validation is enabled.
+    pub fn cmd_begin_debug_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        if let Some(debug_utils) = self.debug_utils() {
+            let label_name = CString::new(name).unwrap_or_default();
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&label_name);
+            unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+        }
+    }
+
+    pub fn cmd_end_debug_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = self.debug_utils() {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    /// Identify the next draw of `command_buffer` if draw ids are enabled.
+    ///
+    /// `id` is pushed at `offset` of the push constants of `layout`, which must
+    /// contain a [`DrawDebugId::push_constant_range`]. A debug label named after
+    /// the id is also inserted when validation is enabled.
+    pub fn cmd_set_draw_debug_id(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        id: DrawDebugId,
+    ) {
+        if !self.draw_debug_ids() {
+            return;
+        }
+
+        unsafe {
+            self.device()
+                .cmd_push_constants(command_buffer, layout, stage_flags, offset, &id.as_bytes())
+        };
+        if let Some(debug_utils) = self.debug_utils() {
+            let label_name = CString::new(id.label()).unwrap();
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&label_name);
+            unsafe { debug_utils.cmd_insert_debug_utils_label(command_buffer, &label) };
+        }
+    }
+
+    /// Write a crash report at `path`. To call once the device is lost.
+    pub fn write_crash_report<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.shared_context.write_crash_report(path)
+    }
+
+    /// Panic because of `error`. If the device was lost, a crash report
+    /// is written to [`crate::CRASH_REPORT_PATH`] first.
+    pub fn handle_device_error(&self, error: vk::Result, message: &str) -> ! {
+        self.shared_context.handle_device_error(error, message)
+    }
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
         let device = self.shared_context.device();
+        // Resources holding no context, the last context sharing the queue destroys them.
+        if Arc::strong_count(&self.deletion_queue) == 1 && self.deletion_queue.pending_count() > 0 {
+            self.shared_context.wait_idle();
+            self.deletion_queue.flush(device);
+        }
         unsafe {
             device.destroy_command_pool(self.transient_command_pool, None);
             device.destroy_command_pool(self.general_command_pool, None);
@@ -1,14 +1,36 @@
-use crate::{debug::*, swapchain::*, MsaaSamples};
+use crate::{
+    bindless::{bindless_features, query_bindless_support},
+    crash::{CrashDiagnostics, CrashExtensions, CRASH_REPORT_PATH},
+    debug::*,
+    latency::query_present_wait_support,
+    leak_tracker::{track_device_created, track_device_destroyed},
+    pipeline_compiler::query_pipeline_cache_control_support,
+    swapchain::*,
+    EnabledDeviceFeatures, LeakSnapshot, MemoryAllocator, MsaaSamples, PhysicalDeviceInfo,
+    SamplerCache, ShadingRateSupport, SubgroupSupport, VksError,
+};
 use ash::{
-    ext::debug_utils,
-    khr::{dynamic_rendering, surface, swapchain, synchronization2},
+    ext::{debug_utils, descriptor_indexing, pipeline_creation_cache_control},
+    khr::{
+        dynamic_rendering, fragment_shading_rate, present_id, present_wait, surface, swapchain,
+        synchronization2,
+    },
     vk, Device, Entry, Instance,
 };
+#[cfg(feature = "winit")]
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use std::{
     ffi::{CStr, CString},
+    io,
     mem::size_of,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
 };
+#[cfg(feature = "winit")]
 use winit::window::Window;
 
 pub const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
@@ -20,34 +42,63 @@ pub struct SharedContext {
     _entry: Entry,
     instance: Instance,
     debug_report_callback: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
+    debug_utils: Option<debug_utils::Device>,
     surface: surface::Instance,
     surface_khr: vk::SurfaceKHR,
     physical_device: vk::PhysicalDevice,
+    physical_device_info: PhysicalDeviceInfo,
     device: Device,
     pub queue_families_indices: QueueFamiliesIndices,
     graphics_compute_queue: vk::Queue,
     present_queue: vk::Queue,
+    transfer_queue: Option<vk::Queue>,
     dynamic_rendering: dynamic_rendering::Device,
     synchronization2: synchronization2::Device,
+    shading_rate_support: ShadingRateSupport,
+    fragment_shading_rate: Option<fragment_shading_rate::Device>,
+    subgroup_support: SubgroupSupport,
+    present_wait: Option<present_wait::Device>,
     has_hdr_support: bool,
+    enabled_device_features: EnabledDeviceFeatures,
+    sampler_cache: SamplerCache,
+    allocator: MemoryAllocator,
+    pipeline_cache: vk::PipelineCache,
+    has_pipeline_cache_control: bool,
+    has_bindless_support: bool,
+    queue_lock: Mutex<()>,
+    transfer_queue_lock: Mutex<()>,
+    crash_diagnostics: CrashDiagnostics,
+    device_lost: AtomicBool,
 }
 
 impl SharedContext {
-    pub fn new(window: &Window, enable_debug: bool) -> Self {
-        let entry =  Entry::linked() ;
-        let instance = create_instance(&entry, window, enable_debug);
-
-        let surface = surface::Instance::new(&entry, &instance);
-        let surface_khr = unsafe {
-            ash_window::create_surface(
-                &entry,
-                &instance,
+    #[cfg(feature = "winit")]
+    pub fn new(window: &Window, enable_debug: bool) -> Result<Self, VksError> {
+        unsafe {
+            Self::from_raw_handles(
                 window.display_handle().unwrap().as_raw(),
-                window.window_handle()  .unwrap().as_raw(),
-                None,
+                window.window_handle().unwrap().as_raw(),
+                enable_debug,
             )
-            .expect("Failed to create surface")
-        };
+        }
+    }
+
+    /// Create a context presenting to a window owned by the host application.
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid and the window must outlive the context.
+    pub unsafe fn from_raw_handles(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        enable_debug: bool,
+    ) -> Result<Self, VksError> {
+        let entry = Entry::linked();
+        let instance = create_instance(&entry, Some(display_handle), enable_debug)?;
+
+        let surface = surface::Instance::new(&entry, &instance);
+        let surface_khr =
+            ash_window::create_surface(&entry, &instance, display_handle, window_handle, None)?;
 
         let debug_report_callback = if enable_debug {
             Some(setup_debug_messenger(&entry, &instance))
@@ -56,44 +107,186 @@ impl SharedContext {
         };
 
         let (physical_device, queue_families_indices) =
-            pick_physical_device(&instance, &surface, surface_khr);
+            pick_physical_device(&instance, &surface, surface_khr)?;
+
+        Self::create(
+            SharedContextCreateInfo {
+                entry,
+                instance,
+                debug_report_callback,
+                surface,
+                surface_khr,
+                physical_device,
+                queue_families_indices,
+            },
+            enable_debug,
+        )
+    }
+
+    /// Create a context without surface on the physical device at `device_index`
+    /// of [`crate::enumerate_physical_devices`], or on the first suitable one.
+    ///
+    /// It has its own instance and device so its work never waits for the
+    /// device of the window, only graphics and compute commands are available.
+    pub fn new_headless(device_index: Option<usize>, enable_debug: bool) -> Result<Self, VksError> {
+        let entry = Entry::linked();
+        let instance = create_instance(&entry, None, enable_debug)?;
+        let surface = surface::Instance::new(&entry, &instance);
 
-        let (device, graphics_compute_queue, present_queue) =
-            create_tracingical_device_with_graphics_queue(
-                &instance,
+        let debug_report_callback = if enable_debug {
+            Some(setup_debug_messenger(&entry, &instance))
+        } else {
+            None
+        };
+
+        let (physical_device, queue_families_indices) =
+            pick_headless_physical_device(&instance, device_index)?;
+
+        Self::create(
+            SharedContextCreateInfo {
+                entry,
+                instance,
+                debug_report_callback,
+                surface,
+                surface_khr: vk::SurfaceKHR::null(),
                 physical_device,
                 queue_families_indices,
-            );
+            },
+            enable_debug,
+        )
+    }
+
+    fn create(create_info: SharedContextCreateInfo, enable_debug: bool) -> Result<Self, VksError> {
+        let SharedContextCreateInfo {
+            entry,
+            instance,
+            debug_report_callback,
+            surface,
+            surface_khr,
+            physical_device,
+            queue_families_indices,
+        } = create_info;
+        let headless = surface_khr == vk::SurfaceKHR::null();
+        let physical_device_info = {
+            let index = unsafe { instance.enumerate_physical_devices() }?
+                .iter()
+                .position(|device| *device == physical_device)
+                .unwrap();
+            PhysicalDeviceInfo::query(&entry, &instance, physical_device, index)
+        };
+
+        let crash_extensions =
+            CrashExtensions::query(&entry, &instance, physical_device, enable_debug);
+        let shading_rate_support = ShadingRateSupport::query(&entry, &instance, physical_device);
+        let subgroup_support = SubgroupSupport::query(&entry, &instance, physical_device);
+        tracing::debug!("Subgroup support: {:?}", subgroup_support);
+        // Presentation timing is meaningless without a surface.
+        let has_present_wait_support =
+            !headless && query_present_wait_support(&entry, &instance, physical_device);
+        let has_pipeline_cache_control =
+            query_pipeline_cache_control_support(&entry, &instance, physical_device);
+        let has_bindless_support = query_bindless_support(&entry, &instance, physical_device);
+        let (
+            device,
+            (graphics_compute_queue, present_queue, transfer_queue),
+            enabled_device_features,
+        ) = create_tracingical_device_with_graphics_queue(
+            &instance,
+            physical_device,
+            queue_families_indices,
+            crash_extensions,
+            shading_rate_support,
+            OptionalExtensions {
+                present_wait: has_present_wait_support,
+                pipeline_cache_control: has_pipeline_cache_control,
+                bindless: has_bindless_support,
+            },
+            headless,
+        )?;
+        let debug_utils = enable_debug.then(|| debug_utils::Device::new(&instance, &device));
+        let crash_diagnostics = CrashDiagnostics::new(
+            &instance,
+            physical_device,
+            &device,
+            crash_extensions,
+            debug_utils.clone(),
+        );
 
         let dynamic_rendering = dynamic_rendering::Device::new(&instance, &device);
         let synchronization2 = synchronization2::Device::new(&instance, &device);
+        let fragment_shading_rate = shading_rate_support
+            .is_supported()
+            .then(|| fragment_shading_rate::Device::new(&instance, &device));
+        let present_wait =
+            has_present_wait_support.then(|| present_wait::Device::new(&instance, &device));
+
+        let has_hdr_support = !headless
+            && unsafe {
+                surface
+                    .get_physical_device_surface_formats(physical_device, surface_khr)?
+                    .contains(&HDR_SURFACE_FORMAT)
+            };
 
-        let has_hdr_support = unsafe {
-            surface
-                .get_physical_device_surface_formats(physical_device, surface_khr)
-                .expect("failed to list physical device surface formats")
-                .contains(&HDR_SURFACE_FORMAT)
-        };
+        let sampler_cache = SamplerCache::new(&instance, physical_device);
+        let allocator = MemoryAllocator::new(&instance, physical_device);
+        let pipeline_cache =
+            unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)? };
+
+        track_device_created();
 
-        Self {
+        Ok(Self {
             _entry: entry,
             instance,
             debug_report_callback,
+            debug_utils,
             surface,
             surface_khr,
             physical_device,
+            physical_device_info,
             device,
             queue_families_indices,
             graphics_compute_queue,
             present_queue,
+            transfer_queue,
             dynamic_rendering,
             synchronization2,
+            shading_rate_support,
+            fragment_shading_rate,
+            subgroup_support,
+            present_wait,
             has_hdr_support,
-        }
+            enabled_device_features,
+            sampler_cache,
+            allocator,
+            pipeline_cache,
+            has_pipeline_cache_control,
+            has_bindless_support,
+            queue_lock: Mutex::new(()),
+            transfer_queue_lock: Mutex::new(()),
+            crash_diagnostics,
+            device_lost: AtomicBool::new(false),
+        })
     }
 }
 
-fn create_instance(entry: &Entry, window: &Window, enable_debug: bool) -> Instance {
+/// Instance and physical device the device of a [`SharedContext`] is created
+/// from. `surface_khr` is null for headless contexts.
+struct SharedContextCreateInfo {
+    entry: Entry,
+    instance: Instance,
+    debug_report_callback: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
+    surface: surface::Instance,
+    surface_khr: vk::SurfaceKHR,
+    physical_device: vk::PhysicalDevice,
+    queue_families_indices: QueueFamiliesIndices,
+}
+
+/// Create the instance, with the surface extensions required by `display_handle` if any.
+fn create_instance(
+    entry: &Entry,
+    display_handle: Option<RawDisplayHandle>,
+    enable_debug: bool,
+) -> Result<Instance, VksError> {
     let app_name = CString::new("Vulkan Application").unwrap();
     let engine_name = CString::new("No Engine").unwrap();
     let app_info = vk::ApplicationInfo::default()
@@ -101,17 +294,18 @@ fn create_instance(entry: &Entry, window: &Window, enable_debug: bool) -> Instan
         .application_version(vk::make_api_version(0, 0, 1, 0))
         .engine_name(engine_name.as_c_str())
         .engine_version(vk::make_api_version(0, 0, 1, 0))
-        .api_version(vk::make_api_version(0, 1, 0, 0));
+        // 1.1 for the subgroup operations of the compute kernels.
+        .api_version(vk::make_api_version(0, 1, 1, 0));
 
-    let mut extension_names =
-        ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
-            .expect("Failed to enumerate required extensions")
-            .to_vec();
+    let mut extension_names = match display_handle {
+        Some(display_handle) => ash_window::enumerate_required_extensions(display_handle)?.to_vec(),
+        None => Vec::new(),
+    };
     extension_names.push(ash::khr::get_physical_device_properties2::NAME.as_ptr());
     if enable_debug {
         extension_names.push(debug_utils::NAME.as_ptr());
     }
-    if has_ext_colorspace_support(entry) {
+    if display_handle.is_some() && has_ext_colorspace_support(entry) {
         extension_names.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
     }
 
@@ -119,11 +313,8 @@ fn create_instance(entry: &Entry, window: &Window, enable_debug: bool) -> Instan
         .application_info(&app_info)
         .enabled_extension_names(&extension_names);
 
-    unsafe {
-        entry
-            .create_instance(&instance_create_info, None)
-            .expect("Failed to create instance")
-    }
+    let instance = unsafe { entry.create_instance(&instance_create_info, None)? };
+    Ok(instance)
 }
 
 /// Pick the first suitable physical device.
@@ -140,11 +331,9 @@ fn pick_physical_device(
     instance: &Instance,
     surface: &surface::Instance,
     surface_khr: vk::SurfaceKHR,
-) -> (vk::PhysicalDevice, QueueFamiliesIndices) {
+) -> Result<(vk::PhysicalDevice, QueueFamiliesIndices), VksError> {
     let devices = unsafe {
-        let mut devices = instance
-            .enumerate_physical_devices()
-            .expect("Failed to enumerate physical devices");
+        let mut devices = instance.enumerate_physical_devices()?;
         devices.sort_by_key(|d| {
             let props = instance.get_physical_device_properties(*d);
             match props.device_type {
@@ -159,7 +348,7 @@ fn pick_physical_device(
     let device = devices
         .into_iter()
         .find(|device| is_device_suitable(instance, surface, surface_khr, *device))
-        .expect("No suitable physical device.");
+        .ok_or_else(|| VksError::UnsupportedFeature("No suitable physical device".into()))?;
 
     let props = unsafe { instance.get_physical_device_properties(device) };
     tracing::debug!("Selected physical device: {:?}", unsafe {
@@ -170,9 +359,96 @@ fn pick_physical_device(
     let queue_families_indices = QueueFamiliesIndices {
         graphics_index: graphics_compute.unwrap(),
         present_index: present.unwrap(),
+        transfer_index: find_transfer_queue_family(instance, device),
+    };
+
+    Ok((device, queue_families_indices))
+}
+
+/// Pick the physical device at `device_index` or the first one with a graphics
+/// and compute queue family and the required extensions.
+fn pick_headless_physical_device(
+    instance: &Instance,
+    device_index: Option<usize>,
+) -> Result<(vk::PhysicalDevice, QueueFamiliesIndices), VksError> {
+    let devices = unsafe { instance.enumerate_physical_devices()? };
+    let is_suitable = |device: vk::PhysicalDevice| {
+        find_graphics_compute_queue_family(instance, device).is_some()
+            && check_device_extension_support(instance, device, true)
+    };
+
+    let device = match device_index {
+        Some(index) => {
+            let device = *devices.get(index).ok_or_else(|| {
+                VksError::UnsupportedFeature(format!("No physical device at index {}", index))
+            })?;
+            if !is_suitable(device) {
+                return Err(VksError::UnsupportedFeature(format!(
+                    "Physical device at index {} is not suitable",
+                    index
+                )));
+            }
+            device
+        }
+        None => devices
+            .into_iter()
+            .find(|device| is_suitable(*device))
+            .ok_or_else(|| VksError::UnsupportedFeature("No suitable physical device".into()))?,
+    };
+
+    let props = unsafe { instance.get_physical_device_properties(device) };
+    tracing::debug!("Selected headless physical device: {:?}", unsafe {
+        CStr::from_ptr(props.device_name.as_ptr())
+    });
+
+    let graphics_index = find_graphics_compute_queue_family(instance, device).unwrap();
+    let queue_families_indices = QueueFamiliesIndices {
+        graphics_index,
+        present_index: graphics_index,
+        transfer_index: find_transfer_queue_family(instance, device),
     };
 
-    (device, queue_families_indices)
+    Ok((device, queue_families_indices))
+}
+
+fn find_graphics_compute_queue_family(
+    instance: &Instance,
+    device: vk::PhysicalDevice,
+) -> Option<u32> {
+    let props = unsafe { instance.get_physical_device_queue_family_properties(device) };
+    props
+        .iter()
+        .position(|family| {
+            family.queue_count > 0
+                && family
+                    .queue_flags
+                    .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .map(|index| index as u32)
+}
+
+/// Find a queue family dedicated to transfers, without graphics support.
+///
+/// Families without compute support either are preferred since they usually
+/// map to the DMA engines of discrete gpus.
+fn find_transfer_queue_family(instance: &Instance, device: vk::PhysicalDevice) -> Option<u32> {
+    let props = unsafe { instance.get_physical_device_queue_family_properties(device) };
+    let is_transfer_only = |family: &vk::QueueFamilyProperties, excluded: vk::QueueFlags| {
+        family.queue_count > 0
+            && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+            && !family.queue_flags.intersects(excluded)
+    };
+    props
+        .iter()
+        .position(|family| {
+            is_transfer_only(family, vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .or_else(|| {
+            props
+                .iter()
+                .position(|family| is_transfer_only(family, vk::QueueFlags::GRAPHICS))
+        })
+        .map(|index| index as u32)
 }
 
 fn is_device_suitable(
@@ -182,17 +458,12 @@ fn is_device_suitable(
     device: vk::PhysicalDevice,
 ) -> bool {
     let (graphics_compute, present) = find_queue_families(instance, surface, surface_khr, device);
-    let extention_support = check_device_extension_support(instance, device);
+    let extention_support = check_device_extension_support(instance, device, false);
     let is_swapchain_adequate = {
         let details = SwapchainSupportDetails::new(device, surface, surface_khr);
         !details.formats.is_empty() && !details.present_modes.is_empty()
     };
-    let features = unsafe { instance.get_physical_device_features(device) };
-    graphics_compute.is_some()
-        && present.is_some()
-        && extention_support
-        && is_swapchain_adequate
-        && features.sampler_anisotropy == vk::TRUE
+    graphics_compute.is_some() && present.is_some() && extention_support && is_swapchain_adequate
 }
 
 fn has_ext_colorspace_support(entry: &Entry) -> bool {
@@ -208,8 +479,12 @@ fn has_ext_colorspace_support(entry: &Entry) -> bool {
     })
 }
 
-fn check_device_extension_support(instance: &Instance, device: vk::PhysicalDevice) -> bool {
-    let required_extentions = get_required_device_extensions();
+fn check_device_extension_support(
+    instance: &Instance,
+    device: vk::PhysicalDevice,
+    headless: bool,
+) -> bool {
+    let required_extentions = get_required_device_extensions(headless);
 
     let extension_props = unsafe {
         instance
@@ -231,16 +506,20 @@ fn check_device_extension_support(instance: &Instance, device: vk::PhysicalDevic
     true
 }
 
-fn get_required_device_extensions() -> [&'static CStr; 7] {
-    [
-        swapchain::NAME,
+/// Headless contexts do not need the swapchain extension.
+fn get_required_device_extensions(headless: bool) -> Vec<&'static CStr> {
+    let mut extensions = vec![
         dynamic_rendering::NAME,
         ash::khr::depth_stencil_resolve::NAME,
         ash::khr::create_renderpass2::NAME,
         ash::khr::multiview::NAME,
         ash::khr::maintenance2::NAME,
         ash::khr::synchronization2::NAME,
-    ]
+    ];
+    if !headless {
+        extensions.push(swapchain::NAME);
+    }
+    extensions
 }
 
 /// Find a queue family with at least one graphics & compute queue and one with
@@ -286,17 +565,38 @@ fn find_queue_families(
     (graphics_compute, present)
 }
 
-/// Create the tracingical device to interact with `device`, a graphics queue
-/// and a presentation queue.
+/// Optional device extensions supported by the physical device.
+#[derive(Clone, Copy)]
+struct OptionalExtensions {
+    present_wait: bool,
+    pipeline_cache_control: bool,
+    bindless: bool,
+}
+
+/// Device, graphics, presentation and transfer queues and enabled features
+/// returned by [`create_tracingical_device_with_graphics_queue`].
+type DeviceAndQueues = (
+    Device,
+    (vk::Queue, vk::Queue, Option<vk::Queue>),
+    EnabledDeviceFeatures,
+);
+
+/// Create the tracingical device to interact with `device`, a graphics queue,
+/// a presentation queue and a transfer queue if the device has a dedicated family.
 ///
 /// # Returns
 ///
-/// Return a tuple containing the tracingical device, the graphics queue and the presentation queue.
+/// Return a tuple containing the tracingical device, the graphics, presentation and transfer
+/// queues and the extensions and features enabled on the device.
 fn create_tracingical_device_with_graphics_queue(
     instance: &Instance,
     device: vk::PhysicalDevice,
     queue_families_indices: QueueFamiliesIndices,
-) -> (Device, vk::Queue, vk::Queue) {
+    crash_extensions: CrashExtensions,
+    shading_rate_support: ShadingRateSupport,
+    optional: OptionalExtensions,
+    headless: bool,
+) -> Result<DeviceAndQueues, VksError> {
     let graphics_family_index = queue_families_indices.graphics_index;
     let present_family_index = queue_families_indices.present_index;
     let queue_priorities = [1.0f32];
@@ -306,6 +606,8 @@ fn create_tracingical_device_with_graphics_queue(
         // And since the family for graphics and presentation could be the same we need to
         // deduplicate it.
         let mut indices = vec![graphics_family_index, present_family_index];
+        indices.extend(queue_families_indices.transfer_index);
+        indices.sort_unstable();
         indices.dedup();
 
         // Now we build an array of `DeviceQueueCreateInfo`.
@@ -320,21 +622,81 @@ fn create_tracingical_device_with_graphics_queue(
             .collect::<Vec<_>>()
     };
 
-    let device_extensions = get_required_device_extensions();
+    let device_extensions = get_required_device_extensions(headless);
+    let mut optional_extensions = crash_extensions.names();
+    if shading_rate_support.is_supported() {
+        optional_extensions.push(fragment_shading_rate::NAME);
+    }
+    if optional.present_wait {
+        optional_extensions.push(present_id::NAME);
+        optional_extensions.push(present_wait::NAME);
+    }
+    if optional.pipeline_cache_control {
+        optional_extensions.push(pipeline_creation_cache_control::NAME);
+    }
+    if optional.bindless {
+        optional_extensions.push(descriptor_indexing::NAME);
+    }
     let device_extensions_ptrs = device_extensions
         .iter()
+        .chain(optional_extensions.iter())
         .map(|ext| ext.as_ptr())
         .collect::<Vec<_>>();
 
-    let device_features = vk::PhysicalDeviceFeatures::default().sampler_anisotropy(true);
+    // Optional, used by the anisotropic samplers, the materials writing texture feedback,
+    // the shading rate image, the blur passes writing to targets of any format,
+    // compressed textures and the line, point and wireframe pipelines.
+    let supported_features = unsafe { instance.get_physical_device_features(device) };
+    let device_features = vk::PhysicalDeviceFeatures::default()
+        .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+        .fragment_stores_and_atomics(supported_features.fragment_stores_and_atomics == vk::TRUE)
+        .shader_storage_image_extended_formats(
+            supported_features.shader_storage_image_extended_formats == vk::TRUE,
+        )
+        .shader_storage_image_write_without_format(
+            supported_features.shader_storage_image_write_without_format == vk::TRUE,
+        )
+        .texture_compression_bc(supported_features.texture_compression_bc == vk::TRUE)
+        .wide_lines(supported_features.wide_lines == vk::TRUE)
+        .large_points(supported_features.large_points == vk::TRUE)
+        .fill_mode_non_solid(supported_features.fill_mode_non_solid == vk::TRUE);
     let mut dynamic_rendering_feature =
         vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true);
     let mut synchronization2_feature =
         vk::PhysicalDeviceSynchronization2Features::default().synchronization2(true);
+    let mut fault_feature =
+        vk::PhysicalDeviceFaultFeaturesEXT::default().device_fault(crash_extensions.device_fault);
     let mut device_features_2 = vk::PhysicalDeviceFeatures2::default()
         .features(device_features)
         .push_next(&mut dynamic_rendering_feature)
         .push_next(&mut synchronization2_feature);
+    if crash_extensions.device_fault {
+        device_features_2 = device_features_2.push_next(&mut fault_feature);
+    }
+    let mut shading_rate_feature = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default()
+        .pipeline_fragment_shading_rate(true)
+        .attachment_fragment_shading_rate(true);
+    if shading_rate_support.is_supported() {
+        device_features_2 = device_features_2.push_next(&mut shading_rate_feature);
+    }
+    let mut present_id_feature = vk::PhysicalDevicePresentIdFeaturesKHR::default().present_id(true);
+    let mut present_wait_feature =
+        vk::PhysicalDevicePresentWaitFeaturesKHR::default().present_wait(true);
+    if optional.present_wait {
+        device_features_2 = device_features_2
+            .push_next(&mut present_id_feature)
+            .push_next(&mut present_wait_feature);
+    }
+    let mut cache_control_feature =
+        vk::PhysicalDevicePipelineCreationCacheControlFeatures::default()
+            .pipeline_creation_cache_control(true);
+    if optional.pipeline_cache_control {
+        device_features_2 = device_features_2.push_next(&mut cache_control_feature);
+    }
+    let mut descriptor_indexing_feature = bindless_features();
+    if optional.bindless {
+        device_features_2 = device_features_2.push_next(&mut descriptor_indexing_feature);
+    }
 
     let device_create_info = vk::DeviceCreateInfo::default()
         .queue_create_infos(&queue_create_infos)
@@ -342,15 +704,27 @@ fn create_tracingical_device_with_graphics_queue(
         .push_next(&mut device_features_2);
 
     // Build device and queues
-    let device = unsafe {
-        instance
-            .create_device(device, &device_create_info, None)
-            .expect("Failed to create tracingical device.")
-    };
+    let device = unsafe { instance.create_device(device, &device_create_info, None)? };
     let graphics_compute_queue = unsafe { device.get_device_queue(graphics_family_index, 0) };
     let present_queue = unsafe { device.get_device_queue(present_family_index, 0) };
+    let transfer_queue = queue_families_indices
+        .transfer_index
+        .map(|index| unsafe { device.get_device_queue(index, 0) });
+
+    let enabled_device_features = EnabledDeviceFeatures {
+        extensions: device_extensions
+            .iter()
+            .chain(optional_extensions.iter())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect(),
+        features: device_features,
+    };
 
-    (device, graphics_compute_queue, present_queue)
+    Ok((
+        device,
+        (graphics_compute_queue, present_queue, transfer_queue),
+        enabled_device_features,
+    ))
 }
 
 impl SharedContext {
@@ -362,14 +736,23 @@ impl SharedContext {
         &self.surface
     }
 
+    /// Null for a headless context.
     pub fn surface_khr(&self) -> vk::SurfaceKHR {
         self.surface_khr
     }
 
+    pub fn is_headless(&self) -> bool {
+        self.surface_khr == vk::SurfaceKHR::null()
+    }
+
     pub fn physical_device(&self) -> vk::PhysicalDevice {
         self.physical_device
     }
 
+    pub fn physical_device_info(&self) -> &PhysicalDeviceInfo {
+        &self.physical_device_info
+    }
+
     pub fn device(&self) -> &Device {
         &self.device
     }
@@ -386,6 +769,11 @@ impl SharedContext {
         self.present_queue
     }
 
+    /// The dedicated transfer queue, or the graphics queue when the device has none.
+    pub fn transfer_queue(&self) -> vk::Queue {
+        self.transfer_queue.unwrap_or(self.graphics_compute_queue)
+    }
+
     pub fn dynamic_rendering(&self) -> &dynamic_rendering::Device {
         &self.dynamic_rendering
     }
@@ -397,6 +785,57 @@ impl SharedContext {
     pub fn has_hdr_support(&self) -> bool {
         self.has_hdr_support
     }
+
+    pub fn shading_rate_support(&self) -> ShadingRateSupport {
+        self.shading_rate_support
+    }
+
+    pub fn subgroup_support(&self) -> SubgroupSupport {
+        self.subgroup_support
+    }
+
+    pub fn enabled_device_features(&self) -> &EnabledDeviceFeatures {
+        &self.enabled_device_features
+    }
+
+    pub fn sampler_cache(&self) -> &SamplerCache {
+        &self.sampler_cache
+    }
+
+    pub fn allocator(&self) -> &MemoryAllocator {
+        &self.allocator
+    }
+
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache
+    }
+
+    pub fn has_pipeline_cache_control(&self) -> bool {
+        self.has_pipeline_cache_control
+    }
+
+    pub fn has_bindless_support(&self) -> bool {
+        self.has_bindless_support
+    }
+
+    /// Loader of VK_KHR_fragment_shading_rate, `None` if not supported.
+    pub fn fragment_shading_rate(&self) -> Option<&fragment_shading_rate::Device> {
+        self.fragment_shading_rate.as_ref()
+    }
+
+    /// Loader of VK_KHR_present_wait, `None` if not supported or headless.
+    pub fn present_wait(&self) -> Option<&present_wait::Device> {
+        self.present_wait.as_ref()
+    }
+
+    pub fn crash_diagnostics(&self) -> &CrashDiagnostics {
+        &self.crash_diagnostics
+    }
+
+    /// Debug utils device functions, only loaded when validation is enabled.
+    pub fn debug_utils(&self) -> Option<&debug_utils::Device> {
+        self.debug_utils.as_ref()
+    }
 }
 
 impl SharedContext {
@@ -520,14 +959,26 @@ impl SharedContext {
             let submit_info = vk::SubmitInfo2::default()
                 .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info));
 
+            // Wait on a fence rather than for the queue to be idle so the
+            // queue lock is released as soon as the work is submitted.
             unsafe {
                 let queue = self.graphics_compute_queue();
-                self.synchronization2
-                    .queue_submit2(queue, std::slice::from_ref(&submit_info), vk::Fence::null())
-                    .expect("Failed to submit to queue");
+                let fence = self
+                    .device
+                    .create_fence(&vk::FenceCreateInfo::default(), None)
+                    .expect("Failed to create fence");
+                {
+                    let _queue_guard = self.lock_queue();
+                    self.synchronization2
+                        .queue_submit2(queue, std::slice::from_ref(&submit_info), fence)
+                        .expect("Failed to submit to queue");
+                }
                 self.device
-                    .queue_wait_idle(queue)
-                    .expect("Failed to wait for queue to be idle");
+                    .wait_for_fences(&[fence], true, u64::MAX)
+                    .unwrap_or_else(|error| {
+                        self.handle_device_error(error, "Failed to wait for fence")
+                    });
+                self.device.destroy_fence(fence, None);
             };
         }
 
@@ -537,20 +988,138 @@ impl SharedContext {
         executor_result
     }
 
+    /// Lock the graphics queue.
+    ///
+    /// Queue operations must be externally synchronized so every submission
+    /// or present made while another thread may use the queue must hold it.
+    pub fn lock_queue(&self) -> MutexGuard<'_, ()> {
+        self.queue_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Lock the queue returned by [`SharedContext::transfer_queue`].
+    ///
+    /// Same as [`SharedContext::lock_queue`] when there is no dedicated transfer queue.
+    pub fn lock_transfer_queue(&self) -> MutexGuard<'_, ()> {
+        let lock = match self.transfer_queue {
+            Some(_) => &self.transfer_queue_lock,
+            None => &self.queue_lock,
+        };
+        lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Write a crash report at `path`. To call once the device is lost.
+    pub fn write_crash_report<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut queues = vec![("graphics", self.graphics_compute_queue)];
+        if self.present_queue != self.graphics_compute_queue {
+            queues.push(("present", self.present_queue));
+        }
+        if let Some(transfer_queue) = self.transfer_queue {
+            queues.push(("transfer", transfer_queue));
+        }
+        self.crash_diagnostics
+            .write_report(&self.device, &queues, path)
+    }
+
+    /// Panic because of `error`. A crash report is written first if the device was lost.
+    pub fn handle_device_error(&self, error: vk::Result, message: &str) -> ! {
+        if error == vk::Result::ERROR_DEVICE_LOST {
+            self.device_lost.store(true, Ordering::Relaxed);
+            match self.write_crash_report(CRASH_REPORT_PATH) {
+                Ok(()) => tracing::error!(
+                    "Device lost, crash report written to {}",
+                    CRASH_REPORT_PATH
+                ),
+                Err(err) => tracing::error!(
+                    "Device lost, failed to write crash report. Cause: {}",
+                    err
+                ),
+            }
+        }
+        panic!("{}. Cause: {}", message, error)
+    }
+
+    /// True once an operation returned `ERROR_DEVICE_LOST`.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
     pub fn graphics_queue_wait_idle(&self) {
-        unsafe {
+        let _queue_guard = self.lock_queue();
+        let result = unsafe {
             self.device
                 .queue_wait_idle(self.graphics_compute_queue())
-                .expect("Failed to wait for queue to be idle")
+        };
+        self.check_idle_result(result, "Failed to wait for queue to be idle");
+    }
+
+    /// Wait for the device to be idle.
+    ///
+    /// Every queue must be externally synchronized during the wait so both
+    /// queue locks are held, see [`SharedContext::lock_queue`].
+    ///
+    /// A lost device is logged instead of panicking since it is idle for good,
+    /// so the resources can still be released during shutdown.
+    pub fn wait_idle(&self) {
+        let _queue_guard = self.lock_queue();
+        // Taken after the graphics queue lock, in the same order as everywhere else.
+        let _transfer_queue_guard = self.transfer_queue.map(|_| {
+            self.transfer_queue_lock
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        });
+        let result = unsafe { self.device.device_wait_idle() };
+        self.check_idle_result(result, "Failed to wait for device to be idle");
+    }
+
+    fn check_idle_result(&self, result: ash::prelude::VkResult<()>, message: &str) {
+        match result {
+            Ok(()) => {}
+            Err(vk::Result::ERROR_DEVICE_LOST) => {
+                if !self.device_lost.swap(true, Ordering::Relaxed) {
+                    tracing::error!("{}. Cause: device lost", message);
+                }
+            }
+            Err(error) => self.handle_device_error(error, message),
         }
     }
+
+    /// Check that every resource was released before the last device is destroyed.
+    fn validate_released_resources(&self) {
+        let live = LeakSnapshot::capture();
+        if live.is_empty() {
+            return;
+        }
+        // Only logged, panicking in drop would abort during unwinding.
+        tracing::error!(
+            "Resources still alive when destroying the device: {}",
+            live
+        );
+    }
 }
 
+/// The context is destroyed once the last `Arc<Context>` is dropped.
+///
+/// Every resource of the crate holds one so they are all released before.
+/// Raw handles kept outside of them, like pipelines or the ui renderer, must
+/// be destroyed before the last context is dropped.
 impl Drop for SharedContext {
     fn drop(&mut self) {
+        self.wait_idle();
+        if track_device_destroyed() {
+            self.validate_released_resources();
+        }
+
         unsafe {
+            self.sampler_cache.destroy(&self.device);
+            self.allocator.destroy(&self.device);
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
+            self.crash_diagnostics.destroy(&self.device);
             self.device.destroy_device(None);
-            self.surface.destroy_surface(self.surface_khr, None);
+            if !self.is_headless() {
+                self.surface.destroy_surface(self.surface_khr, None);
+            }
             if let Some((utils, messenger)) = self.debug_report_callback.take() {
                 utils.destroy_debug_utils_messenger(messenger, None);
             }
@@ -563,4 +1132,13 @@ impl Drop for SharedContext {
 pub struct QueueFamiliesIndices {
     pub graphics_index: u32,
     pub present_index: u32,
+    /// Family dedicated to transfers, `None` when transfers go through the graphics queue.
+    pub transfer_index: Option<u32>,
+}
+
+impl QueueFamiliesIndices {
+    /// Family of [`SharedContext::transfer_queue`].
+    pub fn transfer_family(&self) -> u32 {
+        self.transfer_index.unwrap_or(self.graphics_index)
+    }
 }
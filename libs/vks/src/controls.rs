@@ -1,3 +1,4 @@
+#[cfg(feature = "winit")]
 use winit::{
     event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
@@ -26,6 +27,7 @@ impl InputState {
         }
     }
 
+    #[cfg(feature = "winit")]
     pub fn handle_window_event(self, event: &WindowEvent) -> Self {
         let mut is_forward_pressed = None;
         let mut is_backward_pressed = None;
@@ -89,6 +91,7 @@ impl InputState {
         }
     }
 
+    #[cfg(feature = "winit")]
     pub fn handle_device_event(self, event: &DeviceEvent) -> Self {
         let mut cursor_delta = self.cursor_delta;
 
@@ -0,0 +1,484 @@
+use crate::find_memory_type;
+use ash::{
+    amd::buffer_marker, ext::debug_utils, ext::device_fault, khr, nv, vk, Device, Entry, Instance,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{c_void, CStr, CString},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    ptr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const CRASH_REPORT_PATH: &str = "gpu_crash_report.txt";
+
+/// Number of passes kept in the history of the crash report.
+const PASS_HISTORY: usize = 256;
+/// Number of labels reported for each queue.
+const LABELS_PER_QUEUE: usize = 8;
+/// Number of recent passes reported when no breadcrumb extension is available.
+const RECENT_PASSES: usize = 16;
+/// Set on the checkpoint written at the end of a pass.
+const PASS_END_BIT: u32 = 1 << 31;
+
+/// Optional device extensions used to diagnose a lost device.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CrashExtensions {
+    pub device_fault: bool,
+    pub nv_checkpoints: bool,
+    pub amd_buffer_marker: bool,
+}
+
+impl CrashExtensions {
+    /// Query the supported extensions. Breadcrumbs have a cost on the gpu so they are
+    /// only enabled when `breadcrumbs` is true.
+    pub(crate) fn query(
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        breadcrumbs: bool,
+    ) -> Self {
+        let extension_props = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .expect("Failed to enumerate device extention properties")
+        };
+        let is_supported = |name: &CStr| {
+            extension_props.iter().any(|ext| {
+                let ext_name = unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) };
+                ext_name == name
+            })
+        };
+
+        let device_fault = is_supported(device_fault::NAME) && {
+            let mut fault_features = vk::PhysicalDeviceFaultFeaturesEXT::default();
+            let mut features =
+                vk::PhysicalDeviceFeatures2::default().push_next(&mut fault_features);
+            unsafe {
+                khr::get_physical_device_properties2::Instance::new(entry, instance)
+                    .get_physical_device_features2(physical_device, &mut features)
+            };
+            fault_features.device_fault == vk::TRUE
+        };
+
+        Self {
+            device_fault,
+            nv_checkpoints: breadcrumbs && is_supported(nv::device_diagnostic_checkpoints::NAME),
+            amd_buffer_marker: breadcrumbs && is_supported(buffer_marker::NAME),
+        }
+    }
+
+    pub(crate) fn names(&self) -> Vec<&'static CStr> {
+        let mut names = Vec::new();
+        if self.device_fault {
+            names.push(device_fault::NAME);
+        }
+        if self.nv_checkpoints {
+            names.push(nv::device_diagnostic_checkpoints::NAME);
+        }
+        if self.amd_buffer_marker {
+            names.push(buffer_marker::NAME);
+        }
+        names
+    }
+}
+
+/// Host visible buffer where the gpu writes the ids of the last started
+/// and finished passes.
+struct MarkerBuffer {
+    loader: buffer_marker::Device,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    ptr: *const u32,
+}
+
+// The mapped pointer is only read when writing a crash report.
+unsafe impl Send for MarkerBuffer {}
+unsafe impl Sync for MarkerBuffer {}
+
+impl MarkerBuffer {
+    const STARTED_OFFSET: vk::DeviceSize = 0;
+    const FINISHED_OFFSET: vk::DeviceSize = 4;
+
+    fn new(instance: &Instance, physical_device: vk::PhysicalDevice, device: &Device) -> Self {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(8)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        unsafe {
+            let buffer = device
+                .create_buffer(&buffer_info, None)
+                .expect("Failed to create marker buffer");
+            let mem_requirements = device.get_buffer_memory_requirements(buffer);
+            let mem_type = find_memory_type(
+                mem_requirements,
+                instance.get_physical_device_memory_properties(physical_device),
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            );
+            let alloc_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(mem_requirements.size)
+                .memory_type_index(mem_type);
+            let memory = device
+                .allocate_memory(&alloc_info, None)
+                .expect("Failed to allocate marker buffer memory");
+            device
+                .bind_buffer_memory(buffer, memory, 0)
+                .expect("Failed to bind marker buffer memory");
+            let ptr = device
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .expect("Failed to map marker buffer memory") as *mut u32;
+            ptr::write_bytes(ptr, 0, 2);
+
+            Self {
+                loader: buffer_marker::Device::new(instance, device),
+                buffer,
+                memory,
+                ptr,
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        stage: vk::PipelineStageFlags,
+        offset: vk::DeviceSize,
+        id: u32,
+    ) {
+        unsafe {
+            self.loader
+                .cmd_write_buffer_marker(command_buffer, stage, self.buffer, offset, id)
+        };
+    }
+
+    /// Ids of the last started and finished passes.
+    fn read(&self) -> (u32, u32) {
+        unsafe {
+            (
+                ptr::read_volatile(self.ptr),
+                ptr::read_volatile(self.ptr.add(1)),
+            )
+        }
+    }
+
+    fn destroy(&self, device: &Device) {
+        unsafe {
+            device.unmap_memory(self.memory);
+            device.destroy_buffer(self.buffer, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+struct Pass {
+    id: u32,
+    queue: vk::Queue,
+    name: String,
+}
+
+#[derive(Default)]
+struct PassHistory {
+    next_id: u32,
+    passes: VecDeque<Pass>,
+    open: HashMap<vk::CommandBuffer, Vec<u32>>,
+}
+
+impl PassHistory {
+    fn name(&self, id: u32) -> &str {
+        self.passes
+            .iter()
+            .find(|pass| pass.id == id)
+            .map_or("<unknown>", |pass| pass.name.as_str())
+    }
+}
+
+/// Record the passes submitted to the gpu to explain a lost device.
+///
+/// Each pass is surrounded by a debug label when validation is enabled and by
+/// breadcrumbs (`VK_NV_device_diagnostic_checkpoints` or `VK_AMD_buffer_marker`)
+/// when the driver supports them. When the device is lost, the fault reported
+/// by `VK_EXT_device_fault`, the last passes reached by the gpu and the last
+/// labels of each queue are written to a crash report.
+pub struct CrashDiagnostics {
+    device_fault: Option<device_fault::Device>,
+    checkpoints: Option<nv::device_diagnostic_checkpoints::Device>,
+    markers: Option<MarkerBuffer>,
+    debug_utils: Option<debug_utils::Device>,
+    history: Mutex<PassHistory>,
+}
+
+impl CrashDiagnostics {
+    pub(crate) fn new(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &Device,
+        extensions: CrashExtensions,
+        debug_utils: Option<debug_utils::Device>,
+    ) -> Self {
+        tracing::debug!("Crash diagnostics extensions: {:?}", extensions);
+
+        Self {
+            device_fault: extensions
+                .device_fault
+                .then(|| device_fault::Device::new(instance, device)),
+            checkpoints: extensions
+                .nv_checkpoints
+                .then(|| nv::device_diagnostic_checkpoints::Device::new(instance, device)),
+            markers: extensions
+                .amd_buffer_marker
+                .then(|| MarkerBuffer::new(instance, physical_device, device)),
+            debug_utils,
+            history: Mutex::new(PassHistory::default()),
+        }
+    }
+
+    pub(crate) fn destroy(&self, device: &Device) {
+        if let Some(markers) = self.markers.as_ref() {
+            markers.destroy(device);
+        }
+    }
+
+    fn history(&self) -> std::sync::MutexGuard<'_, PassHistory> {
+        self.history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Record the beginning of the pass `name` in `command_buffer` to be submitted to `queue`.
+    pub fn cmd_begin_pass(&self, command_buffer: vk::CommandBuffer, queue: vk::Queue, name: &str) {
+        let id = {
+            let mut history = self.history();
+            let id = history.next_id;
+            history.next_id = (history.next_id + 1) & !PASS_END_BIT;
+            if history.passes.len() == PASS_HISTORY {
+                history.passes.pop_front();
+            }
+            history.passes.push_back(Pass {
+                id,
+                queue,
+                name: name.to_owned(),
+            });
+            history.open.entry(command_buffer).or_default().push(id);
+            id
+        };
+
+        if let Some(debug_utils) = self.debug_utils.as_ref() {
+            let label_name = CString::new(name).unwrap_or_default();
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&label_name);
+            unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+        }
+        if let Some(checkpoints) = self.checkpoints.as_ref() {
+            unsafe { checkpoints.cmd_set_checkpoint(command_buffer, id as usize as *const c_void) };
+        }
+        if let Some(markers) = self.markers.as_ref() {
+            markers.write(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                MarkerBuffer::STARTED_OFFSET,
+                id,
+            );
+        }
+    }
+
+    /// Record the end of the last pass started in `command_buffer`.
+    pub fn cmd_end_pass(&self, command_buffer: vk::CommandBuffer) {
+        let id = {
+            let mut history = self.history();
+            let open = history.open.entry(command_buffer).or_default();
+            let id = open.pop().expect("No pass to end in command buffer");
+            if open.is_empty() {
+                history.open.remove(&command_buffer);
+            }
+            id
+        };
+
+        if let Some(checkpoints) = self.checkpoints.as_ref() {
+            unsafe {
+                checkpoints.cmd_set_checkpoint(
+                    command_buffer,
+                    (id | PASS_END_BIT) as usize as *const c_void,
+                )
+            };
+        }
+        if let Some(markers) = self.markers.as_ref() {
+            markers.write(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                MarkerBuffer::FINISHED_OFFSET,
+                id,
+            );
+        }
+        if let Some(debug_utils) = self.debug_utils.as_ref() {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    /// Write a report of the state of the gpu at `path`. To call once the device is lost.
+    pub(crate) fn write_report<P: AsRef<Path>>(
+        &self,
+        device: &Device,
+        queues: &[(&str, vk::Queue)],
+        path: P,
+    ) -> io::Result<()> {
+        let mut report = String::new();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let _ = writeln!(report, "Gpu crash report, unix time {}", timestamp);
+
+        let _ = writeln!(report, "\n== Device fault ==");
+        match self.device_fault.as_ref() {
+            Some(device_fault) => report.push_str(&query_device_fault(device, device_fault)),
+            None => report.push_str("VK_EXT_device_fault is not available\n"),
+        }
+
+        let history = self.history();
+
+        let _ = writeln!(report, "\n== Breadcrumbs ==");
+        let mut last_finished = None;
+        if let Some(checkpoints) = self.checkpoints.as_ref() {
+            for (queue_name, queue) in queues {
+                let data = unsafe {
+                    let len = checkpoints.get_queue_checkpoint_data_len(*queue);
+                    let mut data = vec![vk::CheckpointDataNV::default(); len];
+                    checkpoints.get_queue_checkpoint_data(*queue, &mut data);
+                    data
+                };
+                for checkpoint in data {
+                    let marker = checkpoint.p_checkpoint_marker as usize as u32;
+                    let id = marker & !PASS_END_BIT;
+                    let event = if marker & PASS_END_BIT != 0 {
+                        last_finished = Some(id);
+                        "end"
+                    } else {
+                        "begin"
+                    };
+                    let _ = writeln!(
+                        report,
+                        "{} queue reached {} of pass {} '{}' at stage {:?}",
+                        queue_name,
+                        event,
+                        id,
+                        history.name(id),
+                        checkpoint.stage
+                    );
+                }
+            }
+        }
+        if let Some(markers) = self.markers.as_ref() {
+            let (started, finished) = markers.read();
+            last_finished = Some(finished);
+            let _ = writeln!(
+                report,
+                "Last started pass {} '{}', last finished pass {} '{}'",
+                started,
+                history.name(started),
+                finished,
+                history.name(finished)
+            );
+        }
+        if self.checkpoints.is_none() && self.markers.is_none() {
+            report.push_str("No breadcrumb extension is enabled\n");
+        }
+
+        let _ = writeln!(report, "\n== Last labels per queue ==");
+        for (queue_name, queue) in queues {
+            let labels = history
+                .passes
+                .iter()
+                .rev()
+                .filter(|pass| pass.queue == *queue)
+                .take(LABELS_PER_QUEUE)
+                .map(|pass| pass.name.as_str())
+                .collect::<Vec<_>>();
+            let _ = writeln!(report, "{}: {}", queue_name, labels.join(" <- "));
+        }
+
+        let _ = writeln!(report, "\n== Passes in flight ==");
+        let in_flight = match last_finished {
+            Some(finished) => history
+                .passes
+                .iter()
+                .filter(|pass| pass.id > finished)
+                .collect::<Vec<_>>(),
+            None => {
+                report.push_str("Unknown without breadcrumbs, listing the last recorded passes\n");
+                history
+                    .passes
+                    .iter()
+                    .rev()
+                    .take(RECENT_PASSES)
+                    .rev()
+                    .collect()
+            }
+        };
+        for pass in in_flight {
+            let _ = writeln!(report, "{} '{}'", pass.id, pass.name);
+        }
+
+        fs::write(path, report)
+    }
+}
+
+fn query_device_fault(device: &Device, device_fault: &device_fault::Device) -> String {
+    let get_fault_info = device_fault.fp().get_device_fault_info_ext;
+    let mut report = String::new();
+
+    unsafe {
+        let mut counts = vk::DeviceFaultCountsEXT::default();
+        let result = get_fault_info(device.handle(), &mut counts, ptr::null_mut());
+        if result != vk::Result::SUCCESS {
+            return format!("Failed to query device fault counts. Cause: {}\n", result);
+        }
+
+        let mut address_infos =
+            vec![vk::DeviceFaultAddressInfoEXT::default(); counts.address_info_count as usize];
+        let mut vendor_infos =
+            vec![vk::DeviceFaultVendorInfoEXT::default(); counts.vendor_info_count as usize];
+        // The vendor binary is not decoded.
+        counts.vendor_binary_size = 0;
+        let mut info = vk::DeviceFaultInfoEXT {
+            p_address_infos: address_infos.as_mut_ptr(),
+            p_vendor_infos: vendor_infos.as_mut_ptr(),
+            ..Default::default()
+        };
+        let result = get_fault_info(device.handle(), &mut counts, &mut info);
+        if result != vk::Result::SUCCESS && result != vk::Result::INCOMPLETE {
+            return format!("Failed to query device fault info. Cause: {}\n", result);
+        }
+
+        let _ = writeln!(
+            report,
+            "{}",
+            info.description_as_c_str()
+                .map_or_else(|_| "<invalid description>".into(), CStr::to_string_lossy)
+        );
+        for address in address_infos
+            .iter()
+            .take(counts.address_info_count as usize)
+        {
+            let _ = writeln!(
+                report,
+                "{:?} at 0x{:x} (precision 0x{:x})",
+                address.address_type, address.reported_address, address.address_precision
+            );
+        }
+        for vendor in vendor_infos.iter().take(counts.vendor_info_count as usize) {
+            let _ = writeln!(
+                report,
+                "{} (code 0x{:x}, data 0x{:x})",
+                vendor
+                    .description_as_c_str()
+                    .map_or_else(|_| "<invalid description>".into(), CStr::to_string_lossy),
+                vendor.vendor_fault_code,
+                vendor.vendor_fault_data
+            );
+        }
+    }
+
+    report
+}
@@ -1,10 +1,9 @@
 use ash::vk;
 
-use crate::{create_sampler, Context, Image, ImageParameters, Texture};
+use crate::{create_sampler, format_aspect_flags, Context, Image, ImageParameters, Texture};
 use std::{collections::HashMap, sync::Arc};
 
 pub const GBUFFER_NORMALS_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
-pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
 
 
 pub struct GBuffer {
@@ -20,16 +19,17 @@ impl GBuffer {
     pub fn new(
         context: &Arc<Context>,
         extent: vk::Extent2D,
+        color_format: vk::Format,
         depth_format: vk::Format,
         msaa_samples: vk::SampleCountFlags,
     ) -> Self {
         let gbuffer_normals = create_gbuffer_normals(context, extent);
         let gbuffer_depth = create_gbuffer_depth(context, depth_format, extent);
-        let scene_color = create_scene_color(context, extent, msaa_samples);
+        let scene_color = create_scene_color(context, color_format, extent, msaa_samples);
         let scene_depth = create_scene_depth(context, depth_format, extent, msaa_samples);
         let scene_resolve = match msaa_samples {
             vk::SampleCountFlags::TYPE_1 => None,
-            _ => Some(create_scene_resolve(context, extent)),
+            _ => Some(create_scene_resolve(context, color_format, extent)),
         };
 
         Self {
@@ -106,6 +106,7 @@ fn create_gbuffer_depth(
 
 fn create_scene_color(
     context: &Arc<Context>,
+    format: vk::Format,
     extent: vk::Extent2D,
     msaa_samples: vk::SampleCountFlags,
 ) -> Texture {
@@ -121,7 +122,7 @@ fn create_scene_color(
             mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
             extent,
             sample_count: msaa_samples,
-            format: SCENE_COLOR_FORMAT,
+            format,
             usage: image_usage,
             ..Default::default()
         },
@@ -152,8 +153,11 @@ fn create_scene_depth(
     extent: vk::Extent2D,
     msaa_samples: vk::SampleCountFlags,
 ) -> Texture {
+    // Sampled by the post passes when single sampled.
     let image_usage = match msaa_samples {
-        vk::SampleCountFlags::TYPE_1 => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::SampleCountFlags::TYPE_1 => {
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        }
         _ => {
             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
@@ -176,7 +180,8 @@ fn create_scene_depth(
         vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
     );
 
-    let view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::DEPTH);
+    // Sampled through the depth only view of the texture, see `Texture::sampling_view`.
+    let view = image.create_view(vk::ImageViewType::TYPE_2D, format_aspect_flags(format));
 
     let sampler = match msaa_samples {
         vk::SampleCountFlags::TYPE_1 => Some(create_sampler(
@@ -190,13 +195,17 @@ fn create_scene_depth(
     Texture::new(Arc::clone(context), image, view, sampler)
 }
 
-fn create_scene_resolve(context: &Arc<Context>, extent: vk::Extent2D) -> Texture {
+fn create_scene_resolve(
+    context: &Arc<Context>,
+    format: vk::Format,
+    extent: vk::Extent2D,
+) -> Texture {
     let image = Image::create(
         Arc::clone(context),
         ImageParameters {
             mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
             extent,
-            format: SCENE_COLOR_FORMAT,
+            format,
             usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             ..Default::default()
         },
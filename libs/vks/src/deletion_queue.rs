@@ -0,0 +1,186 @@
+use ash::Device;
+use std::{
+    collections::VecDeque,
+    mem::take,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+type Deletion = Box<dyn FnOnce(&Device) + Send>;
+
+/// Resources destroyed once the frames that may still use them are complete.
+///
+/// Entries are keyed by the value of the frame being recorded when they are
+/// enqueued, see [`crate::InFlightFrames::frame`], and destroyed by
+/// [`crate::InFlightFrames::collect_deletions`] once the fences of that frame
+/// and of the frames before it have signaled. Replacing a resource used by the
+/// frames in flight, a resized target or a reloaded pipeline for example, then
+/// needs no wait for the device to be idle.
+///
+/// Vks resources are destroyed by dropping them so they are enqueued as is
+/// with [`DeletionQueue::enqueue`]. Raw handles are destroyed by a closure
+/// enqueued with [`DeletionQueue::enqueue_destroy`].
+///
+/// Enqueued resources holding the context keep it alive, so the owner of the
+/// frames must call [`DeletionQueue::flush`] once the device is idle.
+#[derive(Default)]
+pub struct DeletionQueue {
+    frame: AtomicU64,
+    deletions: Mutex<VecDeque<(u64, Deletion)>>,
+}
+
+impl DeletionQueue {
+    /// Value of the frame being recorded.
+    pub fn frame(&self) -> u64 {
+        self.frame.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_frame(&self, frame: u64) {
+        self.frame.store(frame, Ordering::Release);
+    }
+
+    /// Drop `resource` once the current frame is complete.
+    pub fn enqueue<R: Send + 'static>(&self, resource: R) {
+        self.enqueue_destroy(move |_| drop(resource));
+    }
+
+    /// Call `destroy` once the current frame is complete.
+    pub fn enqueue_destroy<F: FnOnce(&Device) + Send + 'static>(&self, destroy: F) {
+        let frame = self.frame();
+        self.lock().push_back((frame, Box::new(destroy)));
+    }
+
+    /// Number of resources waiting for their frame to complete.
+    pub fn pending_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Destroy the resources enqueued up to `completed_frame`.
+    pub fn collect(&self, device: &Device, completed_frame: u64) {
+        let completed = {
+            let mut deletions = self.lock();
+            let count = deletions
+                .iter()
+                .take_while(|(frame, _)| *frame <= completed_frame)
+                .count();
+            deletions.drain(..count).collect::<Vec<_>>()
+        };
+        // Destroyed outside of the lock since dropping a resource may enqueue others.
+        completed
+            .into_iter()
+            .for_each(|(_, destroy)| destroy(device));
+    }
+
+    /// Destroy every enqueued resource. The device must be idle.
+    pub fn flush(&self, device: &Device) {
+        loop {
+            let deletions = take(&mut *self.lock());
+            if deletions.is_empty() {
+                break;
+            }
+            deletions
+                .into_iter()
+                .for_each(|(_, destroy)| destroy(device));
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<(u64, Deletion)>> {
+        self.deletions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{ptr, sync::Arc};
+
+    use ash::vk;
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    /// Records its name in the log when dropped.
+    struct Resource(&'static str, Log);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    /// Device without functions, the tested deletions don't call Vulkan.
+    fn device() -> Device {
+        unsafe { Device::load_with(|_| ptr::null(), vk::Device::null()) }
+    }
+
+    fn destroyed(log: &Log) -> Vec<&'static str> {
+        log.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn collects_the_resources_of_the_completed_frames() {
+        let device = device();
+        let log = Log::default();
+        let queue = DeletionQueue::default();
+
+        queue.enqueue(Resource("first", Arc::clone(&log)));
+        queue.set_frame(1);
+        queue.enqueue(Resource("second", Arc::clone(&log)));
+        queue.set_frame(2);
+        queue.enqueue(Resource("third", Arc::clone(&log)));
+        assert_eq!(queue.pending_count(), 3);
+
+        queue.collect(&device, 0);
+        assert_eq!(destroyed(&log), ["first"]);
+
+        queue.collect(&device, 0);
+        assert_eq!(destroyed(&log), ["first"]);
+
+        queue.collect(&device, 2);
+        assert_eq!(destroyed(&log), ["first", "second", "third"]);
+        assert_eq!(queue.pending_count(), 0);
+    }
+
+    #[test]
+    fn enqueue_destroy_is_called_with_the_device() {
+        let device = device();
+        let log = Log::default();
+        let queue = DeletionQueue::default();
+
+        queue.set_frame(3);
+        let destroy_log = Arc::clone(&log);
+        queue.enqueue_destroy(move |device| {
+            assert_eq!(device.handle(), vk::Device::null());
+            destroy_log.lock().unwrap().push("handle");
+        });
+
+        queue.collect(&device, 2);
+        assert!(destroyed(&log).is_empty());
+
+        queue.collect(&device, 3);
+        assert_eq!(destroyed(&log), ["handle"]);
+    }
+
+    #[test]
+    fn flush_destroys_the_resources_enqueued_while_flushing() {
+        let device = device();
+        let log = Log::default();
+        let queue = Arc::new(DeletionQueue::default());
+
+        queue.set_frame(10);
+        queue.enqueue(Resource("pending", Arc::clone(&log)));
+        let nested_queue = Arc::clone(&queue);
+        let nested_log = Arc::clone(&log);
+        queue.enqueue_destroy(move |_| {
+            nested_queue.enqueue(Resource("nested", nested_log));
+        });
+
+        queue.flush(&device);
+        assert_eq!(destroyed(&log), ["pending", "nested"]);
+        assert_eq!(queue.pending_count(), 0);
+    }
+}
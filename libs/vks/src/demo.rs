@@ -0,0 +1,190 @@
+use crate::Camera;
+use math::cgmath::{EuclideanSpace, Point3};
+use serde::Deserialize;
+use std::{error::Error, fs, path::Path};
+
+/// Position of the camera at a given time of the demo.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+/// Action triggered by a demo script.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum DemoAction {
+    /// Switch to an environment, by name or path.
+    SetEnvironment(String),
+    /// Load a model, replacing the current one.
+    LoadModel(String),
+    SetVsync(bool),
+    /// Set the vertical field of view of the camera, in degrees.
+    SetFov(f32),
+    /// Stop the application.
+    Quit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DemoEvent {
+    pub time: f32,
+    pub action: DemoAction,
+}
+
+/// Scripted sequence of camera moves and actions.
+///
+/// Scripts are written in RON:
+///
+/// ```ron
+/// (
+///     looping: true,
+///     camera_path: [
+///         (time: 0.0, position: (0.0, 1.0, 5.0), target: (0.0, 0.0, 0.0)),
+///         (time: 10.0, position: (5.0, 2.0, 0.0), target: (0.0, 0.0, 0.0)),
+///     ],
+///     events: [
+///         (time: 5.0, action: SetEnvironment("indoor")),
+///         (time: 8.0, action: SetVsync(false)),
+///     ],
+/// )
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DemoScript {
+    /// Restart from the beginning when the script ends.
+    #[serde(default)]
+    pub looping: bool,
+    /// Length of the script. Defaults to the time of the last keyframe or event.
+    #[serde(default)]
+    pub duration: Option<f32>,
+    #[serde(default)]
+    pub camera_path: Vec<CameraKeyframe>,
+    #[serde(default)]
+    pub events: Vec<DemoEvent>,
+}
+
+impl DemoScript {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let mut script: DemoScript = ron::from_str(&content)?;
+        script.camera_path.sort_by(|a, b| a.time.total_cmp(&b.time));
+        script.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(script)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration.unwrap_or_else(|| {
+            let last_keyframe = self.camera_path.last().map_or(0.0, |k| k.time);
+            let last_event = self.events.last().map_or(0.0, |e| e.time);
+            last_keyframe.max(last_event)
+        })
+    }
+}
+
+/// Play a [`DemoScript`].
+///
+/// Call [`DemoPlayer::update`] once per frame and apply the returned actions.
+pub struct DemoPlayer {
+    script: DemoScript,
+    time: f32,
+    next_event: usize,
+    loops: u32,
+    finished: bool,
+}
+
+impl DemoPlayer {
+    pub fn new(script: DemoScript) -> Self {
+        Self {
+            script,
+            time: 0.0,
+            next_event: 0,
+            loops: 0,
+            finished: false,
+        }
+    }
+
+    /// Advance the demo by `delta_time_secs`.
+    ///
+    /// # Returns
+    ///
+    /// The actions whose time was reached, in order.
+    pub fn update(&mut self, delta_time_secs: f32) -> Vec<DemoAction> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        self.time += delta_time_secs;
+
+        let mut actions = self.take_due_actions();
+
+        let duration = self.script.duration();
+        if self.time >= duration {
+            if self.script.looping && duration > 0.0 {
+                self.time %= duration;
+                self.next_event = 0;
+                self.loops += 1;
+                tracing::info!("Demo loop {} done", self.loops);
+                actions.extend(self.take_due_actions());
+            } else {
+                self.time = duration;
+                self.finished = true;
+            }
+        }
+
+        actions
+    }
+
+    fn take_due_actions(&mut self) -> Vec<DemoAction> {
+        let events = &self.script.events[self.next_event..];
+        let due = events.iter().take_while(|e| e.time <= self.time).count();
+        self.next_event += due;
+        events[..due].iter().map(|e| e.action.clone()).collect()
+    }
+
+    /// Camera position and target at the current time.
+    pub fn camera_position_and_target(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+        let path = &self.script.camera_path;
+        let next = path.iter().position(|k| k.time > self.time);
+        let (from, to) = match next {
+            None => (path.last()?, path.last()?),
+            Some(0) => (&path[0], &path[0]),
+            Some(index) => (&path[index - 1], &path[index]),
+        };
+
+        let span = to.time - from.time;
+        let t = if span > 0.0 {
+            (self.time - from.time) / span
+        } else {
+            0.0
+        };
+        let lerp = |a: [f32; 3], b: [f32; 3]| {
+            let a = Point3::from(a);
+            let b = Point3::from(b);
+            Point3::from_vec(a.to_vec() + (b - a) * t)
+        };
+
+        Some((
+            lerp(from.position, to.position),
+            lerp(from.target, to.target),
+        ))
+    }
+
+    /// Move `camera` along the camera path.
+    pub fn apply_camera(&self, camera: &mut Camera) {
+        if let Some((position, target)) = self.camera_position_and_target() {
+            camera.look_at(position, target);
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Number of times a looping script restarted.
+    pub fn loops(&self) -> u32 {
+        self.loops
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
@@ -1,11 +1,13 @@
 use super::context::Context;
+use super::leak_tracker::{track_create, track_destroy, TrackedResource};
 use ash::vk;
 use std::sync::Arc;
 
 pub struct Descriptors {
     context: Arc<Context>,
     layout: vk::DescriptorSetLayout,
-    pool: vk::DescriptorPool,
+    /// `None` when the sets come from a [`crate::DescriptorAllocator`].
+    pool: Option<vk::DescriptorPool>,
     sets: Vec<vk::DescriptorSet>,
 }
 
@@ -16,10 +18,29 @@ impl Descriptors {
         pool: vk::DescriptorPool,
         sets: Vec<vk::DescriptorSet>,
     ) -> Self {
+        track_create(TrackedResource::Descriptors);
+
+        Self {
+            context,
+            layout,
+            pool: Some(pool),
+            sets,
+        }
+    }
+
+    /// Take ownership of `layout` with `sets` allocated from a [`crate::DescriptorAllocator`],
+    /// which must outlive them.
+    pub fn from_allocator(
+        context: Arc<Context>,
+        layout: vk::DescriptorSetLayout,
+        sets: Vec<vk::DescriptorSet>,
+    ) -> Self {
+        track_create(TrackedResource::Descriptors);
+
         Self {
             context,
             layout,
-            pool,
+            pool: None,
             sets,
         }
     }
@@ -30,7 +51,8 @@ impl Descriptors {
         self.layout
     }
 
-    pub fn pool(&self) -> vk::DescriptorPool {
+    /// Pool of the sets, `None` if they come from a [`crate::DescriptorAllocator`].
+    pub fn pool(&self) -> Option<vk::DescriptorPool> {
         self.pool
     }
 
@@ -47,8 +69,11 @@ impl Drop for Descriptors {
     fn drop(&mut self) {
         let device = self.context.device();
         unsafe {
-            device.destroy_descriptor_pool(self.pool, None);
+            if let Some(pool) = self.pool {
+                device.destroy_descriptor_pool(pool, None);
+            }
             device.destroy_descriptor_set_layout(self.layout, None);
         }
+        track_destroy(TrackedResource::Descriptors);
     }
 }
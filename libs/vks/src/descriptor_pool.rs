@@ -0,0 +1,341 @@
+use crate::{Context, VksError, MAX_FRAMES_IN_FLIGHT};
+use ash::vk;
+use std::sync::Arc;
+
+/// Maximum number of sets of a single pool of a [`GrowableDescriptorPool`].
+const MAX_SETS_PER_POOL: u32 = 4096;
+/// Sets of the first pool of the sets of a [`DescriptorAllocator`] living as long as it.
+const INITIAL_PERSISTENT_SETS: u32 = 64;
+/// Sets of the first pool of each frame of a [`DescriptorAllocator`].
+const INITIAL_SETS_PER_FRAME: u32 = 16;
+
+/// Number of descriptors of a type to reserve for each set of a pool.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolSizeRatio {
+    pub ty: vk::DescriptorType,
+    pub ratio: f32,
+}
+
+impl PoolSizeRatio {
+    pub const fn new(ty: vk::DescriptorType, ratio: f32) -> Self {
+        Self { ty, ratio }
+    }
+}
+
+/// Ratios covering the sets used by the scene materials and passes.
+pub const DEFAULT_POOL_SIZE_RATIOS: [PoolSizeRatio; 5] = [
+    PoolSizeRatio::new(vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    PoolSizeRatio::new(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    PoolSizeRatio::new(vk::DescriptorType::STORAGE_BUFFER, 1.0),
+    PoolSizeRatio::new(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 6.0),
+    PoolSizeRatio::new(vk::DescriptorType::STORAGE_IMAGE, 1.0),
+];
+
+/// List of descriptor pools that grows when a pool runs out of space.
+///
+/// Use it for the sets that live as long as the scene, like materials, whose
+/// count is not known when the pool is created. The next pool holds twice as
+/// many sets as the previous one, or as many as the allocation that did not
+/// fit. An allocation failing on a new pool fails for good: its layouts use a
+/// descriptor type missing from the ratios, or more descriptors than reserved.
+pub struct GrowableDescriptorPool {
+    context: Arc<Context>,
+    ratios: Vec<PoolSizeRatio>,
+    sets_per_pool: u32,
+    pools: Vec<vk::DescriptorPool>,
+    current: usize,
+}
+
+impl GrowableDescriptorPool {
+    pub fn new(context: Arc<Context>, ratios: &[PoolSizeRatio], initial_sets: u32) -> Self {
+        let mut pool = Self {
+            context,
+            ratios: ratios.to_vec(),
+            sets_per_pool: initial_sets.clamp(1, MAX_SETS_PER_POOL),
+            pools: Vec::new(),
+            current: 0,
+        };
+        pool.push_pool(pool.sets_per_pool);
+        pool
+    }
+}
+
+impl GrowableDescriptorPool {
+    /// Allocate one set of `layout`, creating a new pool if the current one is full.
+    pub fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VksError> {
+        Ok(self.allocate_many(&[layout])?[0])
+    }
+
+    /// Allocate one set for each of `layouts`.
+    ///
+    /// The full pools are skipped until one has room, a new pool is created
+    /// after the last one. Fails if the sets do not fit in the new pool.
+    pub fn allocate_many(
+        &mut self,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Vec<vk::DescriptorSet>, VksError> {
+        let mut created_pool = false;
+        loop {
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(self.pools[self.current])
+                .set_layouts(layouts);
+
+            match unsafe {
+                self.context
+                    .device()
+                    .allocate_descriptor_sets(&allocate_info)
+            } {
+                Ok(sets) => return Ok(sets),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !created_pool =>
+                {
+                    created_pool = self.next_pool(layouts.len() as _)
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    /// Free all the sets allocated from the pools. The pools are kept for the next allocations.
+    ///
+    /// None of the sets must still be in use by the gpu.
+    pub fn reset(&mut self) {
+        for pool in self.pools.iter() {
+            unsafe {
+                self.context
+                    .device()
+                    .reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())
+                    .expect("Failed to reset descriptor pool")
+            };
+        }
+        self.current = 0;
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Move to the next pool, creating one for at least `requested_sets` after the last pool.
+    ///
+    /// # Returns
+    ///
+    /// True if the pool was created.
+    fn next_pool(&mut self, requested_sets: u32) -> bool {
+        self.current += 1;
+        if self.current < self.pools.len() {
+            return false;
+        }
+        self.sets_per_pool = next_pool_sets(self.sets_per_pool);
+        let sets = self.sets_per_pool.max(requested_sets);
+        tracing::debug!("Descriptor pool full, creating a new pool of {} sets", sets);
+        self.push_pool(sets);
+        true
+    }
+
+    fn push_pool(&mut self, sets: u32) {
+        let pool_sizes = pool_sizes(&self.ratios, sets);
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(sets);
+
+        let pool = unsafe {
+            self.context
+                .device()
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create descriptor pool")
+        };
+        self.pools.push(pool);
+    }
+}
+
+/// Sets of the pool created after a full pool of `sets`.
+fn next_pool_sets(sets: u32) -> u32 {
+    sets.saturating_mul(2).min(MAX_SETS_PER_POOL)
+}
+
+/// Descriptors of a pool of `sets` sets, at least one of each type of `ratios`.
+fn pool_sizes(ratios: &[PoolSizeRatio], sets: u32) -> Vec<vk::DescriptorPoolSize> {
+    ratios
+        .iter()
+        .map(|ratio| {
+            let descriptor_count = (ratio.ratio * sets as f32).ceil() as u32;
+            vk::DescriptorPoolSize {
+                ty: ratio.ty,
+                descriptor_count: descriptor_count.max(1),
+            }
+        })
+        .collect()
+}
+
+impl Drop for GrowableDescriptorPool {
+    fn drop(&mut self) {
+        for pool in self.pools.drain(..) {
+            unsafe { self.context.device().destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+/// One [`GrowableDescriptorPool`] per frame in flight for the sets that are only
+/// used by one frame, like egui textures or debug passes.
+///
+/// The pool of a frame is reset by [`TransientDescriptorPools::begin_frame`], which
+/// must be called once the fence of that frame was waited for.
+pub struct TransientDescriptorPools {
+    pools: Vec<GrowableDescriptorPool>,
+    current_frame: usize,
+}
+
+impl TransientDescriptorPools {
+    pub fn new(
+        context: &Arc<Context>,
+        ratios: &[PoolSizeRatio],
+        sets_per_frame: u32,
+        frame_count: usize,
+    ) -> Self {
+        let pools = (0..frame_count)
+            .map(|_| GrowableDescriptorPool::new(Arc::clone(context), ratios, sets_per_frame))
+            .collect();
+
+        Self {
+            pools,
+            current_frame: 0,
+        }
+    }
+}
+
+impl TransientDescriptorPools {
+    /// Reset the pools of `frame_index` and use them for the next allocations.
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        self.current_frame = frame_index;
+        self.pools[frame_index].reset();
+    }
+
+    /// Allocate a set of `layout` valid until the frame is begun again.
+    pub fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VksError> {
+        self.pools[self.current_frame].allocate(layout)
+    }
+}
+
+/// Allocator of the descriptor sets of a renderer, so the callers do not
+/// have to compute the size of the pools up front.
+///
+/// Sets from [`DescriptorAllocator::allocate`] live as long as the allocator,
+/// the ones from [`DescriptorAllocator::allocate_frame`] until the allocator
+/// comes back to their frame. Pools are added when full, see
+/// [`GrowableDescriptorPool`].
+pub struct DescriptorAllocator {
+    persistent: GrowableDescriptorPool,
+    transient: TransientDescriptorPools,
+    frame_index: usize,
+}
+
+impl DescriptorAllocator {
+    /// Create an allocator reserving descriptors as `ratios` of the sets,
+    /// usually [`DEFAULT_POOL_SIZE_RATIOS`].
+    pub fn new(context: &Arc<Context>, ratios: &[PoolSizeRatio]) -> Self {
+        Self {
+            persistent: GrowableDescriptorPool::new(
+                Arc::clone(context),
+                ratios,
+                INITIAL_PERSISTENT_SETS,
+            ),
+            transient: TransientDescriptorPools::new(
+                context,
+                ratios,
+                INITIAL_SETS_PER_FRAME,
+                MAX_FRAMES_IN_FLIGHT as _,
+            ),
+            frame_index: 0,
+        }
+    }
+}
+
+impl DescriptorAllocator {
+    /// Allocate a set of `layout` living as long as the allocator.
+    pub fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VksError> {
+        self.persistent.allocate(layout)
+    }
+
+    /// Allocate one set for each of `layouts`, living as long as the allocator.
+    pub fn allocate_many(
+        &mut self,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Vec<vk::DescriptorSet>, VksError> {
+        self.persistent.allocate_many(layouts)
+    }
+
+    /// Allocate a set of `layout` only used by the current frame.
+    pub fn allocate_frame(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VksError> {
+        self.transient.allocate(layout)
+    }
+
+    /// Move to the next frame in flight and free the sets it allocated last time.
+    ///
+    /// Call it once per frame, after waiting for the fence of the frame.
+    pub fn reset_frame(&mut self) {
+        self.frame_index = (self.frame_index + 1) % MAX_FRAMES_IN_FLIGHT as usize;
+        self.transient.begin_frame(self.frame_index);
+    }
+
+    /// Number of pools of the sets living as long as the allocator.
+    pub fn pool_count(&self) -> usize {
+        self.persistent.pool_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pools_double_up_to_the_maximum() {
+        assert_eq!(next_pool_sets(64), 128);
+        assert_eq!(next_pool_sets(MAX_SETS_PER_POOL / 2 + 1), MAX_SETS_PER_POOL);
+        assert_eq!(next_pool_sets(MAX_SETS_PER_POOL), MAX_SETS_PER_POOL);
+        assert_eq!(next_pool_sets(u32::MAX), MAX_SETS_PER_POOL);
+    }
+
+    #[test]
+    fn descriptor_counts_follow_the_ratios() {
+        let sizes = pool_sizes(&DEFAULT_POOL_SIZE_RATIOS, 16);
+        let counts = sizes
+            .iter()
+            .map(|size| (size.ty, size.descriptor_count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                (vk::DescriptorType::UNIFORM_BUFFER, 32),
+                (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 16),
+                (vk::DescriptorType::STORAGE_BUFFER, 16),
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 96),
+                (vk::DescriptorType::STORAGE_IMAGE, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn fractional_ratios_round_up_to_one_descriptor() {
+        let ratios = [
+            PoolSizeRatio::new(vk::DescriptorType::SAMPLER, 0.25),
+            PoolSizeRatio::new(vk::DescriptorType::SAMPLED_IMAGE, 0.0),
+        ];
+        let counts = pool_sizes(&ratios, 10)
+            .iter()
+            .map(|size| size.descriptor_count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [3, 1]);
+    }
+}
@@ -0,0 +1,68 @@
+use ash::vk;
+use std::mem::size_of;
+
+/// Index written when a draw has no node or no material.
+pub const NO_DEBUG_INDEX: u32 = u32::MAX;
+
+/// Identify a draw in validation messages and captures.
+///
+/// When draw ids are enabled on the [`crate::Context`], it is pushed as
+/// push constants before the draw and a debug label named after it is
+/// inserted in the command buffer. Tools reading a capture can then map
+/// each draw back to the scene node and material that produced it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawDebugId {
+    pub node_index: u32,
+    pub material_index: u32,
+}
+
+impl DrawDebugId {
+    /// Size of the push constants.
+    pub const SIZE: u32 = size_of::<DrawDebugId>() as u32;
+
+    pub fn new(node_index: Option<usize>, material_index: Option<usize>) -> Self {
+        Self {
+            node_index: node_index.map_or(NO_DEBUG_INDEX, |index| index as _),
+            material_index: material_index.map_or(NO_DEBUG_INDEX, |index| index as _),
+        }
+    }
+
+    /// Range to add to the pipeline layouts of the draws that push an id.
+    pub fn push_constant_range(
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+    ) -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags,
+            offset,
+            size: Self::SIZE,
+        }
+    }
+
+    /// Name of the debug label of the draw, `node=<index> material=<index>`.
+    pub fn label(&self) -> String {
+        let format_index = |index: u32| match index {
+            NO_DEBUG_INDEX => "none".to_owned(),
+            index => index.to_string(),
+        };
+        format!(
+            "node={} material={}",
+            format_index(self.node_index),
+            format_index(self.material_index)
+        )
+    }
+
+    pub(crate) fn as_bytes(&self) -> [u8; Self::SIZE as usize] {
+        let mut bytes = [0; Self::SIZE as usize];
+        bytes[..4].copy_from_slice(&self.node_index.to_ne_bytes());
+        bytes[4..].copy_from_slice(&self.material_index.to_ne_bytes());
+        bytes
+    }
+}
+
+impl Default for DrawDebugId {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
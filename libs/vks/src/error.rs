@@ -0,0 +1,56 @@
+use ash::vk;
+use std::{error::Error, fmt, io, path::PathBuf};
+
+/// Error of the fallible constructors of the crate.
+#[derive(Debug)]
+pub enum VksError {
+    /// The device was lost, by a driver crash or a gpu reset.
+    DeviceLost,
+    /// The surface of the window was lost, it must be created again.
+    SurfaceLost,
+    /// Host or device memory is exhausted.
+    OutOfMemory,
+    /// The compiled shader at `path` can't be read or is not valid SPIR-V.
+    ShaderLoad { path: PathBuf, cause: io::Error },
+    /// The instance, the device or the surface lack what is described.
+    UnsupportedFeature(String),
+    /// Any other error returned by Vulkan.
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for VksError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => Self::DeviceLost,
+            vk::Result::ERROR_SURFACE_LOST_KHR => Self::SurfaceLost,
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => {
+                Self::OutOfMemory
+            }
+            result => Self::Vulkan(result),
+        }
+    }
+}
+
+impl fmt::Display for VksError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DeviceLost => write!(f, "Device lost"),
+            Self::SurfaceLost => write!(f, "Surface lost"),
+            Self::OutOfMemory => write!(f, "Out of memory"),
+            Self::ShaderLoad { path, cause } => {
+                write!(f, "Failed to load shader {}: {}", path.display(), cause)
+            }
+            Self::UnsupportedFeature(feature) => write!(f, "Unsupported feature: {}", feature),
+            Self::Vulkan(result) => write!(f, "Vulkan error: {}", result),
+        }
+    }
+}
+
+impl Error for VksError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ShaderLoad { cause, .. } => Some(cause),
+            _ => None,
+        }
+    }
+}
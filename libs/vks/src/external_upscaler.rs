@@ -0,0 +1,114 @@
+use crate::{halton_jitter, Texture, UpscaleError, UpscaleInputs, Upscaler};
+use ash::vk;
+
+/// Ratio between the display and render resolutions of a temporal upscaler,
+/// the ones of FSR2 and DLSS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleQuality {
+    Quality,
+    Balanced,
+    Performance,
+    UltraPerformance,
+}
+
+impl UpscaleQuality {
+    pub fn all() -> [Self; 4] {
+        [
+            Self::Quality,
+            Self::Balanced,
+            Self::Performance,
+            Self::UltraPerformance,
+        ]
+    }
+
+    pub fn scale_factor(self) -> f32 {
+        match self {
+            Self::Quality => 1.5,
+            Self::Balanced => 1.7,
+            Self::Performance => 2.0,
+            Self::UltraPerformance => 3.0,
+        }
+    }
+}
+
+/// Record the dispatch of an [`ExternalUpscaler`] through the bindings of the application.
+///
+/// Receives the command buffer, the inputs, the output and the extent the inputs
+/// were rendered at. The context of the upscaler must be created for the display extent.
+pub type UpscaleDispatch =
+    Box<dyn FnMut(vk::CommandBuffer, &UpscaleInputs, &Texture, vk::Extent2D) + Send>;
+
+/// Temporal upscaler provided by the application, like FSR2, DLSS or XeSS.
+///
+/// Their SDKs are not dependencies of this crate. This computes the render
+/// extent and jitter sequence they expect and forwards the dispatch to the
+/// bindings of the application, so the renderer only sees an [`Upscaler`].
+pub struct ExternalUpscaler {
+    quality: UpscaleQuality,
+    display_extent: vk::Extent2D,
+    dispatch: UpscaleDispatch,
+}
+
+impl ExternalUpscaler {
+    pub fn new(
+        quality: UpscaleQuality,
+        display_extent: vk::Extent2D,
+        dispatch: UpscaleDispatch,
+    ) -> Self {
+        Self {
+            quality,
+            display_extent,
+            dispatch,
+        }
+    }
+}
+
+impl ExternalUpscaler {
+    pub fn quality(&self) -> UpscaleQuality {
+        self.quality
+    }
+
+    pub fn set_quality(&mut self, quality: UpscaleQuality) {
+        self.quality = quality;
+    }
+
+    /// To call when the swapchain is resized, before recreating the inputs.
+    pub fn set_display_extent(&mut self, display_extent: vk::Extent2D) {
+        self.display_extent = display_extent;
+    }
+
+    /// Number of frames of the jitter sequence, more when the render resolution is lower.
+    pub fn jitter_phase_count(&self) -> u32 {
+        let render_width = self.render_extent(self.display_extent).width as f32;
+        let ratio = self.display_extent.width as f32 / render_width;
+        (8.0 * ratio * ratio).ceil() as u32
+    }
+}
+
+impl Upscaler for ExternalUpscaler {
+    fn render_extent(&self, display_extent: vk::Extent2D) -> vk::Extent2D {
+        let scale = self.quality.scale_factor();
+        vk::Extent2D {
+            width: ((display_extent.width as f32 / scale) as u32).max(1),
+            height: ((display_extent.height as f32 / scale) as u32).max(1),
+        }
+    }
+
+    fn jitter(&self, frame_index: u64) -> [f32; 2] {
+        halton_jitter(frame_index, self.jitter_phase_count())
+    }
+
+    fn cmd_upscale(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        inputs: &UpscaleInputs,
+        output: &Texture,
+    ) -> Result<(), UpscaleError> {
+        if inputs.motion_vectors.is_none() {
+            return Err(UpscaleError::MissingMotionVectors);
+        }
+        let render_extent = self.render_extent(self.display_extent);
+        (self.dispatch)(command_buffer, inputs, output, render_extent);
+        Ok(())
+    }
+}
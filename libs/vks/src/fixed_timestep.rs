@@ -0,0 +1,210 @@
+use math::{
+    cgmath::{Point3, Quaternion, Vector3, VectorSpace},
+    slerp,
+};
+
+/// Steps per second of a [`FixedTimestep`] created with `Default`.
+pub const DEFAULT_SIMULATION_RATE: u32 = 60;
+/// Most steps run by a single [`FixedTimestep::advance`]. The time left after
+/// a long hitch is dropped rather than catching up with it over many frames.
+pub const MAX_SIMULATION_STEPS_PER_FRAME: u32 = 8;
+
+/// Accumulator running a simulation at a fixed rate whatever the render rate.
+///
+/// Each frame, [`FixedTimestep::advance`] adds the elapsed time to the
+/// accumulator and runs as many whole steps as it holds. What is left is the
+/// fraction of a step the frame is ahead of the last simulated state, used to
+/// interpolate between the last two states with [`Interpolated`].
+///
+/// ```ignore
+/// let alpha = timestep.advance(delta_s, |step| {
+///     position.set(simulate(*position.current(), step));
+/// });
+/// draw(position.interpolate(alpha));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    /// Duration of a step in seconds.
+    step: f32,
+    /// Elapsed time not simulated yet, in seconds.
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Create a timestep of `rate` steps per second.
+    pub fn new(rate: u32) -> Self {
+        Self {
+            step: 1.0 / rate.max(1) as f32,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIMULATION_RATE)
+    }
+}
+
+impl FixedTimestep {
+    /// Duration of a step in seconds.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Add `delta_time` seconds and call `update` with the duration of the
+    /// step for each whole step accumulated.
+    ///
+    /// # Returns
+    ///
+    /// The interpolation factor of the frame, see [`FixedTimestep::alpha`].
+    pub fn advance<F: FnMut(f32)>(&mut self, delta_time: f32, mut update: F) -> f32 {
+        self.accumulator += delta_time.max(0.0);
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == MAX_SIMULATION_STEPS_PER_FRAME {
+                self.accumulator %= self.step;
+                break;
+            }
+            update(self.step);
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        self.alpha()
+    }
+
+    /// Fraction of a step elapsed since the last one, in [0, 1).
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+/// Value blended between two simulation steps.
+pub trait Interpolate {
+    /// `self` at `alpha` 0, `other` at `alpha` 1.
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        self + (other - self) * alpha
+    }
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        self.lerp(*other, alpha)
+    }
+}
+
+impl Interpolate for Point3<f32> {
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        self + (other - self) * alpha
+    }
+}
+
+impl Interpolate for Quaternion<f32> {
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        slerp(*self, *other, alpha)
+    }
+}
+
+/// Last two states of a simulated value, to render it between them.
+#[derive(Debug, Clone, Copy)]
+pub struct Interpolated<T> {
+    previous: T,
+    current: T,
+}
+
+impl<T: Interpolate + Clone> Interpolated<T> {
+    /// Start from `value`, without motion to interpolate.
+    pub fn new(value: T) -> Self {
+        Self {
+            previous: value.clone(),
+            current: value,
+        }
+    }
+
+    /// Record `value` as the state of the last step.
+    pub fn set(&mut self, value: T) {
+        self.previous = std::mem::replace(&mut self.current, value);
+    }
+
+    /// Jump to `value` without interpolating from the previous state.
+    pub fn reset(&mut self, value: T) {
+        *self = Self::new(value);
+    }
+
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    /// The value `alpha` of a step after the previous state, see [`FixedTimestep::alpha`].
+    pub fn interpolate(&self, alpha: f32) -> T {
+        self.previous.interpolate(&self.current, alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_runs_whole_steps_and_keeps_the_remainder() {
+        let mut timestep = FixedTimestep::new(4);
+        let mut steps = Vec::new();
+
+        let alpha = timestep.advance(0.625, |step| steps.push(step));
+
+        assert_eq!(steps, [0.25, 0.25]);
+        assert_eq!(alpha, 0.5);
+    }
+
+    #[test]
+    fn advance_accumulates_short_frames() {
+        let mut timestep = FixedTimestep::new(4);
+        let mut count = 0;
+
+        timestep.advance(0.125, |_| count += 1);
+        assert_eq!(count, 0);
+        let alpha = timestep.advance(0.125, |_| count += 1);
+
+        assert_eq!(count, 1);
+        assert_eq!(alpha, 0.0);
+    }
+
+    #[test]
+    fn advance_drops_the_time_beyond_the_max_steps() {
+        let mut timestep = FixedTimestep::new(4);
+        let mut count = 0;
+
+        let alpha = timestep.advance(10.125, |_| count += 1);
+
+        assert_eq!(count, MAX_SIMULATION_STEPS_PER_FRAME);
+        assert_eq!(alpha, 0.5);
+    }
+
+    #[test]
+    fn advance_ignores_negative_time() {
+        let mut timestep = FixedTimestep::new(4);
+
+        let alpha = timestep.advance(-1.0, |_| panic!("No step expected"));
+
+        assert_eq!(alpha, 0.0);
+    }
+
+    #[test]
+    fn interpolated_blends_the_last_two_states() {
+        let mut value = Interpolated::new(1.0f32);
+        value.set(3.0);
+
+        assert_eq!(value.interpolate(0.0), 1.0);
+        assert_eq!(value.interpolate(0.5), 2.0);
+        assert_eq!(*value.current(), 3.0);
+
+        value.reset(5.0);
+        assert_eq!(value.interpolate(0.5), 5.0);
+    }
+}
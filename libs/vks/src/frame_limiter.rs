@@ -0,0 +1,131 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time before the start of the next frame from which [`FpsLimiter::wait`]
+/// spins instead of sleeping, sleeps overshooting by up to a millisecond.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+/// Cap the frame rate on the cpu, whatever the present mode.
+///
+/// Frames are started at a regular interval. A frame late on its slot starts
+/// right away and the following ones are scheduled from it, so the rate is
+/// not exceeded to catch up.
+///
+/// ```ignore
+/// limiter.wait();
+/// // sample input, record, submit and present...
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FpsLimiter {
+    interval: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FpsLimiter {
+    /// Create a limiter of `max_fps` frames per second, unlimited if `None`.
+    pub fn new(max_fps: Option<u32>) -> Self {
+        let mut limiter = Self::default();
+        limiter.set_max_fps(max_fps);
+        limiter
+    }
+}
+
+impl FpsLimiter {
+    pub fn max_fps(&self) -> Option<u32> {
+        self.interval
+            .map(|interval| (1.0 / interval.as_secs_f64()).round() as u32)
+    }
+
+    /// Set the maximum frame rate, unlimited if `None` or 0.
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.interval = max_fps
+            .filter(|fps| *fps > 0)
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+        self.next_frame = None;
+    }
+
+    /// Wait until the next frame can start. Call once per frame, before
+    /// sampling its input.
+    pub fn wait(&mut self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let now = Instant::now();
+        let start = match self.next_frame {
+            Some(next_frame) if next_frame > now => {
+                if let Some(sleep) = (next_frame - now).checked_sub(SPIN_THRESHOLD) {
+                    thread::sleep(sleep);
+                }
+                while Instant::now() < next_frame {
+                    std::hint::spin_loop();
+                }
+                next_frame
+            }
+            _ => now,
+        };
+        self.next_frame = Some(start + interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_fps_round_trips() {
+        assert_eq!(FpsLimiter::new(Some(60)).max_fps(), Some(60));
+        assert_eq!(FpsLimiter::new(Some(144)).max_fps(), Some(144));
+    }
+
+    #[test]
+    fn zero_or_none_is_unlimited() {
+        assert_eq!(FpsLimiter::new(None).max_fps(), None);
+        assert_eq!(FpsLimiter::new(Some(0)).max_fps(), None);
+    }
+
+    #[test]
+    fn unlimited_wait_does_not_schedule() {
+        let mut limiter = FpsLimiter::new(None);
+        limiter.wait();
+        assert!(limiter.next_frame.is_none());
+    }
+
+    #[test]
+    fn wait_starts_frames_an_interval_apart() {
+        let mut limiter = FpsLimiter::new(Some(200));
+        let interval = Duration::from_millis(5);
+
+        limiter.wait();
+        let first = limiter.next_frame.unwrap();
+        limiter.wait();
+
+        assert!(Instant::now() >= first);
+        assert_eq!(limiter.next_frame, Some(first + interval));
+    }
+
+    #[test]
+    fn late_frame_starts_right_away() {
+        let mut limiter = FpsLimiter::new(Some(1000));
+        limiter.wait();
+        thread::sleep(Duration::from_millis(5));
+
+        let before = Instant::now();
+        limiter.wait();
+        let next_frame = limiter.next_frame.unwrap();
+
+        assert!(next_frame >= before + Duration::from_millis(1));
+        assert!(next_frame <= Instant::now() + Duration::from_millis(1));
+    }
+
+    #[test]
+    fn set_max_fps_resets_the_schedule() {
+        let mut limiter = FpsLimiter::new(Some(60));
+        limiter.wait();
+        limiter.set_max_fps(Some(30));
+        assert!(limiter.next_frame.is_none());
+        assert_eq!(limiter.max_fps(), Some(30));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{create_pipeline, Camera, Context, PipelineParameters, ShaderParameters};
+use ash::vk;
+use math::cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+use std::{mem::size_of, sync::Arc};
+use util::any_as_u8_slice;
+
+/// Size in pixels of the square viewport of the gizmo.
+const GIZMO_SIZE: u32 = 96;
+/// Distance in pixels between the gizmo and the corner of the target.
+const GIZMO_MARGIN: u32 = 16;
+/// Length of the axes relative to the half size of the viewport.
+const GIZMO_AXIS_SCALE: f32 = 0.8;
+/// Distance in pixels from the tip of an axis under which clicks pick it.
+const GIZMO_PICK_RADIUS: f32 = 10.0;
+
+/// Axis aligned direction the camera can be snapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoAxis {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl GizmoAxis {
+    pub fn all() -> [GizmoAxis; 6] {
+        [
+            GizmoAxis::PositiveX,
+            GizmoAxis::NegativeX,
+            GizmoAxis::PositiveY,
+            GizmoAxis::NegativeY,
+            GizmoAxis::PositiveZ,
+            GizmoAxis::NegativeZ,
+        ]
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        match self {
+            GizmoAxis::PositiveX => Vector3::unit_x(),
+            GizmoAxis::NegativeX => -Vector3::unit_x(),
+            GizmoAxis::PositiveY => Vector3::unit_y(),
+            GizmoAxis::NegativeY => -Vector3::unit_y(),
+            GizmoAxis::PositiveZ => Vector3::unit_z(),
+            GizmoAxis::NegativeZ => -Vector3::unit_z(),
+        }
+    }
+
+    /// Move `camera` on this axis of its target, keeping its distance to it.
+    ///
+    /// The up vector of the view is y so the views along y are slightly tilted
+    /// toward +z to stay defined.
+    pub fn snap(&self, camera: &mut Camera) {
+        let target = camera.target();
+        let distance = (camera.position() - target).magnitude();
+        let direction = match self {
+            GizmoAxis::PositiveY | GizmoAxis::NegativeY => {
+                (self.direction() + Vector3::unit_z() * 1e-3).normalize()
+            }
+            _ => self.direction(),
+        };
+        camera.look_at(target + direction * distance, target);
+    }
+}
+
+impl std::fmt::Display for GizmoAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GizmoAxis::PositiveX => write!(f, "+X"),
+            GizmoAxis::NegativeX => write!(f, "-X"),
+            GizmoAxis::PositiveY => write!(f, "+Y"),
+            GizmoAxis::NegativeY => write!(f, "-Y"),
+            GizmoAxis::PositiveZ => write!(f, "+Z"),
+            GizmoAxis::NegativeZ => write!(f, "-Z"),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GizmoConstants {
+    rotation: [[f32; 4]; 4],
+    scale: f32,
+}
+
+/// Rotation part of the view matrix of `camera`.
+fn view_rotation(camera: &Camera) -> Matrix4<f32> {
+    let mut rotation = camera.view_matrix();
+    rotation.w = Vector4::unit_w();
+    rotation
+}
+
+/// Orientation axes of the camera drawn in the top right corner of the target.
+///
+/// Each world axis is drawn as a line, red for x, green for y and blue for z,
+/// the negative ones dimmer. Clicking the tip of an axis, see
+/// [`OrientationGizmo::pick`], snaps the camera to it with [`GizmoAxis::snap`].
+///
+/// Must be recorded inside an active dynamic rendering whose attachments have
+/// the formats and sample count passed at creation.
+pub struct OrientationGizmo {
+    context: Arc<Context>,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl OrientationGizmo {
+    /// Create a gizmo drawn to attachments of `color_format` and `depth_format`
+    /// with `samples`, in passes with a fragment shading rate attachment if `shading_rate_attachment`.
+    pub fn new(
+        context: &Arc<Context>,
+        color_format: vk::Format,
+        depth_format: Option<vk::Format>,
+        samples: vk::SampleCountFlags,
+        shading_rate_attachment: bool,
+    ) -> Self {
+        let device = context.device();
+
+        let pipeline_layout = {
+            let push_constant_range = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: size_of::<GizmoConstants>() as _,
+            }];
+            let layout_info =
+                vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_range);
+
+            unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+        };
+
+        let pipeline = {
+            let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+                .viewport_count(1)
+                .scissor_count(1);
+
+            let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+                .depth_clamp_enable(false)
+                .rasterizer_discard_enable(false)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .depth_bias_enable(false);
+
+            let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+                .sample_shading_enable(false)
+                .rasterization_samples(samples)
+                .min_sample_shading(1.0)
+                .alpha_to_coverage_enable(false)
+                .alpha_to_one_enable(false);
+
+            let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+                .color_write_mask(
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A,
+                )
+                .blend_enable(false)];
+
+            let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+            let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .stencil_test_enable(false);
+
+            create_pipeline::<()>(
+                context,
+                PipelineParameters {
+                    vertex_shader_params: ShaderParameters::new("gizmo"),
+                    fragment_shader_params: ShaderParameters::new("gizmo"),
+                    multisampling_info: &multisampling_info,
+                    viewport_info: &viewport_info,
+                    topology: vk::PrimitiveTopology::LINE_LIST,
+                    rasterizer_info: &rasterizer_info,
+                    dynamic_state_info: Some(&dynamic_state_info),
+                    depth_stencil_info: Some(&depth_stencil_info),
+                    stencil: None,
+                    shading_rate: None,
+                    shading_rate_attachment,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachment_formats: &[color_format],
+                    depth_attachment_format: depth_format,
+                    layout: pipeline_layout,
+                    parent: None,
+                    allow_derivatives: false,
+                },
+            )
+            .expect("Failed to create graphics pipeline")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Area of `extent` covered by the gizmo.
+    pub fn area(extent: vk::Extent2D) -> vk::Rect2D {
+        let size = GIZMO_SIZE.min(extent.width).min(extent.height);
+        let margin = GIZMO_MARGIN
+            .min(extent.width - size)
+            .min(extent.height - size);
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: (extent.width - size - margin) as _,
+                y: margin as _,
+            },
+            extent: vk::Extent2D {
+                width: size,
+                height: size,
+            },
+        }
+    }
+
+    /// Record the draw of the orientation of `camera` in the corner of `extent`.
+    ///
+    /// The viewport and scissor are left on the gizmo area, they must be set
+    /// again before recording other draws.
+    pub fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        camera: &Camera,
+    ) {
+        let device = self.context.device();
+        let area = Self::area(extent);
+
+        let constants = GizmoConstants {
+            rotation: view_rotation(camera).into(),
+            scale: GIZMO_AXIS_SCALE,
+        };
+
+        unsafe {
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: area.offset.x as _,
+                    y: area.offset.y as _,
+                    width: area.extent.width as _,
+                    height: area.extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(command_buffer, 0, &[area]);
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                any_as_u8_slice(&constants),
+            );
+
+            device.cmd_draw(command_buffer, 12, 1, 0, 0);
+        }
+    }
+
+    /// Axis whose tip is under `cursor`, in pixels of `extent`.
+    ///
+    /// When tips overlap the one closest to the viewer is picked.
+    pub fn pick(camera: &Camera, extent: vk::Extent2D, cursor: [f32; 2]) -> Option<GizmoAxis> {
+        let area = Self::area(extent);
+        let half_size = area.extent.width as f32 * 0.5;
+        let center = [
+            area.offset.x as f32 + half_size,
+            area.offset.y as f32 + half_size,
+        ];
+        let rotation = view_rotation(camera);
+
+        GizmoAxis::all()
+            .into_iter()
+            .filter_map(|axis| {
+                let tip = rotation * axis.direction().extend(0.0) * GIZMO_AXIS_SCALE;
+                let x = center[0] + tip.x * half_size;
+                let y = center[1] - tip.y * half_size;
+                let distance = (x - cursor[0]).hypot(y - cursor[1]);
+                (distance <= GIZMO_PICK_RADIUS).then_some((axis, tip.z))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(axis, _)| axis)
+    }
+}
+
+impl Drop for OrientationGizmo {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
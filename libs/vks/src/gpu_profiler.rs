@@ -0,0 +1,282 @@
+use crate::Context;
+use ash::vk;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Largest number of scopes timed in a frame, the next ones are ignored.
+pub const MAX_GPU_SCOPES: u32 = 64;
+pub const DEFAULT_GPU_PROFILER_CAPACITY: usize = 300;
+
+/// Gpu time spent in a scope of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuScopeTiming {
+    pub name: String,
+    /// Number of scopes it is nested in, 0 at the top level.
+    pub depth: u32,
+    /// Duration in milliseconds.
+    pub duration: f64,
+}
+
+/// Gpu timings of the scopes of a frame, in the order they began.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GpuFrameTimings {
+    pub frame: u64,
+    pub scopes: Vec<GpuScopeTiming>,
+}
+
+impl GpuFrameTimings {
+    /// Sum of the durations of the top level scopes in milliseconds.
+    pub fn total(&self) -> f64 {
+        self.scopes
+            .iter()
+            .filter(|scope| scope.depth == 0)
+            .map(|scope| scope.duration)
+            .sum()
+    }
+}
+
+/// Queries of the frames using a frame in flight.
+struct FrameQueries {
+    pool: vk::QueryPool,
+    /// Name and depth of each scope, the scope `i` writes the queries `2i` and `2i + 1`.
+    scopes: Vec<(String, u32)>,
+    frame: u64,
+    /// The queries were recorded and not resolved yet.
+    pending: bool,
+}
+
+/// Time the passes of the frames on the gpu with timestamp queries.
+///
+/// Each frame in flight has its own query pool, reset when a frame begins
+/// recording with [`GpuProfiler::cmd_begin_frame`]. Scopes are recorded between
+/// [`GpuProfiler::begin_scope`] and [`GpuProfiler::end_scope`] and can be
+/// nested. Once the fence of the frame in flight was waited for, the timings of
+/// its last frame are read with [`GpuProfiler::resolve`].
+///
+/// ```ignore
+/// wait_for_fences(fence);
+/// profiler.resolve(frame_index);
+/// profiler.cmd_begin_frame(command_buffer, frame_index);
+/// profiler.begin_scope(command_buffer, "scene");
+/// // ...
+/// profiler.end_scope(command_buffer);
+/// ```
+///
+/// Does nothing if the graphics queue does not support timestamps.
+pub struct GpuProfiler {
+    context: Arc<Context>,
+    frames: Vec<FrameQueries>,
+    /// Frame in flight being recorded.
+    current: Option<usize>,
+    /// Scopes begun and not ended yet, `None` for the ones over [`MAX_GPU_SCOPES`].
+    open_scopes: Vec<Option<usize>>,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    /// Mask of the valid bits of the timestamps.
+    timestamp_mask: u64,
+    capacity: usize,
+    history: VecDeque<GpuFrameTimings>,
+    next_frame: u64,
+}
+
+impl GpuProfiler {
+    /// Create a profiler for `frames_in_flight` frames keeping the timings of
+    /// the last `capacity` frames.
+    pub fn new(context: &Arc<Context>, frames_in_flight: usize, capacity: usize) -> Self {
+        let limits = context.physical_device_limits();
+        let graphics_index = context.queue_families_indices().graphics_index;
+        let valid_bits = context
+            .queue_families()
+            .into_iter()
+            .find(|family| family.index == graphics_index)
+            .map_or(0, |family| family.properties.timestamp_valid_bits);
+
+        let frames = if valid_bits > 0 {
+            (0..frames_in_flight)
+                .map(|_| FrameQueries {
+                    pool: create_query_pool(context),
+                    scopes: Vec::new(),
+                    frame: 0,
+                    pending: false,
+                })
+                .collect()
+        } else {
+            tracing::warn!("The graphics queue does not support timestamps, gpu timings disabled");
+            Vec::new()
+        };
+
+        Self {
+            context: Arc::clone(context),
+            frames,
+            current: None,
+            open_scopes: Vec::new(),
+            timestamp_period: limits.timestamp_period as f64,
+            timestamp_mask: u64::MAX >> (64 - valid_bits.clamp(1, 64)),
+            capacity,
+            history: VecDeque::with_capacity(capacity),
+            next_frame: 0,
+        }
+    }
+}
+
+impl GpuProfiler {
+    pub fn is_supported(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Read the timings of the last frame recorded for the frame in flight
+    /// `frame_index`. Its fence must have been waited for.
+    ///
+    /// Frames whose queries are not all available, because a scope was not
+    /// ended or the frame was not submitted, are dropped.
+    pub fn resolve(&mut self, frame_index: usize) {
+        let Some(queries) = self.frames.get_mut(frame_index) else {
+            return;
+        };
+        if !queries.pending || queries.scopes.is_empty() {
+            queries.pending = false;
+            return;
+        }
+        queries.pending = false;
+
+        let mut timestamps = vec![0u64; queries.scopes.len() * 2];
+        let result = unsafe {
+            self.context.device().get_query_pool_results(
+                queries.pool,
+                0,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        match result {
+            Ok(()) => {}
+            Err(vk::Result::NOT_READY) => return,
+            Err(error) => {
+                tracing::warn!("Failed to read the gpu timestamps: {}", error);
+                return;
+            }
+        }
+
+        let to_ms = |ticks: u64| (ticks & self.timestamp_mask) as f64 * self.timestamp_period / 1e6;
+        let scopes = queries
+            .scopes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|((name, depth), timestamps)| GpuScopeTiming {
+                name: name.clone(),
+                depth: *depth,
+                duration: to_ms(timestamps[1].wrapping_sub(timestamps[0])),
+            })
+            .collect();
+
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(GpuFrameTimings {
+            frame: queries.frame,
+            scopes,
+        });
+    }
+
+    /// Begin recording the scopes of a frame using the frame in flight `frame_index`.
+    ///
+    /// Resets its queries, so the timings of its previous frame must have been
+    /// read with [`GpuProfiler::resolve`].
+    pub fn cmd_begin_frame(&mut self, command_buffer: vk::CommandBuffer, frame_index: usize) {
+        let frame = self.next_frame;
+        self.next_frame += 1;
+        self.open_scopes.clear();
+
+        let Some(queries) = self.frames.get_mut(frame_index) else {
+            self.current = None;
+            return;
+        };
+        unsafe {
+            self.context.device().cmd_reset_query_pool(
+                command_buffer,
+                queries.pool,
+                0,
+                MAX_GPU_SCOPES * 2,
+            )
+        };
+        queries.scopes.clear();
+        queries.frame = frame;
+        queries.pending = true;
+        self.current = Some(frame_index);
+    }
+
+    /// Begin timing the scope `name`, ended by the next call to [`GpuProfiler::end_scope`].
+    pub fn begin_scope(&mut self, command_buffer: vk::CommandBuffer, name: &str) {
+        let depth = self.open_scopes.len() as u32;
+        let scope = self.current.and_then(|current| {
+            let queries = &mut self.frames[current];
+            let index = queries.scopes.len();
+            (index < MAX_GPU_SCOPES as usize).then(|| {
+                queries.scopes.push((name.to_owned(), depth));
+                index
+            })
+        });
+        if let (Some(current), Some(index)) = (self.current, scope) {
+            self.cmd_write_timestamp(command_buffer, current, index as u32 * 2);
+        }
+        self.open_scopes.push(scope);
+    }
+
+    /// End the last scope begun.
+    pub fn end_scope(&mut self, command_buffer: vk::CommandBuffer) {
+        let scope = self.open_scopes.pop().flatten();
+        if let (Some(current), Some(index)) = (self.current, scope) {
+            self.cmd_write_timestamp(command_buffer, current, index as u32 * 2 + 1);
+        }
+    }
+
+    fn cmd_write_timestamp(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        query: u32,
+    ) {
+        // All the previous commands must complete so the scopes do not overlap.
+        unsafe {
+            self.context.synchronization2().cmd_write_timestamp2(
+                command_buffer,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                self.frames[frame_index].pool,
+                query,
+            )
+        };
+    }
+
+    /// Resolved frames, from the oldest to the most recent.
+    pub fn frames(&self) -> impl Iterator<Item = &GpuFrameTimings> {
+        self.history.iter()
+    }
+
+    pub fn last_frame(&self) -> Option<&GpuFrameTimings> {
+        self.history.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        for queries in &self.frames {
+            unsafe { device.destroy_query_pool(queries.pool, None) };
+        }
+    }
+}
+
+fn create_query_pool(context: &Context) -> vk::QueryPool {
+    let create_info = vk::QueryPoolCreateInfo::default()
+        .query_type(vk::QueryType::TIMESTAMP)
+        .query_count(MAX_GPU_SCOPES * 2);
+    unsafe {
+        context
+            .device()
+            .create_query_pool(&create_info, None)
+            .expect("Failed to create timestamp query pool")
+    }
+}
@@ -1,12 +1,23 @@
-use crate::camera::Camera;
-use crate::{DEFAULT_FOV, DEFAULT_FPS_MOVE_SPEED, DEFAULT_Z_FAR, DEFAULT_Z_NEAR};
+use crate::camera::{Camera, CameraMode};
+use crate::{
+    dominant_bottleneck, format_driver_version, FrameBottleneck, FrameTelemetry, FrameTimings,
+    GpuFrameTimings, GpuProfiler, GuiLayout, LatencyMode, Light, LightKind, MaskEdges,
+    MeasurementOverlay, MemoryStats, PickTarget, PickedPixel, RendererSettings, ShadowCasterStats,
+    TestPattern, VertexColorMode, BOTTLENECK_WINDOW, DEFAULT_EMISSIVE_INTENSITY, DEFAULT_SHARPNESS,
+    MAX_EMISSIVE_INTENSITY,
+};
+use crate::{
+    DEFAULT_FOV, DEFAULT_FPS_MOVE_SPEED, DEFAULT_Z_FAR, DEFAULT_Z_NEAR, SSAO_KERNEL_SIZES,
+};
+use ash::vk;
 use egui::{ClippedPrimitive, Context, TexturesDelta, Ui, ViewportId, Widget};
 use egui_winit::State as EguiWinit;
-use math::cgmath::Deg;
+use math::cgmath::{Deg, Point3, Vector3};
 use winit::event::WindowEvent;
 use winit::window::Window as WinitWindow;
 
-const SSAO_KERNEL_SIZES: [u32; 4] = [16, 32, 64, 128];
+const MENU_ID: &str = "menu";
+
 fn get_kernel_size_index(size: u32) -> usize {
     SSAO_KERNEL_SIZES
         .iter()
@@ -29,20 +40,117 @@ pub struct Gui {
     egui: Context,
     egui_winit: EguiWinit,
     camera: Option<Camera>,
+    camera_changed: bool,
+    lights: Option<Vec<Light>>,
+    lights_changed: bool,
+    environments: Vec<String>,
+    environment_loading: bool,
+    environment_changed: bool,
+    frame_timings: Option<Vec<FrameTimings>>,
+    export_telemetry: bool,
+    gpu_timings: Option<Vec<GpuFrameTimings>>,
+    shadow_caster_stats: Option<ShadowCasterStats>,
+    memory_stats: Option<MemoryStats>,
+    pixel_pick_request: Option<(PickTarget, [u32; 2])>,
+    picked_pixel: Option<PickedPixel>,
+    measurement_overlay: Option<MeasurementOverlay>,
+    gpu_info: Option<GpuInfo>,
+    /// Placement of the menu until egui remembers its own, see [`Gui::set_layout`].
+    menu_open: bool,
+    menu_position: Option<egui::Pos2>,
     state: State,
 }
 
-pub struct  RendererSetting {}
+/// Snapshot of the capabilities of the device shown in the gpu panel.
+struct GpuInfo {
+    device: String,
+    driver_version: String,
+    api_version: String,
+    extensions: Vec<String>,
+    memory_heaps: Vec<String>,
+    limits: Vec<(&'static str, String)>,
+    report: String,
+}
+
+impl GpuInfo {
+    fn new(context: &crate::Context) -> Self {
+        let properties = context.physical_device_properties();
+        let limits = properties.limits;
+        let memory = context.get_mem_properties();
+        let memory_heaps = memory.memory_heaps[..memory.memory_heap_count as usize]
+            .iter()
+            .map(|heap| format!("{} MiB {:?}", heap.size / (1024 * 1024), heap.flags))
+            .collect();
+
+        Self {
+            device: context.physical_device_info().name.clone(),
+            driver_version: format_driver_version(properties.vendor_id, properties.driver_version),
+            api_version: format!(
+                "{}.{}.{}",
+                vk::api_version_major(properties.api_version),
+                vk::api_version_minor(properties.api_version),
+                vk::api_version_patch(properties.api_version)
+            ),
+            extensions: context.enabled_device_features().extensions.clone(),
+            memory_heaps,
+            limits: vec![
+                ("Max image 2D", limits.max_image_dimension2_d.to_string()),
+                (
+                    "Max per stage samplers",
+                    limits.max_per_stage_descriptor_samplers.to_string(),
+                ),
+                (
+                    "Max uniform buffer range",
+                    limits.max_uniform_buffer_range.to_string(),
+                ),
+                (
+                    "Max storage buffer range",
+                    limits.max_storage_buffer_range.to_string(),
+                ),
+                (
+                    "Max push constants size",
+                    limits.max_push_constants_size.to_string(),
+                ),
+                (
+                    "Max sampler anisotropy",
+                    limits.max_sampler_anisotropy.to_string(),
+                ),
+                (
+                    "Color sample counts",
+                    format!("{:?}", limits.framebuffer_color_sample_counts),
+                ),
+            ],
+            report: context.capabilities_report(),
+        }
+    }
+}
 
 impl Gui {
-    pub fn new(window: &WinitWindow, renderer_settings: Option<RendererSetting>) -> Self {
+    pub fn new(window: &WinitWindow, renderer_settings: Option<RendererSettings>) -> Self {
         let (egui, egui_winit) = init_egui(window);
 
         Self {
             egui,
             egui_winit,
             camera: None,
-            state: State{},
+            camera_changed: false,
+            lights: None,
+            lights_changed: false,
+            environments: Vec::new(),
+            environment_loading: false,
+            environment_changed: false,
+            frame_timings: None,
+            export_telemetry: false,
+            gpu_timings: None,
+            shadow_caster_stats: None,
+            memory_stats: None,
+            pixel_pick_request: None,
+            picked_pixel: None,
+            measurement_overlay: None,
+            gpu_info: None,
+            menu_open: false,
+            menu_position: None,
+            state: State::default(),
         }
     }
 
@@ -62,17 +170,83 @@ impl Gui {
             pixels_per_point,
             ..
         } = self.egui.run(raw_input, |ctx: &Context| {
-            egui::Window::new("Menu ('H' to toggle)")
-                .default_open(false)
-                .show(ctx, |ui| {
-                    build_renderer_settings_window(ui, &mut self.state);
+            let mut menu = egui::Window::new("Menu ('H' to toggle)")
+                .id(egui::Id::new(MENU_ID))
+                .default_open(self.menu_open);
+            if let Some(position) = self.menu_position {
+                menu = menu.default_pos(position);
+            }
+            menu.show(ctx, |ui| {
+                build_renderer_settings_window(ui, &mut self.state);
+                ui.separator();
+                if !self.environments.is_empty() {
+                    build_environment_window(
+                        ui,
+                        &mut self.state,
+                        &self.environments,
+                        self.environment_loading,
+                    );
                     ui.separator();
-                    build_camera_details_window(ui, &mut self.state, self.camera);
+                }
+                if let Some(camera) = self.camera.as_mut() {
+                    self.camera_changed = build_camera_details_window(ui, camera);
                     ui.separator();
-                    build_animation_player_window(ui, &mut self.state);
-                });
+                }
+                build_animation_player_window(ui, &mut self.state);
+                if let Some(lights) = self.lights.as_mut() {
+                    ui.separator();
+                    self.lights_changed = build_lights_window(ui, lights);
+                }
+                if let Some(frame_timings) = self.frame_timings.as_ref() {
+                    ui.separator();
+                    self.export_telemetry =
+                        build_frame_pacing_window(ui, &mut self.state, frame_timings);
+                }
+                if let Some(gpu_timings) = self.gpu_timings.as_ref() {
+                    ui.separator();
+                    build_gpu_timings_window(ui, gpu_timings);
+                }
+                if let Some(stats) = self.shadow_caster_stats {
+                    ui.separator();
+                    build_shadow_casters_window(ui, stats);
+                }
+                if let Some(stats) = self.memory_stats {
+                    ui.separator();
+                    build_memory_window(ui, stats);
+                }
+                if let Some(gpu_info) = self.gpu_info.as_ref() {
+                    ui.separator();
+                    build_gpu_info_window(ui, gpu_info);
+                }
+            });
+
+            if let Some(picked_pixel) = self.picked_pixel.filter(|_| is_picking(ctx, &self.state)) {
+                egui::show_tooltip_at_pointer(
+                    ctx,
+                    egui::LayerId::background(),
+                    egui::Id::new("pixel_picker"),
+                    |ui| ui.label(picked_pixel.to_string()),
+                );
+            }
+
+            if let Some(overlay) = self.measurement_overlay.as_ref() {
+                draw_measurement_overlay(ctx, overlay);
+            }
         });
 
+        self.pixel_pick_request = if is_picking(&self.egui, &self.state) {
+            let target = PickTarget::all()[self.state.selected_pick_target];
+            self.egui.input(|i| i.pointer.hover_pos()).map(|position| {
+                let position = position * pixels_per_point;
+                (target, [position.x as u32, position.y as u32])
+            })
+        } else {
+            None
+        };
+
+        self.environment_changed =
+            self.state.selected_environment != previous_state.selected_environment;
+
         // self.state.check_renderer_settings_changed(&previous_state);
 
         // self.state.hovered = self.egui.is_pointer_over_area();
@@ -89,10 +263,200 @@ impl Gui {
         }
     }
 
+    /// Set the camera shown in the camera panel. `None` hides the panel.
     pub fn set_camera(&mut self, camera: Option<Camera>) {
         self.camera = camera;
     }
 
+    /// Return the edited camera if it was changed during the last render.
+    pub fn get_new_camera(&self) -> Option<Camera> {
+        self.camera.filter(|_| self.camera_changed)
+    }
+
+    /// True if the ui uses the pointer, whose inputs should not move the camera.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.egui.wants_pointer_input()
+    }
+
+    /// Set the lights shown in the lights panel. `None` hides the panel.
+    pub fn set_lights(&mut self, lights: Option<&[Light]>) {
+        self.lights = lights.map(<[Light]>::to_vec);
+    }
+
+    /// Return the edited lights if they were changed during the last render.
+    pub fn get_new_lights(&self) -> Option<&[Light]> {
+        self.lights.as_deref().filter(|_| self.lights_changed)
+    }
+
+    /// Set the environments listed in the environment dropdown. Empty hides it.
+    pub fn set_environments(&mut self, environments: Vec<String>) {
+        if self.state.selected_environment >= environments.len() {
+            self.state.selected_environment = 0;
+        }
+        self.environments = environments;
+    }
+
+    /// Show that the selected environment is still loading.
+    pub fn set_environment_loading(&mut self, loading: bool) {
+        self.environment_loading = loading;
+    }
+
+    /// Return the index of the environment selected during the last render.
+    pub fn get_selected_environment(&self) -> Option<usize> {
+        self.environment_changed.then_some(self.state.selected_environment)
+    }
+
+    /// Set the frame timings shown in the frame pacing panel. `None` hides the panel.
+    pub fn set_frame_telemetry(&mut self, telemetry: Option<&FrameTelemetry>) {
+        self.frame_timings = telemetry.map(|t| t.frames().copied().collect());
+    }
+
+    /// Show the capabilities of the device of `context` in the gpu panel. `None` hides the panel.
+    /// Set the profiler whose timings are plotted in the gpu timings panel.
+    /// `None` hides the panel.
+    pub fn set_gpu_profiler(&mut self, profiler: Option<&GpuProfiler>) {
+        self.gpu_timings = profiler.map(|p| p.frames().cloned().collect());
+    }
+
+    pub fn set_gpu_info(&mut self, context: Option<&crate::Context>) {
+        self.gpu_info = context.map(GpuInfo::new);
+    }
+
+    /// Set the counters of the shadow caster culling. `None` hides them.
+    pub fn set_shadow_caster_stats(&mut self, stats: Option<ShadowCasterStats>) {
+        self.shadow_caster_stats = stats;
+    }
+
+    /// Set the usage of the device memory. `None` hides it.
+    pub fn set_memory_stats(&mut self, stats: Option<MemoryStats>) {
+        self.memory_stats = stats;
+    }
+
+    /// Return true if the export of the frame timings was requested during the last render.
+    pub fn should_export_telemetry(&self) -> bool {
+        self.export_telemetry
+    }
+
+    pub fn test_pattern(&self) -> Option<TestPattern> {
+        self.state
+            .test_pattern_enabled
+            .then(|| TestPattern::all()[self.state.selected_test_pattern])
+    }
+
+    pub fn should_encode_test_pattern(&self) -> bool {
+        self.state.test_pattern_encode_srgb
+    }
+
+    pub fn latency_mode(&self) -> LatencyMode {
+        LatencyMode::all()[self.state.selected_latency_mode]
+    }
+
+    /// Target and position in pixels of the pixel to show in the picker tooltip.
+    ///
+    /// Some while the picker is enabled, the modifier key is held and the cursor
+    /// is not over the ui. Read it with [`crate::pick_pixel`] then pass the result
+    /// to [`Gui::set_picked_pixel`].
+    pub fn pixel_pick_request(&self) -> Option<(PickTarget, [u32; 2])> {
+        self.pixel_pick_request
+    }
+
+    /// Set the pixel shown in the picker tooltip.
+    pub fn set_picked_pixel(&mut self, picked_pixel: Option<PickedPixel>) {
+        self.picked_pixel = picked_pixel;
+    }
+
+    /// Set the measurements and annotations drawn over the scene, in pixels.
+    pub fn set_measurement_overlay(&mut self, overlay: Option<MeasurementOverlay>) {
+        self.measurement_overlay = overlay;
+    }
+
+    /// Sharpness of the upscale pass, see [`crate::CasUpscale::set_sharpness`].
+    pub fn sharpness(&self) -> f32 {
+        self.state.sharpness
+    }
+
+    /// Scale of the emissive colors of the materials.
+    pub fn emissive_intensity(&self) -> f32 {
+        self.state.emissive_intensity
+    }
+
+    /// How the vertex colors modulate the base color of the materials.
+    pub fn vertex_color_mode(&self) -> VertexColorMode {
+        VertexColorMode::all()[self.state.selected_vertex_color_mode]
+    }
+
+    /// How the edges of alpha masked materials are drawn, to compare the modes.
+    pub fn mask_edges(&self) -> MaskEdges {
+        MaskEdges::all()[self.state.selected_mask_edges]
+    }
+
+    /// Select `mask_edges` in the dropdown, the mode in use when the renderer starts.
+    pub fn set_mask_edges(&mut self, mask_edges: MaskEdges) {
+        self.state.selected_mask_edges = mask_edges.index() as _;
+    }
+
+    /// Select the environment at `index` in the dropdown without reporting it
+    /// with [`Gui::get_selected_environment`].
+    pub fn set_selected_environment(&mut self, index: usize) {
+        if index < self.environments.len() {
+            self.state.selected_environment = index;
+        }
+    }
+
+    /// Layout of the panels, to save in a [`crate::Session`].
+    pub fn layout(&self) -> GuiLayout {
+        let menu_id = egui::Id::new(MENU_ID);
+        let menu_open =
+            egui::collapsing_header::CollapsingState::load(&self.egui, menu_id.with("collapsing"))
+                .map_or(self.menu_open, |state| state.is_open());
+        let menu_position = self
+            .egui
+            .memory(|memory| memory.area_rect(menu_id))
+            .map(|rect| rect.min)
+            .or(self.menu_position);
+
+        GuiLayout {
+            menu_open,
+            menu_position: menu_position.map(|position| [position.x, position.y]),
+            latency_mode: self.state.selected_latency_mode,
+            sharpness: self.state.sharpness,
+            emissive_intensity: self.state.emissive_intensity,
+            vertex_color_mode: self.state.selected_vertex_color_mode,
+            pixel_picker_enabled: self.state.pixel_picker_enabled,
+            pick_target: self.state.selected_pick_target,
+        }
+    }
+
+    /// Restore a layout saved with [`Gui::layout`].
+    ///
+    /// The placement of the menu only applies before its first render.
+    pub fn set_layout(&mut self, layout: GuiLayout) {
+        self.menu_open = layout.menu_open;
+        self.menu_position = layout
+            .menu_position
+            .map(|[x, y]| egui::Pos2::new(x, y));
+        self.state.selected_latency_mode = if layout.latency_mode < LatencyMode::all().len() {
+            layout.latency_mode
+        } else {
+            0
+        };
+        self.state.sharpness = layout.sharpness;
+        self.state.emissive_intensity =
+            layout.emissive_intensity.clamp(0.0, MAX_EMISSIVE_INTENSITY);
+        self.state.selected_vertex_color_mode =
+            if layout.vertex_color_mode < VertexColorMode::all().len() {
+                layout.vertex_color_mode
+            } else {
+                VertexColorMode::default().index() as _
+            };
+        self.state.pixel_picker_enabled = layout.pixel_picker_enabled;
+        self.state.selected_pick_target = if layout.pick_target < PickTarget::all().len() {
+            layout.pick_target
+        } else {
+            0
+        };
+    }
+
     // pub fn get_selected_animation(&self) -> usize {
     //     self.state.selected_animation
     // }
@@ -117,30 +481,6 @@ impl Gui {
     //     self.state.animation_speed
     // }
 
-    // pub fn camera_mode(&self) -> CameraMode {
-    //     self.state.camera_mode
-    // }
-
-    // pub fn camera_fov(&self) -> Deg<f32> {
-    //     Deg(self.state.camera_fov)
-    // }
-
-    // pub fn camera_z_near(&self) -> f32 {
-    //     self.state.camera_z_near
-    // }
-
-    // pub fn camera_z_far(&self) -> f32 {
-    //     self.state.camera_z_far
-    // }
-
-    // pub fn camera_move_speed(&self) -> f32 {
-    //     self.state.camera_move_speed
-    // }
-
-    // pub fn should_reset_camera(&self) -> bool {
-    //     self.state.reset_camera
-    // }
-
     // pub fn get_new_renderer_settings(&self) -> Option<RendererSettings> {
     //     if self.state.renderer_settings_changed {
     //         Some(RendererSettings {
@@ -175,50 +515,490 @@ fn build_animation_player_window(ui: &mut Ui, state: &mut State) {
         .show(ui, |ui| {});
 }
 
-fn build_camera_details_window(ui: &mut Ui, state: &mut State, camera: Option<Camera>) {
-    // egui::CollapsingHeader::new("Camera")
-    //     .default_open(false)
-    //     .show(ui, |ui| {
-    //         if let Some(camera) = camera {
-    //             ui.horizontal(|ui| {
-    //                 ui.radio_value(&mut state.camera_mode, CameraMode::Orbital, "Orbital");
-    //                 ui.radio_value(&mut state.camera_mode, CameraMode::Fps, "Fps");
-    //             });
-
-    //             if let CameraMode::Fps = state.camera_mode {
-    //                 ui.add(
-    //                     egui::Slider::new(&mut state.camera_move_speed, 1.0..=10.0)
-    //                         .text("Move speed"),
-    //                 );
-    //             }
-
-    //             ui.add(egui::Slider::new(&mut state.camera_fov, 30.0..=90.0).text("FOV"));
-    //             ui.add(
-    //                 egui::Slider::new(&mut state.camera_z_near, 0.01..=10.0)
-    //                     .text("Near plane")
-    //                     .logarithmic(true)
-    //                     .max_decimals(2),
-    //             );
-    //             ui.add(
-    //                 egui::Slider::new(&mut state.camera_z_far, 10.0..=1000.0)
-    //                     .text("Far plane")
-    //                     .logarithmic(true),
-    //             );
-
-    //             let p = camera.position();
-    //             let t = camera.target();
-    //             ui.label(format!("Position: {:.3}, {:.3}, {:.3}", p.x, p.y, p.z));
-    //             ui.label(format!("Target: {:.3}, {:.3}, {:.3}", t.x, t.y, t.z));
-
-    //             state.reset_camera = ui.button("Reset").clicked();
-    //             if state.reset_camera {
-    //                 state.camera_fov = DEFAULT_FOV;
-    //                 state.camera_z_near = DEFAULT_Z_NEAR;
-    //                 state.camera_z_far = DEFAULT_Z_FAR;
-    //                 state.camera_move_speed = DEFAULT_FPS_MOVE_SPEED;
-    //             }
-    //         }
-    //     });
+fn build_environment_window(
+    ui: &mut Ui,
+    state: &mut State,
+    environments: &[String],
+    loading: bool,
+) {
+    egui::CollapsingHeader::new("Environment")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Environment").show_index(
+                    ui,
+                    &mut state.selected_environment,
+                    environments.len(),
+                    |i| environments[i].clone(),
+                );
+                if loading {
+                    ui.spinner();
+                }
+            });
+        });
+}
+
+fn build_lights_window(ui: &mut Ui, lights: &mut Vec<Light>) -> bool {
+    let mut changed = false;
+    egui::CollapsingHeader::new("Lights")
+        .default_open(false)
+        .show(ui, |ui| {
+            let mut removed = None;
+            for (index, light) in lights.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    let kind = match light.kind {
+                        LightKind::Directional => "Directional",
+                        LightKind::Point => "Point",
+                        LightKind::Spot { .. } => "Spot",
+                    };
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .checkbox(&mut light.enabled, format!("{} {}", kind, index))
+                            .changed();
+                        changed |= ui.color_edit_button_rgb(&mut light.color).changed();
+                        if ui.button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut light.intensity, 0.0..=100.0)
+                                .text("Intensity")
+                                .logarithmic(true),
+                        )
+                        .changed();
+                });
+            }
+
+            if let Some(index) = removed {
+                lights.remove(index);
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                let new_light = if ui.button("Add directional").clicked() {
+                    Some(Light::directional(-Vector3::unit_y(), [1.0; 3], 1.0))
+                } else if ui.button("Add point").clicked() {
+                    Some(Light::point(Point3::new(0.0, 2.0, 0.0), [1.0; 3], 10.0, None))
+                } else if ui.button("Add spot").clicked() {
+                    Some(Light::spot(
+                        Point3::new(0.0, 2.0, 0.0),
+                        -Vector3::unit_y(),
+                        [1.0; 3],
+                        10.0,
+                        0.0,
+                        std::f32::consts::FRAC_PI_4,
+                    ))
+                } else {
+                    None
+                };
+                if let Some(light) = new_light {
+                    lights.push(light);
+                    changed = true;
+                }
+            });
+        });
+    changed
+}
+
+fn build_shadow_casters_window(ui: &mut Ui, stats: ShadowCasterStats) {
+    egui::CollapsingHeader::new("Shadow casters")
+        .default_open(false)
+        .show(ui, |ui| {
+            egui::Grid::new("shadow_casters").show(ui, |ui| {
+                for (name, value) in [
+                    ("Lights", stats.lights),
+                    ("Shadow maps", stats.maps),
+                    ("Casters drawn", stats.casters),
+                    ("Casters culled", stats.culled),
+                ] {
+                    ui.label(name);
+                    ui.label(value.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn build_memory_window(ui: &mut Ui, stats: MemoryStats) {
+    const MIB: u64 = 1024 * 1024;
+    egui::CollapsingHeader::new("Memory")
+        .default_open(false)
+        .show(ui, |ui| {
+            egui::Grid::new("memory").show(ui, |ui| {
+                for (name, value) in [
+                    ("Device allocations", stats.device_allocations().to_string()),
+                    (
+                        "Blocks",
+                        format!("{} ({} MiB)", stats.blocks, stats.block_bytes / MIB),
+                    ),
+                    (
+                        "Suballocations",
+                        format!(
+                            "{} ({} MiB)",
+                            stats.suballocations,
+                            stats.suballocated_bytes / MIB
+                        ),
+                    ),
+                    (
+                        "Dedicated",
+                        format!(
+                            "{} ({} MiB)",
+                            stats.dedicated_allocations,
+                            stats.dedicated_bytes / MIB
+                        ),
+                    ),
+                ] {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn build_gpu_info_window(ui: &mut Ui, gpu_info: &GpuInfo) {
+    egui::CollapsingHeader::new("GPU")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.label(format!("Device: {}", gpu_info.device));
+            ui.label(format!("Driver: {}", gpu_info.driver_version));
+            ui.label(format!("Vulkan: {}", gpu_info.api_version));
+
+            egui::CollapsingHeader::new(format!("Extensions ({})", gpu_info.extensions.len()))
+                .default_open(false)
+                .show(ui, |ui| {
+                    for extension in gpu_info.extensions.iter() {
+                        ui.label(extension);
+                    }
+                });
+
+            egui::CollapsingHeader::new("Memory heaps")
+                .default_open(false)
+                .show(ui, |ui| {
+                    for (index, heap) in gpu_info.memory_heaps.iter().enumerate() {
+                        ui.label(format!("#{} {}", index, heap));
+                    }
+                });
+
+            egui::CollapsingHeader::new("Limits")
+                .default_open(false)
+                .show(ui, |ui| {
+                    egui::Grid::new("gpu_limits").show(ui, |ui| {
+                        for (name, value) in gpu_info.limits.iter() {
+                            ui.label(*name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            if ui.button("Copy report").clicked() {
+                ui.output_mut(|o| o.copied_text = gpu_info.report.clone());
+            }
+        });
+}
+
+const FRAME_PACING_PLOT_HEIGHT: f32 = 80.0;
+const FRAME_PACING_PLOT_MAX_MS: f64 = 50.0;
+const FRAME_PACING_TARGET_MS: f64 = 1000.0 / 60.0;
+const BOTTLENECK_STRIP_HEIGHT: f32 = 6.0;
+
+fn bottleneck_color(bottleneck: Option<FrameBottleneck>) -> egui::Color32 {
+    match bottleneck {
+        Some(FrameBottleneck::Cpu) => egui::Color32::LIGHT_BLUE,
+        Some(FrameBottleneck::Gpu) => egui::Color32::from_rgb(255, 165, 0),
+        Some(FrameBottleneck::Present) => egui::Color32::from_rgb(200, 120, 255),
+        None => egui::Color32::GRAY,
+    }
+}
+
+fn build_frame_pacing_window(
+    ui: &mut Ui,
+    state: &mut State,
+    frame_timings: &[FrameTimings],
+) -> bool {
+    let mut export = false;
+    egui::CollapsingHeader::new("Frame pacing")
+        .default_open(false)
+        .show(ui, |ui| {
+            let latency_modes = LatencyMode::all();
+            egui::ComboBox::from_label("Latency mode").show_index(
+                ui,
+                &mut state.selected_latency_mode,
+                latency_modes.len(),
+                |i| latency_modes[i].to_string(),
+            );
+
+            let intervals = frame_timings
+                .windows(2)
+                .map(|w| w[1].begin - w[0].begin)
+                .collect::<Vec<_>>();
+            if intervals.is_empty() {
+                ui.label("No frame recorded yet");
+                return;
+            }
+
+            let average = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let worst = intervals.iter().copied().fold(0.0, f64::max);
+            let mut sorted = intervals.clone();
+            sorted.sort_by(f64::total_cmp);
+            let p99 = sorted[(sorted.len() - 1) * 99 / 100];
+            ui.label(format!(
+                "Average {:.2} ms, 99th percentile {:.2} ms, worst {:.2} ms",
+                average, p99, worst
+            ));
+            if let Some(latency) = frame_timings.iter().rev().find_map(FrameTimings::latency) {
+                ui.label(format!(
+                    "Last cpu to gpu completion latency {:.2} ms",
+                    latency
+                ));
+            }
+            if let Some(latency) = frame_timings
+                .iter()
+                .rev()
+                .find_map(FrameTimings::input_to_photon)
+            {
+                ui.label(format!("Last input to photon latency {:.2} ms", latency));
+            }
+
+            // One bar per frame, hitches in red, target frame time as a line.
+            let (response, painter) = ui.allocate_painter(
+                egui::vec2(ui.available_width(), FRAME_PACING_PLOT_HEIGHT),
+                egui::Sense::hover(),
+            );
+            let rect = response.rect;
+            let bar_width = rect.width() / intervals.len() as f32;
+            let to_y = |ms: f64| {
+                let t = (ms / FRAME_PACING_PLOT_MAX_MS).min(1.0) as f32;
+                rect.bottom() - t * rect.height()
+            };
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(128));
+            for (index, interval) in intervals.iter().enumerate() {
+                let x = rect.left() + index as f32 * bar_width;
+                let color = if *interval > average * 1.5 {
+                    egui::Color32::RED
+                } else {
+                    egui::Color32::LIGHT_GREEN
+                };
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(x, to_y(*interval)),
+                        egui::pos2(x + bar_width.max(1.0), rect.bottom()),
+                    ),
+                    0.0,
+                    color,
+                );
+            }
+            let target_y = to_y(FRAME_PACING_TARGET_MS);
+            painter.hline(
+                rect.x_range(),
+                target_y,
+                egui::Stroke::new(1.0, egui::Color32::YELLOW),
+            );
+
+            // Bottleneck of each frame, the most frequent one of the last frames as text.
+            let window = frame_timings.len().saturating_sub(BOTTLENECK_WINDOW);
+            let bottleneck = dominant_bottleneck(&frame_timings[window..]);
+            ui.colored_label(
+                bottleneck_color(bottleneck),
+                match bottleneck {
+                    Some(bottleneck) => {
+                        format!("{} over the last {} frames", bottleneck, BOTTLENECK_WINDOW)
+                    }
+                    None => "Bottleneck unknown".to_owned(),
+                },
+            );
+            let (response, painter) = ui.allocate_painter(
+                egui::vec2(ui.available_width(), BOTTLENECK_STRIP_HEIGHT),
+                egui::Sense::hover(),
+            );
+            let rect = response.rect;
+            let frame_width = rect.width() / frame_timings.len() as f32;
+            for (index, frame) in frame_timings.iter().enumerate() {
+                let x = rect.left() + index as f32 * frame_width;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(x, rect.top()),
+                        egui::pos2(x + frame_width.max(1.0), rect.bottom()),
+                    ),
+                    0.0,
+                    bottleneck_color(frame.bottleneck()),
+                );
+            }
+
+            export = ui.button("Export json timeline").clicked();
+        });
+    export
+}
+
+fn scope_color(index: usize) -> egui::Color32 {
+    const COLORS: [egui::Color32; 6] = [
+        egui::Color32::LIGHT_BLUE,
+        egui::Color32::LIGHT_GREEN,
+        egui::Color32::from_rgb(255, 165, 0),
+        egui::Color32::from_rgb(200, 120, 255),
+        egui::Color32::LIGHT_RED,
+        egui::Color32::LIGHT_YELLOW,
+    ];
+    COLORS[index % COLORS.len()]
+}
+
+fn build_gpu_timings_window(ui: &mut Ui, gpu_timings: &[GpuFrameTimings]) {
+    egui::CollapsingHeader::new("Gpu timings")
+        .default_open(false)
+        .show(ui, |ui| {
+            let Some(last) = gpu_timings.last() else {
+                ui.label("No frame resolved yet");
+                return;
+            };
+
+            // Average of each scope of the last frame over the frames that have it,
+            // the top level scopes in the colors of the plot.
+            let mut top_level = 0;
+            for scope in &last.scopes {
+                let durations = gpu_timings
+                    .iter()
+                    .filter_map(|frame| frame.scopes.iter().find(|s| s.name == scope.name))
+                    .map(|s| s.duration)
+                    .collect::<Vec<_>>();
+                let average = durations.iter().sum::<f64>() / durations.len() as f64;
+                let color = if scope.depth == 0 {
+                    top_level += 1;
+                    scope_color(top_level - 1)
+                } else {
+                    ui.visuals().text_color()
+                };
+                ui.colored_label(
+                    color,
+                    format!(
+                        "{}{}: {:.3} ms, average {:.3} ms",
+                        "  ".repeat(scope.depth as usize),
+                        scope.name,
+                        scope.duration,
+                        average
+                    ),
+                );
+            }
+            ui.label(format!("Total {:.3} ms", last.total()));
+
+            // One bar per frame stacking its top level scopes, target frame time as a line.
+            let (response, painter) = ui.allocate_painter(
+                egui::vec2(ui.available_width(), FRAME_PACING_PLOT_HEIGHT),
+                egui::Sense::hover(),
+            );
+            let rect = response.rect;
+            let bar_width = rect.width() / gpu_timings.len() as f32;
+            let to_height = |ms: f64| (ms / FRAME_PACING_PLOT_MAX_MS) as f32 * rect.height();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(128));
+            for (index, frame) in gpu_timings.iter().enumerate() {
+                let x = rect.left() + index as f32 * bar_width;
+                let mut bottom = rect.bottom();
+                let top_level = frame.scopes.iter().filter(|scope| scope.depth == 0);
+                for (scope_index, scope) in top_level.enumerate() {
+                    let top = (bottom - to_height(scope.duration)).max(rect.top());
+                    painter.rect_filled(
+                        egui::Rect::from_min_max(
+                            egui::pos2(x, top),
+                            egui::pos2(x + bar_width.max(1.0), bottom),
+                        ),
+                        0.0,
+                        scope_color(scope_index),
+                    );
+                    bottom = top;
+                }
+            }
+            painter.hline(
+                rect.x_range(),
+                rect.bottom() - to_height(FRAME_PACING_TARGET_MS),
+                egui::Stroke::new(1.0, egui::Color32::YELLOW),
+            );
+        });
+}
+
+/// Return true if `camera` was changed.
+fn build_camera_details_window(ui: &mut Ui, camera: &mut Camera) -> bool {
+    let mut changed = false;
+    egui::CollapsingHeader::new("Camera")
+        .default_open(false)
+        .show(ui, |ui| {
+            let mut mode = camera.mode();
+            ui.horizontal(|ui| {
+                for value in CameraMode::all() {
+                    ui.radio_value(&mut mode, value, value.to_string());
+                }
+            });
+            if mode != camera.mode() {
+                *camera = camera.with_mode(mode);
+                changed = true;
+            }
+
+            if let Some(mut move_speed) = camera.move_speed() {
+                if ui
+                    .add(egui::Slider::new(&mut move_speed, 1.0..=10.0).text("Move speed"))
+                    .changed()
+                {
+                    camera.set_move_speed(move_speed);
+                    changed = true;
+                }
+            }
+
+            changed |= ui
+                .add(egui::Slider::new(&mut camera.fov.0, 30.0..=90.0).text("FOV"))
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut camera.z_near, 0.01..=10.0)
+                        .text("Near plane")
+                        .logarithmic(true)
+                        .max_decimals(2),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut camera.z_far, 10.0..=1000.0)
+                        .text("Far plane")
+                        .logarithmic(true),
+                )
+                .changed();
+
+            let p = camera.position();
+            let t = camera.target();
+            ui.label(format!("Position: {:.3}, {:.3}, {:.3}", p.x, p.y, p.z));
+            ui.label(format!("Target: {:.3}, {:.3}, {:.3}", t.x, t.y, t.z));
+
+            if ui.button("Reset").clicked() {
+                *camera = Camera::default().with_mode(mode);
+                camera.set_move_speed(DEFAULT_FPS_MOVE_SPEED);
+                changed = true;
+            }
+        });
+    changed
+}
+
+/// True if the pixel under the cursor should be picked.
+fn draw_measurement_overlay(ctx: &Context, overlay: &MeasurementOverlay) {
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let to_pos = |[x, y]: [f32; 2]| egui::pos2(x, y) / ctx.pixels_per_point();
+    let stroke = egui::Stroke::new(2.0, egui::Color32::YELLOW);
+    for [start, end] in &overlay.lines {
+        painter.line_segment([to_pos(*start), to_pos(*end)], stroke);
+    }
+    for (position, text) in &overlay.labels {
+        let position = to_pos(*position);
+        let galley = painter.layout_no_wrap(
+            text.clone(),
+            egui::FontId::proportional(14.0),
+            egui::Color32::WHITE,
+        );
+        let rect = egui::Align2::CENTER_BOTTOM.anchor_size(position, galley.size());
+        painter.rect_filled(rect.expand(2.0), 2.0, egui::Color32::from_black_alpha(160));
+        painter.galley(rect.min, galley, egui::Color32::WHITE);
+    }
+}
+
+fn is_picking(ctx: &Context, state: &State) -> bool {
+    state.pixel_picker_enabled && ctx.input(|i| i.modifiers.ctrl) && !ctx.is_pointer_over_area()
 }
 
 fn build_renderer_settings_window(ui: &mut Ui, state: &mut State) {
@@ -268,6 +1048,21 @@ fn build_renderer_settings_window(ui: &mut Ui, state: &mut State) {
                 ui.heading("Post Processing");
                 ui.separator();
 
+                ui.add(egui::Slider::new(&mut state.sharpness, 0.0..=1.0).text("Sharpness"));
+                ui.add(
+                    egui::Slider::new(&mut state.emissive_intensity, 0.0..=MAX_EMISSIVE_INTENSITY)
+                        .logarithmic(true)
+                        .text("Emissive intensity"),
+                );
+
+                let vertex_color_modes = VertexColorMode::all();
+                egui::ComboBox::from_label("Vertex colors").show_index(
+                    ui,
+                    &mut state.selected_vertex_color_mode,
+                    vertex_color_modes.len(),
+                    |i| vertex_color_modes[i].to_string(),
+                );
+
                 // let tone_map_modes = ToneMapMode::all();
                 // egui::ComboBox::from_label("Tone map mode").show_index(
                 //     ui,
@@ -281,6 +1076,37 @@ fn build_renderer_settings_window(ui: &mut Ui, state: &mut State) {
                 ui.heading("Debug");
                 ui.separator();
 
+                ui.checkbox(&mut state.test_pattern_enabled, "Show test pattern");
+                ui.add_enabled_ui(state.test_pattern_enabled, |ui| {
+                    let test_patterns = TestPattern::all();
+                    egui::ComboBox::from_label("Test pattern").show_index(
+                        ui,
+                        &mut state.selected_test_pattern,
+                        test_patterns.len(),
+                        |i| test_patterns[i].to_string(),
+                    );
+                    ui.checkbox(&mut state.test_pattern_encode_srgb, "Encode sRGB in shader");
+                });
+
+                let mask_edges = MaskEdges::all();
+                egui::ComboBox::from_label("Mask edges").show_index(
+                    ui,
+                    &mut state.selected_mask_edges,
+                    mask_edges.len(),
+                    |i| mask_edges[i].to_string(),
+                );
+
+                ui.checkbox(&mut state.pixel_picker_enabled, "Pixel picker (hold Ctrl)");
+                ui.add_enabled_ui(state.pixel_picker_enabled, |ui| {
+                    let targets = PickTarget::all();
+                    egui::ComboBox::from_label("Picked target").show_index(
+                        ui,
+                        &mut state.selected_pick_target,
+                        targets.len(),
+                        |i| targets[i].to_string(),
+                    );
+                });
+
                 // let output_modes = OutputMode::all();
                 // egui::ComboBox::from_label("Output mode").show_index(
                 //     ui,
@@ -294,7 +1120,37 @@ fn build_renderer_settings_window(ui: &mut Ui, state: &mut State) {
 
 
 #[derive(Clone, Copy)]
-struct State;
+struct State {
+    test_pattern_enabled: bool,
+    selected_test_pattern: usize,
+    test_pattern_encode_srgb: bool,
+    selected_environment: usize,
+    selected_latency_mode: usize,
+    sharpness: f32,
+    emissive_intensity: f32,
+    selected_vertex_color_mode: usize,
+    selected_mask_edges: usize,
+    pixel_picker_enabled: bool,
+    selected_pick_target: usize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            test_pattern_enabled: false,
+            selected_test_pattern: 0,
+            test_pattern_encode_srgb: false,
+            selected_environment: 0,
+            selected_latency_mode: 0,
+            sharpness: DEFAULT_SHARPNESS,
+            emissive_intensity: DEFAULT_EMISSIVE_INTENSITY,
+            selected_vertex_color_mode: VertexColorMode::default().index() as _,
+            selected_mask_edges: MaskEdges::default().index() as _,
+            pixel_picker_enabled: false,
+            selected_pick_target: 0,
+        }
+    }
+}
 
 // #[derive(Clone, Copy)]
 // struct State {
@@ -401,9 +1257,3 @@ struct State;
 //         }
 //     }
 // }
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum CameraMode {
-    Orbital,
-    Fps,
-}
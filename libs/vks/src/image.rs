@@ -1,4 +1,7 @@
-use super::{buffer::*, context::*, swapchain::SwapchainProperties};
+use super::{
+    allocator::*, buffer::*, context::*, leak_tracker::*, mipmaps::*,
+    swapchain::SwapchainProperties,
+};
 use ash::{vk, Device};
 use std::sync::Arc;
 
@@ -37,7 +40,7 @@ impl Default for ImageParameters {
 pub struct Image {
     context: Arc<Context>,
     pub image: vk::Image,
-    memory: Option<vk::DeviceMemory>,
+    allocation: Option<Allocation>,
     pub extent: vk::Extent3D,
     pub format: vk::Format,
     pub mip_levels: u32,
@@ -49,17 +52,21 @@ impl Image {
     fn new(
         context: Arc<Context>,
         image: vk::Image,
-        memory: Option<vk::DeviceMemory>,
+        allocation: Option<Allocation>,
         extent: vk::Extent3D,
         format: vk::Format,
         mip_levels: u32,
         layers: u32,
         managed: bool,
     ) -> Self {
+        if !managed {
+            track_create(TrackedResource::Image);
+        }
+
         Self {
             context,
             image,
-            memory,
+            allocation,
             extent,
             format,
             mip_levels,
@@ -95,29 +102,32 @@ impl Image {
                 .expect("Failed to create image")
         };
         let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
-        let mem_type_index = find_memory_type(
-            mem_requirements,
-            context.get_mem_properties(),
-            parameters.mem_properties,
-        );
-
-        let alloc_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(mem_requirements.size)
-            .memory_type_index(mem_type_index);
-        let memory = unsafe {
-            let mem = device
-                .allocate_memory(&alloc_info, None)
-                .expect("Failed to allocate image memory");
+        let allocation = context.allocate_memory(mem_requirements, parameters.mem_properties);
+        unsafe {
             device
-                .bind_image_memory(image, mem, 0)
-                .expect("Failed to bind image memory");
-            mem
+                .bind_image_memory(image, allocation.memory(), allocation.offset())
+                .expect("Failed to bind image memory")
         };
+        track_allocation(mem_requirements.size);
+        tracing::debug!(
+            target: RESOURCES_TRACING_TARGET,
+            handle = ?image,
+            width = extent.width,
+            height = extent.height,
+            format = ?parameters.format,
+            mip_levels = parameters.mip_levels,
+            layers = parameters.layers,
+            samples = ?parameters.sample_count,
+            usage = ?parameters.usage,
+            size = mem_requirements.size,
+            dedicated = allocation.is_dedicated(),
+            "Image created"
+        );
 
         Image::new(
             context,
             image,
-            Some(memory),
+            Some(allocation),
             extent,
             parameters.format,
             parameters.mip_levels,
@@ -290,6 +300,39 @@ impl Image {
                     vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
                     vk::PipelineStageFlags2::TRANSFER,
                 ),
+                (
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ) => (
+                    vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags2::TRANSFER,
+                ),
+                (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+                    vk::AccessFlags2::NONE,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::PipelineStageFlags2::TRANSFER,
+                    vk::PipelineStageFlags2::TRANSFER,
+                ),
+                (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => (
+                    vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags2::TRANSFER,
+                ),
+                (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
+                    vk::AccessFlags2::NONE,
+                    vk::AccessFlags2::NONE,
+                    vk::PipelineStageFlags2::TRANSFER,
+                    vk::PipelineStageFlags2::NONE,
+                ),
+                (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::AccessFlags2::NONE,
+                    vk::PipelineStageFlags2::TRANSFER,
+                    vk::PipelineStageFlags2::NONE,
+                ),
                 (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
                     vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
                     vk::AccessFlags2::COLOR_ATTACHMENT_READ,
@@ -306,12 +349,54 @@ impl Image {
                         | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
                     vk::PipelineStageFlags2::FRAGMENT_SHADER,
                 ),
+                // Depth sampled by post passes and still tested by the forward passes.
+                (
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                ) => (
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags2::SHADER_SAMPLED_READ,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                ),
+                (
+                    vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ) => (
+                    vk::AccessFlags2::NONE,
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                ),
                 (vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
                     vk::AccessFlags2::NONE,
                     vk::AccessFlags2::SHADER_READ,
                     vk::PipelineStageFlags2::NONE,
                     vk::PipelineStageFlags2::FRAGMENT_SHADER,
                 ),
+                // Compute shaders writing an image sampled by the previous frame.
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL) => (
+                    vk::AccessFlags2::NONE,
+                    vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                ),
+                (vk::ImageLayout::GENERAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+                    vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    vk::AccessFlags2::SHADER_SAMPLED_READ,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ),
                 (
                     vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
                     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
@@ -327,6 +412,15 @@ impl Image {
                     vk::PipelineStageFlags2::TRANSFER,
                     vk::PipelineStageFlags2::TRANSFER,
                 ),
+                (
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ) => (
+                    vk::AccessFlags2::SHADER_READ,
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                    vk::PipelineStageFlags2::TRANSFER,
+                ),
                 (
                     vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
                     vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
@@ -348,17 +442,7 @@ impl Image {
                 }
             };
 
-        let aspect_mask = if new_layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-            || old_layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-        {
-            let mut mask = vk::ImageAspectFlags::DEPTH;
-            if has_stencil_component(self.format) {
-                mask |= vk::ImageAspectFlags::STENCIL;
-            }
-            mask
-        } else {
-            vk::ImageAspectFlags::COLOR
-        };
+        let aspect_mask = format_aspect_flags(self.format);
 
         vk::ImageMemoryBarrier2::default()
             .src_stage_mask(src_stage)
@@ -417,6 +501,134 @@ impl Image {
         }
     }
 
+    /// Copy all the layers of `mip_level` to host memory and wait for it.
+    ///
+    /// The image must be a color image in `TRANSFER_SRC_OPTIMAL` layout whose
+    /// texels are `texel_size` bytes. Used to bring the results of a headless
+    /// context back to the main one.
+    pub fn read_back(&self, mip_level: u32, texel_size: u32) -> Vec<u8> {
+        let width = (self.extent.width >> mip_level).max(1);
+        let height = (self.extent.height >> mip_level).max(1);
+        let size = (width * height * self.layers * texel_size) as vk::DeviceSize;
+
+        let mut buffer = Buffer::create(
+            Arc::clone(&self.context),
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level,
+                base_array_layer: 0,
+                layer_count: self.layers,
+            })
+            .image_extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            });
+        self.context.execute_one_time_commands(|command_buffer| unsafe {
+            self.context.device().cmd_copy_image_to_buffer(
+                command_buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer.buffer,
+                std::slice::from_ref(&region),
+            )
+        });
+
+        let data_ptr = buffer.map_memory() as *const u8;
+        unsafe { std::slice::from_raw_parts(data_ptr, size as _) }.to_vec()
+    }
+
+    /// Copy the texel at `x`, `y` of the first mip and layer to host memory and wait for it.
+    ///
+    /// The image must have the `TRANSFER_SRC` usage, a single sample and be in
+    /// `layout`, which it is left in. Only the depth of depth stencil images is read.
+    /// Stalls the queue, meant for debug tools.
+    pub fn read_back_texel(
+        &self,
+        layout: vk::ImageLayout,
+        x: u32,
+        y: u32,
+        texel_size: u32,
+    ) -> Vec<u8> {
+        let mut buffer = Buffer::create(
+            Arc::clone(&self.context),
+            texel_size as _,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        let aspect_mask = format_aspect_flags(self.format);
+        let copy_aspect = if aspect_mask.contains(vk::ImageAspectFlags::DEPTH) {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+        let barrier = |old_layout, new_layout| {
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+                .old_layout(old_layout)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .new_layout(new_layout)
+                .image(self.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: self.mip_levels,
+                    base_array_layer: 0,
+                    layer_count: self.layers,
+                })
+        };
+
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: copy_aspect,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D {
+                x: x as _,
+                y: y as _,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            });
+        self.context.execute_one_time_commands(|command_buffer| unsafe {
+            let sync = self.context.synchronization2();
+            let to_transfer = [barrier(layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)];
+            sync.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_transfer),
+            );
+            self.context.device().cmd_copy_image_to_buffer(
+                command_buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer.buffer,
+                std::slice::from_ref(&region),
+            );
+            let to_layout = [barrier(vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout)];
+            sync.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_layout),
+            );
+        });
+
+        let data_ptr = buffer.map_memory() as *const u8;
+        unsafe { std::slice::from_raw_parts(data_ptr, texel_size as _) }.to_vec()
+    }
+
     /// Record command to copy [src_image] into this image.
     ///
     /// The full extent of the passed in layer will be copied, so the target image
@@ -449,42 +661,28 @@ impl Image {
     }
 
     pub fn generate_mipmaps(&self, extent: vk::Extent2D) {
-        let format_properties = unsafe {
-            self.context
-                .instance()
-                .get_physical_device_format_properties(self.context.physical_device(), self.format)
-        };
-        if !format_properties
-            .optimal_tiling_features
-            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
-        {
-            panic!(
-                "Linear blitting is not supported for format {:?}.",
-                self.format
-            )
-        }
-
         self.context.execute_one_time_commands(|buffer| {
             self.cmd_generate_mipmaps(buffer, extent);
         });
     }
 
+    /// Generate the mips from the first one, with linear blits or with the
+    /// compute downsampler if the format lacks them, see [`MipmapMethod`].
+    ///
+    /// All the mips must be in `TRANSFER_DST_OPTIMAL` layout and are left in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout. The image must have been created with
+    /// the [`mipmap_usage`] of its format.
     pub fn cmd_generate_mipmaps(&self, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
-        let format_properties = unsafe {
-            self.context
-                .instance()
-                .get_physical_device_format_properties(self.context.physical_device(), self.format)
-        };
-        if !format_properties
-            .optimal_tiling_features
-            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
-        {
-            panic!(
-                "Linear blitting is not supported for format {:?}.",
-                self.format
-            )
+        match MipmapMethod::for_format(&self.context, self.format) {
+            Some(MipmapMethod::Blit) => self.cmd_blit_mipmaps(command_buffer, extent),
+            Some(MipmapMethod::Compute) => {
+                cmd_downsample_mipmaps(&self.context, command_buffer, self, true)
+            }
+            None => panic!("Mipmaps can't be generated for format {:?}", self.format),
         }
+    }
 
+    fn cmd_blit_mipmaps(&self, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
         let mut mip_width = extent.width as i32;
         let mut mip_height = extent.height as i32;
         for level in 1..self.mip_levels {
@@ -577,6 +775,11 @@ impl Image {
     pub fn get_mip_levels(&self) -> u32 {
         self.mip_levels
     }
+
+    /// Name the image in the debug tools, see [`Context::set_object_name`].
+    pub fn set_name(&self, name: &str) {
+        self.context.set_object_name(self.image, name);
+    }
 }
 
 impl Drop for Image {
@@ -584,16 +787,60 @@ impl Drop for Image {
         unsafe {
             if !self.managed {
                 self.context.device().destroy_image(self.image, None);
-            }
-            if let Some(memory) = self.memory {
-                self.context.device().free_memory(memory, None);
+                track_destroy(TrackedResource::Image);
+                tracing::debug!(
+                    target: RESOURCES_TRACING_TARGET,
+                    handle = ?self.image,
+                    format = ?self.format,
+                    "Image destroyed"
+                );
             }
         }
+        if let Some(allocation) = self.allocation.take() {
+            track_free(allocation.size());
+            self.context.free_memory(allocation);
+        }
     }
 }
 
-fn has_stencil_component(format: vk::Format) -> bool {
-    format == vk::Format::D32_SFLOAT_S8_UINT || format == vk::Format::D24_UNORM_S8_UINT
+pub fn has_stencil_component(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D32_SFLOAT_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::S8_UINT
+    )
+}
+
+pub fn has_depth_component(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D32_SFLOAT
+            | vk::Format::D32_SFLOAT_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D16_UNORM
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::X8_D24_UNORM_PACK32
+    )
+}
+
+/// All the aspects of `format`, depth and stencil for combined formats.
+///
+/// Barriers and attachment views must cover every aspect of a depth stencil image.
+pub fn format_aspect_flags(format: vk::Format) -> vk::ImageAspectFlags {
+    let mut aspect_mask = vk::ImageAspectFlags::empty();
+    if has_depth_component(format) {
+        aspect_mask |= vk::ImageAspectFlags::DEPTH;
+    }
+    if has_stencil_component(format) {
+        aspect_mask |= vk::ImageAspectFlags::STENCIL;
+    }
+    if aspect_mask.is_empty() {
+        vk::ImageAspectFlags::COLOR
+    } else {
+        aspect_mask
+    }
 }
 
 pub fn create_image_view(
@@ -686,3 +933,41 @@ pub fn cmd_transition_images_layouts(
             .cmd_pipeline_barrier2(command_buffer, &dependency_info)
     };
 }
+
+pub(crate) fn color_subresource_range() -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// Record a barrier on the color subresource of `image`, `src` and `dst` being
+/// the stage and access of each side of the dependency.
+pub(crate) fn cmd_image_barrier(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(src.0)
+        .src_access_mask(src.1)
+        .old_layout(layouts.0)
+        .dst_stage_mask(dst.0)
+        .dst_access_mask(dst.1)
+        .new_layout(layouts.1)
+        .image(image)
+        .subresource_range(color_subresource_range());
+    let dependency_info =
+        vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
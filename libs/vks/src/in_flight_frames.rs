@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use ash::{vk, Device};
+#[cfg(feature = "gui")]
 use egui::TextureId;
 
 use crate::Context;
@@ -10,19 +11,76 @@ pub const MAX_FRAMES_IN_FLIGHT: u32 = 2;
 pub struct InFlightFrames {
     context: Arc<Context>,
     sync_objects: Vec<SyncObjects>,
+    #[cfg(feature = "gui")]
     pub gui_textures_to_free: Vec<TextureId>,
     current_frame: usize,
+    /// Value of the frame being recorded, increased by each call to `next`.
+    frame: u64,
+    /// Value of the last frame using each sync objects.
+    last_frames: Vec<u64>,
 }
 
 impl InFlightFrames {
     pub fn new(context: Arc<Context>, sync_objects: Vec<SyncObjects>) -> Self {
+        let last_frames = vec![0; sync_objects.len()];
         Self {
             context,
             sync_objects,
+            #[cfg(feature = "gui")]
             gui_textures_to_free: Vec::new(),
             current_frame: 0,
+            frame: 0,
+            last_frames,
         }
     }
+
+    /// Index of the sync objects returned by the last call to `next`.
+    ///
+    /// Resources updated every frame must have one copy per frame in flight
+    /// and use this index, the fence of these sync objects guards them.
+    pub fn current_frame_index(&self) -> usize {
+        let count = self.sync_objects.len();
+        (self.current_frame + count - 1) % count
+    }
+
+    /// Value of the frame being recorded, the key of the [`crate::DeletionQueue`] entries.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Destroy the resources of the [`crate::DeletionQueue`] of the context
+    /// whose frames are complete.
+    ///
+    /// To call once the fence of the sync objects returned by `next` was waited for.
+    /// A frame returning early without submitting is complete as soon as the
+    /// frames before it are.
+    pub fn collect_deletions(&self) {
+        let device = self.context.device();
+        let count = self.sync_objects.len() as u64;
+        let current = self.current_frame_index();
+        // The last frame of each sync objects is complete when its fence is
+        // signaled, except for the frame being recorded. Otherwise the frame
+        // before it using the same sync objects is, it was waited for.
+        let completed_frame = self
+            .sync_objects
+            .iter()
+            .zip(&self.last_frames)
+            .enumerate()
+            .map(|(index, (sync_objects, last_frame))| {
+                let signaled = index != current
+                    && unsafe { device.get_fence_status(sync_objects.fence) }.unwrap_or(false);
+                if signaled {
+                    *last_frame
+                } else {
+                    last_frame.saturating_sub(count)
+                }
+            })
+            .min()
+            .unwrap_or(0);
+        self.context
+            .deletion_queue()
+            .collect(device, completed_frame);
+    }
 }
 
 impl Drop for InFlightFrames {
@@ -39,6 +97,15 @@ impl Iterator for InFlightFrames {
     fn next(&mut self) -> Option<Self::Item> {
         let next = self.sync_objects[self.current_frame];
 
+        #[cfg(feature = "tracy")]
+        if let Some(client) = tracy_client::Client::running() {
+            client.frame_mark();
+        }
+
+        self.frame += 1;
+        self.last_frames[self.current_frame] = self.frame;
+        self.context.deletion_queue().set_frame(self.frame);
+
         self.current_frame = (self.current_frame + 1) % self.sync_objects.len();
 
         Some(next)
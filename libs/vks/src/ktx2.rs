@@ -0,0 +1,402 @@
+use crate::Context;
+use ash::vk;
+use std::{error::Error, fmt};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// Size of the identifier, the header and the index preceding the levels.
+const KTX2_LEVEL_INDEX_OFFSET: usize = 80;
+const KTX2_LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+/// Color model of the data format descriptor of UASTC payloads.
+const DF_MODEL_UASTC: u8 = 166;
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+    /// Not a KTX2 file or truncated.
+    InvalidData(&'static str),
+    /// Cubemaps, arrays and 3D textures are not supported.
+    UnsupportedLayout,
+    /// Zstandard and zlib supercompressions are not supported.
+    UnsupportedSupercompression(u32),
+    /// The device can't sample the format of the file.
+    UnsupportedFormat(vk::Format),
+    /// Basis Universal payloads, ETC1S or UASTC, need a transcoder which is
+    /// not part of vks. Transcode them offline to a format of the device.
+    BasisUniversal,
+}
+
+impl fmt::Display for Ktx2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidData(cause) => write!(f, "Invalid KTX2 data: {}", cause),
+            Self::UnsupportedLayout => {
+                write!(
+                    f,
+                    "Only 2D KTX2 textures without layers or faces are supported"
+                )
+            }
+            Self::UnsupportedSupercompression(scheme) => {
+                write!(f, "Unsupported KTX2 supercompression scheme {}", scheme)
+            }
+            Self::UnsupportedFormat(format) => {
+                write!(f, "Format {:?} can't be sampled by the device", format)
+            }
+            Self::BasisUniversal => write!(f, "Basis Universal KTX2 payloads are not supported"),
+        }
+    }
+}
+
+impl Error for Ktx2Error {}
+
+/// Texels of each mip level of a KTX2 texture, the first being the full size.
+///
+/// The levels are stored in the file so they are uploaded as is, compressed
+/// ones can't be blitted to generate mips anyway.
+pub struct Ktx2Image {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl Ktx2Image {
+    /// Parse the KTX2 file in `bytes`, checking `context` can sample its format.
+    pub fn parse(context: &Context, bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        let image = Self::parse_file(bytes)?;
+        if context
+            .find_supported_format(
+                &[image.format],
+                vk::ImageTiling::OPTIMAL,
+                sampled_image_features(),
+            )
+            .is_none()
+        {
+            return Err(Ktx2Error::UnsupportedFormat(image.format));
+        }
+        Ok(image)
+    }
+
+    /// Parse the KTX2 file in `bytes` whatever the support of its format.
+    fn parse_file(bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        if bytes.len() < KTX2_LEVEL_INDEX_OFFSET || bytes[..12] != KTX2_IDENTIFIER {
+            return Err(Ktx2Error::InvalidData("missing KTX2 identifier"));
+        }
+
+        let format = vk::Format::from_raw(read_u32(bytes, 12)? as _);
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?.max(1);
+        let depth = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?;
+        let face_count = read_u32(bytes, 36)?;
+        // No levels means the mips should be generated, only the first one is uploaded then.
+        let level_count = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+        let dfd_offset = read_u32(bytes, 48)? as usize;
+
+        if width == 0 {
+            return Err(Ktx2Error::InvalidData("null width"));
+        }
+        if depth > 1 || layer_count > 1 || face_count != 1 {
+            return Err(Ktx2Error::UnsupportedLayout);
+        }
+
+        let color_model = bytes.get(dfd_offset + 12).copied();
+        if format == vk::Format::UNDEFINED
+            && (supercompression == SUPERCOMPRESSION_BASIS_LZ
+                || color_model == Some(DF_MODEL_UASTC))
+        {
+            return Err(Ktx2Error::BasisUniversal);
+        }
+        if supercompression != SUPERCOMPRESSION_NONE {
+            return Err(Ktx2Error::UnsupportedSupercompression(supercompression));
+        }
+
+        let index_end =
+            KTX2_LEVEL_INDEX_OFFSET + level_count as usize * KTX2_LEVEL_INDEX_ENTRY_SIZE;
+        if bytes.len() < index_end {
+            return Err(Ktx2Error::InvalidData("truncated level index"));
+        }
+        let mips = (0..level_count as usize)
+            .map(|level| {
+                let entry = KTX2_LEVEL_INDEX_OFFSET + level * KTX2_LEVEL_INDEX_ENTRY_SIZE;
+                let offset = read_u64(bytes, entry)? as usize;
+                let length = read_u64(bytes, entry + 8)? as usize;
+                offset
+                    .checked_add(length)
+                    .and_then(|end| bytes.get(offset..end))
+                    .map(<[u8]>::to_vec)
+                    .ok_or(Ktx2Error::InvalidData("level out of bounds"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            mips,
+        })
+    }
+
+    /// Extent of `mip_level`.
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.width >> mip_level).max(1),
+            height: (self.height >> mip_level).max(1),
+        }
+    }
+}
+
+fn sampled_image_features() -> vk::FormatFeatureFlags {
+    vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Ktx2Error> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Ktx2Error::InvalidData("truncated header"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Ktx2Error> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Ktx2Error::InvalidData("truncated level index"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGBA8: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    /// Header of a KTX2 file and its levels, stored after the level index.
+    struct Ktx2File {
+        format: vk::Format,
+        extent: (u32, u32),
+        depth: u32,
+        layer_count: u32,
+        face_count: u32,
+        supercompression: u32,
+        levels: Vec<Vec<u8>>,
+    }
+
+    impl Ktx2File {
+        fn rgba8(width: u32, height: u32, level_count: u32) -> Self {
+            let levels = (0..level_count)
+                .map(|level| {
+                    let texels = (width >> level).max(1) * (height >> level).max(1);
+                    vec![level as u8; texels as usize * 4]
+                })
+                .collect();
+            Self {
+                format: RGBA8,
+                extent: (width, height),
+                depth: 0,
+                layer_count: 0,
+                face_count: 1,
+                supercompression: SUPERCOMPRESSION_NONE,
+                levels,
+            }
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            let mut bytes = KTX2_IDENTIFIER.to_vec();
+            let dfd_offset = KTX2_LEVEL_INDEX_OFFSET + self.levels.len() * 24;
+            for value in [
+                self.format.as_raw() as u32,
+                1,
+                self.extent.0,
+                self.extent.1,
+                self.depth,
+                self.layer_count,
+                self.face_count,
+                self.levels.len() as u32,
+                self.supercompression,
+                dfd_offset as u32,
+                0,
+                0,
+                0,
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 16]);
+            assert_eq!(bytes.len(), KTX2_LEVEL_INDEX_OFFSET);
+
+            let mut offset = dfd_offset as u64;
+            for level in &self.levels {
+                bytes.extend_from_slice(&offset.to_le_bytes());
+                bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+                bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+                offset += level.len() as u64;
+            }
+            self.levels
+                .iter()
+                .for_each(|level| bytes.extend_from_slice(level));
+            bytes
+        }
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn parses_levels() {
+        let file = Ktx2File::rgba8(4, 2, 3);
+        let image = Ktx2Image::parse_file(&file.bytes()).unwrap();
+
+        assert_eq!(image.format, RGBA8);
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.mips, file.levels);
+        assert_eq!(
+            image.mip_extent(2),
+            vk::Extent2D {
+                width: 1,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    fn without_levels_only_the_first_is_read() {
+        let mut bytes = Ktx2File::rgba8(2, 2, 1).bytes();
+        set_u32(&mut bytes, 40, 0);
+
+        let image = Ktx2Image::parse_file(&bytes).unwrap();
+        assert_eq!(image.mips.len(), 1);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let bytes = Ktx2File::rgba8(4, 4, 2).bytes();
+
+        for length in [0, 12, KTX2_LEVEL_INDEX_OFFSET - 1] {
+            assert!(
+                matches!(
+                    Ktx2Image::parse_file(&bytes[..length]),
+                    Err(Ktx2Error::InvalidData("missing KTX2 identifier"))
+                ),
+                "{} bytes",
+                length
+            );
+        }
+
+        let mut not_ktx2 = bytes.clone();
+        not_ktx2[5] = b'1';
+        assert!(matches!(
+            Ktx2Image::parse_file(&not_ktx2),
+            Err(Ktx2Error::InvalidData("missing KTX2 identifier"))
+        ));
+
+        // The index of the second level is cut.
+        let index_end = KTX2_LEVEL_INDEX_OFFSET + KTX2_LEVEL_INDEX_ENTRY_SIZE + 4;
+        assert!(matches!(
+            Ktx2Image::parse_file(&bytes[..index_end]),
+            Err(Ktx2Error::InvalidData("truncated level index"))
+        ));
+
+        let mut null_width = bytes;
+        set_u32(&mut null_width, 20, 0);
+        assert!(matches!(
+            Ktx2Image::parse_file(&null_width),
+            Err(Ktx2Error::InvalidData("null width"))
+        ));
+    }
+
+    #[test]
+    fn rejects_levels_out_of_bounds() {
+        let bytes = Ktx2File::rgba8(4, 4, 2).bytes();
+        let out_of_bounds = |bytes: &[u8]| {
+            matches!(
+                Ktx2Image::parse_file(bytes),
+                Err(Ktx2Error::InvalidData("level out of bounds"))
+            )
+        };
+
+        // The last level is cut.
+        assert!(out_of_bounds(&bytes[..bytes.len() - 1]));
+
+        // The length of the first level overflows its offset.
+        let mut overflowing = bytes.clone();
+        let length = KTX2_LEVEL_INDEX_OFFSET + 8;
+        overflowing[length..length + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(out_of_bounds(&overflowing));
+
+        // The second level starts past the end of the file.
+        let mut past_end = bytes;
+        let offset = KTX2_LEVEL_INDEX_OFFSET + KTX2_LEVEL_INDEX_ENTRY_SIZE;
+        let end = past_end.len() as u64;
+        past_end[offset..offset + 8].copy_from_slice(&end.to_le_bytes());
+        assert!(out_of_bounds(&past_end));
+    }
+
+    #[test]
+    fn rejects_supercompression() {
+        for scheme in [2, 3] {
+            let file = Ktx2File {
+                supercompression: scheme,
+                ..Ktx2File::rgba8(4, 4, 1)
+            };
+            assert!(matches!(
+                Ktx2Image::parse_file(&file.bytes()),
+                Err(Ktx2Error::UnsupportedSupercompression(s)) if s == scheme
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_basis_universal() {
+        let etc1s = Ktx2File {
+            format: vk::Format::UNDEFINED,
+            supercompression: SUPERCOMPRESSION_BASIS_LZ,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        assert!(matches!(
+            Ktx2Image::parse_file(&etc1s.bytes()),
+            Err(Ktx2Error::BasisUniversal)
+        ));
+
+        // UASTC is only told apart by the color model of its data format descriptor.
+        let uastc = Ktx2File {
+            format: vk::Format::UNDEFINED,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        let mut bytes = uastc.bytes();
+        let dfd_offset = KTX2_LEVEL_INDEX_OFFSET + KTX2_LEVEL_INDEX_ENTRY_SIZE;
+        bytes[dfd_offset + 12] = DF_MODEL_UASTC;
+        assert!(matches!(
+            Ktx2Image::parse_file(&bytes),
+            Err(Ktx2Error::BasisUniversal)
+        ));
+    }
+
+    #[test]
+    fn rejects_layouts_other_than_2d() {
+        let cubemap = Ktx2File {
+            face_count: 6,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        let array = Ktx2File {
+            layer_count: 2,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        let volume = Ktx2File {
+            depth: 4,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        let no_face = Ktx2File {
+            face_count: 0,
+            ..Ktx2File::rgba8(4, 4, 1)
+        };
+        for file in [cubemap, array, volume, no_face] {
+            assert!(matches!(
+                Ktx2Image::parse_file(&file.bytes()),
+                Err(Ktx2Error::UnsupportedLayout)
+            ));
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{FrameTelemetry, Swapchain, MAX_FRAMES_IN_FLIGHT};
+use ash::{khr, vk, Entry, Instance};
+use std::{collections::VecDeque, ffi::CStr, fmt};
+
+/// Maximum time to wait for a frame to be displayed, so a window that stops
+/// presenting, when minimized for example, does not block the render loop.
+const PRESENT_WAIT_TIMEOUT_NS: u64 = 100_000_000;
+
+/// Check support of VK_KHR_present_id and VK_KHR_present_wait and their features.
+pub(crate) fn query_present_wait_support(
+    entry: &Entry,
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let extension_props = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .expect("Failed to enumerate device extention properties")
+    };
+    let has_extension = |name: &CStr| {
+        extension_props.iter().any(|ext| {
+            let ext_name = unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) };
+            ext_name == name
+        })
+    };
+    if !has_extension(khr::present_id::NAME) || !has_extension(khr::present_wait::NAME) {
+        return false;
+    }
+
+    let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+    let mut present_id_features = vk::PhysicalDevicePresentIdFeaturesKHR::default();
+    let mut present_wait_features = vk::PhysicalDevicePresentWaitFeaturesKHR::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default()
+        .push_next(&mut present_id_features)
+        .push_next(&mut present_wait_features);
+    unsafe { properties2.get_physical_device_features2(physical_device, &mut features) };
+
+    present_id_features.present_id == vk::TRUE && present_wait_features.present_wait == vk::TRUE
+}
+
+/// How many presented frames can wait to be displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Up to one frame per frame in flight, for the best throughput.
+    Default,
+    /// A single frame, the next one starts when the previous one is displayed
+    /// so the input it samples is as recent as possible.
+    Low,
+}
+
+impl LatencyMode {
+    pub fn all() -> [Self; 2] {
+        [Self::Default, Self::Low]
+    }
+
+    pub fn max_queued_frames(self) -> usize {
+        match self {
+            Self::Default => MAX_FRAMES_IN_FLIGHT as _,
+            Self::Low => 1,
+        }
+    }
+}
+
+impl fmt::Display for LatencyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "Default"),
+            Self::Low => write!(f, "Low latency"),
+        }
+    }
+}
+
+/// Limit the frames queued for presentation and record when they are displayed.
+///
+/// Relies on VK_KHR_present_wait, without it the frames in flight are only
+/// limited by their fences and the display time is not known.
+///
+/// ```ignore
+/// pacer.wait(&swapchain, &mut telemetry);
+/// telemetry.begin_frame();
+/// // sample input, record and submit...
+/// let present_id = pacer.next_present_id(&swapchain, telemetry.current_frame().unwrap());
+/// swapchain.present_with_id(present_info, present_id);
+/// ```
+pub struct PresentPacer {
+    mode: LatencyMode,
+    next_present_id: u64,
+    /// Swapchain, present id and telemetry frame of the frames not displayed yet.
+    queued: VecDeque<(vk::SwapchainKHR, u64, u64)>,
+}
+
+impl PresentPacer {
+    pub fn new(mode: LatencyMode) -> Self {
+        Self {
+            mode,
+            next_present_id: 1,
+            queued: VecDeque::new(),
+        }
+    }
+}
+
+impl PresentPacer {
+    pub fn mode(&self) -> LatencyMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: LatencyMode) {
+        self.mode = mode;
+    }
+
+    /// Return the id to present the frame `frame` of the telemetry with.
+    pub fn next_present_id(&mut self, swapchain: &Swapchain, frame: u64) -> u64 {
+        let present_id = self.next_present_id;
+        self.next_present_id += 1;
+        if swapchain.supports_present_wait() {
+            self.queued
+                .push_back((swapchain.swapchain_khr(), present_id, frame));
+        }
+        present_id
+    }
+
+    /// Wait until no more frames than allowed by the latency mode are queued.
+    ///
+    /// The display time of the frames found displayed is recorded in `telemetry`.
+    /// Must be called before sampling the input of the next frame.
+    pub fn wait(&mut self, swapchain: &Swapchain, telemetry: &mut FrameTelemetry) {
+        // Ids of a previous swapchain will never be presented by this one.
+        let swapchain_khr = swapchain.swapchain_khr();
+        self.queued
+            .retain(|(queued, _, _)| *queued == swapchain_khr);
+
+        while let Some(&(_, present_id, frame)) = self.queued.front() {
+            let timeout = if self.queued.len() > self.mode.max_queued_frames() - 1 {
+                PRESENT_WAIT_TIMEOUT_NS
+            } else {
+                0
+            };
+            match swapchain.wait_for_present(present_id, timeout) {
+                Ok(()) => {
+                    telemetry.mark_displayed(frame);
+                    self.queued.pop_front();
+                }
+                Err(vk::Result::TIMEOUT) if timeout == 0 => break,
+                Err(error) => {
+                    tracing::debug!("Stopped waiting for present {}: {}", present_id, error);
+                    self.queued.clear();
+                }
+            }
+        }
+    }
+}
+
+impl Default for PresentPacer {
+    fn default() -> Self {
+        Self::new(LatencyMode::Default)
+    }
+}
@@ -0,0 +1,141 @@
+use ash::vk;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+/// Target of the tracing events of the creation and destruction of the gpu resources.
+///
+/// Filter them with `RUST_LOG=vks::resources=debug`.
+pub const RESOURCES_TRACING_TARGET: &str = "vks::resources";
+
+/// Kind of resource followed by the leak tracker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackedResource {
+    Buffer,
+    Image,
+    Texture,
+    Descriptors,
+    Swapchain,
+}
+
+impl TrackedResource {
+    pub const ALL: [TrackedResource; 5] = [
+        TrackedResource::Buffer,
+        TrackedResource::Image,
+        TrackedResource::Texture,
+        TrackedResource::Descriptors,
+        TrackedResource::Swapchain,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const RESOURCE_KIND_COUNT: usize = TrackedResource::ALL.len();
+
+static LIVE_RESOURCES: [AtomicI64; RESOURCE_KIND_COUNT] =
+    [const { AtomicI64::new(0) }; RESOURCE_KIND_COUNT];
+static DEVICE_MEMORY: AtomicI64 = AtomicI64::new(0);
+static LIVE_DEVICES: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn track_device_created() {
+    LIVE_DEVICES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns true if it was the last live device.
+pub(crate) fn track_device_destroyed() -> bool {
+    LIVE_DEVICES.fetch_sub(1, Ordering::Relaxed) == 1
+}
+
+/// True while at least one [`crate::Context`] owns a live device.
+pub fn is_device_alive() -> bool {
+    LIVE_DEVICES.load(Ordering::Relaxed) > 0
+}
+
+pub(crate) fn track_create(resource: TrackedResource) {
+    debug_assert!(
+        is_device_alive(),
+        "{:?} created without a live device",
+        resource
+    );
+    LIVE_RESOURCES[resource.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Resources hold an `Arc<Context>` so this only fails when a raw handle was
+/// copied out of its owner and destroyed after the device.
+pub(crate) fn track_destroy(resource: TrackedResource) {
+    debug_assert!(
+        is_device_alive(),
+        "{:?} destroyed after the device",
+        resource
+    );
+    LIVE_RESOURCES[resource.index()].fetch_sub(1, Ordering::Relaxed);
+}
+
+pub(crate) fn track_allocation(size: vk::DeviceSize) {
+    DEVICE_MEMORY.fetch_add(size as _, Ordering::Relaxed);
+}
+
+pub(crate) fn track_free(size: vk::DeviceSize) {
+    DEVICE_MEMORY.fetch_sub(size as _, Ordering::Relaxed);
+}
+
+/// Count of the live resources and allocated device memory at a given time.
+///
+/// Capture one after the initialization and compare the later ones
+/// against it to detect resources that are never released.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeakSnapshot {
+    live: [i64; RESOURCE_KIND_COUNT],
+    device_memory: i64,
+}
+
+impl LeakSnapshot {
+    pub fn capture() -> Self {
+        Self {
+            live: std::array::from_fn(|i| LIVE_RESOURCES[i].load(Ordering::Relaxed)),
+            device_memory: DEVICE_MEMORY.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn live(&self, resource: TrackedResource) -> i64 {
+        self.live[resource.index()]
+    }
+
+    /// Bytes of device memory allocated through [`crate::Buffer`] and [`crate::Image`].
+    pub fn device_memory(&self) -> i64 {
+        self.device_memory
+    }
+
+    /// Difference between `self` and an earlier `baseline`.
+    pub fn since(&self, baseline: &LeakSnapshot) -> LeakSnapshot {
+        Self {
+            live: std::array::from_fn(|i| self.live[i] - baseline.live[i]),
+            device_memory: self.device_memory - baseline.device_memory,
+        }
+    }
+
+    /// True if no tracked resource is alive and no memory is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.live.iter().all(|&count| count == 0) && self.device_memory == 0
+    }
+
+    /// True if no resource count nor memory grew compared to `baseline`.
+    pub fn is_within(&self, baseline: &LeakSnapshot) -> bool {
+        let diff = self.since(baseline);
+        diff.live.iter().all(|&count| count <= 0) && diff.device_memory <= 0
+    }
+}
+
+impl fmt::Display for LeakSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for resource in TrackedResource::ALL {
+            write!(f, "{:?}: {}, ", resource, self.live(resource))?;
+        }
+        write!(
+            f,
+            "device memory: {:.2} MiB",
+            self.device_memory as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
@@ -1,30 +1,95 @@
+mod allocator;
 mod base;
+mod bindless;
+mod blur;
 mod buffer;
 mod camera;
+mod camera_uniforms;
+mod color;
+mod command_cache;
+mod compute_kernels;
+mod compute_pass;
+mod config;
 mod context;
 mod controls;
+mod crash;
 mod debug;
 mod defered;
+mod deletion_queue;
+mod demo;
 mod descriptor;
+mod descriptor_pool;
+mod draw_id;
+mod error;
+mod external_upscaler;
+mod fixed_timestep;
+mod frame_limiter;
+mod gizmo;
+mod gpu_profiler;
+#[cfg(feature = "gui")]
 mod gui;
 mod image;
 mod in_flight_frames;
+mod ktx2;
+mod latency;
+mod leak_tracker;
+mod light;
+mod limits;
+mod measurement;
+mod mipmaps;
 mod msaa;
+mod offscreen;
+mod panorama;
+mod physical_device;
 mod pipeline;
+mod pipeline_compiler;
+mod pipeline_variants;
+mod pixel_picker;
+mod probe_grid;
+mod queue_handoff;
+mod sampler;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod session;
 mod shader;
+mod shader_hot_reload;
+mod shadow_casters;
+mod shading_rate;
+mod std140;
+mod stereo;
+mod subgroup;
 mod swapchain;
+mod telemetry;
+mod test_pattern;
+mod turntable;
 mod texture;
+mod texture_compression;
+mod texture_feedback;
+mod ui_layer;
+mod upload;
+mod upscale;
 mod util;
 mod vertex;
 pub use self::{
-    base::*, buffer::*, camera::*, context::*, debug::*, descriptor::*, gui::*, image::*,
-    in_flight_frames::*, msaa::*, pipeline::*, shader::*, swapchain::*, texture::*, util::*,
-    vertex::*,
+    allocator::*, base::*, bindless::*, blur::*, buffer::*, camera::*, camera_uniforms::*, color::*, command_cache::*, compute_kernels::*, config::*, controls::*,
+    context::*, crash::*, debug::*, deletion_queue::*, demo::*, descriptor::*, descriptor_pool::*, draw_id::*, error::*, external_upscaler::*, fixed_timestep::*, frame_limiter::*, gizmo::*, gpu_profiler::*,
+    image::*, in_flight_frames::*, ktx2::*, latency::*, leak_tracker::*, light::*, limits::*, measurement::*, mipmaps::*, msaa::*, offscreen::*, panorama::*,
+    physical_device::*, pipeline::*, pipeline_compiler::*, pipeline_variants::*, pixel_picker::*, probe_grid::*, queue_handoff::*, sampler::*, session::*, shader::*, shader_hot_reload::*, shadow_casters::*,
+    shading_rate::*, std140::*, stereo::*, subgroup::*, swapchain::*, telemetry::*, test_pattern::*, turntable::*,
+    texture::*, texture_compression::*, texture_feedback::*, ui_layer::*, upload::*, upscale::*, util::*, vertex::*,
 };
 
+#[cfg(feature = "gui")]
+pub use self::gui::*;
+#[cfg(feature = "scripting")]
+pub use self::scripting::*;
+
 pub use ash;
+#[cfg(feature = "tracy")]
+pub use tracing_tracy;
 use ash::vk;
 use std::sync::Arc;
+#[cfg(feature = "winit")]
 pub use winit;
 
 /// Hold a partially loaded resource.
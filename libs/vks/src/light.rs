@@ -0,0 +1,300 @@
+use crate::{mem_copy, Buffer, Context, MAX_LIGHTS};
+use ash::vk;
+use math::cgmath::{InnerSpace, Point3, Vector3};
+use std::{mem::size_of, sync::Arc};
+
+const DIRECTIONAL_LIGHT_TYPE: f32 = 0.0;
+const POINT_LIGHT_TYPE: f32 = 1.0;
+const SPOT_LIGHT_TYPE: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+/// Cpu side description of a light.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: Option<f32>,
+    pub enabled: bool,
+}
+
+impl Light {
+    pub fn directional(direction: Vector3<f32>, color: [f32; 3], intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            position: Point3::new(0.0, 0.0, 0.0),
+            direction,
+            color,
+            intensity,
+            range: None,
+            enabled: true,
+        }
+    }
+
+    pub fn point(
+        position: Point3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        range: Option<f32>,
+    ) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position,
+            direction: -Vector3::unit_y(),
+            color,
+            intensity,
+            range,
+            enabled: true,
+        }
+    }
+
+    pub fn spot(
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            position,
+            direction,
+            color,
+            intensity,
+            range: None,
+            enabled: true,
+        }
+    }
+}
+
+/// Light as laid out in the lights storage buffer (std430), see `shader/lights/lights.glsl`.
+///
+/// ```glsl
+/// struct Light {
+///     vec4 position;  // w: type (0 directional, 1 point, 2 spot)
+///     vec4 direction; // w: range, 0 when infinite
+///     vec4 color;     // w: intensity
+///     vec4 cone;      // x: cos inner angle, y: cos outer angle
+/// };
+/// layout(std430) readonly buffer Lights {
+///     uint count;
+///     Light lights[];
+/// };
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuLight {
+    position: [f32; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+    cone: [f32; 4],
+}
+
+crate::assert_std140_layout!(GpuLight {
+    position: 0,
+    direction: 16,
+    color: 32,
+    cone: 48,
+}, size: 64);
+
+impl From<&Light> for GpuLight {
+    fn from(light: &Light) -> Self {
+        let (light_type, cone) = match light.kind {
+            LightKind::Directional => (DIRECTIONAL_LIGHT_TYPE, [0.0; 4]),
+            LightKind::Point => (POINT_LIGHT_TYPE, [0.0; 4]),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (
+                SPOT_LIGHT_TYPE,
+                [inner_cone_angle.cos(), outer_cone_angle.cos(), 0.0, 0.0],
+            ),
+        };
+        let direction = if light.direction.magnitude2() > 0.0 {
+            light.direction.normalize()
+        } else {
+            -Vector3::unit_y()
+        };
+        let [r, g, b] = light.color;
+
+        Self {
+            position: [
+                light.position.x,
+                light.position.y,
+                light.position.z,
+                light_type,
+            ],
+            direction: [
+                direction.x,
+                direction.y,
+                direction.z,
+                light.range.unwrap_or(0.0),
+            ],
+            color: [r, g, b, light.intensity],
+            cone,
+        }
+    }
+}
+
+/// Header of the lights storage buffer. Padded so the array starts at 16 bytes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+struct GpuLightsHeader {
+    count: u32,
+    padding: [u32; 3],
+}
+
+pub(crate) const LIGHTS_BUFFER_SIZE: usize = size_of::<GpuLightsHeader>() + MAX_LIGHTS * size_of::<GpuLight>();
+
+/// Keep the scene lights and upload the enabled ones to a storage buffer.
+///
+/// There is one buffer per frame so a frame can be updated while the previous
+/// one is still read by the gpu. Each buffer is only rewritten when the lights
+/// changed since its last upload.
+pub struct LightManager {
+    buffers: Vec<Buffer>,
+    lights: Vec<Light>,
+    version: u64,
+    uploaded_versions: Vec<Option<u64>>,
+}
+
+impl LightManager {
+    pub fn new(context: &Arc<Context>, frame_count: usize) -> Self {
+        let buffers = (0..frame_count)
+            .map(|_| {
+                let mut buffer = Buffer::create(
+                    Arc::clone(context),
+                    LIGHTS_BUFFER_SIZE as _,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                );
+                buffer.map_memory();
+                buffer
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            buffers,
+            lights: Vec::new(),
+            version: 0,
+            uploaded_versions: vec![None; frame_count],
+        }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Add a light and return its index.
+    pub fn add(&mut self, light: Light) -> usize {
+        self.lights.push(light);
+        self.mark_changed();
+        self.lights.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Light {
+        let light = self.lights.remove(index);
+        self.mark_changed();
+        light
+    }
+
+    /// Mutable access to a light. The lights are considered changed if it exists.
+    pub fn light_mut(&mut self, index: usize) -> Option<&mut Light> {
+        if index < self.lights.len() {
+            self.mark_changed();
+        }
+        self.lights.get_mut(index)
+    }
+
+    pub fn set_lights(&mut self, lights: Vec<Light>) {
+        if self.lights != lights {
+            self.lights = lights;
+            self.mark_changed();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.set_lights(Vec::new());
+    }
+
+    fn mark_changed(&mut self) {
+        self.version += 1;
+    }
+
+    /// Upload the lights to the buffer of `frame_index` if it is out of date.
+    ///
+    /// # Returns
+    ///
+    /// True if the buffer was written.
+    pub fn update(&mut self, frame_index: usize) -> bool {
+        if self.uploaded_versions[frame_index] == Some(self.version) {
+            return false;
+        }
+
+        let enabled_lights = self.lights.iter().filter(|l| l.enabled);
+        if enabled_lights.clone().count() > MAX_LIGHTS {
+            tracing::warn!(
+                "Only the first {} enabled lights are uploaded to the gpu",
+                MAX_LIGHTS
+            );
+        }
+        let gpu_lights = enabled_lights
+            .take(MAX_LIGHTS)
+            .map(GpuLight::from)
+            .collect::<Vec<_>>();
+        let header = GpuLightsHeader {
+            count: gpu_lights.len() as _,
+            ..Default::default()
+        };
+
+        let buffer = &mut self.buffers[frame_index];
+        unsafe {
+            let ptr = buffer.map_memory();
+            mem_copy(ptr, &[header]);
+            if !gpu_lights.is_empty() {
+                mem_copy(ptr.add(size_of::<GpuLightsHeader>()), &gpu_lights);
+            }
+        }
+
+        self.uploaded_versions[frame_index] = Some(self.version);
+        true
+    }
+
+    pub fn buffer(&self, frame_index: usize) -> &Buffer {
+        &self.buffers[frame_index]
+    }
+
+    pub fn descriptor_info(&self, frame_index: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffers[frame_index].buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    /// Layout binding to declare the lights buffer in a forward or lighting pass.
+    pub fn descriptor_set_layout_binding(
+        binding: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(stage_flags)
+    }
+}
@@ -0,0 +1,132 @@
+use crate::Context;
+use ash::vk;
+use math::cgmath::Matrix4;
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+
+pub const DEFAULT_FOV: f32 = 45.0;
+pub const DEFAULT_Z_NEAR: f32 = 0.01;
+pub const DEFAULT_Z_FAR: f32 = 100.0;
+
+/// Maximum number of lights uploaded to the gpu.
+pub const MAX_LIGHTS: usize = 64;
+
+// Must be kept in sync with the value in model.vert
+pub const MAX_JOINTS_PER_MESH: usize = 512;
+
+pub const SHADOW_MAP_SIZES: [u32; 4] = [512, 1024, 2048, 4096];
+
+pub const MAX_BLOOM_MIP_COUNT: u32 = 8;
+/// Luminance of the hdr scene color above which it feeds the bloom.
+///
+/// Emissive materials reach it once their emissive color scaled by the
+/// emissive intensity exceeds 1, so raising the intensity makes them glow.
+pub const BLOOM_THRESHOLD: f32 = 1.0;
+
+/// Scale of the emissive colors of the materials, 1 renders them as authored.
+pub const DEFAULT_EMISSIVE_INTENSITY: f32 = 1.0;
+pub const MAX_EMISSIVE_INTENSITY: f32 = 100.0;
+
+pub const SSAO_KERNEL_SIZES: [u32; 4] = [16, 32, 64, 128];
+
+/// Sample counts of the scene attachments, 1 renders without MSAA.
+pub const MSAA_SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+/// Settings of the renderer bounded by the constants above and the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererSettings {
+    /// Samples per pixel of the scene attachments, one of [`MSAA_SAMPLE_COUNTS`].
+    pub msaa_samples: u32,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: DEFAULT_MSAA_SAMPLES,
+        }
+    }
+}
+
+impl RendererSettings {
+    /// Return the settings clamped to the constants of the renderer and the limits of the device.
+    ///
+    /// Each adjusted setting is logged as a warning.
+    pub fn validated(self, context: &Context) -> Self {
+        let limits = context.physical_device_limits();
+        let mut settings = self;
+
+        // The scene renders color and depth with the same sample count.
+        let supported_samples =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        settings.msaa_samples = MSAA_SAMPLE_COUNTS
+            .iter()
+            .copied()
+            .rev()
+            .find(|samples| {
+                *samples <= settings.msaa_samples
+                    && supported_samples.contains(vk::SampleCountFlags::from_raw(*samples))
+            })
+            .unwrap_or(MSAA_SAMPLE_COUNTS[0]);
+
+        settings.log_adjustments(&self);
+        settings
+    }
+
+    /// Sample count of the scene attachments, once validated.
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        vk::SampleCountFlags::from_raw(self.msaa_samples)
+    }
+
+    fn log_adjustments(&self, requested: &Self) {
+        let adjustments = [("msaa samples", requested.msaa_samples, self.msaa_samples)];
+        for (name, requested, value) in adjustments {
+            if requested != value {
+                tracing::warn!(
+                    "Renderer setting {} of {} is not supported, using {}",
+                    name,
+                    requested,
+                    value
+                );
+            }
+        }
+    }
+}
+
+/// Check the fixed size buffers of the renderer against the limits of the device.
+///
+/// Logs an error for each buffer larger than the device allows.
+/// Returns false if any is.
+pub fn check_buffer_limits(context: &Context) -> bool {
+    let limits = context.physical_device_limits();
+    let joints_size = (MAX_JOINTS_PER_MESH * size_of::<Matrix4<f32>>()) as u32;
+    let lights_size = crate::LIGHTS_BUFFER_SIZE as u32;
+
+    let checks = [
+        (
+            "joints uniform buffer",
+            joints_size,
+            limits.max_uniform_buffer_range,
+        ),
+        (
+            "lights storage buffer",
+            lights_size,
+            limits.max_storage_buffer_range,
+        ),
+    ];
+
+    let mut valid = true;
+    for (name, size, limit) in checks {
+        if size > limit {
+            tracing::error!(
+                "The {} is {} bytes but the device allows at most {}",
+                name,
+                size,
+                limit
+            );
+            valid = false;
+        }
+    }
+    valid
+}
@@ -0,0 +1,134 @@
+use math::{
+    cgmath::{Matrix4, MetricSpace, Point3},
+    Bvh, Ray,
+};
+
+/// Text attached to a point of the scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub position: Point3<f32>,
+    pub text: String,
+}
+
+/// Segment between two measured points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub start: Point3<f32>,
+    pub end: Point3<f32>,
+}
+
+impl Measurement {
+    pub fn distance(&self) -> f32 {
+        self.start.distance(self.end)
+    }
+}
+
+/// Measurements and annotations projected on the screen, see [`Measurements::project`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeasurementOverlay {
+    /// Segments in pixels.
+    pub lines: Vec<[[f32; 2]; 2]>,
+    /// Texts and their position in pixels.
+    pub labels: Vec<([f32; 2], String)>,
+}
+
+/// Distances between pairs of points picked in the scene and annotations.
+///
+/// Points are usually found with [`pick_point`] and the result drawn by the
+/// gui with [`crate::Gui::set_measurement_overlay`].
+#[derive(Debug, Clone, Default)]
+pub struct Measurements {
+    /// First point of the measurement in progress.
+    start: Option<Point3<f32>>,
+    measurements: Vec<Measurement>,
+    annotations: Vec<Annotation>,
+}
+
+impl Measurements {
+    /// Start a measurement at `point` or end the one in progress there.
+    ///
+    /// Returns the measurement if it ended.
+    pub fn add_point(&mut self, point: Point3<f32>) -> Option<Measurement> {
+        match self.start.take() {
+            Some(start) => {
+                let measurement = Measurement { start, end: point };
+                self.measurements.push(measurement);
+                Some(measurement)
+            }
+            None => {
+                self.start = Some(point);
+                None
+            }
+        }
+    }
+
+    pub fn annotate<S: Into<String>>(&mut self, position: Point3<f32>, text: S) {
+        self.annotations.push(Annotation {
+            position,
+            text: text.into(),
+        });
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.measurements.is_empty() && self.annotations.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Default::default();
+    }
+
+    /// Project the measurements and annotations with `view_proj` on a target of `extent`.
+    ///
+    /// Segments with an end behind the camera are skipped.
+    pub fn project(&self, view_proj: Matrix4<f32>, extent: [u32; 2]) -> MeasurementOverlay {
+        let to_screen = |point: Point3<f32>| {
+            let clip = view_proj * point.to_homogeneous();
+            (clip.w > 0.0).then(|| {
+                [
+                    (clip.x / clip.w * 0.5 + 0.5) * extent[0] as f32,
+                    (clip.y / clip.w * 0.5 + 0.5) * extent[1] as f32,
+                ]
+            })
+        };
+
+        let mut overlay = MeasurementOverlay::default();
+        for measurement in &self.measurements {
+            if let (Some(start), Some(end)) =
+                (to_screen(measurement.start), to_screen(measurement.end))
+            {
+                overlay.lines.push([start, end]);
+                let middle = [(start[0] + end[0]) * 0.5, (start[1] + end[1]) * 0.5];
+                overlay
+                    .labels
+                    .push((middle, format!("{:.3}", measurement.distance())));
+            }
+        }
+        if let Some(start) = self.start.and_then(to_screen) {
+            overlay.labels.push((start, "+".to_string()));
+        }
+        for annotation in &self.annotations {
+            if let Some(position) = to_screen(annotation.position) {
+                overlay.labels.push((position, annotation.text.clone()));
+            }
+        }
+        overlay
+    }
+}
+
+/// Nearest point where `ray` enters the bounds of the items of `bvh`.
+///
+/// Exact for items whose bounds are their geometry, like axis aligned quads,
+/// the nearest candidate otherwise.
+pub fn pick_point(bvh: &Bvh<f32>, ray: &Ray<f32>) -> Option<Point3<f32>> {
+    bvh.query_ray(ray)
+        .first()
+        .map(|(_, distance)| ray.origin + ray.direction * *distance)
+}
@@ -0,0 +1,381 @@
+use crate::{
+    compute_pass::{cmd_dispatch_groups, create_pipeline_layout},
+    create_compute_pipeline, ComputePipelineParameters, Context, Descriptors, Image, SamplerKey,
+    ShaderParameters, SpecializationConstants, SubgroupSpecialization,
+};
+use ash::vk;
+use std::{ops::Range, sync::Arc};
+
+const SHADER_NAME: &str = "downsample";
+const SUBGROUP_SHADER_NAME: &str = "downsample_subgroup";
+/// Mips written by a dispatch of the downsampler, a group reducing a 32x32
+/// tile of its source mip to a single texel.
+const MIPS_PER_DISPATCH: u32 = 5;
+/// Texels of the first mip written by a group on each axis.
+const GROUP_SIZE: u32 = 16;
+
+/// How the mips of the images of a format are generated, see [`Image::cmd_generate_mipmaps`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipmapMethod {
+    /// Linear blits from each mip to the next one.
+    Blit,
+    /// Compute downsampler writing up to 5 mips per dispatch, for the formats
+    /// without linear blits. It averages boxes of 2x2 texels, clamping the
+    /// last row and column of odd sized mips. With subgroup clustered
+    /// operations, the first mips of a dispatch are averaged in registers
+    /// instead of shared memory.
+    Compute,
+}
+
+impl MipmapMethod {
+    /// Method for the optimal tiling images of `format`, `None` if their mips
+    /// can't be generated.
+    pub fn for_format(context: &Context, format: vk::Format) -> Option<Self> {
+        let features = unsafe {
+            context
+                .instance()
+                .get_physical_device_format_properties(context.physical_device(), format)
+        }
+        .optimal_tiling_features;
+        let blit = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        let compute = vk::FormatFeatureFlags::STORAGE_IMAGE | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+        if features.contains(blit) {
+            Some(Self::Blit)
+        } else if features.contains(compute) && supports_write_without_format(context) {
+            Some(Self::Compute)
+        } else {
+            None
+        }
+    }
+
+    /// Usage the images must be created with for their mips to be generated.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        match self {
+            Self::Blit => vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            Self::Compute => vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        }
+    }
+}
+
+/// Usage the images of `format` must be created with for their mips to be generated.
+pub fn mipmap_usage(context: &Context, format: vk::Format) -> vk::ImageUsageFlags {
+    MipmapMethod::for_format(context, format)
+        .map_or(vk::ImageUsageFlags::empty(), |method| method.usage())
+}
+
+fn supports_write_without_format(context: &Context) -> bool {
+    let features = unsafe {
+        context
+            .instance()
+            .get_physical_device_features(context.physical_device())
+    };
+    features.shader_storage_image_write_without_format == vk::TRUE
+}
+
+/// Resources of a downsampling, kept until the frame recording it is complete.
+struct Downsampling {
+    context: Arc<Context>,
+    views: Vec<vk::ImageView>,
+    _descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl Drop for Downsampling {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.views
+                .iter()
+                .for_each(|view| device.destroy_image_view(*view, None));
+        }
+    }
+}
+
+/// Record the generation of the mips of `image` from its first one with the
+/// compute downsampler, see [`MipmapMethod::Compute`].
+///
+/// The downsampler uses subgroup operations if `subgroups` is true and the
+/// device supports them, see [`SubgroupSpecialization`]. [`Image::cmd_generate_mipmaps`]
+/// always enables them.
+///
+/// All the mips must be in `TRANSFER_DST_OPTIMAL` layout and are left in
+/// `SHADER_READ_ONLY_OPTIMAL` layout. The pipeline and the descriptors are
+/// destroyed by the [`crate::DeletionQueue`] of `context`.
+pub fn cmd_downsample_mipmaps(
+    context: &Arc<Context>,
+    command_buffer: vk::CommandBuffer,
+    image: &Image,
+    subgroups: bool,
+) {
+    let mip_levels = image.mip_levels;
+    let shader_read = (
+        vk::PipelineStageFlags2::COMPUTE_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        vk::AccessFlags2::SHADER_SAMPLED_READ,
+    );
+    let storage_write = (
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_STORAGE_WRITE,
+    );
+
+    if mip_levels == 1 {
+        cmd_mips_barrier(
+            context,
+            command_buffer,
+            image,
+            0..1,
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            shader_read,
+        );
+        return;
+    }
+
+    let views = image.create_mips_views(
+        vk::ImageViewType::TYPE_2D_ARRAY,
+        vk::ImageAspectFlags::COLOR,
+    );
+    let bases = (0..mip_levels - 1)
+        .step_by(MIPS_PER_DISPATCH as _)
+        .collect::<Vec<_>>();
+    let descriptors = create_descriptors(context, &views, &bases);
+    let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+    let specialization = SubgroupSpecialization::new(
+        &context.subgroup_support(),
+        vk::SubgroupFeatureFlags::CLUSTERED,
+        4,
+        subgroups,
+    );
+    let mut constants = SpecializationConstants::new();
+    let shader_name = match specialization {
+        Some(specialization) => {
+            specialization.add_constants(&mut constants);
+            SUBGROUP_SHADER_NAME
+        }
+        None => SHADER_NAME,
+    };
+    let specialization_info = constants.info();
+    let pipeline = create_compute_pipeline(
+        context,
+        ComputePipelineParameters {
+            shader_params: ShaderParameters::specialized(shader_name, &specialization_info),
+            layout: pipeline_layout,
+        },
+    );
+
+    // The content of the mips written by the downsampler is discarded.
+    cmd_mips_barrier(
+        context,
+        command_buffer,
+        image,
+        1..mip_levels,
+        (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+        ),
+        (vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::NONE),
+        storage_write,
+    );
+    unsafe {
+        context
+            .device()
+            .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline)
+    };
+
+    for (set, &base) in descriptors.sets().iter().zip(&bases) {
+        let (source_layout, source_write) = match base {
+            0 => (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                (
+                    vk::PipelineStageFlags2::TRANSFER,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                ),
+            ),
+            _ => (vk::ImageLayout::GENERAL, storage_write),
+        };
+        cmd_mips_barrier(
+            context,
+            command_buffer,
+            image,
+            base..base + 1,
+            (source_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            source_write,
+            shader_read,
+        );
+
+        let mip_count = MIPS_PER_DISPATCH.min(mip_levels - 1 - base);
+        let width = (image.extent.width >> (base + 1)).max(1);
+        let height = (image.extent.height >> (base + 1)).max(1);
+        cmd_dispatch_groups(
+            context,
+            command_buffer,
+            pipeline_layout,
+            *set,
+            &[mip_count, 0, 0, 0],
+            (width.div_ceil(GROUP_SIZE), height.div_ceil(GROUP_SIZE)),
+        );
+    }
+
+    let last_base = bases[bases.len() - 1];
+    cmd_mips_barrier(
+        context,
+        command_buffer,
+        image,
+        last_base + 1..mip_levels,
+        (
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        ),
+        storage_write,
+        shader_read,
+    );
+
+    context.deletion_queue().enqueue(Downsampling {
+        context: Arc::clone(context),
+        views,
+        _descriptors: descriptors,
+        pipeline_layout,
+        pipeline,
+    });
+}
+
+/// Create one set per dispatch, sampling the mip `base` and writing the next ones.
+fn create_descriptors(
+    context: &Arc<Context>,
+    views: &[vk::ImageView],
+    bases: &[u32],
+) -> Descriptors {
+    let device = context.device();
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(MIPS_PER_DISPATCH)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+    ];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let set_count = bases.len() as u32;
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: set_count,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: set_count * MIPS_PER_DISPATCH,
+        },
+    ];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(set_count);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = vec![layout; bases.len()];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    // Texel fetches ignore the filters, any sampler does.
+    let sampler = context.get_sampler(SamplerKey {
+        mag_filter: vk::Filter::NEAREST,
+        min_filter: vk::Filter::NEAREST,
+        anisotropy: false,
+        ..SamplerKey::repeat(1)
+    });
+    let last_view = views.len() - 1;
+    for (set, &base) in sets.iter().zip(bases) {
+        let base = base as usize;
+        let source_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(views[base])
+            .sampler(sampler)];
+        // Slots past the last mip of the image are bound but never written.
+        let mips_info = (1..=MIPS_PER_DISPATCH as usize)
+            .map(|offset| {
+                vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(views[(base + offset).min(last_view)])
+            })
+            .collect::<Vec<_>>();
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&source_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&mips_info),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+/// Record a barrier on the `mips` of all the layers of `image`.
+fn cmd_mips_barrier(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: &Image,
+    mips: Range<u32>,
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(src.0)
+        .src_access_mask(src.1)
+        .old_layout(layouts.0)
+        .dst_stage_mask(dst.0)
+        .dst_access_mask(dst.1)
+        .new_layout(layouts.1)
+        .image(image.image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: mips.start,
+            level_count: mips.len() as _,
+            base_array_layer: 0,
+            layer_count: image.layers,
+        });
+    let dependency_info =
+        vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
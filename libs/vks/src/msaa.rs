@@ -1,3 +1,6 @@
+use ash::vk;
+use std::fmt;
+
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Eq, Default)]
 pub enum MsaaSamples {
     #[default]
@@ -9,3 +12,51 @@ pub enum MsaaSamples {
     S32,
     S64,
 }
+
+/// How the edges of alpha masked materials are drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaskEdges {
+    /// Fragments below the alpha cutoff are discarded, the edges are aliased.
+    #[default]
+    Discard,
+    /// The alpha around the cutoff selects the covered samples. Only smooths
+    /// the edges of multisampled targets.
+    AlphaToCoverage,
+    /// The alpha around the cutoff is blended with the background, which
+    /// smooths the edges without MSAA but depends on the draw order.
+    Blend,
+}
+
+impl MaskEdges {
+    pub fn all() -> [Self; 3] {
+        [Self::Discard, Self::AlphaToCoverage, Self::Blend]
+    }
+
+    /// Mode smoothing the edges for targets of `samples`.
+    pub fn for_samples(samples: vk::SampleCountFlags) -> Self {
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            Self::Discard
+        } else {
+            Self::AlphaToCoverage
+        }
+    }
+
+    /// Value of the `MASK_EDGES` specialization constant of the shaders.
+    pub fn index(self) -> u32 {
+        match self {
+            Self::Discard => 0,
+            Self::AlphaToCoverage => 1,
+            Self::Blend => 2,
+        }
+    }
+}
+
+impl fmt::Display for MaskEdges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Discard => write!(f, "Discard"),
+            Self::AlphaToCoverage => write!(f, "Alpha to coverage"),
+            Self::Blend => write!(f, "Blend"),
+        }
+    }
+}
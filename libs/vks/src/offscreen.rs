@@ -0,0 +1,356 @@
+use crate::{
+    create_sampler, format_aspect_flags, pixel_picker::texel_size, Context, Image, ImageParameters,
+    Texture,
+};
+use ash::vk;
+use std::sync::Arc;
+
+/// Color and depth attachments to render a view outside of the swapchain, for
+/// thumbnails, probe captures or a picture in picture view.
+///
+/// A render is recorded between [`OffscreenTarget::cmd_begin_rendering`] and
+/// [`OffscreenTarget::cmd_end_rendering`]. The color is then left in
+/// `SHADER_READ_ONLY_OPTIMAL` layout, ready to be sampled or read back with
+/// [`OffscreenTarget::read_back`].
+///
+/// Multisampled targets render to transient attachments of `samples`, the
+/// color is resolved to the single sampled color at the end of the rendering.
+pub struct OffscreenTarget {
+    context: Arc<Context>,
+    samples: vk::SampleCountFlags,
+    color: Texture,
+    msaa_color: Option<Texture>,
+    depth: Texture,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        context: &Arc<Context>,
+        extent: vk::Extent2D,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Self {
+        let color = create_attachment(
+            context,
+            extent,
+            color_format,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+        );
+        let msaa_color = (samples != vk::SampleCountFlags::TYPE_1).then(|| {
+            create_attachment(
+                context,
+                extent,
+                color_format,
+                samples,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            )
+        });
+        let depth = create_attachment(
+            context,
+            extent,
+            depth_format,
+            samples,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        );
+
+        // Sampled before the first render.
+        color.image.transition_image_layout(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        Self {
+            context: Arc::clone(context),
+            samples,
+            color,
+            msaa_color,
+            depth,
+        }
+    }
+}
+
+impl OffscreenTarget {
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.color.image.extent.width,
+            height: self.color.image.extent.height,
+        }
+    }
+
+    pub fn color_format(&self) -> vk::Format {
+        self.color.image.format
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth.image.format
+    }
+
+    /// Sample count the pipelines rendering to the target must use.
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+
+    /// Color of the last render, with a linear sampler.
+    pub fn color(&self) -> &Texture {
+        &self.color
+    }
+
+    pub fn depth(&self) -> &Texture {
+        &self.depth
+    }
+
+    /// Transition the attachments for a render discarding their previous content.
+    pub fn cmd_prepare_attachments(&self, command_buffer: vk::CommandBuffer) {
+        for color in std::iter::once(&self.color).chain(&self.msaa_color) {
+            color.image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+        }
+        self.depth.image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        );
+    }
+
+    /// Color attachment of a rendering clearing the target to `clear_color`.
+    ///
+    /// Multisampled targets are resolved to [`OffscreenTarget::color`].
+    pub fn color_attachment_info(
+        &self,
+        clear_color: [f32; 4],
+    ) -> vk::RenderingAttachmentInfo<'static> {
+        let info = vk::RenderingAttachmentInfo::default()
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            })
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR);
+        match self.msaa_color.as_ref() {
+            Some(msaa_color) => info
+                .image_view(msaa_color.view)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                .resolve_image_view(self.color.view)
+                .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            None => info
+                .image_view(self.color.view)
+                .store_op(vk::AttachmentStoreOp::STORE),
+        }
+    }
+
+    /// Depth attachment of a rendering clearing the target. The depth is not stored.
+    pub fn depth_attachment_info(&self) -> vk::RenderingAttachmentInfo<'static> {
+        vk::RenderingAttachmentInfo::default()
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            })
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .image_view(self.depth.view)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+    }
+
+    /// Begin rendering to the target, clearing it, and cover it with the viewport and scissor.
+    ///
+    /// The previous content of the target is discarded.
+    pub fn cmd_begin_rendering(&self, command_buffer: vk::CommandBuffer, clear_color: [f32; 4]) {
+        self.cmd_prepare_attachments(command_buffer);
+
+        let extent = self.extent();
+        let color_attachment_info = self.color_attachment_info(clear_color);
+        let depth_attachment_info = self.depth_attachment_info();
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .depth_attachment(&depth_attachment_info)
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        let device = self.context.device();
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+        }
+    }
+
+    /// End rendering and make the color available to the fragment shaders.
+    pub fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer)
+        };
+        self.color.image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    /// Copy the color of the last render to host memory and wait for it.
+    ///
+    /// # Returns
+    ///
+    /// The tightly packed rows of texels, in the color format of the target.
+    pub fn read_back(&self) -> Vec<u8> {
+        let texel_size = texel_size(self.color_format()).unwrap_or_else(|| {
+            panic!(
+                "Failed to read back offscreen target of format {:?}",
+                self.color_format()
+            )
+        });
+
+        let image = &self.color.image;
+        image.transition_image_layout(
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        let data = image.read_back(0, texel_size);
+        image.transition_image_layout(
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        data
+    }
+
+    /// Copy the color of the last render to host memory converted to
+    /// `format`, and wait for it. Saves the renders of float targets as 8 bits
+    /// texels, an `_SRGB` format encoding the linear colors.
+    ///
+    /// The conversion is a blit, `format` must support `BLIT_DST` like
+    /// `R8G8B8A8_SRGB` always does.
+    ///
+    /// # Returns
+    ///
+    /// The tightly packed rows of texels, in `format`.
+    pub fn read_back_as(&self, format: vk::Format) -> Vec<u8> {
+        if format == self.color_format() {
+            return self.read_back();
+        }
+        let texel_size = texel_size(format)
+            .unwrap_or_else(|| panic!("Failed to read back offscreen target as {:?}", format));
+
+        let extent = self.extent();
+        let converted = Image::create(
+            Arc::clone(&self.context),
+            ImageParameters {
+                mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                extent,
+                format,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+                ..Default::default()
+            },
+        );
+        let source = &self.color.image;
+        let corner = vk::Offset3D {
+            x: extent.width as _,
+            y: extent.height as _,
+            z: 1,
+        };
+        let layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let blit = vk::ImageBlit::default()
+            .src_offsets([vk::Offset3D::default(), corner])
+            .src_subresource(layers)
+            .dst_offsets([vk::Offset3D::default(), corner])
+            .dst_subresource(layers);
+
+        self.context.execute_one_time_commands(|command_buffer| {
+            source.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+            converted.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+            unsafe {
+                self.context.device().cmd_blit_image(
+                    command_buffer,
+                    source.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    converted.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&blit),
+                    vk::Filter::NEAREST,
+                )
+            };
+            source.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+            converted.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+        });
+        converted.read_back(0, texel_size)
+    }
+}
+
+fn create_attachment(
+    context: &Arc<Context>,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    sample_count: vk::SampleCountFlags,
+    usage: vk::ImageUsageFlags,
+) -> Texture {
+    let image = Image::create(
+        Arc::clone(context),
+        ImageParameters {
+            mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            extent,
+            sample_count,
+            format,
+            usage,
+            ..Default::default()
+        },
+    );
+    let view = image.create_view(vk::ImageViewType::TYPE_2D, format_aspect_flags(format));
+    let sampler = usage
+        .contains(vk::ImageUsageFlags::SAMPLED)
+        .then(|| create_sampler(context, vk::Filter::LINEAR, vk::Filter::LINEAR));
+
+    Texture::new(Arc::clone(context), image, view, sampler)
+}
@@ -0,0 +1,308 @@
+use crate::{
+    compute_pass::*, create_compute_pipeline, texture::create_texture, Camera, CameraUBO,
+    ComputePipelineParameters, Context, Descriptors, OffscreenTarget, ShaderParameters, Texture,
+};
+use ash::vk;
+use math::{
+    cgmath::{Deg, Matrix4, Point3, Vector3},
+    perspective_with,
+};
+use std::{fmt, sync::Arc};
+
+const SHADER_NAME: &str = "equirectangular";
+
+/// Encoding of the panoramas saved by a [`PanoramaCapture`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanoramaFormat {
+    /// 8 bits sRGB texels.
+    Png,
+    /// 32 bits float linear texels, keeping the dynamic range of the scene.
+    Exr,
+}
+
+impl PanoramaFormat {
+    /// Format of the panorama texels, `R8G8B8A8_UNORM` or `R32G32B32A32_SFLOAT`.
+    pub fn texel_format(&self) -> vk::Format {
+        match self {
+            Self::Png => vk::Format::R8G8B8A8_UNORM,
+            Self::Exr => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Exr => "exr",
+        }
+    }
+
+    fn texel_size(&self) -> u32 {
+        match self {
+            Self::Png => 4,
+            Self::Exr => 16,
+        }
+    }
+}
+
+impl fmt::Display for PanoramaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Png => write!(f, "PNG"),
+            Self::Exr => write!(f, "EXR"),
+        }
+    }
+}
+
+/// Texels of a panorama read back by [`PanoramaCapture::capture`].
+pub struct PanoramaImage {
+    pub format: PanoramaFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rgba rows, of bytes for png and of native endian `f32` for exr.
+    pub data: Vec<u8>,
+}
+
+/// Capture the scene around a camera to a cubemap and unwrap it to an
+/// equirectangular panorama, twice as wide as high.
+///
+/// The application renders each face to [`PanoramaCapture::face_target`]
+/// with the camera of the face, the faces are then copied to a cubemap which
+/// a compute pass samples for each texel of the panorama. The panorama is
+/// aligned with the world, its center looks at -z and its top at +y.
+pub struct PanoramaCapture {
+    context: Arc<Context>,
+    format: PanoramaFormat,
+    /// Encode the colors to sRGB when unwrapping, the faces holding linear colors.
+    encode_srgb: bool,
+    face_target: OffscreenTarget,
+    cubemap: Texture,
+    panorama: Texture,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl PanoramaCapture {
+    /// Create a capture whose faces are `face_size` pixels wide, rendered in
+    /// attachments of `color_format` and `depth_format` with `samples`.
+    pub fn new(
+        context: &Arc<Context>,
+        face_size: u32,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
+        format: PanoramaFormat,
+    ) -> Self {
+        check_write_without_format(context);
+
+        let face_target = OffscreenTarget::new(
+            context,
+            vk::Extent2D {
+                width: face_size,
+                height: face_size,
+            },
+            color_format,
+            depth_format,
+            samples,
+        );
+        let cubemap = Texture::create_renderable_cubemap(context, face_size, 1, color_format);
+        let panorama = create_texture(
+            context,
+            vk::Extent2D {
+                width: face_size * 4,
+                height: face_size * 2,
+            },
+            format.texel_format(),
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            None,
+        );
+
+        let descriptors = create_descriptors(context, &[(&cubemap, panorama.view)]);
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::new(SHADER_NAME),
+                layout: pipeline_layout,
+            },
+        );
+
+        // 8 bits unorm attachments already hold display encoded colors.
+        let encode_srgb = format == PanoramaFormat::Png
+            && !matches!(
+                color_format,
+                vk::Format::R8G8B8A8_UNORM | vk::Format::B8G8R8A8_UNORM
+            );
+
+        Self {
+            context: Arc::clone(context),
+            format,
+            encode_srgb,
+            face_target,
+            cubemap,
+            panorama,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl PanoramaCapture {
+    pub fn format(&self) -> PanoramaFormat {
+        self.format
+    }
+
+    /// Target each face must be rendered to.
+    pub fn face_target(&self) -> &OffscreenTarget {
+        &self.face_target
+    }
+
+    /// Camera uniforms of the six faces, in the +x, -x, +y, -y, +z, -z order
+    /// of the cubemap layers.
+    ///
+    /// The faces are seen from the position of `camera` with a 90° field of
+    /// view, keeping its clip planes and depth mode.
+    pub fn face_ubos(camera: &Camera) -> [CameraUBO; 6] {
+        cube_face_ubos(camera.position(), camera)
+    }
+
+    /// Capture the panorama around `camera` and read it back.
+    ///
+    /// `render_face` is called for each face with its index and camera
+    /// uniforms, and must render the scene to [`PanoramaCapture::face_target`]
+    /// and wait for it. Stalls the queue, meant for exports.
+    pub fn capture<F>(&self, camera: &Camera, mut render_face: F) -> PanoramaImage
+    where
+        F: FnMut(usize, CameraUBO),
+    {
+        self.cubemap.image.transition_image_layout(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        for (face, ubo) in Self::face_ubos(camera).into_iter().enumerate() {
+            render_face(face, ubo);
+            self.copy_face(face as u32);
+        }
+        self.cubemap.image.transition_image_layout(
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        self.context.execute_one_time_commands(|command_buffer| {
+            self.cmd_unwrap(command_buffer);
+            self.panorama.image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+        });
+
+        let extent = self.panorama.image.extent;
+        PanoramaImage {
+            format: self.format,
+            width: extent.width,
+            height: extent.height,
+            data: self.panorama.image.read_back(0, self.format.texel_size()),
+        }
+    }
+
+    /// Copy the last render of the face target to the layer `face` of the cubemap.
+    fn copy_face(&self, face: u32) {
+        let source = &self.face_target.color().image;
+        let layers = |base_array_layer| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer,
+            layer_count: 1,
+        };
+        let region = vk::ImageCopy::default()
+            .src_subresource(layers(0))
+            .dst_subresource(layers(face))
+            .extent(source.extent);
+
+        self.context.execute_one_time_commands(|command_buffer| {
+            source.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+            unsafe {
+                self.context.device().cmd_copy_image(
+                    command_buffer,
+                    source.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.cubemap.image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&region),
+                )
+            };
+            source.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        });
+    }
+
+    /// Record the unwrap of the cubemap, leaving the panorama in `SHADER_READ_ONLY_OPTIMAL` layout.
+    fn cmd_unwrap(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.context.device().cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            )
+        };
+
+        let parameters = [self.encode_srgb as u32, 0, 0, 0];
+
+        cmd_begin_write(&self.context, command_buffer, self.panorama.image.image);
+        cmd_dispatch_pass(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            self.descriptors.sets()[0],
+            &parameters,
+            self.panorama.image.extent,
+        );
+        cmd_end_write(&self.context, command_buffer, self.panorama.image.image);
+    }
+}
+
+impl Drop for PanoramaCapture {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Camera uniforms of the six faces of a cubemap seen from `eye`, with the
+/// clip planes and depth mode of `camera`, see [`PanoramaCapture::face_ubos`].
+pub(crate) fn cube_face_ubos(eye: Point3<f32>, camera: &Camera) -> [CameraUBO; 6] {
+    let z_far = (!camera.infinite_far).then_some(camera.z_far);
+    let proj = perspective_with(Deg(90.0), 1.0, camera.z_near, z_far, camera.depth_mode);
+
+    face_directions().map(|(direction, up)| {
+        let view = Matrix4::look_at_rh(eye, eye + direction, up);
+        CameraUBO::new(view, proj, proj * view, eye, camera.z_near, camera.z_far)
+    })
+}
+
+/// Forward and up directions of the faces of a cubemap, matching the layers
+/// sampled along each axis with the y flipped projection.
+fn face_directions() -> [(Vector3<f32>, Vector3<f32>); 6] {
+    [
+        (Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_y()),
+        (Vector3::unit_y(), -Vector3::unit_z()),
+        (-Vector3::unit_y(), Vector3::unit_z()),
+        (Vector3::unit_z(), Vector3::unit_y()),
+        (-Vector3::unit_z(), Vector3::unit_y()),
+    ]
+}
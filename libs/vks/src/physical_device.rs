@@ -0,0 +1,118 @@
+use ash::{khr, vk, Entry, Instance};
+use std::{
+    ffi::{CStr, CString},
+    fmt,
+};
+
+/// Description of a physical device, used to pick the gpu of a headless
+/// [`crate::Context`] with [`crate::Context::new_headless`].
+#[derive(Debug, Clone)]
+pub struct PhysicalDeviceInfo {
+    /// Index of the device in the list returned by the Vulkan loader.
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub api_version: u32,
+    /// Identify the same device across instances.
+    pub uuid: [u8; vk::UUID_SIZE],
+    pub device_local_memory: vk::DeviceSize,
+}
+
+impl PhysicalDeviceInfo {
+    pub(crate) fn query(
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        index: usize,
+    ) -> Self {
+        let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+        unsafe { properties2.get_physical_device_properties2(physical_device, &mut properties) };
+        let properties = properties.properties;
+
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let device_local_memory = memory_properties.memory_heaps
+            [..memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+
+        Self {
+            index,
+            name: name.to_string_lossy().into_owned(),
+            device_type: properties.device_type,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: properties.api_version,
+            uuid: id_properties.device_uuid,
+            device_local_memory,
+        }
+    }
+
+    pub fn is_discrete(&self) -> bool {
+        self.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
+    }
+}
+
+impl fmt::Display for PhysicalDeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} ({:?}, {} MiB, Vulkan {}.{}.{})",
+            self.index,
+            self.name,
+            self.device_type,
+            self.device_local_memory / (1024 * 1024),
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+        )
+    }
+}
+
+/// List the physical devices available on the system.
+///
+/// A temporary instance is created so it can be called before any context exists.
+pub fn enumerate_physical_devices() -> Vec<PhysicalDeviceInfo> {
+    let entry = Entry::linked();
+    let app_name = CString::new("Vulkan Application").unwrap();
+    let app_info = vk::ApplicationInfo::default()
+        .application_name(app_name.as_c_str())
+        .api_version(vk::make_api_version(0, 1, 1, 0));
+    let extension_names = [khr::get_physical_device_properties2::NAME.as_ptr()];
+    let instance_create_info = vk::InstanceCreateInfo::default()
+        .application_info(&app_info)
+        .enabled_extension_names(&extension_names);
+
+    unsafe {
+        let instance = entry
+            .create_instance(&instance_create_info, None)
+            .expect("Failed to create instance");
+        let devices = instance
+            .enumerate_physical_devices()
+            .expect("Failed to enumerate physical devices")
+            .into_iter()
+            .enumerate()
+            .map(|(index, device)| PhysicalDeviceInfo::query(&entry, &instance, device, index))
+            .collect();
+        instance.destroy_instance(None);
+        devices
+    }
+}
+
+/// Pick a physical device other than `primary` for offline work, discrete gpus first.
+///
+/// Returns `None` when the system has a single gpu.
+pub fn pick_secondary_physical_device(primary: &PhysicalDeviceInfo) -> Option<PhysicalDeviceInfo> {
+    enumerate_physical_devices()
+        .into_iter()
+        .filter(|device| device.uuid != primary.uuid)
+        .min_by_key(|device| !device.is_discrete())
+}
@@ -1,4 +1,7 @@
-use super::{Context, ShaderModule, Vertex};
+use super::{
+    has_stencil_component, Context, PipelineCompileMode, ShaderModule, ShadingRateState, Vertex,
+    VksError, RESOURCES_TRACING_TARGET,
+};
 use ash::vk;
 use std::{ffi::CString, sync::Arc};
 
@@ -8,9 +11,19 @@ pub struct PipelineParameters<'a> {
     pub fragment_shader_params: ShaderParameters<'a>,
     pub multisampling_info: &'a vk::PipelineMultisampleStateCreateInfo<'a>,
     pub viewport_info: &'a vk::PipelineViewportStateCreateInfo<'a>,
+    /// Primitives assembled from the vertices, point lists need the vertex shader to write `gl_PointSize`.
+    pub topology: vk::PrimitiveTopology,
+    /// Its line width is clamped to what the device supports.
     pub rasterizer_info: &'a vk::PipelineRasterizationStateCreateInfo<'a>,
     pub dynamic_state_info: Option<&'a vk::PipelineDynamicStateCreateInfo<'a>>,
     pub depth_stencil_info: Option<&'a vk::PipelineDepthStencilStateCreateInfo<'a>>,
+    /// Stencil test applied over `depth_stencil_info`.
+    pub stencil: Option<StencilState>,
+    /// Fragment shading rate of the draws, the device must support it.
+    pub shading_rate: Option<ShadingRateState>,
+    /// Drawn in passes with a fragment shading rate attachment, see
+    /// [`crate::ShadingRateImage::rendering_attachment_info`].
+    pub shading_rate_attachment: bool,
     pub color_blend_attachments: &'a [vk::PipelineColorBlendAttachmentState],
     pub color_attachment_formats: &'a [vk::Format],
     pub depth_attachment_format: Option<vk::Format>,
@@ -22,7 +35,22 @@ pub struct PipelineParameters<'a> {
 pub fn create_pipeline<V: Vertex>(
     context: &Arc<Context>,
     params: PipelineParameters,
-) -> vk::Pipeline {
+) -> Result<vk::Pipeline, VksError> {
+    create_pipeline_with_mode::<V>(context, params, PipelineCompileMode::Compile)
+        .map(|pipeline| pipeline.expect("Compiled pipelines are always created"))
+}
+
+/// Create a graphics pipeline, or return `None` if `mode` is
+/// [`PipelineCompileMode::CacheOnly`] and it would have to be compiled.
+pub fn create_pipeline_with_mode<V: Vertex>(
+    context: &Arc<Context>,
+    params: PipelineParameters,
+    mode: PipelineCompileMode,
+) -> Result<Option<vk::Pipeline>, VksError> {
+    if mode == PipelineCompileMode::CacheOnly && !context.has_pipeline_cache_control() {
+        return Ok(None);
+    }
+
     let entry_point_name = CString::new("main").unwrap();
 
     let (_vertex_shader_module, vertex_shader_state_info) = create_shader_stage_info(
@@ -30,14 +58,14 @@ pub fn create_pipeline<V: Vertex>(
         &entry_point_name,
         vk::ShaderStageFlags::VERTEX,
         params.vertex_shader_params,
-    );
+    )?;
 
     let (_fragment_shader_module, fragment_shader_state_info) = create_shader_stage_info(
         context,
         &entry_point_name,
         vk::ShaderStageFlags::FRAGMENT,
         params.fragment_shader_params,
-    );
+    )?;
 
     let shader_states_infos = [vertex_shader_state_info, fragment_shader_state_info];
 
@@ -48,34 +76,67 @@ pub fn create_pipeline<V: Vertex>(
         .vertex_attribute_descriptions(&attributes_descs);
 
     let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(params.topology)
         .primitive_restart_enable(false);
 
+    let rasterizer_info = supported_rasterization_state(context, params.rasterizer_info);
+
     let color_blending_info = vk::PipelineColorBlendStateCreateInfo::default()
         .logic_op_enable(false)
         .logic_op(vk::LogicOp::COPY)
         .attachments(params.color_blend_attachments)
         .blend_constants([0.0, 0.0, 0.0, 0.0]);
 
+    let depth_format = params.depth_attachment_format.unwrap_or_default();
+    let stencil_format = match params.stencil {
+        Some(_) if has_stencil_component(depth_format) => depth_format,
+        Some(_) => {
+            tracing::warn!(
+                "Stencil state set but depth format {:?} has no stencil component",
+                depth_format
+            );
+            vk::Format::UNDEFINED
+        }
+        None => vk::Format::UNDEFINED,
+    };
     let mut dynamic_rendering = vk::PipelineRenderingCreateInfo::default()
         .color_attachment_formats(params.color_attachment_formats)
-        .depth_attachment_format(params.depth_attachment_format.unwrap_or_default());
+        .depth_attachment_format(depth_format)
+        .stencil_attachment_format(stencil_format);
+
+    let stencil_depth_stencil_info = params.stencil.map(|stencil| {
+        params
+            .depth_stencil_info
+            .copied()
+            .unwrap_or_default()
+            .stencil_test_enable(true)
+            .front(stencil.front)
+            .back(stencil.back)
+    });
+    let depth_stencil_info = stencil_depth_stencil_info
+        .as_ref()
+        .or(params.depth_stencil_info);
 
     let mut pipeline_info = vk::GraphicsPipelineCreateInfo::default()
         .stages(&shader_states_infos)
         .vertex_input_state(&vertex_input_info)
         .input_assembly_state(&input_assembly_info)
         .viewport_state(params.viewport_info)
-        .rasterization_state(params.rasterizer_info)
+        .rasterization_state(&rasterizer_info)
         .multisample_state(params.multisampling_info)
         .color_blend_state(&color_blending_info)
         .layout(params.layout)
         .push_next(&mut dynamic_rendering);
 
-    if let Some(depth_stencil_info) = params.depth_stencil_info {
+    if let Some(depth_stencil_info) = depth_stencil_info {
         pipeline_info = pipeline_info.depth_stencil_state(depth_stencil_info)
     }
 
+    let mut shading_rate_info = params.shading_rate.map(|state| state.create_info());
+    if let Some(shading_rate_info) = shading_rate_info.as_mut() {
+        pipeline_info = pipeline_info.push_next(shading_rate_info);
+    }
+
     if let Some(dynamic_state_info) = params.dynamic_state_info {
         pipeline_info = pipeline_info.dynamic_state(dynamic_state_info);
     }
@@ -84,18 +145,120 @@ pub fn create_pipeline<V: Vertex>(
         pipeline_info = pipeline_info.base_pipeline_handle(parent);
     }
 
+    let mut flags = vk::PipelineCreateFlags::empty();
     if params.allow_derivatives {
-        pipeline_info = pipeline_info.flags(vk::PipelineCreateFlags::ALLOW_DERIVATIVES);
+        flags |= vk::PipelineCreateFlags::ALLOW_DERIVATIVES;
     }
+    if params.shading_rate_attachment {
+        flags |= vk::PipelineCreateFlags::RENDERING_FRAGMENT_SHADING_RATE_ATTACHMENT_KHR;
+    }
+    if mode == PipelineCompileMode::CacheOnly {
+        flags |= vk::PipelineCreateFlags::FAIL_ON_PIPELINE_COMPILE_REQUIRED;
+    }
+    pipeline_info = pipeline_info.flags(flags);
 
     let pipeline_infos = [pipeline_info];
 
-    unsafe {
+    let result = unsafe {
         context
             .device()
-            .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None)
-            .expect("Failed to create graphics pipeline")[0]
+            .create_graphics_pipelines(context.pipeline_cache(), &pipeline_infos, None)
+    };
+    match result {
+        Ok(pipelines) => {
+            tracing::debug!(
+                target: RESOURCES_TRACING_TARGET,
+                handle = ?pipelines[0],
+                vertex_shader = params.vertex_shader_params.name,
+                fragment_shader = params.fragment_shader_params.name,
+                "Graphics pipeline created"
+            );
+            Ok(Some(pipelines[0]))
+        }
+        Err((_, vk::Result::PIPELINE_COMPILE_REQUIRED)) => Ok(None),
+        Err((_, error)) => Err(error.into()),
+    }
+}
+
+/// Return a copy of `rasterizer_info` the device can use.
+///
+/// Without the `wideLines` feature the line width falls back to 1, otherwise it is
+/// clamped to the range of the device. Without `fillModeNonSolid` wireframe and point
+/// polygon modes fall back to fill.
+fn supported_rasterization_state<'a>(
+    context: &Context,
+    rasterizer_info: &vk::PipelineRasterizationStateCreateInfo<'a>,
+) -> vk::PipelineRasterizationStateCreateInfo<'a> {
+    let features = context.enabled_device_features().features;
+    let mut info = *rasterizer_info;
+
+    let line_width = if features.wide_lines == vk::TRUE {
+        let [min, max] = context.physical_device_limits().line_width_range;
+        info.line_width.clamp(min, max)
+    } else {
+        1.0
+    };
+    if line_width != info.line_width {
+        tracing::warn!(
+            "Line width {} is not supported, using {}",
+            info.line_width,
+            line_width
+        );
+        info.line_width = line_width;
     }
+
+    if info.polygon_mode != vk::PolygonMode::FILL && features.fill_mode_non_solid != vk::TRUE {
+        tracing::warn!(
+            "Polygon mode {:?} is not supported, using fill",
+            info.polygon_mode
+        );
+        info.polygon_mode = vk::PolygonMode::FILL;
+    }
+
+    info
+}
+
+#[derive(Copy, Clone)]
+pub struct ComputePipelineParameters<'a> {
+    pub shader_params: ShaderParameters<'a>,
+    pub layout: vk::PipelineLayout,
+}
+
+/// Create a compute pipeline from the shader `shader/<name>/<name>.comp.spv`.
+pub fn create_compute_pipeline(
+    context: &Arc<Context>,
+    params: ComputePipelineParameters,
+) -> vk::Pipeline {
+    let entry_point_name = CString::new("main").unwrap();
+    let (_shader_module, stage_info) = create_shader_stage_info(
+        context,
+        &entry_point_name,
+        vk::ShaderStageFlags::COMPUTE,
+        params.shader_params,
+    )
+    .unwrap_or_else(|error| panic!("Failed to create compute pipeline: {}", error));
+
+    let pipeline_info = vk::ComputePipelineCreateInfo::default()
+        .stage(stage_info)
+        .layout(params.layout);
+
+    let pipeline = unsafe {
+        context
+            .device()
+            .create_compute_pipelines(
+                context.pipeline_cache(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .expect("Failed to create compute pipeline")[0]
+    };
+    tracing::debug!(
+        target: RESOURCES_TRACING_TARGET,
+        handle = ?pipeline,
+        shader = params.shader_params.name,
+        "Compute pipeline created"
+    );
+    pipeline
 }
 
 fn create_shader_stage_info<'a>(
@@ -103,10 +266,9 @@ fn create_shader_stage_info<'a>(
     entry_point_name: &'a CString,
     stage: vk::ShaderStageFlags,
     params: ShaderParameters<'a>,
-) -> (ShaderModule, vk::PipelineShaderStageCreateInfo<'a>) {
+) -> Result<(ShaderModule, vk::PipelineShaderStageCreateInfo<'a>), VksError> {
     let extension = get_shader_file_extension(stage);
-    let shader_path = format!("shader/{}/{}.{}.spv",params.name, params.name, extension);
-    let module = ShaderModule::new(Arc::clone(context), shader_path);
+    let module = ShaderModule::new(Arc::clone(context), shader_path(params.name, extension))?;
 
     let mut stage_info = vk::PipelineShaderStageCreateInfo::default()
         .stage(stage)
@@ -116,13 +278,19 @@ fn create_shader_stage_info<'a>(
         stage_info = stage_info.specialization_info(specialization);
     }
 
-    (module, stage_info)
+    Ok((module, stage_info))
+}
+
+/// Path of the compiled stage `extension` of the shader `name`.
+pub(crate) fn shader_path(name: &str, extension: &str) -> String {
+    format!("shader/{}/{}.{}.spv", name, name, extension)
 }
 
 fn get_shader_file_extension(stage: vk::ShaderStageFlags) -> &'static str {
     match stage {
         vk::ShaderStageFlags::VERTEX => "vert",
         vk::ShaderStageFlags::FRAGMENT => "frag",
+        vk::ShaderStageFlags::COMPUTE => "comp",
         _ => panic!("Unsupported shader stage"),
     }
 }
@@ -148,3 +316,66 @@ impl<'a> ShaderParameters<'a> {
         }
     }
 }
+
+/// Stencil operations of the front and back faces.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StencilState {
+    pub front: vk::StencilOpState,
+    pub back: vk::StencilOpState,
+}
+
+impl StencilState {
+    /// Same operations for both faces.
+    pub fn both(op_state: vk::StencilOpState) -> Self {
+        Self {
+            front: op_state,
+            back: op_state,
+        }
+    }
+
+    /// Write `reference` where the geometry is drawn, to build a mask.
+    pub fn write_mask(reference: u32) -> Self {
+        Self::both(
+            vk::StencilOpState::default()
+                .fail_op(vk::StencilOp::KEEP)
+                .pass_op(vk::StencilOp::REPLACE)
+                .depth_fail_op(vk::StencilOp::KEEP)
+                .compare_op(vk::CompareOp::ALWAYS)
+                .compare_mask(0xff)
+                .write_mask(0xff)
+                .reference(reference),
+        )
+    }
+
+    /// Only draw where the mask is `reference`, decals restricted
+    /// to the surfaces that wrote the mask for example.
+    pub fn test_equal(reference: u32) -> Self {
+        Self::both(
+            vk::StencilOpState::default()
+                .fail_op(vk::StencilOp::KEEP)
+                .pass_op(vk::StencilOp::KEEP)
+                .depth_fail_op(vk::StencilOp::KEEP)
+                .compare_op(vk::CompareOp::EQUAL)
+                .compare_mask(0xff)
+                .write_mask(0)
+                .reference(reference),
+        )
+    }
+
+    /// Only draw where the mask is not `reference`.
+    ///
+    /// Used for outlines: the object writes its mask with [`StencilState::write_mask`]
+    /// then a slightly scaled copy is drawn with this state so only the border remains.
+    pub fn outline(reference: u32) -> Self {
+        Self::both(
+            vk::StencilOpState::default()
+                .fail_op(vk::StencilOp::KEEP)
+                .pass_op(vk::StencilOp::KEEP)
+                .depth_fail_op(vk::StencilOp::KEEP)
+                .compare_op(vk::CompareOp::NOT_EQUAL)
+                .compare_mask(0xff)
+                .write_mask(0)
+                .reference(reference),
+        )
+    }
+}
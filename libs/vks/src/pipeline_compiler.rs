@@ -0,0 +1,141 @@
+use ash::{ext, khr, vk, Entry, Instance};
+use std::{
+    ffi::CStr,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+type CompileJob = Box<dyn FnOnce() + Send>;
+
+/// How a pipeline creation may behave if the pipeline is not in the cache of the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineCompileMode {
+    /// Compile the pipeline, blocking until done.
+    Compile,
+    /// Fail instead of compiling, using VK_EXT_pipeline_creation_cache_control.
+    ///
+    /// Always fails if the extension is not supported.
+    CacheOnly,
+}
+
+/// Thread pool compiling pipelines in the background.
+///
+/// Dropping it waits for the compilations in progress.
+pub struct PipelineCompiler {
+    sender: Option<Sender<CompileJob>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PipelineCompiler {
+    pub fn new(thread_count: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<CompileJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..thread_count.max(1))
+            .map(|index| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("pipeline-compiler-{}", index))
+                    .spawn(move || loop {
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn pipeline compiler thread")
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Run `create` on one of the threads of the pool.
+    pub fn compile<F>(&self, create: F) -> PendingPipeline
+    where
+        F: FnOnce() -> vk::Pipeline + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job = Box::new(move || {
+            let _ = sender.send(create());
+        });
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .expect("Failed to queue pipeline compilation");
+        PendingPipeline { receiver }
+    }
+}
+
+/// Half the cores, leaving the others to the render and main threads.
+impl Default for PipelineCompiler {
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map_or(2, |count| count.get());
+        Self::new((cores / 2).clamp(1, 4))
+    }
+}
+
+impl Drop for PipelineCompiler {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A pipeline being compiled by a [`PipelineCompiler`].
+pub struct PendingPipeline {
+    receiver: Receiver<vk::Pipeline>,
+}
+
+impl PendingPipeline {
+    /// Return the pipeline if it is ready, without blocking.
+    pub fn try_take(&self) -> Option<vk::Pipeline> {
+        match self.receiver.try_recv() {
+            Ok(pipeline) => Some(pipeline),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Pipeline compilation failed"),
+        }
+    }
+
+    /// Block until the pipeline is compiled.
+    pub fn wait(self) -> vk::Pipeline {
+        self.receiver.recv().expect("Pipeline compilation failed")
+    }
+}
+
+pub(crate) fn query_pipeline_cache_control_support(
+    entry: &Entry,
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let extension_props = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .expect("Failed to enumerate device extention properties")
+    };
+    let has_extension = extension_props.iter().any(|props| {
+        let ext_name = unsafe { CStr::from_ptr(props.extension_name.as_ptr()) };
+        ext_name == ext::pipeline_creation_cache_control::NAME
+    });
+    if !has_extension {
+        return false;
+    }
+
+    let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+    let mut cache_control_features =
+        vk::PhysicalDevicePipelineCreationCacheControlFeatures::default();
+    let mut features =
+        vk::PhysicalDeviceFeatures2::default().push_next(&mut cache_control_features);
+    unsafe { properties2.get_physical_device_features2(physical_device, &mut features) };
+
+    cache_control_features.pipeline_creation_cache_control == vk::TRUE
+}
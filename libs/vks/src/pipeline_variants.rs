@@ -0,0 +1,242 @@
+use crate::{
+    Context, PendingPipeline, PipelineCompileMode, PipelineCompiler, RESOURCES_TRACING_TARGET,
+};
+use ash::vk;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    mem::size_of,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 32 bits specialization constants, including booleans.
+#[derive(Clone, Debug, Default)]
+pub struct SpecializationConstants {
+    data: Vec<u8>,
+    entries: Vec<vk::SpecializationMapEntry>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_u32(&mut self, constant_id: u32, value: u32) {
+        self.entries.push(vk::SpecializationMapEntry {
+            constant_id,
+            offset: self.data.len() as _,
+            size: size_of::<u32>(),
+        });
+        self.data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    pub fn add_bool(&mut self, constant_id: u32, value: bool) {
+        self.add_u32(constant_id, if value { vk::TRUE } else { vk::FALSE });
+    }
+
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.entries)
+            .data(&self.data)
+    }
+}
+
+/// Outcome of [`PipelineVariantCache::prewarm`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrewarmReport {
+    /// Number of variants requested, without duplicates.
+    pub variants: usize,
+    /// Number of variants created, the others were already cached.
+    pub created: usize,
+    pub total_time: Duration,
+    /// Longest creation of a single variant.
+    pub slowest: Duration,
+}
+
+impl fmt::Display for PrewarmReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} variant(s), {} created in {:.1} ms, slowest {:.1} ms",
+            self.variants,
+            self.created,
+            self.total_time.as_secs_f64() * 1000.0,
+            self.slowest.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Pipelines created on demand for each variant of a shader.
+///
+/// A variant is usually selected by the specialization constants the key maps
+/// to, like the features of a material for the uber shader. The pipelines are
+/// destroyed with the cache or by [`PipelineVariantCache::clear`].
+///
+/// Variants can also be compiled in the background with
+/// [`PipelineVariantCache::get_or_compile`] so a new one never hitches a frame.
+pub struct PipelineVariantCache<K> {
+    context: Arc<Context>,
+    pipelines: HashMap<K, vk::Pipeline>,
+    pending: HashMap<K, PendingPipeline>,
+}
+
+impl<K: Eq + Hash + Copy> PipelineVariantCache<K> {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            pipelines: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Copy> PipelineVariantCache<K> {
+    /// Return the pipeline of `key`, creating it with `create` the first time.
+    pub fn get_or_create<F: FnOnce(K) -> vk::Pipeline>(
+        &mut self,
+        key: K,
+        create: F,
+    ) -> vk::Pipeline {
+        let cached = self.pipelines.len();
+        *self.pipelines.entry(key).or_insert_with(|| {
+            tracing::debug!("Creating pipeline variant, {} already cached", cached);
+            create(key)
+        })
+    }
+
+    /// Return the pipeline of `key`, or `fallback` while it compiles on `compiler`.
+    ///
+    /// `create` is first called on this thread with [`PipelineCompileMode::CacheOnly`]
+    /// so pipelines already in the cache of the driver are used right away. If it
+    /// returns `None` it is called again on the compiler with [`PipelineCompileMode::Compile`].
+    pub fn get_or_compile<F>(
+        &mut self,
+        compiler: &PipelineCompiler,
+        key: K,
+        fallback: vk::Pipeline,
+        create: F,
+    ) -> vk::Pipeline
+    where
+        K: Send + 'static,
+        F: Fn(K, PipelineCompileMode) -> Option<vk::Pipeline> + Send + 'static,
+    {
+        if let Some(pipeline) = self.get(&key) {
+            return pipeline;
+        }
+
+        if let Some(pending) = self.pending.get(&key) {
+            return match pending.try_take() {
+                Some(pipeline) => {
+                    self.pending.remove(&key);
+                    self.pipelines.insert(key, pipeline);
+                    pipeline
+                }
+                None => fallback,
+            };
+        }
+
+        if let Some(pipeline) = create(key, PipelineCompileMode::CacheOnly) {
+            self.pipelines.insert(key, pipeline);
+            return pipeline;
+        }
+
+        tracing::debug!(
+            "Compiling pipeline variant in the background, {} in progress",
+            self.pending.len()
+        );
+        let pending = compiler.compile(move || {
+            create(key, PipelineCompileMode::Compile).expect("Failed to compile pipeline variant")
+        });
+        self.pending.insert(key, pending);
+        fallback
+    }
+
+    /// Create the pipelines of all `keys` up front with `create`, so none of them
+    /// hitches the frame first using it.
+    ///
+    /// Variants compiling in the background are waited for. The creations also
+    /// fill the pipeline cache of the context.
+    pub fn prewarm<I, F>(&mut self, keys: I, mut create: F) -> PrewarmReport
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(K) -> vk::Pipeline,
+    {
+        let start = Instant::now();
+        let mut report = PrewarmReport::default();
+        let mut seen = Vec::new();
+        for key in keys {
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            report.variants += 1;
+
+            if let Some(pending) = self.pending.remove(&key) {
+                self.pipelines.insert(key, pending.wait());
+            }
+            if self.pipelines.contains_key(&key) {
+                continue;
+            }
+
+            let creation_start = Instant::now();
+            self.pipelines.insert(key, create(key));
+            report.created += 1;
+            report.slowest = report.slowest.max(creation_start.elapsed());
+        }
+        report.total_time = start.elapsed();
+        report
+    }
+
+    /// True if some variants are still compiling.
+    pub fn is_compiling(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<vk::Pipeline> {
+        self.pipelines.get(key).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Destroy all the pipelines, when the attachment formats change for example.
+    ///
+    /// None of them must still be in use by the gpu. Waits for the ones compiling.
+    pub fn clear(&mut self) {
+        for (_, pending) in self.pending.drain() {
+            let pipeline = pending.wait();
+            destroy_pipeline(&self.context, pipeline);
+        }
+        for (_, pipeline) in self.pipelines.drain() {
+            destroy_pipeline(&self.context, pipeline);
+        }
+    }
+}
+
+impl<K> Drop for PipelineVariantCache<K> {
+    fn drop(&mut self) {
+        for (_, pending) in self.pending.drain() {
+            let pipeline = pending.wait();
+            destroy_pipeline(&self.context, pipeline);
+        }
+        for (_, pipeline) in self.pipelines.drain() {
+            destroy_pipeline(&self.context, pipeline);
+        }
+    }
+}
+
+fn destroy_pipeline(context: &Context, pipeline: vk::Pipeline) {
+    unsafe { context.device().destroy_pipeline(pipeline, None) };
+    tracing::debug!(
+        target: RESOURCES_TRACING_TARGET,
+        handle = ?pipeline,
+        "Pipeline variant destroyed"
+    );
+}
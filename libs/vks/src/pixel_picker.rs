@@ -0,0 +1,194 @@
+use crate::{DrawDebugId, Image, NO_DEBUG_INDEX};
+use ash::vk;
+use std::fmt;
+
+/// Render targets the pixel picker can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickTarget {
+    Color,
+    Depth,
+    Normals,
+    /// Attachment where each draw writes its [`DrawDebugId`], as `R32G32_UINT`.
+    ObjectId,
+}
+
+impl PickTarget {
+    pub fn all() -> [Self; 4] {
+        [Self::Color, Self::Depth, Self::Normals, Self::ObjectId]
+    }
+}
+
+impl fmt::Display for PickTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Color => write!(f, "Color"),
+            Self::Depth => write!(f, "Depth"),
+            Self::Normals => write!(f, "Normals"),
+            Self::ObjectId => write!(f, "Object id"),
+        }
+    }
+}
+
+/// Decoded value of a picked pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelValue {
+    Color([f32; 4]),
+    Depth(f32),
+    Normal([f32; 3]),
+    ObjectId(DrawDebugId),
+}
+
+impl fmt::Display for PixelValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Color([r, g, b, a]) => {
+                write!(f, "rgba({:.4}, {:.4}, {:.4}, {:.4})", r, g, b, a)
+            }
+            Self::Depth(depth) => write!(f, "depth {:.6}", depth),
+            Self::Normal([x, y, z]) => write!(f, "normal({:.3}, {:.3}, {:.3})", x, y, z),
+            Self::ObjectId(id) => write!(f, "{}", id.label()),
+        }
+    }
+}
+
+/// A pixel read back by [`pick_pixel`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickedPixel {
+    pub target: PickTarget,
+    pub position: [u32; 2],
+    pub value: PixelValue,
+}
+
+impl fmt::Display for PickedPixel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at ({}, {}): {}",
+            self.target, self.position[0], self.position[1], self.value
+        )
+    }
+}
+
+/// Read back the pixel at `position` of `image`, the render target `target`.
+///
+/// `image` must be in `layout` and is left in it, see [`Image::read_back_texel`].
+/// Returns `None` if the position is outside of the image or its format can't
+/// be decoded. Waits for the device, so only call it when the user asks for it.
+pub fn pick_pixel(
+    image: &Image,
+    layout: vk::ImageLayout,
+    target: PickTarget,
+    position: [u32; 2],
+) -> Option<PickedPixel> {
+    let [x, y] = position;
+    if x >= image.extent.width || y >= image.extent.height {
+        return None;
+    }
+
+    let texel_size = texel_size(image.format)?;
+    let texel = image.read_back_texel(layout, x, y, texel_size);
+
+    let value = match target {
+        PickTarget::ObjectId => {
+            if image.format != vk::Format::R32G32_UINT {
+                return None;
+            }
+            let node_index = u32::from_ne_bytes(texel[0..4].try_into().unwrap());
+            let material_index = u32::from_ne_bytes(texel[4..8].try_into().unwrap());
+            let index = |index| (index != NO_DEBUG_INDEX).then_some(index as usize);
+            PixelValue::ObjectId(DrawDebugId::new(index(node_index), index(material_index)))
+        }
+        PickTarget::Depth => PixelValue::Depth(decode_texel(image.format, &texel)?[0]),
+        PickTarget::Normals => {
+            let [x, y, z, _] = decode_texel(image.format, &texel)?;
+            PixelValue::Normal([x, y, z])
+        }
+        PickTarget::Color => PixelValue::Color(decode_texel(image.format, &texel)?),
+    };
+
+    Some(PickedPixel {
+        target,
+        position,
+        value,
+    })
+}
+
+/// Size of a texel of `format` as copied to a buffer, only the depth of depth stencil formats.
+pub(crate) fn texel_size(format: vk::Format) -> Option<u32> {
+    let size = match format {
+        vk::Format::D16_UNORM => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT
+        | vk::Format::D32_SFLOAT_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::X8_D24_UNORM_PACK32 => 4,
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_UINT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    };
+    Some(size)
+}
+
+/// Decode a texel to rgba, depth is returned in red. sRGB values are not linearized.
+fn decode_texel(format: vk::Format, texel: &[u8]) -> Option<[f32; 4]> {
+    let unorm8 = |index: usize| texel[index] as f32 / 255.0;
+    let u32_at =
+        |index: usize| u32::from_ne_bytes(texel[index * 4..index * 4 + 4].try_into().unwrap());
+    let f32_at = |index: usize| f32::from_bits(u32_at(index));
+
+    let value = match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => {
+            [unorm8(0), unorm8(1), unorm8(2), unorm8(3)]
+        }
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+            [unorm8(2), unorm8(1), unorm8(0), unorm8(3)]
+        }
+        vk::Format::A2B10G10R10_UNORM_PACK32 => {
+            let packed = u32_at(0);
+            let channel = |shift: u32, max: u32| ((packed >> shift) & max) as f32 / max as f32;
+            [
+                channel(0, 0x3ff),
+                channel(10, 0x3ff),
+                channel(20, 0x3ff),
+                channel(30, 0x3),
+            ]
+        }
+        vk::Format::R16G16B16A16_SFLOAT => {
+            let half = |index: usize| {
+                f16_to_f32(u16::from_ne_bytes([texel[index * 2], texel[index * 2 + 1]]))
+            };
+            [half(0), half(1), half(2), half(3)]
+        }
+        vk::Format::R32G32B32A32_SFLOAT => [f32_at(0), f32_at(1), f32_at(2), f32_at(3)],
+        vk::Format::R32_SFLOAT | vk::Format::D32_SFLOAT | vk::Format::D32_SFLOAT_S8_UINT => {
+            [f32_at(0), 0.0, 0.0, 1.0]
+        }
+        vk::Format::D16_UNORM => {
+            let depth = u16::from_ne_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32;
+            [depth, 0.0, 0.0, 1.0]
+        }
+        vk::Format::D24_UNORM_S8_UINT | vk::Format::X8_D24_UNORM_PACK32 => {
+            let depth = (u32_at(0) & 0xff_ffff) as f32 / 0xff_ffff as f32;
+            [depth, 0.0, 0.0, 1.0]
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
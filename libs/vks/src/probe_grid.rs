@@ -0,0 +1,603 @@
+use crate::{
+    compute_pass::{check_write_without_format, cmd_dispatch_groups, create_pipeline_layout},
+    create_compute_pipeline, create_pipeline, create_sampler,
+    panorama::cube_face_ubos,
+    Camera, CameraUBO, ComputePipelineParameters, Context, Descriptors, Image, ImageParameters,
+    OffscreenTarget, PipelineParameters, ShaderParameters, Texture,
+};
+use ash::vk;
+use math::cgmath::{Matrix4, Point3, Vector3};
+use std::{mem::size_of, sync::Arc};
+use util::any_as_u8_slice;
+
+/// Format of the ambient cubes, keeping the dynamic range of the scene.
+const PROBE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Faces of an ambient cube, stored side by side along x.
+const AMBIENT_CUBE_FACES: u32 = 6;
+/// Size of the cubes drawn for each probe, relative to the spacing of the grid.
+const DEBUG_PROBE_SCALE: f32 = 0.1;
+
+/// Placement and update rate of a [`ProbeGrid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeGridSettings {
+    /// Corners of the box covered by the grid, the outer probes lie on its faces.
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    /// Probes along each axis.
+    pub counts: [u32; 3],
+    /// Size in pixels of the faces rendered from each probe.
+    pub face_size: u32,
+    /// Probes updated by each call to [`ProbeGrid::update`].
+    pub probes_per_update: u32,
+}
+
+impl Default for ProbeGridSettings {
+    fn default() -> Self {
+        Self {
+            min: Point3::new(-4.0, 0.0, -4.0),
+            max: Point3::new(4.0, 4.0, 4.0),
+            counts: [4, 2, 4],
+            face_size: 16,
+            probes_per_update: 1,
+        }
+    }
+}
+
+/// Grid parameters read by the shaders sampling the probes, the
+/// `ProbeGridParams` of `shader/probe_grid/probe_grid.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeGridUniform {
+    /// xyz: position of the first probe.
+    pub origin: [f32; 4],
+    /// xyz: distance between two neighbour probes.
+    pub spacing: [f32; 4],
+    pub counts: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ProbeDebugConstants {
+    view_proj: [[f32; 4]; 4],
+    grid: ProbeGridUniform,
+}
+
+/// Grid of irradiance probes lighting the dynamic objects, so they pick up
+/// the local lighting of the scene instead of only the environment map.
+///
+/// Each probe is stored as an ambient cube, the irradiance along the six axes,
+/// in a 2D array texture: the texels x * 6 + face of row y in layer z belong
+/// to the probe (x, y, z). Shaders sample it with `sampleProbeGrid` of
+/// `shader/probe_grid/probe_grid.glsl`, the texture being in
+/// `SHADER_READ_ONLY_OPTIMAL` layout outside of updates.
+///
+/// Probes are updated by rendering the scene around them to
+/// [`ProbeGrid::face_target`] and averaging each face. [`ProbeGrid::bake`]
+/// updates all of them while [`ProbeGrid::update`] amortizes the updates over
+/// frames. Probes not updated yet are black.
+pub struct ProbeGrid {
+    context: Arc<Context>,
+    settings: ProbeGridSettings,
+    face_target: OffscreenTarget,
+    probes: Texture,
+    next_probe: u32,
+    update_descriptors: Descriptors,
+    update_pipeline_layout: vk::PipelineLayout,
+    update_pipeline: vk::Pipeline,
+    debug_descriptors: Descriptors,
+    debug_pipeline_layout: vk::PipelineLayout,
+    debug_pipeline: vk::Pipeline,
+}
+
+impl ProbeGrid {
+    /// Create a grid whose faces are rendered in attachments of `color_format`
+    /// and `depth_format` with `samples`, the attachments the probes are drawn
+    /// to as well. The probes are drawn in passes with a fragment shading rate
+    /// attachment if `shading_rate_attachment`.
+    pub fn new(
+        context: &Arc<Context>,
+        settings: ProbeGridSettings,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
+        shading_rate_attachment: bool,
+    ) -> Self {
+        check_write_without_format(context);
+        assert!(
+            settings.counts.iter().all(|count| *count > 0),
+            "Probe grids need at least a probe along each axis"
+        );
+
+        let face_target = OffscreenTarget::new(
+            context,
+            vk::Extent2D {
+                width: settings.face_size,
+                height: settings.face_size,
+            },
+            color_format,
+            depth_format,
+            samples,
+        );
+        let probes = create_probes(context, settings.counts);
+
+        let update_descriptors =
+            crate::compute_pass::create_descriptors(context, &[(face_target.color(), probes.view)]);
+        let update_pipeline_layout = create_pipeline_layout(context, update_descriptors.layout());
+        let update_pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::new("ambient_cube"),
+                layout: update_pipeline_layout,
+            },
+        );
+
+        let debug_descriptors = create_debug_descriptors(context, &probes);
+        let (debug_pipeline_layout, debug_pipeline) = create_debug_pipeline(
+            context,
+            debug_descriptors.layout(),
+            color_format,
+            depth_format,
+            samples,
+            shading_rate_attachment,
+        );
+
+        Self {
+            context: Arc::clone(context),
+            settings,
+            face_target,
+            probes,
+            next_probe: 0,
+            update_descriptors,
+            update_pipeline_layout,
+            update_pipeline,
+            debug_descriptors,
+            debug_pipeline_layout,
+            debug_pipeline,
+        }
+    }
+}
+
+impl ProbeGrid {
+    pub fn settings(&self) -> ProbeGridSettings {
+        self.settings
+    }
+
+    pub fn probe_count(&self) -> u32 {
+        self.settings.counts.iter().product()
+    }
+
+    /// Ambient cubes of the probes, see [`ProbeGrid`] for the layout.
+    pub fn probes(&self) -> &Texture {
+        &self.probes
+    }
+
+    /// Target each face of a probe must be rendered to.
+    pub fn face_target(&self) -> &OffscreenTarget {
+        &self.face_target
+    }
+
+    pub fn uniform(&self) -> ProbeGridUniform {
+        let (origin, spacing) = self.origin_and_spacing();
+        let [x, y, z] = self.settings.counts;
+        ProbeGridUniform {
+            origin: [origin.x, origin.y, origin.z, 0.0],
+            spacing: [spacing.x, spacing.y, spacing.z, 0.0],
+            counts: [x, y, z, 0],
+        }
+    }
+
+    /// Position of the probe at `index`, x varying first then y and z.
+    pub fn probe_position(&self, index: u32) -> Point3<f32> {
+        let [x, y, z] = self.probe_coordinates(index);
+        let (origin, spacing) = self.origin_and_spacing();
+        origin
+            + Vector3::new(
+                x as f32 * spacing.x,
+                y as f32 * spacing.y,
+                z as f32 * spacing.z,
+            )
+    }
+
+    /// Update the next [`ProbeGridSettings::probes_per_update`] probes, in turn.
+    ///
+    /// `render_face` is called for each face with the face target and the
+    /// camera uniforms of the face, and must render the scene to the target and
+    /// wait for it. The faces keep the clip planes of `camera`. Stalls the queue.
+    pub fn update<F>(&mut self, camera: &Camera, mut render_face: F)
+    where
+        F: FnMut(&OffscreenTarget, CameraUBO),
+    {
+        let count = self.settings.probes_per_update.min(self.probe_count());
+        for _ in 0..count {
+            self.update_probe(self.next_probe, camera, &mut render_face);
+            self.next_probe = (self.next_probe + 1) % self.probe_count();
+        }
+    }
+
+    /// Update all the probes, see [`ProbeGrid::update`].
+    pub fn bake<F>(&mut self, camera: &Camera, mut render_face: F)
+    where
+        F: FnMut(&OffscreenTarget, CameraUBO),
+    {
+        for index in 0..self.probe_count() {
+            self.update_probe(index, camera, &mut render_face);
+        }
+        self.next_probe = 0;
+    }
+
+    /// Record the draw of a small cube at each probe showing its ambient cube.
+    ///
+    /// Must be recorded inside an active dynamic rendering whose attachments
+    /// have the formats passed at creation, with its viewport and scissor set.
+    pub fn cmd_draw_probes(&self, command_buffer: vk::CommandBuffer, view_proj: Matrix4<f32>) {
+        let device = self.context.device();
+
+        let mut grid = self.uniform();
+        let min_spacing = grid.spacing[..3].iter().copied().fold(f32::MAX, f32::min);
+        grid.origin[3] = min_spacing * DEBUG_PROBE_SCALE;
+        let constants = ProbeDebugConstants {
+            view_proj: view_proj.into(),
+            grid,
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.debug_pipeline,
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.debug_pipeline_layout,
+                0,
+                self.debug_descriptors.sets(),
+                &[],
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                self.debug_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                any_as_u8_slice(&constants),
+            );
+
+            device.cmd_draw(
+                command_buffer,
+                AMBIENT_CUBE_FACES * 6,
+                self.probe_count(),
+                0,
+                0,
+            );
+        }
+    }
+
+    fn probe_coordinates(&self, index: u32) -> [u32; 3] {
+        let [x, y, _] = self.settings.counts;
+        [index % x, (index / x) % y, index / (x * y)]
+    }
+
+    /// Position of the first probe and distance between the probes.
+    ///
+    /// Axes with a single probe have it in the middle of the box.
+    fn origin_and_spacing(&self) -> (Point3<f32>, Vector3<f32>) {
+        let ProbeGridSettings {
+            min, max, counts, ..
+        } = self.settings;
+        let axis = |min: f32, max: f32, count: u32| match count {
+            1 => ((min + max) * 0.5, 1.0),
+            _ => (min, (max - min) / (count - 1) as f32),
+        };
+        let (x, spacing_x) = axis(min.x, max.x, counts[0]);
+        let (y, spacing_y) = axis(min.y, max.y, counts[1]);
+        let (z, spacing_z) = axis(min.z, max.z, counts[2]);
+        (
+            Point3::new(x, y, z),
+            Vector3::new(spacing_x, spacing_y, spacing_z),
+        )
+    }
+
+    fn update_probe<F>(&self, index: u32, camera: &Camera, render_face: &mut F)
+    where
+        F: FnMut(&OffscreenTarget, CameraUBO),
+    {
+        let [x, y, z] = self.probe_coordinates(index);
+        let position = self.probe_position(index);
+
+        for (face, ubo) in cube_face_ubos(position, camera).into_iter().enumerate() {
+            render_face(&self.face_target, ubo);
+
+            let parameters = [x * AMBIENT_CUBE_FACES + face as u32, y, z, 0];
+            self.context.execute_one_time_commands(|command_buffer| {
+                unsafe {
+                    self.context.device().cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        self.update_pipeline,
+                    )
+                };
+                self.cmd_probes_barrier(
+                    command_buffer,
+                    (
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                        vk::AccessFlags2::SHADER_READ,
+                    ),
+                    (
+                        vk::PipelineStageFlags2::COMPUTE_SHADER,
+                        vk::AccessFlags2::SHADER_WRITE,
+                    ),
+                );
+                cmd_dispatch_groups(
+                    &self.context,
+                    command_buffer,
+                    self.update_pipeline_layout,
+                    self.update_descriptors.sets()[0],
+                    &parameters,
+                    (1, 1),
+                );
+                self.cmd_probes_barrier(
+                    command_buffer,
+                    (
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags2::COMPUTE_SHADER,
+                        vk::AccessFlags2::SHADER_WRITE,
+                    ),
+                    (
+                        vk::PipelineStageFlags2::VERTEX_SHADER
+                            | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                        vk::AccessFlags2::SHADER_READ,
+                    ),
+                );
+            });
+        }
+    }
+
+    /// Barrier on all the layers of the probes, keeping their content.
+    fn cmd_probes_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layouts: (vk::ImageLayout, vk::ImageLayout),
+        src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) {
+        let barrier = vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(src.0)
+            .src_access_mask(src.1)
+            .old_layout(layouts.0)
+            .dst_stage_mask(dst.0)
+            .dst_access_mask(dst.1)
+            .new_layout(layouts.1)
+            .image(self.probes.image.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: self.probes.image.layers,
+            });
+        let dependency_info =
+            vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&barrier));
+        unsafe {
+            self.context
+                .synchronization2()
+                .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+        };
+    }
+}
+
+impl Drop for ProbeGrid {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.update_pipeline, None);
+            device.destroy_pipeline_layout(self.update_pipeline_layout, None);
+            device.destroy_pipeline(self.debug_pipeline, None);
+            device.destroy_pipeline_layout(self.debug_pipeline_layout, None);
+        }
+    }
+}
+
+/// Create the ambient cubes of a grid of `counts` probes, cleared to black.
+fn create_probes(context: &Arc<Context>, [x, y, z]: [u32; 3]) -> Texture {
+    let image = Image::create(
+        Arc::clone(context),
+        ImageParameters {
+            mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            extent: vk::Extent2D {
+                width: x * AMBIENT_CUBE_FACES,
+                height: y,
+            },
+            layers: z,
+            format: PROBE_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            ..Default::default()
+        },
+    );
+
+    image.transition_image_layout(
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+    context.execute_one_time_commands(|command_buffer| unsafe {
+        context.device().cmd_clear_color_image(
+            command_buffer,
+            image.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+            &[vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: z,
+            }],
+        )
+    });
+    image.transition_image_layout(
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+
+    let view = image.create_view(
+        vk::ImageViewType::TYPE_2D_ARRAY,
+        vk::ImageAspectFlags::COLOR,
+    );
+    // Probes are fetched, interpolating them is done by the shaders.
+    let sampler = create_sampler(context, vk::Filter::NEAREST, vk::Filter::NEAREST);
+
+    Texture::new(Arc::clone(context), image, view, Some(sampler))
+}
+
+fn create_debug_descriptors(context: &Arc<Context>, probes: &Texture) -> Descriptors {
+    let device = context.device();
+    let bindings = [vk::DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 1,
+    }];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let image_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(probes.view)
+        .sampler(probes.sampler.expect("Probes must have a sampler"))];
+    let descriptor_writes = [vk::WriteDescriptorSet::default()
+        .dst_set(sets[0])
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(&image_info)];
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_debug_pipeline(
+    context: &Arc<Context>,
+    set_layout: vk::DescriptorSetLayout,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    shading_rate_attachment: bool,
+) -> (vk::PipelineLayout, vk::Pipeline) {
+    let device = context.device();
+
+    let pipeline_layout = {
+        let layouts = [set_layout];
+        let push_constant_range = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: size_of::<ProbeDebugConstants>() as _,
+        }];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&layouts)
+            .push_constant_ranges(&push_constant_range);
+
+        unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+    };
+
+    let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(false)
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(false)];
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .stencil_test_enable(false);
+
+    let pipeline = create_pipeline::<()>(
+        context,
+        PipelineParameters {
+            vertex_shader_params: ShaderParameters::new("probe_grid"),
+            fragment_shader_params: ShaderParameters::new("probe_grid"),
+            multisampling_info: &multisampling_info,
+            viewport_info: &viewport_info,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            rasterizer_info: &rasterizer_info,
+            dynamic_state_info: Some(&dynamic_state_info),
+            depth_stencil_info: Some(&depth_stencil_info),
+            stencil: None,
+            shading_rate: None,
+            shading_rate_attachment,
+            color_blend_attachments: &color_blend_attachments,
+            color_attachment_formats: &[color_format],
+            depth_attachment_format: Some(depth_format),
+            layout: pipeline_layout,
+            parent: None,
+            allow_derivatives: false,
+        },
+    )
+    .expect("Failed to create graphics pipeline");
+
+    (pipeline_layout, pipeline)
+}
@@ -0,0 +1,262 @@
+use crate::{image::color_subresource_range, Context, Texture};
+use ash::vk;
+use std::sync::Arc;
+
+/// Synchronization used to hand a texture from the compute pass producing it
+/// to the graphics pass consuming it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoffMode {
+    /// Both passes are recorded for the same queue. The producer sets an event
+    /// after its dispatches and the consumer waits for it right before reading,
+    /// so unrelated work recorded in between overlaps with the compute pass.
+    Event,
+    /// The producer is submitted to another queue. Its submission signals a
+    /// semaphore the consumer submission waits for, and the ownership of the
+    /// texture is transferred when the queue families differ.
+    Semaphore,
+}
+
+/// Handoff of a texture written by a compute pass, SSAO or particles for example,
+/// to the graphics pass sampling it.
+///
+/// The producer records [`TextureHandoff::cmd_release`] after writing the texture
+/// in `GENERAL` layout and the consumer records [`TextureHandoff::cmd_acquire`]
+/// before sampling it in `SHADER_READ_ONLY_OPTIMAL` layout. With
+/// [`HandoffMode::Semaphore`] the submissions must also signal and wait for the
+/// semaphore infos of the handoff.
+///
+/// The event and the semaphore are reused every frame so one handoff is needed
+/// per frame in flight.
+pub struct TextureHandoff {
+    context: Arc<Context>,
+    mode: HandoffMode,
+    image: vk::Image,
+    producer_family: u32,
+    consumer_family: u32,
+    /// Stages of the consumer reading the texture.
+    consumer_stage: vk::PipelineStageFlags2,
+    /// Replace the fine grained dependencies by full ones, for debugging.
+    serialized: bool,
+    event: vk::Event,
+    semaphore: vk::Semaphore,
+}
+
+impl TextureHandoff {
+    /// Hand `texture` from the compute pass to the `consumer_stage` of the graphics pass.
+    ///
+    /// `Event` mode requires both queue families to be the same.
+    pub fn new(
+        context: &Arc<Context>,
+        texture: &Texture,
+        mode: HandoffMode,
+        (producer_family, consumer_family): (u32, u32),
+        consumer_stage: vk::PipelineStageFlags2,
+    ) -> Self {
+        assert!(
+            mode == HandoffMode::Semaphore || producer_family == consumer_family,
+            "Event handoffs require the producer and the consumer to share a queue"
+        );
+
+        let device = context.device();
+        let event = unsafe {
+            device
+                .create_event(&vk::EventCreateInfo::default(), None)
+                .expect("Failed to create event")
+        };
+        let semaphore = unsafe {
+            device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                .expect("Failed to create semaphore")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            mode,
+            image: texture.image.image,
+            producer_family,
+            consumer_family,
+            consumer_stage,
+            serialized: false,
+            event,
+            semaphore,
+        }
+    }
+}
+
+impl TextureHandoff {
+    pub fn mode(&self) -> HandoffMode {
+        self.mode
+    }
+
+    pub fn is_serialized(&self) -> bool {
+        self.serialized
+    }
+
+    /// Make the consumer wait for the whole producer work with full barriers.
+    ///
+    /// Meant to confirm that artifacts come from a missing synchronization: if they
+    /// disappear once serialized, the handoff or the passes around it are at fault.
+    pub fn set_serialized(&mut self, serialized: bool) {
+        if serialized != self.serialized {
+            tracing::info!(
+                "Compute to graphics handoff {}",
+                if serialized {
+                    "serialized"
+                } else {
+                    "overlapped"
+                }
+            );
+        }
+        self.serialized = serialized;
+    }
+
+    /// Semaphore to signal when submitting the producer, `None` in `Event` mode.
+    pub fn signal_semaphore_info(&self) -> Option<vk::SemaphoreSubmitInfo<'static>> {
+        (self.mode == HandoffMode::Semaphore).then(|| {
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        })
+    }
+
+    /// Semaphore to wait for when submitting the consumer, `None` in `Event` mode.
+    ///
+    /// Once serialized no work of the consumer submission starts before the producer is done.
+    pub fn wait_semaphore_info(&self) -> Option<vk::SemaphoreSubmitInfo<'static>> {
+        (self.mode == HandoffMode::Semaphore).then(|| {
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.semaphore)
+                .stage_mask(self.dst_stage())
+        })
+    }
+
+    /// Record the end of the producer side of the handoff, after the last dispatch writing the texture.
+    pub fn cmd_release(&self, command_buffer: vk::CommandBuffer) {
+        let sync2 = self.context.synchronization2();
+        match self.mode {
+            HandoffMode::Event if self.serialized => {
+                let barrier = self.barrier(self.src_scope(), self.dst_scope());
+                let dependency_info = dependency_info(&barrier);
+                unsafe { sync2.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+            }
+            HandoffMode::Event => {
+                let barrier = self.barrier(self.src_scope(), self.dst_scope());
+                let dependency_info = dependency_info(&barrier);
+                unsafe { sync2.cmd_set_event2(command_buffer, self.event, &dependency_info) };
+            }
+            HandoffMode::Semaphore if self.transfers_ownership() => {
+                let barrier = self
+                    .barrier(
+                        self.src_scope(),
+                        (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+                    )
+                    .src_queue_family_index(self.producer_family)
+                    .dst_queue_family_index(self.consumer_family);
+                let dependency_info = dependency_info(&barrier);
+                unsafe { sync2.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+            }
+            // The semaphore signal makes the writes available, the layout changes on acquire.
+            HandoffMode::Semaphore => {}
+        }
+    }
+
+    /// Record the start of the consumer side of the handoff, before the first read of the texture.
+    pub fn cmd_acquire(&self, command_buffer: vk::CommandBuffer) {
+        let sync2 = self.context.synchronization2();
+        match self.mode {
+            // The barrier of the release already did all the work.
+            HandoffMode::Event if self.serialized => {}
+            HandoffMode::Event => {
+                let barrier = self.barrier(self.src_scope(), self.dst_scope());
+                let dependency_info = dependency_info(&barrier);
+                unsafe {
+                    sync2.cmd_wait_events2(
+                        command_buffer,
+                        std::slice::from_ref(&self.event),
+                        std::slice::from_ref(&dependency_info),
+                    );
+                    sync2.cmd_reset_event2(command_buffer, self.event, self.dst_stage());
+                }
+            }
+            HandoffMode::Semaphore => {
+                // Chained to the semaphore wait, which covers the same stages.
+                let mut barrier =
+                    self.barrier((self.dst_stage(), vk::AccessFlags2::NONE), self.dst_scope());
+                if self.transfers_ownership() {
+                    barrier = barrier
+                        .src_queue_family_index(self.producer_family)
+                        .dst_queue_family_index(self.consumer_family);
+                }
+                let dependency_info = dependency_info(&barrier);
+                unsafe { sync2.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+            }
+        }
+    }
+
+    fn transfers_ownership(&self) -> bool {
+        self.producer_family != self.consumer_family
+    }
+
+    fn src_scope(&self) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+        if self.serialized {
+            (
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                vk::AccessFlags2::MEMORY_WRITE,
+            )
+        } else {
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            )
+        }
+    }
+
+    fn dst_stage(&self) -> vk::PipelineStageFlags2 {
+        if self.serialized {
+            vk::PipelineStageFlags2::ALL_COMMANDS
+        } else {
+            self.consumer_stage
+        }
+    }
+
+    fn dst_scope(&self) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+        if self.serialized {
+            (
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+            )
+        } else {
+            (self.consumer_stage, vk::AccessFlags2::SHADER_READ)
+        }
+    }
+
+    fn barrier(
+        &self,
+        src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) -> vk::ImageMemoryBarrier2<'static> {
+        vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(src.0)
+            .src_access_mask(src.1)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .dst_stage_mask(dst.0)
+            .dst_access_mask(dst.1)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image(self.image)
+            .subresource_range(color_subresource_range())
+    }
+}
+
+impl Drop for TextureHandoff {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_event(self.event, None);
+            device.destroy_semaphore(self.semaphore, None);
+        }
+    }
+}
+
+fn dependency_info<'a>(barrier: &'a vk::ImageMemoryBarrier2<'a>) -> vk::DependencyInfo<'a> {
+    vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(barrier))
+}
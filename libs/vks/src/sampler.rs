@@ -0,0 +1,171 @@
+use crate::RESOURCES_TRACING_TARGET;
+use ash::{vk, Device, Instance};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+pub const DEFAULT_MAX_ANISOTROPY: f32 = 16.0;
+
+/// Parameters of a sampler of the [`SamplerCache`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerKey {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// Filter anisotropically up to the max anisotropy of the cache, if supported.
+    pub anisotropy: bool,
+    pub border_color: vk::BorderColor,
+    pub max_lod: f32,
+}
+
+impl SamplerKey {
+    /// Linear filtering, repeating the texture, with anisotropic filtering of `mip_levels` mips.
+    pub fn repeat(mip_levels: u32) -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            anisotropy: true,
+            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+            max_lod: mip_levels as _,
+        }
+    }
+}
+
+impl Eq for SamplerKey {}
+
+impl Hash for SamplerKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.anisotropy.hash(state);
+        self.border_color.hash(state);
+        self.max_lod.to_bits().hash(state);
+    }
+}
+
+/// Samplers shared by the textures, destroyed with the device.
+///
+/// Also holds the global max anisotropy, clamped to the limit of the device.
+/// Anisotropic filtering is never enabled if the device does not support it.
+pub struct SamplerCache {
+    samplers: Mutex<HashMap<(SamplerKey, u32), vk::Sampler>>,
+    anisotropy_supported: bool,
+    device_max_anisotropy: f32,
+    max_anisotropy: AtomicU32,
+}
+
+impl SamplerCache {
+    pub(crate) fn new(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let anisotropy_supported = features.sampler_anisotropy == vk::TRUE;
+        let device_max_anisotropy = properties.limits.max_sampler_anisotropy;
+        tracing::debug!(
+            "Anisotropic filtering supported: {}, up to {}x",
+            anisotropy_supported,
+            device_max_anisotropy
+        );
+
+        let cache = Self {
+            samplers: Mutex::new(HashMap::new()),
+            anisotropy_supported,
+            device_max_anisotropy,
+            max_anisotropy: AtomicU32::new(1.0f32.to_bits()),
+        };
+        cache.set_max_anisotropy(DEFAULT_MAX_ANISOTROPY);
+        cache
+    }
+}
+
+impl SamplerCache {
+    pub fn is_anisotropy_supported(&self) -> bool {
+        self.anisotropy_supported
+    }
+
+    /// Max anisotropy of the new samplers, 1 when anisotropic filtering is disabled.
+    pub fn max_anisotropy(&self) -> f32 {
+        f32::from_bits(self.max_anisotropy.load(Ordering::Relaxed))
+    }
+
+    /// Set the max anisotropy, clamped between 1 and the limit of the device.
+    ///
+    /// Only applies to the samplers requested afterwards, so the textures
+    /// already created keep their filtering.
+    pub fn set_max_anisotropy(&self, max_anisotropy: f32) {
+        let max_anisotropy = if self.anisotropy_supported {
+            max_anisotropy.clamp(1.0, self.device_max_anisotropy)
+        } else {
+            1.0
+        };
+        self.max_anisotropy
+            .store(max_anisotropy.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the sampler for `key`, creating it if needed.
+    pub fn get(&self, device: &Device, key: SamplerKey) -> vk::Sampler {
+        let max_anisotropy = if key.anisotropy {
+            self.max_anisotropy()
+        } else {
+            1.0
+        };
+
+        let mut samplers = self.samplers.lock().unwrap();
+        *samplers
+            .entry((key, max_anisotropy.to_bits()))
+            .or_insert_with(|| {
+                let sampler_info = vk::SamplerCreateInfo::default()
+                    .mag_filter(key.mag_filter)
+                    .min_filter(key.min_filter)
+                    .address_mode_u(key.address_mode_u)
+                    .address_mode_v(key.address_mode_v)
+                    .address_mode_w(key.address_mode_w)
+                    .anisotropy_enable(max_anisotropy > 1.0)
+                    .max_anisotropy(max_anisotropy)
+                    .border_color(key.border_color)
+                    .unnormalized_coordinates(false)
+                    .compare_enable(false)
+                    .compare_op(vk::CompareOp::ALWAYS)
+                    .mipmap_mode(key.mipmap_mode)
+                    .mip_lod_bias(0.0)
+                    .min_lod(0.0)
+                    .max_lod(key.max_lod);
+
+                let sampler = unsafe {
+                    device
+                        .create_sampler(&sampler_info, None)
+                        .expect("Failed to create sampler")
+                };
+                tracing::debug!(
+                    target: RESOURCES_TRACING_TARGET,
+                    handle = ?sampler,
+                    ?key,
+                    max_anisotropy,
+                    "Sampler created"
+                );
+                sampler
+            })
+    }
+
+    pub(crate) fn destroy(&self, device: &Device) {
+        let mut samplers = self.samplers.lock().unwrap();
+        for (_, sampler) in samplers.drain() {
+            unsafe { device.destroy_sampler(sampler, None) };
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{DemoAction, PickedPixel, PixelValue};
+use rhai::{Engine, Scope, AST};
+use std::{cell::RefCell, error::Error, path::Path, path::PathBuf, rc::Rc};
+
+/// Name of the function of the scripts called every frame.
+const FRAME_FUNCTION: &str = "frame";
+
+/// Expected color of a pixel of the rendered frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelAssertion {
+    pub position: [u32; 2],
+    pub expected: [f32; 4],
+    /// Largest difference accepted on each channel.
+    pub tolerance: f32,
+}
+
+impl PixelAssertion {
+    /// True if `picked` is a color within the tolerance of the expected one.
+    pub fn check(&self, picked: Option<&PickedPixel>) -> bool {
+        match picked.map(|picked| picked.value) {
+            Some(PixelValue::Color(color)) => color
+                .iter()
+                .zip(self.expected)
+                .all(|(value, expected)| (value - expected).abs() <= self.tolerance),
+            _ => false,
+        }
+    }
+}
+
+/// Command issued by a script, applied by the application.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    /// Place the camera at `position` looking at `target`.
+    LookAt {
+        position: [f32; 3],
+        target: [f32; 3],
+    },
+    Action(DemoAction),
+    /// Save the next presented frame to a file.
+    Screenshot(PathBuf),
+    /// Read back a pixel of the next presented frame and report it with
+    /// [`ScriptHost::report_assertion`].
+    AssertPixel(PixelAssertion),
+}
+
+/// Run a Rhai script driving the application, for demos and regression scenarios.
+///
+/// The top level statements of the script run once when it is loaded, then its
+/// `frame(index, time)` function, if any, is called every frame. Both issue
+/// commands through the functions below, returned by [`ScriptHost::update`]:
+///
+/// ```rhai
+/// fn frame(index, time) {
+///     look_at(5.0 * cos(time), 2.0, 5.0 * sin(time), 0.0, 0.0, 0.0);
+///     if index == 60 {
+///         screenshot("frame_60.png");
+///         assert_pixel(400, 300, 0.2, 0.2, 0.2, 1.0, 0.05);
+///     }
+///     if index == 120 {
+///         quit();
+///     }
+/// }
+/// ```
+///
+/// Available functions: `look_at(x, y, z, target_x, target_y, target_z)`,
+/// `set_environment(name)`, `load_model(path)`, `set_vsync(enabled)`,
+/// `set_fov(degrees)`, `screenshot(path)`,
+/// `assert_pixel(x, y, r, g, b, a, tolerance)` and `quit()`.
+///
+/// A script error stops the script, counts as a failure and asks the application to quit.
+pub struct ScriptHost {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    commands: Rc<RefCell<Vec<ScriptCommand>>>,
+    has_frame_function: bool,
+    frame: u64,
+    time: f32,
+    failures: u32,
+    stopped: bool,
+}
+
+impl ScriptHost {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_owned();
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let engine = create_engine(&commands);
+        let ast = engine.compile_file(path.clone())?;
+        let has_frame_function = ast
+            .iter_functions()
+            .any(|function| function.name == FRAME_FUNCTION);
+
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+
+        Ok(Self {
+            path,
+            engine,
+            ast,
+            scope,
+            commands,
+            has_frame_function,
+            frame: 0,
+            time: 0.0,
+            failures: 0,
+            stopped: false,
+        })
+    }
+}
+
+impl ScriptHost {
+    /// Advance the script by one frame of `delta_time_secs`.
+    ///
+    /// # Returns
+    ///
+    /// The commands issued since the last call, in order.
+    pub fn update(&mut self, delta_time_secs: f32) -> Vec<ScriptCommand> {
+        if self.has_frame_function && !self.stopped {
+            let arguments = (self.frame as i64, self.time as f64);
+            if let Err(error) = self.engine.call_fn::<rhai::Dynamic>(
+                &mut self.scope,
+                &self.ast,
+                FRAME_FUNCTION,
+                arguments,
+            ) {
+                tracing::error!(
+                    "Script {} failed at frame {}: {}",
+                    self.path.display(),
+                    self.frame,
+                    error
+                );
+                self.stopped = true;
+                self.failures += 1;
+                self.commands
+                    .borrow_mut()
+                    .push(ScriptCommand::Action(DemoAction::Quit));
+            }
+        }
+        self.frame += 1;
+        self.time += delta_time_secs;
+        self.commands.borrow_mut().drain(..).collect()
+    }
+
+    /// Check `assertion` against the pixel read back by the application.
+    pub fn report_assertion(&mut self, assertion: &PixelAssertion, picked: Option<PickedPixel>) {
+        if assertion.check(picked.as_ref()) {
+            tracing::info!("Script assertion passed at {:?}", assertion.position);
+        } else {
+            self.failures += 1;
+            match picked {
+                Some(picked) => tracing::error!(
+                    "Script assertion failed: expected {:?} ± {}, got {}",
+                    assertion.expected,
+                    assertion.tolerance,
+                    picked
+                ),
+                None => tracing::error!(
+                    "Script assertion failed: pixel {:?} could not be read",
+                    assertion.position
+                ),
+            }
+        }
+    }
+
+    /// Number of failed assertions and script errors.
+    pub fn failure_count(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+fn create_engine(commands: &Rc<RefCell<Vec<ScriptCommand>>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let push = |commands: &Rc<RefCell<Vec<ScriptCommand>>>| {
+        let commands = Rc::clone(commands);
+        move |command: ScriptCommand| commands.borrow_mut().push(command)
+    };
+
+    let issue = push(commands);
+    engine.register_fn(
+        "look_at",
+        move |x: f64, y: f64, z: f64, target_x: f64, target_y: f64, target_z: f64| {
+            issue(ScriptCommand::LookAt {
+                position: [x as f32, y as f32, z as f32],
+                target: [target_x as f32, target_y as f32, target_z as f32],
+            })
+        },
+    );
+    let issue = push(commands);
+    engine.register_fn("set_environment", move |name: &str| {
+        issue(ScriptCommand::Action(DemoAction::SetEnvironment(
+            name.to_owned(),
+        )))
+    });
+    let issue = push(commands);
+    engine.register_fn("load_model", move |path: &str| {
+        issue(ScriptCommand::Action(DemoAction::LoadModel(
+            path.to_owned(),
+        )))
+    });
+    let issue = push(commands);
+    engine.register_fn("set_vsync", move |enabled: bool| {
+        issue(ScriptCommand::Action(DemoAction::SetVsync(enabled)))
+    });
+    let issue = push(commands);
+    engine.register_fn("set_fov", move |degrees: f64| {
+        issue(ScriptCommand::Action(DemoAction::SetFov(degrees as f32)))
+    });
+    let issue = push(commands);
+    engine.register_fn("screenshot", move |path: &str| {
+        issue(ScriptCommand::Screenshot(PathBuf::from(path)))
+    });
+    let issue = push(commands);
+    engine.register_fn(
+        "assert_pixel",
+        move |x: i64, y: i64, r: f64, g: f64, b: f64, a: f64, tolerance: f64| {
+            issue(ScriptCommand::AssertPixel(PixelAssertion {
+                position: [x.max(0) as u32, y.max(0) as u32],
+                expected: [r as f32, g as f32, b as f32, a as f32],
+                tolerance: tolerance as f32,
+            }))
+        },
+    );
+    let issue = push(commands);
+    engine.register_fn("quit", move || {
+        issue(ScriptCommand::Action(DemoAction::Quit))
+    });
+
+    engine
+}
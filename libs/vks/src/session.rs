@@ -0,0 +1,101 @@
+use crate::{
+    Camera, RendererConfig, VertexColorMode, DEFAULT_EMISSIVE_INTENSITY, DEFAULT_SHARPNESS,
+};
+use math::cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, path::Path};
+
+pub const DEFAULT_SESSION_PATH: &str = ".vkrs_session";
+
+/// Position of the camera and the point it looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+impl CameraPose {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            position: camera.position().into(),
+            target: camera.target().into(),
+        }
+    }
+
+    /// Place `camera` at this pose, keeping its mode.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.look_at(Point3::from(self.position), Point3::from(self.target));
+    }
+}
+
+/// Layout and settings of the panels of the gui.
+///
+/// Selections are indices in the lists of the panels and fall back to the
+/// first entry when out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuiLayout {
+    pub menu_open: bool,
+    /// Top left corner of the menu window, in points.
+    pub menu_position: Option<[f32; 2]>,
+    pub latency_mode: usize,
+    pub sharpness: f32,
+    pub emissive_intensity: f32,
+    /// Index in [`crate::VertexColorMode::all`], linear when out of range.
+    pub vertex_color_mode: usize,
+    pub pixel_picker_enabled: bool,
+    pub pick_target: usize,
+}
+
+impl Default for GuiLayout {
+    fn default() -> Self {
+        Self {
+            menu_open: false,
+            menu_position: None,
+            latency_mode: 0,
+            sharpness: DEFAULT_SHARPNESS,
+            emissive_intensity: DEFAULT_EMISSIVE_INTENSITY,
+            vertex_color_mode: VertexColorMode::default().index() as _,
+            pixel_picker_enabled: false,
+            pick_target: 0,
+        }
+    }
+}
+
+/// State of the application saved on exit and restored on startup, so users
+/// resume where they left off.
+///
+/// Sessions are written in RON:
+///
+/// ```ron
+/// (
+///     camera: Some((position: (0.0, 1.0, 5.0), target: (0.0, 0.0, 0.0))),
+///     environment: Some("indoor"),
+///     renderer: Some((clear_color: (0.1, 0.1, 0.1, 1.0), fov: 45.0, vsync: true)),
+///     gui: (menu_open: true, menu_position: Some((20.0, 20.0))),
+/// )
+/// ```
+///
+/// Missing entries take their default value and leave the application as is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub camera: Option<CameraPose>,
+    /// Name of the environment, the stem of its file.
+    pub environment: Option<String>,
+    pub renderer: Option<RendererConfig>,
+    pub gui: GuiLayout,
+}
+
+impl Session {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        Ok(ron::from_str(&content)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use super::Context;
+use super::{Context, VksError};
 use ash::{vk, Device};
 use std::{path::Path, sync::Arc};
 
@@ -9,10 +9,10 @@ pub struct ShaderModule {
 }
 
 impl ShaderModule {
-    pub fn new<P: AsRef<Path>>(context: Arc<Context>, path: P) -> Self {
-        let source = read_shader_from_file(path);
-        let module = create_shader_module(context.device(), &source);
-        Self { context, module }
+    pub fn new<P: AsRef<Path>>(context: Arc<Context>, path: P) -> Result<Self, VksError> {
+        let source = read_shader_from_file(path)?;
+        let module = create_shader_module(context.device(), &source)?;
+        Ok(Self { context, module })
     }
 }
 
@@ -29,17 +29,18 @@ impl Drop for ShaderModule {
     }
 }
 
-fn read_shader_from_file<P: AsRef<Path>>(path: P) -> Vec<u32> {
-    tracing::debug!("Loading shader file {}", path.as_ref().to_str().unwrap());
-    let mut file = std::fs::File::open(path).expect("Failed to open shader file");
-    ash::util::read_spv(&mut file).expect("Failed to read shader source")
+fn read_shader_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<u32>, VksError> {
+    let path = path.as_ref();
+    tracing::debug!("Loading shader file {}", path.display());
+    std::fs::File::open(path)
+        .and_then(|mut file| ash::util::read_spv(&mut file))
+        .map_err(|cause| VksError::ShaderLoad {
+            path: path.to_path_buf(),
+            cause,
+        })
 }
 
-fn create_shader_module(device: &Device, code: &[u32]) -> vk::ShaderModule {
+fn create_shader_module(device: &Device, code: &[u32]) -> Result<vk::ShaderModule, VksError> {
     let create_info = vk::ShaderModuleCreateInfo::default().code(code);
-    unsafe {
-        device
-            .create_shader_module(&create_info, None)
-            .expect("Failed to create shader module")
-    }
+    Ok(unsafe { device.create_shader_module(&create_info, None)? })
 }
@@ -0,0 +1,116 @@
+use crate::shader_path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Delay between two checks of the modification times of the shader files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SHADER_EXTENSIONS: [&str; 3] = ["vert", "frag", "comp"];
+const SPIRV_MAGIC_NUMBER: u32 = 0x0723_0203;
+
+struct WatchedFile {
+    shader: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Modified since the last report, waiting for the writes to settle.
+    changed: bool,
+}
+
+/// Watch the compiled shaders used by the pipelines and report the ones
+/// recompiled since the application started, to rebuild their pipelines
+/// without restarting.
+///
+/// Call [`ShaderWatcher::poll`] once per frame. A change is reported once the
+/// file stopped changing for a poll interval, so a compiler still writing it
+/// is not read half way, and only if it holds SPIR-V. Reported shaders are
+/// valid for [`crate::ShaderModule::new`], the pipelines using them can be
+/// recreated after waiting for the gpu.
+#[derive(Default)]
+pub struct ShaderWatcher {
+    files: Vec<WatchedFile>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ShaderWatcher {
+    /// Watch the stages of the shader `name`, the one given to [`crate::ShaderParameters`].
+    pub fn watch(&mut self, name: &str) {
+        if self.files.iter().any(|file| file.shader == name) {
+            return;
+        }
+        for extension in SHADER_EXTENSIONS {
+            let path = PathBuf::from(shader_path(name, extension));
+            let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+                continue;
+            };
+            self.files.push(WatchedFile {
+                shader: name.to_owned(),
+                path,
+                modified: Some(modified),
+                changed: false,
+            });
+        }
+    }
+
+    pub fn is_watching(&self, name: &str) -> bool {
+        self.files.iter().any(|file| file.shader == name)
+    }
+
+    /// Check the modification times of the files.
+    ///
+    /// # Returns
+    ///
+    /// The names of the shaders whose pipelines must be recreated, without duplicates.
+    pub fn poll(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last_poll| now - last_poll < POLL_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+
+        let mut reloaded = Vec::new();
+        for file in self.files.iter_mut() {
+            let Ok(modified) = fs::metadata(&file.path).and_then(|metadata| metadata.modified())
+            else {
+                continue;
+            };
+            if file.modified != Some(modified) {
+                file.modified = Some(modified);
+                file.changed = true;
+                continue;
+            }
+            if !file.changed {
+                continue;
+            }
+            file.changed = false;
+
+            if !is_spirv(&file.path) {
+                tracing::warn!("Ignoring invalid shader {}", file.path.display());
+                continue;
+            }
+            tracing::info!("Shader {} changed", file.path.display());
+            if !reloaded.contains(&file.shader) {
+                reloaded.push(file.shader.clone());
+            }
+        }
+        reloaded
+    }
+}
+
+/// True if the file at `path` can be read as SPIR-V.
+fn is_spirv(path: &Path) -> bool {
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    ash::util::read_spv(&mut file).is_ok_and(|code| code.first() == Some(&SPIRV_MAGIC_NUMBER))
+}
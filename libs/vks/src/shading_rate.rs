@@ -0,0 +1,667 @@
+use crate::{
+    create_compute_pipeline, create_sampler,
+    image::{cmd_image_barrier, color_subresource_range},
+    texture::create_texture,
+    ComputePipelineParameters, Context, Descriptors, Image, ShaderParameters, Texture,
+};
+use ash::{khr, vk, Entry, Instance};
+use std::{ffi::CStr, mem::size_of, sync::Arc};
+
+/// Rate of the shading rate attachment, one fragment per pixel.
+pub const SHADING_RATE_1X1: u8 = 0;
+/// Rate of the shading rate attachment, one fragment per 2x2 pixels.
+pub const SHADING_RATE_2X2: u8 = (1 << 2) | 1;
+
+const GENERATE_SHADER_NAME: &str = "shading_rate";
+const GENERATE_GROUP_SIZE: u32 = 8;
+const HISTORY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Support of VK_KHR_fragment_shading_rate by the physical device.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShadingRateSupport {
+    /// The rate can be set per draw.
+    pub pipeline: bool,
+    /// The rate can be read from an attachment.
+    pub attachment: bool,
+    /// Size in pixels covered by a texel of the attachment.
+    pub texel_size: vk::Extent2D,
+}
+
+impl ShadingRateSupport {
+    pub(crate) fn query(
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        let extension_props = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .expect("Failed to enumerate device extention properties")
+        };
+        let is_supported = extension_props.iter().any(|ext| {
+            let ext_name = unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) };
+            ext_name == khr::fragment_shading_rate::NAME
+        });
+        if !is_supported {
+            return Self::default();
+        }
+
+        let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+
+        let mut rate_features = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut rate_features);
+        unsafe { properties2.get_physical_device_features2(physical_device, &mut features) };
+
+        let mut rate_properties = vk::PhysicalDeviceFragmentShadingRatePropertiesKHR::default();
+        let mut properties =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut rate_properties);
+        unsafe { properties2.get_physical_device_properties2(physical_device, &mut properties) };
+
+        Self {
+            pipeline: rate_features.pipeline_fragment_shading_rate == vk::TRUE,
+            attachment: rate_features.attachment_fragment_shading_rate == vk::TRUE,
+            texel_size: rate_properties.min_fragment_shading_rate_attachment_texel_size,
+        }
+    }
+
+    /// True if both the per draw and the attachment rates are supported.
+    pub fn is_supported(&self) -> bool {
+        self.pipeline && self.attachment
+    }
+}
+
+/// Fragment shading rate state of a pipeline, or set dynamically with
+/// [`Context::cmd_set_fragment_shading_rate`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShadingRateState {
+    pub fragment_size: vk::Extent2D,
+    /// Combine the draw rate with the primitive rate, then with the attachment rate.
+    pub combiner_ops: [vk::FragmentShadingRateCombinerOpKHR; 2],
+}
+
+impl ShadingRateState {
+    /// Use the rate of the shading rate attachment.
+    pub fn attachment() -> Self {
+        Self {
+            fragment_size: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            combiner_ops: [
+                vk::FragmentShadingRateCombinerOpKHR::KEEP,
+                vk::FragmentShadingRateCombinerOpKHR::REPLACE,
+            ],
+        }
+    }
+
+    /// Shade every fragment of the draw at `fragment_size`, ignoring the attachment.
+    pub fn uniform(fragment_size: vk::Extent2D) -> Self {
+        Self {
+            fragment_size,
+            combiner_ops: [vk::FragmentShadingRateCombinerOpKHR::KEEP; 2],
+        }
+    }
+
+    pub(crate) fn create_info(&self) -> vk::PipelineFragmentShadingRateStateCreateInfoKHR<'static> {
+        vk::PipelineFragmentShadingRateStateCreateInfoKHR::default()
+            .fragment_size(self.fragment_size)
+            .combiner_ops(self.combiner_ops)
+    }
+}
+
+impl Default for ShadingRateState {
+    fn default() -> Self {
+        Self::uniform(vk::Extent2D {
+            width: 1,
+            height: 1,
+        })
+    }
+}
+
+/// Thresholds used to pick the rate of each tile of the screen.
+///
+/// A tile is shaded at 2x2 if its luminance contrast is below
+/// `contrast_threshold`, if it is farther than `periphery_radius` from the
+/// center of the screen, or if `motion` is above `motion_threshold`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ShadingRateParameters {
+    /// Relative luminance contrast of the tile in the previous frame.
+    pub contrast_threshold: f32,
+    /// Distance from the center, 1 being the middle of the edges of the screen.
+    pub periphery_radius: f32,
+    pub motion_threshold: f32,
+    /// Speed of the camera in units per second.
+    pub motion: f32,
+}
+
+impl Default for ShadingRateParameters {
+    fn default() -> Self {
+        Self {
+            contrast_threshold: 0.05,
+            periphery_radius: 0.8,
+            motion_threshold: 4.0,
+            motion: 0.0,
+        }
+    }
+}
+
+impl ShadingRateParameters {
+    fn as_bytes(&self) -> Vec<u8> {
+        [
+            self.contrast_threshold,
+            self.periphery_radius,
+            self.motion_threshold,
+            self.motion,
+        ]
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect()
+    }
+}
+
+/// Shading rate attachment generated from the previous frame.
+///
+/// Each frame the rendered image is downsampled into a history image with
+/// [`ShadingRateImage::cmd_capture`]. The next frame, a compute pass
+/// measures the luminance contrast of each tile of the history and writes the
+/// rate of the tile in the attachment used by the scene pass, along with a
+/// color coded version of it that can be drawn over the frame to debug it.
+///
+/// Requires the swapchain images to support transfers and the device to support
+/// [`ShadingRateSupport::is_supported`].
+///
+/// ```ignore
+/// rate_image.cmd_generate(command_buffer, parameters);
+/// begin_rendering(rendering_info.push_next(&mut rate_image.rendering_attachment_info()));
+/// // draw with pipelines created with ShadingRateState::attachment()
+/// end_rendering();
+/// rate_image.cmd_capture(command_buffer, swapchain_image);
+/// ```
+pub struct ShadingRateImage {
+    context: Arc<Context>,
+    texel_size: vk::Extent2D,
+    extent: vk::Extent2D,
+    rate: Texture,
+    history: Texture,
+    debug: Texture,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl ShadingRateImage {
+    /// Create the attachment for a framebuffer of `framebuffer_extent`.
+    pub fn new(context: &Arc<Context>, framebuffer_extent: vk::Extent2D) -> Self {
+        let support = context.shading_rate_support();
+        assert!(
+            support.is_supported(),
+            "Fragment shading rate is not supported by the device"
+        );
+
+        let texel_size = support.texel_size;
+        let extent = vk::Extent2D {
+            width: framebuffer_extent.width.div_ceil(texel_size.width),
+            height: framebuffer_extent.height.div_ceil(texel_size.height),
+        };
+
+        let rate = create_texture(
+            context,
+            extent,
+            vk::Format::R8_UINT,
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR,
+            None,
+        );
+        let history_extent = vk::Extent2D {
+            width: extent.width * 2,
+            height: extent.height * 2,
+        };
+        let history = create_texture(
+            context,
+            history_extent,
+            HISTORY_FORMAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            Some(create_sampler(
+                context,
+                vk::Filter::NEAREST,
+                vk::Filter::NEAREST,
+            )),
+        );
+        let debug = create_texture(
+            context,
+            extent,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+            None,
+        );
+
+        // Start from a black history, everything is shaded at 2x2 on the first frame.
+        context.execute_one_time_commands(|command_buffer| {
+            history.image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+            unsafe {
+                context.device().cmd_clear_color_image(
+                    command_buffer,
+                    history.image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &vk::ClearColorValue::default(),
+                    &[color_subresource_range()],
+                )
+            };
+            cmd_image_barrier(
+                context,
+                command_buffer,
+                history.image.image,
+                (
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                (
+                    vk::PipelineStageFlags2::TRANSFER,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                ),
+                (
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::AccessFlags2::SHADER_READ,
+                ),
+            );
+        });
+
+        let descriptors = create_descriptors(context, &rate, &history, &debug);
+        let (pipeline, pipeline_layout) = create_generate_pipeline(context, descriptors.layout());
+
+        Self {
+            context: Arc::clone(context),
+            texel_size,
+            extent,
+            rate,
+            history,
+            debug,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl ShadingRateImage {
+    pub fn texel_size(&self) -> vk::Extent2D {
+        self.texel_size
+    }
+
+    /// Size of the attachment in texels.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Attachment info to chain to the rendering info of the pass using the rates.
+    pub fn rendering_attachment_info(
+        &self,
+    ) -> vk::RenderingFragmentShadingRateAttachmentInfoKHR<'static> {
+        vk::RenderingFragmentShadingRateAttachmentInfoKHR::default()
+            .image_view(self.rate.view)
+            .image_layout(vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR)
+            .shading_rate_attachment_texel_size(self.texel_size)
+    }
+
+    /// Write the rates of the frame from the history of the previous one.
+    ///
+    /// Must be recorded outside of a render pass, before the pass using the rates.
+    pub fn cmd_generate(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        parameters: ShadingRateParameters,
+    ) {
+        let device = self.context.device();
+
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.rate.image.image,
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+        );
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.debug.image.image,
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+            (vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::NONE),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+        );
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &parameters.as_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                self.extent.width.div_ceil(GENERATE_GROUP_SIZE),
+                self.extent.height.div_ceil(GENERATE_GROUP_SIZE),
+                1,
+            );
+        }
+
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.rate.image.image,
+            (
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR,
+                vk::AccessFlags2::FRAGMENT_SHADING_RATE_ATTACHMENT_READ_KHR,
+            ),
+        );
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.debug.image.image,
+            (
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_READ,
+            ),
+        );
+    }
+
+    /// Downsample `source` in the history read by the next [`ShadingRateImage::cmd_generate`].
+    ///
+    /// `source` must be in the `TRANSFER_SRC_OPTIMAL` layout.
+    pub fn cmd_capture(&self, command_buffer: vk::CommandBuffer, source: &Image) {
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.history.image.image,
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        );
+
+        cmd_blit(
+            &self.context,
+            command_buffer,
+            source,
+            &self.history.image,
+            full_region(source),
+            full_region(&self.history.image),
+            vk::Filter::LINEAR,
+        );
+
+        cmd_image_barrier(
+            &self.context,
+            command_buffer,
+            self.history.image.image,
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_READ,
+            ),
+        );
+    }
+
+    /// Draw the color coded rates in the top right quarter of `target`.
+    ///
+    /// Green tiles are shaded at 1x1 and red ones at 2x2. `target` must be in
+    /// the `TRANSFER_DST_OPTIMAL` layout.
+    pub fn cmd_draw_debug(&self, command_buffer: vk::CommandBuffer, target: &Image) {
+        let [_, max] = full_region(target);
+        let min = vk::Offset3D {
+            x: max.x / 2,
+            y: 0,
+            z: 0,
+        };
+        let max = vk::Offset3D {
+            y: max.y / 2,
+            ..max
+        };
+
+        cmd_blit(
+            &self.context,
+            command_buffer,
+            &self.debug.image,
+            target,
+            full_region(&self.debug.image),
+            [min, max],
+            vk::Filter::NEAREST,
+        );
+    }
+}
+
+impl Drop for ShadingRateImage {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn full_region(image: &Image) -> [vk::Offset3D; 2] {
+    [
+        vk::Offset3D::default(),
+        vk::Offset3D {
+            x: image.extent.width as _,
+            y: image.extent.height as _,
+            z: 1,
+        },
+    ]
+}
+
+fn cmd_blit(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    src: &Image,
+    dst: &Image,
+    src_offsets: [vk::Offset3D; 2],
+    dst_offsets: [vk::Offset3D; 2],
+    filter: vk::Filter,
+) {
+    let subresource = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let region = vk::ImageBlit {
+        src_subresource: subresource,
+        src_offsets,
+        dst_subresource: subresource,
+        dst_offsets,
+    };
+    unsafe {
+        context.device().cmd_blit_image(
+            command_buffer,
+            src.image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+            filter,
+        )
+    };
+}
+
+fn create_descriptors(
+    context: &Arc<Context>,
+    rate: &Texture,
+    history: &Texture,
+    debug: &Texture,
+) -> Descriptors {
+    let device = context.device();
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(2)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+    ];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: 2,
+        },
+    ];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let history_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(history.view)
+        .sampler(history.sampler.unwrap())];
+    let rate_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(rate.view)];
+    let debug_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(debug.view)];
+    let writes = [
+        vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&history_info),
+        vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&rate_info),
+        vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(2)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&debug_info),
+    ];
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_generate_pipeline(
+    context: &Arc<Context>,
+    set_layout: vk::DescriptorSetLayout,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::COMPUTE,
+        offset: 0,
+        size: size_of::<ShadingRateParameters>() as u32,
+    }];
+    let set_layouts = [set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    let layout = unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    };
+
+    let pipeline = create_compute_pipeline(
+        context,
+        ComputePipelineParameters {
+            shader_params: ShaderParameters::new(GENERATE_SHADER_NAME),
+            layout,
+        },
+    );
+
+    (pipeline, layout)
+}
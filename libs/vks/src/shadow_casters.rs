@@ -0,0 +1,171 @@
+use crate::{Light, LightKind};
+use math::{
+    cgmath::{InnerSpace, Matrix4, Point3, Vector3},
+    Aabb, Bvh, Frustum,
+};
+use std::fmt;
+
+/// Items casting shadows in the shadow map(s) of a light.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightCasters {
+    /// Index of the light.
+    pub light: usize,
+    /// Items to draw in each map of the light: one per cascade for directional
+    /// lights, a single one for the others.
+    pub lists: Vec<Vec<usize>>,
+}
+
+/// Counters of the shadow caster culling, shown in the stats panel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShadowCasterStats {
+    pub lights: usize,
+    pub maps: usize,
+    /// Items drawn, summed over all the maps.
+    pub casters: usize,
+    /// Items skipped, summed over all the maps.
+    pub culled: usize,
+}
+
+impl fmt::Display for ShadowCasterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lights, {} maps, {} casters drawn, {} culled",
+            self.lights, self.maps, self.casters, self.culled
+        )
+    }
+}
+
+/// Per light lists of the items to draw in the shadow maps, so each shadow
+/// pass only draws the items its light can see instead of the whole scene.
+///
+/// Items are the ones of the [`Bvh`] the lists are gathered from:
+/// - directional lights keep the items in the frustum of each cascade, whose
+///   projections must already include the casters in front of the slice;
+/// - spot lights keep the items in their cone, up to their range;
+/// - point lights keep the items within their range.
+///
+/// Disabled lights get no list and lights without range keep everything in
+/// front of them.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowCasterLists {
+    lights: Vec<LightCasters>,
+    item_count: usize,
+}
+
+impl ShadowCasterLists {
+    /// Gather the casters of `lights` among the items of `bvh`.
+    ///
+    /// `cascades` are the view projections of the cascades of the directional lights.
+    pub fn gather(lights: &[Light], bvh: &Bvh<f32>, cascades: &[Matrix4<f32>]) -> Self {
+        let lights = lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| light.enabled)
+            .map(|(index, light)| {
+                let lists = match light.kind {
+                    LightKind::Directional => cascades
+                        .iter()
+                        .map(|view_proj| {
+                            let mut casters = Vec::new();
+                            bvh.query_frustum(&Frustum::from_view_proj(*view_proj), &mut casters);
+                            casters
+                        })
+                        .collect(),
+                    LightKind::Point => {
+                        let range = light.range.unwrap_or(f32::INFINITY);
+                        let mut casters = Vec::new();
+                        bvh.query(
+                            |aabb| distance_to_aabb(light.position, aabb) <= range,
+                            &mut casters,
+                        );
+                        vec![casters]
+                    }
+                    LightKind::Spot {
+                        outer_cone_angle, ..
+                    } => {
+                        let range = light.range.unwrap_or(f32::INFINITY);
+                        let mut casters = Vec::new();
+                        bvh.query(
+                            |aabb| {
+                                cone_intersects_aabb(
+                                    light.position,
+                                    light.direction,
+                                    outer_cone_angle,
+                                    range,
+                                    aabb,
+                                )
+                            },
+                            &mut casters,
+                        );
+                        vec![casters]
+                    }
+                };
+                LightCasters {
+                    light: index,
+                    lists,
+                }
+            })
+            .collect();
+
+        Self {
+            lights,
+            item_count: bvh.len(),
+        }
+    }
+
+    pub fn lights(&self) -> &[LightCasters] {
+        &self.lights
+    }
+
+    /// Casters of the light at `light`, `None` if it is disabled.
+    pub fn light(&self, light: usize) -> Option<&LightCasters> {
+        self.lights.iter().find(|casters| casters.light == light)
+    }
+
+    pub fn stats(&self) -> ShadowCasterStats {
+        let maps = self.lights.iter().map(|light| light.lists.len()).sum();
+        let casters = self
+            .lights
+            .iter()
+            .flat_map(|light| light.lists.iter())
+            .map(Vec::len)
+            .sum();
+        ShadowCasterStats {
+            lights: self.lights.len(),
+            maps,
+            casters,
+            culled: maps * self.item_count - casters,
+        }
+    }
+}
+
+/// Distance from `point` to `aabb`, 0 inside.
+fn distance_to_aabb(point: Point3<f32>, aabb: &Aabb<f32>) -> f32 {
+    let (min, max) = (aabb.min(), aabb.max());
+    let offset = Vector3::new(
+        (min.x - point.x).max(point.x - max.x).max(0.0),
+        (min.y - point.y).max(point.y - max.y).max(0.0),
+        (min.z - point.z).max(point.z - max.z).max(0.0),
+    );
+    offset.magnitude()
+}
+
+/// True if the bounding sphere of `aabb` intersects the cone of half angle
+/// `angle` from `apex` along `direction`, cut at `range`.
+fn cone_intersects_aabb(
+    apex: Point3<f32>,
+    direction: Vector3<f32>,
+    angle: f32,
+    range: f32,
+    aabb: &Aabb<f32>,
+) -> bool {
+    let direction = direction.normalize();
+    let radius = (aabb.max() - aabb.min()).magnitude() * 0.5;
+    let to_center = aabb.get_center() - Vector3::new(apex.x, apex.y, apex.z);
+    let along = to_center.dot(direction);
+    let across = (to_center.magnitude2() - along * along).max(0.0).sqrt();
+    // Distance from the center to the side of the cone, negative inside.
+    let to_side = angle.cos() * across - angle.sin() * along;
+    to_side <= radius && along <= range + radius && along >= -radius
+}
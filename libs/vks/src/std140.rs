@@ -0,0 +1,150 @@
+use math::cgmath::{Matrix4, Point3, Vector2, Vector3, Vector4};
+
+/// 3 component vector aligned like a GLSL `vec3` in std140/std430 blocks.
+///
+/// Takes 16 bytes so it can be used in arrays. When a scalar follows a `vec3`
+/// in the shader, use a plain `[f32; 3]` instead and let the scalar fill the gap.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec3A {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// 4 component vector aligned like a GLSL `vec4`.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec4A {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+/// 2 component vector aligned like a GLSL `vec2`.
+#[repr(C, align(8))]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec2A {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Column major 4x4 matrix aligned like a GLSL `mat4`.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4A(pub [[f32; 4]; 4]);
+
+/// Element of a std140 array.
+///
+/// In std140 every array element is rounded up to 16 bytes, so an array of
+/// scalars must be declared as `[Padded<f32>; N]` on the Rust side.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Padded<T: Copy>(pub T);
+
+impl From<Vector3<f32>> for Vec3A {
+    fn from(v: Vector3<f32>) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<Point3<f32>> for Vec3A {
+    fn from(p: Point3<f32>) -> Self {
+        Self {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+        }
+    }
+}
+
+impl From<Vector4<f32>> for Vec4A {
+    fn from(v: Vector4<f32>) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: v.w,
+        }
+    }
+}
+
+impl From<Vector2<f32>> for Vec2A {
+    fn from(v: Vector2<f32>) -> Self {
+        Self { x: v.x, y: v.y }
+    }
+}
+
+impl From<Matrix4<f32>> for Mat4A {
+    fn from(m: Matrix4<f32>) -> Self {
+        Self(m.into())
+    }
+}
+
+impl Default for Mat4A {
+    fn default() -> Self {
+        Self([[0.0; 4]; 4])
+    }
+}
+
+impl<T: Copy> From<T> for Padded<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+/// Round `offset` up to the next multiple of `alignment`.
+pub const fn align_up(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// Stride of an array element with `size` bytes in a std140 block.
+pub const fn std140_array_stride(size: usize) -> usize {
+    align_up(size, 16)
+}
+
+/// Check at compile time the offsets of the fields of a `#[repr(C)]` struct
+/// and optionally its size.
+///
+/// The offsets are the ones reported by the shader reflection or computed
+/// by hand from the std140/std430 rules.
+///
+/// ```ignore
+/// assert_std140_layout!(CameraUBO {
+///     view: 0,
+///     proj: 64,
+///     eye: 128,
+/// });
+/// ```
+#[macro_export]
+macro_rules! assert_std140_layout {
+    ($ty:ty { $($field:ident: $offset:expr),* $(,)? }) => {
+        const _: () = {
+            $(
+                assert!(
+                    ::std::mem::offset_of!($ty, $field) == $offset,
+                    concat!(
+                        "std140 mismatch: ",
+                        stringify!($ty),
+                        "::",
+                        stringify!($field),
+                        " is not at offset ",
+                        stringify!($offset)
+                    )
+                );
+            )*
+        };
+    };
+    ($ty:ty { $($field:ident: $offset:expr),* $(,)? }, size: $size:expr) => {
+        $crate::assert_std140_layout!($ty { $($field: $offset),* });
+        const _: () = assert!(
+            ::std::mem::size_of::<$ty>() == $size,
+            concat!("std140 mismatch: ", stringify!($ty), " is not ", stringify!($size), " bytes")
+        );
+    };
+}
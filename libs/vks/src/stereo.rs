@@ -0,0 +1,316 @@
+use crate::{
+    create_pipeline, Camera, Context, Descriptors, PipelineParameters, ShaderParameters, Texture,
+};
+use ash::vk;
+use math::cgmath::{InnerSpace, Vector3};
+use std::{fmt, mem::size_of, sync::Arc};
+use util::any_as_u8_slice;
+
+/// How the views of the eyes are composited on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    /// Left eye on the left half, right eye on the right one, each squeezed
+    /// horizontally like the half side-by-side input of 3D displays.
+    SideBySide,
+    /// Red from the left eye, green and blue from the right one, for red/cyan glasses.
+    Anaglyph,
+}
+
+impl StereoMode {
+    pub fn all() -> [Self; 2] {
+        [Self::SideBySide, Self::Anaglyph]
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            Self::SideBySide => 0,
+            Self::Anaglyph => 1,
+        }
+    }
+}
+
+impl fmt::Display for StereoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SideBySide => write!(f, "Side by side"),
+            Self::Anaglyph => write!(f, "Anaglyph"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoSettings {
+    pub mode: StereoMode,
+    /// Distance between the eyes, in units of the scene.
+    pub ipd: f32,
+    /// Distance in front of the camera where the eyes converge, which appears
+    /// at the depth of the screen.
+    pub convergence: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            mode: StereoMode::Anaglyph,
+            ipd: 0.064,
+            convergence: 2.0,
+        }
+    }
+}
+
+impl StereoSettings {
+    /// Cameras of the left and right eyes, offset on both sides of `camera` and
+    /// looking at the convergence point.
+    pub fn eye_cameras(&self, camera: &Camera) -> [Camera; 2] {
+        let position = camera.position();
+        let forward = (camera.target() - position).normalize();
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let convergence_point = position + forward * self.convergence;
+
+        [-0.5, 0.5].map(|side| {
+            let mut eye = *camera;
+            eye.look_at(position + right * self.ipd * side, convergence_point);
+            eye
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct StereoConstants {
+    mode: u32,
+}
+
+/// Composite the views of the eyes on the current rendering.
+///
+/// Must be recorded inside an active dynamic rendering whose color attachment
+/// has the format passed at creation, while the eye textures are in the
+/// `SHADER_READ_ONLY_OPTIMAL` layout. The eye textures are bound at creation,
+/// so the pass must be created again with them.
+pub struct StereoCompositePass {
+    context: Arc<Context>,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl StereoCompositePass {
+    pub fn new(
+        context: &Arc<Context>,
+        color_format: vk::Format,
+        left_eye: &Texture,
+        right_eye: &Texture,
+    ) -> Self {
+        let device = context.device();
+        let descriptors = create_descriptors(context, [left_eye, right_eye]);
+
+        let pipeline_layout = {
+            let layouts = [descriptors.layout()];
+            let push_constant_range = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<StereoConstants>() as _,
+            }];
+            let layout_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&layouts)
+                .push_constant_ranges(&push_constant_range);
+
+            unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+        };
+
+        let pipeline = {
+            let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+                .viewport_count(1)
+                .scissor_count(1);
+
+            let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+                .depth_clamp_enable(false)
+                .rasterizer_discard_enable(false)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .depth_bias_enable(false);
+
+            let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+                .sample_shading_enable(false)
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                .min_sample_shading(1.0)
+                .alpha_to_coverage_enable(false)
+                .alpha_to_one_enable(false);
+
+            let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+                .color_write_mask(
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A,
+                )
+                .blend_enable(false)];
+
+            let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+            create_pipeline::<()>(
+                context,
+                PipelineParameters {
+                    vertex_shader_params: ShaderParameters::new("stereo"),
+                    fragment_shader_params: ShaderParameters::new("stereo"),
+                    multisampling_info: &multisampling_info,
+                    viewport_info: &viewport_info,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    rasterizer_info: &rasterizer_info,
+                    dynamic_state_info: Some(&dynamic_state_info),
+                    depth_stencil_info: None,
+                    stencil: None,
+                    shading_rate: None,
+                    shading_rate_attachment: false,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachment_formats: &[color_format],
+                    depth_attachment_format: None,
+                    layout: pipeline_layout,
+                    parent: None,
+                    allow_derivatives: false,
+                },
+            )
+            .expect("Failed to create graphics pipeline")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Record the composition of the eyes over the whole `extent` in `mode`.
+    pub fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        mode: StereoMode,
+    ) {
+        let device = self.context.device();
+
+        let constants = StereoConstants { mode: mode.id() };
+
+        unsafe {
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                any_as_u8_slice(&constants),
+            );
+
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for StereoCompositePass {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn create_descriptors(context: &Arc<Context>, eyes: [&Texture; 2]) -> Descriptors {
+    let device = context.device();
+    let bindings = [0, 1].map(|binding| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    });
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: eyes.len() as _,
+    }];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let image_infos = eyes.map(|eye| {
+        [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(eye.view)
+            .sampler(eye.sampler.expect("Eye textures must have a sampler"))]
+    });
+    let descriptor_writes = image_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(sets[0])
+                .dst_binding(binding as _)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(image_info)
+        })
+        .collect::<Vec<_>>();
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
@@ -0,0 +1,139 @@
+use crate::SpecializationConstants;
+use ash::{khr, vk, Entry, Instance};
+
+/// Subgroup properties of the physical device.
+///
+/// Compute kernels can use subgroup operations to reduce through registers
+/// instead of shared memory. Their size must then be known when the pipeline
+/// is created, see [`SubgroupSpecialization`].
+#[derive(Debug, Clone, Copy)]
+pub struct SubgroupSupport {
+    /// Default number of invocations of a subgroup.
+    pub size: u32,
+    /// Range of sizes a pipeline can require, equal to `size` if it cannot be controlled.
+    pub min_size: u32,
+    pub max_size: u32,
+    /// Stages supporting subgroup operations.
+    pub stages: vk::ShaderStageFlags,
+    pub operations: vk::SubgroupFeatureFlags,
+    pub quad_operations_in_all_stages: bool,
+}
+
+impl SubgroupSupport {
+    pub(crate) fn query(
+        entry: &Entry,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        let api_version = unsafe {
+            instance
+                .get_physical_device_properties(physical_device)
+                .api_version
+        };
+        // Subgroup operations are core since Vulkan 1.1.
+        if api_version < vk::API_VERSION_1_1 {
+            return Self::default();
+        }
+
+        let properties2 = khr::get_physical_device_properties2::Instance::new(entry, instance);
+        let mut subgroup_properties = vk::PhysicalDeviceSubgroupProperties::default();
+        let mut size_control_properties =
+            vk::PhysicalDeviceSubgroupSizeControlProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default()
+            .push_next(&mut subgroup_properties)
+            .push_next(&mut size_control_properties);
+        unsafe { properties2.get_physical_device_properties2(physical_device, &mut properties) };
+
+        let size = subgroup_properties.subgroup_size;
+        let (min_size, max_size) = if size_control_properties.min_subgroup_size == 0 {
+            (size, size)
+        } else {
+            (
+                size_control_properties.min_subgroup_size,
+                size_control_properties.max_subgroup_size,
+            )
+        };
+
+        Self {
+            size,
+            min_size,
+            max_size,
+            stages: subgroup_properties.supported_stages,
+            operations: subgroup_properties.supported_operations,
+            quad_operations_in_all_stages: subgroup_properties.quad_operations_in_all_stages
+                == vk::TRUE,
+        }
+    }
+
+    /// True if `stage` supports all of `operations`.
+    pub fn supports(
+        &self,
+        stage: vk::ShaderStageFlags,
+        operations: vk::SubgroupFeatureFlags,
+    ) -> bool {
+        self.stages.contains(stage) && self.operations.contains(operations)
+    }
+}
+
+/// Without subgroup support every invocation is its own subgroup.
+impl Default for SubgroupSupport {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            min_size: 1,
+            max_size: 1,
+            stages: vk::ShaderStageFlags::empty(),
+            operations: vk::SubgroupFeatureFlags::empty(),
+            quad_operations_in_all_stages: false,
+        }
+    }
+}
+
+/// Subgroup variant of a compute kernel.
+///
+/// Variants are separate shaders, `<kernel>_subgroup`, as a shader declaring
+/// subgroup capabilities the device lacks is invalid even if it does not use
+/// them. They declare `layout (constant_id = 0) const uint SUBGROUP_SIZE`, the
+/// smallest size their subgroups may have, the constants of the kernel itself
+/// starting at [`SubgroupSpecialization::KERNEL_CONSTANT_ID`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubgroupSpecialization {
+    size: u32,
+}
+
+impl SubgroupSpecialization {
+    pub const SIZE_CONSTANT_ID: u32 = 0;
+    pub const KERNEL_CONSTANT_ID: u32 = 1;
+
+    /// Variant using `operations` in subgroups of at least `min_size` invocations.
+    ///
+    /// # Returns
+    ///
+    /// `None` if `enabled` is false or if compute shaders of the device don't
+    /// support it, the naive kernel should then be used. Disabling it is
+    /// useful to compare a kernel against its naive version.
+    pub fn new(
+        support: &SubgroupSupport,
+        operations: vk::SubgroupFeatureFlags,
+        min_size: u32,
+        enabled: bool,
+    ) -> Option<Self> {
+        // Without size control the size can vary between pipelines on some
+        // devices, the smallest one is the only safe assumption.
+        let size = support.min_size;
+        let supported = size >= min_size.max(2)
+            && support.supports(
+                vk::ShaderStageFlags::COMPUTE,
+                vk::SubgroupFeatureFlags::BASIC | operations,
+            );
+        (enabled && supported).then_some(Self { size })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn add_constants(&self, constants: &mut SpecializationConstants) {
+        constants.add_u32(Self::SIZE_CONSTANT_ID, self.size);
+    }
+}
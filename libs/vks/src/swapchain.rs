@@ -1,14 +1,62 @@
 use super::{
     context::Context,
     image::{create_image_view, Image},
+    leak_tracker::{track_create, track_destroy, TrackedResource, RESOURCES_TRACING_TARGET},
+    LatencyMode, PresentPacer, VksError,
 };
 use ash::{
     khr::{surface, swapchain},
     prelude::VkResult,
     vk, Device,
 };
+use math::cgmath::{Deg, Matrix4, SquareMatrix};
 use std::sync::Arc;
 
+/// How the images of a [`Swapchain`] are presented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresentConfig {
+    /// Present modes by order of preference, the first one supported by the
+    /// surface is used. FIFO, always supported, if none is.
+    pub preferred_modes: Vec<vk::PresentModeKHR>,
+    /// Number of images of the swapchain, clamped to the limits of the
+    /// surface. One more than the minimum if `None`.
+    pub image_count: Option<u32>,
+    /// How many presented frames can wait to be displayed, see [`PresentConfig::pacer`].
+    pub frame_latency: LatencyMode,
+}
+
+impl PresentConfig {
+    /// MAILBOX, FIFO_RELAXED or FIFO with `vsync`, IMMEDIATE without.
+    pub fn vsync(vsync: bool) -> Self {
+        let preferred_modes = if vsync {
+            vec![
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO_RELAXED,
+                vk::PresentModeKHR::FIFO,
+            ]
+        } else {
+            vec![vk::PresentModeKHR::IMMEDIATE]
+        };
+
+        Self {
+            preferred_modes,
+            image_count: None,
+            frame_latency: LatencyMode::Default,
+        }
+    }
+
+    /// Pacer limiting the queued frames to the frame latency of the config.
+    pub fn pacer(&self) -> PresentPacer {
+        PresentPacer::new(self.frame_latency)
+    }
+}
+
+impl Default for PresentConfig {
+    fn default() -> Self {
+        Self::vsync(true)
+    }
+}
+
 pub struct Swapchain {
     context: Arc<Context>,
     swapchain: swapchain::Device,
@@ -22,22 +70,59 @@ impl Swapchain {
     /// Create the swapchain with optimal settings possible with
     /// `device`.
     ///
-    /// # Returns
-    ///
-    /// A tuple containing the swapchain loader and the actual swapchain.
+    /// Fails with [`VksError::SurfaceLost`] if the window was destroyed.
     pub fn create(
         context: Arc<Context>,
         swapchain_support_details: SwapchainSupportDetails,
         dimensions: [u32; 2],
         preferred_format: Option<vk::SurfaceFormatKHR>,
-        preferred_vsync: bool,
-    ) -> Self {
+        present_config: &PresentConfig,
+    ) -> Result<Self, VksError> {
+        Self::create_replacing(
+            context,
+            swapchain_support_details,
+            dimensions,
+            preferred_format,
+            present_config,
+            vk::SwapchainKHR::null(),
+        )
+    }
+
+    /// Create a swapchain replacing this one, which is retired.
+    ///
+    /// This one can no longer be presented to but must be kept until the
+    /// frames presenting its images are complete, see [`crate::DeletionQueue`].
+    pub fn recreate(
+        &self,
+        swapchain_support_details: SwapchainSupportDetails,
+        dimensions: [u32; 2],
+        preferred_format: Option<vk::SurfaceFormatKHR>,
+        present_config: &PresentConfig,
+    ) -> Result<Self, VksError> {
+        Self::create_replacing(
+            Arc::clone(&self.context),
+            swapchain_support_details,
+            dimensions,
+            preferred_format,
+            present_config,
+            self.swapchain_khr,
+        )
+    }
+
+    fn create_replacing(
+        context: Arc<Context>,
+        swapchain_support_details: SwapchainSupportDetails,
+        dimensions: [u32; 2],
+        preferred_format: Option<vk::SurfaceFormatKHR>,
+        present_config: &PresentConfig,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<Self, VksError> {
         tracing::debug!("Creating swapchain.");
 
         let properties = swapchain_support_details.get_ideal_swapchain_properties(
             preferred_format,
             dimensions,
-            preferred_vsync,
+            present_config,
         );
 
         let format = properties.format;
@@ -58,7 +143,7 @@ impl Swapchain {
                 .image_color_space(format.color_space)
                 .image_extent(extent)
                 .image_array_layers(1)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+                .image_usage(properties.usage);
 
             builder = if graphics != present {
                 builder
@@ -69,42 +154,44 @@ impl Swapchain {
             };
 
             builder
-                .pre_transform(swapchain_support_details.capabilities.current_transform)
+                .pre_transform(properties.transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(present_mode)
                 .clipped(true)
+                .old_swapchain(old_swapchain)
         };
 
         let swapchain = swapchain::Device::new(context.instance(), context.device());
-        let swapchain_khr = unsafe {
-            swapchain
-                .create_swapchain(&create_info, None)
-                .expect("Failed to create swapchain")
-        };
-        let images = unsafe {
-            swapchain
-                .get_swapchain_images(swapchain_khr)
-                .expect("Failed to get swapchain images")
+        let swapchain_khr = unsafe { swapchain.create_swapchain(&create_info, None)? };
+        let images = match unsafe { swapchain.get_swapchain_images(swapchain_khr) } {
+            Ok(images) => images
                 .iter()
                 .map(|image| {
                     Image::create_swapchain_image(Arc::clone(&context), *image, properties)
                 })
-                .collect::<Vec<_>>()
+                .collect::<Vec<_>>(),
+            Err(error) => {
+                unsafe { swapchain.destroy_swapchain(swapchain_khr, None) };
+                return Err(error.into());
+            }
         };
         let views = Self::create_views(context.device(), &images, properties);
 
         let swapchain = Self::new(context, swapchain, swapchain_khr, properties, images, views);
-
         tracing::debug!(
-            "Created swapchain.\n\tFormat: {:?}\n\tColorSpace: {:?}\n\tPresentMode: {:?}\n\tExtent: {:?}\n\tImageCount: {:?}",
-            format.format,
-            format.color_space,
-            present_mode,
-            extent,
-            swapchain.image_count(),
+            target: RESOURCES_TRACING_TARGET,
+            handle = ?swapchain_khr,
+            width = extent.width,
+            height = extent.height,
+            format = ?format.format,
+            color_space = ?format.color_space,
+            ?present_mode,
+            transform = ?properties.transform,
+            image_count = swapchain.image_count(),
+            "Swapchain created"
         );
 
-        swapchain
+        Ok(swapchain)
     }
 
     /// Create one image view for each image of the swapchain.
@@ -138,6 +225,8 @@ impl Swapchain {
         images: Vec<Image>,
         image_views: Vec<vk::ImageView>,
     ) -> Self {
+        track_create(TrackedResource::Swapchain);
+
         Self {
             context,
             swapchain,
@@ -189,19 +278,73 @@ impl Swapchain {
     }
 
     pub fn present(&self, present_info: &vk::PresentInfoKHR) -> VkResult<bool> {
+        let _queue_guard = self.context.lock_queue();
         unsafe {
             self.swapchain
                 .queue_present(self.context.present_queue(), present_info)
         }
     }
 
+    /// True if the display of presented images can be waited for.
+    pub fn supports_present_wait(&self) -> bool {
+        self.context.present_wait().is_some()
+    }
+
+    /// Present the image of `present_info` with `present_id`, see [`crate::PresentPacer`].
+    ///
+    /// The id is ignored if present wait is not supported.
+    pub fn present_with_id(
+        &self,
+        present_info: vk::PresentInfoKHR,
+        present_id: u64,
+    ) -> VkResult<bool> {
+        if !self.supports_present_wait() {
+            return self.present(&present_info);
+        }
+
+        let present_ids = [present_id];
+        let mut present_id_info = vk::PresentIdKHR::default().present_ids(&present_ids);
+        let present_info = present_info.push_next(&mut present_id_info);
+        self.present(&present_info)
+    }
+
+    /// Wait until the image presented with `present_id` is displayed.
+    ///
+    /// # Panics
+    ///
+    /// If present wait is not supported.
+    pub fn wait_for_present(&self, present_id: u64, timeout: u64) -> VkResult<()> {
+        let present_wait = self
+            .context
+            .present_wait()
+            .expect("Present wait is not supported by the device");
+        unsafe { present_wait.wait_for_present(self.swapchain_khr, present_id, timeout) }
+    }
+
+    /// Destroy the swapchain. Does nothing if it was already destroyed.
     pub fn destroy(&mut self) {
+        if self.swapchain_khr == vk::SwapchainKHR::null() {
+            return;
+        }
         unsafe {
             self.image_views
-                .iter()
-                .for_each(|v| self.context.device().destroy_image_view(*v, None));
+                .drain(..)
+                .for_each(|v| self.context.device().destroy_image_view(v, None));
             self.swapchain.destroy_swapchain(self.swapchain_khr, None);
         }
+        tracing::debug!(
+            target: RESOURCES_TRACING_TARGET,
+            handle = ?self.swapchain_khr,
+            "Swapchain destroyed"
+        );
+        self.swapchain_khr = vk::SwapchainKHR::null();
+        track_destroy(TrackedResource::Swapchain);
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        self.destroy();
     }
 }
 
@@ -246,17 +389,28 @@ impl SwapchainSupportDetails {
         &self,
         preferred_format: Option<vk::SurfaceFormatKHR>,
         preferred_dimensions: [u32; 2],
-        preferred_vsync: bool,
+        present_config: &PresentConfig,
     ) -> SwapchainProperties {
         let format = Self::choose_swapchain_surface_format(&self.formats, preferred_format);
-        let present_mode =
-            Self::choose_swapchain_surface_present_mode(&self.present_modes, preferred_vsync);
-        let extent = Self::choose_swapchain_extent(self.capabilities, preferred_dimensions);
-        let min_image_count = Self::choose_image_count(self.capabilities);
+        let present_mode = Self::choose_swapchain_surface_present_mode(
+            &self.present_modes,
+            &present_config.preferred_modes,
+        );
+        let transform = Self::choose_swapchain_transform(self.capabilities);
+        let extent =
+            Self::choose_swapchain_extent(self.capabilities, preferred_dimensions, transform);
+        let min_image_count =
+            Self::choose_image_count(self.capabilities, present_config.image_count);
+        // Transfers are optional, they are used to copy from and blit to the images.
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (self.capabilities.supported_usage_flags
+                & (vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST));
         SwapchainProperties {
             format,
             present_mode,
             extent,
+            usage,
+            transform,
             min_image_count,
         }
     }
@@ -286,27 +440,42 @@ impl SwapchainSupportDetails {
 
     /// Choose the swapchain present mode.
     ///
-    /// If only one is supported then defaults to it (must be FIFO by the specs)
-    /// If vsync is requested then we chose the first available among MAILBOX, FIFO_RELAXED, FIFO
-    /// Otherwise we go for immediate
+    /// The first of `preferred_modes` available, FIFO otherwise since it
+    /// is the only one always supported by the specs.
     fn choose_swapchain_surface_present_mode(
         available_present_modes: &[vk::PresentModeKHR],
-        preferred_vsync: bool,
+        preferred_modes: &[vk::PresentModeKHR],
     ) -> vk::PresentModeKHR {
-        if available_present_modes.len() == 1 {
-            return available_present_modes[0];
-        }
+        preferred_modes
+            .iter()
+            .find(|mode| available_present_modes.contains(mode))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
 
-        if preferred_vsync {
-            if available_present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
-                vk::PresentModeKHR::MAILBOX
-            } else if available_present_modes.contains(&vk::PresentModeKHR::FIFO_RELAXED) {
-                vk::PresentModeKHR::FIFO_RELAXED
-            } else {
-                vk::PresentModeKHR::FIFO
-            }
+    /// Choose the swapchain pre transform.
+    ///
+    /// The current transform of the surface when it is a rotation, the
+    /// application then renders rotated content and the compositor does not
+    /// have to rotate the images (mostly on Android). Identity otherwise, to
+    /// let the compositor handle mirrored transforms.
+    fn choose_swapchain_transform(
+        capabilities: vk::SurfaceCapabilitiesKHR,
+    ) -> vk::SurfaceTransformFlagsKHR {
+        let current = capabilities.current_transform;
+        let rotations = vk::SurfaceTransformFlagsKHR::IDENTITY
+            | vk::SurfaceTransformFlagsKHR::ROTATE_90
+            | vk::SurfaceTransformFlagsKHR::ROTATE_180
+            | vk::SurfaceTransformFlagsKHR::ROTATE_270;
+        if rotations.contains(current) {
+            current
+        } else if capabilities
+            .supported_transforms
+            .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
+        {
+            vk::SurfaceTransformFlagsKHR::IDENTITY
         } else {
-            vk::PresentModeKHR::IMMEDIATE
+            current
         }
     }
 
@@ -314,25 +483,37 @@ impl SwapchainSupportDetails {
     ///
     /// If a current extent is defined it will be returned.
     /// Otherwise the surface extent clamped between the min
-    /// and max image extent will be returned.
+    /// and max image extent will be returned. The extent is in the native
+    /// orientation of the surface, so the dimensions of the window are swapped
+    /// when `transform` rotates by a quarter turn.
     fn choose_swapchain_extent(
         capabilities: vk::SurfaceCapabilitiesKHR,
         preferred_dimensions: [u32; 2],
+        transform: vk::SurfaceTransformFlagsKHR,
     ) -> vk::Extent2D {
         if capabilities.current_extent.width != u32::MAX {
             return capabilities.current_extent;
         }
 
+        let [preferred_width, preferred_height] = if is_quarter_turn(transform) {
+            [preferred_dimensions[1], preferred_dimensions[0]]
+        } else {
+            preferred_dimensions
+        };
         let min = capabilities.min_image_extent;
         let max = capabilities.max_image_extent;
-        let width = preferred_dimensions[0].min(max.width).max(min.width);
-        let height = preferred_dimensions[1].min(max.height).max(min.height);
+        let width = preferred_width.min(max.width).max(min.width);
+        let height = preferred_height.min(max.height).max(min.height);
         vk::Extent2D { width, height }
     }
 
-    fn choose_image_count(capabilities: vk::SurfaceCapabilitiesKHR) -> u32 {
+    /// Choose the image count, `preferred` or one more than the minimum,
+    /// clamped to the limits of the surface.
+    fn choose_image_count(capabilities: vk::SurfaceCapabilitiesKHR, preferred: Option<u32>) -> u32 {
         let max = capabilities.max_image_count;
-        let mut preferred = capabilities.min_image_count + 1;
+        let mut preferred = preferred
+            .unwrap_or(capabilities.min_image_count + 1)
+            .max(capabilities.min_image_count);
         if max > 0 && preferred > max {
             preferred = max;
         }
@@ -344,6 +525,50 @@ impl SwapchainSupportDetails {
 pub struct SwapchainProperties {
     pub format: vk::SurfaceFormatKHR,
     pub present_mode: vk::PresentModeKHR,
+    /// Extent of the images, in the native orientation of the surface.
     pub extent: vk::Extent2D,
+    pub usage: vk::ImageUsageFlags,
+    /// Rotation the application applies to its rendering, see [`SwapchainProperties::pre_rotation`].
+    pub transform: vk::SurfaceTransformFlagsKHR,
     min_image_count: u32,
 }
+
+impl SwapchainProperties {
+    /// Extent of the images as seen on the display, once rotated by the compositor.
+    pub fn display_extent(&self) -> vk::Extent2D {
+        if is_quarter_turn(self.transform) {
+            vk::Extent2D {
+                width: self.extent.height,
+                height: self.extent.width,
+            }
+        } else {
+            self.extent
+        }
+    }
+
+    /// Aspect ratio to build the projections with.
+    pub fn aspect(&self) -> f32 {
+        let extent = self.display_extent();
+        extent.width as f32 / extent.height as f32
+    }
+
+    /// Rotation of the clip space to apply after the projection so the
+    /// content appears upright once the images are displayed.
+    ///
+    /// Identity unless the surface is rotated, the viewports and scissors
+    /// keep using [`SwapchainProperties::extent`].
+    pub fn pre_rotation(&self) -> Matrix4<f32> {
+        let angle = match self.transform {
+            vk::SurfaceTransformFlagsKHR::ROTATE_90 => 90.0,
+            vk::SurfaceTransformFlagsKHR::ROTATE_180 => 180.0,
+            vk::SurfaceTransformFlagsKHR::ROTATE_270 => 270.0,
+            _ => return Matrix4::identity(),
+        };
+        Matrix4::from_angle_z(Deg(angle))
+    }
+}
+
+fn is_quarter_turn(transform: vk::SurfaceTransformFlagsKHR) -> bool {
+    transform == vk::SurfaceTransformFlagsKHR::ROTATE_90
+        || transform == vk::SurfaceTransformFlagsKHR::ROTATE_270
+}
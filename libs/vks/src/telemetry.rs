@@ -0,0 +1,317 @@
+use ash::vk;
+use serde::Serialize;
+use std::{collections::VecDeque, fmt, fs::File, io, io::BufWriter, path::Path, time::Instant};
+
+pub const DEFAULT_TELEMETRY_CAPACITY: usize = 600;
+/// Number of frames the bottleneck is computed over, so a single hitch does not change it.
+pub const BOTTLENECK_WINDOW: usize = 60;
+
+/// Cpu side step of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStage {
+    /// The swapchain image was acquired.
+    Acquired,
+    /// The input moving the camera was sampled.
+    InputSampled,
+    /// The command buffers were recorded.
+    Recorded,
+    /// The command buffers were submitted.
+    Submitted,
+    /// The image was queued for presentation.
+    Presented,
+}
+
+/// Timestamps of a frame, in milliseconds since the creation of the telemetry.
+///
+/// A stage that was never reached is `None`, when the frame was skipped
+/// because the swapchain was out of date for example.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FrameTimings {
+    pub frame: u64,
+    pub begin: f64,
+    /// When the cpu finished waiting for the fence of the frame in flight.
+    pub waited: Option<f64>,
+    pub acquired: Option<f64>,
+    /// When the input of the frame was sampled, `None` if it was at its beginning.
+    pub input_sampled: Option<f64>,
+    pub recorded: Option<f64>,
+    pub submitted: Option<f64>,
+    pub presented: Option<f64>,
+    /// When the cpu observed the completion of the frame on the gpu.
+    pub gpu_complete: Option<f64>,
+    /// When the frame was displayed, only known with [`crate::PresentPacer`].
+    pub displayed: Option<f64>,
+}
+
+impl FrameTimings {
+    /// Time spent by the cpu on the frame.
+    pub fn cpu_time(&self) -> Option<f64> {
+        self.presented.map(|presented| presented - self.begin)
+    }
+
+    /// Time from the beginning of the frame to its completion on the gpu.
+    pub fn latency(&self) -> Option<f64> {
+        self.gpu_complete.map(|complete| complete - self.begin)
+    }
+
+    /// Time from the sampling of the input of the frame to its display.
+    pub fn input_to_photon(&self) -> Option<f64> {
+        let sampled = self.input_sampled.unwrap_or(self.begin);
+        self.displayed.map(|displayed| displayed - sampled)
+    }
+
+    /// What the cpu spent most of the frame on, `None` if the frame was not presented.
+    ///
+    /// Waiting for the fence means the gpu is late, waiting to acquire or present
+    /// the image means the presentation engine is, and anything else is cpu work.
+    pub fn bottleneck(&self) -> Option<FrameBottleneck> {
+        let waited = self.waited.unwrap_or(self.begin);
+        let acquired = self.acquired?;
+        let submitted = self.submitted?;
+        let presented = self.presented?;
+
+        let gpu_wait = waited - self.begin;
+        let present_wait = (acquired - waited) + (presented - submitted);
+        let cpu_work = submitted - acquired;
+
+        let bottleneck = if gpu_wait >= present_wait && gpu_wait >= cpu_work {
+            FrameBottleneck::Gpu
+        } else if present_wait >= cpu_work {
+            FrameBottleneck::Present
+        } else {
+            FrameBottleneck::Cpu
+        };
+        Some(bottleneck)
+    }
+}
+
+/// What limits the frame rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum FrameBottleneck {
+    Cpu,
+    Gpu,
+    Present,
+}
+
+impl FrameBottleneck {
+    /// Suggestion to raise the frame rate when this is the bottleneck.
+    pub fn suggestion(self) -> &'static str {
+        match self {
+            Self::Cpu => "reduce the number of draw calls or enable static batching",
+            Self::Gpu => "reduce the render resolution or enable dynamic resolution",
+            Self::Present => "disable vsync or use the low latency mode",
+        }
+    }
+}
+
+impl fmt::Display for FrameBottleneck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "CPU bound"),
+            Self::Gpu => write!(f, "GPU bound"),
+            Self::Present => write!(f, "Present bound"),
+        }
+    }
+}
+
+/// Most frequent bottleneck of `frames`.
+pub fn dominant_bottleneck<'a, I: IntoIterator<Item = &'a FrameTimings>>(
+    frames: I,
+) -> Option<FrameBottleneck> {
+    let mut counts = [0usize; 3];
+    for bottleneck in frames.into_iter().filter_map(FrameTimings::bottleneck) {
+        counts[bottleneck as usize] += 1;
+    }
+    [
+        FrameBottleneck::Cpu,
+        FrameBottleneck::Gpu,
+        FrameBottleneck::Present,
+    ]
+    .into_iter()
+    .zip(counts)
+    .filter(|(_, count)| *count > 0)
+    .max_by_key(|(_, count)| *count)
+    .map(|(bottleneck, _)| bottleneck)
+}
+
+#[derive(Serialize)]
+struct TelemetryExport<'a> {
+    frames: Vec<&'a FrameTimings>,
+}
+
+/// Ring buffer of the timings of the last frames.
+///
+/// Averages hide the hitches, so every frame is kept to spot the ones
+/// that took longer than their neighbours.
+///
+/// ```ignore
+/// telemetry.begin_frame();
+/// wait_for_fences(fence);
+/// telemetry.fence_signaled(fence);
+/// acquire_next_image();
+/// telemetry.mark(FrameStage::Acquired);
+/// // ...
+/// telemetry.end_frame(fence);
+/// ```
+pub struct FrameTelemetry {
+    start: Instant,
+    capacity: usize,
+    frames: VecDeque<FrameTimings>,
+    current: Option<FrameTimings>,
+    next_frame: u64,
+    pending_fences: Vec<(vk::Fence, u64)>,
+    bottleneck: Option<FrameBottleneck>,
+}
+
+impl FrameTelemetry {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            current: None,
+            next_frame: 0,
+            pending_fences: Vec::new(),
+            bottleneck: None,
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+
+    /// Start recording the timings of a new frame.
+    ///
+    /// A frame that was started but not ended is pushed as is.
+    pub fn begin_frame(&mut self) {
+        if let Some(frame) = self.current.take() {
+            self.push(frame);
+        }
+
+        self.current = Some(FrameTimings {
+            frame: self.next_frame,
+            begin: self.now(),
+            ..Default::default()
+        });
+        self.next_frame += 1;
+    }
+
+    /// Record the time of `stage` for the current frame.
+    pub fn mark(&mut self, stage: FrameStage) {
+        let now = self.now();
+        if let Some(frame) = self.current.as_mut() {
+            let timestamp = match stage {
+                FrameStage::Acquired => &mut frame.acquired,
+                FrameStage::InputSampled => &mut frame.input_sampled,
+                FrameStage::Recorded => &mut frame.recorded,
+                FrameStage::Submitted => &mut frame.submitted,
+                FrameStage::Presented => &mut frame.presented,
+            };
+            *timestamp = Some(now);
+        }
+    }
+
+    /// End the current frame whose work signals `fence` when complete.
+    pub fn end_frame(&mut self, fence: vk::Fence) {
+        if let Some(frame) = self.current.take() {
+            self.pending_fences.retain(|(f, _)| *f != fence);
+            self.pending_fences.push((fence, frame.frame));
+            self.push(frame);
+            self.update_bottleneck();
+        }
+    }
+
+    /// Must be called right after waiting for `fence` to record the end
+    /// of the wait and the completion of the frame that used it last.
+    pub fn fence_signaled(&mut self, fence: vk::Fence) {
+        let now = self.now();
+        if let Some(frame) = self.current.as_mut() {
+            frame.waited = Some(now);
+        }
+
+        let Some(index) = self.pending_fences.iter().position(|(f, _)| *f == fence) else {
+            return;
+        };
+        let (_, frame_id) = self.pending_fences.swap_remove(index);
+
+        if let Some(frame) = self.frames.iter_mut().rev().find(|f| f.frame == frame_id) {
+            frame.gpu_complete = Some(now);
+        }
+    }
+
+    fn update_bottleneck(&mut self) {
+        let window = self.frames.len().saturating_sub(BOTTLENECK_WINDOW);
+        let bottleneck = dominant_bottleneck(self.frames.range(window..));
+        if bottleneck != self.bottleneck {
+            if let Some(bottleneck) = bottleneck {
+                tracing::info!(
+                    "Frames are now {}, {}",
+                    bottleneck.to_string().to_lowercase(),
+                    bottleneck.suggestion()
+                );
+            }
+            self.bottleneck = bottleneck;
+        }
+    }
+
+    /// Most frequent bottleneck of the last [`BOTTLENECK_WINDOW`] frames.
+    pub fn bottleneck(&self) -> Option<FrameBottleneck> {
+        self.bottleneck
+    }
+
+    /// Record that the frame `frame` was displayed.
+    pub fn mark_displayed(&mut self, frame: u64) {
+        let now = self.now();
+        if let Some(frame) = self.frames.iter_mut().rev().find(|f| f.frame == frame) {
+            frame.displayed = Some(now);
+        }
+    }
+
+    /// Number of the frame being recorded.
+    pub fn current_frame(&self) -> Option<u64> {
+        self.current.as_ref().map(|frame| frame.frame)
+    }
+
+    fn push(&mut self, frame: FrameTimings) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// Recorded frames, from the oldest to the most recent.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameTimings> {
+        self.frames.iter()
+    }
+
+    /// Time between the beginning of each recorded frame and the next one.
+    pub fn frame_intervals(&self) -> Vec<f64> {
+        self.frames
+            .iter()
+            .zip(self.frames.iter().skip(1))
+            .map(|(previous, next)| next.begin - previous.begin)
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.current = None;
+        self.pending_fences.clear();
+        self.bottleneck = None;
+    }
+
+    /// Write the recorded frames as a json timeline.
+    pub fn export_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        let export = TelemetryExport {
+            frames: self.frames.iter().collect(),
+        };
+        serde_json::to_writer_pretty(writer, &export).map_err(io::Error::from)
+    }
+}
+
+impl Default for FrameTelemetry {
+    fn default() -> Self {
+        Self::new(DEFAULT_TELEMETRY_CAPACITY)
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{create_pipeline, Context, PipelineParameters, ShaderParameters};
+use ash::vk;
+use std::{mem::size_of, sync::Arc};
+use util::any_as_u8_slice;
+
+/// Reference patterns used to check the output transform on a given display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Linear and perceptual grey ramps, smooth and in 16 steps.
+    GradientRamp,
+    /// 75% and 100% color bars.
+    ColorBars,
+    /// Black and white checkerboard next to a 50% linear grey patch.
+    Checkerboard { cell_size: u32 },
+}
+
+impl TestPattern {
+    pub fn all() -> [TestPattern; 5] {
+        [
+            TestPattern::GradientRamp,
+            TestPattern::ColorBars,
+            TestPattern::Checkerboard { cell_size: 1 },
+            TestPattern::Checkerboard { cell_size: 2 },
+            TestPattern::Checkerboard { cell_size: 8 },
+        ]
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            TestPattern::GradientRamp => 0,
+            TestPattern::ColorBars => 1,
+            TestPattern::Checkerboard { .. } => 2,
+        }
+    }
+
+    fn cell_size(&self) -> u32 {
+        match self {
+            TestPattern::Checkerboard { cell_size } => *cell_size,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for TestPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestPattern::GradientRamp => write!(f, "Gradient ramps"),
+            TestPattern::ColorBars => write!(f, "Color bars"),
+            TestPattern::Checkerboard { cell_size } => write!(f, "Checkerboard {}px", cell_size),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TestPatternConstants {
+    extent: [f32; 2],
+    pattern: u32,
+    cell_size: u32,
+    opacity: f32,
+    encode_srgb: u32,
+}
+
+/// Draw test patterns on top of the current rendering.
+///
+/// Must be recorded inside an active dynamic rendering whose color
+/// attachment has the format passed at creation.
+pub struct TestPatternPass {
+    context: Arc<Context>,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl TestPatternPass {
+    pub fn new(
+        context: &Arc<Context>,
+        color_format: vk::Format,
+        depth_format: Option<vk::Format>,
+    ) -> Self {
+        let device = context.device();
+
+        let pipeline_layout = {
+            let push_constant_range = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<TestPatternConstants>() as _,
+            }];
+            let layout_info =
+                vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_range);
+
+            unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+        };
+
+        let pipeline = {
+            let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+                .viewport_count(1)
+                .scissor_count(1);
+
+            let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+                .depth_clamp_enable(false)
+                .rasterizer_discard_enable(false)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .depth_bias_enable(false);
+
+            let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+                .sample_shading_enable(false)
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                .min_sample_shading(1.0)
+                .alpha_to_coverage_enable(false)
+                .alpha_to_one_enable(false);
+
+            let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+                .color_write_mask(
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A,
+                )
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .alpha_blend_op(vk::BlendOp::ADD)];
+
+            let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+            let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .stencil_test_enable(false);
+
+            create_pipeline::<()>(
+                context,
+                PipelineParameters {
+                    vertex_shader_params: ShaderParameters::new("test_pattern"),
+                    fragment_shader_params: ShaderParameters::new("test_pattern"),
+                    multisampling_info: &multisampling_info,
+                    viewport_info: &viewport_info,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    rasterizer_info: &rasterizer_info,
+                    dynamic_state_info: Some(&dynamic_state_info),
+                    depth_stencil_info: Some(&depth_stencil_info),
+                    stencil: None,
+                    shading_rate: None,
+                    shading_rate_attachment: false,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachment_formats: &[color_format],
+                    depth_attachment_format: depth_format,
+                    layout: pipeline_layout,
+                    parent: None,
+                    allow_derivatives: false,
+                },
+            )
+            .expect("Failed to create graphics pipeline")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Record the draw of `pattern` over the whole `extent`.
+    ///
+    /// `encode_srgb` applies the sRGB curve in the shader. It should only be set
+    /// when the output transform is [`crate::OutputTransform::ShaderSrgb`], toggling it
+    /// otherwise shows what a double or missing encoding looks like.
+    pub fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        pattern: TestPattern,
+        encode_srgb: bool,
+        opacity: f32,
+    ) {
+        let device = self.context.device();
+
+        let constants = TestPatternConstants {
+            extent: [extent.width as _, extent.height as _],
+            pattern: pattern.id(),
+            cell_size: pattern.cell_size(),
+            opacity,
+            encode_srgb: encode_srgb as _,
+        };
+
+        unsafe {
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                any_as_u8_slice(&constants),
+            );
+
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for TestPatternPass {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
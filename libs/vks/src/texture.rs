@@ -1,4 +1,7 @@
-use super::{buffer::*, context::*, image::*, util::*};
+use super::{
+    buffer::*, context::*, image::*, ktx2::*, leak_tracker::*, mipmaps::*, sampler::*,
+    texture_compression::*, util::*, VksError,
+};
 use ash::vk;
 use std::{mem::size_of_val, sync::Arc};
 
@@ -7,14 +10,18 @@ pub struct Texture {
     pub image: Image,
     pub view: vk::ImageView,
     pub sampler: Option<vk::Sampler>,
+    /// The sampler comes from the [`SamplerCache`] which destroys it.
+    cached_sampler: bool,
+    /// Depth only view of depth stencil textures, see [`Texture::sampling_view`].
+    depth_view: Option<vk::ImageView>,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct SamplerParameters {
     pub mag_filter: vk::Filter,
     pub min_filter: vk::Filter,
+    /// Up to the max anisotropy of the [`SamplerCache`].
     pub anisotropy_enabled: bool,
-    pub max_anisotropy: f32,
 }
 
 impl Default for SamplerParameters {
@@ -23,7 +30,6 @@ impl Default for SamplerParameters {
             mag_filter: vk::Filter::LINEAR,
             min_filter: vk::Filter::LINEAR,
             anisotropy_enabled: false,
-            max_anisotropy: 0.0,
         }
     }
 }
@@ -35,25 +41,75 @@ impl Texture {
         view: vk::ImageView,
         sampler: Option<vk::Sampler>,
     ) -> Self {
+        track_create(TrackedResource::Texture);
+
+        // Views sampling depth stencil images must select a single aspect.
+        let is_depth_stencil =
+            has_depth_component(image.format) && has_stencil_component(image.format);
+        let depth_view = is_depth_stencil.then(|| {
+            let view_type = match image.layers {
+                1 => vk::ImageViewType::TYPE_2D,
+                _ => vk::ImageViewType::TYPE_2D_ARRAY,
+            };
+            image.create_view(view_type, vk::ImageAspectFlags::DEPTH)
+        });
+
         Texture {
             context,
             image,
             view,
             sampler,
+            cached_sampler: false,
+            depth_view,
         }
     }
 
+    /// Create a texture whose sampler is owned by the [`SamplerCache`] of `context`.
+    pub fn with_cached_sampler(
+        context: Arc<Context>,
+        image: Image,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Self {
+        let mut texture = Self::new(context, image, view, Some(sampler));
+        texture.cached_sampler = true;
+        texture
+    }
+
+    /// Blocks until the upload is complete, see [`crate::UploadContext`] to stream uploads.
+    ///
+    /// Fails with [`VksError::UnsupportedFeature`] if the image is larger than
+    /// the device allows.
+    ///
+    /// # Panics
+    ///
+    /// If `data` does not hold the `width * height` rgba texels.
     pub fn from_rgba(
         context: &Arc<Context>,
         width: u32,
         height: u32,
         data: &[u8],
         linear: bool,
-    ) -> Self {
+    ) -> Result<Self, VksError> {
+        assert_eq!(
+            data.len(),
+            width as usize * height as usize * 4,
+            "Texel data does not match a {}x{} rgba image",
+            width,
+            height
+        );
+        let max_dimension = context.physical_device_limits().max_image_dimension2_d;
+        if width > max_dimension || height > max_dimension {
+            return Err(VksError::UnsupportedFeature(format!(
+                "Texture of {}x{} texels, the device allows at most {} per dimension",
+                width, height, max_dimension
+            )));
+        }
+
         let (texture, _) = context.execute_one_time_commands(|command_buffer| {
             Self::cmd_from_rgba(context, command_buffer, width, height, data, linear)
         });
-        texture
+        Ok(texture)
     }
 
     pub fn cmd_from_rgba(
@@ -67,7 +123,6 @@ impl Texture {
         let max_mip_levels = ((width.min(height) as f32).log2().floor() + 1.0) as u32;
         let extent = vk::Extent2D { width, height };
         let image_size = size_of_val(data) as vk::DeviceSize;
-        let device = context.device();
 
         let mut buffer = Buffer::create(
             Arc::clone(context),
@@ -81,11 +136,7 @@ impl Texture {
             mem_copy(ptr, data);
         }
 
-        let format = if linear {
-            vk::Format::R8G8B8A8_UNORM
-        } else {
-            vk::Format::R8G8B8A8_SRGB
-        };
+        let format = context.color_policy().texture_format(linear);
 
         let image = Image::create(
             Arc::clone(context),
@@ -96,7 +147,8 @@ impl Texture {
                 mip_levels: max_mip_levels,
                 usage: vk::ImageUsageFlags::TRANSFER_SRC
                     | vk::ImageUsageFlags::TRANSFER_DST
-                    | vk::ImageUsageFlags::SAMPLED,
+                    | vk::ImageUsageFlags::SAMPLED
+                    | mipmap_usage(context, format),
                 ..Default::default()
             },
         );
@@ -117,32 +169,148 @@ impl Texture {
 
         let image_view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::COLOR);
 
-        let sampler = {
-            let sampler_info = vk::SamplerCreateInfo::default()
-                .mag_filter(vk::Filter::LINEAR)
-                .min_filter(vk::Filter::LINEAR)
-                .address_mode_u(vk::SamplerAddressMode::REPEAT)
-                .address_mode_v(vk::SamplerAddressMode::REPEAT)
-                .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .anisotropy_enable(true)
-                .max_anisotropy(16.0)
-                .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
-                .unnormalized_coordinates(false)
-                .compare_enable(false)
-                .compare_op(vk::CompareOp::ALWAYS)
-                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                .mip_lod_bias(0.0)
-                .min_lod(0.0)
-                .max_lod(max_mip_levels as _);
+        let sampler = context.get_sampler(SamplerKey::repeat(max_mip_levels));
+
+        let texture = Texture::with_cached_sampler(Arc::clone(context), image, image_view, sampler);
+
+        (texture, buffer)
+    }
+
+    /// Create a texture from block compressed data and its mips.
+    ///
+    /// Like [`Texture::cmd_from_rgba`], `linear` textures hold data and are never decoded.
+    pub fn cmd_from_compressed(
+        context: &Arc<Context>,
+        command_buffer: vk::CommandBuffer,
+        compressed: &CompressedImage,
+        linear: bool,
+    ) -> (Self, Buffer) {
+        let srgb = context.color_policy().texture_format(linear) == vk::Format::R8G8B8A8_SRGB;
+        let format = compressed.format.vk_format(srgb);
+        Self::cmd_from_mips(
+            context,
+            command_buffer,
+            format,
+            compressed.mip_extent(0),
+            &compressed.mips,
+        )
+    }
+
+    /// Parse a KTX2 file and upload its mips, blocking until the upload is complete.
+    ///
+    /// The texture keeps the format of the file, see [`Ktx2Image::parse`] for
+    /// the files that can be loaded.
+    pub fn from_ktx2(context: &Arc<Context>, bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        let ktx2 = Ktx2Image::parse(context, bytes)?;
+        let (texture, _) = context.execute_one_time_commands(|command_buffer| {
+            Self::cmd_from_ktx2(context, command_buffer, &ktx2)
+        });
+        Ok(texture)
+    }
+
+    pub fn cmd_from_ktx2(
+        context: &Arc<Context>,
+        command_buffer: vk::CommandBuffer,
+        ktx2: &Ktx2Image,
+    ) -> (Self, Buffer) {
+        Self::cmd_from_mips(
+            context,
+            command_buffer,
+            ktx2.format,
+            ktx2.mip_extent(0),
+            &ktx2.mips,
+        )
+    }
+
+    /// Create a texture of `format` from the data of each of its mips, the first being `extent`.
+    fn cmd_from_mips(
+        context: &Arc<Context>,
+        command_buffer: vk::CommandBuffer,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        mips: &[Vec<u8>],
+    ) -> (Self, Buffer) {
+        let mip_levels = mips.len() as u32;
+        let data = mips.concat();
+        let device = context.device();
+
+        let mut buffer = Buffer::create(
+            Arc::clone(context),
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        unsafe {
+            let ptr = buffer.map_memory();
+            mem_copy(ptr, &data);
+        }
+
+        let image = Image::create(
+            Arc::clone(context),
+            ImageParameters {
+                mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                extent,
+                format,
+                mip_levels,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                ..Default::default()
+            },
+        );
+
+        // Mips can't be generated by blitting compressed images so each one is copied.
+        {
+            image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+
+            let mut offset = 0;
+            let regions = mips
+                .iter()
+                .enumerate()
+                .map(|(mip_level, mip)| {
+                    let region = vk::BufferImageCopy::default()
+                        .buffer_offset(offset)
+                        .image_subresource(vk::ImageSubresourceLayers {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            mip_level: mip_level as _,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        })
+                        .image_extent(vk::Extent3D {
+                            width: (extent.width >> mip_level).max(1),
+                            height: (extent.height >> mip_level).max(1),
+                            depth: 1,
+                        });
+                    offset += mip.len() as vk::DeviceSize;
+                    region
+                })
+                .collect::<Vec<_>>();
 
             unsafe {
-                device
-                    .create_sampler(&sampler_info, None)
-                    .expect("Failed to create sampler")
-            }
-        };
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    buffer.buffer,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                )
+            };
+
+            image.cmd_transition_image_layout(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        }
+
+        let image_view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::COLOR);
 
-        let texture = Texture::new(Arc::clone(context), image, image_view, Some(sampler));
+        let sampler = context.get_sampler(SamplerKey::repeat(mip_levels));
+
+        let texture = Texture::with_cached_sampler(Arc::clone(context), image, image_view, sampler);
 
         (texture, buffer)
     }
@@ -162,7 +330,6 @@ impl Texture {
         };
         let extent = vk::Extent2D { width, height };
         let image_size = size_of_val(data) as vk::DeviceSize;
-        let device = context.device();
 
         let mut buffer = Buffer::create(
             Arc::clone(context),
@@ -176,10 +343,12 @@ impl Texture {
             mem_copy(ptr, data);
         }
 
+        let format = vk::Format::R32G32B32A32_SFLOAT;
         let usage = if with_mipmaps {
             vk::ImageUsageFlags::TRANSFER_SRC
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::SAMPLED
+                | mipmap_usage(context, format)
         } else {
             vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED
         };
@@ -189,7 +358,7 @@ impl Texture {
             ImageParameters {
                 mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
                 extent,
-                format: vk::Format::R32G32B32A32_SFLOAT,
+                format,
                 mip_levels: max_mip_levels,
                 usage,
                 ..Default::default()
@@ -220,31 +389,16 @@ impl Texture {
 
         let sampler = {
             let params = sampler_parameters.unwrap_or_default();
-            let sampler_info = vk::SamplerCreateInfo::default()
-                .mag_filter(params.mag_filter)
-                .min_filter(params.min_filter)
-                .address_mode_u(vk::SamplerAddressMode::REPEAT)
-                .address_mode_v(vk::SamplerAddressMode::REPEAT)
-                .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .anisotropy_enable(params.anisotropy_enabled)
-                .max_anisotropy(params.max_anisotropy)
-                .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK)
-                .unnormalized_coordinates(false)
-                .compare_enable(false)
-                .compare_op(vk::CompareOp::ALWAYS)
-                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                .mip_lod_bias(0.0)
-                .min_lod(0.0)
-                .max_lod(max_mip_levels as _);
-
-            unsafe {
-                device
-                    .create_sampler(&sampler_info, None)
-                    .expect("Failed to create sampler")
-            }
+            context.get_sampler(SamplerKey {
+                mag_filter: params.mag_filter,
+                min_filter: params.min_filter,
+                anisotropy: params.anisotropy_enabled,
+                border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+                ..SamplerKey::repeat(max_mip_levels)
+            })
         };
 
-        Texture::new(Arc::clone(context), image, image_view, Some(sampler))
+        Texture::with_cached_sampler(Arc::clone(context), image, image_view, sampler)
     }
 
     pub fn create_renderable_cubemap(
@@ -271,7 +425,8 @@ impl Texture {
                 usage: vk::ImageUsageFlags::TRANSFER_SRC
                     | vk::ImageUsageFlags::TRANSFER_DST
                     | vk::ImageUsageFlags::SAMPLED
-                    | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                    | vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | mipmap_usage(context, format),
                 create_flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
                 ..Default::default()
             },
@@ -369,13 +524,80 @@ impl Texture {
     }
 }
 
+impl Texture {
+    /// View to sample the texture with.
+    ///
+    /// The attachment view of depth stencil textures covers both aspects and
+    /// cannot be sampled, they are sampled through a depth only view instead.
+    pub fn sampling_view(&self) -> vk::ImageView {
+        self.depth_view.unwrap_or(self.view)
+    }
+
+    /// Layout of the texture when it is sampled.
+    ///
+    /// Depth textures are sampled in `DEPTH_STENCIL_READ_ONLY_OPTIMAL` layout
+    /// so post passes, SSAO or fog for example, can sample the scene depth
+    /// while the forward transparent pass still depth tests against it with
+    /// depth writes disabled.
+    pub fn sampling_layout(&self) -> vk::ImageLayout {
+        if has_depth_component(self.image.format) {
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+    }
+
+    /// Name the image and the views of the texture in the debug tools.
+    ///
+    /// Cached samplers are shared between textures and keep their name.
+    pub fn set_name(&self, name: &str) {
+        self.image.set_name(name);
+        self.context
+            .set_object_name(self.view, &format!("{} view", name));
+        if let Some(depth_view) = self.depth_view {
+            self.context
+                .set_object_name(depth_view, &format!("{} depth view", name));
+        }
+        if let Some(sampler) = self.sampler.filter(|_| !self.cached_sampler) {
+            self.context
+                .set_object_name(sampler, &format!("{} sampler", name));
+        }
+    }
+}
+
 impl Drop for Texture {
     fn drop(&mut self) {
         unsafe {
-            if let Some(sampler) = self.sampler.take() {
+            if let Some(sampler) = self.sampler.take().filter(|_| !self.cached_sampler) {
                 self.context.device().destroy_sampler(sampler, None);
             }
+            if let Some(depth_view) = self.depth_view.take() {
+                self.context.device().destroy_image_view(depth_view, None);
+            }
             self.context.device().destroy_image_view(self.view, None);
         }
+        track_destroy(TrackedResource::Texture);
     }
 }
+
+/// Create a 2d texture of `extent` with a single mip and its view.
+pub(crate) fn create_texture(
+    context: &Arc<Context>,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    sampler: Option<vk::Sampler>,
+) -> Texture {
+    let image = Image::create(
+        Arc::clone(context),
+        ImageParameters {
+            mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            extent,
+            format,
+            usage,
+            ..Default::default()
+        },
+    );
+    let view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::COLOR);
+    Texture::new(Arc::clone(context), image, view, sampler)
+}
@@ -0,0 +1,896 @@
+use crate::Context;
+use ash::vk;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+const CACHE_MAGIC: &[u8; 4] = b"BCN\x01";
+const CACHE_EXTENSION: &str = "bcn";
+/// Weights of the 16 colors interpolated between the endpoints of a BC7 mode 6 block, out of 64.
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+const PRINCIPAL_AXIS_ITERATIONS: usize = 8;
+const REFINE_ITERATIONS: usize = 2;
+
+/// Block compressed formats textures can be encoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFormat {
+    /// One channel, for masks and occlusion.
+    Bc4,
+    /// Two channels, for normal maps whose z is reconstructed in the shader.
+    Bc5,
+    /// Four channels, for colors and packed data.
+    Bc7,
+}
+
+impl BlockFormat {
+    /// Size of a block of 4x4 texels in bytes.
+    pub fn block_size(self) -> usize {
+        match self {
+            Self::Bc4 => 8,
+            Self::Bc5 | Self::Bc7 => 16,
+        }
+    }
+
+    /// Only BC7 has an sRGB variant, other formats always hold data.
+    pub fn vk_format(self, srgb: bool) -> vk::Format {
+        match (self, srgb) {
+            (Self::Bc4, _) => vk::Format::BC4_UNORM_BLOCK,
+            (Self::Bc5, _) => vk::Format::BC5_UNORM_BLOCK,
+            (Self::Bc7, false) => vk::Format::BC7_UNORM_BLOCK,
+            (Self::Bc7, true) => vk::Format::BC7_SRGB_BLOCK,
+        }
+    }
+
+    fn id(self) -> u32 {
+        match self {
+            Self::Bc4 => 4,
+            Self::Bc5 => 5,
+            Self::Bc7 => 7,
+        }
+    }
+
+    fn from_id(id: u32) -> Option<Self> {
+        match id {
+            4 => Some(Self::Bc4),
+            5 => Some(Self::Bc5),
+            7 => Some(Self::Bc7),
+            _ => None,
+        }
+    }
+}
+
+/// What a texture holds, deciding the format it is compressed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureUsageHint {
+    Color,
+    /// Data sampled as is, like packed metallic and roughness.
+    Data,
+    NormalMap,
+    /// Only the red channel is used.
+    SingleChannel,
+}
+
+impl TextureUsageHint {
+    pub fn block_format(self) -> BlockFormat {
+        match self {
+            Self::Color | Self::Data => BlockFormat::Bc7,
+            Self::NormalMap => BlockFormat::Bc5,
+            Self::SingleChannel => BlockFormat::Bc4,
+        }
+    }
+}
+
+/// Trade off between the time spent compressing and the quality of the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CompressionQuality {
+    /// Endpoints along the principal axis of each block.
+    #[default]
+    Fast,
+    /// Also refine the endpoints of BC7 blocks and try all their p-bits.
+    High,
+}
+
+impl CompressionQuality {
+    fn id(self) -> u8 {
+        match self {
+            Self::Fast => 0,
+            Self::High => 1,
+        }
+    }
+}
+
+/// Settings of the compression of textures at load time.
+///
+/// Compression is done on the cpu, once per texture when a cache directory is
+/// set. Disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct TextureCompression {
+    pub enabled: bool,
+    pub quality: CompressionQuality,
+    /// Directory where compressed textures are stored, keyed by their content.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl TextureCompression {
+    /// True if enabled and BC formats can be sampled by `context`.
+    pub fn is_enabled(&self, context: &Context) -> bool {
+        self.enabled && supports_texture_compression(context)
+    }
+
+    /// Compress the `rgba` texels of a texture and its mips, or load them from the cache.
+    pub fn compress(
+        &self,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        hint: TextureUsageHint,
+    ) -> CompressedImage {
+        let format = hint.block_format();
+        let cache_path = self.cache_dir.as_ref().map(|dir| {
+            let key = cache_key(width, height, rgba, format, self.quality);
+            dir.join(format!("{:016x}.{}", key, CACHE_EXTENSION))
+        });
+
+        if let Some(path) = cache_path.as_ref() {
+            if let Ok(image) = CompressedImage::read(path) {
+                tracing::debug!("Loaded compressed texture from {}", path.display());
+                return image;
+            }
+        }
+
+        let start = Instant::now();
+        let image = CompressedImage::compress(width, height, rgba, format, self.quality);
+        tracing::debug!(
+            "Compressed {}x{} texture to {:?} in {:?}",
+            width,
+            height,
+            format,
+            start.elapsed()
+        );
+
+        if let Some(path) = cache_path.as_ref() {
+            let result = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| image.write(path));
+            if let Err(error) = result {
+                tracing::warn!(
+                    "Failed to cache compressed texture {}: {}",
+                    path.display(),
+                    error
+                );
+            }
+        }
+
+        image
+    }
+}
+
+/// True if the device can sample BC compressed textures.
+pub fn supports_texture_compression(context: &Context) -> bool {
+    let features = unsafe {
+        context
+            .instance()
+            .get_physical_device_features(context.physical_device())
+    };
+    features.texture_compression_bc == vk::TRUE
+}
+
+/// Blocks of each mip level of a compressed texture, the first being the full size.
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    /// Compress `rgba` and its mips, down to the level whose smallest side is one texel.
+    pub fn compress(
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        format: BlockFormat,
+        quality: CompressionQuality,
+    ) -> Self {
+        let mip_levels = mip_level_count(width, height);
+
+        let mut mips = Vec::with_capacity(mip_levels as usize);
+        let mut level = (width, height, rgba.to_vec());
+        for mip in 0..mip_levels {
+            mips.push(compress_level(level.0, level.1, &level.2, format, quality));
+            if mip + 1 < mip_levels {
+                level = downsample(level.0, level.1, &level.2);
+            }
+        }
+
+        Self {
+            format,
+            width,
+            height,
+            mips,
+        }
+    }
+
+    /// Extent of `mip_level`.
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.width >> mip_level).max(1),
+            height: (self.height >> mip_level).max(1),
+        }
+    }
+
+    fn read(path: &Path) -> io::Result<Self> {
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != CACHE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a compressed texture",
+            ));
+        }
+
+        let mut read_u32 = || -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            file.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let format = BlockFormat::from_id(read_u32()?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown block format"))?;
+        let width = read_u32()?;
+        let height = read_u32()?;
+        let mip_levels = read_u32()?;
+        if width == 0 || height == 0 || mip_levels != mip_level_count(width, height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid texture extent",
+            ));
+        }
+        let lengths = (0..mip_levels)
+            .map(|_| read_u32())
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut image = Self {
+            format,
+            width,
+            height,
+            mips: Vec::with_capacity(lengths.len()),
+        };
+        for (mip_level, length) in lengths.into_iter().enumerate() {
+            // Checked before allocating, a corrupted length could be anything.
+            if length as usize != image.mip_size(mip_level as u32) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Mip length does not match its block count",
+                ));
+            }
+            let mut mip = vec![0; length as usize];
+            file.read_exact(&mut mip)?;
+            image.mips.push(mip);
+        }
+
+        Ok(image)
+    }
+
+    /// Size in bytes of the blocks of `mip_level`.
+    fn mip_size(&self, mip_level: u32) -> usize {
+        let extent = self.mip_extent(mip_level);
+        (extent.width.div_ceil(4) * extent.height.div_ceil(4)) as usize * self.format.block_size()
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        file.write_all(CACHE_MAGIC)?;
+        for value in [
+            self.format.id(),
+            self.width,
+            self.height,
+            self.mips.len() as u32,
+        ] {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        for mip in self.mips.iter() {
+            file.write_all(&(mip.len() as u32).to_le_bytes())?;
+        }
+        for mip in self.mips.iter() {
+            file.write_all(mip)?;
+        }
+        file.flush()
+    }
+}
+
+/// Number of mips down to the level whose smallest side is one texel.
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.min(height).max(1).ilog2() + 1
+}
+
+/// FNV-1a hash of the texels and the compression parameters.
+fn cache_key(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    format: BlockFormat,
+    quality: CompressionQuality,
+) -> u64 {
+    let header = [
+        &width.to_le_bytes()[..],
+        &height.to_le_bytes(),
+        &format.id().to_le_bytes(),
+        &[quality.id()],
+    ];
+    header
+        .into_iter()
+        .flatten()
+        .chain(rgba)
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Average each 2x2 texels, the last row or column is repeated for odd sizes.
+fn downsample(width: u32, height: u32, rgba: &[u8]) -> (u32, u32, Vec<u8>) {
+    let next_width = (width / 2).max(1);
+    let next_height = (height / 2).max(1);
+    let texel = |x: u32, y: u32, channel: usize| {
+        let x = x.min(width - 1) as usize;
+        let y = y.min(height - 1) as usize;
+        rgba[(y * width as usize + x) * 4 + channel] as u32
+    };
+
+    let mut data = Vec::with_capacity((next_width * next_height * 4) as usize);
+    for y in 0..next_height {
+        for x in 0..next_width {
+            for channel in 0..4 {
+                let sum = texel(x * 2, y * 2, channel)
+                    + texel(x * 2 + 1, y * 2, channel)
+                    + texel(x * 2, y * 2 + 1, channel)
+                    + texel(x * 2 + 1, y * 2 + 1, channel);
+                data.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    (next_width, next_height, data)
+}
+
+fn compress_level(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    format: BlockFormat,
+    quality: CompressionQuality,
+) -> Vec<u8> {
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+    let mut data = Vec::with_capacity((blocks_x * blocks_y) as usize * format.block_size());
+
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let texels = fetch_block(width, height, rgba, block_x * 4, block_y * 4);
+            match format {
+                BlockFormat::Bc4 => data.extend_from_slice(&encode_bc4(channel(&texels, 0))),
+                BlockFormat::Bc5 => {
+                    data.extend_from_slice(&encode_bc4(channel(&texels, 0)));
+                    data.extend_from_slice(&encode_bc4(channel(&texels, 1)));
+                }
+                BlockFormat::Bc7 => data.extend_from_slice(&encode_bc7(&texels, quality)),
+            }
+        }
+    }
+    data
+}
+
+/// Texels of the block at `x`, `y`, the edge texels are repeated outside the image.
+fn fetch_block(width: u32, height: u32, rgba: &[u8], x: u32, y: u32) -> [[u8; 4]; 16] {
+    let mut texels = [[0; 4]; 16];
+    for (index, texel) in texels.iter_mut().enumerate() {
+        let tx = (x + index as u32 % 4).min(width - 1) as usize;
+        let ty = (y + index as u32 / 4).min(height - 1) as usize;
+        let offset = (ty * width as usize + tx) * 4;
+        texel.copy_from_slice(&rgba[offset..offset + 4]);
+    }
+    texels
+}
+
+fn channel(texels: &[[u8; 4]; 16], channel: usize) -> [u8; 16] {
+    texels.map(|texel| texel[channel])
+}
+
+/// Encode a BC4 block with its extremes as endpoints, in the 8 values mode.
+fn encode_bc4(values: [u8; 16]) -> [u8; 8] {
+    let max = *values.iter().max().unwrap();
+    let min = *values.iter().min().unwrap();
+
+    let mut block = [0; 8];
+    block[0] = max;
+    block[1] = min;
+    if max == min {
+        return block;
+    }
+
+    let mut palette = [max as u32, min as u32, 0, 0, 0, 0, 0, 0];
+    for (index, value) in palette.iter_mut().enumerate().skip(2) {
+        let weight = index as u32 - 1;
+        *value = ((7 - weight) * max as u32 + weight * min as u32 + 3) / 7;
+    }
+
+    let mut indices = 0u64;
+    for (texel, value) in values.iter().enumerate() {
+        let index = palette
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, p)| (**p as i32 - *value as i32).abs())
+            .unwrap()
+            .0;
+        indices |= (index as u64) << (texel * 3);
+    }
+    block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+/// Endpoints of a BC7 mode 6 block, quantized to 7 bits plus a shared p-bit.
+#[derive(Clone, Copy)]
+struct Bc7Endpoints {
+    codes: [[u8; 4]; 2],
+    pbits: [u8; 2],
+}
+
+impl Bc7Endpoints {
+    fn quantize(endpoints: [[f32; 4]; 2], pbits: [u8; 2]) -> Self {
+        let mut codes = [[0; 4]; 2];
+        for (endpoint, code) in codes.iter_mut().enumerate() {
+            for (channel, code) in code.iter_mut().enumerate() {
+                let value = (endpoints[endpoint][channel] - pbits[endpoint] as f32) / 2.0;
+                *code = value.round().clamp(0.0, 127.0) as u8;
+            }
+        }
+        Self { codes, pbits }
+    }
+
+    /// Quantize with the p-bit of each endpoint giving the smallest error.
+    fn quantize_best_pbits(endpoints: [[f32; 4]; 2]) -> Self {
+        let mut pbits = [0; 2];
+        for (endpoint, pbit) in pbits.iter_mut().enumerate() {
+            let error = |p: u8| {
+                let quantized = Self::quantize([endpoints[endpoint]; 2], [p; 2]).value(0);
+                (0..4)
+                    .map(|c| (quantized[c] as f32 - endpoints[endpoint][c]).powi(2))
+                    .sum::<f32>()
+            };
+            *pbit = (error(1) < error(0)) as u8;
+        }
+        Self::quantize(endpoints, pbits)
+    }
+
+    fn value(&self, endpoint: usize) -> [u32; 4] {
+        self.codes[endpoint].map(|code| ((code as u32) << 1) | self.pbits[endpoint] as u32)
+    }
+
+    fn palette(&self) -> [[u32; 4]; 16] {
+        let e0 = self.value(0);
+        let e1 = self.value(1);
+        BC7_WEIGHTS.map(|w| std::array::from_fn(|c| ((64 - w) * e0[c] + w * e1[c] + 32) >> 6))
+    }
+
+    /// Index of the closest palette color of each texel and the total squared error.
+    fn fit(&self, texels: &[[u8; 4]; 16]) -> ([u8; 16], u32) {
+        let palette = self.palette();
+        let mut indices = [0; 16];
+        let mut total = 0;
+        for (texel, index) in texels.iter().zip(indices.iter_mut()) {
+            let (best, error) = palette
+                .iter()
+                .map(|color| {
+                    (0..4)
+                        .map(|c| (color[c] as i32 - texel[c] as i32).pow(2) as u32)
+                        .sum::<u32>()
+                })
+                .enumerate()
+                .min_by_key(|(_, error)| *error)
+                .unwrap();
+            *index = best as u8;
+            total += error;
+        }
+        (indices, total)
+    }
+}
+
+/// Encode a BC7 block in mode 6, a single subset with 4 bits indices and alpha.
+fn encode_bc7(texels: &[[u8; 4]; 16], quality: CompressionQuality) -> [u8; 16] {
+    let endpoints = principal_axis_endpoints(texels);
+
+    let candidates = match quality {
+        CompressionQuality::Fast => vec![Bc7Endpoints::quantize_best_pbits(endpoints)],
+        CompressionQuality::High => [[0, 0], [0, 1], [1, 0], [1, 1]]
+            .into_iter()
+            .map(|pbits| Bc7Endpoints::quantize(endpoints, pbits))
+            .collect(),
+    };
+    let (mut best, (mut indices, mut error)) = candidates
+        .into_iter()
+        .map(|candidate| (candidate, candidate.fit(texels)))
+        .min_by_key(|(_, (_, error))| *error)
+        .unwrap();
+
+    if quality == CompressionQuality::High {
+        for _ in 0..REFINE_ITERATIONS {
+            let Some(refined) = least_squares_endpoints(texels, &indices) else {
+                break;
+            };
+            let candidate = Bc7Endpoints::quantize_best_pbits(refined);
+            let (candidate_indices, candidate_error) = candidate.fit(texels);
+            if candidate_error >= error {
+                break;
+            }
+            best = candidate;
+            indices = candidate_indices;
+            error = candidate_error;
+        }
+    }
+
+    pack_bc7_mode6(best, indices)
+}
+
+/// Endpoints at the extremes of the projection of the texels on their principal axis.
+fn principal_axis_endpoints(texels: &[[u8; 4]; 16]) -> [[f32; 4]; 2] {
+    let colors = texels.map(|texel| texel.map(|c| c as f32));
+    let mut mean = [0.0; 4];
+    for color in colors.iter() {
+        for c in 0..4 {
+            mean[c] += color[c] / 16.0;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for color in colors.iter() {
+        let d: [f32; 4] = std::array::from_fn(|c| color[c] - mean[c]);
+        for i in 0..4 {
+            for j in 0..4 {
+                covariance[i][j] += d[i] * d[j];
+            }
+        }
+    }
+
+    // Power iteration, the dominant eigenvector of the covariance.
+    let mut axis = [1.0f32; 4];
+    for _ in 0..PRINCIPAL_AXIS_ITERATIONS {
+        let next: [f32; 4] =
+            std::array::from_fn(|i| (0..4).map(|j| covariance[i][j] * axis[j]).sum());
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < f32::EPSILON {
+            return [mean; 2];
+        }
+        axis = next.map(|v| v / length);
+    }
+
+    let (min, max) = colors
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), color| {
+            let t = (0..4).map(|c| (color[c] - mean[c]) * axis[c]).sum::<f32>();
+            (min.min(t), max.max(t))
+        });
+    [min, max].map(|t| std::array::from_fn(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0)))
+}
+
+/// Endpoints minimizing the squared error of the texels for fixed `indices`.
+fn least_squares_endpoints(texels: &[[u8; 4]; 16], indices: &[u8; 16]) -> Option<[[f32; 4]; 2]> {
+    let (mut a, mut b, mut c) = (0.0f32, 0.0f32, 0.0f32);
+    let mut rhs = [[0.0f32; 4]; 2];
+    for (texel, index) in texels.iter().zip(indices) {
+        let w = BC7_WEIGHTS[*index as usize] as f32 / 64.0;
+        a += (1.0 - w) * (1.0 - w);
+        b += (1.0 - w) * w;
+        c += w * w;
+        for channel in 0..4 {
+            rhs[0][channel] += (1.0 - w) * texel[channel] as f32;
+            rhs[1][channel] += w * texel[channel] as f32;
+        }
+    }
+
+    let determinant = a * c - b * b;
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let e0 = std::array::from_fn(|ch| {
+        ((c * rhs[0][ch] - b * rhs[1][ch]) / determinant).clamp(0.0, 255.0)
+    });
+    let e1 = std::array::from_fn(|ch| {
+        ((a * rhs[1][ch] - b * rhs[0][ch]) / determinant).clamp(0.0, 255.0)
+    });
+    Some([e0, e1])
+}
+
+fn pack_bc7_mode6(mut endpoints: Bc7Endpoints, mut indices: [u8; 16]) -> [u8; 16] {
+    // The most significant bit of the first index is implicit and must be 0.
+    if indices[0] >= 8 {
+        endpoints.codes.swap(0, 1);
+        endpoints.pbits.swap(0, 1);
+        indices = indices.map(|index| 15 - index);
+    }
+
+    let mut bits = 0u128;
+    let mut offset = 0;
+    let mut write = |value: u32, count: u32| {
+        bits |= (value as u128) << offset;
+        offset += count;
+    };
+
+    write(1 << 6, 7);
+    for channel in 0..4 {
+        write(endpoints.codes[0][channel] as u32, 7);
+        write(endpoints.codes[1][channel] as u32, 7);
+    }
+    write(endpoints.pbits[0] as u32, 1);
+    write(endpoints.pbits[1] as u32, 1);
+    for (texel, index) in indices.iter().enumerate() {
+        write(*index as u32, if texel == 0 { 3 } else { 4 });
+    }
+
+    bits.to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texels of a BC4 block, as sampled.
+    fn decode_bc4(block: &[u8]) -> [u8; 16] {
+        let (max, min) = (block[0] as u32, block[1] as u32);
+        let mut index_bytes = [0; 8];
+        index_bytes[..6].copy_from_slice(&block[2..8]);
+        let indices = u64::from_le_bytes(index_bytes);
+        std::array::from_fn(|texel| match (indices >> (texel * 3)) & 7 {
+            0 => max as u8,
+            1 => min as u8,
+            index => {
+                let weight = index as u32 - 1;
+                (((7 - weight) * max + weight * min + 3) / 7) as u8
+            }
+        })
+    }
+
+    /// Texels of a BC7 mode 6 block, as sampled.
+    fn decode_bc7_mode6(block: &[u8]) -> [[u8; 4]; 16] {
+        let bits = u128::from_le_bytes(block.try_into().unwrap());
+        let mut offset = 0;
+        let mut read = |count: u32| {
+            let value = (bits >> offset) as u32 & ((1 << count) - 1);
+            offset += count;
+            value
+        };
+
+        assert_eq!(read(7), 1 << 6, "Not a mode 6 block");
+        // Channels are interleaved, the red of both endpoints comes first.
+        let mut codes = [[0; 4]; 2];
+        for channel in 0..4 {
+            for code in codes.iter_mut() {
+                code[channel] = read(7);
+            }
+        }
+        let pbits = [read(1), read(1)];
+        let indices: [u32; 16] = std::array::from_fn(|texel| read(if texel == 0 { 3 } else { 4 }));
+
+        let endpoints: [[u32; 4]; 2] =
+            std::array::from_fn(|e| codes[e].map(|code| (code << 1) | pbits[e]));
+        indices.map(|index| {
+            let w = BC7_WEIGHTS[index as usize];
+            std::array::from_fn(|c| {
+                (((64 - w) * endpoints[0][c] + w * endpoints[1][c] + 32) >> 6) as u8
+            })
+        })
+    }
+
+    fn max_error(a: &[[u8; 4]; 16], b: &[[u8; 4]; 16]) -> u8 {
+        a.iter()
+            .zip(b)
+            .flat_map(|(a, b)| a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)))
+            .max()
+            .unwrap()
+    }
+
+    /// Texels along a diagonal of the color cube, with an opaque alpha.
+    fn gradient_block() -> [[u8; 4]; 16] {
+        std::array::from_fn(|texel| {
+            let value = texel as u8 * 16;
+            [value, value / 2, 255 - value, 255]
+        })
+    }
+
+    fn gradient_image(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|index| {
+                let (x, y) = (index % width, index / width);
+                [(x * 16) as u8, (y * 16) as u8, 128, 255]
+            })
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn bc4_keeps_flat_blocks_exact() {
+        let block = encode_bc4([77; 16]);
+        assert_eq!(block, [77, 77, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(decode_bc4(&block), [77; 16]);
+    }
+
+    #[test]
+    fn bc4_encodes_within_half_a_palette_step() {
+        let values: [u8; 16] = std::array::from_fn(|texel| 10 + texel as u8 * 13);
+        let block = encode_bc4(values);
+
+        assert_eq!((block[0], block[1]), (205, 10));
+        let decoded = decode_bc4(&block);
+        for (value, decoded) in values.iter().zip(decoded) {
+            // The palette has 8 values, about 28 apart.
+            assert!(
+                value.abs_diff(decoded) <= 14,
+                "{} decoded as {}",
+                value,
+                decoded
+            );
+        }
+    }
+
+    #[test]
+    fn bc7_blocks_are_mode_6_with_an_implicit_index_bit() {
+        let texels = gradient_block();
+        for quality in [CompressionQuality::Fast, CompressionQuality::High] {
+            let block = encode_bc7(&texels, quality);
+            assert_eq!(block[0] & 0x7f, 1 << 6);
+            assert!(max_error(&decode_bc7_mode6(&block), &texels) <= 12);
+        }
+    }
+
+    #[test]
+    fn bc7_keeps_flat_blocks_close() {
+        let texels = [[200, 100, 30, 128]; 16];
+        let block = encode_bc7(&texels, CompressionQuality::Fast);
+        assert!(max_error(&decode_bc7_mode6(&block), &texels) <= 1);
+    }
+
+    #[test]
+    fn high_quality_is_not_worse() {
+        let texels: [[u8; 4]; 16] = std::array::from_fn(|texel| {
+            let t = texel as u8;
+            [t * 15, 255 - t * 7, (t % 4) * 60, 200 + t]
+        });
+        let error = |quality| {
+            let decoded = decode_bc7_mode6(&encode_bc7(&texels, quality));
+            decoded
+                .iter()
+                .zip(&texels)
+                .flat_map(|(a, b)| a.iter().zip(b).map(|(a, b)| (*a as i32 - *b as i32).pow(2)))
+                .sum::<i32>()
+        };
+        assert!(error(CompressionQuality::High) <= error(CompressionQuality::Fast));
+    }
+
+    #[test]
+    fn downsample_averages_and_repeats_the_edges() {
+        #[rustfmt::skip]
+        let rgba = [
+            0, 0, 0, 0,   4, 4, 4, 4,   100, 0, 0, 0,
+            8, 8, 8, 8,   12, 12, 12, 12,   200, 0, 0, 0,
+        ];
+        let (width, height, data) = downsample(3, 2, &rgba);
+
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(data, [6, 6, 6, 6]);
+
+        let (width, height, data) = downsample(1, 1, &[9, 9, 9, 9]);
+        assert_eq!((width, height, data), (1, 1, vec![9, 9, 9, 9]));
+    }
+
+    #[test]
+    fn compress_fills_the_blocks_of_each_mip() {
+        let image = CompressedImage::compress(
+            13,
+            7,
+            &gradient_image(13, 7),
+            BlockFormat::Bc5,
+            CompressionQuality::Fast,
+        );
+
+        // 13x7, 6x3 and 3x1 texels.
+        let lengths = image.mips.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, [4 * 2 * 16, 2 * 16, 16]);
+        assert_eq!(
+            image.mip_extent(2),
+            vk::Extent2D {
+                width: 3,
+                height: 1
+            }
+        );
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(1024, 256), 9);
+    }
+
+    #[test]
+    fn cache_key_depends_on_the_parameters() {
+        let rgba = gradient_image(4, 4);
+        let key = cache_key(4, 4, &rgba, BlockFormat::Bc7, CompressionQuality::Fast);
+
+        assert_eq!(
+            key,
+            cache_key(4, 4, &rgba, BlockFormat::Bc7, CompressionQuality::Fast)
+        );
+        assert_ne!(
+            key,
+            cache_key(4, 4, &rgba, BlockFormat::Bc7, CompressionQuality::High)
+        );
+        assert_ne!(
+            key,
+            cache_key(4, 4, &rgba, BlockFormat::Bc5, CompressionQuality::Fast)
+        );
+        assert_ne!(
+            key,
+            cache_key(2, 8, &rgba, BlockFormat::Bc7, CompressionQuality::Fast)
+        );
+    }
+
+    #[test]
+    fn compressed_images_are_cached() {
+        let dir = temp_dir("bcn-cache");
+        let compression = TextureCompression {
+            enabled: true,
+            quality: CompressionQuality::Fast,
+            cache_dir: Some(dir.clone()),
+        };
+        let rgba = gradient_image(8, 8);
+
+        let image = compression.compress(8, 8, &rgba, TextureUsageHint::Color);
+        let entries = fs::read_dir(&dir).unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+
+        let cached = CompressedImage::read(&entries[0].as_ref().unwrap().path()).unwrap();
+        assert_eq!(cached.format, BlockFormat::Bc7);
+        assert_eq!((cached.width, cached.height), (8, 8));
+        assert_eq!(cached.mips, image.mips);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_rejects_lengths_not_matching_the_blocks() {
+        let dir = temp_dir("bcn-lengths");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.bcn");
+        let image = CompressedImage::compress(
+            8,
+            4,
+            &gradient_image(8, 4),
+            BlockFormat::Bc4,
+            CompressionQuality::Fast,
+        );
+        image.write(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        // Magic, format, width, height, mip count then the lengths.
+        let lengths_offset = 4 * 5;
+
+        let mut corrupted = bytes.clone();
+        corrupted[lengths_offset..lengths_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &corrupted).unwrap();
+        let error = CompressedImage::read(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut corrupted = bytes.clone();
+        corrupted[16..20].copy_from_slice(&40u32.to_le_bytes());
+        fs::write(&path, &corrupted).unwrap();
+        let error = CompressedImage::read(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let error = CompressedImage::read(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
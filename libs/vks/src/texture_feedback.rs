@@ -0,0 +1,520 @@
+use crate::{
+    create_compute_pipeline, Buffer, ComputePipelineParameters, Context, Descriptors, Image,
+    ShaderParameters,
+};
+use ash::vk;
+use std::{fmt, mem::size_of, sync::Arc};
+
+/// Number of mips a feedback entry can refer to.
+pub const MAX_FEEDBACK_MIPS: u32 = 16;
+/// Value of a feedback entry no material wrote to since the last reduction.
+pub const EMPTY_FEEDBACK_ENTRY: u32 = u32::MAX;
+
+const REDUCE_GROUP_SIZE: u32 = 64;
+const REDUCE_SHADER_NAME: &str = "texture_feedback";
+/// Mip mask and number of feedback entries of each texture.
+const USAGE_WORDS_PER_TEXTURE: usize = 2;
+
+/// Usage of a texture registered in a [`TextureFeedback`].
+#[derive(Debug, Clone)]
+pub struct TextureUsage {
+    pub name: String,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+    pub texel_size: u32,
+    /// Mask of the mips sampled during the last collected frame.
+    pub frame_mips: u32,
+    /// Mask of the mips sampled since the last reset of the statistics.
+    pub sampled_mips: u32,
+    /// Number of frames the texture was sampled in.
+    pub frames_sampled: u64,
+    /// Number of feedback entries written for the texture.
+    pub entries: u64,
+}
+
+impl TextureUsage {
+    /// Most detailed mip sampled since the last reset, `None` if never sampled.
+    ///
+    /// The mips before it could be evicted without visible difference.
+    pub fn finest_sampled_mip(&self) -> Option<u32> {
+        (self.sampled_mips != 0).then(|| self.sampled_mips.trailing_zeros())
+    }
+
+    /// Size in bytes of `mip`.
+    pub fn mip_size(&self, mip: u32) -> u64 {
+        let width = (self.extent.width >> mip).max(1) as u64;
+        let height = (self.extent.height >> mip).max(1) as u64;
+        width * height * self.texel_size as u64
+    }
+
+    /// Size in bytes of all the mips of the texture.
+    pub fn resident_size(&self) -> u64 {
+        (0..self.mip_levels).map(|mip| self.mip_size(mip)).sum()
+    }
+
+    /// Size in bytes of the mips more detailed than the finest sampled one.
+    ///
+    /// The whole texture is wasted if it was never sampled.
+    pub fn wasted_size(&self) -> u64 {
+        let finest = self.finest_sampled_mip().unwrap_or(self.mip_levels);
+        (0..finest.min(self.mip_levels))
+            .map(|mip| self.mip_size(mip))
+            .sum()
+    }
+}
+
+/// Statistics of all the textures of a [`TextureFeedback`].
+#[derive(Debug, Clone)]
+pub struct TextureUsageReport {
+    pub frames: u64,
+    pub textures: Vec<TextureUsage>,
+}
+
+impl TextureUsageReport {
+    pub fn resident_size(&self) -> u64 {
+        self.textures.iter().map(TextureUsage::resident_size).sum()
+    }
+
+    pub fn wasted_size(&self) -> u64 {
+        self.textures.iter().map(TextureUsage::wasted_size).sum()
+    }
+}
+
+impl fmt::Display for TextureUsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let to_mib = |size: u64| size as f64 / (1024.0 * 1024.0);
+        writeln!(
+            f,
+            "{} frames, {:.2} MiB resident, {:.2} MiB wasted",
+            self.frames,
+            to_mib(self.resident_size()),
+            to_mib(self.wasted_size())
+        )?;
+        for texture in self.textures.iter() {
+            let finest = texture
+                .finest_sampled_mip()
+                .map_or("never sampled".to_owned(), |mip| {
+                    format!("finest mip {}", mip)
+                });
+            writeln!(
+                f,
+                "  {}: {}/{} frames, {}, mips {:#b}, {:.2} MiB wasted",
+                texture.name,
+                texture.frames_sampled,
+                self.frames,
+                finest,
+                texture.sampled_mips,
+                to_mib(texture.wasted_size())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Instrumentation of the textures and mips sampled by the materials.
+///
+/// Materials write the texture index and the mip they sample to a feedback
+/// buffer shared by all draws, with `writeTextureFeedback` from
+/// `shader/texture_feedback/feedback.glsl`. At the end of the frame a compute
+/// pass reduces the entries into a mask of the sampled mips for each texture
+/// and clears them for the next frame. The result of a frame is read back once
+/// its fence was waited for.
+///
+/// Entries are shared by screen tiles so the counts are an estimation, but a
+/// mip that is never reported is not visible on screen. The streaming of the
+/// textures can rely on it to decide which mips must be resident.
+///
+/// ```ignore
+/// wait_for_fences(frame_fence);
+/// feedback.collect(frame_index);
+/// // record the draws of the materials, bound to feedback.descriptor_info()
+/// feedback.cmd_reduce(command_buffer, frame_index);
+/// ```
+pub struct TextureFeedback {
+    context: Arc<Context>,
+    feedback: Buffer,
+    entry_count: u32,
+    usage_buffers: Vec<Buffer>,
+    max_textures: u32,
+    textures: Vec<TextureUsage>,
+    frames: u64,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl TextureFeedback {
+    /// Create the feedback buffer of `entry_count` entries and the usage
+    /// read back buffers of `max_textures` for each of the `frame_count` frames.
+    pub fn new(
+        context: &Arc<Context>,
+        entry_count: u32,
+        max_textures: u32,
+        frame_count: usize,
+    ) -> Self {
+        let feedback_size = (entry_count as usize * size_of::<u32>()) as vk::DeviceSize;
+        let feedback = Buffer::create(
+            Arc::clone(context),
+            feedback_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+        context.execute_one_time_commands(|command_buffer| unsafe {
+            context.device().cmd_fill_buffer(
+                command_buffer,
+                feedback.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                EMPTY_FEEDBACK_ENTRY,
+            )
+        });
+
+        let usage_size =
+            (max_textures as usize * USAGE_WORDS_PER_TEXTURE * size_of::<u32>()) as vk::DeviceSize;
+        let usage_buffers = (0..frame_count)
+            .map(|_| {
+                let mut buffer = Buffer::create(
+                    Arc::clone(context),
+                    usage_size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                );
+                let ptr = buffer.map_memory();
+                unsafe { std::ptr::write_bytes(ptr as *mut u8, 0, usage_size as usize) };
+                buffer
+            })
+            .collect::<Vec<_>>();
+
+        let descriptors = create_descriptors(context, &feedback, &usage_buffers);
+        let (pipeline, pipeline_layout) = create_reduce_pipeline(context, descriptors.layout());
+
+        Self {
+            context: Arc::clone(context),
+            feedback,
+            entry_count,
+            usage_buffers,
+            max_textures,
+            textures: Vec::new(),
+            frames: 0,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl TextureFeedback {
+    /// Start tracking `image` and return the index the materials must write for it.
+    pub fn register(&mut self, name: &str, image: &Image) -> u32 {
+        let index = self.textures.len() as u32;
+        assert!(
+            index < self.max_textures,
+            "Failed to register texture {}, the feedback holds {} textures",
+            name,
+            self.max_textures
+        );
+        if image.mip_levels > MAX_FEEDBACK_MIPS {
+            tracing::warn!(
+                "Texture {} has {} mips, only the first {} are tracked",
+                name,
+                image.mip_levels,
+                MAX_FEEDBACK_MIPS
+            );
+        }
+
+        self.textures.push(TextureUsage {
+            name: name.to_owned(),
+            extent: vk::Extent2D {
+                width: image.extent.width,
+                height: image.extent.height,
+            },
+            mip_levels: image.mip_levels,
+            texel_size: texel_size(image.format),
+            frame_mips: 0,
+            sampled_mips: 0,
+            frames_sampled: 0,
+            entries: 0,
+        });
+        index
+    }
+
+    /// Info to bind the feedback buffer as a storage buffer of the materials.
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.feedback.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    /// Reduce the entries written by the materials in the usage buffer of `frame_index`.
+    ///
+    /// Must be recorded outside of a render pass, after the last draw sampling
+    /// the tracked textures.
+    pub fn cmd_reduce(&self, command_buffer: vk::CommandBuffer, frame_index: usize) {
+        let device = self.context.device();
+
+        self.cmd_barrier(
+            command_buffer,
+            self.feedback.buffer,
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+            ),
+        );
+
+        let constants = [self.entry_count, self.textures.len() as u32];
+        let constants_bytes = constants
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &self.descriptors.sets()[frame_index..=frame_index],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &constants_bytes,
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                self.entry_count.div_ceil(REDUCE_GROUP_SIZE),
+                1,
+                1,
+            );
+        }
+
+        self.cmd_barrier(
+            command_buffer,
+            self.usage_buffers[frame_index].buffer,
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+            (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ),
+        );
+        // The entries cleared by the reduction are written again by the next frame.
+        self.cmd_barrier(
+            command_buffer,
+            self.feedback.buffer,
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::SHADER_WRITE,
+            ),
+        );
+    }
+
+    fn cmd_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) {
+        let barrier = vk::BufferMemoryBarrier2::default()
+            .src_stage_mask(src.0)
+            .src_access_mask(src.1)
+            .dst_stage_mask(dst.0)
+            .dst_access_mask(dst.1)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        let dependency_info =
+            vk::DependencyInfo::default().buffer_memory_barriers(std::slice::from_ref(&barrier));
+        unsafe {
+            self.context
+                .synchronization2()
+                .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+        };
+    }
+
+    /// Accumulate the usage reduced by the last submission of `frame_index`.
+    ///
+    /// Must be called once the fence of the frame was waited for.
+    pub fn collect(&mut self, frame_index: usize) {
+        let buffer = &mut self.usage_buffers[frame_index];
+        let words = self.textures.len() * USAGE_WORDS_PER_TEXTURE;
+        let usage =
+            unsafe { std::slice::from_raw_parts_mut(buffer.map_memory() as *mut u32, words) };
+
+        for (texture, usage) in self
+            .textures
+            .iter_mut()
+            .zip(usage.chunks_exact_mut(USAGE_WORDS_PER_TEXTURE))
+        {
+            texture.frame_mips = usage[0];
+            texture.sampled_mips |= usage[0];
+            texture.entries += usage[1] as u64;
+            if usage[0] != 0 {
+                texture.frames_sampled += 1;
+            }
+            usage.fill(0);
+        }
+        self.frames += 1;
+    }
+
+    pub fn textures(&self) -> &[TextureUsage] {
+        &self.textures
+    }
+
+    pub fn report(&self) -> TextureUsageReport {
+        TextureUsageReport {
+            frames: self.frames,
+            textures: self.textures.clone(),
+        }
+    }
+
+    /// Forget the usage collected so far. The registered textures are kept.
+    pub fn reset_stats(&mut self) {
+        self.frames = 0;
+        for texture in self.textures.iter_mut() {
+            texture.frame_mips = 0;
+            texture.sampled_mips = 0;
+            texture.frames_sampled = 0;
+            texture.entries = 0;
+        }
+    }
+}
+
+impl Drop for TextureFeedback {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn create_descriptors(
+    context: &Arc<Context>,
+    feedback: &Buffer,
+    usage_buffers: &[Buffer],
+) -> Descriptors {
+    let device = context.device();
+    let bindings = [0, 1].map(|binding| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+    });
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let set_count = usage_buffers.len() as u32;
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: set_count * 2,
+    }];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(set_count);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = vec![layout; usage_buffers.len()];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    for (set, usage) in sets.iter().zip(usage_buffers.iter()) {
+        let feedback_info = [vk::DescriptorBufferInfo::default()
+            .buffer(feedback.buffer)
+            .range(vk::WHOLE_SIZE)];
+        let usage_info = [vk::DescriptorBufferInfo::default()
+            .buffer(usage.buffer)
+            .range(vk::WHOLE_SIZE)];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&feedback_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&usage_info),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
+
+fn create_reduce_pipeline(
+    context: &Arc<Context>,
+    set_layout: vk::DescriptorSetLayout,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::COMPUTE,
+        offset: 0,
+        size: 2 * size_of::<u32>() as u32,
+    }];
+    let set_layouts = [set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    let layout = unsafe {
+        context
+            .device()
+            .create_pipeline_layout(&layout_info, None)
+            .expect("Failed to create pipeline layout")
+    };
+
+    let pipeline = create_compute_pipeline(
+        context,
+        ComputePipelineParameters {
+            shader_params: ShaderParameters::new(REDUCE_SHADER_NAME),
+            layout,
+        },
+    );
+
+    (pipeline, layout)
+}
+
+/// Size in bytes of a texel of the usual texture formats. Defaults to 4.
+fn texel_size(format: vk::Format) -> u32 {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => 1,
+        vk::Format::R8G8_UNORM | vk::Format::R16_SFLOAT => 2,
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => 4,
+    }
+}
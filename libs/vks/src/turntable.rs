@@ -0,0 +1,95 @@
+use crate::Camera;
+use math::cgmath::{InnerSpace, Point3, Vector3};
+use std::f32::consts::TAU;
+
+/// Speed and sampling of a [`Turntable`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurntableSettings {
+    /// Duration of a full revolution in seconds.
+    pub duration: f32,
+    /// Frames per second of the exported sequences.
+    pub fps: u32,
+    /// Distance between the camera and its target, the one of the camera
+    /// when the turntable starts if `None`.
+    pub radius: Option<f32>,
+}
+
+impl Default for TurntableSettings {
+    fn default() -> Self {
+        Self {
+            duration: 8.0,
+            fps: 30,
+            radius: None,
+        }
+    }
+}
+
+/// Orbit of the camera around its target at a fixed angular velocity, to
+/// showcase a model.
+///
+/// The camera keeps its elevation and turns around the y axis of the target,
+/// starting from where it was. [`Turntable::advance`] drives it in real time
+/// while [`Turntable::frame_camera`] samples it at the frames of a sequence.
+#[derive(Debug, Clone, Copy)]
+pub struct Turntable {
+    settings: TurntableSettings,
+    target: Point3<f32>,
+    /// Offset of the camera from the target when the turntable started.
+    start_offset: Vector3<f32>,
+    time: f32,
+}
+
+impl Turntable {
+    pub fn new(settings: TurntableSettings, camera: &Camera) -> Self {
+        let target = camera.target();
+        let offset = camera.position() - target;
+        let start_offset = match settings.radius {
+            Some(radius) => offset.normalize_to(radius),
+            None => offset,
+        };
+
+        Self {
+            settings,
+            target,
+            start_offset,
+            time: 0.0,
+        }
+    }
+}
+
+impl Turntable {
+    pub fn settings(&self) -> TurntableSettings {
+        self.settings
+    }
+
+    /// Frames of a full revolution at the rate of the settings.
+    pub fn frame_count(&self) -> u32 {
+        ((self.settings.duration * self.settings.fps as f32).round() as u32).max(1)
+    }
+
+    /// Advance the orbit by `delta_time` seconds and move `camera` to it.
+    pub fn advance(&mut self, camera: &mut Camera, delta_time: f32) {
+        self.time = (self.time + delta_time) % self.settings.duration.max(f32::EPSILON);
+        *camera = self.camera_at(camera, self.time);
+    }
+
+    /// `camera` placed at the frame `frame` of the sequence.
+    pub fn frame_camera(&self, camera: &Camera, frame: u32) -> Camera {
+        self.camera_at(camera, frame as f32 / self.settings.fps.max(1) as f32)
+    }
+
+    /// `camera` placed on the orbit `time` seconds after the start.
+    fn camera_at(&self, camera: &Camera, time: f32) -> Camera {
+        let angle = TAU * time / self.settings.duration.max(f32::EPSILON);
+        let (sin, cos) = angle.sin_cos();
+        let offset = Vector3::new(
+            self.start_offset.x * cos + self.start_offset.z * sin,
+            self.start_offset.y,
+            self.start_offset.z * cos - self.start_offset.x * sin,
+        );
+
+        let mut camera = *camera;
+        camera.look_at(self.target + offset, self.target);
+        camera
+    }
+}
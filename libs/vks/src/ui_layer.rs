@@ -0,0 +1,398 @@
+use crate::{
+    create_pipeline, create_sampler, texture::create_texture, Context, Descriptors,
+    OutputTransform, PipelineParameters, ShaderParameters, Texture,
+};
+use ash::vk;
+use std::{mem::size_of, sync::Arc};
+use util::any_as_u8_slice;
+
+/// Format of the ui layer, 8 bits are enough once encoded.
+///
+/// The gui renderer drawing to it must output linear colors, with its
+/// `srgb_framebuffer` option set.
+pub const UI_LAYER_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// The ui layer as the surface of the gui renderer, to derive its options
+/// from the [`crate::ColorPolicy`].
+pub const UI_LAYER_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: UI_LAYER_FORMAT,
+    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+};
+
+/// Brightness of the ui white on extended range outputs, the graphics white
+/// of ITU-R BT.2408.
+pub const DEFAULT_UI_WHITE_NITS: f32 = 203.0;
+
+/// Brightness of the 1.0 white of scRGB outputs.
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UiCompositeConstants {
+    white_scale: f32,
+    encode_srgb: u32,
+}
+
+/// Layer the ui is rendered to before being composited over the final image.
+///
+/// Keeps the ui out of the post processing of the scene and at a stable
+/// brightness: on extended range outputs the ui white is mapped to a fixed
+/// number of nits instead of the 80 nits of the scRGB 1.0.
+///
+/// The ui is recorded between [`UiLayer::cmd_begin_rendering`] and
+/// [`UiLayer::cmd_end_rendering`] then blended over the target with
+/// [`UiLayer::cmd_composite`]. The layer must be created again when the
+/// target is resized.
+pub struct UiLayer {
+    context: Arc<Context>,
+    texture: Texture,
+    output_transform: OutputTransform,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl UiLayer {
+    /// Create a layer of `extent` composited over targets of `surface_format`.
+    pub fn new(
+        context: &Arc<Context>,
+        extent: vk::Extent2D,
+        surface_format: vk::SurfaceFormatKHR,
+    ) -> Self {
+        let device = context.device();
+
+        let sampler = create_sampler(context, vk::Filter::NEAREST, vk::Filter::NEAREST);
+        let texture = create_texture(
+            context,
+            extent,
+            UI_LAYER_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            Some(sampler),
+        );
+        // Sampled before the first render.
+        texture.image.transition_image_layout(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        let descriptors = create_descriptors(context, &texture);
+
+        let pipeline_layout = {
+            let layouts = [descriptors.layout()];
+            let push_constant_range = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<UiCompositeConstants>() as _,
+            }];
+            let layout_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&layouts)
+                .push_constant_ranges(&push_constant_range);
+
+            unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+        };
+
+        let pipeline = {
+            let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+                .viewport_count(1)
+                .scissor_count(1);
+
+            let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+                .depth_clamp_enable(false)
+                .rasterizer_discard_enable(false)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .depth_bias_enable(false);
+
+            let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+                .sample_shading_enable(false)
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                .min_sample_shading(1.0)
+                .alpha_to_coverage_enable(false)
+                .alpha_to_one_enable(false);
+
+            // The layer holds premultiplied colors.
+            let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+                .color_write_mask(
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A,
+                )
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::ONE)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD)];
+
+            let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+            create_pipeline::<()>(
+                context,
+                PipelineParameters {
+                    vertex_shader_params: ShaderParameters::new("ui_composite"),
+                    fragment_shader_params: ShaderParameters::new("ui_composite"),
+                    multisampling_info: &multisampling_info,
+                    viewport_info: &viewport_info,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    rasterizer_info: &rasterizer_info,
+                    dynamic_state_info: Some(&dynamic_state_info),
+                    depth_stencil_info: None,
+                    stencil: None,
+                    shading_rate: None,
+                    shading_rate_attachment: false,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachment_formats: &[surface_format.format],
+                    depth_attachment_format: None,
+                    layout: pipeline_layout,
+                    parent: None,
+                    allow_derivatives: false,
+                },
+            )
+            .expect("Failed to create graphics pipeline")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            texture,
+            output_transform: context.color_policy().output_transform(surface_format),
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl UiLayer {
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.texture.image.extent.width,
+            height: self.texture.image.extent.height,
+        }
+    }
+
+    /// Value the ui white is written with for a white of `white_nits`.
+    ///
+    /// Only extended range outputs are scaled, the white of the other ones
+    /// being the one of the display.
+    pub fn white_scale(&self, white_nits: f32) -> f32 {
+        match self.output_transform {
+            OutputTransform::Linear => white_nits / SCRGB_WHITE_NITS,
+            OutputTransform::HardwareSrgb | OutputTransform::ShaderSrgb => 1.0,
+        }
+    }
+
+    /// Begin rendering to the layer, clearing it to transparent.
+    pub fn cmd_begin_rendering(&self, command_buffer: vk::CommandBuffer) {
+        self.texture.image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0; 4] },
+            })
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .image_view(self.texture.view)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent(),
+            });
+
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info)
+        };
+    }
+
+    /// End rendering and make the layer available to [`UiLayer::cmd_composite`].
+    pub fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer)
+        };
+        self.texture.image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    /// Record the blend of the layer over `target_view`, with the ui white at `white_nits`.
+    ///
+    /// `target_view` must be in `COLOR_ATTACHMENT_OPTIMAL` layout, with the
+    /// extent of the layer and the format passed at creation. It is left in
+    /// the same layout, outside of any rendering.
+    pub fn cmd_composite(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target_view: vk::ImageView,
+        white_nits: f32,
+    ) {
+        let device = self.context.device();
+        let extent = self.extent();
+
+        // The target was just rendered to.
+        let barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            );
+        let dependency_info =
+            vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&barrier));
+        unsafe {
+            self.context
+                .synchronization2()
+                .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+        };
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .image_view(target_view)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(std::slice::from_ref(&color_attachment_info))
+            .layer_count(1)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        let constants = UiCompositeConstants {
+            white_scale: self.white_scale(white_nits),
+            encode_srgb: (self.output_transform == OutputTransform::ShaderSrgb) as u32,
+        };
+
+        unsafe {
+            self.context
+                .dynamic_rendering()
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    max_depth: 1.0,
+                    ..Default::default()
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    extent,
+                    ..Default::default()
+                }],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                self.descriptors.sets(),
+                &[],
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                any_as_u8_slice(&constants),
+            );
+
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            self.context
+                .dynamic_rendering()
+                .cmd_end_rendering(command_buffer);
+        }
+    }
+}
+
+impl Drop for UiLayer {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+fn create_descriptors(context: &Arc<Context>, texture: &Texture) -> Descriptors {
+    let device = context.device();
+    let bindings = [vk::DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 1,
+    }];
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let sets = unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .expect("Failed to allocate descriptor sets")
+    };
+
+    let image_info = [vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(texture.view)
+        .sampler(texture.sampler.expect("Ui layer must have a sampler"))];
+    let descriptor_writes = [vk::WriteDescriptorSet::default()
+        .dst_set(sets[0])
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(&image_info)];
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    Descriptors::new(Arc::clone(context), layout, pool, sets)
+}
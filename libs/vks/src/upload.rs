@@ -0,0 +1,502 @@
+use super::{buffer::*, context::*, image::*, mipmaps::*, sampler::*, texture::*};
+use ash::vk;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Value of a submission of an [`UploadContext`].
+///
+/// Values increase with each submission like the values of a timeline
+/// semaphore, and submissions complete in order, so a ticket is complete as
+/// soon as a later one is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(u64);
+
+/// A resource whose copies were recorded by an [`UploadContext`].
+///
+/// The resource is returned by [`UploadContext::cmd_finish`] once its ticket
+/// is complete and must not be used before.
+pub struct PendingUpload<R> {
+    ticket: UploadTicket,
+    resource: R,
+}
+
+impl<R> PendingUpload<R> {
+    /// Ticket of the submission copying the resource.
+    pub fn ticket(&self) -> UploadTicket {
+        self.ticket
+    }
+}
+
+/// Resource that can be uploaded by an [`UploadContext`].
+pub trait Upload {
+    /// Record the acquisition of the resource by the graphics queue, and the
+    /// work that can only be done on that queue, after the copies are complete.
+    ///
+    /// `families` are the transfer and the graphics queue families.
+    fn cmd_acquire(
+        &self,
+        context: &Context,
+        command_buffer: vk::CommandBuffer,
+        families: (u32, u32),
+    );
+}
+
+impl Upload for Buffer {
+    fn cmd_acquire(
+        &self,
+        context: &Context,
+        command_buffer: vk::CommandBuffer,
+        families: (u32, u32),
+    ) {
+        cmd_buffer_ownership_barrier(
+            context,
+            command_buffer,
+            self.buffer,
+            families,
+            (
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                vk::AccessFlags2::MEMORY_READ,
+            ),
+        );
+    }
+}
+
+impl Upload for Texture {
+    /// Also generate the mips since blits are not available on transfer queues.
+    fn cmd_acquire(
+        &self,
+        context: &Context,
+        command_buffer: vk::CommandBuffer,
+        families: (u32, u32),
+    ) {
+        cmd_image_ownership_barrier(
+            context,
+            command_buffer,
+            &self.image,
+            families,
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_READ | vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        );
+        let extent = vk::Extent2D {
+            width: self.image.extent.width,
+            height: self.image.extent.height,
+        };
+        self.image.cmd_generate_mipmaps(command_buffer, extent);
+    }
+}
+
+/// A submitted batch of copies.
+struct UploadBatch {
+    ticket: UploadTicket,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Kept alive until the copies are complete.
+    _staging_buffers: Vec<Buffer>,
+}
+
+/// Batch the staging copies of buffers and textures and submit them on the
+/// dedicated transfer queue of the device, or on the graphics queue when it
+/// has none, without waiting for them.
+///
+/// Uploads recorded between two calls to [`UploadContext::submit`] share a
+/// submission and its [`UploadTicket`]. The renderer polls the tickets with
+/// [`UploadContext::is_complete`] and records [`UploadContext::cmd_finish`]
+/// in its frame command buffer to acquire the resources on the graphics queue,
+/// so large scenes are streamed while rendering continues.
+///
+/// Unlike [`create_device_local_buffer_with_data`] and [`Texture::from_rgba`]
+/// nothing blocks until [`UploadContext::wait`] is called. The staging buffers
+/// are freed once their submission completes.
+pub struct UploadContext {
+    context: Arc<Context>,
+    /// Transfer and graphics queue families.
+    families: (u32, u32),
+    command_pool: vk::CommandPool,
+    /// Command buffer and staging buffers of the batch being recorded.
+    recording: Option<(vk::CommandBuffer, Vec<Buffer>)>,
+    in_flight: VecDeque<UploadBatch>,
+    next_ticket: u64,
+    completed_ticket: u64,
+}
+
+impl UploadContext {
+    pub fn new(context: &Arc<Context>) -> Self {
+        let indices = context.queue_families_indices();
+        let families = (indices.transfer_family(), indices.graphics_index);
+
+        let command_pool_info = vk::CommandPoolCreateInfo::default()
+            .queue_family_index(families.0)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let command_pool = unsafe {
+            context
+                .device()
+                .create_command_pool(&command_pool_info, None)
+                .expect("Failed to create command pool")
+        };
+
+        Self {
+            context: Arc::clone(context),
+            families,
+            command_pool,
+            recording: None,
+            in_flight: VecDeque::new(),
+            next_ticket: 1,
+            completed_ticket: 0,
+        }
+    }
+}
+
+impl UploadContext {
+    /// True when the copies are submitted to a dedicated transfer queue.
+    pub fn has_dedicated_queue(&self) -> bool {
+        self.families.0 != self.families.1
+    }
+
+    /// Record the upload of `data` to a new device local buffer with `usage`.
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> PendingUpload<Buffer> {
+        let staging_buffer =
+            create_host_visible_buffer(&self.context, vk::BufferUsageFlags::TRANSFER_SRC, data);
+        let buffer = Buffer::create(
+            Arc::clone(&self.context),
+            staging_buffer.size,
+            vk::BufferUsageFlags::TRANSFER_DST | usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+
+        let command_buffer = self.command_buffer();
+        buffer.cmd_copy(command_buffer, &staging_buffer, staging_buffer.size);
+        if self.has_dedicated_queue() {
+            cmd_buffer_ownership_barrier(
+                &self.context,
+                command_buffer,
+                buffer.buffer,
+                self.families,
+                (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            );
+        }
+
+        self.pending(buffer, staging_buffer)
+    }
+
+    /// Record the upload of a mipmapped rgba texture, see [`Texture::from_rgba`].
+    ///
+    /// Only the first mip is copied on the transfer queue, the others are
+    /// generated by [`UploadContext::cmd_finish`].
+    pub fn upload_texture_rgba(
+        &mut self,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        linear: bool,
+    ) -> PendingUpload<Texture> {
+        let max_mip_levels = ((width.min(height) as f32).log2().floor() + 1.0) as u32;
+        let extent = vk::Extent2D { width, height };
+
+        let staging_buffer =
+            create_host_visible_buffer(&self.context, vk::BufferUsageFlags::TRANSFER_SRC, data);
+        let format = self.context.color_policy().texture_format(linear);
+        let image = Image::create(
+            Arc::clone(&self.context),
+            ImageParameters {
+                mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                extent,
+                format,
+                mip_levels: max_mip_levels,
+                usage: vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::SAMPLED
+                    | mipmap_usage(&self.context, format),
+                ..Default::default()
+            },
+        );
+
+        let command_buffer = self.command_buffer();
+        image.cmd_transition_image_layout(
+            command_buffer,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        image.cmd_copy_buffer(command_buffer, &staging_buffer, extent);
+        if self.has_dedicated_queue() {
+            cmd_image_ownership_barrier(
+                &self.context,
+                command_buffer,
+                &image,
+                self.families,
+                (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            );
+        }
+
+        let view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::COLOR);
+        let sampler = self.context.get_sampler(SamplerKey::repeat(max_mip_levels));
+        let texture = Texture::with_cached_sampler(Arc::clone(&self.context), image, view, sampler);
+
+        self.pending(texture, staging_buffer)
+    }
+
+    /// Submit the uploads recorded since the last submission.
+    ///
+    /// # Returns
+    ///
+    /// The ticket of the submission, or the one of the previous submission
+    /// when nothing was recorded.
+    pub fn submit(&mut self) -> UploadTicket {
+        let Some((command_buffer, staging_buffers)) = self.recording.take() else {
+            return UploadTicket(self.next_ticket - 1);
+        };
+
+        let device = self.context.device();
+        unsafe {
+            device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to end command buffer")
+        };
+
+        let fence = unsafe {
+            device
+                .create_fence(&vk::FenceCreateInfo::default(), None)
+                .expect("Failed to create fence")
+        };
+        let command_buffer_info =
+            vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer);
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(std::slice::from_ref(&command_buffer_info));
+        {
+            let _queue_guard = self.context.lock_transfer_queue();
+            unsafe {
+                self.context
+                    .synchronization2()
+                    .queue_submit2(
+                        self.context.transfer_queue(),
+                        std::slice::from_ref(&submit_info),
+                        fence,
+                    )
+                    .expect("Failed to submit uploads")
+            };
+        }
+
+        let ticket = UploadTicket(self.next_ticket);
+        self.next_ticket += 1;
+        self.in_flight.push_back(UploadBatch {
+            ticket,
+            command_buffer,
+            fence,
+            _staging_buffers: staging_buffers,
+        });
+
+        ticket
+    }
+
+    /// True once the copies of the submission of `ticket` are complete.
+    pub fn is_complete(&mut self, ticket: UploadTicket) -> bool {
+        self.poll();
+        ticket.0 <= self.completed_ticket
+    }
+
+    /// Wait for the copies of the submission of `ticket` to complete.
+    pub fn wait(&mut self, ticket: UploadTicket) {
+        assert!(
+            ticket.0 < self.next_ticket,
+            "Upload {:?} was not submitted",
+            ticket
+        );
+
+        let fences = self
+            .in_flight
+            .iter()
+            .take_while(|batch| batch.ticket <= ticket)
+            .map(|batch| batch.fence)
+            .collect::<Vec<_>>();
+        if !fences.is_empty() {
+            unsafe {
+                self.context
+                    .device()
+                    .wait_for_fences(&fences, true, u64::MAX)
+                    .unwrap_or_else(|error| {
+                        self.context
+                            .handle_device_error(error, "Failed to wait for uploads")
+                    })
+            };
+        }
+        self.poll();
+    }
+
+    /// Record the acquisition of the resource of `upload` by the graphics
+    /// queue in `command_buffer` and return the resource, usable by the
+    /// commands recorded after.
+    ///
+    /// # Panics
+    ///
+    /// If the ticket of `upload` is not complete.
+    pub fn cmd_finish<R: Upload>(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        upload: PendingUpload<R>,
+    ) -> R {
+        assert!(
+            self.is_complete(upload.ticket),
+            "Upload {:?} is not complete",
+            upload.ticket
+        );
+        upload
+            .resource
+            .cmd_acquire(&self.context, command_buffer, self.families);
+        upload.resource
+    }
+
+    /// Command buffer of the batch being recorded, begun on first use.
+    fn command_buffer(&mut self) -> vk::CommandBuffer {
+        if let Some((command_buffer, _)) = self.recording {
+            return command_buffer;
+        }
+
+        let device = self.context.device();
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(self.command_pool)
+            .command_buffer_count(1);
+        let command_buffer = unsafe {
+            device
+                .allocate_command_buffers(&allocate_info)
+                .expect("Failed to allocate command buffer")[0]
+        };
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("Failed to begin command buffer")
+        };
+
+        self.recording = Some((command_buffer, Vec::new()));
+        command_buffer
+    }
+
+    fn pending<R>(&mut self, resource: R, staging_buffer: Buffer) -> PendingUpload<R> {
+        if let Some((_, staging_buffers)) = self.recording.as_mut() {
+            staging_buffers.push(staging_buffer);
+        }
+        PendingUpload {
+            ticket: UploadTicket(self.next_ticket),
+            resource,
+        }
+    }
+
+    /// Release the batches whose copies are complete.
+    fn poll(&mut self) {
+        while let Some(batch) = self.in_flight.front() {
+            let signaled = unsafe { self.context.device().get_fence_status(batch.fence) }
+                .unwrap_or_else(|error| {
+                    self.context
+                        .handle_device_error(error, "Failed to get upload fence status")
+                });
+            if !signaled {
+                break;
+            }
+
+            let batch = self.in_flight.pop_front().unwrap();
+            self.completed_ticket = batch.ticket.0;
+            self.free_batch(batch);
+        }
+    }
+
+    fn free_batch(&self, batch: UploadBatch) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_fence(batch.fence, None);
+            device.free_command_buffers(self.command_pool, &[batch.command_buffer]);
+        }
+    }
+}
+
+impl Drop for UploadContext {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.in_flight.back().map(|batch| batch.ticket) {
+            self.wait(ticket);
+        }
+        // Destroying the pool frees the command buffer of a batch never submitted.
+        unsafe {
+            self.context
+                .device()
+                .destroy_command_pool(self.command_pool, None)
+        };
+    }
+}
+
+/// Queue family indices of an ownership transfer from the transfer queue to
+/// the graphics queue, ignored when both queues are of the same family.
+fn ownership_families(families: (u32, u32)) -> (u32, u32) {
+    if families.0 == families.1 {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    } else {
+        families
+    }
+}
+
+/// Record the release of `buffer` when `dst` is none, its acquisition by `dst` otherwise.
+fn cmd_buffer_ownership_barrier(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    families: (u32, u32),
+    dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let (src_family, dst_family) = ownership_families(families);
+    let barrier = vk::BufferMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(dst.0)
+        .dst_access_mask(dst.1)
+        .src_queue_family_index(src_family)
+        .dst_queue_family_index(dst_family)
+        .buffer(buffer)
+        .size(vk::WHOLE_SIZE);
+    let dependency_info =
+        vk::DependencyInfo::default().buffer_memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
+
+/// Record the release of all the mips of `image` when `dst` is none, their
+/// acquisition by `dst` otherwise. The image stays in `TRANSFER_DST_OPTIMAL` layout.
+fn cmd_image_ownership_barrier(
+    context: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: &Image,
+    families: (u32, u32),
+    dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let (src_family, dst_family) = ownership_families(families);
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(dst.0)
+        .dst_access_mask(dst.1)
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_queue_family_index(src_family)
+        .dst_queue_family_index(dst_family)
+        .image(image.image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: image.mip_levels,
+            base_array_layer: 0,
+            layer_count: image.layers,
+        });
+    let dependency_info =
+        vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&barrier));
+    unsafe {
+        context
+            .synchronization2()
+            .cmd_pipeline_barrier2(command_buffer, &dependency_info)
+    };
+}
@@ -0,0 +1,221 @@
+use crate::{
+    compute_pass::*, create_compute_pipeline, ComputePipelineParameters, Context, Descriptors,
+    ShaderParameters, Texture,
+};
+use ash::vk;
+use std::{error::Error, fmt, sync::Arc};
+
+const SHADER_NAME: &str = "cas";
+pub const DEFAULT_SHARPNESS: f32 = 0.5;
+
+/// Inputs of an [`Upscaler`], rendered at [`Upscaler::render_extent`].
+///
+/// The textures must be in `SHADER_READ_ONLY_OPTIMAL` layout.
+pub struct UpscaleInputs<'a> {
+    pub color: &'a Texture,
+    pub depth: &'a Texture,
+    /// Screen space motion of each pixel since the previous frame, in uv.
+    /// Temporal upscalers fail without it, spatial ones ignore it.
+    pub motion_vectors: Option<&'a Texture>,
+    /// Sub pixel offset applied to the projection this frame, in pixels.
+    pub jitter: [f32; 2],
+    /// Time since the previous frame in seconds.
+    pub delta_time: f32,
+    /// Discard the history, on camera cuts for example.
+    pub reset: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleError {
+    /// A temporal upscaler was given no motion vectors.
+    MissingMotionVectors,
+    /// The inputs or the output are not the textures the upscaler was created with.
+    UnexpectedTextures,
+}
+
+impl fmt::Display for UpscaleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingMotionVectors => write!(f, "Temporal upscalers require motion vectors"),
+            Self::UnexpectedTextures => write!(
+                f,
+                "The upscaler must be recreated to change its inputs or output"
+            ),
+        }
+    }
+}
+
+impl Error for UpscaleError {}
+
+/// Upscale the scene rendered at a reduced resolution to the display resolution.
+///
+/// Spatial upscalers only use the color, temporal ones also accumulate the
+/// jittered frames using the depth and motion vectors.
+pub trait Upscaler {
+    /// Extent the scene must be rendered at for `display_extent`.
+    fn render_extent(&self, display_extent: vk::Extent2D) -> vk::Extent2D;
+
+    /// Sub pixel jitter of the projection for `frame_index`, in pixels.
+    ///
+    /// None by default, as spatial upscalers don't need it.
+    fn jitter(&self, _frame_index: u64) -> [f32; 2] {
+        [0.0, 0.0]
+    }
+
+    /// Record the upscale of `inputs` to `output`, left in `SHADER_READ_ONLY_OPTIMAL` layout.
+    ///
+    /// Nothing is recorded if it fails.
+    fn cmd_upscale(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        inputs: &UpscaleInputs,
+        output: &Texture,
+    ) -> Result<(), UpscaleError>;
+}
+
+/// Point `index` of the Halton sequence of `base`, in [0, 1).
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Jitter of `frame_index` in a sequence of `phase_count` offsets, in [-0.5, 0.5) pixels.
+pub fn halton_jitter(frame_index: u64, phase_count: u32) -> [f32; 2] {
+    let index = (frame_index % phase_count as u64) as u32 + 1;
+    [halton(index, 2) - 0.5, halton(index, 3) - 0.5]
+}
+
+/// Contrast adaptive sharpening of a texture into another, upscaling it if
+/// the target is larger.
+///
+/// Meant to output the scene rendered at a reduced resolution to the display
+/// resolution. Sharpening is stronger in low contrast areas so edges don't ring.
+///
+/// The source must have a sampler and be in `SHADER_READ_ONLY_OPTIMAL` layout
+/// when the pass is recorded. The target must have the `STORAGE` usage, its
+/// content is discarded and it is left in `SHADER_READ_ONLY_OPTIMAL` layout.
+pub struct CasUpscale {
+    context: Arc<Context>,
+    sharpness: f32,
+    source_extent: vk::Extent3D,
+    source: vk::Image,
+    target: vk::Image,
+    target_extent: vk::Extent3D,
+    descriptors: Descriptors,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl CasUpscale {
+    pub fn new(context: &Arc<Context>, source: &Texture, target: &Texture, sharpness: f32) -> Self {
+        check_write_without_format(context);
+
+        let descriptors = create_descriptors(context, &[(source, target.view)]);
+        let pipeline_layout = create_pipeline_layout(context, descriptors.layout());
+        let pipeline = create_compute_pipeline(
+            context,
+            ComputePipelineParameters {
+                shader_params: ShaderParameters::new(SHADER_NAME),
+                layout: pipeline_layout,
+            },
+        );
+
+        Self {
+            context: Arc::clone(context),
+            sharpness: sharpness.clamp(0.0, 1.0),
+            source_extent: source.image.extent,
+            source: source.image.image,
+            target: target.image.image,
+            target_extent: target.image.extent,
+            descriptors,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+}
+
+impl CasUpscale {
+    pub fn sharpness(&self) -> f32 {
+        self.sharpness
+    }
+
+    /// Set the sharpness, from 0 for the least sharpening to 1.
+    pub fn set_sharpness(&mut self, sharpness: f32) {
+        self.sharpness = sharpness.clamp(0.0, 1.0);
+    }
+
+    /// Ratio between the source and target widths.
+    pub fn scale(&self) -> f32 {
+        self.source_extent.width as f32 / self.target_extent.width as f32
+    }
+
+    /// Record the pass. Must be recorded outside of a render pass.
+    pub fn cmd_upscale(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.context.device().cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            )
+        };
+
+        let parameters = [
+            (1.0 / self.source_extent.width as f32).to_bits(),
+            (1.0 / self.source_extent.height as f32).to_bits(),
+            self.sharpness.to_bits(),
+            0,
+        ];
+
+        cmd_begin_write(&self.context, command_buffer, self.target);
+        cmd_dispatch_pass(
+            &self.context,
+            command_buffer,
+            self.pipeline_layout,
+            self.descriptors.sets()[0],
+            &parameters,
+            self.target_extent,
+        );
+        cmd_end_write(&self.context, command_buffer, self.target);
+    }
+}
+
+impl Drop for CasUpscale {
+    fn drop(&mut self) {
+        let device = self.context.device();
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// The textures are bound when the pass is created, so `inputs` and `output`
+/// must be the source and target it was created with.
+impl Upscaler for CasUpscale {
+    fn render_extent(&self, display_extent: vk::Extent2D) -> vk::Extent2D {
+        let scale = self.scale();
+        vk::Extent2D {
+            width: ((display_extent.width as f32 * scale) as u32).max(1),
+            height: ((display_extent.height as f32 * scale) as u32).max(1),
+        }
+    }
+
+    fn cmd_upscale(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        inputs: &UpscaleInputs,
+        output: &Texture,
+    ) -> Result<(), UpscaleError> {
+        if inputs.color.image.image != self.source || output.image.image != self.target {
+            return Err(UpscaleError::UnexpectedTextures);
+        }
+        CasUpscale::cmd_upscale(self, command_buffer);
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use ash::{
     vk::{self, DeviceSize},
 };
 use std::{ffi::c_void, mem::size_of, sync::Arc};
+#[cfg(feature = "winit")]
 use winit::{
     dpi::PhysicalSize,
     event::{DeviceEvent, ElementState, KeyEvent, WindowEvent},
@@ -11,10 +12,25 @@ use winit::{
 };
 
 use crate::{
-    in_flight_frames::{InFlightFrames, SyncObjects}, Camera, Context, Image, ImageParameters, RenderData, RenderError, Texture, MAX_FRAMES_IN_FLIGHT
+    format_aspect_flags, in_flight_frames::{InFlightFrames, SyncObjects}, Context, Image, ImageParameters, Texture, MAX_FRAMES_IN_FLIGHT
 };
+#[cfg(feature = "winit")]
+use crate::{Camera, RenderError};
+#[cfg(feature = "gui")]
+use crate::RenderData;
 
-pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+/// Formats of the scene color targets by order of preference. Half floats keep
+/// enough range for HDR at half the bandwidth of full floats, the packed format
+/// without alpha is the last resort.
+pub const SCENE_COLOR_FORMATS: [vk::Format; 3] = [
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::B10G11R11_UFLOAT_PACK32,
+];
+/// Usage the scene color format is negotiated for, rendered then sampled.
+pub const SCENE_COLOR_USAGE: vk::ImageUsageFlags = vk::ImageUsageFlags::from_raw(
+    vk::ImageUsageFlags::COLOR_ATTACHMENT.as_raw() | vk::ImageUsageFlags::SAMPLED.as_raw(),
+);
 
 /// Utility function that copy the content of a slice at the position of a given pointer.
 pub unsafe fn mem_copy<T: Copy>(ptr: *mut c_void, data: &[T]) {
@@ -105,23 +121,141 @@ pub fn create_sync_objects(context: &Arc<Context>) -> InFlightFrames {
     InFlightFrames::new(Arc::clone(context), sync_objects_vec)
 }
 
+/// What to favor when several depth formats are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthFormatPreference {
+    /// The most precise format, 32 bits float depth.
+    #[default]
+    Precision,
+    /// The smallest format, down to 16 bits depth.
+    Memory,
+}
+
+/// Requirements of the depth format, see [`find_depth_format_for`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DepthFormatRequest {
+    /// Only pick formats with a stencil component.
+    pub require_stencil: bool,
+    pub preference: DepthFormatPreference,
+}
+
+impl DepthFormatRequest {
+    /// Candidate formats, in order of preference.
+    fn candidates(&self) -> &'static [vk::Format] {
+        match (self.require_stencil, self.preference) {
+            (false, DepthFormatPreference::Precision) => &[
+                vk::Format::D32_SFLOAT,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+            ],
+            (false, DepthFormatPreference::Memory) => &[
+                vk::Format::D16_UNORM,
+                vk::Format::X8_D24_UNORM_PACK32,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D32_SFLOAT,
+            ],
+            (true, DepthFormatPreference::Precision) => &[
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D16_UNORM_S8_UINT,
+            ],
+            (true, DepthFormatPreference::Memory) => &[
+                vk::Format::D16_UNORM_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D32_SFLOAT_S8_UINT,
+            ],
+        }
+    }
+}
+
 pub fn find_depth_format(context: &Context) -> vk::Format {
-    let candidates = vec![
-        vk::Format::D32_SFLOAT,
-        vk::Format::D32_SFLOAT_S8_UINT,
-        vk::Format::D24_UNORM_S8_UINT,
-    ];
-    context
+    find_depth_format_for(context, DepthFormatRequest::default())
+}
+
+/// Find a depth format with a stencil component, for masking effects.
+pub fn find_depth_stencil_format(context: &Context) -> vk::Format {
+    find_depth_format_for(
+        context,
+        DepthFormatRequest {
+            require_stencil: true,
+            ..Default::default()
+        },
+    )
+}
+
+/// Find the first depth format of `request` usable as an attachment.
+pub fn find_depth_format_for(context: &Context, request: DepthFormatRequest) -> vk::Format {
+    let format = context
         .find_supported_format(
-            &candidates,
+            request.candidates(),
             vk::ImageTiling::OPTIMAL,
             vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
         )
-        .expect("Failed to find a supported depth format")
+        .unwrap_or_else(|| panic!("Failed to find a supported depth format for {:?}", request));
+    tracing::debug!("Depth format {:?} picked for {:?}", format, request);
+    format
+}
+
+/// Format features needed with optimal tiling by the images of `usage`.
+pub fn format_features_for_usage(usage: vk::ImageUsageFlags) -> vk::FormatFeatureFlags {
+    [
+        (
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::FormatFeatureFlags::TRANSFER_SRC,
+        ),
+        (
+            vk::ImageUsageFlags::TRANSFER_DST,
+            vk::FormatFeatureFlags::TRANSFER_DST,
+        ),
+        (
+            vk::ImageUsageFlags::SAMPLED,
+            vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        ),
+        (
+            vk::ImageUsageFlags::STORAGE,
+            vk::FormatFeatureFlags::STORAGE_IMAGE,
+        ),
+        (
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT,
+        ),
+        (
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        ),
+    ]
+    .into_iter()
+    .filter(|(image_usage, _)| usage.contains(*image_usage))
+    .fold(vk::FormatFeatureFlags::empty(), |features, (_, feature)| {
+        features | feature
+    })
+}
+
+/// Find the first of [`SCENE_COLOR_FORMATS`] usable for `usage`, color
+/// attachments must support blending.
+///
+/// The picked format must be the one given to [`create_scene_color`] and to
+/// the pipelines drawing in the scene color.
+pub fn find_scene_color_format(context: &Context, usage: vk::ImageUsageFlags) -> vk::Format {
+    let mut features = format_features_for_usage(usage);
+    if usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT) {
+        features |= vk::FormatFeatureFlags::COLOR_ATTACHMENT_BLEND;
+    }
+    let format = context
+        .find_supported_format(&SCENE_COLOR_FORMATS, vk::ImageTiling::OPTIMAL, features)
+        .unwrap_or_else(|| {
+            panic!(
+                "Failed to find a supported scene color format for {:?}",
+                usage
+            )
+        });
+    tracing::debug!("Scene color format {:?} picked for {:?}", format, usage);
+    format
 }
 
 pub fn create_scene_color(
     context: &Arc<Context>,
+    format: vk::Format,
     extent: vk::Extent2D,
     msaa_samples: vk::SampleCountFlags,
 ) -> Texture {
@@ -137,7 +271,7 @@ pub fn create_scene_color(
             mem_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
             extent,
             sample_count: msaa_samples,
-            format: SCENE_COLOR_FORMAT,
+            format,
             usage: image_usage,
             ..Default::default()
         },
@@ -168,8 +302,11 @@ pub fn create_scene_depth(
     extent: vk::Extent2D,
     msaa_samples: vk::SampleCountFlags,
 ) -> Texture {
+    // Sampled by the post passes when single sampled.
     let image_usage = match msaa_samples {
-        vk::SampleCountFlags::TYPE_1 => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::SampleCountFlags::TYPE_1 => {
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        }
         _ => {
             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
@@ -192,7 +329,9 @@ pub fn create_scene_depth(
         vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
     );
 
-    let view = image.create_view(vk::ImageViewType::TYPE_2D, vk::ImageAspectFlags::DEPTH);
+    // Attachment views cover the stencil aspect too when the format has one,
+    // the texture is sampled through its depth only view, see `Texture::sampling_view`.
+    let view = image.create_view(vk::ImageViewType::TYPE_2D, format_aspect_flags(format));
 
     let sampler = match msaa_samples {
         vk::SampleCountFlags::TYPE_1 => Some(create_sampler(
@@ -206,13 +345,22 @@ pub fn create_scene_depth(
     Texture::new(Arc::clone(context), image, view, sampler)
 }
 
+/// Stand-in for the draw data of the gui without the `gui` feature, so
+/// [`WindowApp::cmd_draw`] keeps the same signature. Never constructed.
+#[cfg(all(feature = "winit", not(feature = "gui")))]
+pub enum RenderData {}
+
+#[cfg(feature = "winit")]
 pub trait WindowApp {
     fn new_frame(&mut self);
     fn end_frame(&mut self, window: &Window);
     fn handle_window_event(&mut self, _window: &Window, event: &WindowEvent);
     fn handle_device_event(&mut self, event: &DeviceEvent);
-    fn recreate_swapchain(&mut self, dimensions: [u32; 2], vsync: bool, hdr: bool);
     fn on_exit(&mut self) {}
+    /// Return true when the application asks to be closed.
+    fn should_exit(&self) -> bool {
+        false
+    }
     fn render(&mut self, window: &Window, camera: Camera) -> Result<(), RenderError>;
     fn cmd_draw(&mut self, command_buffer: vk::CommandBuffer, frame_index: usize,ui_render_data: Option<&RenderData>);
 }
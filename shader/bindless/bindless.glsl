@@ -0,0 +1,18 @@
+// Textures of a vks::TextureRegistry, indexed by the handles given when registering them.
+//
+// Define BINDLESS_SET before including this file.
+
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (set = BINDLESS_SET, binding = 0) uniform sampler2D bindlessTextures[];
+
+// Handle of an absent texture, see gltf_model::NO_TEXTURE.
+const uint NO_TEXTURE = 0xFFFFFFFFu;
+
+// Sample the texture of `handle` at `uv`, `fallback` if there is no texture.
+vec4 sampleBindless(uint handle, vec2 uv, vec4 fallback) {
+    if (handle == NO_TEXTURE) {
+        return fallback;
+    }
+    return texture(bindlessTextures[nonuniformEXT(handle)], uv);
+}
@@ -0,0 +1,51 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+#extension GL_GOOGLE_include_directive: require
+
+// Integrate the specular brdf for the angle of view along the width and the
+// roughness down the height. The scale and bias to apply to the reflectance
+// at normal incidence are stored in the red and green channels, so the
+// lookup is sampled at vec2(dot(normal, view), roughness).
+
+#include "../ggx/ggx.glsl"
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec2 outBrdf;
+
+const uint SAMPLE_COUNT = 1024u;
+
+// Smith geometry term with the remapping of the roughness for image based lighting.
+float geometrySmith(float normalDotView, float normalDotLight, float roughness) {
+    float k = roughness * roughness / 2.0;
+    float view = normalDotView / (normalDotView * (1.0 - k) + k);
+    float light = normalDotLight / (normalDotLight * (1.0 - k) + k);
+    return view * light;
+}
+
+vec2 integrateBrdf(float normalDotView, float roughness) {
+    vec3 view = vec3(sqrt(1.0 - normalDotView * normalDotView), 0.0, normalDotView);
+    vec3 normal = vec3(0.0, 0.0, 1.0);
+
+    vec2 brdf = vec2(0.0);
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 halfVector = importanceSampleGgx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        vec3 light = normalize(2.0 * dot(view, halfVector) * halfVector - view);
+        float normalDotLight = max(light.z, 0.0);
+        if (normalDotLight > 0.0) {
+            float normalDotHalf = max(halfVector.z, 0.0);
+            float viewDotHalf = max(dot(view, halfVector), 0.0);
+            float visibility = geometrySmith(normalDotView, normalDotLight, roughness)
+                * viewDotHalf / (normalDotHalf * normalDotView);
+            float fresnel = pow(1.0 - viewDotHalf, 5.0);
+            brdf += vec2(1.0 - fresnel, fresnel) * visibility;
+        }
+    }
+    return brdf / float(SAMPLE_COUNT);
+}
+
+void main() {
+    // The coordinates go up from the last row, the roughness goes down from the first.
+    outBrdf = integrateBrdf(fragCoords.x, 1.0 - fragCoords.y);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Project the cube around the origin to a face of a cubemap, see
+// environment::SkyboxModel. The fragment shaders get the direction of the
+// texels they write.
+
+layout (location = 0) in vec3 inPosition;
+
+layout (push_constant) uniform Camera {
+    mat4 viewProj;
+} camera;
+
+layout (location = 0) out vec3 fragDirection;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    fragDirection = inPosition;
+    gl_Position = camera.viewProj * vec4(inPosition, 1.0);
+    // The faces of a cubemap are seen from inside the cube, their horizontal
+    // axis is mirrored compared to the right handed views of the faces. It
+    // also winds the inner faces clockwise, which the FRONT culling keeps.
+    gl_Position.x = -gl_Position.x;
+}
@@ -0,0 +1,36 @@
+// Declarations shared by the passes downsampling a level of a depth pyramid,
+// keeping the farthest or the closest depth of the source texels covered by
+// each target texel.
+
+layout (local_size_x = 8, local_size_y = 8) in;
+
+layout (constant_id = 1) const bool KEEP_MIN = false;
+
+layout (binding = 0) uniform sampler2D source;
+layout (binding = 1, r32f) uniform writeonly image2D target;
+
+layout (push_constant) uniform Parameters {
+    ivec2 sourceSize;
+    // The next level is half the size of the target and is written too.
+    uint writeNext;
+} params;
+
+float reduce(float a, float b) {
+    return KEEP_MIN ? min(a, b) : max(a, b);
+}
+
+// Reduced depth of the source texels covered by `texel` of a target of `size`.
+float coveredDepth(ivec2 texel, ivec2 size) {
+    // Odd sizes make a target texel cover up to 3 source texels on an axis.
+    ivec2 first = (texel * params.sourceSize) / size;
+    ivec2 last = ((texel + 1) * params.sourceSize + size - 1) / size - 1;
+    last = min(last, first + 2);
+
+    float depth = texelFetch(source, first, 0).r;
+    for (int y = first.y; y <= last.y; y++) {
+        for (int x = first.x; x <= last.x; x++) {
+            depth = reduce(depth, texelFetch(source, ivec2(x, y), 0).r);
+        }
+    }
+    return depth;
+}
@@ -0,0 +1,75 @@
+// Declarations shared by the downsamplers generating up to 5 mips of an image
+// in a single dispatch. Each group averages a 32x32 tile of the source mip
+// down to a single texel.
+
+layout (local_size_x = 16, local_size_y = 16) in;
+
+layout (binding = 0) uniform sampler2DArray source;
+layout (binding = 1) uniform writeonly image2DArray mips[5];
+
+layout (push_constant) uniform Parameters {
+    uint mipCount;
+} params;
+
+// Intermediate mips of the tile.
+shared vec4 tile[16][16];
+
+// Storage image arrays are only indexed with constants, dynamic indexing
+// being an optional feature.
+ivec2 mipSize(uint mip) {
+    switch (mip) {
+        case 0u: return imageSize(mips[0]).xy;
+        case 1u: return imageSize(mips[1]).xy;
+        case 2u: return imageSize(mips[2]).xy;
+        case 3u: return imageSize(mips[3]).xy;
+        default: return imageSize(mips[4]).xy;
+    }
+}
+
+void store(uint mip, ivec3 texel, vec4 color) {
+    if (any(greaterThanEqual(texel.xy, mipSize(mip)))) {
+        return;
+    }
+    switch (mip) {
+        case 0u: imageStore(mips[0], texel, color); break;
+        case 1u: imageStore(mips[1], texel, color); break;
+        case 2u: imageStore(mips[2], texel, color); break;
+        case 3u: imageStore(mips[3], texel, color); break;
+        default: imageStore(mips[4], texel, color); break;
+    }
+}
+
+// Average of the 2x2 texels of the source covered by `texel` of the first mip.
+// The last row and column of odd sized mips are clamped.
+vec4 sourceAverage(ivec2 texel, int layer, ivec2 maxTexel) {
+    ivec2 first = min(texel * 2, maxTexel);
+    ivec2 last = min(texel * 2 + 1, maxTexel);
+    return 0.25 * (texelFetch(source, ivec3(first, layer), 0)
+        + texelFetch(source, ivec3(last.x, first.y, layer), 0)
+        + texelFetch(source, ivec3(first.x, last.y, layer), 0)
+        + texelFetch(source, ivec3(last, layer), 0));
+}
+
+// Average the 4 texels of the previous mip of the tile for the invocations
+// whose coordinates are multiples of the stride of `mip`. Returns `color`
+// for the others.
+vec4 tileAverage(ivec2 local, uint mip, bool active, vec4 color) {
+    memoryBarrierShared();
+    barrier();
+
+    int offset = (1 << mip) / 2;
+    if (active) {
+        color = 0.25 * (tile[local.y][local.x]
+            + tile[local.y][local.x + offset]
+            + tile[local.y + offset][local.x]
+            + tile[local.y + offset][local.x + offset]);
+    }
+
+    memoryBarrierShared();
+    barrier();
+
+    if (active) {
+        tile[local.y][local.x] = color;
+    }
+    return color;
+}
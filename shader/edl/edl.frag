@@ -0,0 +1,69 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Eye-Dome Lighting, shades each pixel by how much its neighbours are closer
+// to the camera, outlining the shapes of point clouds without normals.
+
+layout (set = 0, binding = 0) uniform sampler2D colorSampler;
+layout (set = 0, binding = 1) uniform sampler2D depthSampler;
+
+layout (push_constant) uniform PushConstants {
+    float zNear;
+    float zFar;
+    // 0 disables the shading.
+    float strength;
+    // Distance of the neighbours in pixels.
+    float radius;
+    vec3 background;
+    uint encodeSrgb;
+} pc;
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec4 outColor;
+
+const vec2 NEIGHBOURS[8] = vec2[](
+    vec2(1.0, 0.0), vec2(0.7071, 0.7071), vec2(0.0, 1.0), vec2(-0.7071, 0.7071),
+    vec2(-1.0, 0.0), vec2(-0.7071, -0.7071), vec2(0.0, -1.0), vec2(0.7071, -0.7071)
+);
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+float linearDepth(float depth) {
+    return pc.zNear * pc.zFar / (pc.zFar - depth * (pc.zFar - pc.zNear));
+}
+
+float logDepth(vec2 coords) {
+    return log2(linearDepth(texture(depthSampler, coords).r));
+}
+
+void main() {
+    float depth = texture(depthSampler, fragCoords).r;
+    if (depth >= 1.0) {
+        vec3 background = pc.encodeSrgb == 1 ? linearToSrgb(pc.background) : pc.background;
+        outColor = vec4(background, 1.0);
+        return;
+    }
+
+    vec3 color = texture(colorSampler, fragCoords).rgb;
+
+    if (pc.strength > 0.0) {
+        float center = log2(linearDepth(depth));
+        vec2 texelSize = 1.0 / vec2(textureSize(depthSampler, 0));
+        float response = 0.0;
+        for (int i = 0; i < 8; i++) {
+            vec2 coords = fragCoords + NEIGHBOURS[i] * pc.radius * texelSize;
+            // The background is farther than any point and never occludes.
+            float neighbour = texture(depthSampler, coords).r < 1.0 ? logDepth(coords) : center;
+            response += max(0.0, center - neighbour);
+        }
+        color *= exp(-response / 8.0 * pc.strength * 300.0);
+    }
+
+    outColor = vec4(pc.encodeSrgb == 1 ? linearToSrgb(color) : color, 1.0);
+}
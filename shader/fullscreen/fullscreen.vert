@@ -0,0 +1,20 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Quad covering the target, with coordinates going from 0 at the bottom to 1
+// at the top.
+
+layout (location = 0) in vec2 inPosition;
+layout (location = 1) in vec2 inCoords;
+
+layout (location = 0) out vec2 fragCoords;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    fragCoords = inCoords;
+    gl_Position = vec4(inPosition, 0.0, 1.0);
+}
@@ -0,0 +1,34 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+layout (push_constant) uniform PushConstants {
+    mat4 rotation;
+    float scale;
+} pc;
+
+layout (location = 0) out vec3 outColor;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+
+    // Two vertices per axis line, +X, -X, +Y, -Y, +Z and -Z.
+    int line = gl_VertexIndex / 2;
+    int axis = line / 2;
+    bool negative = (line & 1) == 1;
+    bool tip = (gl_VertexIndex & 1) == 1;
+
+    vec3 direction = vec3(0.0);
+    direction[axis] = negative ? -1.0 : 1.0;
+    vec3 color = vec3(0.0);
+    color[axis] = 1.0;
+    outColor = negative ? color * 0.35 : color;
+
+    vec3 position = (pc.rotation * vec4(tip ? direction : vec3(0.0), 0.0)).xyz * pc.scale;
+
+    // View space is y up, flip it for Vulkan. The depth is not tested.
+    gl_Position = vec4(position.x, -position.y, 0.5, 1.0);
+}
@@ -0,0 +1,41 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Convolve the environment over the hemisphere around the direction of the
+// texel, the diffuse light received by a surface facing it.
+
+layout (binding = 0) uniform samplerCube environmentSampler;
+
+layout (location = 0) in vec3 fragDirection;
+
+layout (location = 0) out vec4 outColor;
+
+const float PI = 3.14159265359;
+const float SAMPLE_DELTA = 0.025;
+// Mips below the last one sampled, whose faces are 64 texels wide.
+const float SAMPLED_MIP_OFFSET = 7.0;
+
+void main() {
+    vec3 normal = normalize(fragDirection);
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    // The samples are too sparse for the finer mips.
+    float lod = max(float(textureQueryLevels(environmentSampler)) - SAMPLED_MIP_OFFSET, 0.0);
+
+    vec3 irradiance = vec3(0.0);
+    float sampleCount = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+            vec3 tangentSample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 direction = tangentSample.x * right + tangentSample.y * up + tangentSample.z * normal;
+            irradiance += textureLod(environmentSampler, direction, lod).rgb
+                * cos(theta) * sin(theta);
+            sampleCount += 1.0;
+        }
+    }
+
+    outColor = vec4(PI * irradiance / sampleCount, 1.0);
+}
@@ -0,0 +1,46 @@
+// Lights of the scene, see vks::LightManager.
+//
+// Define LIGHTS_BINDING before including this file.
+
+// See vks::GpuLight.
+struct Light {
+    vec4 position;  // w: type
+    vec4 direction; // w: range, 0 when infinite
+    vec4 color;     // w: intensity
+    vec4 cone;      // x: cos inner angle, y: cos outer angle
+};
+
+const float LIGHT_TYPE_DIRECTIONAL = 0.0;
+const float LIGHT_TYPE_SPOT = 2.0;
+
+layout (std430, binding = LIGHTS_BINDING) readonly buffer Lights {
+    uint count;
+    Light lights[];
+} sceneLights;
+
+// Radiance received at `position` from `light`, attenuated as in KHR_lights_punctual,
+// and the direction from `position` to the light.
+vec3 lightRadiance(Light light, vec3 position, out vec3 lightDirection) {
+    vec3 radiance = light.color.rgb * light.color.w;
+    if (light.position.w == LIGHT_TYPE_DIRECTIONAL) {
+        lightDirection = -light.direction.xyz;
+        return radiance;
+    }
+
+    vec3 toLight = light.position.xyz - position;
+    float distanceSquared = max(dot(toLight, toLight), 0.0001);
+    lightDirection = toLight * inversesqrt(distanceSquared);
+
+    float attenuation = 1.0 / distanceSquared;
+    float range = light.direction.w;
+    if (range > 0.0) {
+        float ratio = distanceSquared / (range * range);
+        float window = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+        attenuation *= window * window;
+    }
+    if (light.position.w == LIGHT_TYPE_SPOT) {
+        float cosAngle = dot(light.direction.xyz, -lightDirection);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cosAngle);
+    }
+    return radiance * attenuation;
+}
@@ -0,0 +1,36 @@
+// Declarations shared by the histograms of the log2 luminance of an image,
+// bin 0 counting the black texels.
+
+layout (local_size_x = 16, local_size_y = 16) in;
+
+const uint BIN_COUNT = 256;
+
+layout (binding = 0) uniform sampler2D source;
+
+layout (std430, binding = 1) buffer Histogram {
+    uint bins[BIN_COUNT];
+} histogram;
+
+layout (push_constant) uniform Parameters {
+    float minLogLuminance;
+    float inverseLogLuminanceRange;
+} params;
+
+shared uint localBins[BIN_COUNT];
+
+uint binOf(vec3 color) {
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    if (luminance < 1e-5) {
+        return 0;
+    }
+    float position = clamp((log2(luminance) - params.minLogLuminance) * params.inverseLogLuminanceRange, 0.0, 1.0);
+    return uint(position * float(BIN_COUNT - 2)) + 1;
+}
+
+// Add the bins of the group to the histogram, one invocation per bin.
+void mergeLocalBins() {
+    uint local = gl_LocalInvocationIndex;
+    if (localBins[local] > 0) {
+        atomicAdd(histogram.bins[local], localBins[local]);
+    }
+}
@@ -0,0 +1,73 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Vertex shader of the glTF models drawn with the uber shader, see scene::ModelRender.
+//
+// Skinned nodes blend the matrices of the joints of their skin, bound at binding 12
+// with the dynamic offset of the skin. They are relative to the node, whose global
+// transform is pushed for each draw.
+
+const uint MAX_JOINTS = 512;
+
+layout (binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 viewProj;
+    mat4 invertedView;
+    mat4 invertedProj;
+    mat4 prevViewProj;
+    vec3 eye;
+    float padding;
+    float zNear;
+    float zFar;
+} camera;
+
+layout (binding = 12) uniform Skin {
+    mat4 jointMatrices[MAX_JOINTS];
+} skin;
+
+// The draw id of vks::DrawDebugId is pushed in the first 8 bytes.
+layout (push_constant) uniform PushConstants {
+    layout (offset = 16) mat4 transform;
+    uint skinned;
+} pc;
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 1) in vec3 inNormal;
+layout (location = 2) in vec2 inTexCoord;
+layout (location = 4) in vec4 inTangent;
+layout (location = 5) in vec4 inWeights;
+layout (location = 6) in uvec4 inJoints;
+// Linear COLOR_0 of the primitive, white when it has none.
+layout (location = 7) in vec4 inColor;
+
+layout (location = 1) out vec2 fragTexCoord;
+layout (location = 2) out vec3 fragNormal;
+layout (location = 3) out vec4 fragTangent;
+layout (location = 4) out vec4 fragColor;
+layout (location = 5) out vec3 fragPosition;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    mat4 world = pc.transform;
+    if (pc.skinned != 0) {
+        world *= inWeights.x * skin.jointMatrices[inJoints.x]
+            + inWeights.y * skin.jointMatrices[inJoints.y]
+            + inWeights.z * skin.jointMatrices[inJoints.z]
+            + inWeights.w * skin.jointMatrices[inJoints.w];
+    }
+
+    vec4 position = world * vec4(inPosition, 1.0);
+    gl_Position = camera.viewProj * position;
+
+    mat3 normalMatrix = transpose(inverse(mat3(world)));
+    fragTexCoord = inTexCoord;
+    fragNormal = normalMatrix * inNormal;
+    fragTangent = vec4(mat3(world) * inTangent.xyz, inTangent.w);
+    fragColor = inColor;
+    fragPosition = position.xyz;
+}
@@ -0,0 +1,17 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+layout (location = 0) in vec3 fragColor;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    // Round sprites
+    vec2 coords = gl_PointCoord * 2.0 - 1.0;
+    if (dot(coords, coords) > 1.0) {
+        discard;
+    }
+
+    outColor = vec4(fragColor, 1.0);
+}
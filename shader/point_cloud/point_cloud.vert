@@ -0,0 +1,59 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Points are pulled from a storage buffer instead of vertex attributes.
+struct Point {
+    vec3 position;
+    uint color;
+};
+
+layout (set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 viewProj;
+    mat4 invertedView;
+    mat4 invertedProj;
+    mat4 prevViewProj;
+    vec3 eye;
+    float padding;
+    float zNear;
+    float zFar;
+} camera;
+
+layout (std430, set = 1, binding = 0) readonly buffer Points {
+    Point points[];
+};
+
+layout (push_constant) uniform PushConstants {
+    float viewportHeight;
+    // World space size of the points.
+    float pointSize;
+    float minPointSize;
+    float maxPointSize;
+} pc;
+
+layout (location = 0) out vec3 fragColor;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+    float gl_PointSize;
+};
+
+vec3 srgbToLinear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+void main() {
+    Point point = points[gl_VertexIndex];
+
+    gl_Position = camera.viewProj * vec4(point.position, 1.0);
+
+    // Project the world size on screen, so points shrink with the distance.
+    float size = pc.pointSize * camera.proj[1][1] * pc.viewportHeight * 0.5 / gl_Position.w;
+    gl_PointSize = clamp(size, pc.minPointSize, pc.maxPointSize);
+
+    fragColor = srgbToLinear(unpackUnorm4x8(point.color).rgb);
+}
@@ -0,0 +1,55 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+#extension GL_GOOGLE_include_directive: require
+
+// Convolve the environment with the GGX distribution of the roughness of the
+// mip, the specular light reflected in the direction of the texel.
+
+#include "../ggx/ggx.glsl"
+
+layout (binding = 0) uniform samplerCube environmentSampler;
+
+layout (push_constant) uniform PushConstants {
+    layout (offset = 64) float roughness;
+} pc;
+
+layout (location = 0) in vec3 fragDirection;
+
+layout (location = 0) out vec4 outColor;
+
+const uint SAMPLE_COUNT = 1024u;
+
+void main() {
+    vec3 normal = normalize(fragDirection);
+    // Seen along the normal, the reflections are not stretched at grazing angles.
+    vec3 view = normal;
+
+    float faceSize = float(textureSize(environmentSampler, 0).x);
+    float texelSolidAngle = 4.0 * PI / (6.0 * faceSize * faceSize);
+
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 halfVector = importanceSampleGgx(hammersley(i, SAMPLE_COUNT), normal, pc.roughness);
+        vec3 light = normalize(2.0 * dot(view, halfVector) * halfVector - view);
+        float normalDotLight = dot(normal, light);
+        if (normalDotLight > 0.0) {
+            // Sample the mip whose texels cover the solid angle of the sample,
+            // sparse samples of the finer mips would show bright dots.
+            float normalDotHalf = max(dot(normal, halfVector), 0.0);
+            float halfDotView = max(dot(halfVector, view), 0.0);
+            float pdf = distributionGgx(normalDotHalf, pc.roughness) * normalDotHalf
+                / (4.0 * halfDotView) + 0.0001;
+            float sampleSolidAngle = 1.0 / (float(SAMPLE_COUNT) * pdf + 0.0001);
+            float lod = pc.roughness == 0.0
+                ? 0.0
+                : 0.5 * log2(sampleSolidAngle / texelSolidAngle);
+
+            color += textureLod(environmentSampler, light, lod).rgb * normalDotLight;
+            weight += normalDotLight;
+        }
+    }
+
+    outColor = vec4(color / weight, 1.0);
+}
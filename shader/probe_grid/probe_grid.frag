@@ -0,0 +1,16 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+#extension GL_GOOGLE_include_directive: require
+
+#define PROBE_GRID_BINDING 0
+#include "probe_grid.glsl"
+
+layout (location = 0) flat in ivec3 inProbe;
+layout (location = 1) flat in vec3 inNormal;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    outColor = vec4(ambientCube(inProbe, inNormal), 1.0);
+}
@@ -0,0 +1,44 @@
+// Irradiance probe grid, see vks::ProbeGrid.
+//
+// Define PROBE_GRID_BINDING before including this file. Each probe is an
+// ambient cube: the texels x * 6 + face of row y in layer z hold the
+// irradiance of the probe (x, y, z) along +x, -x, +y, -y, +z and -z.
+
+layout (binding = PROBE_GRID_BINDING) uniform sampler2DArray probeGrid;
+
+struct ProbeGridParams {
+    // xyz: position of the first probe.
+    vec4 origin;
+    // xyz: distance between two neighbour probes.
+    vec4 spacing;
+    // xyz: probes along each axis.
+    uvec4 counts;
+};
+
+// Irradiance of `probe` along `normal`, blending the faces of the ambient cube.
+vec3 ambientCube(ivec3 probe, vec3 normal) {
+    vec3 weights = normal * normal;
+    int x = probe.x * 6;
+    vec3 irradianceX = texelFetch(probeGrid, ivec3(x + (normal.x < 0.0 ? 1 : 0), probe.yz), 0).rgb;
+    vec3 irradianceY = texelFetch(probeGrid, ivec3(x + (normal.y < 0.0 ? 3 : 2), probe.yz), 0).rgb;
+    vec3 irradianceZ = texelFetch(probeGrid, ivec3(x + (normal.z < 0.0 ? 5 : 4), probe.yz), 0).rgb;
+    return weights.x * irradianceX + weights.y * irradianceY + weights.z * irradianceZ;
+}
+
+// Irradiance at `position` along `normal`, trilinearly interpolated between
+// the eight surrounding probes. Positions outside of the grid use its border.
+vec3 sampleProbeGrid(ProbeGridParams grid, vec3 position, vec3 normal) {
+    ivec3 last = ivec3(grid.counts.xyz) - 1;
+    vec3 cell = clamp((position - grid.origin.xyz) / grid.spacing.xyz, vec3(0.0), vec3(last));
+    ivec3 base = ivec3(floor(cell));
+    vec3 factors = cell - vec3(base);
+
+    vec3 irradiance = vec3(0.0);
+    for (int corner = 0; corner < 8; corner++) {
+        ivec3 offset = ivec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+        vec3 weights = mix(1.0 - factors, factors, vec3(offset));
+        ivec3 probe = min(base + offset, last);
+        irradiance += weights.x * weights.y * weights.z * ambientCube(probe, normal);
+    }
+    return irradiance;
+}
@@ -0,0 +1,54 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// A small cube per probe, each face showing the matching face of its ambient cube.
+
+layout (push_constant) uniform PushConstants {
+    mat4 viewProj;
+    // xyz: position of the first probe, w: half size of the cubes.
+    vec4 origin;
+    // xyz: distance between two neighbour probes.
+    vec4 spacing;
+    uvec4 counts;
+} pc;
+
+layout (location = 0) flat out ivec3 outProbe;
+layout (location = 1) flat out vec3 outNormal;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+// Corners of the two triangles of a face.
+const vec2 CORNERS[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+    // Six faces of six vertices, in the +x, -x, +y, -y, +z, -z order of the ambient cubes.
+    int face = gl_VertexIndex / 6;
+    int axis = face / 2;
+    vec2 corner = CORNERS[gl_VertexIndex % 6];
+
+    vec3 normal = vec3(0.0);
+    normal[axis] = (face & 1) == 1 ? -1.0 : 1.0;
+    vec3 tangent = vec3(0.0);
+    tangent[(axis + 1) % 3] = 1.0;
+    vec3 bitangent = vec3(0.0);
+    bitangent[(axis + 2) % 3] = 1.0;
+
+    uint count = pc.counts.x * pc.counts.y;
+    ivec3 probe = ivec3(
+        gl_InstanceIndex % pc.counts.x,
+        (gl_InstanceIndex / pc.counts.x) % pc.counts.y,
+        gl_InstanceIndex / count
+    );
+    outProbe = probe;
+    outNormal = normal;
+
+    vec3 center = pc.origin.xyz + vec3(probe) * pc.spacing.xyz;
+    vec3 position = center + (normal + tangent * corner.x + bitangent * corner.y) * pc.origin.w;
+    gl_Position = pc.viewProj * vec4(position, 1.0);
+}
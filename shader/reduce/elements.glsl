@@ -0,0 +1,46 @@
+// Typed operations on the 32 bits elements of the compute kernels, see vks::KernelElement.
+//
+// Elements are stored as uint and reinterpreted according to ELEMENT_TYPE.
+
+layout (constant_id = 0) const uint ELEMENT_TYPE = 0;
+layout (constant_id = 1) const uint OPERATION = 0;
+
+const uint ELEMENT_U32 = 0;
+const uint ELEMENT_I32 = 1;
+const uint ELEMENT_F32 = 2;
+
+const uint OPERATION_SUM = 0;
+const uint OPERATION_MIN = 1;
+const uint OPERATION_MAX = 2;
+
+// Neutral element of the operation.
+uint identity() {
+    if (OPERATION == OPERATION_SUM) {
+        return ELEMENT_TYPE == ELEMENT_F32 ? floatBitsToUint(0.0) : 0u;
+    }
+    bool keepMin = OPERATION == OPERATION_MIN;
+    if (ELEMENT_TYPE == ELEMENT_F32) {
+        // Positive or negative infinity.
+        return keepMin ? 0x7f800000u : 0xff800000u;
+    }
+    if (ELEMENT_TYPE == ELEMENT_I32) {
+        return keepMin ? 0x7fffffffu : 0x80000000u;
+    }
+    return keepMin ? 0xffffffffu : 0u;
+}
+
+uint combine(uint a, uint b) {
+    if (ELEMENT_TYPE == ELEMENT_F32) {
+        float x = uintBitsToFloat(a);
+        float y = uintBitsToFloat(b);
+        float result = OPERATION == OPERATION_SUM ? x + y : OPERATION == OPERATION_MIN ? min(x, y) : max(x, y);
+        return floatBitsToUint(result);
+    }
+    if (ELEMENT_TYPE == ELEMENT_I32) {
+        int x = int(a);
+        int y = int(b);
+        int result = OPERATION == OPERATION_SUM ? x + y : OPERATION == OPERATION_MIN ? min(x, y) : max(x, y);
+        return uint(result);
+    }
+    return OPERATION == OPERATION_SUM ? a + b : OPERATION == OPERATION_MIN ? min(a, b) : max(a, b);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Write the linear scene color to the swapchain image.
+layout (binding = 0) uniform sampler2D sceneColor;
+
+layout (push_constant) uniform PushConstants {
+    // Encode the colors for targets holding sRGB values without hardware encoding.
+    uint encodeSrgb;
+} pc;
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+void main() {
+    vec3 color = texture(sceneColor, fragCoords).rgb;
+    outColor = vec4(pc.encodeSrgb == 1 ? linearToSrgb(clamp(color, 0.0, 1.0)) : color, 1.0);
+}
@@ -0,0 +1,35 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Background of the scene, the environment seen in the direction of the pixel.
+// The first mip of the pre-filtered map is the sharp environment.
+
+layout (binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 viewProj;
+    mat4 invertedView;
+    mat4 invertedProj;
+    mat4 prevViewProj;
+    vec3 eye;
+    float padding;
+    float zNear;
+    float zFar;
+} camera;
+
+layout (binding = 10) uniform samplerCube preFilteredSampler;
+
+// Position of the pixel in normalized device coordinates.
+layout (location = 0) in vec2 fragPosition;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    // Any depth between the planes unprojects to a point on the ray of the pixel,
+    // in front of the eye at the origin of the view space.
+    vec4 target = camera.invertedProj * vec4(fragPosition, 0.5, 1.0);
+    vec3 direction = mat3(camera.invertedView) * (target.xyz / target.w);
+
+    outColor = vec4(textureLod(preFilteredSampler, direction, 0.0).rgb, 1.0);
+}
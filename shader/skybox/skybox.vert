@@ -0,0 +1,17 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+layout (location = 0) out vec2 fragPosition;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+
+    // Full screen triangle
+    vec2 coords = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    fragPosition = coords * 2.0 - 1.0;
+    gl_Position = vec4(fragPosition, 0.0, 1.0);
+}
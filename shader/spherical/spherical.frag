@@ -0,0 +1,24 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Sample the equirectangular environment in the direction of the cubemap texel,
+// with the mapping of the panoramas written by equirectangular.comp.
+
+layout (binding = 0) uniform sampler2D equirectangularSampler;
+
+layout (location = 0) in vec3 fragDirection;
+
+layout (location = 0) out vec4 outColor;
+
+const float PI = 3.14159265359;
+
+void main() {
+    vec3 direction = normalize(fragDirection);
+    float longitude = atan(direction.x, -direction.z);
+    float latitude = asin(clamp(direction.y, -1.0, 1.0));
+    vec2 uv = vec2(longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI);
+
+    // The derivatives jump where the longitude wraps, the first mip avoids a seam.
+    outColor = vec4(textureLod(equirectangularSampler, uv, 0.0).rgb, 1.0);
+}
@@ -0,0 +1,30 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+#define MODE_SIDE_BY_SIDE 0
+#define MODE_ANAGLYPH 1
+
+layout (binding = 0) uniform sampler2D leftEye;
+layout (binding = 1) uniform sampler2D rightEye;
+
+layout (push_constant) uniform PushConstants {
+    uint mode;
+} pc;
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    if (pc.mode == MODE_SIDE_BY_SIDE) {
+        // Each eye is squeezed in half of the width.
+        vec2 coords = vec2(fract(fragCoords.x * 2.0), fragCoords.y);
+        outColor = fragCoords.x < 0.5 ? texture(leftEye, coords) : texture(rightEye, coords);
+    } else {
+        // Red from the left eye, green and blue from the right one.
+        vec3 left = texture(leftEye, fragCoords).rgb;
+        vec3 right = texture(rightEye, fragCoords).rgb;
+        outColor = vec4(left.r, right.gb, 1.0);
+    }
+}
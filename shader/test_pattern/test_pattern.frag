@@ -0,0 +1,94 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+#define PATTERN_GRADIENT_RAMP 0
+#define PATTERN_COLOR_BARS 1
+#define PATTERN_CHECKERBOARD 2
+
+layout (push_constant) uniform PushConstants {
+    vec2 extent;
+    uint pattern;
+    uint cellSize;
+    float opacity;
+    uint encodeSrgb;
+} pc;
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 srgbToLinear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+// Top half: linear ramp. Bottom half: perceptual ramp (equal steps after encoding).
+// Each half is split in a smooth and a 16 steps version.
+vec3 gradientRamp(vec2 uv) {
+    float value = uv.x;
+    if (fract(uv.y * 2.0) > 0.5) {
+        value = floor(value * 16.0) / 15.0;
+    }
+    if (uv.y > 0.5) {
+        return srgbToLinear(vec3(value));
+    }
+    return vec3(value);
+}
+
+// 75% SMPTE like color bars with a full intensity strip at the bottom.
+vec3 colorBars(vec2 uv) {
+    const vec3 bars[8] = vec3[](
+        vec3(1.0, 1.0, 1.0),
+        vec3(1.0, 1.0, 0.0),
+        vec3(0.0, 1.0, 1.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(1.0, 0.0, 1.0),
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+        vec3(0.0, 0.0, 0.0)
+    );
+    vec3 color = bars[min(int(uv.x * 8.0), 7)];
+    if (uv.y < 0.75) {
+        color *= 0.75;
+    }
+    return srgbToLinear(color);
+}
+
+// Checkerboard next to a 50% linear grey patch. Both should look the same
+// from a distance if the output transform is right.
+vec3 checkerboard(vec2 pixel, vec2 uv) {
+    if (uv.x > 0.5) {
+        return vec3(0.5);
+    }
+    uvec2 cell = uvec2(pixel) / max(pc.cellSize, 1u);
+    return vec3(float((cell.x + cell.y) & 1u));
+}
+
+void main() {
+
+    vec2 pixel = fragCoords * pc.extent;
+    vec2 uv = fragCoords;
+
+    vec3 color;
+    if (pc.pattern == PATTERN_GRADIENT_RAMP) {
+        color = gradientRamp(uv);
+    } else if (pc.pattern == PATTERN_COLOR_BARS) {
+        color = colorBars(uv);
+    } else {
+        color = checkerboard(pixel, uv);
+    }
+
+    if (pc.encodeSrgb != 0) {
+        color = linearToSrgb(color);
+    }
+
+    outColor = vec4(color, pc.opacity);
+}
@@ -2,10 +2,22 @@
 #version 450
 
 #extension GL_ARB_separate_shader_objects: enable
+#extension GL_GOOGLE_include_directive: require
+
+// Lit by the lights at binding 2 when enabled, which only then must be in the layout.
+layout (constant_id = 0) const bool LIGHTS = false;
+
+const float AMBIENT = 0.1;
+
 layout (binding = 1) uniform sampler2D texSampler;
 
+#define LIGHTS_BINDING 2
+#include "../lights/lights.glsl"
+
 // layout (location = 0) in vec3 fragColor;
 layout (location = 1) in vec2 fragTexCoord;
+layout (location = 2) in vec3 fragNormal;
+layout (location = 5) in vec3 fragPosition;
 
 layout (location = 0) out vec4 outColor;
 
@@ -13,4 +25,15 @@ void main() {
 
     outColor = texture(texSampler, fragTexCoord);
     // outColor=vec4(fragTexCoord,0,1);
+
+    if (LIGHTS) {
+        vec3 normal = normalize(fragNormal);
+        vec3 lighting = vec3(AMBIENT);
+        for (uint i = 0; i < sceneLights.count; i++) {
+            vec3 lightDirection;
+            vec3 radiance = lightRadiance(sceneLights.lights[i], fragPosition, lightDirection);
+            lighting += radiance * max(dot(normal, lightDirection), 0.0);
+        }
+        outColor.rgb *= lighting;
+    }
 }
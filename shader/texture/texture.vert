@@ -8,6 +8,13 @@ layout (location = 1) in vec2 inTexCoord;
 
 // layout (location = 0) out vec3 fragColor;
 layout (location = 1) out vec2 fragTexCoord;
+// Read by the uber shader when a material is used, the quad faces the camera.
+layout (location = 2) out vec3 fragNormal;
+layout (location = 3) out vec4 fragTangent;
+// The quad has no vertex colors.
+layout (location = 4) out vec4 fragColor;
+// Lit at its position on the z = 0 plane.
+layout (location = 5) out vec3 fragPosition;
 
 out gl_PerVertex {
     vec4 gl_Position;
@@ -17,4 +24,8 @@ void main() {
 
     gl_Position = vec4(inPosition, 0.0, 1.0);
     fragTexCoord = inTexCoord;
+    fragNormal = vec3(0.0, 0.0, -1.0);
+    fragTangent = vec4(1.0, 0.0, 0.0, 1.0);
+    fragColor = vec4(1.0);
+    fragPosition = vec3(inPosition, 0.0);
 }
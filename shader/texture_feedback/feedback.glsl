@@ -0,0 +1,20 @@
+// Texture feedback written by the materials, see vks::TextureFeedback.
+//
+// Define TEXTURE_FEEDBACK_BINDING before including this file.
+
+layout (std430, binding = TEXTURE_FEEDBACK_BINDING) buffer TextureFeedback {
+    uint entries[];
+} textureFeedback;
+
+const uint TEXTURE_FEEDBACK_TILE_SIZE = 8;
+
+// Record that the mip of `tex` sampled at `uv` is used by the screen tile of the fragment.
+void writeTextureFeedback(uint textureIndex, sampler2D tex, vec2 uv) {
+    uvec2 tile = uvec2(gl_FragCoord.xy) / TEXTURE_FEEDBACK_TILE_SIZE;
+    uint hash = (tile.x * 73856093u) ^ (tile.y * 19349663u) ^ (textureIndex * 83492791u);
+    uint slot = hash % uint(textureFeedback.entries.length());
+
+    float lod = max(textureQueryLod(tex, uv).y, 0.0);
+    uint mip = min(uint(lod), 15u);
+    textureFeedback.entries[slot] = (textureIndex << 4) | mip;
+}
@@ -0,0 +1,24 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+layout (push_constant) uniform PushConstants {
+    mat4 mvp;
+    vec4 normalMatrix[3];
+    vec4 color;
+} pc;
+
+layout (location = 0) in vec3 fragNormal;
+layout (location = 1) in vec4 fragColor;
+
+layout (location = 0) out vec4 outColor;
+
+// Key light coming from above the camera, which looks at the model from the front right.
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 1.0, 0.8));
+const float AMBIENT = 0.25;
+
+void main() {
+    // Models are drawn without culling, back faces are lit like front faces.
+    float diffuse = abs(dot(normalize(fragNormal), LIGHT_DIRECTION));
+    outColor = vec4(pc.color.rgb * fragColor.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), 1.0);
+}
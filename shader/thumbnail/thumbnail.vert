@@ -0,0 +1,29 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 1) in vec3 inNormal;
+// Linear COLOR_0 of the primitive, white when it has none.
+layout (location = 7) in vec4 inColor;
+
+layout (push_constant) uniform PushConstants {
+    mat4 mvp;
+    // Columns of the rotation and scale of the model matrix.
+    vec4 normalMatrix[3];
+    vec4 color;
+} pc;
+
+layout (location = 0) out vec3 fragNormal;
+layout (location = 1) out vec4 fragColor;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    gl_Position = pc.mvp * vec4(inPosition, 1.0);
+    mat3 normalMatrix = mat3(pc.normalMatrix[0].xyz, pc.normalMatrix[1].xyz, pc.normalMatrix[2].xyz);
+    fragNormal = normalMatrix * inNormal;
+    fragColor = inColor;
+}
@@ -0,0 +1,240 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+#extension GL_GOOGLE_include_directive: require
+
+// Uber shader of the materials.
+//
+// The features are selected by specialization constants, see gltf_model::MaterialFeatures.
+// Disabled features are removed when the pipeline is created so only the bindings of the
+// enabled ones must be in the layout. Optional textures of an enabled feature are bound
+// to a white texture when the material has none. Materials are lit by the lights at
+// binding 8 and, with ENVIRONMENT, by the maps of the environment at bindings 9 to 11,
+// see environment::Environment::layout_bindings.
+
+layout (constant_id = 0) const uint TEXTURE_INDEX = 0;
+layout (constant_id = 1) const bool TEXTURE_FEEDBACK = false;
+layout (constant_id = 2) const bool MATERIAL = false;
+layout (constant_id = 3) const bool NORMAL_MAP = false;
+layout (constant_id = 4) const bool EMISSIVE = false;
+layout (constant_id = 5) const bool CLEARCOAT = false;
+layout (constant_id = 6) const bool TRANSMISSION = false;
+layout (constant_id = 7) const uint ALPHA_MODE = 0;
+layout (constant_id = 8) const uint DEBUG_VIEW = 0;
+layout (constant_id = 9) const uint VERTEX_COLOR = 0;
+layout (constant_id = 10) const bool DOUBLE_SIDED = false;
+layout (constant_id = 11) const uint MASK_EDGES = 0;
+layout (constant_id = 12) const bool ENVIRONMENT = false;
+
+const uint ALPHA_MODE_MASK = 1;
+const uint ALPHA_MODE_BLEND = 2;
+
+const uint DEBUG_VIEW_NORMALS = 1;
+
+// See vks::MaskEdges.
+const uint MASK_EDGES_DISCARD = 0;
+const uint MASK_EDGES_BLEND = 2;
+
+// See vks::VertexColorMode.
+const uint VERTEX_COLOR_LINEAR = 1;
+const uint VERTEX_COLOR_SRGB = 2;
+
+// See gltf_model::NormalMapDecoding.
+const uint NORMAL_FLAG_FLIP_Y = 1;
+const uint NORMAL_FLAG_RECONSTRUCT_Z = 2;
+
+// Direction from the surface to the viewer.
+const vec3 VIEW_DIRECTION = vec3(0.0, 0.0, -1.0);
+const float AMBIENT = 0.1;
+
+#define TEXTURE_FEEDBACK_BINDING 2
+#include "../texture_feedback/feedback.glsl"
+
+#define LIGHTS_BINDING 8
+#include "../lights/lights.glsl"
+
+layout (binding = 1) uniform sampler2D colorSampler;
+
+// See gltf_model::MaterialUniform.
+layout (binding = 3) uniform MaterialUniform {
+    vec4 color;
+    vec3 emissive;
+    float alphaCutoff;
+    float metallic;
+    float roughness;
+    float clearcoatFactor;
+    float clearcoatRoughness;
+    float transmission;
+    float normalScale;
+    uint normalFlags;
+    float emissiveIntensity;
+    // Rows of the KHR_texture_transform of the textures.
+    vec4 colorUvTransform[2];
+    vec4 normalUvTransform[2];
+    vec4 emissiveUvTransform[2];
+    vec4 clearcoatUvTransform[2];
+    vec4 transmissionUvTransform[2];
+} material;
+
+layout (binding = 4) uniform sampler2D normalSampler;
+layout (binding = 5) uniform sampler2D emissiveSampler;
+layout (binding = 6) uniform sampler2D clearcoatSampler;
+layout (binding = 7) uniform sampler2D transmissionSampler;
+
+layout (binding = 9) uniform samplerCube irradianceSampler;
+layout (binding = 10) uniform samplerCube preFilteredSampler;
+layout (binding = 11) uniform sampler2D brdfLookupSampler;
+
+layout (location = 1) in vec2 fragTexCoord;
+layout (location = 2) in vec3 fragNormal;
+layout (location = 3) in vec4 fragTangent;
+layout (location = 4) in vec4 fragColor;
+layout (location = 5) in vec3 fragPosition;
+
+layout (location = 0) out vec4 outColor;
+
+vec2 transformUv(vec4 transform[2]) {
+    vec3 uv = vec3(fragTexCoord, 1.0);
+    return vec2(dot(transform[0].xyz, uv), dot(transform[1].xyz, uv));
+}
+
+// -1 on the back faces of double sided materials, whose tangent frame is flipped
+// to face the viewer.
+float getFaceSign() {
+    return DOUBLE_SIDED && !gl_FrontFacing ? -1.0 : 1.0;
+}
+
+vec3 getNormal() {
+    vec3 normal = normalize(fragNormal);
+    if (NORMAL_MAP) {
+        vec3 tangent = normalize(fragTangent.xyz);
+        vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+        vec2 normalUv = transformUv(material.normalUvTransform);
+        vec3 tangentNormal = texture(normalSampler, normalUv).rgb * 2.0 - 1.0;
+        if ((material.normalFlags & NORMAL_FLAG_RECONSTRUCT_Z) != 0) {
+            tangentNormal.z = sqrt(max(0.0, 1.0 - dot(tangentNormal.xy, tangentNormal.xy)));
+        }
+        if ((material.normalFlags & NORMAL_FLAG_FLIP_Y) != 0) {
+            tangentNormal.y = -tangentNormal.y;
+        }
+        tangentNormal.xy *= material.normalScale;
+        normal = normalize(mat3(tangent, bitangent, normal) * tangentNormal);
+    }
+    return normal * getFaceSign();
+}
+
+vec3 srgbToLinear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+
+float specular(vec3 normal, vec3 lightDirection, float roughness) {
+    vec3 halfVector = normalize(lightDirection + VIEW_DIRECTION);
+    float shininess = 2.0 / max(roughness * roughness * roughness * roughness, 0.001) - 2.0;
+    return pow(max(dot(normal, halfVector), 0.0), shininess);
+}
+
+// Image based lighting of the environment, replacing the constant ambient.
+vec3 environmentLight(vec3 normal, vec3 diffuseColor, vec3 specularColor, float roughness) {
+    vec3 diffuseLight = texture(irradianceSampler, normal).rgb;
+
+    // The rougher mips of the pre-filtered map are the blurrier reflections.
+    vec3 reflection = reflect(-VIEW_DIRECTION, normal);
+    float lod = roughness * float(textureQueryLevels(preFilteredSampler) - 1);
+    vec3 specularLight = textureLod(preFilteredSampler, reflection, lod).rgb;
+
+    float normalDotView = max(dot(normal, VIEW_DIRECTION), 0.0);
+    vec2 brdf = texture(brdfLookupSampler, vec2(normalDotView, roughness)).rg;
+
+    return diffuseColor * diffuseLight + specularLight * (specularColor * brdf.x + brdf.y);
+}
+
+void main() {
+    if (TEXTURE_FEEDBACK) {
+        writeTextureFeedback(TEXTURE_INDEX, colorSampler, fragTexCoord);
+    }
+
+    vec2 colorUv = MATERIAL ? transformUv(material.colorUvTransform) : fragTexCoord;
+    vec4 baseColor = texture(colorSampler, colorUv);
+    if (!MATERIAL) {
+        outColor = baseColor;
+        return;
+    }
+
+    baseColor *= material.color;
+    if (VERTEX_COLOR == VERTEX_COLOR_LINEAR) {
+        baseColor *= fragColor;
+    } else if (VERTEX_COLOR == VERTEX_COLOR_SRGB) {
+        baseColor *= vec4(srgbToLinear(fragColor.rgb), fragColor.a);
+    }
+    if (ALPHA_MODE == ALPHA_MODE_MASK) {
+        if (MASK_EDGES != MASK_EDGES_DISCARD) {
+            // Ramp the alpha from 0 to 1 over a pixel around the cutoff so
+            // the covered samples or the blending antialias the edge.
+            float ramp = max(fwidth(baseColor.a), 0.0001);
+            baseColor.a = clamp((baseColor.a - material.alphaCutoff) / ramp + 0.5, 0.0, 1.0);
+            // Blended fragments still write their depth, drop the invisible ones.
+            if (MASK_EDGES == MASK_EDGES_BLEND && baseColor.a == 0.0) {
+                discard;
+            }
+        } else if (baseColor.a < material.alphaCutoff) {
+            discard;
+        }
+    }
+
+    vec3 normal = getNormal();
+    if (DEBUG_VIEW == DEBUG_VIEW_NORMALS) {
+        outColor = vec4(normal * 0.5 + 0.5, 1.0);
+        return;
+    }
+    vec3 coatNormal = normalize(fragNormal) * getFaceSign();
+    vec3 diffuse = vec3(0.0);
+    vec3 specularLight = vec3(0.0);
+    vec3 coatLight = vec3(0.0);
+    for (uint i = 0; i < sceneLights.count; i++) {
+        vec3 lightDirection;
+        vec3 radiance = lightRadiance(sceneLights.lights[i], fragPosition, lightDirection);
+        vec3 irradiance = radiance * max(dot(normal, lightDirection), 0.0);
+        diffuse += irradiance;
+        specularLight += irradiance * specular(normal, lightDirection, material.roughness);
+        if (CLEARCOAT) {
+            coatLight += irradiance
+                * specular(coatNormal, lightDirection, material.clearcoatRoughness);
+        }
+    }
+
+    vec3 dielectric = vec3(0.04);
+    vec3 specularColor = mix(dielectric, baseColor.rgb, material.metallic);
+    vec3 diffuseColor = baseColor.rgb * (1.0 - material.metallic);
+
+    vec3 color = diffuseColor * diffuse + specularColor * specularLight;
+    if (ENVIRONMENT) {
+        color += environmentLight(normal, diffuseColor, specularColor, material.roughness);
+    } else {
+        color += diffuseColor * AMBIENT;
+    }
+
+    if (TRANSMISSION) {
+        // Without the scene behind the surface, let the background show through.
+        vec2 transmissionUv = transformUv(material.transmissionUvTransform);
+        float transmission = material.transmission * texture(transmissionSampler, transmissionUv).r;
+        baseColor.a *= 1.0 - transmission;
+    }
+
+    if (CLEARCOAT) {
+        vec2 clearcoatUv = transformUv(material.clearcoatUvTransform);
+        float clearcoat = material.clearcoatFactor * texture(clearcoatSampler, clearcoatUv).r;
+        color = mix(color, coatLight, clearcoat * 0.25);
+    }
+
+    if (EMISSIVE) {
+        // Not clamped, bright emissive surfaces go above 1 and feed the bloom.
+        color += material.emissive * material.emissiveIntensity
+            * texture(emissiveSampler, transformUv(material.emissiveUvTransform)).rgb;
+    }
+
+    bool maskEdges = ALPHA_MODE == ALPHA_MODE_MASK && MASK_EDGES != MASK_EDGES_DISCARD;
+    float alpha = ALPHA_MODE == ALPHA_MODE_BLEND || TRANSMISSION || maskEdges
+        ? baseColor.a
+        : 1.0;
+    outColor = vec4(color, alpha);
+}
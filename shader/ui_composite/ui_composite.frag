@@ -0,0 +1,37 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects: enable
+
+// Ui layer, premultiplied linear colors.
+layout (binding = 0) uniform sampler2D ui;
+
+layout (push_constant) uniform PushConstants {
+    // Value of the ui white in the target, above 1 for extended range targets.
+    float whiteScale;
+    // Encode the colors for targets holding sRGB values without hardware encoding.
+    uint encodeSrgb;
+} pc;
+
+layout (location = 0) in vec2 fragCoords;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+void main() {
+    vec4 color = texture(ui, fragCoords);
+    if (color.a <= 0.0) {
+        discard;
+    }
+
+    vec3 rgb = color.rgb * pc.whiteScale;
+    if (pc.encodeSrgb == 1) {
+        rgb = linearToSrgb(rgb / color.a) * color.a;
+    }
+    // Blended over the scene with ONE, ONE_MINUS_SRC_ALPHA.
+    outColor = vec4(rgb, color.a);
+}